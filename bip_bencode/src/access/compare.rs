@@ -0,0 +1,80 @@
+use access::bencode::{BRefAccess, BencodeRefKind};
+use access::dict::BDictAccess;
+use access::list::BListAccess;
+
+/// Compare two bencode values by their semantic content.
+///
+/// The values may be of different types, for example a `BencodeRef` and a `BencodeMut`.
+/// Dictionaries are compared in sorted key order, so the order that keys were inserted
+/// or decoded in is ignored. If a dictionary implementation reports the same key more
+/// than once, only the first occurrence of that key is compared. List order is significant.
+pub fn structural_eq<A, B>(a: &A, b: &B) -> bool
+    where A: BRefAccess, A::BKey: AsRef<[u8]>, B: BRefAccess, B::BKey: AsRef<[u8]> {
+    match (a.kind(), b.kind()) {
+        (BencodeRefKind::Int(a), BencodeRefKind::Int(b))     => a == b,
+        (BencodeRefKind::Bytes(a), BencodeRefKind::Bytes(b)) => a == b,
+        (BencodeRefKind::List(a), BencodeRefKind::List(b))   => list_eq(a, b),
+        (BencodeRefKind::Dict(a), BencodeRefKind::Dict(b))   => dict_eq(a, b),
+        _ => false
+    }
+}
+
+fn list_eq<A, B>(a: &BListAccess<A>, b: &BListAccess<B>) -> bool
+    where A: BRefAccess, A::BKey: AsRef<[u8]>, B: BRefAccess, B::BKey: AsRef<[u8]> {
+    a.len() == b.len() && a.into_iter().zip(b.into_iter()).all(|(a, b)| structural_eq(a, b))
+}
+
+fn dict_eq<K1, V1, K2, V2>(a: &BDictAccess<K1, V1>, b: &BDictAccess<K2, V2>) -> bool
+    where K1: AsRef<[u8]>, V1: BRefAccess, V1::BKey: AsRef<[u8]>,
+          K2: AsRef<[u8]>, V2: BRefAccess, V2::BKey: AsRef<[u8]> {
+    let a_list = sorted_entries(a);
+    let b_list = sorted_entries(b);
+
+    a_list.len() == b_list.len() && a_list.iter().zip(b_list.iter()).all(|(&(a_key, a_value), &(b_key, b_value))| {
+        a_key == b_key && structural_eq(a_value, b_value)
+    })
+}
+
+fn sorted_entries<K, V>(dict: &BDictAccess<K, V>) -> Vec<(&[u8], &V)>
+    where K: AsRef<[u8]> {
+    let mut entries: Vec<(&[u8], &V)> = dict.to_list().into_iter().map(|(k, v)| (k.as_ref(), v)).collect();
+
+    // Stable sort, so the first occurrence of a duplicate key is the one kept
+    entries.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+    entries.dedup_by(|&mut (a, _), &mut (b, _)| a == b);
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use access::compare;
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    #[test]
+    fn positive_structural_eq_ref_and_mut() {
+        let bencode = BencodeRef::decode(b"d1:ai1e1:bl1:cee", BDecodeOpt::default()).unwrap();
+        let bencode_mut = bencode.to_mut();
+
+        assert!(compare::structural_eq(&bencode, &bencode_mut));
+    }
+
+    #[test]
+    fn negative_structural_eq_different_types() {
+        let int = BencodeRef::decode(b"i1e", BDecodeOpt::default()).unwrap();
+        let bytes = BencodeRef::decode(b"1:1", BDecodeOpt::default()).unwrap();
+
+        assert!(!compare::structural_eq(&int, &bytes));
+    }
+
+    #[test]
+    fn negative_structural_eq_extra_key() {
+        let first = BencodeRef::decode(b"d1:ai1ee", BDecodeOpt::default()).unwrap();
+        let second = BencodeRef::decode(b"d1:ai1e1:bi2ee", BDecodeOpt::default()).unwrap();
+
+        assert!(!compare::structural_eq(&first, &second));
+    }
+}
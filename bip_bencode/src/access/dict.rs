@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 
+use access::bencode::BRefAccess;
+
 /// Trait for working with generic map data structures.
 pub trait BDictAccess<K, V> {
     /// Convert the dictionary to an unordered list of key/value pairs.
@@ -17,6 +19,31 @@ pub trait BDictAccess<K, V> {
 
     /// Remove a value from the dictionary and return it.
     fn remove(&mut self, key: &[u8]) -> Option<V>;
+
+    /// Lookup a value by descending through nested dictionaries using each key in the path.
+    ///
+    /// Returns `None` if the path is empty, a key is missing, or an intermediate value is not a dictionary.
+    fn lookup_path<'a>(&'a self, path: &[&[u8]]) -> Option<&'a V>
+        where K: 'a, V: BRefAccess<BKey=K, BType=V> + 'a {
+        let (first_key, rest_keys) = match path.split_first() {
+            Some(n) => n,
+            None    => return None
+        };
+
+        let mut value = match self.lookup(first_key) {
+            Some(n) => n,
+            None    => return None
+        };
+
+        for key in rest_keys {
+            value = match value.dict().and_then(|dict| dict.lookup(key)) {
+                Some(n) => n,
+                None    => return None
+            };
+        }
+
+        Some(value)
+    }
 }
 
 impl<'a, V> BDictAccess<&'a [u8], V> for BTreeMap<&'a [u8], V> {
@@ -61,4 +88,51 @@ impl<'a, V> BDictAccess<Cow<'a, [u8]>, V> for BTreeMap<Cow<'a, [u8]>, V> {
     fn remove(&mut self, key: &[u8]) -> Option<V> {
         self.remove(key)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use access::bencode::BRefAccess;
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    const NESTED: &'static [u8] = b"d4:infod5:filesd4:pathl4:spamee6:lengthi5e4:name4:testee";
+
+    #[test]
+    fn positive_lookup_path_deep() {
+        let bencode = BencodeRef::decode(NESTED, BDecodeOpt::default()).unwrap();
+        let dict = bencode.dict().unwrap();
+
+        let path = dict.lookup_path(&[b"info", b"files", b"path"]).unwrap();
+        assert_eq!("spam", path.list().unwrap()[0].str().unwrap());
+
+        let length = dict.lookup_path(&[b"info", b"length"]).unwrap();
+        assert_eq!(5, length.int().unwrap());
+    }
+
+    #[test]
+    fn negative_lookup_path_missing_key() {
+        let bencode = BencodeRef::decode(NESTED, BDecodeOpt::default()).unwrap();
+        let dict = bencode.dict().unwrap();
+
+        assert!(dict.lookup_path(&[b"info", b"missing", b"path"]).is_none());
+    }
+
+    #[test]
+    fn negative_lookup_path_wrong_type_intermediate() {
+        let bencode = BencodeRef::decode(NESTED, BDecodeOpt::default()).unwrap();
+        let dict = bencode.dict().unwrap();
+
+        assert!(dict.lookup_path(&[b"info", b"name", b"path"]).is_none());
+    }
+
+    #[test]
+    fn negative_lookup_path_empty() {
+        let bencode = BencodeRef::decode(NESTED, BDecodeOpt::default()).unwrap();
+        let dict = bencode.dict().unwrap();
+
+        assert!(dict.lookup_path(&[]).is_none());
+    }
+}
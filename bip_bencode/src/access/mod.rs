@@ -1,4 +1,5 @@
 pub mod bencode;
+pub mod compare;
 pub mod convert;
 pub mod dict;
 pub mod list;
\ No newline at end of file
@@ -0,0 +1,304 @@
+//! Deserialize bencode into rust types.
+
+use std::default::Default;
+use std::fmt::Display;
+use std::str;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Visitor};
+use serde::de::value::{BorrowedBytesDeserializer, BorrowedStrDeserializer};
+
+use access::bencode::{BRefAccess, BRefAccessExt, BencodeRefKind};
+use error::{BencodeSerdeError, BencodeSerdeErrorKind, BencodeSerdeResult};
+use reference::bencode_ref::BencodeRef;
+use reference::decode_opt::BDecodeOpt;
+
+impl de::Error for BencodeSerdeError {
+    fn custom<T: Display>(msg: T) -> BencodeSerdeError {
+        msg.to_string().into()
+    }
+}
+
+/// Deserialize the given bencoded bytes into a value, using the default decode options.
+pub fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> BencodeSerdeResult<T> {
+    from_bytes_opt(bytes, BDecodeOpt::default())
+}
+
+/// Deserialize the given bencoded bytes into a value, using the given decode options.
+pub fn from_bytes_opt<'de, T: Deserialize<'de>>(bytes: &'de [u8], opts: BDecodeOpt) -> BencodeSerdeResult<T> {
+    let bencode = try!(BencodeRef::decode(bytes, opts));
+
+    T::deserialize(Deserializer::new(&bencode))
+}
+
+/// Deserializer for converting a `BencodeRef` into rust types.
+///
+/// Integers are deserialized as `i64`, and bytes are borrowed from the original buffer.
+pub struct Deserializer<'a, 'de: 'a> {
+    bencode: &'a BencodeRef<'de>
+}
+
+impl<'a, 'de> Deserializer<'a, 'de> {
+    /// Create a new `Deserializer` for the given bencode.
+    pub fn new(bencode: &'a BencodeRef<'de>) -> Deserializer<'a, 'de> {
+        Deserializer{ bencode: bencode }
+    }
+}
+
+impl<'a, 'de> de::Deserializer<'de> for Deserializer<'a, 'de> {
+    type Error = BencodeSerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        match self.bencode.kind() {
+            BencodeRefKind::Int(n)   => visitor.visit_i64(n),
+            BencodeRefKind::Bytes(_) => visitor.visit_borrowed_bytes(self.bencode.bytes_ext().unwrap()),
+            BencodeRefKind::List(n)  => {
+                visitor.visit_seq(ListAccess{ values: n.into_iter().collect(), index: 0 })
+            },
+            BencodeRefKind::Dict(n)  => {
+                let mut entries = n.to_list();
+                entries.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+
+                visitor.visit_map(DictAccess{ entries: entries, index: 0 })
+            }
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        match self.bencode.int() {
+            Some(0) => visitor.visit_bool(false),
+            Some(1) => visitor.visit_bool(true),
+            _       => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        match self.bencode.str_ext() {
+            Some(n) => visitor.visit_borrowed_str(n),
+            None    => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        // Missing values never make it this far, they are left out of the dictionary
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> BencodeSerdeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> BencodeSerdeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> BencodeSerdeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V)
+        -> BencodeSerdeResult<V::Value> {
+        if let Some(variant) = self.bencode.str_ext() {
+            let deserializer: BorrowedStrDeserializer<BencodeSerdeError> = BorrowedStrDeserializer::new(variant);
+
+            return visitor.visit_enum(deserializer)
+        }
+
+        match self.bencode.dict().map(|dict| dict.to_list()) {
+            Some(ref entries) if entries.len() == 1 => {
+                let (variant, value) = entries[0];
+
+                visitor.visit_enum(VariantAccess{ variant: *variant, value: value })
+            },
+            _ => Err(BencodeSerdeError::from_kind(BencodeSerdeErrorKind::UnsupportedType{ type_name: "Enum" }))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf
+        seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ListAccess<'a, 'de: 'a> {
+    values: Vec<&'a BencodeRef<'de>>,
+    index:  usize
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for ListAccess<'a, 'de> {
+    type Error = BencodeSerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> BencodeSerdeResult<Option<T::Value>> {
+        match self.values.get(self.index) {
+            Some(value) => {
+                self.index += 1;
+
+                seed.deserialize(Deserializer::new(value)).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len() - self.index)
+    }
+}
+
+struct DictAccess<'a, 'de: 'a> {
+    entries: Vec<(&'a &'de [u8], &'a BencodeRef<'de>)>,
+    index:   usize
+}
+
+impl<'a, 'de> de::MapAccess<'de> for DictAccess<'a, 'de> {
+    type Error = BencodeSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> BencodeSerdeResult<Option<K::Value>> {
+        match self.entries.get(self.index) {
+            Some(&(key, _)) => {
+                let deserializer: BorrowedBytesDeserializer<BencodeSerdeError> = BorrowedBytesDeserializer::new(*key);
+
+                seed.deserialize(deserializer).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> BencodeSerdeResult<V::Value> {
+        let (_, value) = self.entries[self.index];
+        self.index += 1;
+
+        seed.deserialize(Deserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len() - self.index)
+    }
+}
+
+struct VariantAccess<'a, 'de: 'a> {
+    variant: &'de [u8],
+    value:   &'a BencodeRef<'de>
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for VariantAccess<'a, 'de> {
+    type Error   = BencodeSerdeError;
+    type Variant = Deserializer<'a, 'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> BencodeSerdeResult<(V::Value, Deserializer<'a, 'de>)> {
+        let variant = try!(str::from_utf8(self.variant).map_err(|_| {
+            BencodeSerdeError::from_kind(BencodeSerdeErrorKind::InvalidKey{ expected_type: "UTF-8 Bytes" })
+        }));
+        let deserializer: BorrowedStrDeserializer<BencodeSerdeError> = BorrowedStrDeserializer::new(variant);
+        let value = try!(seed.deserialize(deserializer));
+
+        Ok((value, Deserializer::new(self.value)))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for Deserializer<'a, 'de> {
+    type Error = BencodeSerdeError;
+
+    fn unit_variant(self) -> BencodeSerdeResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> BencodeSerdeResult<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> BencodeSerdeResult<V::Value> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> BencodeSerdeResult<V::Value> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_bytes;
+
+    use de;
+    use ser;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Peer {
+        ip:   String,
+        port: u16
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Started,
+        Stopped { reason: String },
+        Bytes(i64)
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Response {
+        interval:    i64,
+        #[serde(rename = "tracker id")]
+        tracker_id:  Option<String>,
+        warning:     Option<String>,
+        peers:       Vec<Peer>,
+        #[serde(with = "serde_bytes")]
+        peers6:      Vec<u8>,
+        events:      Vec<Event>,
+        complete:    bool
+    }
+
+    #[test]
+    fn positive_round_trip_nested() {
+        let response = Response{
+            interval:   1800,
+            tracker_id: Some("abc".to_owned()),
+            warning:    None,
+            peers:      vec![Peer{ ip: "127.0.0.1".to_owned(), port: 6881 }, Peer{ ip: "::1".to_owned(), port: 80 }],
+            peers6:     vec![0xFF, 0x00, 0xFE],
+            events:     vec![Event::Started, Event::Stopped{ reason: "done".to_owned() }, Event::Bytes(-5)],
+            complete:   true
+        };
+
+        let bytes = ser::to_bytes(&response).unwrap();
+        let decoded: Response = de::from_bytes(&bytes).unwrap();
+
+        assert_eq!(response, decoded);
+    }
+
+    #[test]
+    fn positive_from_bytes_borrowed() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(with = "serde_bytes")]
+            hash: &'a [u8]
+        }
+
+        let bytes = b"d4:hash2:\xFF\xFE4:name4:teste";
+        let borrowed: Borrowed = de::from_bytes(&bytes[..]).unwrap();
+
+        assert_eq!("test", borrowed.name);
+        assert_eq!(&b"\xFF\xFE"[..], borrowed.hash);
+    }
+
+    #[test]
+    fn positive_to_bytes_struct_sorted() {
+        let peer = Peer{ ip: "a".to_owned(), port: 1 };
+
+        assert_eq!(&b"d2:ip1:a4:porti1ee"[..], &ser::to_bytes(&peer).unwrap()[..]);
+    }
+
+    #[test]
+    fn negative_from_bytes_wrong_type() {
+        de::from_bytes::<Peer>(b"d2:ipi5e4:porti1ee").unwrap_err();
+    }
+
+    #[test]
+    fn negative_from_bytes_int_out_of_range() {
+        de::from_bytes::<Peer>(b"d2:ip1:a4:porti70000ee").unwrap_err();
+    }
+}
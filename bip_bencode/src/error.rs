@@ -40,6 +40,12 @@ error_chain! {
             description("Invalid Integer Found To Fail Parsing")
             display("Invalid Integer Found To Fail Parsing At {:?}", pos)
         }
+        InvalidIntOverflow {
+            pos: usize
+         } {
+            description("Invalid Integer Found To Overflow An i64")
+            display("Invalid Integer Found To Overflow An i64 At {:?}", pos)
+        }
         InvalidKeyOrdering {
             pos: usize,
             key: Vec<u8>
@@ -66,6 +72,13 @@ error_chain! {
             description("Invalid Byte Length Found To Overflow Buffer Length")
             display("Invalid Byte Length Found To Overflow Buffer Length At {:?}", pos)
         }
+        InvalidLengthExceeded {
+            pos: usize,
+            max: usize
+        } {
+            description("Invalid Byte Length Found To Exceed The Maximum")
+            display("Invalid Byte Length Found To Exceed The Maximum At {:?} For Maximum {:?}", pos, max)
+        }
         InvalidRecursionExceeded {
             pos: usize,
             max: usize
@@ -73,6 +86,13 @@ error_chain! {
             description("Invalid Recursion Limit Exceeded")
             display("Invalid Recursion Limit Exceeded At {:?} For Limit {:?}", pos, max)
         }
+        InvalidBufferExceeded {
+            pos: usize,
+            max: usize
+        } {
+            description("Invalid Value Found To Exceed The Buffer Limit")
+            display("Invalid Value Found To Exceed The Buffer Limit At {:?} For Limit {:?}", pos, max)
+        }
     }
 }
 
@@ -95,5 +115,117 @@ error_chain! {
             description("Wrong Type In Bencode")
             display("Wrong Type In Bencode For {:?} Expected Type {}", key, expected_type)
         }
+        InvalidJsonValue {
+            path:    String,
+            details: String
+         } {
+            description("Invalid JSON Value For Bencode")
+            display("Invalid JSON Value For Bencode At {:?}: {}", path, details)
+        }
+    }
+}
+
+error_chain! {
+    types {
+        BencodeSerdeError, BencodeSerdeErrorKind, BencodeSerdeResultExt, BencodeSerdeResult;
+    }
+
+    links {
+        BencodeParse(BencodeParseError, BencodeParseErrorKind);
+    }
+
+    errors {
+        UnsupportedType {
+            type_name: &'static str
+         } {
+            description("Unsupported Type For Bencode")
+            display("Unsupported Type For Bencode Found As {}", type_name)
+        }
+        IntegerOverflow {
+            value: u64
+         } {
+            description("Integer Does Not Fit In An i64")
+            display("Integer Does Not Fit In An i64 For {:?}", value)
+        }
+        InvalidKey {
+            expected_type: &'static str
+         } {
+            description("Invalid Dictionary Key Type")
+            display("Invalid Dictionary Key Type Expected Type {}", expected_type)
+        }
+    }
+}
+
+impl BencodeParseErrorKind {
+    /// Index into the input bytes where the parser gave up.
+    ///
+    /// Returns `None` for errors that were not generated by the parser.
+    pub fn byte_offset(&self) -> Option<usize> {
+        match *self {
+            BencodeParseErrorKind::BytesEmpty{ pos }                 |
+            BencodeParseErrorKind::InvalidByte{ pos }                |
+            BencodeParseErrorKind::InvalidIntNoDelimiter{ pos }      |
+            BencodeParseErrorKind::InvalidIntNegativeZero{ pos }     |
+            BencodeParseErrorKind::InvalidIntZeroPadding{ pos }      |
+            BencodeParseErrorKind::InvalidIntParseError{ pos }       |
+            BencodeParseErrorKind::InvalidIntOverflow{ pos }         |
+            BencodeParseErrorKind::InvalidKeyOrdering{ pos, .. }     |
+            BencodeParseErrorKind::InvalidKeyDuplicates{ pos, .. }   |
+            BencodeParseErrorKind::InvalidLengthNegative{ pos }      |
+            BencodeParseErrorKind::InvalidLengthOverflow{ pos }      |
+            BencodeParseErrorKind::InvalidLengthExceeded{ pos, .. }  |
+            BencodeParseErrorKind::InvalidBufferExceeded{ pos, .. }  |
+            BencodeParseErrorKind::InvalidRecursionExceeded{ pos, .. } => Some(pos),
+            _ => None
+        }
+    }
+
+    /// Description of the token the parser expected to find at the byte offset.
+    pub fn expected(&self) -> &'static str {
+        match *self {
+            BencodeParseErrorKind::BytesEmpty{ .. }               => "More Bytes",
+            BencodeParseErrorKind::InvalidByte{ .. }              => "Integer, List, Dictionary, Or Byte Length Start",
+            BencodeParseErrorKind::InvalidIntNoDelimiter{ .. }    => "Integer Or Byte Length Delimiter",
+            BencodeParseErrorKind::InvalidIntNegativeZero{ .. }   => "Integer Without A Negative Zero",
+            BencodeParseErrorKind::InvalidIntZeroPadding{ .. }    => "Integer Without Zero Padding",
+            BencodeParseErrorKind::InvalidIntParseError{ .. }     => "Decimal Integer",
+            BencodeParseErrorKind::InvalidIntOverflow{ .. }       => "Integer Within The i64 Range",
+            BencodeParseErrorKind::InvalidKeyOrdering{ .. }       => "Dictionary Key In Sorted Order",
+            BencodeParseErrorKind::InvalidKeyDuplicates{ .. }     => "Unique Dictionary Key",
+            BencodeParseErrorKind::InvalidLengthNegative{ .. }    => "Non Negative Byte Length",
+            BencodeParseErrorKind::InvalidLengthOverflow{ .. }    => "Byte Length Within The Buffer",
+            BencodeParseErrorKind::InvalidLengthExceeded{ .. }    => "Byte Length Within The Maximum",
+            BencodeParseErrorKind::InvalidBufferExceeded{ .. }    => "Value Within The Buffer Limit",
+            BencodeParseErrorKind::InvalidRecursionExceeded{ .. } => "Value Within The Recursion Limit",
+            _ => "Unknown"
+        }
+    }
+
+    /// Description of the token the parser found at the byte offset.
+    pub fn found(&self) -> &'static str {
+        match *self {
+            BencodeParseErrorKind::BytesEmpty{ .. }               => "End Of Input",
+            BencodeParseErrorKind::InvalidByte{ .. }              => "Unexpected Byte",
+            BencodeParseErrorKind::InvalidIntNoDelimiter{ .. }    => "End Of Input",
+            BencodeParseErrorKind::InvalidIntNegativeZero{ .. }   => "Negative Zero",
+            BencodeParseErrorKind::InvalidIntZeroPadding{ .. }    => "Zero Padded Integer",
+            BencodeParseErrorKind::InvalidIntParseError{ .. }     => "Non Numeric Integer",
+            BencodeParseErrorKind::InvalidIntOverflow{ .. }       => "Out Of Range Integer",
+            BencodeParseErrorKind::InvalidKeyOrdering{ .. }       => "Out Of Order Dictionary Key",
+            BencodeParseErrorKind::InvalidKeyDuplicates{ .. }     => "Duplicate Dictionary Key",
+            BencodeParseErrorKind::InvalidLengthNegative{ .. }    => "Negative Byte Length",
+            BencodeParseErrorKind::InvalidLengthOverflow{ .. }    => "Byte Length Past The Buffer",
+            BencodeParseErrorKind::InvalidLengthExceeded{ .. }    => "Byte Length Past The Maximum",
+            BencodeParseErrorKind::InvalidBufferExceeded{ .. }    => "Value Past The Buffer Limit",
+            BencodeParseErrorKind::InvalidRecursionExceeded{ .. } => "Nested Value Past The Recursion Limit",
+            _ => "Unknown"
+        }
+    }
+}
+
+impl BencodeParseError {
+    /// See `BencodeParseErrorKind::byte_offset`.
+    pub fn byte_offset(&self) -> Option<usize> {
+        self.kind().byte_offset()
     }
 }
@@ -0,0 +1,249 @@
+use std::borrow::Cow;
+use std::str;
+
+use base64;
+use serde_json::{Map, Value};
+
+use access::bencode::{BMutAccess, BRefAccess, BencodeRefKind};
+use error::{BencodeConvertError, BencodeConvertErrorKind, BencodeConvertResult};
+use mutable::bencode_mut::BencodeMut;
+
+/// Prefix applied to JSON strings holding base64 encoded bytes.
+///
+/// Byte strings and dictionary keys that are not valid UTF-8 are base64 encoded and given
+/// this prefix. UTF-8 strings that happen to start with the prefix are encoded the same way,
+/// so that every JSON string maps back to exactly one byte string. When converting back, any
+/// string with this prefix is base64 decoded, and any other string is taken as its UTF-8 bytes.
+pub const BASE64_PREFIX: &'static str = "base64:";
+
+/// Convert the given bencode into a JSON value.
+///
+/// Dictionaries map to objects, lists to arrays, integers to numbers, and bytes to strings;
+/// see `BASE64_PREFIX` for how bytes that are not valid UTF-8 are represented.
+pub fn to_json_value<T>(bencode: &T) -> Value
+    where T: BRefAccess, T::BKey: AsRef<[u8]> {
+    match bencode.kind() {
+        BencodeRefKind::Int(n)   => Value::from(n),
+        BencodeRefKind::Bytes(n) => Value::String(bytes_to_json_str(n)),
+        BencodeRefKind::List(n)  => Value::Array(n.into_iter().map(to_json_value).collect()),
+        BencodeRefKind::Dict(n)  => {
+            let mut map = Map::new();
+
+            for (key, value) in n.to_list() {
+                map.insert(bytes_to_json_str(key.as_ref()), to_json_value(value));
+            }
+
+            Value::Object(map)
+        }
+    }
+}
+
+/// Convert the given JSON value back into bencode.
+///
+/// The reverse of `to_json_value`, so numbers must be integers that fit in an `i64`, and strings
+/// are converted to bytes as described in `BASE64_PREFIX`. Nulls, booleans, and floats have no
+/// bencode equivalent and produce an error, with a JSON pointer to the offending value.
+pub fn from_json_value(json: &Value) -> BencodeConvertResult<BencodeMut<'static>> {
+    value_from_json(json, &mut String::new())
+}
+
+fn value_from_json(json: &Value, path: &mut String) -> BencodeConvertResult<BencodeMut<'static>> {
+    match *json {
+        Value::Number(ref n) => {
+            n.as_i64()
+                .map(BencodeMut::new_int)
+                .ok_or_else(|| invalid_json(path, format!("Number {} Is Not An Integer Within The i64 Range", n)))
+        },
+        Value::String(ref n) => json_str_to_bytes(n, path).map(|bytes| BencodeMut::new_bytes(Cow::Owned(bytes))),
+        Value::Array(ref n)  => {
+            let mut bencode = BencodeMut::new_list();
+            {
+                let list = bencode.list_mut().unwrap();
+
+                for (index, value) in n.iter().enumerate() {
+                    let path_len = push_path(path, &index.to_string());
+                    list.push(try!(value_from_json(value, path)));
+                    path.truncate(path_len);
+                }
+            }
+
+            Ok(bencode)
+        },
+        Value::Object(ref n) => {
+            let mut bencode = BencodeMut::new_dict();
+            {
+                let dict = bencode.dict_mut().unwrap();
+
+                for (key, value) in n.iter() {
+                    let path_len = push_path(path, key);
+                    let key_bytes = try!(json_str_to_bytes(key, path));
+                    let value_bencode = try!(value_from_json(value, path));
+                    path.truncate(path_len);
+
+                    // Distinct JSON keys can only collide if one is a redundant base64 form of the other
+                    if dict.insert(Cow::Owned(key_bytes), value_bencode).is_some() {
+                        return Err(invalid_json(path, format!("Key {:?} Duplicates Another Key Once Decoded", key)))
+                    }
+                }
+            }
+
+            Ok(bencode)
+        },
+        Value::Null    => Err(invalid_json(path, "Null Has No Bencode Equivalent".to_owned())),
+        Value::Bool(_) => Err(invalid_json(path, "Boolean Has No Bencode Equivalent".to_owned()))
+    }
+}
+
+/// Push the given key on to the JSON pointer, returning the length of the pointer before the push.
+fn push_path(path: &mut String, key: &str) -> usize {
+    let path_len = path.len();
+
+    path.push('/');
+    path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+
+    path_len
+}
+
+fn invalid_json(path: &str, details: String) -> BencodeConvertError {
+    BencodeConvertError::from_kind(BencodeConvertErrorKind::InvalidJsonValue{ path: path.to_owned(), details: details })
+}
+
+fn json_str_to_bytes(json_str: &str, path: &str) -> BencodeConvertResult<Vec<u8>> {
+    if json_str.starts_with(BASE64_PREFIX) {
+        base64::decode(&json_str[BASE64_PREFIX.len()..])
+            .map_err(|_| invalid_json(path, format!("String {:?} Has An Invalid Base64 Payload", json_str)))
+    } else {
+        Ok(json_str.as_bytes().to_vec())
+    }
+}
+
+fn bytes_to_json_str(bytes: &[u8]) -> String {
+    match str::from_utf8(bytes) {
+        Ok(n) if !n.starts_with(BASE64_PREFIX) => n.to_owned(),
+        _ => format!("{}{}", BASE64_PREFIX, base64::encode(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use serde_json::{self, Value};
+
+    use access::bencode::BRefAccess;
+    use error::BencodeConvertErrorKind;
+    use json::{self, BASE64_PREFIX};
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    const TORRENT: &'static [u8] = b"d8:announce17:udp://test.com:804:infod6:lengthi1024e4:name8:test.txt12:piece lengthi512e6:pieces4:\x00\xFF\x10\x20ee";
+
+    #[test]
+    fn positive_to_json_value_torrent() {
+        let bencode = BencodeRef::decode(TORRENT, BDecodeOpt::default()).unwrap();
+        let json = json::to_json_value(&bencode);
+
+        assert_eq!("udp://test.com:80", json["announce"]);
+        assert_eq!(1024, json["info"]["length"]);
+        assert_eq!("test.txt", json["info"]["name"]);
+        assert_eq!(512, json["info"]["piece length"]);
+        assert_eq!(format!("{}AP8QIA==", BASE64_PREFIX), json["info"]["pieces"]);
+    }
+
+    #[test]
+    fn positive_json_round_trip_torrent() {
+        let bencode = BencodeRef::decode(TORRENT, BDecodeOpt::default()).unwrap();
+        let json_text = serde_json::to_string(&json::to_json_value(&bencode)).unwrap();
+
+        let json_value = serde_json::from_str(&json_text).unwrap();
+        let round_trip = json::from_json_value(&json_value).unwrap();
+
+        assert_eq!(TORRENT, &round_trip.encode()[..]);
+    }
+
+    #[test]
+    fn positive_json_round_trip_non_utf8_and_prefixed_keys() {
+        let bytes = b"d8:base64:al3:abce12:not_prefixedi-9223372036854775808e2:\xFF\xFEi1ee";
+        let bencode = BencodeRef::decode(bytes, BDecodeOpt::default()).unwrap();
+
+        let round_trip = json::from_json_value(&json::to_json_value(&bencode)).unwrap();
+
+        assert_eq!(&bytes[..], &round_trip.encode()[..]);
+    }
+
+    #[test]
+    fn positive_from_json_value_edited_torrent() {
+        let bencode = BencodeRef::decode(TORRENT, BDecodeOpt::default()).unwrap();
+        let mut json_value = json::to_json_value(&bencode);
+
+        json_value["announce"] = Value::from("udp://other.com:80");
+        let edited = json::from_json_value(&json_value).unwrap();
+
+        assert_eq!("udp://other.com:80", edited.dict().unwrap().lookup(b"announce").unwrap().str().unwrap());
+        assert_eq!(&b"\x00\xFF\x10\x20"[..], edited.dict().unwrap().lookup(b"info").unwrap()
+                                                 .dict().unwrap().lookup(b"pieces").unwrap().bytes().unwrap());
+    }
+
+    fn invalid_json_path(json_value: Value) -> String {
+        match json::from_json_value(&json_value).unwrap_err().0 {
+            BencodeConvertErrorKind::InvalidJsonValue{ path, .. } => path,
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_from_json_value_float() {
+        assert_eq!("/info/length", invalid_json_path(serde_json::from_str(r#"{"info":{"length":1.5}}"#).unwrap()));
+    }
+
+    #[test]
+    fn negative_from_json_value_integer_overflow() {
+        assert_eq!("", invalid_json_path(serde_json::from_str("9223372036854775808").unwrap()));
+    }
+
+    #[test]
+    fn negative_from_json_value_null_and_bool() {
+        assert_eq!("/0", invalid_json_path(serde_json::from_str("[null]").unwrap()));
+        assert_eq!("/a~1b/1", invalid_json_path(serde_json::from_str(r#"{"a/b":[1,true]}"#).unwrap()));
+    }
+
+    #[test]
+    fn negative_from_json_value_bad_base64() {
+        let json_value = Value::from(format!("{}not base64!", BASE64_PREFIX));
+
+        assert_eq!("", invalid_json_path(json_value));
+    }
+
+    #[test]
+    fn negative_from_json_value_duplicate_decoded_keys() {
+        let json_value = serde_json::from_str(&format!(r#"{{"a":1,"{}YQ==":2}}"#, BASE64_PREFIX)).unwrap();
+
+        assert_eq!("", invalid_json_path(json_value));
+    }
+
+    #[test]
+    fn positive_to_json_value_list() {
+        let bencode = BencodeRef::decode(b"li-5e3:asdlee", BDecodeOpt::default()).unwrap();
+        let json = json::to_json_value(&bencode);
+
+        assert_eq!(-5, json[0]);
+        assert_eq!("asd", json[1]);
+        assert!(json[2].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn positive_to_json_value_non_utf8_key() {
+        let bencode = BencodeRef::decode(b"d2:\xFF\xFEi1ee", BDecodeOpt::default()).unwrap();
+        let json = json::to_json_value(&bencode);
+
+        assert_eq!(1, json[&format!("{}//4=", BASE64_PREFIX)]);
+    }
+
+    #[test]
+    fn positive_to_json_value_prefixed_utf8() {
+        let bencode = BencodeRef::decode(b"8:base64:a", BDecodeOpt::default()).unwrap();
+        let json = json::to_json_value(&bencode);
+
+        assert_eq!(format!("{}YmFzZTY0OmE=", BASE64_PREFIX), json);
+    }
+}
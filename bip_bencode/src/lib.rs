@@ -37,12 +37,33 @@
 
 #[macro_use]
 extern crate error_chain;
+#[cfg(feature = "serde_json")]
+extern crate base64;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_bytes;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
 mod access;
 mod cow;
 mod mutable;
 mod reference;
 mod error;
+#[cfg(feature = "serde_json")]
+mod json;
+mod pretty;
+mod stream;
+
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "serde")]
+pub mod ser;
 
 /// Traits for implementation functionality.
 pub mod inner {
@@ -58,12 +79,18 @@ pub mod ext {
 pub use reference::bencode_ref::{BencodeRef};
 pub use mutable::bencode_mut::{BencodeMut};
 pub use access::bencode::{BRefAccess, BencodeRefKind, BMutAccess, BencodeMutKind};
+pub use access::compare::structural_eq;
 pub use access::convert::{BConvert};
 pub use access::dict::BDictAccess;
 pub use access::list::BListAccess;
 pub use reference::decode_opt::BDecodeOpt;
+pub use stream::StreamDecoder;
+pub use pretty::{BPretty, BPrettyOpt, pretty_print};
+#[cfg(feature = "serde_json")]
+pub use json::{BASE64_PREFIX, from_json_value, to_json_value};
 pub use error::{BencodeParseError, BencodeParseErrorKind, BencodeParseResult};
 pub use error::{BencodeConvertError, BencodeConvertErrorKind, BencodeConvertResult};
+pub use error::{BencodeSerdeError, BencodeSerdeErrorKind, BencodeSerdeResult};
 
 const BEN_END: u8 = b'e';
 const DICT_START: u8 = b'd';
@@ -77,7 +104,7 @@ const BYTE_LEN_END: u8 = b':';
 /// Construct a `BencodeMut` map by supplying string references as keys and `BencodeMut` as values.
 #[macro_export]
 macro_rules! ben_map {
-( $($key:expr => $val:expr),* ) => {
+    ( $($key:expr => $val:expr),* $(,)? ) => {
         {
             use bip_bencode::{BMutAccess, BencodeMut};
             use bip_bencode::inner::BCowConvert;
@@ -98,7 +125,7 @@ macro_rules! ben_map {
 /// Construct a `BencodeMut` list by supplying a list of `BencodeMut` values.
 #[macro_export]
 macro_rules! ben_list {
-    ( $($ben:expr),* ) => {
+    ( $($ben:expr),* $(,)? ) => {
         {
             use bip_bencode::{BencodeMut, BMutAccess};
             
@@ -115,6 +142,16 @@ macro_rules! ben_list {
     }
 }
 
+/// Construct a `BencodeMut` dictionary by supplying string references as keys and `BencodeMut` as values.
+///
+/// Alias for `ben_map!`, pairs with `ben_list!` when building nested structures inline.
+#[macro_export]
+macro_rules! ben_dict {
+    ( $($key:expr => $val:expr),* $(,)? ) => {
+        ben_map!{ $($key => $val),* }
+    }
+}
+
 /// Construct `BencodeMut` bytes by supplying a type convertible to `Vec<u8>`.
 #[macro_export]
 macro_rules! ben_bytes {
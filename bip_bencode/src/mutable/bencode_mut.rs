@@ -21,6 +21,10 @@ pub enum InnerBencodeMut<'a> {
 }
 
 /// `BencodeMut` object that stores references to some data.
+///
+/// Equality is based on content, dictionaries compare equal regardless of insertion order.
+/// Cloning is deep, so nested lists and dictionaries of the clone can be modified without
+/// affecting the original.
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub struct BencodeMut<'a> {
     inner:   InnerBencodeMut<'a>
@@ -51,6 +55,24 @@ impl<'a> BencodeMut<'a> {
         BencodeMut::new(InnerBencodeMut::Dict(BTreeMap::new()))
     }
 
+    /// Convert the `BencodeMut` into one that owns all of its bytes, so it no longer borrows any data.
+    ///
+    /// Useful for keeping a template, built from borrowed keys or values, around to clone variants from.
+    pub fn into_owned(self) -> BencodeMut<'static> {
+        let inner = match self.inner {
+            InnerBencodeMut::Int(n)   => InnerBencodeMut::Int(n),
+            InnerBencodeMut::Bytes(n) => InnerBencodeMut::Bytes(Cow::Owned(n.into_owned())),
+            InnerBencodeMut::List(n)  => InnerBencodeMut::List(n.into_iter().map(BencodeMut::into_owned).collect()),
+            InnerBencodeMut::Dict(n)  => {
+                InnerBencodeMut::Dict(n.into_iter()
+                    .map(|(key, value)| (Cow::Owned(key.into_owned()), value.into_owned()))
+                    .collect())
+            }
+        };
+
+        BencodeMut::new(inner)
+    }
+
     /// Encode the `BencodeMut` into a buffer representing the bencode.
     pub fn encode(&self) -> Vec<u8> {
         let mut buffer = Vec::new();
@@ -142,8 +164,69 @@ impl<'a> BMutAccess for BencodeMut<'a> {
 
 #[cfg(test)]
 mod test {
-    use access::bencode::BMutAccess;
+    use access::bencode::{BMutAccess, BRefAccess};
     use mutable::bencode_mut::BencodeMut;
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    fn info_template() -> BencodeMut<'static> {
+        let mut info = BencodeMut::new_dict();
+        {
+            let mut file = BencodeMut::new_dict();
+            {
+                let file_mut = file.dict_mut().unwrap();
+                file_mut.insert((&b"length"[..]).into(), BencodeMut::new_int(1024));
+                file_mut.insert((&b"path"[..]).into(), BencodeMut::new_bytes((&b"a.txt"[..]).into()));
+            }
+            let mut files = BencodeMut::new_list();
+            files.list_mut().unwrap().push(file);
+
+            let info_mut = info.dict_mut().unwrap();
+            info_mut.insert((&b"files"[..]).into(), files);
+            info_mut.insert((&b"name"[..]).into(), BencodeMut::new_bytes((&b"template"[..]).into()));
+        }
+
+        info
+    }
+
+    #[test]
+    fn positive_clone_nested_dict_independent() {
+        let original = info_template();
+        let original_bytes = original.encode();
+
+        let mut variant = original.clone();
+        assert_eq!(original, variant);
+        {
+            let variant_mut = variant.dict_mut().unwrap();
+            variant_mut.insert((&b"name"[..]).into(), BencodeMut::new_bytes((&b"variant"[..]).into()));
+
+            let files = variant_mut.lookup_mut(b"files").unwrap().list_mut().unwrap();
+            let file = files.get_mut(0).unwrap().dict_mut().unwrap();
+            file.insert((&b"length"[..]).into(), BencodeMut::new_int(2048));
+        }
+
+        assert!(original != variant);
+        assert_eq!(original_bytes, original.encode());
+        assert_eq!(1024, original.dict().unwrap().lookup(b"files").unwrap().list().unwrap().get(0).unwrap()
+                                 .dict().unwrap().lookup(b"length").unwrap().int().unwrap());
+        assert_eq!(2048, variant.dict().unwrap().lookup(b"files").unwrap().list().unwrap().get(0).unwrap()
+                                .dict().unwrap().lookup(b"length").unwrap().int().unwrap());
+    }
+
+    #[test]
+    fn positive_into_owned_outlives_buffer() {
+        let owned = {
+            let buffer = b"4:name8:template".to_vec();
+
+            let mut borrowed = BencodeMut::new_dict();
+            borrowed.dict_mut().unwrap().insert((&buffer[2..6]).into(), BencodeMut::new_bytes((&buffer[8..16]).into()));
+
+            assert_eq!(borrowed, borrowed.clone().into_owned());
+            borrowed.into_owned()
+        };
+
+        assert_eq!(&b"d4:name8:templatee"[..], &owned.encode()[..]);
+    }
 
     #[test]
     fn positive_int_encode() {
@@ -202,4 +285,76 @@ mod test {
         let dict_bytes = b"d3:asd6:asdasde";
         assert_eq!(&dict_bytes[..], &bencode_dict.encode()[..]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn positive_unordered_dict_encode_sorted() {
+        let mut bencode_dict = BencodeMut::new_dict();
+
+        {
+            let dict_mut = bencode_dict.dict_mut().unwrap();
+            dict_mut.insert((&b"zz"[..]).into(), BencodeMut::new_int(1));
+            dict_mut.insert((&b"a"[..]).into(), BencodeMut::new_int(2));
+            dict_mut.insert((&b"ab"[..]).into(), BencodeMut::new_int(3));
+            dict_mut.insert((&b"B"[..]).into(), BencodeMut::new_int(4));
+        }
+
+        let dict_bytes = b"d1:Bi4e1:ai2e2:abi3e2:zzi1ee";
+        let encoded = bencode_dict.encode();
+        assert_eq!(&dict_bytes[..], &encoded[..]);
+
+        // Decoding with key sort checking enabled should accept the canonical ordering
+        let decoded = BencodeRef::decode(&encoded, BDecodeOpt::new(2, true, true)).unwrap();
+        assert_eq!(3, decoded.dict().unwrap().lookup(b"ab").unwrap().int().unwrap());
+    }
+
+    #[test]
+    fn positive_dict_encode_insertion_order_independent() {
+        let mut first_dict = BencodeMut::new_dict();
+        let mut second_dict = BencodeMut::new_dict();
+
+        {
+            let dict_mut = first_dict.dict_mut().unwrap();
+            dict_mut.insert((&b"b"[..]).into(), BencodeMut::new_int(1));
+            dict_mut.insert((&b"a"[..]).into(), BencodeMut::new_int(2));
+        }
+        {
+            let dict_mut = second_dict.dict_mut().unwrap();
+            dict_mut.insert((&b"a"[..]).into(), BencodeMut::new_int(2));
+            dict_mut.insert((&b"b"[..]).into(), BencodeMut::new_int(1));
+        }
+
+        assert_eq!(first_dict.encode(), second_dict.encode());
+    }
+
+    #[test]
+    fn positive_eq_dict_insertion_order() {
+        let mut first_dict = BencodeMut::new_dict();
+        let mut second_dict = BencodeMut::new_dict();
+
+        {
+            let dict_mut = first_dict.dict_mut().unwrap();
+            dict_mut.insert((&b"b"[..]).into(), BencodeMut::new_int(1));
+            dict_mut.insert((&b"a"[..]).into(), BencodeMut::new_int(2));
+        }
+        {
+            let dict_mut = second_dict.dict_mut().unwrap();
+            dict_mut.insert((&b"a"[..]).into(), BencodeMut::new_int(2));
+            dict_mut.insert((&b"b"[..]).into(), BencodeMut::new_int(1));
+        }
+
+        assert_eq!(first_dict, second_dict);
+    }
+
+    #[test]
+    fn negative_eq_list_order() {
+        let mut first_list = BencodeMut::new_list();
+        let mut second_list = BencodeMut::new_list();
+
+        first_list.list_mut().unwrap().push(BencodeMut::new_int(1));
+        first_list.list_mut().unwrap().push(BencodeMut::new_int(2));
+        second_list.list_mut().unwrap().push(BencodeMut::new_int(2));
+        second_list.list_mut().unwrap().push(BencodeMut::new_int(1));
+
+        assert_ne!(first_list, second_list);
+    }
+}
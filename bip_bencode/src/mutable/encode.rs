@@ -43,7 +43,8 @@ fn encode_list<T>(list: &BListAccess<T>, bytes: &mut Vec<u8>)
 
 fn encode_dict<'a, K, V>(dict: &BDictAccess<K, V>, bytes: &mut Vec<u8>)
     where K: AsRef<[u8]>, V: BRefAccess, V::BKey: AsRef<[u8]> {
-    // Need To Sort The Keys In The Map Before Encoding
+    // Need To Sort The Keys In The Map Before Encoding, Not All BDictAccess
+    // Implementations Are Ordered, And The Spec Requires Keys Sorted As Raw Bytes
     let mut sort_dict = dict.to_list();
     sort_dict.sort_by(|&(a, _), &(b, _)| a.as_ref().cmp(b.as_ref()));
 
@@ -0,0 +1,178 @@
+use std::default::Default;
+use std::fmt::{self, Display, Formatter};
+use std::str;
+
+use access::bencode::{BRefAccess, BencodeRefKind};
+use access::dict::BDictAccess;
+use access::list::BListAccess;
+
+const DEFAULT_INDENT:            usize = 4;
+const DEFAULT_MAX_BYTES_PREVIEW: usize = 64;
+
+/// Stores options for modifying pretty print behavior.
+#[derive(Copy, Clone)]
+pub struct BPrettyOpt {
+    indent:            usize,
+    max_bytes_preview: usize
+}
+
+impl BPrettyOpt {
+    /// Create a new `BPrettyOpt` object.
+    pub fn new(indent: usize, max_bytes_preview: usize) -> BPrettyOpt {
+        BPrettyOpt{ indent: indent, max_bytes_preview: max_bytes_preview }
+    }
+
+    /// Number of spaces to indent each nested level by.
+    pub fn indent(&self) -> usize {
+        self.indent
+    }
+
+    /// Maximum number of bytes of a UTF-8 string to display before truncating it.
+    pub fn max_bytes_preview(&self) -> usize {
+        self.max_bytes_preview
+    }
+}
+
+impl Default for BPrettyOpt {
+    fn default() -> BPrettyOpt {
+        BPrettyOpt::new(DEFAULT_INDENT, DEFAULT_MAX_BYTES_PREVIEW)
+    }
+}
+
+/// Wrapper for displaying some bencode as indented, human readable text.
+///
+/// Bytes that are valid UTF-8 are displayed as strings, otherwise they are
+/// displayed as `Bytes(len)`. Dictionary keys are displayed in sorted order.
+pub struct BPretty<'a, T: 'a> {
+    bencode: &'a T,
+    opts:    BPrettyOpt
+}
+
+impl<'a, T> BPretty<'a, T> {
+    /// Create a new `BPretty` object for the given bencode.
+    pub fn new(bencode: &'a T, opts: BPrettyOpt) -> BPretty<'a, T> {
+        BPretty{ bencode: bencode, opts: opts }
+    }
+}
+
+impl<'a, T> Display for BPretty<'a, T>
+    where T: BRefAccess, T::BKey: AsRef<[u8]> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_value(f, self.bencode, self.opts, 0)
+    }
+}
+
+/// Render the given bencode as indented, human readable text.
+pub fn pretty_print<T>(bencode: &T, opts: BPrettyOpt) -> String
+    where T: BRefAccess, T::BKey: AsRef<[u8]> {
+    BPretty::new(bencode, opts).to_string()
+}
+
+fn write_value<T>(f: &mut Formatter, value: &T, opts: BPrettyOpt, level: usize) -> fmt::Result
+    where T: BRefAccess, T::BKey: AsRef<[u8]> {
+    match value.kind() {
+        BencodeRefKind::Int(n)   => write!(f, "{}", n),
+        BencodeRefKind::Bytes(n) => write_bytes(f, n, opts),
+        BencodeRefKind::List(n)  => write_list(f, n, opts, level),
+        BencodeRefKind::Dict(n)  => write_dict(f, n, opts, level)
+    }
+}
+
+fn write_bytes(f: &mut Formatter, bytes: &[u8], opts: BPrettyOpt) -> fmt::Result {
+    match str::from_utf8(bytes) {
+        Ok(n) if n.len() > opts.max_bytes_preview() => {
+            // Back up to a char boundary so we dont split a code point
+            let mut end = opts.max_bytes_preview();
+            while !n.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            write!(f, "{:?}...", &n[..end])
+        },
+        Ok(n)  => write!(f, "{:?}", n),
+        Err(_) => write!(f, "Bytes({})", bytes.len())
+    }
+}
+
+fn write_list<T>(f: &mut Formatter, list: &BListAccess<T>, opts: BPrettyOpt, level: usize) -> fmt::Result
+    where T: BRefAccess, T::BKey: AsRef<[u8]> {
+    if list.len() == 0 {
+        return write!(f, "List []")
+    }
+
+    try!(writeln!(f, "List ["));
+    for (index, value) in list.into_iter().enumerate() {
+        try!(write_indent(f, opts, level + 1));
+        try!(write_value(f, value, opts, level + 1));
+
+        if index + 1 != list.len() {
+            try!(write!(f, ","));
+        }
+        try!(writeln!(f, ""));
+    }
+    try!(write_indent(f, opts, level));
+
+    write!(f, "]")
+}
+
+fn write_dict<K, V>(f: &mut Formatter, dict: &BDictAccess<K, V>, opts: BPrettyOpt, level: usize) -> fmt::Result
+    where K: AsRef<[u8]>, V: BRefAccess, V::BKey: AsRef<[u8]> {
+    let mut sort_dict = dict.to_list();
+    sort_dict.sort_by(|&(a, _), &(b, _)| a.as_ref().cmp(b.as_ref()));
+
+    if sort_dict.is_empty() {
+        return write!(f, "Dict {{}}")
+    }
+
+    try!(writeln!(f, "Dict {{"));
+    for (index, &(key, value)) in sort_dict.iter().enumerate() {
+        try!(write_indent(f, opts, level + 1));
+        try!(write_bytes(f, key.as_ref(), opts));
+        try!(write!(f, ": "));
+        try!(write_value(f, value, opts, level + 1));
+
+        if index + 1 != sort_dict.len() {
+            try!(write!(f, ","));
+        }
+        try!(writeln!(f, ""));
+    }
+    try!(write_indent(f, opts, level));
+
+    write!(f, "}}")
+}
+
+fn write_indent(f: &mut Formatter, opts: BPrettyOpt, level: usize) -> fmt::Result {
+    write!(f, "{:width$}", "", width = opts.indent() * level)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use pretty::{self, BPrettyOpt};
+    use reference::bencode_ref::BencodeRef;
+    use reference::decode_opt::BDecodeOpt;
+
+    #[test]
+    fn positive_pretty_print_nested() {
+        let bytes = b"d4:infod6:lengthi5e6:pieces3:\xFF\xFE\xFDe4:listli1eleee";
+        let bencode = BencodeRef::decode(&bytes[..], BDecodeOpt::default()).unwrap();
+
+        let expected = "Dict {\n  \"info\": Dict {\n    \"length\": 5,\n    \"pieces\": Bytes(3)\n  },\n  \"list\": List [\n    1,\n    List []\n  ]\n}";
+        assert_eq!(expected, pretty::pretty_print(&bencode, BPrettyOpt::new(2, 64)));
+    }
+
+    #[test]
+    fn positive_pretty_print_truncated_bytes() {
+        let bencode = BencodeRef::decode(&b"10:abcdefghij"[..], BDecodeOpt::default()).unwrap();
+
+        assert_eq!("\"abcd\"...", pretty::pretty_print(&bencode, BPrettyOpt::new(4, 4)));
+    }
+
+    #[test]
+    fn positive_pretty_print_empty_dict() {
+        let bencode = BencodeRef::decode(&b"de"[..], BDecodeOpt::default()).unwrap();
+
+        assert_eq!("Dict {}", pretty::pretty_print(&bencode, BPrettyOpt::default()));
+    }
+}
@@ -1,8 +1,12 @@
 use access::bencode::BRefAccessExt;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use std::str;
 
-use access::bencode::{BRefAccess, BencodeRefKind};
+use access::bencode::{BMutAccess, BRefAccess, BencodeRefKind};
+use access::compare;
+use mutable::bencode_mut::BencodeMut;
 use reference::decode;
 use reference::decode_opt::BDecodeOpt;
 use access::dict::BDictAccess;
@@ -29,7 +33,10 @@ impl<'a> Into<BencodeRef<'a>> for InnerBencodeRef<'a> {
 }
 
 /// `BencodeRef` object that stores references to some buffer.
-#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+///
+/// Equality and hashing are based on the decoded content, not the underlying buffer,
+/// so dictionaries that were decoded with their keys in a different order are equal.
+#[derive(Debug, Clone)]
 pub struct BencodeRef<'a> {
     inner: InnerBencodeRef<'a>
 }
@@ -47,6 +54,22 @@ impl<'a> BencodeRef<'a> {
         Ok(bencode)
     }
 
+    /// Decode the given bytes into a `BencodeRef` using `BDecodeOpt::strict`.
+    ///
+    /// On top of the duplicate dictionary keys that are always rejected, this rejects dictionary keys
+    /// that are out of order, which should be used when a value will be re-encoded or hashed.
+    pub fn decode_strict(bytes: &'a [u8]) -> BencodeParseResult<BencodeRef<'a>> {
+        BencodeRef::decode(bytes, BDecodeOpt::strict())
+    }
+
+    /// Decode a single value from the start of the given bytes into a `BencodeRef` using the given decode options.
+    ///
+    /// Returns the value, along with the index of the first byte that was not consumed, so that any
+    /// payload following the value can be split off. The full decode option is ignored.
+    pub fn decode_prefix(bytes: &'a [u8], opts: BDecodeOpt) -> BencodeParseResult<(BencodeRef<'a>, usize)> {
+        decode::decode(bytes, 0, opts, 0)
+    }
+
     /// Get a byte slice of the current bencode byte representation.
     pub fn buffer(&self) -> &'a [u8] {
         match self.inner {
@@ -56,6 +79,55 @@ impl<'a> BencodeRef<'a> {
             InnerBencodeRef::Dict(_, buffer)  => buffer
         }
     }
+
+    /// Deep copy the `BencodeRef` into an owned `BencodeMut` that can be modified.
+    pub fn to_mut(&self) -> BencodeMut<'static> {
+        match self.inner {
+            InnerBencodeRef::Int(n, _)       => BencodeMut::new_int(n),
+            InnerBencodeRef::Bytes(n, _)     => BencodeMut::new_bytes(Cow::Owned(n.to_vec())),
+            InnerBencodeRef::List(ref n, _)  => {
+                let mut bencode_list = BencodeMut::new_list();
+                {
+                    let list = bencode_list.list_mut().unwrap();
+                    for value in n {
+                        list.push(value.to_mut());
+                    }
+                }
+
+                bencode_list
+            },
+            InnerBencodeRef::Dict(ref n, _)  => {
+                let mut bencode_dict = BencodeMut::new_dict();
+                {
+                    let dict = bencode_dict.dict_mut().unwrap();
+                    for (key, value) in n {
+                        dict.insert(Cow::Owned(key.to_vec()), value.to_mut());
+                    }
+                }
+
+                bencode_dict
+            }
+        }
+    }
+}
+
+impl<'a> PartialEq for BencodeRef<'a> {
+    fn eq(&self, other: &BencodeRef<'a>) -> bool {
+        compare::structural_eq(self, other)
+    }
+}
+
+impl<'a> Eq for BencodeRef<'a> {}
+
+impl<'a> Hash for BencodeRef<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.inner {
+            InnerBencodeRef::Int(n, _)      => { 0u8.hash(state); n.hash(state) },
+            InnerBencodeRef::Bytes(n, _)    => { 1u8.hash(state); n.hash(state) },
+            InnerBencodeRef::List(ref n, _) => { 2u8.hash(state); n.hash(state) },
+            InnerBencodeRef::Dict(ref n, _) => { 3u8.hash(state); n.hash(state) }
+        }
+    }
 }
 
 impl<'a> BRefAccess for BencodeRef<'a> {
@@ -126,10 +198,61 @@ impl<'a> BRefAccessExt<'a> for BencodeRef<'a> {
 mod tests {
     use std::default::Default;
 
-    use access::bencode::BRefAccess;
+    use access::bencode::{BMutAccess, BRefAccess};
+    use error::BencodeParseErrorKind;
+    use mutable::bencode_mut::BencodeMut;
     use reference::bencode_ref::BencodeRef;
     use reference::decode_opt::BDecodeOpt;
 
+    #[test]
+    fn positive_decode_strict_sorted_dict() {
+        let dict_bytes = b"d1:ai1e1:bi2ee";
+        let bencode = BencodeRef::decode_strict(&dict_bytes[..]).unwrap();
+
+        assert_eq!(2, bencode.dict().unwrap().lookup(b"b").unwrap().int().unwrap());
+    }
+
+    #[test]
+    fn positive_decode_lenient_unsorted_keys() {
+        BencodeRef::decode(b"d1:bi2e1:ai1ee", BDecodeOpt::default()).unwrap();
+    }
+
+    #[test]
+    fn negative_decode_strict_unsorted_keys() {
+        let error = BencodeRef::decode_strict(b"d1:bi2e1:ai1ee").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidKeyOrdering{ pos, ref key } => {
+                assert_eq!(7, pos);
+                assert_eq!(b"a", &key[..]);
+            },
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_strict_duplicate_keys() {
+        let error = BencodeRef::decode_strict(b"d1:ai1e1:ai2ee").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidKeyDuplicates{ pos, ref key } => {
+                assert_eq!(7, pos);
+                assert_eq!(b"a", &key[..]);
+            },
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_strict_nested_duplicate_keys() {
+        let error = BencodeRef::decode_strict(b"ld1:ad1:xi1e1:xi2eeee").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidKeyDuplicates{ pos, .. } => assert_eq!(12, pos),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
     #[test]
     fn positive_int_buffer() {
         let int_bytes = b"i-500e";
@@ -257,4 +380,62 @@ mod tests {
         let dict_bytes = b"d3:asd3:asde";
         assert_eq!(dict_bytes, bencode_dict.buffer());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn positive_to_mut_round_trip() {
+        let dict_bytes = b"d4:listli1e3:asde6:numberi5ee";
+        let bencode = BencodeRef::decode(&dict_bytes[..], BDecodeOpt::default()).unwrap();
+
+        let mut bencode_mut = bencode.to_mut();
+        assert_eq!(&dict_bytes[..], &bencode_mut.encode()[..]);
+
+        bencode_mut.dict_mut().unwrap().insert((&b"number"[..]).into(), BencodeMut::new_int(-10));
+        let encoded = bencode_mut.encode();
+
+        let bencode = BencodeRef::decode(&encoded, BDecodeOpt::default()).unwrap();
+        let bencode_dict = bencode.dict().unwrap();
+        assert_eq!(-10, bencode_dict.lookup(b"number").unwrap().int().unwrap());
+        assert_eq!("asd", bencode_dict.lookup(b"list").unwrap().list().unwrap()[1].str().unwrap());
+    }
+
+    #[test]
+    fn positive_eq_unordered_dicts() {
+        let first = BencodeRef::decode(&b"d1:ai1e1:bi2ee"[..], BDecodeOpt::default()).unwrap();
+        let second = BencodeRef::decode(&b"d1:bi2e1:ai1ee"[..], BDecodeOpt::default()).unwrap();
+
+        assert_ne!(first.buffer(), second.buffer());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn negative_eq_reordered_lists() {
+        let first = BencodeRef::decode(&b"li1ei2ee"[..], BDecodeOpt::default()).unwrap();
+        let second = BencodeRef::decode(&b"li2ei1ee"[..], BDecodeOpt::default()).unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn positive_decode_prefix_with_payload() {
+        let message = b"d8:msg_typei1e5:piecei0ee\x00\x01\x02\x03";
+        let (bencode, end_pos) = BencodeRef::decode_prefix(&message[..], BDecodeOpt::default()).unwrap();
+
+        assert_eq!(25, end_pos);
+        assert_eq!(&b"\x00\x01\x02\x03"[..], &message[end_pos..]);
+        assert_eq!(1, bencode.dict().unwrap().lookup(b"msg_type").unwrap().int().unwrap());
+    }
+
+    #[test]
+    fn positive_decode_prefix_no_payload() {
+        let message = b"i5e";
+        let (_, end_pos) = BencodeRef::decode_prefix(&message[..], BDecodeOpt::default()).unwrap();
+
+        assert_eq!(message.len(), end_pos);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_decode_prefix_truncated() {
+        BencodeRef::decode_prefix(&b"d8:msg_typei1e"[..], BDecodeOpt::default()).unwrap();
+    }
+}
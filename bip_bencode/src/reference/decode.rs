@@ -8,7 +8,7 @@ use error::{BencodeParseError, BencodeParseErrorKind, BencodeParseResult};
 
 pub fn decode<'a>(bytes: &'a [u8], pos: usize, opts: BDecodeOpt, depth: usize) -> BencodeParseResult<(BencodeRef<'a>, usize)> {
     if depth >= opts.max_recursion() {
-        return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidRecursionExceeded{ pos: pos, max: depth }))
+        return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidRecursionExceeded{ pos: pos, max: opts.max_recursion() }))
     }
     let curr_byte = try!(peek_byte(bytes, pos));
     
@@ -26,7 +26,7 @@ pub fn decode<'a>(bytes: &'a [u8], pos: usize, opts: BDecodeOpt, depth: usize) -
             Ok((InnerBencodeRef::Dict(bencode, &bytes[pos..next_pos]).into(), next_pos))
         },
         ::BYTE_LEN_LOW...::BYTE_LEN_HIGH => {
-            let (bencode, next_pos) = try!(decode_bytes(bytes, pos));
+            let (bencode, next_pos) = try!(decode_bytes(bytes, pos, opts.max_byte_string_len()));
             // Include the length digit, don't increment position
             Ok((InnerBencodeRef::Bytes(bencode, &bytes[pos..next_pos]).into(), next_pos))
         },
@@ -55,30 +55,36 @@ fn decode_int<'a>(bytes: &'a [u8], pos: usize, delim: u8) -> BencodeParseResult<
         }
     }
     
-    let int_str = match str::from_utf8(int_byte_slice) {
-        Ok(n)  => n,
-        Err(_) => return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidIntParseError{ pos: pos }))
-    };
+    // Only an optional minus sign followed by digits is allowed, the parser below would also accept a plus sign
+    let digits = if int_byte_slice.first() == Some(&b'-') { &int_byte_slice[1..] } else { int_byte_slice };
+    if digits.is_empty() || !digits.iter().all(|byte| byte.is_ascii_digit()) {
+        return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidIntParseError{ pos: pos }))
+    }
+    // Validated as ascii above
+    let int_str = str::from_utf8(int_byte_slice).unwrap();
     
     // Position of end of integer type, next byte is the start of the next value
     let absolute_end_pos = pos + relative_end_pos;
     let next_pos = absolute_end_pos + 1;
     match i64::from_str_radix(int_str, 10) {
         Ok(n)  => Ok((n, next_pos)),
-        Err(_) => Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidIntParseError{ pos: pos }))
+        // Input is well formed at this point, so the only way to fail is by not fitting in an i64
+        Err(_) => Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidIntOverflow{ pos: pos }))
     }
 }
     
-fn decode_bytes<'a>(bytes: &'a [u8], pos: usize) -> BencodeParseResult<(&'a [u8], usize)> {
+fn decode_bytes<'a>(bytes: &'a [u8], pos: usize, max_len: usize) -> BencodeParseResult<(&'a [u8], usize)> {
     let (num_bytes, start_pos) = try!(decode_int(bytes, pos, ::BYTE_LEN_END));
 
     if num_bytes < 0 {
         return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidLengthNegative{ pos: pos }))
     } 
     
-    // Should be safe to cast to usize (TODO: Check if cast would overflow to provide
-    // a more helpful error message, otherwise, parsing will probably fail with an
-    // unrelated message).
+    // Check against the cap before the buffer, so a stream never waits on bytes for a length we won't accept
+    // (comparing as u64 also catches lengths that would truncate when cast to a 32 bit usize)
+    if num_bytes as u64 > max_len as u64 {
+        return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidLengthExceeded{ pos: pos, max: max_len }))
+    }
     let num_bytes = num_bytes as usize;
     
     if num_bytes > bytes[start_pos..].len() {
@@ -115,12 +121,13 @@ fn decode_dict<'a>(bytes: &'a [u8], pos: usize, opts: BDecodeOpt, depth: usize)
     let mut curr_byte = try!(peek_byte(bytes, curr_pos));
     
     while curr_byte != ::BEN_END {
-        let (key_bytes, next_pos) = try!(decode_bytes(bytes, curr_pos));
+        let key_pos = curr_pos;
+        let (key_bytes, next_pos) = try!(decode_bytes(bytes, curr_pos, opts.max_byte_string_len()));
         
         // Spec says that the keys must be in alphabetical order
         match (bencode_dict.keys().last(), opts.check_key_sort()) {
             (Some(last_key), true) if key_bytes < *last_key => {
-                return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidKeyOrdering{ pos: curr_pos, key: key_bytes.to_vec() }))
+                return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidKeyOrdering{ pos: key_pos, key: key_bytes.to_vec() }))
             },
             _ => ()
         };
@@ -130,7 +137,7 @@ fn decode_dict<'a>(bytes: &'a [u8], pos: usize, opts: BDecodeOpt, depth: usize)
         match bencode_dict.entry(key_bytes) {
             Entry::Vacant(n)   => n.insert(value),
             Entry::Occupied(_) => {
-                return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidKeyDuplicates{ pos: curr_pos, key: key_bytes.to_vec() }))
+                return Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidKeyDuplicates{ pos: key_pos, key: key_bytes.to_vec() }))
             }
         };
 
@@ -153,6 +160,7 @@ mod tests {
     use std::default::Default;
 
     use access::bencode::BRefAccess;
+    use error::BencodeParseErrorKind;
     use reference::bencode_ref::BencodeRef;
     use reference::decode_opt::BDecodeOpt;
 
@@ -179,9 +187,21 @@ mod tests {
     const INT_DOUBLE_ZERO: &'static [u8] = b"i00e";
     const INT_NEGATIVE_ZERO: &'static [u8] = b"i-0e";
     const INT_DOUBLE_NEGATIVE: &'static [u8] = b"i--5e";
+    const INT_PLUS_SIGN: &'static [u8] = b"i+5e";
+    const INT_EMPTY: &'static [u8] = b"ie";
+    const INT_ONLY_NEGATIVE: &'static [u8] = b"i-e";
+    const INT_NEGATIVE_LEADING_ZERO: &'static [u8] = b"i-05e";
+    const INT_OVERFLOW: &'static [u8] = b"i9223372036854775808e";
+    const INT_UNDERFLOW: &'static [u8] = b"i-9223372036854775809e";
+    const INT_MAX: &'static [u8] = b"i9223372036854775807e";
+    const INT_MIN: &'static [u8] = b"i-9223372036854775808e";
     const DICT_UNORDERED_KEYS: &'static [u8] = b"d5:z_key5:value5:a_key5:valuee";
     const DICT_DUP_KEYS_SAME_DATA: &'static [u8] = b"d5:a_keyi0e5:a_keyi0ee";
     const DICT_DUP_KEYS_DIFF_DATA: &'static [u8] = b"d5:a_keyi0e5:a_key7:a_valuee";
+    const TRUNCATED_DICT: &'static [u8] = b"d3:keyl3:val";
+    const TRUNCATED_BYTES: &'static [u8] = b"l10:short";
+    const NESTED_BAD_INT: &'static [u8] = b"d1:ad1:bd1:ci5a0eeee";
+    const NESTED_BAD_BYTE: &'static [u8] = b"ld1:ald1:bxeeee";
 
     #[test]
     fn positive_decode_general() {
@@ -251,7 +271,7 @@ mod tests {
 
     #[test]
     fn positive_decode_bytes() {
-        let bytes = super::decode_bytes(BYTES, 0).unwrap().0;
+        let bytes = super::decode_bytes(BYTES, 0, usize::max_value()).unwrap().0;
         assert_eq!(bytes.len(), 5);
         assert_eq!(bytes[0] as char, 'Å');
         assert_eq!(bytes[1] as char, 'æ');
@@ -262,7 +282,7 @@ mod tests {
 
     #[test]
     fn positive_decode_bytes_zero_len() {
-        let bytes = super::decode_bytes(BYTES_ZERO_LEN, 0).unwrap().0;
+        let bytes = super::decode_bytes(BYTES_ZERO_LEN, 0, usize::max_value()).unwrap().0;
         assert_eq!(bytes.len(), 0);
     }
 
@@ -347,6 +367,119 @@ mod tests {
         super::decode_int(INT_DOUBLE_NEGATIVE, 1, ::BEN_END).unwrap().0;
     }
 
+    #[test]
+    fn positive_decode_int_limits() {
+        assert_eq!(i64::max_value(), BencodeRef::decode(INT_MAX, BDecodeOpt::default()).unwrap().int().unwrap());
+        assert_eq!(i64::min_value(), BencodeRef::decode(INT_MIN, BDecodeOpt::default()).unwrap().int().unwrap());
+    }
+
+    fn decode_error_kind(bytes: &[u8]) -> BencodeParseErrorKind {
+        BencodeRef::decode(bytes, BDecodeOpt::default()).unwrap_err().0
+    }
+
+    #[test]
+    fn negative_decode_ref_int_leading_zero() {
+        match decode_error_kind(b"i03e") {
+            BencodeParseErrorKind::InvalidIntZeroPadding{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_negative_zero() {
+        match decode_error_kind(INT_NEGATIVE_ZERO) {
+            BencodeParseErrorKind::InvalidIntNegativeZero{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_negative_leading_zero() {
+        match decode_error_kind(INT_NEGATIVE_LEADING_ZERO) {
+            BencodeParseErrorKind::InvalidIntNegativeZero{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_overflow() {
+        match decode_error_kind(INT_OVERFLOW) {
+            BencodeParseErrorKind::InvalidIntOverflow{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_underflow() {
+        match decode_error_kind(INT_UNDERFLOW) {
+            BencodeParseErrorKind::InvalidIntOverflow{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_plus_sign() {
+        match decode_error_kind(INT_PLUS_SIGN) {
+            BencodeParseErrorKind::InvalidIntParseError{ pos: 1 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_ref_int_without_digits() {
+        for bytes in &[INT_EMPTY, INT_ONLY_NEGATIVE] {
+            match decode_error_kind(bytes) {
+                BencodeParseErrorKind::InvalidIntParseError{ pos: 1 } => (),
+                other => panic!("Unexpected Error Kind {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn negative_decode_bytes_length_exceeds_max() {
+        let error = BencodeRef::decode(b"d3:key99999999999:", BDecodeOpt::default().with_max_byte_string_len(1024)).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidLengthExceeded{ pos: 6, max: 1024 } => (),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_bytes_length_exceeds_default_max() {
+        let error = BencodeRef::decode(b"8388609:", BDecodeOpt::default()).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidLengthExceeded{ pos: 0, max: 8388608 } => (),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_decode_key_length_exceeds_max() {
+        let error = BencodeRef::decode(b"d5:a_keyi0ee", BDecodeOpt::default().with_max_byte_string_len(4)).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidLengthExceeded{ pos: 1, max: 4 } => (),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_decode_bytes_length_at_max() {
+        let bencode = BencodeRef::decode(b"4:spam", BDecodeOpt::default().with_max_byte_string_len(4)).unwrap();
+
+        assert_eq!("spam", bencode.str().unwrap());
+    }
+
+    #[test]
+    fn negative_decode_bytes_length_overflow() {
+        match decode_error_kind(b"99999999999999999999:a") {
+            BencodeParseErrorKind::InvalidIntOverflow{ pos: 0 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
     #[test]
     #[should_panic]
     fn negative_decode_dict_unordered_keys() {
@@ -364,4 +497,101 @@ mod tests {
     fn negative_decode_dict_dup_keys_diff_data() {
         BencodeRef::decode(DICT_DUP_KEYS_DIFF_DATA, BDecodeOpt::default()).unwrap();
     }
+
+    #[test]
+    fn negative_decode_truncated_dict_offset() {
+        let error = BencodeRef::decode(TRUNCATED_DICT, BDecodeOpt::default()).unwrap_err();
+
+        assert_eq!(Some(TRUNCATED_DICT.len()), error.byte_offset());
+        assert_eq!("End Of Input", error.kind().found());
+    }
+
+    #[test]
+    fn negative_decode_truncated_bytes_offset() {
+        let error = BencodeRef::decode(TRUNCATED_BYTES, BDecodeOpt::default()).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidLengthOverflow{ .. } => (),
+            _ => panic!("Unexpected Error Kind")
+        }
+        assert_eq!(Some(1), error.byte_offset());
+    }
+
+    #[test]
+    fn negative_decode_nested_bad_int_offset() {
+        let error = BencodeRef::decode(NESTED_BAD_INT, BDecodeOpt::default()).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidIntParseError{ .. } => (),
+            _ => panic!("Unexpected Error Kind")
+        }
+        // Offset points at the first digit of the integer
+        assert_eq!(Some(13), error.byte_offset());
+    }
+
+    #[test]
+    fn negative_decode_nested_bad_byte_offset() {
+        let error = BencodeRef::decode(NESTED_BAD_BYTE, BDecodeOpt::default()).unwrap_err();
+
+        assert_eq!(Some(10), error.byte_offset());
+        assert_eq!("Unexpected Byte", error.kind().found());
+        assert_eq!("Integer, List, Dictionary, Or Byte Length Start", error.kind().expected());
+    }
+
+    #[test]
+    fn negative_decode_dict_dup_keys_offset() {
+        let error = BencodeRef::decode(DICT_DUP_KEYS_SAME_DATA, BDecodeOpt::default()).unwrap_err();
+
+        assert_eq!(Some(11), error.byte_offset());
+    }
+
+    fn nested(start: u8, inner: &[u8], depth: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for _ in 0..depth {
+            bytes.push(start);
+            if start == ::DICT_START {
+                bytes.extend_from_slice(b"1:a");
+            }
+        }
+        bytes.extend_from_slice(inner);
+        for _ in 0..depth {
+            bytes.push(::BEN_END);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn positive_decode_list_at_recursion_limit() {
+        let bytes = nested(::LIST_START, b"", 10);
+
+        BencodeRef::decode(&bytes, BDecodeOpt::new(10, false, true)).unwrap();
+    }
+
+    #[test]
+    fn negative_decode_list_past_recursion_limit() {
+        let bytes = nested(::LIST_START, b"", 11);
+        let error = BencodeRef::decode(&bytes, BDecodeOpt::new(10, false, true)).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidRecursionExceeded{ pos, max } => {
+                assert_eq!(10, pos);
+                assert_eq!(10, max);
+            },
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn negative_decode_dict_past_recursion_limit() {
+        // Innermost value counts towards the depth as well
+        let bytes = nested(::DICT_START, b"i0e", 10);
+        let error = BencodeRef::decode(&bytes, BDecodeOpt::new(10, false, true)).unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidRecursionExceeded{ .. } => (),
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
 }
@@ -3,23 +3,36 @@ use std::default::Default;
 const DEFAULT_MAX_RECURSION:       usize = 50;
 const DEFAULT_CHECK_KEY_SORT:      bool = false;
 const DEFAULT_ENFORCE_FULL_DECODE: bool = true;
+const DEFAULT_MAX_BYTE_STRING_LEN: usize = 8 * 1024 * 1024;
 
 /// Stores decoding options for modifying decode behavior.
 #[derive(Copy, Clone)]
 pub struct BDecodeOpt {
     max_recursion:       usize,
     check_key_sort:      bool,
-    enforce_full_decode: bool
+    enforce_full_decode: bool,
+    max_byte_string_len: usize
 }
 
 impl BDecodeOpt {
     /// Create a new `BDecodeOpt` object.
     pub fn new(max_recursion: usize, check_key_sort: bool, enforce_full_decode: bool) -> BDecodeOpt {
         BDecodeOpt{ max_recursion: max_recursion, check_key_sort: check_key_sort,
-                    enforce_full_decode: enforce_full_decode }
+                    enforce_full_decode: enforce_full_decode, max_byte_string_len: DEFAULT_MAX_BYTE_STRING_LEN }
+    }
+
+    /// Create a `BDecodeOpt` object that enforces everything the spec requires.
+    ///
+    /// Dictionary keys must be unique and in sorted order, and the input must be fully decoded,
+    /// so that any input that is accepted has exactly one canonical encoding.
+    pub fn strict() -> BDecodeOpt {
+        BDecodeOpt::new(DEFAULT_MAX_RECURSION, true, true)
     }
 
     /// Maximum limit allowed when decoding bencode.
+    ///
+    /// Every list, dictionary, and value nested inside of them counts as one level of depth,
+    /// so a malicious payload of deeply nested values will error instead of overflowing the stack.
     pub fn max_recursion(&self) -> usize {
         self.max_recursion
     }
@@ -29,12 +42,28 @@ impl BDecodeOpt {
         self.check_key_sort
     }
 
+    /// Set the maximum length that a byte string may declare, which is 8 MiB by default.
+    ///
+    /// Lengths are checked as soon as they are decoded, before the bytes they cover have arrived,
+    /// so a `StreamDecoder` will error on a huge length prefix instead of buffering towards it.
+    pub fn with_max_byte_string_len(mut self, max_byte_string_len: usize) -> BDecodeOpt {
+        self.max_byte_string_len = max_byte_string_len;
+
+        self
+    }
+
+    /// Maximum length that a byte string, including dictionary keys, may declare.
+    pub fn max_byte_string_len(&self) -> usize {
+        self.max_byte_string_len
+    }
+
     /// Whether or not we enforce that the decoded bencode must make up all of the input
     /// bytes or not.
     ///
     /// It may be useful to disable this if for example, the input bencode is prepended to
     /// some payload and you would like to disassociate it. In this case, to find where the
-    /// rest of the payload starts that wasn't decoded, get the bencode buffer, and call len().
+    /// rest of the payload starts that wasn't decoded, get the bencode buffer, and call len(),
+    /// or use `BencodeRef::decode_prefix` which returns that position directly.
     pub fn enforce_full_decode(&self) -> bool {
         self.enforce_full_decode
     }
@@ -0,0 +1,398 @@
+//! Serialize rust types into bencode.
+
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use serde::ser::{self, Serialize};
+
+use access::bencode::{BMutAccess, BRefAccess};
+use error::{BencodeSerdeError, BencodeSerdeErrorKind, BencodeSerdeResult};
+use mutable::bencode_mut::BencodeMut;
+
+impl ser::Error for BencodeSerdeError {
+    fn custom<T: Display>(msg: T) -> BencodeSerdeError {
+        msg.to_string().into()
+    }
+}
+
+/// Serialize the given value into bencoded bytes.
+///
+/// Dictionary keys are always emitted in sorted order.
+pub fn to_bytes<T: ?Sized + Serialize>(value: &T) -> BencodeSerdeResult<Vec<u8>> {
+    to_bencode(value).map(|bencode| bencode.encode())
+}
+
+/// Serialize the given value into a `BencodeMut`.
+///
+/// Bencode has no representation for a missing value, so `None` and unit values are
+/// skipped when they are fields of a struct or map, and are an error anywhere else.
+pub fn to_bencode<T: ?Sized + Serialize>(value: &T) -> BencodeSerdeResult<BencodeMut<'static>> {
+    try!(value.serialize(Serializer)).ok_or_else(|| {
+        BencodeSerdeError::from_kind(BencodeSerdeErrorKind::UnsupportedType{ type_name: "None" })
+    })
+}
+
+fn serialize_key<T: ?Sized + Serialize>(key: &T) -> BencodeSerdeResult<Vec<u8>> {
+    let bencode = try!(to_bencode(key));
+
+    if let Some(bytes) = bencode.bytes() {
+        Ok(bytes.to_vec())
+    } else if let Some(int) = bencode.int() {
+        Ok(int.to_string().into_bytes())
+    } else {
+        Err(BencodeSerdeError::from_kind(BencodeSerdeErrorKind::InvalidKey{ expected_type: "Bytes" }))
+    }
+}
+
+fn new_list(values: Vec<BencodeMut<'static>>) -> BencodeMut<'static> {
+    let mut bencode_list = BencodeMut::new_list();
+    {
+        let list = bencode_list.list_mut().unwrap();
+        for value in values {
+            list.push(value);
+        }
+    }
+
+    bencode_list
+}
+
+fn new_variant(variant: &'static str, value: BencodeMut<'static>) -> BencodeMut<'static> {
+    let mut bencode_dict = BencodeMut::new_dict();
+    bencode_dict.dict_mut().unwrap().insert(Cow::Borrowed(variant.as_bytes()), value);
+
+    bencode_dict
+}
+
+/// Serializer for converting rust types into `BencodeMut` values.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    type SerializeSeq           = SerializeList;
+    type SerializeTuple         = SerializeList;
+    type SerializeTupleStruct   = SerializeList;
+    type SerializeTupleVariant  = SerializeList;
+    type SerializeMap           = SerializeDict;
+    type SerializeStruct        = SerializeDict;
+    type SerializeStructVariant = SerializeDict;
+
+    fn serialize_bool(self, v: bool) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(if v { 1 } else { 0 })
+    }
+
+    fn serialize_i8(self, v: i8) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> BencodeSerdeResult<Self::Ok> {
+        Ok(Some(BencodeMut::new_int(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> BencodeSerdeResult<Self::Ok> {
+        if v > i64::max_value() as u64 {
+            Err(BencodeSerdeError::from_kind(BencodeSerdeErrorKind::IntegerOverflow{ value: v }))
+        } else {
+            self.serialize_i64(v as i64)
+        }
+    }
+
+    fn serialize_f32(self, _v: f32) -> BencodeSerdeResult<Self::Ok> {
+        Err(BencodeSerdeError::from_kind(BencodeSerdeErrorKind::UnsupportedType{ type_name: "f32" }))
+    }
+
+    fn serialize_f64(self, _v: f64) -> BencodeSerdeResult<Self::Ok> {
+        Err(BencodeSerdeError::from_kind(BencodeSerdeErrorKind::UnsupportedType{ type_name: "f64" }))
+    }
+
+    fn serialize_char(self, v: char) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> BencodeSerdeResult<Self::Ok> {
+        Ok(Some(BencodeMut::new_bytes(Cow::Owned(v.to_vec()))))
+    }
+
+    fn serialize_none(self) -> BencodeSerdeResult<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> BencodeSerdeResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> BencodeSerdeResult<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> BencodeSerdeResult<Self::Ok> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str)
+        -> BencodeSerdeResult<Self::Ok> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T)
+        -> BencodeSerdeResult<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _variant_index: u32, variant: &'static str,
+                                                         value: &T) -> BencodeSerdeResult<Self::Ok> {
+        Ok(Some(new_variant(variant, try!(to_bencode(value)))))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> BencodeSerdeResult<SerializeList> {
+        Ok(SerializeList::new(None, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> BencodeSerdeResult<SerializeList> {
+        Ok(SerializeList::new(None, len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> BencodeSerdeResult<SerializeList> {
+        Ok(SerializeList::new(None, len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, len: usize)
+        -> BencodeSerdeResult<SerializeList> {
+        Ok(SerializeList::new(Some(variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> BencodeSerdeResult<SerializeDict> {
+        Ok(SerializeDict::new(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> BencodeSerdeResult<SerializeDict> {
+        Ok(SerializeDict::new(None))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize)
+        -> BencodeSerdeResult<SerializeDict> {
+        Ok(SerializeDict::new(Some(variant)))
+    }
+}
+
+/// Serializer for sequences, tuples, and tuple variants.
+pub struct SerializeList {
+    variant: Option<&'static str>,
+    values:  Vec<BencodeMut<'static>>
+}
+
+impl SerializeList {
+    fn new(variant: Option<&'static str>, len: usize) -> SerializeList {
+        SerializeList{ variant: variant, values: Vec::with_capacity(len) }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        let bencode = try!(to_bencode(value));
+        self.values.push(bencode);
+
+        Ok(())
+    }
+
+    fn finish(self) -> BencodeSerdeResult<Option<BencodeMut<'static>>> {
+        let bencode_list = new_list(self.values);
+
+        match self.variant {
+            Some(variant) => Ok(Some(new_variant(variant, bencode_list))),
+            None          => Ok(Some(bencode_list))
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+/// Serializer for maps, structs, and struct variants.
+pub struct SerializeDict {
+    variant:  Option<&'static str>,
+    dict:     BencodeMut<'static>,
+    next_key: Option<Vec<u8>>
+}
+
+impl SerializeDict {
+    fn new(variant: Option<&'static str>) -> SerializeDict {
+        SerializeDict{ variant: variant, dict: BencodeMut::new_dict(), next_key: None }
+    }
+
+    fn insert<T: ?Sized + Serialize>(&mut self, key: Vec<u8>, value: &T) -> BencodeSerdeResult<()> {
+        // Missing values are left out of the dictionary entirely
+        if let Some(bencode) = try!(value.serialize(Serializer)) {
+            self.dict.dict_mut().unwrap().insert(Cow::Owned(key), bencode);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> BencodeSerdeResult<Option<BencodeMut<'static>>> {
+        match self.variant {
+            Some(variant) => Ok(Some(new_variant(variant, self.dict))),
+            None          => Ok(Some(self.dict))
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeDict {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> BencodeSerdeResult<()> {
+        self.next_key = Some(try!(serialize_key(key)));
+
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> BencodeSerdeResult<()> {
+        let key = try!(self.next_key.take().ok_or_else(|| {
+            BencodeSerdeError::from("Value Serialized Before Key".to_owned())
+        }));
+
+        self.insert(key, value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeDict {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> BencodeSerdeResult<()> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeDict {
+    type Ok    = Option<BencodeMut<'static>>;
+    type Error = BencodeSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> BencodeSerdeResult<()> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> BencodeSerdeResult<Self::Ok> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ser;
+
+    #[test]
+    fn positive_to_bytes_map_sorted() {
+        let mut map = HashMap::new();
+        map.insert("zebra", 1);
+        map.insert("apple", 2);
+        map.insert("mango", 3);
+
+        assert_eq!(&b"d5:applei2e5:mangoi3e5:zebrai1ee"[..], &ser::to_bytes(&map).unwrap()[..]);
+    }
+
+    #[test]
+    fn positive_to_bytes_skip_none_field() {
+        #[derive(Serialize)]
+        struct Test {
+            present: Option<i64>,
+            missing: Option<i64>
+        }
+
+        let test = Test{ present: Some(5), missing: None };
+        assert_eq!(&b"d7:presenti5ee"[..], &ser::to_bytes(&test).unwrap()[..]);
+    }
+
+    #[test]
+    fn negative_to_bytes_float() {
+        ser::to_bytes(&5.0f64).unwrap_err();
+    }
+
+    #[test]
+    fn negative_to_bytes_u64_overflow() {
+        ser::to_bytes(&u64::max_value()).unwrap_err();
+    }
+}
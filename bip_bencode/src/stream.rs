@@ -0,0 +1,415 @@
+use std::cmp;
+
+use error::{BencodeParseError, BencodeParseErrorKind, BencodeParseResult};
+use mutable::bencode_mut::BencodeMut;
+use reference::decode;
+use reference::decode_opt::BDecodeOpt;
+
+/// Longest integer (or byte string length) that can fit in an i64, including the sign.
+const MAX_INT_LEN: usize = 20;
+
+const DEFAULT_MAX_BUFFERED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Incremental decoder for a stream of top level bencode values.
+///
+/// Bytes are pushed into the decoder as they become available, and any values that
+/// were completed by those bytes are returned as owned `BencodeMut` objects. Bytes
+/// belonging to a value that has not finished yet are buffered until the next feed.
+/// Only the new bytes are scanned on each feed, and a value is decoded once it has finished.
+///
+/// Positions reported in errors are relative to the start of the stream, not the
+/// start of the slice passed to the most recent feed. Once an error is returned, the
+/// offending bytes are left in the buffer, so subsequent feeds will return the same error.
+pub struct StreamDecoder {
+    opts:         BDecodeOpt,
+    buffer:       Vec<u8>,
+    consumed:     usize,
+    max_buffered: usize,
+    scan_pos:     usize,
+    scan_depth:   usize,
+    scan_state:   ScanState
+}
+
+/// Progress through the value at the front of the buffer, with positions relative to the start of the stream.
+#[derive(Copy, Clone)]
+enum ScanState {
+    /// Expecting the start of a value, or the end of a list or dictionary.
+    Value,
+    /// Within an integer whose digits start at the given position.
+    Int{ pos: usize, len: usize },
+    /// Within the length of a byte string that starts at the given position.
+    Length{ pos: usize, len: usize, length: u64 },
+    /// Within the contents of a byte string.
+    Bytes{ remaining: u64 }
+}
+
+/// Result of scanning the bytes that are buffered.
+enum Scan {
+    /// Value at the front of the buffer needs more bytes.
+    Incomplete,
+    /// Value at the front of the buffer has finished, or is invalid, and should be decoded.
+    Decode,
+    /// Value at the front of the buffer will never decode.
+    Error(BencodeParseErrorKind)
+}
+
+impl StreamDecoder {
+    /// Create a new `StreamDecoder` using the given decode options.
+    ///
+    /// The full decode option is ignored, as values are always split off of the stream.
+    pub fn new(opts: BDecodeOpt) -> StreamDecoder {
+        StreamDecoder{ opts: opts, buffer: Vec::new(), consumed: 0, max_buffered: DEFAULT_MAX_BUFFERED_BYTES,
+                       scan_pos: 0, scan_depth: 0, scan_state: ScanState::Value }
+    }
+
+    /// Set the maximum number of bytes that will be buffered for a value that has not finished yet.
+    ///
+    /// Defaults to 16 MiB. Feeding more bytes than this for a single value returns an `InvalidBufferExceeded` error.
+    pub fn with_max_buffered_bytes(mut self, max_buffered: usize) -> StreamDecoder {
+        self.max_buffered = max_buffered;
+
+        self
+    }
+
+    /// Feed the given bytes into the decoder, returning all top level values that were completed.
+    ///
+    /// If a value is invalid after other values were completed by this call, the completed values
+    /// are returned, and the error is returned by the next call (which may feed no bytes).
+    pub fn feed(&mut self, bytes: &[u8]) -> BencodeParseResult<Vec<BencodeMut<'static>>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut values = Vec::new();
+        let mut buffer_pos = 0;
+
+        let result = loop {
+            match self.scan() {
+                Scan::Incomplete => break Ok(()),
+                Scan::Decode     => {
+                    match decode::decode(&self.buffer, buffer_pos, self.opts, 0) {
+                        Ok((bencode, next_pos)) => {
+                            values.push(bencode.to_mut());
+                            buffer_pos = next_pos;
+                        },
+                        Err(error) => break Err(shift_error(error, self.consumed))
+                    }
+                    self.reset_scan(buffer_pos);
+                },
+                Scan::Error(kind) => break Err(BencodeParseError::from_kind(kind))
+            }
+        };
+
+        self.buffer.drain(..buffer_pos);
+        self.consumed += buffer_pos;
+
+        let result = result.and_then(|_| {
+            if self.buffer.len() > self.max_buffered {
+                Err(BencodeParseError::from_kind(BencodeParseErrorKind::InvalidBufferExceeded{ pos: self.consumed,
+                                                                                               max: self.max_buffered }))
+            } else {
+                Ok(())
+            }
+        });
+
+        // Errors rescan the value on the next feed, so that it returns the same error
+        if result.is_err() {
+            self.reset_scan(0);
+        } else {
+            self.scan_pos -= buffer_pos;
+        }
+
+        match result {
+            Err(error) if values.is_empty() => Err(error),
+            _                               => Ok(values)
+        }
+    }
+
+    /// Number of bytes that have been decoded into complete values.
+    pub fn bytes_consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Number of bytes that are buffered, waiting on the rest of a value.
+    pub fn bytes_buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Start scanning a new value at the given buffer position.
+    fn reset_scan(&mut self, buffer_pos: usize) {
+        self.scan_pos = buffer_pos;
+        self.scan_depth = 0;
+        self.scan_state = ScanState::Value;
+    }
+
+    /// Scan the bytes that have not been scanned yet, stopping once the value at the front of the buffer has finished.
+    ///
+    /// Only the structure of the value is checked, anything invalid is left for the decoder to report, except
+    /// for integers and lengths which could otherwise be buffered forever without a delimiter.
+    fn scan(&mut self) -> Scan {
+        while self.scan_pos < self.buffer.len() {
+            let pos = self.consumed + self.scan_pos;
+            let byte = self.buffer[self.scan_pos];
+
+            self.scan_state = match self.scan_state {
+                ScanState::Bytes{ remaining } => {
+                    let skip = cmp::min(remaining, (self.buffer.len() - self.scan_pos) as u64);
+                    self.scan_pos += skip as usize;
+
+                    ScanState::Bytes{ remaining: remaining - skip }
+                },
+                ScanState::Value => {
+                    self.scan_pos += 1;
+
+                    match byte {
+                        ::BEN_END if self.scan_depth > 0 => {
+                            self.scan_depth -= 1;
+                            ScanState::Value
+                        },
+                        _ if self.scan_depth >= self.opts.max_recursion() => return Scan::Decode,
+                        ::INT_START => ScanState::Int{ pos: pos + 1, len: 0 },
+                        ::LIST_START | ::DICT_START => {
+                            self.scan_depth += 1;
+                            ScanState::Value
+                        },
+                        ::BYTE_LEN_LOW...::BYTE_LEN_HIGH => ScanState::Length{ pos: pos, len: 1, length: (byte - b'0') as u64 },
+                        _ => return Scan::Decode
+                    }
+                },
+                ScanState::Int{ pos: int_pos, len } => {
+                    self.scan_pos += 1;
+
+                    match byte {
+                        ::BEN_END => ScanState::Value,
+                        b'-' | ::BYTE_LEN_LOW...::BYTE_LEN_HIGH if len < MAX_INT_LEN => ScanState::Int{ pos: int_pos, len: len + 1 },
+                        b'-' | ::BYTE_LEN_LOW...::BYTE_LEN_HIGH => {
+                            return Scan::Error(BencodeParseErrorKind::InvalidIntOverflow{ pos: int_pos })
+                        },
+                        _ => return Scan::Error(BencodeParseErrorKind::InvalidIntParseError{ pos: int_pos })
+                    }
+                },
+                ScanState::Length{ pos: length_pos, len, length } => {
+                    self.scan_pos += 1;
+
+                    match byte {
+                        // Decoder reports lengths that are too large, so we never wait on their bytes
+                        ::BYTE_LEN_END if length > i64::max_value() as u64 ||
+                                          length > self.opts.max_byte_string_len() as u64 => return Scan::Decode,
+                        ::BYTE_LEN_END => ScanState::Bytes{ remaining: length },
+                        ::BYTE_LEN_LOW...::BYTE_LEN_HIGH if len < MAX_INT_LEN => {
+                            let length = length.saturating_mul(10).saturating_add((byte - b'0') as u64);
+
+                            ScanState::Length{ pos: length_pos, len: len + 1, length: length }
+                        },
+                        ::BYTE_LEN_LOW...::BYTE_LEN_HIGH => {
+                            return Scan::Error(BencodeParseErrorKind::InvalidIntOverflow{ pos: length_pos })
+                        },
+                        _ => return Scan::Error(BencodeParseErrorKind::InvalidIntParseError{ pos: length_pos })
+                    }
+                }
+            };
+
+            if let ScanState::Bytes{ remaining: 0 } = self.scan_state {
+                self.scan_state = ScanState::Value;
+            }
+            if let (0, ScanState::Value) = (self.scan_depth, self.scan_state) {
+                return Scan::Decode
+            }
+        }
+
+        Scan::Incomplete
+    }
+}
+
+fn shift_error(error: BencodeParseError, offset: usize) -> BencodeParseError {
+    let kind = match error.0 {
+        BencodeParseErrorKind::BytesEmpty{ pos }               => BencodeParseErrorKind::BytesEmpty{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidByte{ pos }              => BencodeParseErrorKind::InvalidByte{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidIntNoDelimiter{ pos }    => BencodeParseErrorKind::InvalidIntNoDelimiter{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidIntNegativeZero{ pos }   => BencodeParseErrorKind::InvalidIntNegativeZero{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidIntZeroPadding{ pos }    => BencodeParseErrorKind::InvalidIntZeroPadding{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidIntParseError{ pos }     => BencodeParseErrorKind::InvalidIntParseError{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidIntOverflow{ pos }       => BencodeParseErrorKind::InvalidIntOverflow{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidKeyOrdering{ pos, key }  => BencodeParseErrorKind::InvalidKeyOrdering{ pos: pos + offset, key: key },
+        BencodeParseErrorKind::InvalidKeyDuplicates{ pos, key } => BencodeParseErrorKind::InvalidKeyDuplicates{ pos: pos + offset, key: key },
+        BencodeParseErrorKind::InvalidLengthNegative{ pos }    => BencodeParseErrorKind::InvalidLengthNegative{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidLengthOverflow{ pos }    => BencodeParseErrorKind::InvalidLengthOverflow{ pos: pos + offset },
+        BencodeParseErrorKind::InvalidLengthExceeded{ pos, max } => BencodeParseErrorKind::InvalidLengthExceeded{ pos: pos + offset, max: max },
+        BencodeParseErrorKind::InvalidRecursionExceeded{ pos, max } => BencodeParseErrorKind::InvalidRecursionExceeded{ pos: pos + offset, max: max },
+        other => other
+    };
+
+    BencodeParseError::from_kind(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::default::Default;
+
+    use access::bencode::BRefAccess;
+    use error::BencodeParseErrorKind;
+    use reference::decode_opt::BDecodeOpt;
+    use stream::StreamDecoder;
+
+    #[test]
+    fn positive_feed_single_value() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        let values = decoder.feed(b"d3:keyi5ee").unwrap();
+
+        assert_eq!(1, values.len());
+        assert_eq!(5, values[0].dict().unwrap().lookup(b"key").unwrap().int().unwrap());
+        assert_eq!(10, decoder.bytes_consumed());
+        assert_eq!(0, decoder.bytes_buffered());
+    }
+
+    #[test]
+    fn positive_feed_byte_by_byte() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+        let bytes = b"l4:spami-42ee";
+
+        for byte in &bytes[..bytes.len() - 1] {
+            assert!(decoder.feed(&[*byte]).unwrap().is_empty());
+        }
+        assert_eq!(0, decoder.bytes_consumed());
+        assert_eq!(bytes.len() - 1, decoder.bytes_buffered());
+
+        let values = decoder.feed(&bytes[bytes.len() - 1..]).unwrap();
+        let list = values[0].list().unwrap();
+
+        assert_eq!("spam", list[0].str().unwrap());
+        assert_eq!(-42, list[1].int().unwrap());
+        assert_eq!(bytes.len(), decoder.bytes_consumed());
+    }
+
+    #[test]
+    fn positive_feed_multiple_values_with_partial() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        let values = decoder.feed(b"i1e3:abci2e5:ab").unwrap();
+
+        assert_eq!(3, values.len());
+        assert_eq!(11, decoder.bytes_consumed());
+        assert_eq!(4, decoder.bytes_buffered());
+
+        let values = decoder.feed(b"cde").unwrap();
+
+        assert_eq!("abcde", values[0].str().unwrap());
+        assert_eq!(0, decoder.bytes_buffered());
+    }
+
+    #[test]
+    fn positive_feed_huge_length_buffers_without_max() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default().with_max_byte_string_len(::std::usize::MAX));
+
+        assert!(decoder.feed(b"99999999999:").unwrap().is_empty());
+        assert_eq!(12, decoder.bytes_buffered());
+    }
+
+    #[test]
+    fn negative_feed_huge_length_exceeds_max() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default().with_max_byte_string_len(1 << 20));
+
+        decoder.feed(b"i1e").unwrap();
+        let error = decoder.feed(b"99999999999:").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidLengthExceeded{ pos, max } => {
+                assert_eq!(3, pos);
+                assert_eq!(1 << 20, max);
+            },
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn negative_feed_error_stream_offset() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        decoder.feed(b"i1e").unwrap();
+        let error = decoder.feed(b"lx").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidByte{ pos } => assert_eq!(4, pos),
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn negative_feed_value_then_garbage() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        let values = decoder.feed(b"i1ei2ex").unwrap();
+        assert_eq!(2, values.len());
+        assert_eq!(1, values[0].int().unwrap());
+        assert_eq!(2, values[1].int().unwrap());
+        assert_eq!(6, decoder.bytes_consumed());
+
+        let error = decoder.feed(b"").unwrap_err();
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidByte{ pos } => assert_eq!(6, pos),
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn positive_feed_byte_string_with_delimiters_byte_by_byte() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+        let bytes = b"d0:0:3:key6:le:ie:e";
+
+        let mut values = Vec::new();
+        for byte in bytes.iter() {
+            values.extend(decoder.feed(&[*byte]).unwrap());
+        }
+
+        assert_eq!(1, values.len());
+        assert_eq!("le:ie:", values[0].dict().unwrap().lookup(b"key").unwrap().str().unwrap());
+        assert_eq!("", values[0].dict().unwrap().lookup(b"").unwrap().str().unwrap());
+        assert_eq!(0, decoder.bytes_buffered());
+    }
+
+    #[test]
+    fn negative_feed_unterminated_int_exceeds_max_len() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        decoder.feed(b"i1e").unwrap();
+        assert!(decoder.feed(b"i12345678901234567890").unwrap().is_empty());
+        let error = decoder.feed(b"1").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidIntOverflow{ pos } => assert_eq!(4, pos),
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn negative_feed_unterminated_length_exceeds_max_len() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default());
+
+        let error = decoder.feed(b"123456789012345678901").unwrap_err();
+
+        match *error.kind() {
+            BencodeParseErrorKind::InvalidIntOverflow{ pos } => assert_eq!(0, pos),
+            _ => panic!("Unexpected Error Kind")
+        }
+    }
+
+    #[test]
+    fn negative_feed_exceeds_max_buffered_bytes() {
+        let mut decoder = StreamDecoder::new(BDecodeOpt::default()).with_max_buffered_bytes(8);
+
+        assert_eq!(1, decoder.feed(b"i1el4:spam").unwrap().len());
+        for _ in 0..2 {
+            let error = decoder.feed(b"4:eggs").unwrap_err();
+            assert_eq!(Some(3), error.byte_offset());
+            assert_eq!("Value Within The Buffer Limit", error.kind().expected());
+            assert_eq!("Value Past The Buffer Limit", error.kind().found());
+
+            match *error.kind() {
+                BencodeParseErrorKind::InvalidBufferExceeded{ pos, max } => {
+                    assert_eq!(3, pos);
+                    assert_eq!(8, max);
+                },
+                _ => panic!("Unexpected Error Kind")
+            }
+        }
+    }
+}
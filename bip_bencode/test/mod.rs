@@ -17,4 +17,40 @@ fn positive_ben_list_macro() {
     )).encode();
 
     assert_eq!("li5ee".as_bytes(), &result[..]);
-}
\ No newline at end of file
+}
+
+#[test]
+fn positive_ben_dict_macro() {
+    let result = (ben_dict!{
+        "b" => ben_int!(2),
+        "a" => ben_int!(1),
+    }).encode();
+
+    assert_eq!("d1:ai1e1:bi2ee".as_bytes(), &result[..]);
+}
+
+#[test]
+fn positive_ben_empty_macros() {
+    assert_eq!("le".as_bytes(), &ben_list!().encode()[..]);
+    assert_eq!("de".as_bytes(), &ben_dict!{}.encode()[..]);
+}
+
+#[test]
+fn positive_ben_nested_macros() {
+    let result = (ben_dict!{
+        "announce" => ben_bytes!("udp://test.com:80"),
+        "info" => ben_dict!{
+            "files" => ben_list![
+                ben_dict!{
+                    "length" => ben_int!(5),
+                    "path" => ben_list![ben_bytes!("dir"), ben_bytes!("file")]
+                },
+                ben_list![ben_int!(-1), ben_list![]]
+            ],
+            "name" => ben_bytes!(String::from("test"))
+        }
+    }).encode();
+
+    assert_eq!("d8:announce17:udp://test.com:804:infod5:filesld6:lengthi5e4:pathl3:dir4:fileeeli-1eleee4:name4:testee".as_bytes(),
+               &result[..]);
+}
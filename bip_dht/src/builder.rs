@@ -9,8 +9,15 @@ use bip_util::net;
 use mio::Sender;
 
 use router::Router;
+use routing::persist::{self, RestoredTable};
 use worker::{self, OneshotTask, DhtEvent, ShutdownCause};
 
+/// Routers we bootstrap from when no routers or nodes were supplied.
+const DEFAULT_ROUTERS: [Router; 4] = [Router::uTorrent,
+                                      Router::BitTorrent,
+                                      Router::BitComet,
+                                      Router::Transmission];
+
 /// Maintains a Distributed Hash (Routing) Table.
 pub struct MainlineDht {
     send: Sender<OneshotTask>,
@@ -30,13 +37,19 @@ impl MainlineDht {
         let send = try!(worker::start_mainline_dht(send_sock,
                                                    recv_sock,
                                                    builder.read_only,
+                                                   builder.implied_port,
                                                    builder.ext_addr,
+                                                   builder.restored,
                                                    handshaker,
                                                    kill_sock,
                                                    kill_addr));
 
         let nodes: Vec<SocketAddr> = builder.nodes.into_iter().collect();
-        let routers: Vec<Router> = builder.routers.into_iter().collect();
+        let routers: Vec<Router> = if builder.routers.is_empty() && nodes.is_empty() {
+            DEFAULT_ROUTERS.to_vec()
+        } else {
+            builder.routers.into_iter().collect()
+        };
 
         if send.send(OneshotTask::StartBootstrap(routers, nodes)).is_err() {
             warn!("bip_dt: MainlineDht failed to send a start bootstrap message...");
@@ -59,6 +72,20 @@ impl MainlineDht {
         }
     }
 
+    /// Export the recently responsive nodes in our routing table, so that they can be
+    /// restored with DhtBuilder::restore_routing_table the next time we start up.
+    ///
+    /// Returns an empty Vec if the DHT has already shut down.
+    pub fn export_routing_table(&self) -> Vec<u8> {
+        let (send, recv) = mpsc::channel();
+
+        if self.send.send(OneshotTask::ExportRoutingTable(send)).is_err() {
+            warn!("bip_dht: MainlineDht failed to send an export routing table message...");
+        }
+
+        recv.recv().unwrap_or(Vec::new())
+    }
+
     /// An event Receiver which will receive events occuring within the DHT.
     ///
     /// It is important to at least monitor the DHT for shutdown events as any calls
@@ -94,22 +121,26 @@ pub struct DhtBuilder {
     nodes: HashSet<SocketAddr>,
     routers: HashSet<Router>,
     read_only: bool,
+    implied_port: bool,
     src_addr: SocketAddr,
     ext_addr: Option<SocketAddr>,
+    restored: Option<RestoredTable>,
 }
 
 impl DhtBuilder {
     /// Create a new DhtBuilder.
     ///
-    /// This should not be used directly, force the user to supply builder with
-    /// some initial bootstrap method.
-    fn new() -> DhtBuilder {
+    /// If no routers or nodes are added before starting the DHT, we will bootstrap
+    /// from a default set of well known routers.
+    pub fn new() -> DhtBuilder {
         DhtBuilder {
             nodes: HashSet::new(),
             routers: HashSet::new(),
             read_only: true,
+            implied_port: false,
             src_addr: net::default_route_v4(),
             ext_addr: None,
+            restored: None,
         }
     }
 
@@ -147,6 +178,39 @@ impl DhtBuilder {
         self
     }
 
+    /// Add the address of a bootstrap node, which is treated as a router.
+    ///
+    /// Useful for bootstrapping off of private routers, or routers reachable over IPv6.
+    pub fn add_bootstrap_node(self, node_addr: SocketAddr) -> DhtBuilder {
+        self.add_router(Router::Custom(node_addr))
+    }
+
+    /// Replace any routers that were added with the given bootstrap node addresses.
+    ///
+    /// See DhtBuilder::add_bootstrap_node.
+    pub fn set_bootstrap_nodes(mut self, node_addrs: Vec<SocketAddr>) -> DhtBuilder {
+        self.routers = node_addrs.into_iter().map(Router::Custom).collect();
+
+        self
+    }
+
+    /// Restore a routing table exported with MainlineDht::export_routing_table.
+    ///
+    /// Our previous node id is reused and the exported nodes are placed in our routing
+    /// table, as well as being used to bootstrap from, which saves us from having to
+    /// rediscover nodes through the routers.
+    pub fn restore_routing_table(mut self, bytes: &[u8]) -> io::Result<DhtBuilder> {
+        let restored = try!(persist::restore_tables(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())));
+
+        for &(_, addr) in restored.nodes().iter().filter(|&&(_, addr)| addr.is_ipv4()) {
+            self.nodes.insert(addr);
+        }
+        self.restored = Some(restored);
+
+        Ok(self)
+    }
+
     /// Set the read only flag when communicating with other nodes. Indicates
     /// that remote nodes should not add us to their routing table.
     ///
@@ -158,11 +222,23 @@ impl DhtBuilder {
         self
     }
 
+    /// Set whether our announces should ask nodes to use the source port of the announce
+    /// instead of the port of our handshaker. Default value is false.
+    ///
+    /// This is useful for clients behind a NAT, where the port seen by nodes may not be
+    /// the port we are listening on, as long as the handshaker shares our DHT socket.
+    pub fn set_implied_port(mut self, implied_port: bool) -> DhtBuilder {
+        self.implied_port = implied_port;
+
+        self
+    }
+
     /// Provide the DHT with our external address. If this is not supplied we will
     /// have to deduce this information from remote nodes.
     ///
     /// Purpose of the external address is to generate a NodeId that conforms to
-    /// BEP 42 so that nodes can safely store information on our node.
+    /// BEP 42 so that nodes can safely store information on our node. A restored
+    /// NodeId that does not conform to an IPv4 external address will be replaced.
     pub fn set_external_addr(mut self, addr: SocketAddr) -> DhtBuilder {
         self.ext_addr = Some(addr);
 
@@ -1,6 +1,6 @@
 use std::io;
 
-use bip_bencode::BencodeConvertError;
+use bip_bencode::{BencodeConvertError, BencodeParseError};
 
 use message::error::ErrorMessage;
 
@@ -11,6 +11,7 @@ error_chain! {
 
     foreign_links {
         Bencode(BencodeConvertError);
+        BencodeParse(BencodeParseError);
         Io(io::Error);
     }
 
@@ -37,5 +38,11 @@ error_chain! {
             description("Node Sent Us An Invalid Request Message")
             display("Node Sent Us An Invalid Request Message With Code {:?} And Message {}", msg.error_code(), msg.error_message())
         }
+        InvalidRoutingTable {
+            details: String
+        } {
+            description("Exported Routing Table Is Invalid")
+            display("Exported Routing Table Is Invalid: {}", details)
+        }
     }
 }
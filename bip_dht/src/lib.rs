@@ -19,7 +19,6 @@ extern crate error_chain;
 // - Unrecognized requests which contain either an 'info_hash' or 'target' arguments are interpreted as 'find_node'
 // - Client identification will be present in all outgoing messages in the form of the 'v' key TODO
 // const CLIENT_IDENTIFICATION: &'static [u8] = &[b'B', b'I', b'P', 0, 1];
-// * IPv6 is currently NOT supported in this implementation
 
 // TODO: The Vuze dht operates over a protocol that is different than the mainline dht.
 // It would be possible to create a dht client that can work over both dhts simultaneously,
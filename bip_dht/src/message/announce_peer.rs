@@ -26,6 +26,7 @@ pub struct AnnouncePeerRequest<'a> {
     info_hash: InfoHash,
     token: &'a [u8],
     port: ConnectPort,
+    read_only: bool,
 }
 
 impl<'a> AnnouncePeerRequest<'a> {
@@ -41,6 +42,7 @@ impl<'a> AnnouncePeerRequest<'a> {
             info_hash: info_hash,
             token: token,
             port: port,
+            read_only: false,
         }
     }
 
@@ -94,6 +96,18 @@ impl<'a> AnnouncePeerRequest<'a> {
         self.port
     }
 
+    /// Flag the request as coming from a read only node, as specified in BEP 43.
+    pub fn with_read_only(mut self, read_only: bool) -> AnnouncePeerRequest<'a> {
+        self.read_only = read_only;
+
+        self
+    }
+
+    /// Whether the requester is a read only node.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         // In case a client errors out when the port key is not present, even when
         // implied port is specified, we will provide a dummy value in that case.
@@ -102,20 +116,15 @@ impl<'a> AnnouncePeerRequest<'a> {
             ConnectPort::Explicit(n) => (n, 0),
         };
 
-        (ben_map!{
-            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
-            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
-            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
-            message::REQUEST_TYPE_KEY => ben_bytes!(request::ANNOUNCE_PEER_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                IMPLIED_PORT_KEY => ben_int!(implied_value),
-                message::INFO_HASH_KEY => ben_bytes!(self.info_hash.as_ref()),
-                PORT_KEY => ben_int!(displayed_port as i64),
-                message::TOKEN_KEY => ben_bytes!(self.token)
-            }
-        })
-            .encode()
+        let request_args = ben_map!{
+            message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
+            IMPLIED_PORT_KEY => ben_int!(implied_value),
+            message::INFO_HASH_KEY => ben_bytes!(self.info_hash.as_ref()),
+            PORT_KEY => ben_int!(displayed_port as i64),
+            message::TOKEN_KEY => ben_bytes!(self.token)
+        };
+
+        request::encode_request(self.trans_id, request::ANNOUNCE_PEER_TYPE_KEY, request_args, self.read_only)
     }
 }
 
@@ -165,3 +174,57 @@ impl<'a> AnnouncePeerResponse<'a> {
             .encode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt::{self, NodeId, InfoHash};
+
+    use message::MessageType;
+    use message::announce_peer::{AnnouncePeerRequest, ConnectPort};
+    use message::request::RequestType;
+    use message::response::ExpectedResponse;
+
+    #[test]
+    fn positive_encode_implied_port() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+        let info_hash: InfoHash = [2u8; bt::INFO_HASH_LEN].into();
+
+        let encoded =
+            AnnouncePeerRequest::new(b"aa", node_id, info_hash, b"token", ConnectPort::Implied)
+                .encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        let args = bencode.dict().unwrap().lookup(b"a").unwrap().dict().unwrap();
+        assert_eq!(Some(1), args.lookup(b"implied_port").and_then(|i| i.int()));
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::AnnouncePeer(a)) => {
+                assert_eq!(ConnectPort::Implied, a.connect_port());
+            }
+            _ => panic!("Failed To Decode AnnouncePeerRequest"),
+        }
+    }
+
+    #[test]
+    fn positive_encode_explicit_port_read_only() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+        let info_hash: InfoHash = [2u8; bt::INFO_HASH_LEN].into();
+
+        let encoded = AnnouncePeerRequest::new(b"aa",
+                                               node_id,
+                                               info_hash,
+                                               b"token",
+                                               ConnectPort::Explicit(6881))
+            .with_read_only(true)
+            .encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::AnnouncePeer(a)) => {
+                assert_eq!(ConnectPort::Explicit(6881), a.connect_port());
+                assert!(a.read_only());
+            }
+            _ => panic!("Failed To Decode AnnouncePeerRequest"),
+        }
+    }
+}
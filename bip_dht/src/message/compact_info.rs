@@ -1,4 +1,4 @@
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
 
 use bip_bencode::Bencode;
 use bip_util::error::{LengthError, LengthResult, LengthErrorKind};
@@ -12,6 +12,8 @@ use bip_util::sha::ShaHash;
 
 const BYTES_PER_COMPACT_IP: usize = 6;
 const BYTES_PER_COMPACT_NODE_INFO: usize = 26;
+const BYTES_PER_COMPACT_IP6: usize = 18;
+const BYTES_PER_COMPACT_NODE_INFO6: usize = 38;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct CompactNodeInfo<'a> {
@@ -70,6 +72,64 @@ impl<'a> Iterator for CompactNodeInfoIter<'a> {
 
 // ----------------------------------------------------------------------------//
 
+/// Compact node info for IPv6 nodes, found under the nodes6 key as specified in BEP 32.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CompactNodeInfo6<'a> {
+    nodes: &'a [u8],
+}
+
+impl<'a> CompactNodeInfo6<'a> {
+    pub fn new(nodes: &'a [u8]) -> LengthResult<CompactNodeInfo6<'a>> {
+        if nodes.len() % BYTES_PER_COMPACT_NODE_INFO6 != 0 {
+            Err(LengthError::new(LengthErrorKind::LengthMultipleExpected,
+                                 BYTES_PER_COMPACT_NODE_INFO6))
+        } else {
+            Ok(CompactNodeInfo6 { nodes: nodes })
+        }
+    }
+
+    pub fn nodes(&self) -> &'a [u8] {
+        self.nodes
+    }
+}
+
+impl<'a> IntoIterator for CompactNodeInfo6<'a> {
+    type Item = (NodeId, SocketAddrV6);
+    type IntoIter = CompactNodeInfo6Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        CompactNodeInfo6Iter {
+            nodes: self.nodes,
+            pos: 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CompactNodeInfo6Iter<'a> {
+    nodes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for CompactNodeInfo6Iter<'a> {
+    type Item = (NodeId, SocketAddrV6);
+
+    fn next(&mut self) -> Option<(NodeId, SocketAddrV6)> {
+        if self.pos == self.nodes.len() {
+            None
+        } else {
+            let compact_info_offset = self.pos + BYTES_PER_COMPACT_NODE_INFO6;
+            let compact_info = &self.nodes[self.pos..compact_info_offset];
+
+            self.pos += BYTES_PER_COMPACT_NODE_INFO6;
+
+            Some(parts_from_compact_info6(compact_info))
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct CompactValueInfo<'a> {
     values: &'a [Bencode<'a>],
@@ -148,6 +208,16 @@ fn parts_from_compact_info(compact_info: &[u8]) -> (NodeId, SocketAddrV4) {
     (node_id, socket)
 }
 
+/// Panics if the size of compact_info is less than BYTES_PER_COMPACT_NODE_INFO6.
+fn parts_from_compact_info6(compact_info: &[u8]) -> (NodeId, SocketAddrV6) {
+    let node_id = ShaHash::from_hash(&compact_info[0..bt::NODE_ID_LEN]).unwrap();
+
+    let compact_ip_offset = bt::NODE_ID_LEN + BYTES_PER_COMPACT_IP6;
+    let socket = socket_v6_from_bytes_be(&compact_info[bt::NODE_ID_LEN..compact_ip_offset])
+        .unwrap();
+
+    (node_id, socket)
+}
 
 fn socket_v4_from_bytes_be(bytes: &[u8]) -> LengthResult<SocketAddrV4> {
     if bytes.len() != BYTES_PER_COMPACT_IP {
@@ -166,14 +236,38 @@ fn socket_v4_from_bytes_be(bytes: &[u8]) -> LengthResult<SocketAddrV4> {
     }
 }
 
+fn socket_v6_from_bytes_be(bytes: &[u8]) -> LengthResult<SocketAddrV6> {
+    if bytes.len() != BYTES_PER_COMPACT_IP6 {
+        Err(LengthError::new(LengthErrorKind::LengthExpected, BYTES_PER_COMPACT_IP6))
+    } else {
+        let mut segments = [0u16; 8];
+        for (segment, chunk) in segments.iter_mut().zip(bytes[..16].chunks(2)) {
+            *segment = (chunk[0] as u16) << 8 | chunk[1] as u16;
+        }
+
+        let port = (bytes[16] as u16) << 8 | bytes[17] as u16;
+
+        let ip = Ipv6Addr::new(segments[0],
+                               segments[1],
+                               segments[2],
+                               segments[3],
+                               segments[4],
+                               segments[5],
+                               segments[6],
+                               segments[7]);
+
+        Ok(SocketAddrV6::new(ip, port, 0, 0))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::net::{SocketAddrV4, Ipv4Addr};
+    use std::net::{SocketAddrV4, SocketAddrV6, Ipv4Addr, Ipv6Addr};
 
     use bip_util::bt::NodeId;
     use bip_util::sha::ShaHash;
 
-    use message::compact_info::{CompactNodeInfo, CompactValueInfo};
+    use message::compact_info::{CompactNodeInfo, CompactNodeInfo6, CompactValueInfo};
 
     #[test]
     fn positive_compact_nodes_empty() {
@@ -219,6 +313,28 @@ mod tests {
                    SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 2), 240));
     }
 
+    #[test]
+    fn positive_compact_nodes6_one() {
+        let bytes = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0x20, 0x01, 0x0d,
+                     0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 170, 169];
+        let compact_node = CompactNodeInfo6::new(&bytes[..]).unwrap();
+
+        let collected_info: Vec<(NodeId, SocketAddrV6)> = compact_node.into_iter().collect();
+        assert_eq!(collected_info.len(), 1);
+
+        assert_eq!(collected_info[0].0,
+                   ShaHash::from_hash(&bytes[0..20]).unwrap());
+        assert_eq!(collected_info[0].1,
+                   SocketAddrV6::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 43689, 0, 0));
+    }
+
+    #[test]
+    fn negative_compact_nodes6_v4_length() {
+        let bytes = [1u8; 26];
+
+        assert!(CompactNodeInfo6::new(&bytes[..]).is_err());
+    }
+
     #[test]
     fn positive_compact_values_empty() {
         let bencode_values = Vec::new();
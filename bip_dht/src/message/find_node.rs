@@ -1,10 +1,12 @@
+use std::collections::BTreeMap;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary};
 use bip_util::bt::NodeId;
 
 use message;
-use message::compact_info::CompactNodeInfo;
-use message::request::{self, RequestValidate};
-use message::response::ResponseValidate;
+use message::compact_info::{CompactNodeInfo, CompactNodeInfo6};
+use message::request::{self, RequestValidate, Want};
+use message::response::{self, ResponseValidate};
 use error::DhtResult;
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -12,6 +14,8 @@ pub struct FindNodeRequest<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
     target_id: NodeId,
+    want: Option<Want>,
+    read_only: bool,
 }
 
 impl<'a> FindNodeRequest<'a> {
@@ -20,6 +24,23 @@ impl<'a> FindNodeRequest<'a> {
             trans_id: trans_id,
             node_id: node_id,
             target_id: target_id,
+            want: None,
+            read_only: false,
+        }
+    }
+
+    /// Create a FindNodeRequest asking for nodes of the given address families.
+    pub fn with_want(trans_id: &'a [u8],
+                     node_id: NodeId,
+                     target_id: NodeId,
+                     want: Want)
+                     -> FindNodeRequest<'a> {
+        FindNodeRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            target_id: target_id,
+            want: Some(want),
+            read_only: false,
         }
     }
 
@@ -40,7 +61,15 @@ impl<'a> FindNodeRequest<'a> {
         let target_id_bytes = try!(validate.lookup_and_convert_bytes(rqst_root, target_key));
         let target_id = try!(validate.validate_node_id(target_id_bytes));
 
-        Ok(FindNodeRequest::new(trans_id, node_id, target_id))
+        let want = try!(validate.lookup_want(rqst_root));
+
+        Ok(FindNodeRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            target_id: target_id,
+            want: want,
+            read_only: false,
+        })
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -55,18 +84,35 @@ impl<'a> FindNodeRequest<'a> {
         self.target_id
     }
 
+    /// Address families the requester asked for, if they specified any.
+    pub fn want(&self) -> Option<Want> {
+        self.want
+    }
+
+    /// Flag the request as coming from a read only node, as specified in BEP 43.
+    pub fn with_read_only(mut self, read_only: bool) -> FindNodeRequest<'a> {
+        self.read_only = read_only;
+
+        self
+    }
+
+    /// Whether the requester is a read only node.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn encode(&self) -> Vec<u8> {
-        (ben_map!{
-            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
-            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
-            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
-            message::REQUEST_TYPE_KEY => ben_bytes!(request::FIND_NODE_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                message::TARGET_ID_KEY => ben_bytes!(self.target_id.as_ref())
-            }
-        })
-            .encode()
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::TARGET_ID_KEY.as_bytes(),
+                            ben_bytes!(self.target_id.as_ref()));
+        if let Some(want) = self.want {
+            request_args.insert(message::WANT_KEY.as_bytes(), want.to_bencode());
+        }
+
+        request::encode_request(self.trans_id, request::FIND_NODE_TYPE_KEY, Bencode::Dict(request_args), self.read_only)
     }
 }
 
@@ -75,6 +121,7 @@ pub struct FindNodeResponse<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
     nodes: CompactNodeInfo<'a>,
+    nodes6: Option<CompactNodeInfo6<'a>>,
 }
 
 impl<'a> FindNodeResponse<'a> {
@@ -89,9 +136,25 @@ impl<'a> FindNodeResponse<'a> {
             trans_id: trans_id,
             node_id: node_id,
             nodes: compact_nodes,
+            nodes6: None,
         })
     }
 
+    /// Create a FindNodeResponse which also contains IPv6 nodes.
+    pub fn with_nodes6(trans_id: &'a [u8],
+                       node_id: NodeId,
+                       nodes: &'a [u8],
+                       nodes6: &'a [u8])
+                       -> DhtResult<FindNodeResponse<'a>> {
+        let validate = ResponseValidate::new(trans_id);
+        let compact_nodes6 = try!(validate.validate_nodes6(nodes6));
+
+        let mut find_node_rsp = try!(FindNodeResponse::new(trans_id, node_id, nodes));
+        find_node_rsp.nodes6 = Some(compact_nodes6);
+
+        Ok(find_node_rsp)
+    }
+
     pub fn from_parts(rsp_root: &Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8])
                       -> DhtResult<FindNodeResponse<'a>> {
@@ -100,9 +163,23 @@ impl<'a> FindNodeResponse<'a> {
         let node_id_bytes = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODE_ID_KEY));
         let node_id = try!(validate.validate_node_id(node_id_bytes));
 
-        let nodes = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODES_KEY));
+        // Nodes that were only asked for IPv6 nodes may leave out the nodes key
+        match rsp_root.lookup(message::NODES6_KEY.as_bytes()) {
+            Some(_) => {
+                let nodes6 = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODES6_KEY));
+                let nodes = match rsp_root.lookup(message::NODES_KEY.as_bytes()) {
+                    Some(_) => try!(validate.lookup_and_convert_bytes(rsp_root, message::NODES_KEY)),
+                    None => &[],
+                };
+
+                FindNodeResponse::with_nodes6(trans_id, node_id, nodes, nodes6)
+            }
+            None => {
+                let nodes = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODES_KEY));
 
-        FindNodeResponse::new(trans_id, node_id, nodes)
+                FindNodeResponse::new(trans_id, node_id, nodes)
+            }
+        }
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -117,16 +194,132 @@ impl<'a> FindNodeResponse<'a> {
         self.nodes
     }
 
+    pub fn nodes6(&self) -> Option<CompactNodeInfo6<'a>> {
+        self.nodes6
+    }
+
     pub fn encode(&self) -> Vec<u8> {
+        let mut response_args = BTreeMap::new();
+
+        response_args.insert(message::NODE_ID_KEY.as_bytes(),
+                             ben_bytes!(self.node_id.as_ref()));
+        response_args.insert(message::NODES_KEY.as_bytes(), ben_bytes!(self.nodes.nodes()));
+        if let Some(nodes6) = self.nodes6 {
+            response_args.insert(message::NODES6_KEY.as_bytes(), ben_bytes!(nodes6.nodes()));
+        }
+
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
             message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
             message::MESSAGE_TYPE_KEY => ben_bytes!(message::RESPONSE_TYPE_KEY),
-            message::RESPONSE_TYPE_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                message::NODES_KEY => ben_bytes!(self.nodes.nodes())
-            }
+            response::RESPONSE_ARGS_KEY => Bencode::Dict(response_args)
         })
             .encode()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv6Addr, SocketAddrV6};
+
+    use bip_bencode::Bencode;
+    use bip_util::bt::{self, NodeId};
+
+    use message::MessageType;
+    use message::find_node::{FindNodeRequest, FindNodeResponse};
+    use message::request::{RequestType, Want};
+    use message::response::{ResponseType, ExpectedResponse};
+
+    #[test]
+    fn positive_decode_nodes6_response() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+        let mut nodes6 = vec![2u8; bt::NODE_ID_LEN];
+        nodes6.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        nodes6.extend_from_slice(&[0x1A, 0xE1]);
+
+        let encoded = FindNodeResponse::with_nodes6(b"aa", node_id, &[], &nodes6)
+            .unwrap()
+            .encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::FindNode).unwrap() {
+            MessageType::Response(ResponseType::FindNode(f)) => {
+                assert_eq!(node_id, f.node_id());
+                assert_eq!(0, f.nodes().into_iter().count());
+
+                let decoded: Vec<_> = f.nodes6().unwrap().into_iter().collect();
+                let expected_ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+                assert_eq!(vec![([2u8; bt::NODE_ID_LEN].into(),
+                                 SocketAddrV6::new(expected_ip, 6881, 0, 0))],
+                           decoded);
+            }
+            _ => panic!("Failed To Decode FindNodeResponse"),
+        }
+    }
+
+    #[test]
+    fn positive_decode_response_without_nodes6() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+
+        let encoded = FindNodeResponse::new(b"aa", node_id, &[3u8; 26]).unwrap().encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::FindNode).unwrap() {
+            MessageType::Response(ResponseType::FindNode(f)) => {
+                assert_eq!(1, f.nodes().into_iter().count());
+                assert!(f.nodes6().is_none());
+            }
+            _ => panic!("Failed To Decode FindNodeResponse"),
+        }
+    }
+
+    #[test]
+    fn positive_decode_request_want() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+
+        let encoded = FindNodeRequest::with_want(b"aa", node_id, node_id, Want::Both).encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(RequestType::FindNode(f)) => {
+                assert_eq!(Some(Want::Both), f.want());
+            }
+            _ => panic!("Failed To Decode FindNodeRequest"),
+        }
+    }
+
+    #[test]
+    fn positive_encode_request_read_only() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+
+        let encoded = FindNodeRequest::new(b"aa", node_id, node_id).with_read_only(true).encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        assert_eq!(Some(1), bencode.dict().unwrap().lookup(b"ro").and_then(|r| r.int()));
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(request) => assert!(request.read_only()),
+            _ => panic!("Failed To Decode FindNodeRequest"),
+        }
+    }
+
+    #[test]
+    fn positive_decode_request_not_read_only() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+
+        let encoded = FindNodeRequest::new(b"aa", node_id, node_id).encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        assert!(bencode.dict().unwrap().lookup(b"ro").is_none());
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(request) => assert!(!request.read_only()),
+            _ => panic!("Failed To Decode FindNodeRequest"),
+        }
+    }
+
+    #[test]
+    fn negative_decode_nodes6_wrong_length() {
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+
+        assert!(FindNodeResponse::with_nodes6(b"aa", node_id, &[], &[2u8; 26]).is_err());
+    }
+}
@@ -4,8 +4,8 @@ use bip_bencode::{Bencode, BencodeConvert, Dictionary};
 use bip_util::bt::{NodeId, InfoHash};
 
 use message;
-use message::compact_info::{CompactNodeInfo, CompactValueInfo};
-use message::request::{self, RequestValidate};
+use message::compact_info::{CompactNodeInfo, CompactNodeInfo6, CompactValueInfo};
+use message::request::{self, RequestValidate, Want};
 use message::response::{self, ResponseValidate};
 use error::{DhtResult, DhtErrorKind, DhtError};
 
@@ -14,6 +14,8 @@ pub struct GetPeersRequest<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
     info_hash: InfoHash,
+    want: Option<Want>,
+    read_only: bool,
 }
 
 impl<'a> GetPeersRequest<'a> {
@@ -22,6 +24,23 @@ impl<'a> GetPeersRequest<'a> {
             trans_id: trans_id,
             node_id: node_id,
             info_hash: info_hash,
+            want: None,
+            read_only: false,
+        }
+    }
+
+    /// Create a GetPeersRequest asking for nodes of the given address families.
+    pub fn with_want(trans_id: &'a [u8],
+                     node_id: NodeId,
+                     info_hash: InfoHash,
+                     want: Want)
+                     -> GetPeersRequest<'a> {
+        GetPeersRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            info_hash: info_hash,
+            want: Some(want),
+            read_only: false,
         }
     }
 
@@ -38,7 +57,15 @@ impl<'a> GetPeersRequest<'a> {
             try!(validate.lookup_and_convert_bytes(rqst_root, message::INFO_HASH_KEY));
         let info_hash = try!(validate.validate_info_hash(info_hash_bytes));
 
-        Ok(GetPeersRequest::new(trans_id, node_id, info_hash))
+        let want = try!(validate.lookup_want(rqst_root));
+
+        Ok(GetPeersRequest {
+            trans_id: trans_id,
+            node_id: node_id,
+            info_hash: info_hash,
+            want: want,
+            read_only: false,
+        })
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -53,18 +80,35 @@ impl<'a> GetPeersRequest<'a> {
         self.info_hash
     }
 
+    /// Address families the requester asked for, if they specified any.
+    pub fn want(&self) -> Option<Want> {
+        self.want
+    }
+
+    /// Flag the request as coming from a read only node, as specified in BEP 43.
+    pub fn with_read_only(mut self, read_only: bool) -> GetPeersRequest<'a> {
+        self.read_only = read_only;
+
+        self
+    }
+
+    /// Whether the requester is a read only node.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn encode(&self) -> Vec<u8> {
-        (ben_map!{
-            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
-            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
-            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
-            message::REQUEST_TYPE_KEY => ben_bytes!(request::GET_PEERS_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref()),
-                message::INFO_HASH_KEY => ben_bytes!(self.info_hash.as_ref())
-            }
-        })
-            .encode()
+        let mut request_args = BTreeMap::new();
+
+        request_args.insert(message::NODE_ID_KEY.as_bytes(),
+                            ben_bytes!(self.node_id.as_ref()));
+        request_args.insert(message::INFO_HASH_KEY.as_bytes(),
+                            ben_bytes!(self.info_hash.as_ref()));
+        if let Some(want) = self.want {
+            request_args.insert(message::WANT_KEY.as_bytes(), want.to_bencode());
+        }
+
+        request::encode_request(self.trans_id, request::GET_PEERS_TYPE_KEY, Bencode::Dict(request_args), self.read_only)
     }
 }
 
@@ -83,6 +127,7 @@ pub struct GetPeersResponse<'a> {
     // because they are only used for bootstraping and not to announce to.
     token: Option<&'a [u8]>,
     info_type: CompactInfoType<'a>,
+    nodes6: Option<CompactNodeInfo6<'a>>,
 }
 
 impl<'a> GetPeersResponse<'a> {
@@ -96,9 +141,23 @@ impl<'a> GetPeersResponse<'a> {
             node_id: node_id,
             token: token,
             info_type: info_type,
+            nodes6: None,
         }
     }
 
+    /// Create a GetPeersResponse which also contains IPv6 nodes.
+    pub fn with_nodes6(trans_id: &'a [u8],
+                       node_id: NodeId,
+                       token: Option<&'a [u8]>,
+                       info_type: CompactInfoType<'a>,
+                       nodes6: CompactNodeInfo6<'a>)
+                       -> GetPeersResponse<'a> {
+        let mut get_peers_rsp = GetPeersResponse::new(trans_id, node_id, token, info_type);
+        get_peers_rsp.nodes6 = Some(nodes6);
+
+        get_peers_rsp
+    }
+
     pub fn from_parts(rsp_root: &'a Dictionary<'a, Bencode<'a>>,
                       trans_id: &'a [u8])
                       -> DhtResult<GetPeersResponse<'a>> {
@@ -111,6 +170,13 @@ impl<'a> GetPeersResponse<'a> {
 
         let maybe_nodes = validate.lookup_and_convert_bytes(rsp_root, message::NODES_KEY);
         let maybe_values = validate.lookup_and_convert_list(rsp_root, message::VALUES_KEY);
+        let opt_nodes6 = match rsp_root.lookup(message::NODES6_KEY.as_bytes()) {
+            Some(_) => {
+                let nodes6 = try!(validate.lookup_and_convert_bytes(rsp_root, message::NODES6_KEY));
+                Some(try!(validate.validate_nodes6(nodes6)))
+            }
+            None => None,
+        };
 
         // TODO: Check if nodes in the wild actually send a 2d array of bytes as values or if they
         // stick with the more compact single byte array like that used for nodes.
@@ -128,6 +194,10 @@ impl<'a> GetPeersResponse<'a> {
                 let values_info = try!(validate.validate_values(values));
                 CompactInfoType::Values(values_info)
             }
+            (Err(_), Err(_)) if opt_nodes6.is_some() => {
+                // Nodes that were only asked for IPv6 nodes may leave out the nodes key
+                CompactInfoType::Nodes(try!(validate.validate_nodes(&[])))
+            }
             (Err(_), Err(_)) => {
                 return Err(DhtError::from_kind(DhtErrorKind::InvalidResponse {
                     details: "Failed To Find nodes Or values In Node Response".to_owned(),
@@ -135,7 +205,12 @@ impl<'a> GetPeersResponse<'a> {
             }
         };
 
-        Ok(GetPeersResponse::new(trans_id, node_id, token, info_type))
+        match opt_nodes6 {
+            Some(nodes6) => {
+                Ok(GetPeersResponse::with_nodes6(trans_id, node_id, token, info_type, nodes6))
+            }
+            None => Ok(GetPeersResponse::new(trans_id, node_id, token, info_type)),
+        }
     }
 
     pub fn transaction_id(&self) -> &'a [u8] {
@@ -154,6 +229,10 @@ impl<'a> GetPeersResponse<'a> {
         self.info_type
     }
 
+    pub fn nodes6(&self) -> Option<CompactNodeInfo6<'a>> {
+        self.nodes6
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut response_args = BTreeMap::new();
 
@@ -180,6 +259,9 @@ impl<'a> GetPeersResponse<'a> {
                                      Bencode::List(values.values().to_vec()));
             }
         };
+        if let Some(nodes6) = self.nodes6 {
+            response_args.insert(message::NODES6_KEY.as_bytes(), ben_bytes!(nodes6.nodes()));
+        }
 
         (ben_map!{
             //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
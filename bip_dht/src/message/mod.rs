@@ -19,6 +19,7 @@ pub mod announce_peer;
 // Top level message keys
 const TRANSACTION_ID_KEY: &'static str = "t";
 const MESSAGE_TYPE_KEY: &'static str = "y";
+const READ_ONLY_KEY: &'static str = "ro";
 // const CLIENT_TYPE_KEY:    &'static str = "v";
 
 // Top level message type sentinels
@@ -32,6 +33,8 @@ const ROOT_ID_KEY: &'static str = "root";
 // Keys common across message types
 const NODE_ID_KEY: &'static str = "id";
 const NODES_KEY: &'static str = "nodes";
+const NODES6_KEY: &'static str = "nodes6";
+const WANT_KEY: &'static str = "want";
 const VALUES_KEY: &'static str = "values";
 const TARGET_ID_KEY: &'static str = "target";
 const INFO_HASH_KEY: &'static str = "info_hash";
@@ -12,6 +12,7 @@ use error::DhtResult;
 pub struct PingRequest<'a> {
     trans_id: &'a [u8],
     node_id: NodeId,
+    read_only: bool,
 }
 
 impl<'a> PingRequest<'a> {
@@ -19,6 +20,7 @@ impl<'a> PingRequest<'a> {
         PingRequest {
             trans_id: trans_id,
             node_id: node_id,
+            read_only: false,
         }
     }
 
@@ -42,17 +44,24 @@ impl<'a> PingRequest<'a> {
         self.node_id
     }
 
+    /// Flag the request as coming from a read only node, as specified in BEP 43.
+    pub fn with_read_only(mut self, read_only: bool) -> PingRequest<'a> {
+        self.read_only = read_only;
+
+        self
+    }
+
+    /// Whether the requester is a read only node.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub fn encode(&self) -> Vec<u8> {
-        (ben_map!{
-            //message::CLIENT_TYPE_KEY => ben_bytes!(dht::CLIENT_IDENTIFICATION),
-            message::TRANSACTION_ID_KEY => ben_bytes!(self.trans_id),
-            message::MESSAGE_TYPE_KEY => ben_bytes!(message::REQUEST_TYPE_KEY),
-            message::REQUEST_TYPE_KEY => ben_bytes!(request::PING_TYPE_KEY),
-            request::REQUEST_ARGS_KEY => ben_map!{
-                message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref())
-            }
-        })
-            .encode()
+        let request_args = ben_map!{
+            message::NODE_ID_KEY => ben_bytes!(self.node_id.as_ref())
+        };
+
+        request::encode_request(self.trans_id, request::PING_TYPE_KEY, request_args, self.read_only)
     }
 }
 
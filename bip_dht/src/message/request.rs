@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
 use bip_bencode::{Bencode, BencodeConvert, Dictionary, BencodeConvertError};
 use bip_util::bt::{NodeId, InfoHash};
 
@@ -19,6 +22,10 @@ pub const ANNOUNCE_PEER_TYPE_KEY: &'static str = "announce_peer";
 // const GET_DATA_TYPE_KEY:          &'static str = "get";
 // const PUT_DATA_TYPE_KEY:          &'static str = "put";
 
+// Values for the want key
+const WANT_NODES_KEY: &'static str = "n4";
+const WANT_NODES6_KEY: &'static str = "n6";
+
 // ----------------------------------------------------------------------------//
 
 pub struct RequestValidate<'a> {
@@ -51,6 +58,32 @@ impl<'a> RequestValidate<'a> {
             DhtError::from_kind(DhtErrorKind::InvalidRequest { msg: error_msg })
         })
     }
+
+    /// Lookup the optional want key in the request.
+    ///
+    /// Values we do not recognize are ignored, if no recognized values are present, None is returned.
+    pub fn lookup_want(&self, rqst_root: &Dictionary<'a, Bencode<'a>>) -> DhtResult<Option<Want>> {
+        let want_list = match rqst_root.lookup(message::WANT_KEY.as_bytes()) {
+            Some(_) => try!(self.lookup_and_convert_list(rqst_root, message::WANT_KEY)),
+            None => return Ok(None),
+        };
+
+        let (mut want_v4, mut want_v6) = (false, false);
+        for want in want_list.iter() {
+            match want.str() {
+                Some(WANT_NODES_KEY) => want_v4 = true,
+                Some(WANT_NODES6_KEY) => want_v6 = true,
+                _ => (),
+            }
+        }
+
+        Ok(match (want_v4, want_v6) {
+            (true, true) => Some(Want::Both),
+            (true, false) => Some(Want::V4),
+            (false, true) => Some(Want::V6),
+            (false, false) => None,
+        })
+    }
 }
 
 impl<'a> BencodeConvert for RequestValidate<'a> {
@@ -63,6 +96,40 @@ impl<'a> BencodeConvert for RequestValidate<'a> {
 
 // ----------------------------------------------------------------------------//
 
+/// Address families a requester wants nodes for, as specified in BEP 32.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Want {
+    V4,
+    V6,
+    Both,
+}
+
+impl Want {
+    /// Want implied for a request that did not specify one, which is the family it was sent over.
+    pub fn from_addr(addr: &SocketAddr) -> Want {
+        match addr {
+            &SocketAddr::V4(_) => Want::V4,
+            &SocketAddr::V6(_) => Want::V6,
+        }
+    }
+
+    pub fn wants_v4(&self) -> bool {
+        *self != Want::V6
+    }
+
+    pub fn wants_v6(&self) -> bool {
+        *self != Want::V4
+    }
+
+    pub fn to_bencode(&self) -> Bencode<'static> {
+        match *self {
+            Want::V4 => ben_list!(ben_bytes!(WANT_NODES_KEY)),
+            Want::V6 => ben_list!(ben_bytes!(WANT_NODES6_KEY)),
+            Want::Both => ben_list!(ben_bytes!(WANT_NODES_KEY), ben_bytes!(WANT_NODES6_KEY)),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum RequestType<'a> {
     Ping(PingRequest<'a>),
@@ -80,23 +147,29 @@ impl<'a> RequestType<'a> {
         let validate = RequestValidate::new(trans_id);
         let rqst_root = try!(validate.lookup_and_convert_dict(root, REQUEST_ARGS_KEY));
 
+        // Any non zero value is treated as the requester being read only
+        let read_only = match root.lookup(message::READ_ONLY_KEY.as_bytes()).map(|r| r.int()) {
+            Some(Some(n)) => n != 0,
+            _ => false,
+        };
+
         match rqst_type {
             PING_TYPE_KEY => {
                 let ping_rqst = try!(PingRequest::from_parts(rqst_root, trans_id));
-                Ok(RequestType::Ping(ping_rqst))
+                Ok(RequestType::Ping(ping_rqst.with_read_only(read_only)))
             }
             FIND_NODE_TYPE_KEY => {
                 let find_node_rqst =
                     try!(FindNodeRequest::from_parts(rqst_root, trans_id, message::TARGET_ID_KEY));
-                Ok(RequestType::FindNode(find_node_rqst))
+                Ok(RequestType::FindNode(find_node_rqst.with_read_only(read_only)))
             }
             GET_PEERS_TYPE_KEY => {
                 let get_peers_rqst = try!(GetPeersRequest::from_parts(rqst_root, trans_id));
-                Ok(RequestType::GetPeers(get_peers_rqst))
+                Ok(RequestType::GetPeers(get_peers_rqst.with_read_only(read_only)))
             }
             ANNOUNCE_PEER_TYPE_KEY => {
                 let announce_peer_rqst = try!(AnnouncePeerRequest::from_parts(rqst_root, trans_id));
-                Ok(RequestType::AnnouncePeer(announce_peer_rqst))
+                Ok(RequestType::AnnouncePeer(announce_peer_rqst.with_read_only(read_only)))
             }
             // GET_DATA_TYPE_KEY => {
             // let get_data_rqst = try!(GetDataRequest::new(rqst_root, trans_id));
@@ -110,7 +183,7 @@ impl<'a> RequestType<'a> {
                 if let Some(target_key) = forward_compatible_find_node(rqst_root) {
                     let find_node_rqst =
                         try!(FindNodeRequest::from_parts(rqst_root, trans_id, target_key));
-                    Ok(RequestType::FindNode(find_node_rqst))
+                    Ok(RequestType::FindNode(find_node_rqst.with_read_only(read_only)))
                 } else {
                     let error_message =
                         ErrorMessage::new(trans_id.to_owned(),
@@ -122,6 +195,50 @@ impl<'a> RequestType<'a> {
             }
         }
     }
+
+    /// Node id of the requester.
+    pub fn node_id(&self) -> NodeId {
+        match *self {
+            RequestType::Ping(ref p) => p.node_id(),
+            RequestType::FindNode(ref f) => f.node_id(),
+            RequestType::GetPeers(ref g) => g.node_id(),
+            RequestType::AnnouncePeer(ref a) => a.node_id(),
+        }
+    }
+
+    /// Whether the requester is a read only node, as specified in BEP 43.
+    pub fn read_only(&self) -> bool {
+        match *self {
+            RequestType::Ping(ref p) => p.read_only(),
+            RequestType::FindNode(ref f) => f.read_only(),
+            RequestType::GetPeers(ref g) => g.read_only(),
+            RequestType::AnnouncePeer(ref a) => a.read_only(),
+        }
+    }
+}
+
+/// Encode a request with the given method and arguments.
+///
+/// Requests from read only nodes are flagged so that receivers do not add them to
+/// their routing table, as specified in BEP 43.
+pub fn encode_request<'a>(trans_id: &'a [u8],
+                          rqst_type: &'a str,
+                          rqst_args: Bencode<'a>,
+                          read_only: bool)
+                          -> Vec<u8> {
+    let mut rqst_root = BTreeMap::new();
+
+    // rqst_root.insert(message::CLIENT_TYPE_KEY.as_bytes(), ben_bytes!(dht::CLIENT_IDENTIFICATION));
+    rqst_root.insert(message::TRANSACTION_ID_KEY.as_bytes(), ben_bytes!(trans_id));
+    rqst_root.insert(message::MESSAGE_TYPE_KEY.as_bytes(),
+                     ben_bytes!(message::REQUEST_TYPE_KEY));
+    rqst_root.insert(message::REQUEST_TYPE_KEY.as_bytes(), ben_bytes!(rqst_type));
+    rqst_root.insert(REQUEST_ARGS_KEY.as_bytes(), rqst_args);
+    if read_only {
+        rqst_root.insert(message::READ_ONLY_KEY.as_bytes(), ben_int!(1));
+    }
+
+    Bencode::Dict(rqst_root).encode()
 }
 
 /// Mainline dht extension for forward compatibility.
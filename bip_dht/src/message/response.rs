@@ -1,7 +1,7 @@
 use bip_bencode::{Bencode, BencodeConvert, Dictionary, BencodeConvertError};
 use bip_util::bt::NodeId;
 
-use message::compact_info::{CompactNodeInfo, CompactValueInfo};
+use message::compact_info::{CompactNodeInfo, CompactNodeInfo6, CompactValueInfo};
 use message::ping::PingResponse;
 use message::find_node::FindNodeResponse;
 use message::get_peers::GetPeersResponse;
@@ -43,6 +43,18 @@ impl<'a> ResponseValidate<'a> {
         })
     }
 
+    /// Validate the given nodes string which should be IPv6 compact
+    pub fn validate_nodes6<'b>(&self, nodes: &'b [u8]) -> DhtResult<CompactNodeInfo6<'b>> {
+        CompactNodeInfo6::new(nodes).map_err(|_| {
+            DhtError::from_kind(DhtErrorKind::InvalidResponse {
+                details: format!("TID {:?} Found Nodes6 Structure With {} Number Of Bytes \
+                                  Instead Of Correct Multiple",
+                                 self.trans_id,
+                                 nodes.len()),
+            })
+        })
+    }
+
     pub fn validate_values<'b>(&self,
                                values: &'b [Bencode<'a>])
                                -> DhtResult<CompactValueInfo<'b>> {
@@ -83,7 +83,16 @@ impl Bucket {
         // See if any lower priority nodes are present in the table, we cant do
         // nodes that have equal status because we have to prefer longer lasting
         // nodes in the case of a good status which helps with stability.
-        let replace_index = self.nodes.iter().position(|node| node.status() < new_node_status);
+        let mut replace_index = self.nodes.iter().position(|node| node.status() < new_node_status);
+
+        // Nodes whose id does not conform to their address are lower priority than conforming
+        // nodes with an equal status, which makes it harder to poison our routing table.
+        if replace_index.is_none() && new_node.is_compliant() {
+            replace_index = self.nodes
+                .iter()
+                .position(|node| node.status() == new_node_status && !node.is_compliant());
+        }
+
         if let Some(index) = replace_index {
             self.nodes[index] = new_node;
 
@@ -149,11 +158,14 @@ impl<'a> Iterator for PingableNodes<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
     use bip_util::sha::{self, ShaHash};
     use bip_util::test as bip_test;
 
     use routing::bucket::{self, Bucket};
     use routing::node::{Node, NodeStatus};
+    use security;
 
     #[test]
     fn positive_initial_no_nodes() {
@@ -243,6 +255,28 @@ mod tests {
         assert!(bucket.good_nodes().find(|node| &&new_good_node == node).is_none());
     }
 
+    #[test]
+    fn positive_replace_non_compliant_node() {
+        let mut bucket = Bucket::new();
+
+        let public_ip = Ipv4Addr::new(124, 31, 75, 21);
+        let public_addr = SocketAddr::V4(SocketAddrV4::new(public_ip, 6881));
+        let dummy_ids = bip_test::dummy_block_node_ids(super::MAX_BUCKET_SIZE as u8);
+        for index in 0..super::MAX_BUCKET_SIZE {
+            let node = Node::as_good(dummy_ids[index], public_addr);
+            assert!(!node.is_compliant());
+
+            bucket.add_node(node);
+        }
+
+        let compliant_node = Node::as_good(security::generate_compliant_id_ipv4(public_ip),
+                                           public_addr);
+        bucket.add_node(compliant_node.clone());
+
+        assert!(bucket.good_nodes().find(|node| &&compliant_node == node).is_some());
+        assert_eq!(bucket.good_nodes().count(), super::MAX_BUCKET_SIZE);
+    }
+
     #[test]
     fn positive_resist_questionable_node_churn() {
         let mut bucket = Bucket::new();
@@ -10,6 +10,8 @@ use bip_util::bt::NodeId;
 use bip_util::test;
 use chrono::{Duration, DateTime, UTC};
 
+use security;
+
 // TODO: Should remove as_* functions and replace them with from_requested, from_responded, etc to hide the logic
 // of the nodes initial status.
 
@@ -112,6 +114,11 @@ impl Node {
         self.addr
     }
 
+    /// Whether the node id conforms to the address of the node, as specified in BEP 42.
+    pub fn is_compliant(&self) -> bool {
+        security::is_compliant_node(self.id, self.addr)
+    }
+
     pub fn encode(&self) -> [u8; 26] {
         let mut encoded = [0u8; 26];
 
@@ -142,6 +149,36 @@ impl Node {
         encoded
     }
 
+    pub fn encode_v6(&self) -> [u8; 38] {
+        let mut encoded = [0u8; 38];
+
+        {
+            let mut encoded_iter = encoded.iter_mut();
+
+            // Copy the node id over
+            for (src, dst) in self.id.as_ref().iter().zip(encoded_iter.by_ref()) {
+                *dst = *src;
+            }
+
+            // Copy the ip address over
+            match self.addr {
+                SocketAddr::V6(v6) => {
+                    for (src, dst) in v6.ip().octets().iter().zip(encoded_iter.by_ref()) {
+                        *dst = *src;
+                    }
+                }
+                _ => panic!("bip_dht: Cannot encode a SocketAddrV4 as IPv6..."),
+            }
+        }
+
+        // Copy the port over
+        let port = self.addr.port();
+        encoded[36] = (port >> 8) as u8;
+        encoded[37] = port as u8;
+
+        encoded
+    }
+
     /// Current status of the node.
     pub fn status(&self) -> NodeStatus {
         let curr_time = UTC::now();
@@ -250,7 +287,7 @@ fn recently_requested(node: &Node, curr_time: DateTime<UTC>) -> NodeStatus {
 #[cfg(test)]
 mod tests {
     use std::iter;
-    use std::net::{Ipv4Addr, SocketAddrV4, SocketAddr};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6, SocketAddr};
 
     use bip_util::bt::NodeId;
     use bip_util::test as bip_test;
@@ -280,6 +317,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn positive_encode_node_v6() {
+        let node_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20];
+        let v6_ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let port = 6881;
+
+        let sock_addr = SocketAddr::V6(SocketAddrV6::new(v6_ip, port, 0, 0));
+        let node = Node::as_good(node_id.into(), sock_addr);
+
+        let encoded_node = node.encode_v6();
+
+        let port_bytes = [(port >> 8) as u8, port as u8];
+        for (expected, actual) in node_id.iter()
+            .chain(v6_ip.octets().iter())
+            .chain(port_bytes.iter())
+            .zip(encoded_node.iter()) {
+            assert_eq!(expected, actual);
+        }
+    }
+
     #[test]
     fn positive_as_bad() {
         let node = Node::as_bad(bip_test::dummy_node_id(), bip_test::dummy_socket_addr_v4());
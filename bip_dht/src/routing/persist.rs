@@ -0,0 +1,227 @@
+use std::net::SocketAddr;
+
+use bip_bencode::{Bencode, BencodeConvert, BencodeConvertError};
+use bip_util::bt::NodeId;
+
+use error::{DhtError, DhtErrorKind, DhtResult};
+use message::compact_info::{CompactNodeInfo, CompactNodeInfo6};
+use routing::node::Node;
+use routing::table::{BucketContents, RoutingTable};
+
+// Keys for the exported routing table
+const ROOT_KEY: &'static str = "root";
+const NODE_ID_KEY: &'static str = "id";
+const NODES_KEY: &'static str = "nodes";
+const NODES6_KEY: &'static str = "nodes6";
+
+/// Node id and contacts restored from an exported routing table.
+#[derive(Clone, Debug)]
+pub struct RestoredTable {
+    node_id: NodeId,
+    nodes: Vec<(NodeId, SocketAddr)>,
+}
+
+impl RestoredTable {
+    /// Node id that the exported routing table was using.
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// Contacts for the nodes that were exported.
+    pub fn nodes(&self) -> &[(NodeId, SocketAddr)] {
+        &self.nodes
+    }
+
+    /// Add the restored nodes to the routing table of their address family.
+    ///
+    /// Nodes are added as questionable since they may have gone away while we were not running.
+    pub fn populate(&self, table: &mut RoutingTable, table_v6: &mut RoutingTable) {
+        for &(id, addr) in self.nodes.iter() {
+            let node = Node::as_questionable(id, addr);
+
+            match addr {
+                SocketAddr::V4(_) => table.add_node(node),
+                SocketAddr::V6(_) => table_v6.add_node(node),
+            }
+        }
+    }
+}
+
+struct PersistValidate;
+
+impl BencodeConvert for PersistValidate {
+    type Error = DhtError;
+
+    fn handle_error(&self, error: BencodeConvertError) -> DhtError {
+        error.into()
+    }
+}
+
+/// Export the good nodes within the routing tables, along with our node id, as bencode.
+pub fn export_tables(table: &RoutingTable, table_v6: &RoutingTable) -> Vec<u8> {
+    let node_id = table.node_id();
+
+    let mut nodes_bytes = Vec::new();
+    for_each_good_node(table, |node| nodes_bytes.extend_from_slice(&node.encode()));
+
+    let mut nodes6_bytes = Vec::new();
+    for_each_good_node(table_v6, |node| nodes6_bytes.extend_from_slice(&node.encode_v6()));
+
+    (ben_map!{
+        NODE_ID_KEY => ben_bytes!(node_id.as_ref()),
+        NODES_KEY => ben_bytes!(&nodes_bytes),
+        NODES6_KEY => ben_bytes!(&nodes6_bytes)
+    })
+        .encode()
+}
+
+/// Restore the node id and nodes from routing tables exported with export_tables.
+pub fn restore_tables(bytes: &[u8]) -> DhtResult<RestoredTable> {
+    let validate = PersistValidate;
+
+    let bencode = try!(Bencode::decode(bytes));
+    let root = try!(validate.convert_dict(&bencode, ROOT_KEY));
+
+    let node_id_bytes = try!(validate.lookup_and_convert_bytes(root, NODE_ID_KEY));
+    let node_id = try!(NodeId::from_hash(node_id_bytes).map_err(|_| invalid_table("Node ID")));
+
+    let nodes_bytes = try!(validate.lookup_and_convert_bytes(root, NODES_KEY));
+    let nodes = try!(CompactNodeInfo::new(nodes_bytes).map_err(|_| invalid_table("Nodes")));
+
+    let nodes6_bytes = try!(validate.lookup_and_convert_bytes(root, NODES6_KEY));
+    let nodes6 = try!(CompactNodeInfo6::new(nodes6_bytes).map_err(|_| invalid_table("Nodes6")));
+
+    let restored_nodes = nodes.into_iter()
+        .map(|(id, v4_addr)| (id, SocketAddr::V4(v4_addr)))
+        .chain(nodes6.into_iter().map(|(id, v6_addr)| (id, SocketAddr::V6(v6_addr))))
+        .collect();
+
+    Ok(RestoredTable {
+        node_id: node_id,
+        nodes: restored_nodes,
+    })
+}
+
+fn invalid_table(field: &str) -> DhtError {
+    DhtError::from_kind(DhtErrorKind::InvalidRoutingTable {
+        details: format!("Exported {} Has An Invalid Length", field),
+    })
+}
+
+/// Invoke the closure for every good node within the table.
+fn for_each_good_node<F>(table: &RoutingTable, mut node_fn: F)
+    where F: FnMut(&Node)
+{
+    for bucket in table.buckets() {
+        match bucket {
+            BucketContents::Empty => (),
+            BucketContents::Sorted(b) |
+            BucketContents::Assorted(b) => {
+                for node in b.good_nodes() {
+                    node_fn(node);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
+    use bip_util::bt::{self, NodeId};
+    use bip_util::test as bip_test;
+
+    use routing::node::Node;
+    use routing::persist;
+    use routing::table::{RoutingTable, BucketContents};
+
+    fn pingable_nodes(table: &RoutingTable) -> Vec<Node> {
+        let mut nodes = Vec::new();
+
+        for bucket in table.buckets() {
+            match bucket {
+                BucketContents::Empty => (),
+                BucketContents::Sorted(b) |
+                BucketContents::Assorted(b) => nodes.extend(b.pingable_nodes().cloned()),
+            }
+        }
+
+        nodes
+    }
+
+    #[test]
+    fn positive_export_restore_round_trip() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
+        let mut table_v6 = RoutingTable::new_v6(table_id.into());
+
+        let mut good_nodes = Vec::new();
+        for (index, addr) in bip_test::dummy_block_socket_addrs(3).into_iter().enumerate() {
+            let mut node_id = table_id;
+            node_id[0] = index as u8 + 2;
+
+            good_nodes.push(Node::as_good(node_id.into(), addr));
+        }
+        let ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        good_nodes.push(Node::as_good([9u8; bt::NODE_ID_LEN].into(),
+                                      SocketAddr::V6(SocketAddrV6::new(ip, 6881, 0, 0))));
+
+        for node in good_nodes.iter() {
+            table.add_node(node.clone());
+            table_v6.add_node(node.clone());
+        }
+
+        let exported = persist::export_tables(&table, &table_v6);
+        let restored = persist::restore_tables(&exported).unwrap();
+
+        assert_eq!(restored.node_id(), NodeId::from(table_id));
+        assert_eq!(restored.nodes().len(), good_nodes.len());
+
+        let mut restored_table = RoutingTable::new(restored.node_id());
+        let mut restored_table_v6 = RoutingTable::new_v6(restored.node_id());
+        restored.populate(&mut restored_table, &mut restored_table_v6);
+
+        let mut restored_nodes = pingable_nodes(&restored_table);
+        restored_nodes.extend(pingable_nodes(&restored_table_v6));
+
+        for node in good_nodes.iter() {
+            assert!(restored_nodes.contains(node));
+        }
+    }
+
+    #[test]
+    fn positive_export_skips_unresponsive_nodes() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
+        let table_v6 = RoutingTable::new_v6(table_id.into());
+
+        let addrs = bip_test::dummy_block_socket_addrs(2);
+        table.add_node(Node::as_good([2u8; bt::NODE_ID_LEN].into(), addrs[0]));
+        table.add_node(Node::as_questionable([3u8; bt::NODE_ID_LEN].into(), addrs[1]));
+
+        let exported = persist::export_tables(&table, &table_v6);
+        let restored = persist::restore_tables(&exported).unwrap();
+
+        assert_eq!(restored.nodes(),
+                   &[(NodeId::from([2u8; bt::NODE_ID_LEN]), addrs[0])][..]);
+    }
+
+    #[test]
+    fn negative_restore_invalid_bencode() {
+        assert!(persist::restore_tables(b"d2:id").is_err());
+    }
+
+    #[test]
+    fn negative_restore_truncated_nodes() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let exported = (ben_map!{
+            "id" => ben_bytes!(&table_id[..]),
+            "nodes" => ben_bytes!(&[0u8; 25][..]),
+            "nodes6" => ben_bytes!(&[0u8; 0][..])
+        })
+            .encode();
+
+        assert!(persist::restore_tables(&exported).is_err());
+    }
+}
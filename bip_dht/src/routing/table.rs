@@ -20,16 +20,28 @@ pub struct RoutingTable {
     // of the last bucket in the buckets array.
     buckets: Vec<Bucket>,
     node_id: NodeId,
+    // IPv4 and IPv6 nodes are kept in separate tables, as specified in BEP 32.
+    ipv6: bool,
 }
 
 impl RoutingTable {
-    /// Create a new RoutingTable with the given node id as our id.
+    /// Create a new RoutingTable for IPv4 nodes with the given node id as our id.
     pub fn new(node_id: NodeId) -> RoutingTable {
+        RoutingTable::with_family(node_id, false)
+    }
+
+    /// Create a new RoutingTable for IPv6 nodes with the given node id as our id.
+    pub fn new_v6(node_id: NodeId) -> RoutingTable {
+        RoutingTable::with_family(node_id, true)
+    }
+
+    fn with_family(node_id: NodeId, ipv6: bool) -> RoutingTable {
         let buckets = vec![Bucket::new()];
 
         RoutingTable {
             buckets: buckets,
             node_id: node_id,
+            ipv6: ipv6,
         }
     }
 
@@ -38,6 +50,11 @@ impl RoutingTable {
         self.node_id
     }
 
+    /// Returns true if the RoutingTable holds IPv6 nodes.
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6
+    }
+
     /// Iterator over the closest good nodes to the given node id.
     ///
     /// The closeness of nodes has a maximum granularity of a bucket. For most use
@@ -80,9 +97,11 @@ impl RoutingTable {
     }
 
     /// Add the node to the RoutingTable if there is space for it.
+    ///
+    /// Nodes with an address family different from that of the RoutingTable are ignored.
     pub fn add_node(&mut self, node: Node) {
         // Doing some checks and calculations here, outside of the recursion
-        if node.status() == NodeStatus::Bad {
+        if node.status() == NodeStatus::Bad || node.addr().is_ipv6() != self.ipv6 {
             return;
         }
         let num_same_bits = leading_bit_count(self.node_id, node.id());
@@ -450,6 +469,8 @@ fn index_is_in_bounds(length: usize, checked_index: Option<usize>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
     use bip_util::bt::{self, NodeId};
     use bip_util::test as bip_test;
 
@@ -621,6 +642,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn positive_add_node_v6_separate_tables() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table = RoutingTable::new(table_id.into());
+        let mut table_v6 = RoutingTable::new_v6(table_id.into());
+
+        let block_addrs = bip_test::dummy_block_socket_addrs(bucket::MAX_BUCKET_SIZE as u16);
+        for (index, addr) in block_addrs.iter().enumerate() {
+            let mut node_id = table_id;
+            node_id[0] = index as u8 + 2;
+
+            table.add_node(Node::as_good(node_id.into(), *addr));
+        }
+        let v4_nodes: Vec<Node> = table.closest_nodes(table_id.into()).cloned().collect();
+
+        for index in 0..bucket::MAX_BUCKET_SIZE {
+            let mut node_id = table_id;
+            node_id[0] = index as u8 + 2;
+
+            let ip = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, index as u16);
+            let addr = SocketAddr::V6(SocketAddrV6::new(ip, 6881, 0, 0));
+            let node = Node::as_good(node_id.into(), addr);
+
+            table.add_node(node.clone());
+            table_v6.add_node(node);
+        }
+
+        // IPv6 nodes went to their own table and left the IPv4 table untouched
+        let v4_nodes_after: Vec<Node> = table.closest_nodes(table_id.into()).cloned().collect();
+        assert_eq!(v4_nodes, v4_nodes_after);
+        assert!(v4_nodes_after.iter().all(|n| n.addr().is_ipv4()));
+
+        assert_eq!(table_v6.closest_nodes(table_id.into()).count(),
+                   bucket::MAX_BUCKET_SIZE);
+        assert!(table_v6.closest_nodes(table_id.into()).all(|n| n.addr().is_ipv6()));
+    }
+
+    #[test]
+    fn negative_add_node_v4_to_v6_table() {
+        let table_id = [1u8; bt::NODE_ID_LEN];
+        let mut table_v6 = RoutingTable::new_v6(table_id.into());
+
+        let mut node_id = table_id;
+        node_id[0] = 0;
+        table_v6.add_node(Node::as_good(node_id.into(), bip_test::dummy_socket_addr_v4()));
+
+        assert_eq!(table_v6.closest_nodes(table_id.into()).count(), 0);
+    }
+
     #[test]
     fn negative_node_id_equal_table_id() {
         let table_id = [1u8; bt::NODE_ID_LEN];
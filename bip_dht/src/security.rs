@@ -1,7 +1,4 @@
-// TODO: Remove this when we actually use the security module.
-#![allow(unused)]
-
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddr};
 
 use bip_util::bt::{self, NodeId};
 use bip_util::convert;
@@ -9,6 +6,7 @@ use crc::crc32;
 use rand;
 
 const IPV4_MASK: u32 = 0x030F3FFF;
+#[allow(unused)]
 const IPV6_MASK: u64 = 0x0103070F1F3F7FFF;
 
 const CRC32C_ARG_SLICE_SIZE: usize = 8;
@@ -49,6 +47,16 @@ fn generate_compliant_id(masked_ip_be: u64, num_octets: usize, rand: u8) -> [u8;
 
 // ----------------------------------------------------------------------------//
 
+/// Compares the given address against the given node id to see if the node id is valid.
+///
+/// Since IPv6 node ids are not checked yet, nodes with an IPv6 address are always compliant.
+pub fn is_compliant_node(id: NodeId, addr: SocketAddr) -> bool {
+    match addr {
+        SocketAddr::V4(v4_addr) => is_compliant_ipv4_addr(*v4_addr.ip(), id),
+        SocketAddr::V6(_) => true,
+    }
+}
+
 /// Compares the given ipv4 address against the given node id to see if the node id is valid.
 pub fn is_compliant_ipv4_addr(addr: Ipv4Addr, id: NodeId) -> bool {
     if is_security_compliant_ipv4_exempt(addr) {
@@ -61,10 +69,7 @@ pub fn is_compliant_ipv4_addr(addr: Ipv4Addr, id: NodeId) -> bool {
 
 /// Checks to see if the given ipv4 address is exempt from a security check.
 fn is_security_compliant_ipv4_exempt(addr: Ipv4Addr) -> bool {
-    // TODO: Since we are not using this module yet, we dont have to use the ip feature gate which is not stable yet.
-
-    false
-    // addr.is_loopback() || addr.is_private() || addr.is_link_local()
+    addr.is_loopback() || addr.is_private() || addr.is_link_local()
 }
 
 /// Compares the given masked ip (v4 or v6) against the given node id to see if the node if is valid.
@@ -87,8 +92,7 @@ fn is_compliant_addr(masked_ip_be: u64, num_octets: usize, id: NodeId) -> bool {
     let rand_masked_ip = masked_ip_be | ((r as u64) << (ip_bits_used - 3));
 
     // Move the rand_masked_ip bytes over to an array for running through crc32c
-    let mut rand_masked_ip_bytes = convert::eight_bytes_to_array(rand_masked_ip);
-    let starting_byte = rand_masked_ip_bytes.len() - num_octets;
+    let rand_masked_ip_bytes = convert::eight_bytes_to_array(rand_masked_ip);
 
     // Official spec says to store the rand_masked_ip in a 64 bit integer (8 byte array) and hash
     // the result, however, the rasterbar spec says to store them in a 32 bit integer (4 byte
@@ -129,7 +133,9 @@ fn mask_ipv4_be(addr: Ipv4Addr) -> u32 {
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+    use bip_util::bt::NodeId;
 
     const IPV4_ONE: (u8, u8, u8, u8) = (124, 31, 75, 21);
     const IPV4_ONE_RAND: u8 = 1;
@@ -226,6 +232,41 @@ mod tests {
         assert_eq!(node_id[19], IPV4_FIVE_RAND);
     }
 
+    #[test]
+    fn positive_generate_compliant_ipv4_is_compliant() {
+        for &(a, b, c, d) in [IPV4_ONE, IPV4_TWO, IPV4_THREE, IPV4_FOUR, IPV4_FIVE].iter() {
+            let ipv4_addr = Ipv4Addr::new(a, b, c, d);
+            let node_id = super::generate_compliant_id_ipv4(ipv4_addr);
+
+            assert!(super::is_compliant_ipv4_addr(ipv4_addr, node_id));
+        }
+    }
+
+    #[test]
+    fn positive_is_compliant_node_exempt_addr() {
+        let node_id = NodeId::from([0u8; 20]);
+
+        for &(a, b, c, d) in [(127, 0, 0, 1), (10, 0, 0, 1), (192, 168, 1, 1), (169, 254, 0, 1)]
+            .iter() {
+            let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(a, b, c, d), 6881));
+
+            assert!(super::is_compliant_node(node_id, addr));
+        }
+    }
+
+    #[test]
+    fn negative_is_compliant_node_wrong_prefix() {
+        let ip_addr = Ipv4Addr::new(IPV4_ONE.0, IPV4_ONE.1, IPV4_ONE.2, IPV4_ONE.3);
+        let mut id = [0u8; 20];
+        id[0] = IPV4_ONE_BITS.0 ^ 0xFF;
+        id[1] = IPV4_ONE_BITS.1;
+        id[2] = IPV4_ONE_BITS.2;
+        id[19] = IPV4_ONE_RAND;
+
+        let addr = SocketAddr::V4(SocketAddrV4::new(ip_addr, 6881));
+        assert!(!super::is_compliant_node(id.into(), addr));
+    }
+
     #[test]
     fn positive_is_compliant_ipv4_test_one() {
         let ip_addr = Ipv4Addr::new(IPV4_ONE.0, IPV4_ONE.1, IPV4_ONE.2, IPV4_ONE.3);
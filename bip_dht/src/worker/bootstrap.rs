@@ -34,15 +34,19 @@ pub enum BootstrapStatus {
 pub struct TableBootstrap {
     table_id: NodeId,
     id_generator: MIDGenerator,
+    read_only: bool,
     starting_nodes: Vec<SocketAddr>,
     active_messages: HashMap<TransactionID, Timeout>,
     starting_routers: HashSet<SocketAddr>,
+    // Starting routers and nodes that responded to the initial bootstrap
+    responded: HashSet<SocketAddr>,
     curr_bootstrap_bucket: usize,
 }
 
 impl TableBootstrap {
     pub fn new<I>(table_id: NodeId,
                   id_generator: MIDGenerator,
+                  read_only: bool,
                   nodes: Vec<SocketAddr>,
                   routers: I)
                   -> TableBootstrap
@@ -53,9 +57,11 @@ impl TableBootstrap {
         TableBootstrap {
             table_id: table_id,
             id_generator: id_generator,
+            read_only: read_only,
             starting_nodes: nodes,
             starting_routers: router_filter,
             active_messages: HashMap::new(),
+            responded: HashSet::new(),
             curr_bootstrap_bucket: 0,
         }
     }
@@ -68,6 +74,7 @@ impl TableBootstrap {
     {
         // Reset the bootstrap state
         self.active_messages.clear();
+        self.responded.clear();
         self.curr_bootstrap_bucket = 0;
 
         // Generate transaction id for the initial bootstrap messages
@@ -88,6 +95,7 @@ impl TableBootstrap {
         self.active_messages.insert(trans_id, timeout);
 
         let find_node_msg = FindNodeRequest::new(trans_id.as_ref(), self.table_id, self.table_id)
+            .with_read_only(self.read_only)
             .encode();
         // Ping all initial routers and nodes
         for addr in self.starting_routers.iter().chain(self.starting_nodes.iter()) {
@@ -104,7 +112,13 @@ impl TableBootstrap {
         self.starting_routers.contains(&addr)
     }
 
+    /// Number of starting routers and nodes that responded to the initial bootstrap.
+    pub fn num_responded(&self) -> usize {
+        self.responded.len()
+    }
+
     pub fn recv_response<'a, H>(&mut self,
+                                addr: &SocketAddr,
                                 trans_id: &TransactionID,
                                 table: &RoutingTable,
                                 out: &SyncSender<(Vec<u8>, SocketAddr)>,
@@ -123,7 +137,11 @@ impl TableBootstrap {
 
         // If this response was from the initial bootstrap, we don't want to clear the timeout or remove
         // the token from the map as we want to wait until the proper timeout has been triggered before starting
-        if self.curr_bootstrap_bucket != 0 {
+        if self.curr_bootstrap_bucket == 0 {
+            if self.is_router(addr) || self.starting_nodes.contains(addr) {
+                self.responded.insert(*addr);
+            }
+        } else {
             // Message was not from the initial ping
             // Remove the timeout from the event loop
             event_loop.clear_timeout(timeout);
@@ -154,6 +172,20 @@ impl TableBootstrap {
             return self.current_bootstrap_status();
         }
 
+        // Initial bootstrap timed out, so every starting node has had a chance to respond
+        if self.curr_bootstrap_bucket == 0 {
+            let num_starting = self.starting_routers.len() + self.starting_nodes.len();
+
+            if self.num_responded() == 0 {
+                warn!("bip_dht: None of the {} bootstrap routers and nodes responded...",
+                      num_starting);
+            } else {
+                info!("bip_dht: {} of {} bootstrap routers and nodes responded...",
+                      self.num_responded(),
+                      num_starting);
+            }
+        }
+
         // Check if we need to bootstrap on the next bucket
         if self.active_messages.is_empty() {
             return self.bootstrap_next_bucket(table, out, event_loop);
@@ -242,6 +274,7 @@ impl TableBootstrap {
             // Generate a transaction id
             let trans_id = self.id_generator.generate();
             let find_node_msg = FindNodeRequest::new(trans_id.as_ref(), self.table_id, target_id)
+                .with_read_only(self.read_only)
                 .encode();
 
             // Add a timeout for the node
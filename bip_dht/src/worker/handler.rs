@@ -8,7 +8,7 @@ use std::thread;
 
 use bip_bencode::Bencode;
 use bip_handshake::Handshaker;
-use bip_util::bt::InfoHash;
+use bip_util::bt::{InfoHash, NodeId};
 use bip_util::convert;
 use bip_util::net::IpAddr;
 use log::LogLevel;
@@ -20,11 +20,12 @@ use message::find_node::FindNodeResponse;
 use message::get_peers::{GetPeersResponse, CompactInfoType};
 use message::announce_peer::{AnnouncePeerResponse, ConnectPort};
 use message::error::{ErrorCode, ErrorMessage};
-use message::request::RequestType;
+use message::request::{RequestType, Want};
 use message::response::{ResponseType, ExpectedResponse};
-use message::compact_info::{CompactNodeInfo, CompactValueInfo};
+use message::compact_info::{CompactNodeInfo, CompactNodeInfo6, CompactValueInfo};
 use router::Router;
 use routing::node::Node;
+use routing::persist;
 use routing::table::RoutingTable;
 use storage::AnnounceStorage;
 use token::{TokenStore, Token};
@@ -44,15 +45,17 @@ const BOOTSTRAP_GOOD_NODE_THRESHOLD: usize = 10;
 
 /// Spawns a DHT handler that maintains our routing table and executes our actions on the DHT.
 pub fn create_dht_handler<H>(table: RoutingTable,
+                             table_v6: RoutingTable,
                              out: SyncSender<(Vec<u8>, SocketAddr)>,
                              read_only: bool,
+                             implied_port: bool,
                              handshaker: H,
                              kill_sock: UdpSocket,
                              kill_addr: SocketAddr)
                              -> io::Result<mio::Sender<OneshotTask>>
     where H: Handshaker + 'static
 {
-    let mut handler = DhtHandler::new(table, out, read_only, handshaker);
+    let mut handler = DhtHandler::new(table, table_v6, out, read_only, implied_port, handshaker);
     let mut event_loop = try!(EventLoop::new());
 
     let loop_channel = event_loop.channel();
@@ -114,12 +117,14 @@ pub struct DhtHandler<H> {
 /// to table actions while still being able to pass around the bulky parameters.
 struct DetachedDhtHandler<H> {
     read_only: bool,
+    implied_port: bool,
     handshaker: H,
     out_channel: SyncSender<(Vec<u8>, SocketAddr)>,
     token_store: TokenStore,
     aid_generator: AIDGenerator,
     bootstrapping: bool,
     routing_table: RoutingTable,
+    routing_table_v6: RoutingTable,
     active_stores: AnnounceStorage,
     // If future actions is not empty, that means we are still bootstrapping
     // since we will always spin up a table refresh action after bootstrapping.
@@ -131,8 +136,10 @@ impl<H> DhtHandler<H>
     where H: Handshaker
 {
     fn new(table: RoutingTable,
+           table_v6: RoutingTable,
            out: SyncSender<(Vec<u8>, SocketAddr)>,
            read_only: bool,
+           implied_port: bool,
            handshaker: H)
            -> DhtHandler<H> {
         let mut aid_generator = AIDGenerator::new();
@@ -140,17 +147,19 @@ impl<H> DhtHandler<H>
         // Insert the refresh task to execute after the bootstrap
         let mut mid_generator = aid_generator.generate();
         let refresh_trans_id = mid_generator.generate();
-        let table_refresh = TableRefresh::new(mid_generator);
+        let table_refresh = TableRefresh::new(mid_generator, read_only);
         let future_actions = vec![PostBootstrapAction::Refresh(table_refresh, refresh_trans_id)];
 
         let detached = DetachedDhtHandler {
             read_only: read_only,
+            implied_port: implied_port,
             handshaker: handshaker,
             out_channel: out,
             token_store: TokenStore::new(),
             aid_generator: aid_generator,
             bootstrapping: false,
             routing_table: table,
+            routing_table_v6: table_v6,
             active_stores: AnnounceStorage::new(),
             future_actions: future_actions,
             event_notifiers: Vec::new(),
@@ -163,6 +172,24 @@ impl<H> DhtHandler<H>
     }
 }
 
+impl<H> DetachedDhtHandler<H> {
+    /// RoutingTable for nodes of the same address family as the given address.
+    fn routing_table_for(&self, addr: &SocketAddr) -> &RoutingTable {
+        match addr {
+            &SocketAddr::V4(_) => &self.routing_table,
+            &SocketAddr::V6(_) => &self.routing_table_v6,
+        }
+    }
+
+    /// Mutable RoutingTable for nodes of the same address family as the given address.
+    fn routing_table_for_mut(&mut self, addr: &SocketAddr) -> &mut RoutingTable {
+        match addr {
+            &SocketAddr::V4(_) => &mut self.routing_table,
+            &SocketAddr::V6(_) => &mut self.routing_table_v6,
+        }
+    }
+}
+
 impl<H> Handler for DhtHandler<H>
     where H: Handshaker
 {
@@ -187,6 +214,9 @@ impl<H> Handler for DhtHandler<H>
                                     info_hash,
                                     should_announce);
             }
+            OneshotTask::ExportRoutingTable(send) => {
+                handle_export_routing_table(self, send);
+            }
             OneshotTask::Shutdown(cause) => {
                 handle_shutdown(self, event_loop, cause);
             }
@@ -232,6 +262,20 @@ fn broadcast_dht_event(notifiers: &mut Vec<mpsc::Sender<DhtEvent>>, event: DhtEv
     notifiers.retain(|send| send.send(event).is_ok());
 }
 
+/// Compact node info for the closest nodes to the given id within the RoutingTable.
+fn encode_closest_nodes(table: &RoutingTable, target_id: NodeId) -> Vec<u8> {
+    let mut closest_nodes_bytes = Vec::with_capacity(38 * 8);
+    for node in table.closest_nodes(target_id).take(8) {
+        if table.is_ipv6() {
+            closest_nodes_bytes.extend_from_slice(&node.encode_v6());
+        } else {
+            closest_nodes_bytes.extend_from_slice(&node.encode());
+        }
+    }
+
+    closest_nodes_bytes
+}
+
 /// Number of good nodes in the RoutingTable.
 fn num_good_nodes(table: &RoutingTable) -> usize {
     table.closest_nodes(table.node_id()).filter(|n| n.status() == NodeStatus::Good).count()
@@ -327,6 +371,14 @@ fn attempt_rebootstrap<H>(bootstrap: &mut TableBootstrap,
 
 // ----------------------------------------------------------------------------//
 
+/// Add the sender of a request to the routing table as questionable, unless they flagged
+/// themselves as a read only node, as specified in BEP 43.
+fn add_requester(table: &mut RoutingTable, request: &RequestType, addr: SocketAddr) {
+    if !request.read_only() {
+        table.add_node(Node::as_questionable(request.node_id(), addr));
+    }
+}
+
 fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                       event_loop: &mut EventLoop<DhtHandler<H>>,
                       buffer: &[u8],
@@ -363,9 +415,6 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
     });
 
     // Do not process requests if we are read only
-    // TODO: Add read only flags to messages we send it we are read only!
-    // Also, check for read only flags on responses we get before adding nodes
-    // to our RoutingTable.
     if work_storage.read_only {
         match message {
             Ok(MessageType::Request(_)) => return,
@@ -373,6 +422,10 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
         }
     }
 
+    if let Ok(MessageType::Request(ref request)) = message {
+        add_requester(work_storage.routing_table_for_mut(&addr), request, addr);
+    }
+
     // Process the given message
     match message {
         Ok(MessageType::Request(RequestType::Ping(p))) => {
@@ -380,7 +433,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let node = Node::as_good(p.node_id(), addr);
 
             // Node requested from us, mark it in the Routingtable
-            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+            work_storage.routing_table_for(&addr).find_node(&node).map(|n| n.remote_request());
 
             let ping_rsp = PingResponse::new(p.transaction_id(),
                                              work_storage.routing_table.node_id());
@@ -396,18 +449,33 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let node = Node::as_good(f.node_id(), addr);
 
             // Node requested from us, mark it in the Routingtable
-            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+            work_storage.routing_table_for(&addr).find_node(&node).map(|n| n.remote_request());
 
-            // Grab the closest nodes
-            let mut closest_nodes_bytes = Vec::with_capacity(26 * 8);
-            for node in work_storage.routing_table.closest_nodes(f.target_id()).take(8) {
-                closest_nodes_bytes.extend_from_slice(&node.encode());
-            }
+            // Grab the closest nodes from the tables the requester wants
+            let want = f.want().unwrap_or(Want::from_addr(&addr));
+            let closest_nodes_bytes = if want.wants_v4() {
+                encode_closest_nodes(&work_storage.routing_table, f.target_id())
+            } else {
+                Vec::new()
+            };
+            let closest_nodes6_bytes = if want.wants_v6() {
+                encode_closest_nodes(&work_storage.routing_table_v6, f.target_id())
+            } else {
+                Vec::new()
+            };
 
-            let find_node_rsp = FindNodeResponse::new(f.transaction_id(),
-                                                      work_storage.routing_table.node_id(),
-                                                      &closest_nodes_bytes)
-                .unwrap();
+            let find_node_rsp = if want.wants_v6() {
+                FindNodeResponse::with_nodes6(f.transaction_id(),
+                                              work_storage.routing_table.node_id(),
+                                              &closest_nodes_bytes,
+                                              &closest_nodes6_bytes)
+                    .unwrap()
+            } else {
+                FindNodeResponse::new(f.transaction_id(),
+                                      work_storage.routing_table.node_id(),
+                                      &closest_nodes_bytes)
+                    .unwrap()
+            };
             let find_node_msg = find_node_rsp.encode();
 
             if work_storage.out_channel.send((find_node_msg, addr)).is_err() {
@@ -420,7 +488,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let node = Node::as_good(g.node_id(), addr);
 
             // Node requested from us, mark it in the Routingtable
-            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+            work_storage.routing_table_for(&addr).find_node(&node).map(|n| n.remote_request());
 
             // TODO: Move socket address serialization code into bip_util
             // TODO: Check what the maximum number of values we can give without overflowing a udp packet
@@ -457,11 +525,18 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 contact_info_bencode.push(ben_bytes!(&contact_info_bytes[start..end]));
             }
 
-            // Grab the closest nodes
-            let mut closest_nodes_bytes = Vec::with_capacity(26 * 8);
-            for node in work_storage.routing_table.closest_nodes(g.info_hash()).take(8) {
-                closest_nodes_bytes.extend_from_slice(&node.encode());
-            }
+            // Grab the closest nodes from the tables the requester wants
+            let want = g.want().unwrap_or(Want::from_addr(&addr));
+            let closest_nodes_bytes = if want.wants_v4() {
+                encode_closest_nodes(&work_storage.routing_table, g.info_hash())
+            } else {
+                Vec::new()
+            };
+            let closest_nodes6_bytes = if want.wants_v6() {
+                encode_closest_nodes(&work_storage.routing_table_v6, g.info_hash())
+            } else {
+                Vec::new()
+            };
 
             // Wrap up the nodes/values we are going to be giving them
             let token = work_storage.token_store.checkout(IpAddr::from_socket_addr(addr));
@@ -472,10 +547,18 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 CompactInfoType::Nodes(CompactNodeInfo::new(&closest_nodes_bytes).unwrap())
             };
 
-            let get_peers_rsp = GetPeersResponse::new(g.transaction_id(),
-                                                      work_storage.routing_table.node_id(),
-                                                      Some(token.as_ref()),
-                                                      comapct_info_type);
+            let get_peers_rsp = if want.wants_v6() {
+                GetPeersResponse::with_nodes6(g.transaction_id(),
+                                              work_storage.routing_table.node_id(),
+                                              Some(token.as_ref()),
+                                              comapct_info_type,
+                                              CompactNodeInfo6::new(&closest_nodes6_bytes).unwrap())
+            } else {
+                GetPeersResponse::new(g.transaction_id(),
+                                      work_storage.routing_table.node_id(),
+                                      Some(token.as_ref()),
+                                      comapct_info_type)
+            };
             let get_peers_msg = get_peers_rsp.encode();
 
             if work_storage.out_channel.send((get_peers_msg, addr)).is_err() {
@@ -488,7 +571,7 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let node = Node::as_good(a.node_id(), addr);
 
             // Node requested from us, mark it in the Routingtable
-            work_storage.routing_table.find_node(&node).map(|n| n.remote_request());
+            work_storage.routing_table_for(&addr).find_node(&node).map(|n| n.remote_request());
 
             // Validate the token
             let is_valid = match Token::new(a.token()) {
@@ -554,16 +637,21 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
 
                 work_storage.routing_table.add_node(Node::as_questionable(id, sock_addr));
             }
+            for (id, v6_addr) in f.nodes6().into_iter().flat_map(|nodes6| nodes6) {
+                let sock_addr = SocketAddr::V6(v6_addr);
+
+                work_storage.routing_table_v6.add_node(Node::as_questionable(id, sock_addr));
+            }
 
             let bootstrap_complete = {
                 let opt_bootstrap = match table_actions.get_mut(&trans_id.action_id()) {
                     Some(&mut TableAction::Refresh(_)) => {
-                        work_storage.routing_table.add_node(node);
+                        work_storage.routing_table_for_mut(&addr).add_node(node);
                         None
                     }
                     Some(&mut TableAction::Bootstrap(ref mut bootstrap, ref mut attempts)) => {
                         if !bootstrap.is_router(&node.addr()) {
-                            work_storage.routing_table.add_node(node);
+                            work_storage.routing_table_for_mut(&addr).add_node(node);
                         }
                         Some((bootstrap, attempts))
                     }
@@ -579,7 +667,8 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
                 };
 
                 if let Some((bootstrap, attempts)) = opt_bootstrap {
-                    match bootstrap.recv_response(&trans_id,
+                    match bootstrap.recv_response(&addr,
+                                                  &trans_id,
                                                   &work_storage.routing_table,
                                                   &work_storage.out_channel,
                                                   event_loop) {
@@ -641,7 +730,12 @@ fn handle_incoming<H>(handler: &mut DhtHandler<H>,
             let trans_id = TransactionID::from_bytes(g.transaction_id()).unwrap();
             let node = Node::as_good(g.node_id(), addr);
 
-            work_storage.routing_table.add_node(node.clone());
+            work_storage.routing_table_for_mut(&addr).add_node(node.clone());
+            for (id, v6_addr) in g.nodes6().into_iter().flat_map(|nodes6| nodes6) {
+                let sock_addr = SocketAddr::V6(v6_addr);
+
+                work_storage.routing_table_v6.add_node(Node::as_questionable(id, sock_addr));
+            }
 
             let opt_lookup = {
                 match table_actions.get_mut(&trans_id.action_id()) {
@@ -711,6 +805,15 @@ fn handle_register_sender<H>(handler: &mut DhtHandler<H>, sender: mpsc::Sender<D
     handler.detached.event_notifiers.push(sender);
 }
 
+fn handle_export_routing_table<H>(handler: &mut DhtHandler<H>, sender: mpsc::Sender<Vec<u8>>) {
+    let exported = persist::export_tables(&handler.detached.routing_table,
+                                          &handler.detached.routing_table_v6);
+
+    if sender.send(exported).is_err() {
+        warn!("bip_dht: Failed to send an exported routing table, receiver hung up...");
+    }
+}
+
 fn handle_start_bootstrap<H>(handler: &mut DhtHandler<H>,
                              event_loop: &mut EventLoop<DhtHandler<H>>,
                              routers: Vec<Router>,
@@ -719,13 +822,16 @@ fn handle_start_bootstrap<H>(handler: &mut DhtHandler<H>,
 {
     let (work_storage, table_actions) = (&mut handler.detached, &mut handler.table_actions);
 
-    let router_iter = routers.into_iter()
-        .filter_map(|r| r.ipv4_addr().ok().map(|v4| SocketAddr::V4(v4)));
+    // Prefer IPv4 addresses for routers, but allow custom routers with only an IPv6 address
+    let router_iter = routers.into_iter().filter_map(|r| {
+        r.ipv4_addr().map(|v4| SocketAddr::V4(v4)).or_else(|_| r.socket_addr()).ok()
+    });
 
     let mid_generator = work_storage.aid_generator.generate();
     let action_id = mid_generator.action_id();
     let mut table_bootstrap = TableBootstrap::new(work_storage.routing_table.node_id(),
                                                   mid_generator,
+                                                  work_storage.read_only,
                                                   nodes,
                                                   router_iter);
 
@@ -782,6 +888,7 @@ fn handle_start_lookup<H>(table_actions: &mut HashMap<ActionID, TableAction>,
         match TableLookup::new(work_storage.routing_table.node_id(),
                                info_hash,
                                mid_generator,
+                               work_storage.read_only,
                                should_announce,
                                &work_storage.routing_table,
                                &work_storage.out_channel,
@@ -965,9 +1072,16 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
 {
     let (work_storage, table_actions) = (&mut handler.detached, &mut handler.table_actions);
 
+    // Either have the remote use the source port of our announce, or the port of our handshaker
+    let announce_port = if work_storage.implied_port {
+        ConnectPort::Implied
+    } else {
+        ConnectPort::Explicit(work_storage.handshaker.port())
+    };
+
     let opt_lookup_info = match table_actions.remove(&trans_id.action_id()) {
         Some(TableAction::Lookup(mut lookup)) => {
-            Some((lookup.recv_finished(work_storage.handshaker.port(),
+            Some((lookup.recv_finished(announce_port,
                                        &work_storage.routing_table,
                                        &work_storage.out_channel),
                   lookup.info_hash()))
@@ -1009,3 +1123,55 @@ fn handle_check_lookup_endgame<H>(handler: &mut DhtHandler<H>,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bip_bencode::Bencode;
+    use bip_util::bt::{self, NodeId};
+    use bip_util::test as bip_test;
+
+    use message::MessageType;
+    use message::find_node::FindNodeRequest;
+    use message::response::ExpectedResponse;
+    use routing::table::{BucketContents, RoutingTable};
+
+    fn num_pingable_nodes(table: &RoutingTable) -> usize {
+        table.buckets()
+            .map(|bucket| {
+                match bucket {
+                    BucketContents::Empty => 0,
+                    BucketContents::Sorted(b) |
+                    BucketContents::Assorted(b) => b.pingable_nodes().count(),
+                }
+            })
+            .sum()
+    }
+
+    fn add_requester(read_only: bool) -> usize {
+        let mut table = RoutingTable::new([0u8; bt::NODE_ID_LEN].into());
+        let node_id: NodeId = [1u8; bt::NODE_ID_LEN].into();
+        let addr = bip_test::dummy_socket_addr_v4();
+
+        let encoded = FindNodeRequest::new(b"aa", node_id, node_id)
+            .with_read_only(read_only)
+            .encode();
+        let bencode = Bencode::decode(&encoded).unwrap();
+
+        match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+            MessageType::Request(request) => super::add_requester(&mut table, &request, addr),
+            _ => panic!("Failed To Decode FindNodeRequest"),
+        }
+
+        num_pingable_nodes(&table)
+    }
+
+    #[test]
+    fn positive_add_requester() {
+        assert_eq!(1, add_requester(false));
+    }
+
+    #[test]
+    fn positive_skip_read_only_requester() {
+        assert_eq!(0, add_requester(true));
+    }
+}
@@ -48,6 +48,7 @@ pub struct TableLookup {
     // If we have received any values in the lookup.
     recv_values: bool,
     id_generator: MIDGenerator,
+    read_only: bool,
     will_announce: bool,
     // DistanceToBeat is the distance that the responses of the current lookup needs to beat,
     // interestingly enough (and super important), this distance may not be eqaul to the
@@ -66,6 +67,7 @@ impl TableLookup {
     pub fn new<H>(table_id: NodeId,
                   target_id: InfoHash,
                   id_generator: MIDGenerator,
+                  read_only: bool,
                   will_announce: bool,
                   table: &RoutingTable,
                   out: &SyncSender<(Vec<u8>, SocketAddr)>,
@@ -97,6 +99,7 @@ impl TableLookup {
             in_endgame: false,
             recv_values: false,
             id_generator: id_generator,
+            read_only: read_only,
             will_announce: will_announce,
             all_sorted_nodes: all_sorted_nodes,
             announce_tokens: HashMap::new(),
@@ -267,7 +270,7 @@ impl TableLookup {
     }
 
     pub fn recv_finished(&mut self,
-                         handshake_port: u16,
+                         announce_port: ConnectPort,
                          table: &RoutingTable,
                          out: &SyncSender<(Vec<u8>, SocketAddr)>)
                          -> LookupStatus {
@@ -290,7 +293,8 @@ impl TableLookup {
                                              self.table_id,
                                              self.target_id,
                                              token.as_ref(),
-                                             ConnectPort::Explicit(handshake_port));
+                                             announce_port)
+                        .with_read_only(self.read_only);
                 let announce_peer_msg = announce_peer_req.encode();
 
                 if out.send((announce_peer_msg, node.addr())).is_err() {
@@ -356,7 +360,9 @@ impl TableLookup {
 
             // Send the message to the node
             let get_peers_msg =
-                GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id).encode();
+                GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id)
+                    .with_read_only(self.read_only)
+                    .encode();
             if out.send((get_peers_msg, node.addr())).is_err() {
                 error!("bip_dht: Could not send a lookup message through the channel...");
                 return LookupStatus::Failed;
@@ -413,7 +419,9 @@ impl TableLookup {
 
                 // Send the message to the node
                 let get_peers_msg =
-                    GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id).encode();
+                    GetPeersRequest::new(trans_id.as_ref(), self.table_id, self.target_id)
+                        .with_read_only(self.read_only)
+                        .encode();
                 if out.send((get_peers_msg, node.addr())).is_err() {
                     error!("bip_dht: Could not send an endgame message through the channel...");
                     return LookupStatus::Failed;
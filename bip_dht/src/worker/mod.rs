@@ -3,11 +3,13 @@ use std::net::{SocketAddr, UdpSocket};
 use std::sync::mpsc;
 
 use bip_handshake::Handshaker;
-use bip_util::bt::InfoHash;
+use bip_util::bt::{InfoHash, NodeId};
 use mio;
 
 use router::Router;
+use routing::persist::RestoredTable;
 use routing::table::{self, RoutingTable};
+use security;
 use transaction::TransactionID;
 
 pub mod bootstrap;
@@ -27,6 +29,8 @@ pub enum OneshotTask {
     StartBootstrap(Vec<Router>, Vec<SocketAddr>),
     /// Start a lookup for the given InfoHash.
     StartLookup(InfoHash, bool),
+    /// Export the good nodes in our routing tables to the given sender.
+    ExportRoutingTable(mpsc::Sender<Vec<u8>>),
     /// Gracefully shutdown the DHT and associated workers.
     Shutdown(ShutdownCause),
 }
@@ -66,12 +70,26 @@ pub enum ShutdownCause {
     Unspecified,
 }
 
+/// Pick the node id we will use, preferring a restored id as long as it conforms to our
+/// external address (BEP 42), otherwise generating one that does.
+fn initial_node_id(ext_addr: Option<SocketAddr>, restored_id: Option<NodeId>) -> NodeId {
+    match (ext_addr, restored_id) {
+        (Some(SocketAddr::V4(v4_addr)), Some(id)) if security::is_compliant_ipv4_addr(*v4_addr.ip(),
+                                                                                    id) => id,
+        (Some(SocketAddr::V4(v4_addr)), _) => security::generate_compliant_id_ipv4(*v4_addr.ip()),
+        (_, Some(id)) => id,
+        (_, None) => table::random_node_id(),
+    }
+}
+
 /// Spawns the necessary workers that make up our local DHT node and connects them via channels
 /// so that they can send and receive DHT messages.
 pub fn start_mainline_dht<H>(send_socket: UdpSocket,
                              recv_socket: UdpSocket,
                              read_only: bool,
-                             _: Option<SocketAddr>,
+                             implied_port: bool,
+                             ext_addr: Option<SocketAddr>,
+                             restored: Option<RestoredTable>,
                              handshaker: H,
                              kill_sock: UdpSocket,
                              kill_addr: SocketAddr)
@@ -80,11 +98,17 @@ pub fn start_mainline_dht<H>(send_socket: UdpSocket,
 {
     let outgoing = messenger::create_outgoing_messenger(send_socket);
 
-    // TODO: Utilize the security extension.
-    let routing_table = RoutingTable::new(table::random_node_id());
+    let node_id = initial_node_id(ext_addr, restored.as_ref().map(|r| r.node_id()));
+    let mut routing_table = RoutingTable::new(node_id);
+    let mut routing_table_v6 = RoutingTable::new_v6(node_id);
+    if let Some(restored) = restored {
+        restored.populate(&mut routing_table, &mut routing_table_v6);
+    }
     let message_sender = try!(handler::create_dht_handler(routing_table,
+                                                          routing_table_v6,
                                                           outgoing,
                                                           read_only,
+                                                          implied_port,
                                                           handshaker,
                                                           kill_sock,
                                                           kill_addr));
@@ -6,6 +6,7 @@ use bip_util::bt::{self, NodeId};
 use mio::EventLoop;
 
 use message::find_node::FindNodeRequest;
+use message::request::Want;
 use routing::node::NodeStatus;
 use routing::table::{self, RoutingTable};
 use transaction::MIDGenerator;
@@ -23,13 +24,15 @@ pub enum RefreshStatus {
 
 pub struct TableRefresh {
     id_generator: MIDGenerator,
+    read_only: bool,
     curr_refresh_bucket: usize,
 }
 
 impl TableRefresh {
-    pub fn new(id_generator: MIDGenerator) -> TableRefresh {
+    pub fn new(id_generator: MIDGenerator, read_only: bool) -> TableRefresh {
         TableRefresh {
             id_generator: id_generator,
+            read_only: read_only,
             curr_refresh_bucket: 0,
         }
     }
@@ -56,7 +59,12 @@ impl TableRefresh {
             let trans_id = self.id_generator.generate();
 
             // Construct the message
-            let find_node_req = FindNodeRequest::new(trans_id.as_ref(), table.node_id(), target_id);
+            // Ask for both families so that we also learn about IPv6 nodes
+            let find_node_req = FindNodeRequest::with_want(trans_id.as_ref(),
+                                                           table.node_id(),
+                                                           target_id,
+                                                           Want::Both)
+                .with_read_only(self.read_only);
             let find_node_msg = find_node_req.encode();
 
             // Send the message
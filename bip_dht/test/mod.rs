@@ -0,0 +1,29 @@
+extern crate bip_bencode;
+extern crate bip_dht;
+extern crate bip_handshake;
+extern crate bip_util;
+
+use std::net::SocketAddr;
+
+use bip_handshake::Handshaker;
+use bip_util::bt::{InfoHash, PeerId};
+
+mod test_bootstrap_node;
+
+struct MockHandshaker;
+
+impl Handshaker for MockHandshaker {
+    type MetadataEnvelope = ();
+
+    fn id(&self) -> PeerId {
+        [0u8; 20].into()
+    }
+
+    fn port(&self) -> u16 {
+        6881
+    }
+
+    fn connect(&mut self, _: Option<PeerId>, _: InfoHash, _: SocketAddr) {}
+
+    fn metadata(&mut self, _: ()) {}
+}
@@ -0,0 +1,32 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use bip_bencode::Bencode;
+use bip_dht::DhtBuilder;
+use bip_dht::message::MessageType;
+use bip_dht::message::request::RequestType;
+use bip_dht::message::response::ExpectedResponse;
+
+use MockHandshaker;
+
+#[test]
+fn positive_custom_bootstrap_node_queried() {
+    let bootstrap_sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+    bootstrap_sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let bootstrap_addr = bootstrap_sock.local_addr().unwrap();
+
+    let _dht = DhtBuilder::new()
+        .add_bootstrap_node(bootstrap_addr)
+        .set_source_addr("127.0.0.1:0".parse().unwrap())
+        .start_mainline(MockHandshaker)
+        .unwrap();
+
+    let mut buffer = [0u8; 1500];
+    let (length, _) = bootstrap_sock.recv_from(&mut buffer).unwrap();
+
+    let bencode = Bencode::decode(&buffer[..length]).unwrap();
+    match MessageType::new(&bencode, |_| ExpectedResponse::None).unwrap() {
+        MessageType::Request(RequestType::FindNode(_)) => (),
+        _ => panic!("Bootstrap Node Did Not Receive A FindNodeRequest"),
+    }
+}
@@ -11,15 +11,16 @@ extern crate rand;
 mod benches {
     use std::fs;
 
-    use bip_disk::fs::NativeFileSystem;
+    use bip_disk::fs::{InMemoryFileSystem, MmapFileSystem, NativeFileSystem};
     use bip_disk::fs_cache::FileHandleCache;
     use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, InfoHash, Block, BlockMetadata, FileSystem};
+    use bip_disk::{BlockAllocator, PooledBlockAllocator};
     use bip_metainfo::{DirectAccessor, MetainfoBuilder, Metainfo, PieceLength};
     use bytes::BytesMut;
     use futures::stream::{self, Stream};
     use futures::sink::{self, Sink};
     use rand::{self, Rng};
-    use test::Bencher;
+    use test::{self, Bencher};
 
     /// Set to true if you are playing around with anything that could affect file
     /// sizes for an existing or new benchmarks. As a precaution, if the disk manager
@@ -120,6 +121,39 @@ mod benches {
         b.iter(|| process_blocks(piece_length, block_length, info_hash, &bytes[..], &mut block_d_send, &mut block_d_recv))
     }
 
+    /// Benchmarking method to setup a torrent file whose data already exists, and benchmark checking the existing pieces.
+    fn bench_check_existing_file_with_threads(b: &mut Bencher, piece_length: usize, file_length: usize, threads: usize) {
+        let (metainfo, bytes) = generate_single_file_torrent(piece_length, file_length);
+
+        b.iter(|| {
+            let filesystem = InMemoryFileSystem::new();
+            let mut file = filesystem.open_file("benchmark_file").unwrap();
+            filesystem.write_file(&mut file, 0, &bytes[..]).unwrap();
+
+            let disk_manager = DiskManagerBuilder::new()
+                .with_stream_buffer_capacity(1000000)
+                .with_piece_check_threads(threads)
+                .build(filesystem);
+
+            let (d_send, d_recv) = disk_manager.split();
+
+            let mut block_d_send = d_send.wait();
+            let mut block_d_recv = d_recv.wait();
+
+            add_metainfo_file(metainfo.clone(), &mut block_d_send, &mut block_d_recv);
+        })
+    }
+
+    #[bench]
+    fn bench_check_existing_1_mb_pieces_1_thread(b: &mut Bencher) {
+        bench_check_existing_file_with_threads(b, 1 * 1024 * 1024, 64 * 1024 * 1024, 1)
+    }
+
+    #[bench]
+    fn bench_check_existing_1_mb_pieces_4_threads(b: &mut Bencher) {
+        bench_check_existing_file_with_threads(b, 1 * 1024 * 1024, 64 * 1024 * 1024, 4)
+    }
+
     #[bench]
     fn bench_native_fs_1_mb_pieces_128_kb_blocks(b: &mut Bencher) {
         let piece_length = 1 * 1024 * 1024;
@@ -209,4 +243,65 @@ mod benches {
 
         bench_process_file_with_fs(b, piece_length, block_length, file_length, filesystem);
     }
-}
\ No newline at end of file
+
+    #[bench]
+    fn bench_mmap_fs_1_mb_pieces_128_kb_blocks(b: &mut Bencher) {
+        let piece_length = 1 * 1024 * 1024;
+        let block_length = 128 * 1024;
+        let file_length = 2 * 1024 * 1024;
+        let data_directory = "target/bench_data/bench_mmap_fs_1_mb_pieces_128_kb_blocks";
+
+        if WIPE_DATA_DIR {
+            let _ = fs::remove_dir_all(data_directory);
+        }
+        let filesystem = MmapFileSystem::with_directory(data_directory);
+
+        bench_process_file_with_fs(b, piece_length, block_length, file_length, filesystem);
+    }
+
+    #[bench]
+    fn bench_file_handle_cache_mmap_fs_1_mb_pieces_128_kb_blocks(b: &mut Bencher) {
+        let piece_length = 1 * 1024 * 1024;
+        let block_length = 128 * 1024;
+        let file_length = 2 * 1024 * 1024;
+        let data_directory = "target/bench_data/bench_mmap_fs_1_mb_pieces_128_kb_blocks";
+
+        if WIPE_DATA_DIR {
+            let _ = fs::remove_dir_all(data_directory);
+        }
+        let filesystem = FileHandleCache::new(MmapFileSystem::with_directory(data_directory), 1);
+
+        bench_process_file_with_fs(b, piece_length, block_length, file_length, filesystem);
+    }
+
+    /// Number of piece buffers to request for each iteration of the allocator benchmarks.
+    const ALLOCATOR_BENCH_PIECES: usize = 64;
+
+    #[bench]
+    fn bench_vec_allocator_1_mb_pieces(b: &mut Bencher) {
+        let piece_length = 1 * 1024 * 1024;
+
+        b.iter(|| {
+            for _ in 0..ALLOCATOR_BENCH_PIECES {
+                let buffer = vec![0u8; piece_length];
+
+                test::black_box(&buffer);
+            }
+        })
+    }
+
+    #[bench]
+    fn bench_pooled_allocator_1_mb_pieces(b: &mut Bencher) {
+        let piece_length = 1 * 1024 * 1024;
+        let allocator = PooledBlockAllocator::new();
+
+        b.iter(|| {
+            for _ in 0..ALLOCATOR_BENCH_PIECES {
+                let buffer = allocator.allocate(piece_length);
+
+                test::black_box(&buffer);
+                allocator.deallocate(buffer);
+            }
+        })
+    }
+}
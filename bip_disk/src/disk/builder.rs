@@ -1,3 +1,4 @@
+use disk::{AllocationMode, SyncMode};
 use disk::fs::FileSystem;
 use disk::manager::{DiskManager};
 
@@ -5,19 +6,30 @@ use futures_cpupool::Builder;
 
 const DEFAULT_PENDING_SIZE:   usize = 10;
 const DEFAULT_COMPLETED_SIZE: usize = 10;
+const DEFAULT_CHECK_THREADS:  usize = 4;
+const DEFAULT_WRITE_DEPTH:    usize = 10;
 
 /// `DiskManagerBuilder` for building `DiskManager`s with different settings.
 pub struct DiskManagerBuilder {
     builder:        Builder,
     pending_size:   usize,
-    completed_size: usize
+    completed_size: usize,
+    write_depth:    usize,
+    coalesce:       bool,
+    check_threads:  usize,
+    check_progress: bool,
+    alloc_mode:     AllocationMode,
+    sync_mode:      SyncMode
 }
 
 impl DiskManagerBuilder {
     /// Create a new `DiskManagerBuilder`.
     pub fn new() -> DiskManagerBuilder {
         DiskManagerBuilder{ builder: Builder::new(), pending_size: DEFAULT_PENDING_SIZE,
-                            completed_size: DEFAULT_COMPLETED_SIZE }
+                            completed_size: DEFAULT_COMPLETED_SIZE, write_depth: DEFAULT_WRITE_DEPTH,
+                            coalesce: false, check_threads: DEFAULT_CHECK_THREADS,
+                            check_progress: false, alloc_mode: AllocationMode::Sparse,
+                            sync_mode: SyncMode::Piece }
     }
 
     /// Use a custom `Builder` for the `CpuPool`.
@@ -38,6 +50,48 @@ impl DiskManagerBuilder {
         self
     }
 
+    /// Specify the maximum number of `ProcessBlock` messages that can be pending at once.
+    ///
+    /// Once this many blocks are waiting to be written, the sink will apply backpressure to
+    /// any more `ProcessBlock` messages until the stream has received some `BlockProcessed` messages.
+    pub fn with_write_queue_depth(mut self, depth: usize) -> DiskManagerBuilder {
+        self.write_depth = depth;
+        self
+    }
+
+    /// Specify whether or not pending `ProcessBlock` messages are batched, and sorted by file and offset, before being written.
+    ///
+    /// Runs of adjacent blocks in a batch are merged into a single write, which cuts down on seeking
+    /// when blocks arrive out of order.
+    pub fn with_write_coalescing(mut self, enabled: bool) -> DiskManagerBuilder {
+        self.coalesce = enabled;
+        self
+    }
+
+    /// Specify the maximum number of threads used to check existing pieces for a torrent.
+    pub fn with_piece_check_threads(mut self, threads: usize) -> DiskManagerBuilder {
+        self.check_threads = threads;
+        self
+    }
+
+    /// Specify whether or not `TorrentCheckProgress` messages are sent while checking existing pieces for a torrent.
+    pub fn with_piece_check_progress(mut self, enabled: bool) -> DiskManagerBuilder {
+        self.check_progress = enabled;
+        self
+    }
+
+    /// Specify how files are allocated when a torrent is added.
+    pub fn with_allocation_mode(mut self, mode: AllocationMode) -> DiskManagerBuilder {
+        self.alloc_mode = mode;
+        self
+    }
+
+    /// Specify when the files of a torrent are synced as pieces are completed.
+    pub fn with_sync_mode(mut self, mode: SyncMode) -> DiskManagerBuilder {
+        self.sync_mode = mode;
+        self
+    }
+
     /// Retrieve the `CpuPool` builder.
     pub fn worker_config(&mut self) -> &mut Builder {
         &mut self.builder
@@ -53,6 +107,36 @@ impl DiskManagerBuilder {
         self.completed_size
     }
 
+    /// Retrieve the write queue depth.
+    pub fn write_queue_depth(&self) -> usize {
+        self.write_depth
+    }
+
+    /// Retrieve whether or not write coalescing is enabled.
+    pub fn write_coalescing(&self) -> bool {
+        self.coalesce
+    }
+
+    /// Retrieve the maximum number of piece check threads.
+    pub fn piece_check_threads(&self) -> usize {
+        self.check_threads
+    }
+
+    /// Retrieve whether or not piece check progress messages are sent.
+    pub fn piece_check_progress(&self) -> bool {
+        self.check_progress
+    }
+
+    /// Retrieve the allocation mode.
+    pub fn allocation_mode(&self) -> AllocationMode {
+        self.alloc_mode
+    }
+
+    /// Retrieve the sync mode.
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
     /// Build a `DiskManager` with the given `FileSystem`.
     pub fn build<F>(self, fs: F) -> DiskManager<F>
         where F: FileSystem + Send + Sync + 'static {
@@ -52,13 +52,20 @@ impl<F> FileSystem for FileHandleCache<F> where F: FileSystem {
         })
     }
 
-    fn sync_file<P>(&self, path: P) -> io::Result<()>
+    fn sync_path<P>(&self, path: P) -> io::Result<()>
         where P: AsRef<Path> + Send + 'static {
         self.run_with_lock(|cache, _| {
             cache.clear()
         });
 
-        self.inner.sync_file(path)
+        self.inner.sync_path(path)
+    }
+
+    fn sync_file(&self, file: &Self::File) -> io::Result<()> {
+        let lock_file = file.lock()
+        .expect("bip_disk: Failed To Lock File In FileHandleCache::sync_file");
+
+        self.inner.sync_file(&*lock_file)
     }
 
     fn file_size(&self, file: &Self::File) -> io::Result<u64> {
@@ -81,4 +88,8 @@ impl<F> FileSystem for FileHandleCache<F> where F: FileSystem {
 
         self.inner.write_file(&mut *lock_file, offset, buffer)
     }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        self.inner.available_space(path)
+    }
 }
\ No newline at end of file
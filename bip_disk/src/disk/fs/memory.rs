@@ -0,0 +1,165 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use disk::fs::FileSystem;
+
+/// File that exists in memory.
+pub struct InMemoryFile {
+    path: PathBuf
+}
+
+/// File system that stores all files in memory.
+///
+/// Cloning the file system will share the underlying files between the clones.
+#[derive(Clone)]
+pub struct InMemoryFileSystem {
+    files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>
+}
+
+impl InMemoryFileSystem {
+    /// Create a new, empty `InMemoryFileSystem`.
+    pub fn new() -> InMemoryFileSystem {
+        InMemoryFileSystem{ files: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Run the given closure with exclusive access to the files, keyed by their path.
+    pub fn run_with_lock<C, R>(&self, call: C) -> R
+        where C: FnOnce(&mut HashMap<PathBuf, Vec<u8>>) -> R {
+        let mut lock_files = self.files.lock().unwrap();
+
+        call(&mut *lock_files)
+    }
+}
+
+impl FileSystem for InMemoryFileSystem {
+    type File = InMemoryFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        let file_path = path.as_ref().to_path_buf();
+
+        self.run_with_lock(|files| {
+            if !files.contains_key(&file_path) {
+                files.insert(file_path.clone(), Vec::new());
+            }
+        });
+
+        Ok(InMemoryFile{ path: file_path })
+    }
+
+    fn sync_path<P>(&self, _path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        Ok(())
+    }
+
+    fn sync_file(&self, _file: &Self::File) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
+        self.run_with_lock(|files| {
+            files.get(&file.path)
+                .map(|file| file.len() as u64)
+                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
+        })
+    }
+
+    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        self.run_with_lock(|files| {
+            files.get(&file.path)
+                .map(|file_buffer| {
+                    // Reading past the end of the file reads zero bytes, same as a native file
+                    let cast_offset = cmp::min(offset as usize, file_buffer.len());
+                    let bytes_to_copy = cmp::min(file_buffer.len() - cast_offset, buffer.len());
+                    let bytes = &file_buffer[cast_offset..(bytes_to_copy + cast_offset)];
+
+                    buffer[..bytes_to_copy].clone_from_slice(bytes);
+
+                    bytes_to_copy
+                })
+                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
+        })
+    }
+
+    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        self.run_with_lock(|files| {
+            files.get_mut(&file.path)
+                .map(|file_buffer| {
+                    let cast_offset = offset as usize;
+
+                    // Fill in zeroes if we are writing past the end of the file
+                    let last_byte_pos = cast_offset + buffer.len();
+                    if last_byte_pos > file_buffer.len() {
+                        file_buffer.resize(last_byte_pos, 0);
+                    }
+
+                    file_buffer[cast_offset..last_byte_pos].clone_from_slice(buffer);
+
+                    buffer.len()
+                })
+                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
+        })
+    }
+
+    /// Memory is not tracked, so this always reports the maximum amount of space.
+    fn available_space(&self, _path: &Path) -> io::Result<u64> {
+        Ok(u64::max_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use disk::fs::FileSystem;
+    use disk::fs::memory::InMemoryFileSystem;
+
+    #[test]
+    fn positive_open_file_creates_empty() {
+        let fs = InMemoryFileSystem::new();
+
+        let file = fs.open_file("dir/file").unwrap();
+
+        assert_eq!(0, fs.file_size(&file).unwrap());
+        assert!(fs.run_with_lock(|files| files.contains_key(Path::new("dir/file"))));
+    }
+
+    #[test]
+    fn positive_write_past_end_zero_fills() {
+        let fs = InMemoryFileSystem::new();
+        let mut file = fs.open_file("file").unwrap();
+
+        assert_eq!(2, fs.write_file(&mut file, 3, &[1, 2]).unwrap());
+
+        let mut buffer = [9u8; 5];
+        assert_eq!(5, fs.read_file(&mut file, 0, &mut buffer).unwrap());
+        assert_eq!([0, 0, 0, 1, 2], buffer);
+    }
+
+    #[test]
+    fn positive_read_past_end() {
+        let fs = InMemoryFileSystem::new();
+        let mut file = fs.open_file("file").unwrap();
+        fs.write_file(&mut file, 0, &[1, 2, 3]).unwrap();
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(1, fs.read_file(&mut file, 2, &mut buffer).unwrap());
+        assert_eq!(3, buffer[0]);
+        assert_eq!(0, fs.read_file(&mut file, 10, &mut buffer).unwrap());
+    }
+
+    #[test]
+    fn positive_clone_shares_files() {
+        let fs = InMemoryFileSystem::new();
+        let clone_fs = fs.clone();
+
+        let mut file = fs.open_file("file").unwrap();
+        fs.write_file(&mut file, 0, &[1, 2, 3]).unwrap();
+
+        let clone_file = clone_fs.open_file("file").unwrap();
+        assert_eq!(3, clone_fs.file_size(&clone_file).unwrap());
+    }
+}
@@ -0,0 +1,231 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use disk::fs::FileSystem;
+use disk::fs::native;
+
+use memmap::MmapMut;
+
+/// File that exists on disk, accessed through a memory map.
+pub struct MmapFile {
+    file: File,
+    // Zero length files can not be mapped, so the map is created on first write
+    map:  Option<MmapMut>
+}
+
+impl MmapFile {
+    /// Create a new MmapFile, mapping the file if it is not empty.
+    fn new(file: File) -> io::Result<MmapFile> {
+        let mut mmap_file = MmapFile{ file: file, map: None };
+        try!(mmap_file.remap());
+
+        Ok(mmap_file)
+    }
+
+    /// Map (or re-map) the whole file into memory.
+    fn remap(&mut self) -> io::Result<()> {
+        let file_len = try!(self.file.metadata()).len();
+
+        self.map = if file_len == 0 {
+            None
+        } else {
+            Some(try!(unsafe { MmapMut::map_mut(&self.file) }))
+        };
+
+        Ok(())
+    }
+
+    /// Length of the current mapping in bytes.
+    fn map_len(&self) -> usize {
+        self.map.as_ref().map(|map| map.len()).unwrap_or(0)
+    }
+}
+
+/// File system that maps to the OS file system, reading and writing through memory maps.
+///
+/// Reads are served directly from the mapped pages, avoiding a system call per read. Writes
+/// that extend a file will grow the file and re-map it.
+pub struct MmapFileSystem {
+    current_dir: PathBuf
+}
+
+impl MmapFileSystem {
+    /// Initialize a new `MmapFileSystem` with the default directory set.
+    pub fn with_directory<P>(default: P) -> MmapFileSystem
+        where P: AsRef<Path> {
+        MmapFileSystem{ current_dir: default.as_ref().to_path_buf() }
+    }
+}
+
+impl FileSystem for MmapFileSystem {
+    type File = MmapFile;
+
+    fn open_file<P>(&self, path: P) -> io::Result<Self::File>
+        where P: AsRef<Path> + Send + 'static {
+        let combine_path = native::combine_user_path(&path, &self.current_dir);
+        let file = try!(native::create_new_file(&combine_path));
+
+        MmapFile::new(file)
+    }
+
+    fn sync_path<P>(&self, _path: P) -> io::Result<()>
+        where P: AsRef<Path> + Send + 'static {
+        Ok(())
+    }
+
+    fn sync_file(&self, file: &MmapFile) -> io::Result<()> {
+        if let Some(ref map) = file.map {
+            try!(map.flush());
+        }
+
+        file.file.sync_data()
+    }
+
+    fn file_size(&self, file: &MmapFile) -> io::Result<u64> {
+        file.file.metadata().map(|metadata| metadata.len())
+    }
+
+    fn read_file(&self, file: &mut MmapFile, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+        // File may have been grown through another handle since we mapped it
+        if offset as usize + buffer.len() > file.map_len() {
+            try!(file.remap());
+        }
+
+        let map_len = file.map_len();
+        let cast_offset = cmp::min(offset as usize, map_len);
+        let bytes_to_copy = cmp::min(map_len - cast_offset, buffer.len());
+
+        if let Some(ref map) = file.map {
+            buffer[..bytes_to_copy].copy_from_slice(&map[cast_offset..(cast_offset + bytes_to_copy)]);
+        }
+
+        Ok(bytes_to_copy)
+    }
+
+    fn write_file(&self, file: &mut MmapFile, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+        let cast_offset = offset as usize;
+        let last_byte_pos = cast_offset + buffer.len();
+
+        // Grow the file (filling in zeroes) and re-map it if we are writing past the end
+        if last_byte_pos > file.map_len() {
+            try!(file.file.set_len(last_byte_pos as u64));
+            try!(file.remap());
+        }
+
+        if let Some(ref mut map) = file.map {
+            map[cast_offset..last_byte_pos].copy_from_slice(buffer);
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        native::available_space_at(&native::combine_user_path(&path, &self.current_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use disk::fs::FileSystem;
+    use disk::fs::mmap::MmapFileSystem;
+
+    /// Clean directory (unique to the test) to use for the file system, which is removed when dropped.
+    struct TestDirectory {
+        path: PathBuf
+    }
+
+    impl TestDirectory {
+        fn new(name: &str) -> TestDirectory {
+            let path = env::temp_dir().join("bip_disk_mmap_tests").join(name);
+            let _ = fs::remove_dir_all(&path);
+
+            TestDirectory{ path: path }
+        }
+
+        fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn positive_write_grows_file() {
+        let dir = TestDirectory::new("positive_write_grows_file");
+        let fs = MmapFileSystem::with_directory(dir.path());
+        let mut file = fs.open_file("sub/file").unwrap();
+
+        assert_eq!(0, fs.file_size(&file).unwrap());
+        assert_eq!(2, fs.write_file(&mut file, 3, &[1, 2]).unwrap());
+        assert_eq!(5, fs.file_size(&file).unwrap());
+
+        let mut buffer = [9u8; 5];
+        assert_eq!(5, fs.read_file(&mut file, 0, &mut buffer).unwrap());
+        assert_eq!([0, 0, 0, 1, 2], buffer);
+    }
+
+    #[test]
+    fn positive_write_persists_after_reopen() {
+        let dir = TestDirectory::new("positive_write_persists_after_reopen");
+        let fs = MmapFileSystem::with_directory(dir.path());
+
+        {
+            let mut file = fs.open_file("file").unwrap();
+            fs.write_file(&mut file, 0, b"hello").unwrap();
+            fs.write_file(&mut file, 5, b" world").unwrap();
+        }
+
+        let mut file = fs.open_file("file").unwrap();
+        let mut buffer = [0u8; 11];
+        assert_eq!(11, fs.read_file(&mut file, 0, &mut buffer).unwrap());
+        assert_eq!(b"hello world", &buffer);
+        assert_eq!(b"hello world".to_vec(), fs::read(dir.path().join("file")).unwrap());
+    }
+
+    #[test]
+    fn positive_read_after_grow_through_other_handle() {
+        let dir = TestDirectory::new("positive_read_after_grow_through_other_handle");
+        let fs = MmapFileSystem::with_directory(dir.path());
+        let mut file_one = fs.open_file("file").unwrap();
+        let mut file_two = fs.open_file("file").unwrap();
+
+        fs.write_file(&mut file_two, 0, &[1, 2, 3]).unwrap();
+
+        let mut buffer = [0u8; 3];
+        assert_eq!(3, fs.read_file(&mut file_one, 0, &mut buffer).unwrap());
+        assert_eq!([1, 2, 3], buffer);
+    }
+
+    #[test]
+    fn positive_read_past_end() {
+        let dir = TestDirectory::new("positive_read_past_end");
+        let fs = MmapFileSystem::with_directory(dir.path());
+        let mut file = fs.open_file("file").unwrap();
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(0, fs.read_file(&mut file, 0, &mut buffer).unwrap());
+
+        fs.write_file(&mut file, 0, &[1, 2, 3]).unwrap();
+        assert_eq!(1, fs.read_file(&mut file, 2, &mut buffer).unwrap());
+        assert_eq!(3, buffer[0]);
+        assert_eq!(0, fs.read_file(&mut file, 10, &mut buffer).unwrap());
+    }
+
+    #[test]
+    fn positive_available_space_missing_directory() {
+        let dir = TestDirectory::new("positive_available_space_missing_directory");
+        let fs = MmapFileSystem::with_directory(dir.path());
+
+        assert!(fs.available_space(Path::new("not/created/yet")).unwrap() > 0);
+    }
+}
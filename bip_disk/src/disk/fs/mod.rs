@@ -2,6 +2,8 @@ use std::path::{Path};
 use std::io::{self};
 
 pub mod cache;
+pub mod memory;
+pub mod mmap;
 pub mod native;
 
 /// Trait for performing operations on some file system.
@@ -17,10 +19,13 @@ pub trait FileSystem {
     fn open_file<P>(&self, path: P) -> io::Result<Self::File>
         where P: AsRef<Path> + Send + 'static;
 
-    /// Sync the file.
-    fn sync_file<P>(&self, path: P) -> io::Result<()>
+    /// Sync the file at the given path.
+    fn sync_path<P>(&self, path: P) -> io::Result<()>
         where P: AsRef<Path> + Send + 'static;
 
+    /// Sync the data written to the file, so that it is durable in case of a crash.
+    fn sync_file(&self, file: &Self::File) -> io::Result<()>;
+
     /// Get the size of the file in bytes.
     fn file_size(&self, file: &Self::File) -> io::Result<u64>;
 
@@ -34,6 +39,11 @@ pub trait FileSystem {
     /// On success, return the number of bytes written. If offset is
     /// past the current size of the file, zeroes will be filled in.
     fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize>;
+
+    /// Get the number of bytes available for new data at the given path.
+    ///
+    /// The path does not need to exist yet.
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
 }
 
 impl<'a, F> FileSystem for &'a F where F: FileSystem {
@@ -44,9 +54,13 @@ impl<'a, F> FileSystem for &'a F where F: FileSystem {
         FileSystem::open_file(*self, path)
     }
 
-    fn sync_file<P>(&self, path: P) -> io::Result<()>
+    fn sync_path<P>(&self, path: P) -> io::Result<()>
         where P: AsRef<Path> + Send + 'static {
-        FileSystem::sync_file(*self, path)
+        FileSystem::sync_path(*self, path)
+    }
+
+    fn sync_file(&self, file: &Self::File) -> io::Result<()> {
+        FileSystem::sync_file(*self, file)
     }
 
     fn file_size(&self, file: &Self::File) -> io::Result<u64> {
@@ -60,4 +74,8 @@ impl<'a, F> FileSystem for &'a F where F: FileSystem {
     fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
         FileSystem::write_file(*self, file, offset, buffer)
     }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        FileSystem::available_space(*self, path)
+    }
 }
\ No newline at end of file
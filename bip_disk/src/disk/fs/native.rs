@@ -5,7 +5,7 @@ use std::borrow::Cow;
 
 use disk::fs::FileSystem;
 
-// TODO: This should be sanitizing paths passed into it so they don't escape the base directory!!!
+use fs2;
 
 /// File that exists on disk.
 pub struct NativeFile {
@@ -43,11 +43,15 @@ impl FileSystem for NativeFileSystem {
         Ok(NativeFile::new(file))
     }
 
-    fn sync_file<P>(&self, _path: P) -> io::Result<()>
+    fn sync_path<P>(&self, _path: P) -> io::Result<()>
         where P: AsRef<Path> + Send + 'static {
         Ok(())
     }
 
+    fn sync_file(&self, file: &NativeFile) -> io::Result<()> {
+        file.file.sync_data()
+    }
+
     fn file_size(&self, file: &NativeFile) -> io::Result<u64> {
         file.file.metadata().map(|metadata| metadata.len())
     }
@@ -63,12 +67,16 @@ impl FileSystem for NativeFileSystem {
 
         file.file.write(buffer)
     }
+
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        available_space_at(&combine_user_path(&path, &self.current_dir))
+    }
 }
 
 /// Create a new file with read and write options.
 ///
 /// Intermediate directories will be created if they do not exist.
-fn create_new_file<P>(path: P) -> io::Result<File>
+pub fn create_new_file<P>(path: P) -> io::Result<File>
     where P: AsRef<Path> {
     match path.as_ref().parent() {
         Some(parent_dir) => {
@@ -82,8 +90,19 @@ fn create_new_file<P>(path: P) -> io::Result<File>
     }
 }
 
+/// Get the space available on the disk holding the given path.
+///
+/// If the path does not exist yet, the closest ancestor that does exist is used.
+pub fn available_space_at(path: &Path) -> io::Result<u64> {
+    let existing_path = path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."));
+
+    fs2::available_space(existing_path)
+}
+
 /// Create a path from the user path and current directory.
-fn combine_user_path<'a, P>(user_path: &'a P, current_dir: &Path) -> Cow<'a, Path>
+pub fn combine_user_path<'a, P>(user_path: &'a P, current_dir: &Path) -> Cow<'a, Path>
     where P: AsRef<Path> {
     let ref_user_path = user_path.as_ref();
 
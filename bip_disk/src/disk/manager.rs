@@ -2,14 +2,16 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use disk::fs::FileSystem;
-use disk::{IDiskMessage, ODiskMessage};
+use disk::{IDiskMessage, ODiskMessage, TorrentEvent};
 use disk::tasks;
 use disk::tasks::context::DiskManagerContext;
 use disk::builder::DiskManagerBuilder;
 
+use bip_util::bt::InfoHash;
+use bip_util::send::TrySender;
 use crossbeam::sync::MsQueue;
 use futures::task::{self, Task};
-use futures::sync::mpsc::{self, Receiver};
+use futures::sync::mpsc::{self, Receiver, UnboundedReceiver};
 use futures::{StartSend, Poll, Stream, Sink, AsyncSink, Async};
 use futures_cpupool::{CpuPool};
 
@@ -23,21 +25,65 @@ impl<F> DiskManager<F> {
     /// Create a `DiskManager` from the given `DiskManagerBuilder`.
     pub fn from_builder(mut builder: DiskManagerBuilder, fs: F) -> DiskManager<F> {
         let cur_sink_capacity = Arc::new(AtomicUsize::new(0));
+        let cur_writes = Arc::new(AtomicUsize::new(0));
         let sink_capacity = builder.sink_buffer_capacity();
+        let max_writes = Arc::new(AtomicUsize::new(builder.write_queue_depth()));
         let stream_capacity = builder.stream_buffer_capacity();
+        let check_threads = builder.piece_check_threads();
+        let check_progress = builder.piece_check_progress();
+        let write_coalescing = builder.write_coalescing();
+        let alloc_mode = builder.allocation_mode();
+        let sync_mode = builder.sync_mode();
         let pool_builder = builder.worker_config();
 
         let (out_send, out_recv) = mpsc::channel(stream_capacity);
-        let context = DiskManagerContext::new(out_send, fs);
+        let context = DiskManagerContext::new(out_send, fs, check_threads, check_progress, alloc_mode, sync_mode);
+        context.set_write_coalescing(write_coalescing);
         let task_queue = Arc::new(MsQueue::new());
 
         let sink = DiskManagerSink::new(pool_builder.create(), context, sink_capacity, cur_sink_capacity.clone(),
-            task_queue.clone());
-        let stream = DiskManagerStream::new(out_recv, cur_sink_capacity, task_queue.clone());
+            max_writes, cur_writes.clone(), task_queue.clone());
+        let stream = DiskManagerStream::new(out_recv, cur_sink_capacity, cur_writes, task_queue.clone());
 
         DiskManager{ sink: sink, stream: stream }
     }
 
+    /// Set the maximum number of `ProcessBlock` messages that can be pending at once.
+    ///
+    /// See `DiskManagerSink::set_write_queue_depth`.
+    pub fn set_write_queue_depth(&self, depth: usize) {
+        self.sink.set_write_queue_depth(depth)
+    }
+
+    /// Set whether or not pending `ProcessBlock` messages are batched and merged before being written.
+    ///
+    /// See `DiskManagerSink::set_write_coalescing`.
+    pub fn set_write_coalescing(&self, enabled: bool) {
+        self.sink.set_write_coalescing(enabled)
+    }
+
+    /// Submit the given message without blocking, handing it back if it could not be submitted.
+    ///
+    /// See `DiskManagerSink::try_submit`.
+    pub fn try_submit(&self, msg: IDiskMessage) -> Result<(), IDiskMessage>
+        where F: FileSystem + Send + Sync + 'static {
+        self.sink.try_submit(msg)
+    }
+
+    /// Subscribe to the `TorrentEvent`s for every torrent, published after this call.
+    ///
+    /// See `DiskManagerSink::subscribe`.
+    pub fn subscribe(&self) -> UnboundedReceiver<TorrentEvent> {
+        self.sink.subscribe()
+    }
+
+    /// Cancel the in progress add for the given torrent.
+    ///
+    /// See `DiskManagerSink::cancel_add_torrent`.
+    pub fn cancel_add_torrent(&self, hash: InfoHash) -> bool {
+        self.sink.cancel_add_torrent(hash)
+    }
+
     /// Break the `DiskManager` into a sink and stream.
     ///
     /// The returned sink implements `Clone`.
@@ -76,36 +122,133 @@ pub struct DiskManagerSink<F> {
     context:      DiskManagerContext<F>,
     max_capacity: usize,
     cur_capacity: Arc<AtomicUsize>,
+    max_writes:   Arc<AtomicUsize>,
+    cur_writes:   Arc<AtomicUsize>,
     task_queue:   Arc<MsQueue<Task>>
 }
 
 impl<F> Clone for DiskManagerSink<F> {
     fn clone(&self) -> DiskManagerSink<F> {
         DiskManagerSink{ pool: self.pool.clone(), context: self.context.clone(), max_capacity: self.max_capacity,
-                         cur_capacity: self.cur_capacity.clone(), task_queue: self.task_queue.clone() }
+                         cur_capacity: self.cur_capacity.clone(), max_writes: self.max_writes.clone(),
+                         cur_writes: self.cur_writes.clone(), task_queue: self.task_queue.clone() }
     }
 }
 
 impl<F> DiskManagerSink<F> {
-    fn new(pool: CpuPool, context: DiskManagerContext<F>, max_capacity: usize,
-           cur_capacity: Arc<AtomicUsize>, task_queue: Arc<MsQueue<Task>>) -> DiskManagerSink<F> {
-        DiskManagerSink{ pool: pool, context: context, max_capacity: max_capacity,
-                         cur_capacity: cur_capacity, task_queue: task_queue }
+    fn new(pool: CpuPool, context: DiskManagerContext<F>, max_capacity: usize, cur_capacity: Arc<AtomicUsize>,
+           max_writes: Arc<AtomicUsize>, cur_writes: Arc<AtomicUsize>, task_queue: Arc<MsQueue<Task>>) -> DiskManagerSink<F> {
+        DiskManagerSink{ pool: pool, context: context, max_capacity: max_capacity, cur_capacity: cur_capacity,
+                         max_writes: max_writes, cur_writes: cur_writes, task_queue: task_queue }
+    }
+
+    /// Set the maximum number of `ProcessBlock` messages that can be pending at once.
+    ///
+    /// Applies to all clones of this sink. Lowering the depth below the number of blocks
+    /// currently pending will not cancel any of them, but will hold back new blocks until
+    /// enough of them have been received from the stream.
+    pub fn set_write_queue_depth(&self, depth: usize) {
+        self.max_writes.store(depth, Ordering::SeqCst);
+    }
+
+    /// Set whether or not pending `ProcessBlock` messages are batched and merged before being written.
+    ///
+    /// Applies to all clones of this sink, for blocks sent after the call. Each block still gets
+    /// its own `BlockProcessed` or `ProcessBlockError` message.
+    pub fn set_write_coalescing(&self, enabled: bool) {
+        self.context.set_write_coalescing(enabled);
+    }
+
+    /// Subscribe to the `TorrentEvent`s for every torrent, published after this call.
+    ///
+    /// Events are buffered until they are received, and publishing stops once the receiver is dropped.
+    /// Any number of subscriptions can be made, each receiving every event.
+    pub fn subscribe(&self) -> UnboundedReceiver<TorrentEvent> {
+        self.context.subscribe()
+    }
+
+    /// Cancel the in progress add for the given torrent, stopping the check of its existing pieces.
+    ///
+    /// The add will fail with a `TorrentError` of kind `Cancelled`, though any files that were already allocated
+    /// are left in place. Adds that have been sent but not yet started are cancelled too. Returns false if
+    /// there was no add in progress for the torrent.
+    pub fn cancel_add_torrent(&self, hash: InfoHash) -> bool {
+        self.context.cancel_check(hash)
+    }
+
+    /// Submit the given message without blocking, handing it back if it could not be submitted.
+    ///
+    /// The message is handed back if the sink is at capacity (or, for `ProcessBlock` messages, if the
+    /// write queue is full), or if the `DiskManager` has been shutdown. Unlike `start_send`, the current
+    /// task is not notified when capacity frees up, so callers should try again after receiving from the stream.
+    pub fn try_submit(&self, msg: IDiskMessage) -> Result<(), IDiskMessage>
+        where F: FileSystem + Send + Sync + 'static {
+        if self.context.is_shutdown() {
+            return Err(msg)
+        }
+
+        match TrySender::try_send(self, msg) {
+            Some(msg) => Err(msg),
+            None      => Ok(())
+        }
     }
 
-    fn try_submit_work(&self) -> bool {
+    fn try_submit_work(&self, item: &IDiskMessage) -> bool {
+        let is_write = match item {
+            &IDiskMessage::ProcessBlock(_) => true,
+            _                              => false
+        };
+
+        if is_write && !self.try_submit_write() {
+            return false
+        }
+
         let cur_capacity = self.cur_capacity.fetch_add(1, Ordering::SeqCst);
 
         if cur_capacity < self.max_capacity {
             true
         } else {
             self.cur_capacity.fetch_sub(1, Ordering::SeqCst);
+            if is_write {
+                self.cur_writes.fetch_sub(1, Ordering::SeqCst);
+            }
+
+            false
+        }
+    }
+
+    fn try_submit_write(&self) -> bool {
+        let cur_writes = self.cur_writes.fetch_add(1, Ordering::SeqCst);
+
+        if cur_writes < self.max_writes.load(Ordering::SeqCst) {
+            true
+        } else {
+            self.cur_writes.fetch_sub(1, Ordering::SeqCst);
 
             false
         }
     }
 }
 
+impl<F> TrySender<IDiskMessage> for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static {
+    /// Submit the given message, handing it back if the sink is at capacity.
+    ///
+    /// Panics if the `DiskManager` has been shutdown.
+    fn try_send(&self, msg: IDiskMessage) -> Option<IDiskMessage> {
+        if self.context.is_shutdown() {
+            panic!("bip_disk: DiskManagerSink Received IDiskMessage After Shutdown")
+        }
+
+        if self.try_submit_work(&msg) {
+            tasks::execute_on_pool(msg, &self.pool, self.context.clone());
+
+            None
+        } else {
+            Some(msg)
+        }
+    }
+}
+
 impl<F> Sink for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static {
     type SinkItem = IDiskMessage;
     type SinkError = ();
@@ -113,7 +256,12 @@ impl<F> Sink for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
         info!("Starting Send For DiskManagerSink With IDiskMessage");
 
-        if self.try_submit_work() {
+        if self.context.is_shutdown() {
+            info!("DiskManagerSink Rejected IDiskMessage After Shutdown");
+            return Err(())
+        }
+
+        if self.try_submit_work(&item) {
             info!("DiskManagerSink Submitted Work On First Attempt");
             tasks::execute_on_pool(item, &self.pool, self.context.clone());
 
@@ -126,7 +274,7 @@ impl<F> Sink for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static
         info!("DiskManagerSink Failed To Submit Work On First Attempt, Adding Task To Queue");
         self.task_queue.push(task::current());
 
-        if self.try_submit_work() {
+        if self.try_submit_work(&item) {
             // Receiver will look at the queue but wake us up, even though we dont need it to now...
             info!("DiskManagerSink Submitted Work On Second Attempt");
             tasks::execute_on_pool(item, &self.pool, self.context.clone());
@@ -149,15 +297,23 @@ impl<F> Sink for DiskManagerSink<F> where F: FileSystem + Send + Sync + 'static
 pub struct DiskManagerStream {
     recv:         Receiver<ODiskMessage>,
     cur_capacity: Arc<AtomicUsize>,
+    cur_writes:   Arc<AtomicUsize>,
     task_queue:   Arc<MsQueue<Task>>
 }
 
 impl DiskManagerStream {
-    fn new(recv: Receiver<ODiskMessage>, cur_capacity: Arc<AtomicUsize>, task_queue: Arc<MsQueue<Task>>) -> DiskManagerStream {
-        DiskManagerStream{ recv: recv, cur_capacity: cur_capacity, task_queue: task_queue }
+    fn new(recv: Receiver<ODiskMessage>, cur_capacity: Arc<AtomicUsize>, cur_writes: Arc<AtomicUsize>,
+           task_queue: Arc<MsQueue<Task>>) -> DiskManagerStream {
+        DiskManagerStream{ recv: recv, cur_capacity: cur_capacity, cur_writes: cur_writes, task_queue: task_queue }
     }
 
-    fn complete_work(&self) {
+    fn complete_work(&self, msg: &ODiskMessage) {
+        match msg {
+            &ODiskMessage::BlockProcessed(_) |
+            &ODiskMessage::ProcessBlockError(_, _) => { self.cur_writes.fetch_sub(1, Ordering::SeqCst); },
+            _                                      => ()
+        }
+
         self.cur_capacity.fetch_sub(1, Ordering::SeqCst);
     }
 }
@@ -173,9 +329,14 @@ impl Stream for DiskManagerStream {
             res @ Ok(Async::Ready(Some(ODiskMessage::TorrentAdded(_)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::TorrentRemoved(_)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::TorrentSynced(_)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::ResumeDataExported(_, _)))) |
             res @ Ok(Async::Ready(Some(ODiskMessage::BlockLoaded(_)))) |
-            res @ Ok(Async::Ready(Some(ODiskMessage::BlockProcessed(_)))) => {
-                self.complete_work();
+            res @ Ok(Async::Ready(Some(ODiskMessage::BlockProcessed(_)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::ProcessBlockError(_, _)))) |
+            res @ Ok(Async::Ready(Some(ODiskMessage::ShutdownComplete))) => {
+                if let Ok(Async::Ready(Some(ref msg))) = res {
+                    self.complete_work(msg);
+                }
 
                 info!("Notifying DiskManager That We Can Submit More Work");
                 loop {
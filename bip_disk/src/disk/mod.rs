@@ -7,6 +7,7 @@ use bip_util::bt::{InfoHash};
 pub mod builder;
 pub mod manager;
 pub mod fs;
+pub mod resume;
 mod tasks;
 
 //----------------------------------------------------------------------------//
@@ -16,6 +17,16 @@ mod tasks;
 pub enum IDiskMessage {
     /// Message to add a torrent to the disk manager.
     AddTorrent(Metainfo),
+    /// Message to add a torrent to the disk manager, using resume data exported with `ExportResumeData`.
+    ///
+    /// Pieces that were good when the resume data was exported are not checked again, unless
+    /// the size of any file in the torrent has changed since then.
+    AddTorrentWithResumeData(Metainfo, Vec<u8>),
+    /// Message to add a torrent to the disk manager, with priorities for the files in the torrent.
+    ///
+    /// Priorities are given in the same order as the files in the info dictionary. Skipped
+    /// files are not allocated, and pieces spanning them are not checked if they don't exist.
+    AddTorrentWithPriorities(Metainfo, Vec<FilePriority>),
     /// Message to remove a torrent from the disk manager.
     ///
     /// Note, this will NOT remove any data from the `FileSystem`,
@@ -33,10 +44,18 @@ pub enum IDiskMessage {
     /// message should be sent, otherwise, `IDiskMessage::RemoveTorrent` is
     /// sufficient.
     SyncTorrent(InfoHash),
+    /// Message to export the good pieces and file sizes of the torrent as bencoded resume data.
+    ExportResumeData(InfoHash),
     /// Message to load the given block in to memory.
     LoadBlock(BlockMut),
     /// Message to process the given block and persist it.
-    ProcessBlock(Block)
+    ProcessBlock(Block),
+    /// Message to shut down the `DiskManager`.
+    ///
+    /// This waits for all previously sent messages to finish, and then
+    /// syncs every torrent, before sending `ODiskMessage::ShutdownComplete`.
+    /// Any messages sent after this will be rejected by the sink.
+    Shutdown
 }
 
 /// Messages that can be received from the `DiskManager`.
@@ -47,24 +66,104 @@ pub enum ODiskMessage {
     /// Any good pieces already existing for the torrent will be sent
     /// as `FoundGoodPiece` messages BEFORE this message is sent.
     TorrentAdded(InfoHash),
+    /// Message indicating progress checking existing pieces for the given torrent (hash),
+    /// as well as the number of pieces checked so far, and the total number of pieces to check.
+    ///
+    /// Only sent if enabled with `DiskManagerBuilder::with_piece_check_progress`.
+    TorrentCheckProgress(InfoHash, u64, u64),
     /// Message indicating that the torrent has been removed.
     TorrentRemoved(InfoHash),
     /// Message indicating that the torrent has been synced.
     TorrentSynced(InfoHash),
+    /// Message containing the bencoded resume data exported for the given torrent (hash).
+    ResumeDataExported(InfoHash, Vec<u8>),
     /// Message indicating that a good piece has been identified for
     /// the given torrent (hash), as well as the piece index.
+    ///
+    /// Pieces are checked as soon as the last block of the piece has been
+    /// processed, so this message is sent BEFORE the `BlockProcessed` message
+    /// for that block.
     FoundGoodPiece(InfoHash, u64),
     /// Message indicating that a bad piece has been identified for
     /// the given torrent (hash), as well as the piece index.
+    ///
+    /// Every block of the piece should be downloaded again, as there is no
+    /// way to tell which of the blocks were bad.
     FoundBadPiece(InfoHash, u64),
     /// Message indicating that the given block has been loaded.
     BlockLoaded(BlockMut),
     /// Message indicating that the given block has been processed.
     BlockProcessed(Block),
+    /// Message indicating that all pending messages have finished, and all torrents have been
+    /// synced, after a `Shutdown` message. This will be the last message sent.
+    ///
+    /// Errors from syncing any torrent will be sent as `TorrentError` messages BEFORE this message is sent.
+    ShutdownComplete,
     /// Error occurring from a `AddTorrent` or `RemoveTorrent` message.
     TorrentError(InfoHash, TorrentError),
     /// Error occurring from a `LoadBlock` message.
     LoadBlockError(BlockMut, BlockError),
     /// Error occurring from a `ProcessBlock` message.
     ProcessBlockError(Block, BlockError)
-}
\ No newline at end of file
+}
+
+//----------------------------------------------------------------------------//
+
+/// Lifecycle events for torrents, received from a subscription on the `DiskManager`.
+///
+/// Events are published as the corresponding `ODiskMessage`s are sent, so subscribers
+/// see the same progress as the stream, without having to track pieces themselves.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TorrentEvent {
+    /// Piece at the given index was found to be good.
+    PieceCompleted(InfoHash, u64),
+    /// File at the given index in the info dictionary has had all of its pieces found to be good.
+    ///
+    /// Sent after the `PieceCompleted` event for the piece that completed the file.
+    FileCompleted(InfoHash, usize),
+    /// Every piece of the torrent has been found to be good.
+    ///
+    /// Sent after the `FileCompleted` events for the piece that completed the torrent.
+    TorrentFinished(InfoHash),
+    /// Error occurred for the torrent, described by the displayed error.
+    ///
+    /// The error itself is sent in the `ODiskMessage` for the message that failed.
+    Error(InfoHash, String)
+}
+
+//----------------------------------------------------------------------------//
+
+/// Mode used to allocate files for a newly added torrent.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AllocationMode {
+    /// Write a single byte at the end of each file, letting the `FileSystem` fill in the rest.
+    ///
+    /// On most file systems, this will create a sparse file.
+    Sparse,
+    /// Write out zeroes for the whole length of each file.
+    ///
+    /// This avoids fragmentation, and running out of space part way through a download.
+    Full
+}
+
+/// Priority of a file within a torrent.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FilePriority {
+    /// File is not wanted, and will not be allocated.
+    ///
+    /// Pieces spanning both a skipped and a wanted file are still written in whole.
+    Skip,
+    /// File is wanted.
+    Normal
+}
+
+/// Mode used to sync the files of a torrent as pieces are completed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SyncMode {
+    /// Never sync files as pieces are completed, only on `SyncTorrent` or `RemoveTorrent`.
+    Never,
+    /// Sync the files spanned by a piece as soon as it is found to be good.
+    Piece,
+    /// Sync the files spanned by the good pieces of a torrent once the given number of them are found.
+    Periodic(usize)
+}
@@ -0,0 +1,158 @@
+use error::{TorrentError, TorrentErrorKind, TorrentResult};
+
+use bip_bencode::{BencodeRef, BencodeMut, BMutAccess, BDecodeOpt, BConvert, BencodeConvertError};
+use bip_util::bt::{self, InfoHash};
+
+// Keys for the exported resume data
+const ROOT_KEY:       &'static [u8] = b"root";
+const INFO_HASH_KEY:  &'static [u8] = b"info hash";
+const PIECES_KEY:     &'static [u8] = b"pieces";
+const FILE_SIZES_KEY: &'static [u8] = b"file sizes";
+
+/// Good pieces and file allocation state for a torrent, used to resume it without re-checking every piece.
+///
+/// Resume data is exported with `IDiskMessage::ExportResumeData`, and restored with
+/// `IDiskMessage::AddTorrentWithResumeData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResumeData {
+    info_hash:   InfoHash,
+    good_pieces: Vec<u64>,
+    file_sizes:  Vec<u64>
+}
+
+struct ResumeConvert;
+
+impl BConvert for ResumeConvert {
+    type Error = TorrentError;
+
+    fn handle_error(&self, error: BencodeConvertError) -> TorrentError {
+        TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{ details: error.to_string() })
+    }
+}
+
+impl ResumeData {
+    /// Create a new ResumeData for the given torrent, with the given good pieces and file sizes.
+    ///
+    /// File sizes should be given in the same order as the files in the info dictionary.
+    pub fn new(info_hash: InfoHash, mut good_pieces: Vec<u64>, file_sizes: Vec<u64>) -> ResumeData {
+        good_pieces.sort();
+        good_pieces.dedup();
+
+        ResumeData{ info_hash: info_hash, good_pieces: good_pieces, file_sizes: file_sizes }
+    }
+
+    /// Parse ResumeData that was exported with `ResumeData::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> TorrentResult<ResumeData> {
+        let convert = ResumeConvert;
+
+        let bencode = try!(BencodeRef::decode(bytes, BDecodeOpt::default())
+            .map_err(|err| TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{ details: err.to_string() })));
+        let root = try!(convert.convert_dict(&bencode, ROOT_KEY));
+
+        let info_hash_bytes = try!(convert.lookup_and_convert_bytes(root, INFO_HASH_KEY));
+        let info_hash = try!(InfoHash::from_hash(info_hash_bytes)
+            .map_err(|_| TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{
+                details: format!("InfoHash Should Be {} Bytes But Was {} Bytes", bt::INFO_HASH_LEN, info_hash_bytes.len())
+            })));
+
+        let bitfield = try!(convert.lookup_and_convert_bytes(root, PIECES_KEY));
+        let good_pieces = bitfield.iter()
+            .enumerate()
+            .flat_map(|(byte_index, &byte)| {
+                (0..8).filter(move |bit| byte & (0x80 >> bit) != 0).map(move |bit| (byte_index * 8 + bit) as u64)
+            })
+            .collect();
+
+        let mut file_sizes = Vec::new();
+        for size in try!(convert.lookup_and_convert_list(root, FILE_SIZES_KEY)) {
+            let size = try!(convert.convert_int(size, FILE_SIZES_KEY));
+            if size < 0 {
+                return Err(TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{
+                    details: format!("File Size {} Is Negative", size)
+                }))
+            }
+
+            file_sizes.push(size as u64);
+        }
+
+        Ok(ResumeData::new(info_hash, good_pieces, file_sizes))
+    }
+
+    /// Encode the ResumeData as bencode, with the good pieces packed into a bitfield.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let num_bytes = self.good_pieces.last().map(|&index| index as usize / 8 + 1).unwrap_or(0);
+        let mut bitfield = vec![0u8; num_bytes];
+        for &index in self.good_pieces.iter() {
+            bitfield[index as usize / 8] |= 0x80 >> (index % 8);
+        }
+
+        let mut file_sizes = BencodeMut::new_list();
+        {
+            let file_sizes_access = file_sizes.list_mut().unwrap();
+
+            for &size in self.file_sizes.iter() {
+                file_sizes_access.push(ben_int!(size as i64));
+            }
+        }
+
+        (ben_map!{
+            INFO_HASH_KEY  => ben_bytes!(self.info_hash.as_ref()),
+            PIECES_KEY     => ben_bytes!(&bitfield[..]),
+            FILE_SIZES_KEY => file_sizes
+        }).encode()
+    }
+
+    /// InfoHash of the torrent the resume data is for.
+    pub fn info_hash(&self) -> InfoHash {
+        self.info_hash
+    }
+
+    /// Indices of the pieces that were good, in ascending order.
+    pub fn good_pieces(&self) -> &[u64] {
+        &self.good_pieces
+    }
+
+    /// Sizes of the files in the torrent when the resume data was exported.
+    pub fn file_sizes(&self) -> &[u64] {
+        &self.file_sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResumeData;
+    use error::TorrentErrorKind;
+
+    use bip_util::bt::{self, InfoHash};
+
+    #[test]
+    fn positive_resume_data_round_trip() {
+        let resume_data = ResumeData::new(InfoHash::from([1u8; bt::INFO_HASH_LEN]), vec![9, 0, 3, 8], vec![1024, 0]);
+
+        let restored = ResumeData::from_bytes(&resume_data.to_bytes()).unwrap();
+
+        assert_eq!(resume_data, restored);
+        assert_eq!(&[0, 3, 8, 9][..], restored.good_pieces());
+    }
+
+    #[test]
+    fn positive_resume_data_packs_bitfield() {
+        let resume_data = ResumeData::new(InfoHash::from([0u8; bt::INFO_HASH_LEN]), vec![0, 9], Vec::new());
+
+        let mut expected = b"d10:file sizesle9:info hash20:".to_vec();
+        expected.extend_from_slice(&[0u8; bt::INFO_HASH_LEN]);
+        expected.extend_from_slice(b"6:pieces2:\x80\x40e");
+
+        assert_eq!(expected, resume_data.to_bytes());
+    }
+
+    #[test]
+    fn negative_resume_data_invalid_info_hash() {
+        let error = ResumeData::from_bytes(b"d10:file sizesle9:info hash1:a6:pieces0:e").unwrap_err();
+
+        match error.kind() {
+            &TorrentErrorKind::InvalidResumeData{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+}
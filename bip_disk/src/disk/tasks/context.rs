@@ -1,19 +1,34 @@
-use std::sync::{Arc, RwLock, Mutex};
+use std::sync::{Arc, RwLock, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::HashMap;
 
-use disk::ODiskMessage;
-use disk::tasks::helpers::piece_checker::PieceCheckerState;
+use disk::{AllocationMode, SyncMode, ODiskMessage, TorrentEvent};
+use disk::tasks::helpers::piece_checker::{CancelToken, PieceCheckerState};
+use memory::allocator::PooledBlockAllocator;
+use memory::block::Block;
 
 use bip_metainfo::Metainfo;
 use bip_util::bt::InfoHash;
-use futures::sync::mpsc::Sender;
+use futures::sync::mpsc::{self, Sender, UnboundedSender, UnboundedReceiver};
 use futures::sink::Sink;
 use futures::sink::Wait;
 
 pub struct DiskManagerContext<F> {
     torrents:    Arc<RwLock<HashMap<InfoHash, Mutex<MetainfoState>>>>,
     out:         Sender<ODiskMessage>,
-    fs:          Arc<F>
+    fs:          Arc<F>,
+    allocator:   Arc<PooledBlockAllocator>,
+    threads:     usize,
+    progress:    bool,
+    alloc_mode:  AllocationMode,
+    sync_mode:   SyncMode,
+    unsynced:    Arc<Mutex<HashMap<InfoHash, Vec<u64>>>>,
+    checks:      Arc<Mutex<HashMap<InfoHash, CancelToken>>>,
+    events:      Arc<Mutex<Vec<UnboundedSender<TorrentEvent>>>>,
+    coalesce:    Arc<AtomicBool>,
+    writes:      Arc<Mutex<Vec<Block>>>,
+    work:        Arc<(Mutex<usize>, Condvar)>,
+    shutdown:    Arc<AtomicBool>
 }
 
 pub struct MetainfoState {
@@ -28,8 +43,15 @@ impl MetainfoState {
 }
 
 impl<F> DiskManagerContext<F> {
-    pub fn new(out: Sender<ODiskMessage>, fs: F) -> DiskManagerContext<F> {
-        DiskManagerContext{ torrents: Arc::new(RwLock::new(HashMap::new())), out: out, fs: Arc::new(fs) }
+    pub fn new(out: Sender<ODiskMessage>, fs: F, check_threads: usize, check_progress: bool,
+               alloc_mode: AllocationMode, sync_mode: SyncMode) -> DiskManagerContext<F> {
+        DiskManagerContext{ torrents: Arc::new(RwLock::new(HashMap::new())), out: out, fs: Arc::new(fs),
+                            allocator: Arc::new(PooledBlockAllocator::new()), threads: check_threads,
+                            progress: check_progress, alloc_mode: alloc_mode, sync_mode: sync_mode,
+                            unsynced: Arc::new(Mutex::new(HashMap::new())), coalesce: Arc::new(AtomicBool::new(false)),
+                            checks: Arc::new(Mutex::new(HashMap::new())), events: Arc::new(Mutex::new(Vec::new())),
+                            writes: Arc::new(Mutex::new(Vec::new())),
+                            work: Arc::new((Mutex::new(0), Condvar::new())), shutdown: Arc::new(AtomicBool::new(false)) }
     }
 
     pub fn blocking_sender(&self) -> Wait<Sender<ODiskMessage>> {
@@ -40,6 +62,154 @@ impl<F> DiskManagerContext<F> {
         &self.fs
     }
 
+    pub fn allocator(&self) -> &PooledBlockAllocator {
+        &self.allocator
+    }
+
+    pub fn check_threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn check_progress(&self) -> bool {
+        self.progress
+    }
+
+    pub fn allocation_mode(&self) -> AllocationMode {
+        self.alloc_mode
+    }
+
+    /// Record the given pieces as good but not yet synced, returning the pieces that should be synced now.
+    pub fn add_unsynced_pieces(&self, hash: InfoHash, pieces: &[u64]) -> Vec<u64> {
+        let sync_threshold = match self.sync_mode {
+            SyncMode::Never             => return Vec::new(),
+            SyncMode::Piece             => 1,
+            SyncMode::Periodic(pieces)  => pieces
+        };
+
+        let mut lock_unsynced = self.unsynced.lock()
+            .expect("bip_disk: DiskManagerContext::add_unsynced_pieces Failed To Lock Unsynced");
+        let unsynced = lock_unsynced.entry(hash).or_insert_with(Vec::new);
+
+        unsynced.extend_from_slice(pieces);
+        if !unsynced.is_empty() && unsynced.len() >= sync_threshold {
+            unsynced.drain(..).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Subscribe to all `TorrentEvent`s published after this call.
+    pub fn subscribe(&self) -> UnboundedReceiver<TorrentEvent> {
+        let (send, recv) = mpsc::unbounded();
+
+        self.events.lock()
+            .expect("bip_disk: DiskManagerContext::subscribe Failed To Lock Events")
+            .push(send);
+
+        recv
+    }
+
+    /// Publish the given event to every subscriber, dropping subscribers that have gone away.
+    pub fn publish_event(&self, event: TorrentEvent) {
+        let mut lock_events = self.events.lock()
+            .expect("bip_disk: DiskManagerContext::publish_event Failed To Lock Events");
+
+        lock_events.retain(|send| send.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Start tracking a check for the given torrent, returning the token used to cancel it.
+    ///
+    /// Starting a check for a torrent that is already being checked shares the existing token.
+    pub fn start_check(&self, hash: InfoHash) -> CancelToken {
+        self.checks.lock()
+            .expect("bip_disk: DiskManagerContext::start_check Failed To Lock Checks")
+            .entry(hash)
+            .or_insert_with(CancelToken::new)
+            .clone()
+    }
+
+    pub fn finish_check(&self, hash: InfoHash) {
+        self.checks.lock()
+            .expect("bip_disk: DiskManagerContext::finish_check Failed To Lock Checks")
+            .remove(&hash);
+    }
+
+    /// Cancel the check for the given torrent, returning false if no check was being tracked.
+    pub fn cancel_check(&self, hash: InfoHash) -> bool {
+        let lock_checks = self.checks.lock()
+            .expect("bip_disk: DiskManagerContext::cancel_check Failed To Lock Checks");
+
+        lock_checks.get(&hash)
+            .map(|cancel| cancel.cancel())
+            .is_some()
+    }
+
+    pub fn set_write_coalescing(&self, enabled: bool) {
+        self.coalesce.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn write_coalescing(&self) -> bool {
+        self.coalesce.load(Ordering::SeqCst)
+    }
+
+    /// Queue the given block to be written with the next batch of pending writes.
+    pub fn queue_write(&self, block: Block) {
+        self.writes.lock()
+            .expect("bip_disk: DiskManagerContext::queue_write Failed To Lock Writes")
+            .push(block);
+    }
+
+    /// Take all of the blocks waiting to be written.
+    ///
+    /// May be empty if another worker has already taken the batch.
+    pub fn take_queued_writes(&self) -> Vec<Block> {
+        let mut lock_writes = self.writes.lock()
+            .expect("bip_disk: DiskManagerContext::take_queued_writes Failed To Lock Writes");
+
+        lock_writes.drain(..).collect()
+    }
+
+    pub fn start_work(&self) {
+        let mut lock_work = self.work.0.lock()
+            .expect("bip_disk: DiskManagerContext::start_work Failed To Lock Work");
+
+        *lock_work += 1;
+    }
+
+    pub fn finish_work(&self) {
+        let mut lock_work = self.work.0.lock()
+            .expect("bip_disk: DiskManagerContext::finish_work Failed To Lock Work");
+
+        *lock_work -= 1;
+        self.work.1.notify_all();
+    }
+
+    /// Block until the only work left is the work that the caller is running.
+    pub fn wait_for_other_work(&self) {
+        let mut lock_work = self.work.0.lock()
+            .expect("bip_disk: DiskManagerContext::wait_for_other_work Failed To Lock Work");
+
+        while *lock_work > 1 {
+            lock_work = self.work.1.wait(lock_work)
+                .expect("bip_disk: DiskManagerContext::wait_for_other_work Failed To Wait On Work");
+        }
+    }
+
+    pub fn start_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    pub fn torrent_hashes(&self) -> Vec<InfoHash> {
+        let read_torrents = self.torrents.read()
+            .expect("bip_disk: DiskManagerContext::torrent_hashes Failed To Read Torrent");
+
+        read_torrents.keys().cloned().collect()
+    }
+
     pub fn insert_torrent(&self, file: Metainfo, state: PieceCheckerState) -> bool {
         let mut write_torrents = self.torrents.write()
             .expect("bip_disk: DiskManagerContext::insert_torrents Failed To Write Torrent");
@@ -77,6 +247,10 @@ impl<F> DiskManagerContext<F> {
         let mut write_torrents = self.torrents.write()
             .expect("bip_disk: DiskManagerContext::remove_torrent Failed To Write Torrent");
 
+        self.unsynced.lock()
+            .expect("bip_disk: DiskManagerContext::remove_torrent Failed To Lock Unsynced")
+            .remove(&hash);
+
         write_torrents.remove(&hash)
             .map(|_| true)
             .unwrap_or(false)
@@ -85,6 +259,11 @@ impl<F> DiskManagerContext<F> {
 
 impl<F> Clone for DiskManagerContext<F> {
     fn clone(&self) -> DiskManagerContext<F> {
-        DiskManagerContext{ torrents: self.torrents.clone(), out: self.out.clone(), fs: self.fs.clone() }
+        DiskManagerContext{ torrents: self.torrents.clone(), out: self.out.clone(), fs: self.fs.clone(),
+                            allocator: self.allocator.clone(), threads: self.threads,
+                            progress: self.progress, alloc_mode: self.alloc_mode, sync_mode: self.sync_mode,
+                            unsynced: self.unsynced.clone(), checks: self.checks.clone(), coalesce: self.coalesce.clone(),
+                            events: self.events.clone(), writes: self.writes.clone(), work: self.work.clone(),
+                            shutdown: self.shutdown.clone() }
     }
 }
\ No newline at end of file
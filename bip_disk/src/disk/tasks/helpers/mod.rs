@@ -1,13 +1,37 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use bip_metainfo::File;
 
+use error::{TorrentError, TorrentErrorKind, TorrentResult};
+
 pub mod piece_accessor;
 pub mod piece_checker;
+pub mod piece_hasher;
 
 pub fn build_path(parent_directory: Option<&Path>, file: &File) -> PathBuf {
     match parent_directory {
         Some(dir) => PathBuf::from(dir).join(file.path()),
         None      => file.path().to_owned()
     }
-}
\ No newline at end of file
+}
+
+/// Validates that the path built for the given file stays within the download directory.
+///
+/// Rejects empty paths, as well as paths containing absolute, parent, or current directory components.
+pub fn validate_path(parent_directory: Option<&Path>, file: &File) -> TorrentResult<()> {
+    let paths = [parent_directory.unwrap_or(Path::new("")), file.path()];
+    let file_path_empty = file.path().components().next().is_none();
+
+    let path_escapes = paths.iter()
+        .flat_map(|path| path.components())
+        .any(|component| match component {
+            Component::Normal(_) => false,
+            _                    => true
+        });
+
+    if file_path_empty || path_escapes {
+        Err(TorrentError::from_kind(TorrentErrorKind::InvalidFilePath{ file_path: build_path(parent_directory, file) }))
+    } else {
+        Ok(())
+    }
+}
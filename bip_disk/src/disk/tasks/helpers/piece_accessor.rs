@@ -4,6 +4,7 @@ use std::io;
 use disk::fs::{FileSystem};
 use memory::block::BlockMetadata;
 use disk::tasks::helpers;
+use disk::tasks::helpers::piece_hasher::PieceDigest;
 
 use bip_metainfo::{Info};
 
@@ -20,24 +21,80 @@ impl<'a, F> PieceAccessor<'a, F> where F: FileSystem {
         }
     }
 
+    /// Read the block described by the message into the buffer, stitching together regions from each file it spans.
     pub fn read_piece(&self, piece_buffer: &mut [u8], message: &BlockMetadata) -> io::Result<()> {
         self.run_with_file_regions(message, |mut file, offset, begin, end| {
-            let bytes_read = try!(self.fs.read_file(&mut file, offset, &mut piece_buffer[begin..end]));
-            assert_eq!(bytes_read, end - begin);
+            // File system may return less than we asked for, keep reading until the region is filled
+            let mut bytes_read = 0;
+            while begin + bytes_read < end {
+                let next_bytes_read = try!(self.fs.read_file(&mut file, offset + bytes_read as u64,
+                                                             &mut piece_buffer[(begin + bytes_read)..end]));
+
+                if next_bytes_read == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Failed To Read Whole Region Of File"))
+                }
+                bytes_read += next_bytes_read;
+            }
 
             Ok(())
         })
     }
 
+    /// Read the block described by the message straight in to the digest, a chunk at a time.
+    ///
+    /// Only the chunk buffer is read in to, so a buffer for the whole block is never needed.
+    pub fn read_piece_into_hasher(&self, chunk_buffer: &mut [u8], message: &BlockMetadata, digest: &mut PieceDigest) -> io::Result<()> {
+        if chunk_buffer.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Chunk Buffer Is Empty"))
+        }
+
+        self.run_with_file_regions(message, |mut file, offset, begin, end| {
+            let mut bytes_read = 0;
+            while begin + bytes_read < end {
+                let chunk_length = cmp::min(chunk_buffer.len(), end - begin - bytes_read);
+                let next_bytes_read = try!(self.fs.read_file(&mut file, offset + bytes_read as u64,
+                                                             &mut chunk_buffer[..chunk_length]));
+
+                if next_bytes_read == 0 {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Failed To Read Whole Region Of File"))
+                }
+                digest.update(&chunk_buffer[..next_bytes_read]);
+                bytes_read += next_bytes_read;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write the block described by the message from the buffer, splitting it up into regions for each file it spans.
     pub fn write_piece(&self, piece_buffer: &[u8], message: &BlockMetadata) -> io::Result<()> {
         self.run_with_file_regions(message, |mut file, offset, begin, end| {
-            let bytes_written = try!(self.fs.write_file(&mut file, offset, &piece_buffer[begin..end]));
-            assert_eq!(bytes_written, end - begin);
+            // File system may write less than we asked for, keep writing until the region is written
+            let mut bytes_written = 0;
+            while begin + bytes_written < end {
+                let next_bytes_written = try!(self.fs.write_file(&mut file, offset + bytes_written as u64,
+                                                                 &piece_buffer[(begin + bytes_written)..end]));
+
+                if next_bytes_written == 0 {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "Failed To Write Whole Region Of File"))
+                }
+                bytes_written += next_bytes_written;
+            }
 
             Ok(())
         })
     }
 
+    /// Sync the data of every file that the given piece spans.
+    pub fn sync_piece(&self, piece_index: u64) -> io::Result<()> {
+        let piece_length = self.info_dict.piece_length() as usize;
+        let message = BlockMetadata::with_default_hash(piece_index, 0, piece_length);
+
+        self.run_with_file_regions(&message, |file, _, _, _| {
+            self.fs.sync_file(&file)
+        })
+    }
+
     /// Run the given closure with the file, the file offset, and the read/write buffer stard (inclusive) and end (exclusive) indices.
     /// TODO: We do not detect when/if the file size changes after the initial file size check, so the returned number of 
     fn run_with_file_regions<C>(&self, message: &BlockMetadata, mut callback: C) -> io::Result<()>
@@ -73,4 +130,171 @@ impl<'a, F> PieceAccessor<'a, F> where F: FileSystem {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::io;
+    use std::path::Path;
+
+    use disk::fs::FileSystem;
+    use disk::fs::memory::{InMemoryFile, InMemoryFileSystem};
+    use disk::tasks::helpers::piece_accessor::PieceAccessor;
+    use disk::tasks::helpers::piece_hasher::{PieceHasher, Sha1PieceHasher};
+    use memory::block::BlockMetadata;
+
+    use bip_metainfo::Info;
+
+    /// File system which reads and writes at most a single byte at a time.
+    struct TrickleFileSystem {
+        inner: InMemoryFileSystem
+    }
+
+    impl FileSystem for TrickleFileSystem {
+        type File = InMemoryFile;
+
+        fn open_file<P>(&self, path: P) -> io::Result<InMemoryFile>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.open_file(path)
+        }
+
+        fn sync_path<P>(&self, path: P) -> io::Result<()>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.sync_path(path)
+        }
+
+        fn sync_file(&self, file: &InMemoryFile) -> io::Result<()> {
+            self.inner.sync_file(file)
+        }
+
+        fn file_size(&self, file: &InMemoryFile) -> io::Result<u64> {
+            self.inner.file_size(file)
+        }
+
+        fn read_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+            let trickle_length = cmp::min(1, buffer.len());
+
+            self.inner.read_file(file, offset, &mut buffer[..trickle_length])
+        }
+
+        fn write_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+            let trickle_length = cmp::min(1, buffer.len());
+
+            self.inner.write_file(file, offset, &buffer[..trickle_length])
+        }
+
+        fn available_space(&self, path: &Path) -> io::Result<u64> {
+            self.inner.available_space(path)
+        }
+    }
+
+    /// Build an info dictionary with three files, of lengths 6, 6, and 4, with a piece length of 4.
+    ///
+    /// Returns the info dictionary, as well as the file system containing the file data (bytes 0 to 15).
+    fn three_file_info() -> (Info, InMemoryFileSystem) {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(b"d5:filesl");
+        bytes.extend_from_slice(b"d6:lengthi6e4:pathl1:aee");
+        bytes.extend_from_slice(b"d6:lengthi6e4:pathl1:bee");
+        bytes.extend_from_slice(b"d6:lengthi4e4:pathl1:cee");
+        bytes.extend_from_slice(b"e4:name3:dir12:piece lengthi4e6:pieces80:");
+        bytes.extend_from_slice(&[0u8; 80]);
+        bytes.extend_from_slice(b"e");
+
+        let fs = InMemoryFileSystem::new();
+        fs.run_with_lock(|files| {
+            files.insert(Path::new("dir/a").to_path_buf(), vec![0, 1, 2, 3, 4, 5]);
+            files.insert(Path::new("dir/b").to_path_buf(), vec![6, 7, 8, 9, 10, 11]);
+            files.insert(Path::new("dir/c").to_path_buf(), vec![12, 13, 14, 15]);
+        });
+
+        (Info::from_bytes(bytes).unwrap(), fs)
+    }
+
+    #[test]
+    fn positive_read_piece_spans_file_boundary() {
+        let (info, fs) = three_file_info();
+        let accessor = PieceAccessor::new(&fs, &info);
+
+        let mut buffer = [0u8; 4];
+        accessor.read_piece(&mut buffer, &BlockMetadata::with_default_hash(1, 0, 4)).unwrap();
+
+        assert_eq!([4, 5, 6, 7], buffer);
+    }
+
+    #[test]
+    fn positive_read_piece_block_spans_file_boundary() {
+        let (info, fs) = three_file_info();
+        let accessor = PieceAccessor::new(&fs, &info);
+
+        let mut buffer = [0u8; 3];
+        accessor.read_piece(&mut buffer, &BlockMetadata::with_default_hash(2, 1, 3)).unwrap();
+
+        assert_eq!([9, 10, 11], buffer);
+    }
+
+    #[test]
+    fn positive_read_piece_short_reads() {
+        let (info, fs) = three_file_info();
+        let trickle_fs = TrickleFileSystem{ inner: fs };
+        let accessor = PieceAccessor::new(&trickle_fs, &info);
+
+        let mut buffer = [0u8; 4];
+        accessor.read_piece(&mut buffer, &BlockMetadata::with_default_hash(1, 0, 4)).unwrap();
+
+        assert_eq!([4, 5, 6, 7], buffer);
+    }
+
+    #[test]
+    fn positive_read_piece_into_hasher_matches_buffered() {
+        let (info, fs) = three_file_info();
+        let trickle_fs = TrickleFileSystem{ inner: fs.clone() };
+
+        // Block spans all three files, and is fed in chunks that do not line up with the files
+        let message = BlockMetadata::with_default_hash(1, 0, 12);
+        let mut buffer = [0u8; 12];
+        PieceAccessor::new(&fs, &info).read_piece(&mut buffer, &message).unwrap();
+        let buffered_digest = Sha1PieceHasher.hash_piece(&buffer);
+
+        for &chunk_length in [1, 5, 64].iter() {
+            let mut chunk_buffer = vec![0u8; chunk_length];
+            let mut digest = Sha1PieceHasher.start_digest();
+            PieceAccessor::new(&fs, &info).read_piece_into_hasher(&mut chunk_buffer, &message, &mut *digest).unwrap();
+
+            assert_eq!(buffered_digest, digest.finish());
+        }
+
+        let mut digest = Sha1PieceHasher.start_digest();
+        PieceAccessor::new(&trickle_fs, &info).read_piece_into_hasher(&mut [0u8; 4], &message, &mut *digest).unwrap();
+        assert_eq!(buffered_digest, digest.finish());
+    }
+
+    #[test]
+    fn positive_write_piece_short_writes() {
+        let (info, fs) = three_file_info();
+        let trickle_fs = TrickleFileSystem{ inner: fs.clone() };
+        let accessor = PieceAccessor::new(&trickle_fs, &info);
+
+        accessor.write_piece(&[20, 21, 22, 23], &BlockMetadata::with_default_hash(1, 0, 4)).unwrap();
+
+        let (file_a, file_b) = fs.run_with_lock(|files| {
+            (files[Path::new("dir/a")].clone(), files[Path::new("dir/b")].clone())
+        });
+        assert_eq!(vec![0, 1, 2, 3, 20, 21], file_a);
+        assert_eq!(vec![22, 23, 8, 9, 10, 11], file_b);
+    }
+
+    #[test]
+    fn negative_read_piece_truncated_file() {
+        let (info, fs) = three_file_info();
+        fs.run_with_lock(|files| files.insert(Path::new("dir/b").to_path_buf(), vec![6]));
+        let accessor = PieceAccessor::new(&fs, &info);
+
+        let mut buffer = [0u8; 4];
+        let error = accessor.read_piece(&mut buffer, &BlockMetadata::with_default_hash(1, 0, 4)).unwrap_err();
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    }
+}
@@ -1,34 +1,122 @@
 use std::collections::{HashMap, HashSet};
 use std::cmp;
 use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use disk::tasks::helpers::piece_accessor::PieceAccessor;
+use disk::tasks::helpers::piece_hasher::PieceHasher;
+use disk::{AllocationMode, FilePriority};
 use disk::fs::{FileSystem};
+use disk::resume::ResumeData;
+use memory::allocator::BlockAllocator;
 use memory::block::BlockMetadata;
 use error::{TorrentResult, TorrentError, TorrentErrorKind};
 use disk::tasks::helpers;
 
 use bip_metainfo::{Info};
-use bip_util::bt::InfoHash;
+use crossbeam;
+
+const DEFAULT_NUM_THREADS: usize = 1;
+const ALLOCATION_CHUNK_SIZE: usize = 64 * 1024;
+const CHECK_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Raw OS error codes indicating that the disk is full.
+#[cfg(unix)]
+const OUT_OF_SPACE_CODES: &'static [i32] = &[28]; // ENOSPC
+#[cfg(windows)]
+const OUT_OF_SPACE_CODES: &'static [i32] = &[39, 112]; // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+#[cfg(not(any(unix, windows)))]
+const OUT_OF_SPACE_CODES: &'static [i32] = &[];
+
+/// Token used to cancel an in progress check, shared between all of its clones.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>
+}
+
+impl CancelToken {
+    /// Create a new CancelToken that has not been cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancel any check using this token, or any of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether or not the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+// ----------------------------------------------------------------------------//
 
 /// Calculates hashes on existing files within the file system given and reports good/bad pieces.
 pub struct PieceChecker<'a, F> {
     fs:            F,
     info_dict:     &'a Info,
-    checker_state: &'a mut PieceCheckerState
+    checker_state: &'a mut PieceCheckerState,
+    allocator:     &'a BlockAllocator,
+    hasher:        &'a PieceHasher,
+    num_threads:   usize,
+    progress:      Option<&'a mut (FnMut(u64, u64) + Send)>,
+    priorities:    &'a [FilePriority],
+    cancel:        Option<&'a CancelToken>
 }
 
-impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
+impl<'a, F> PieceChecker<'a, F> where F: FileSystem + Sync + 'a {
     /// Create the initial PieceCheckerState for the PieceChecker.
-    pub fn init_state(fs: F, info_dict: &'a Info) -> TorrentResult<PieceCheckerState> {
+    ///
+    /// Files that do not exist yet will be allocated using the given allocation mode. Existing pieces
+    /// will be checked using at most `num_threads` threads, and the optional progress callback will be
+    /// invoked after each existing piece is checked.
+    #[cfg(test)]
+    pub fn init_state(fs: F, info_dict: &'a Info, allocator: &'a BlockAllocator, hasher: &'a PieceHasher,
+                      alloc_mode: AllocationMode, num_threads: usize,
+                      opt_progress: Option<&'a mut (FnMut(u64, u64) + Send)>) -> TorrentResult<PieceCheckerState> {
+        PieceChecker::init_state_with(fs, info_dict, allocator, hasher, alloc_mode, num_threads, opt_progress, None, &[], None)
+    }
+
+    /// Create the initial PieceCheckerState for the PieceChecker, with optional resume data and file priorities.
+    ///
+    /// Good pieces from the resume data are reported as good without being checked again, unless the size of any
+    /// file has changed since the resume data was exported, in which case every piece is checked. Resume data for
+    /// a different torrent is rejected with an `InvalidResumeData` error.
+    ///
+    /// Files with a priority of `FilePriority::Skip` are not allocated, and pieces spanning a skipped file that was
+    /// not already allocated are not checked. Files without a priority are treated as `FilePriority::Normal`.
+    ///
+    /// If the optional cancel token is cancelled, the check stops before the next file is allocated or the
+    /// next piece is checked, with a `Cancelled` error. Files that were already allocated are left in place.
+    pub fn init_state_with(fs: F, info_dict: &'a Info, allocator: &'a BlockAllocator, hasher: &'a PieceHasher,
+                           alloc_mode: AllocationMode, num_threads: usize,
+                           opt_progress: Option<&'a mut (FnMut(u64, u64) + Send)>,
+                           opt_resume: Option<&ResumeData>,
+                           file_priorities: &'a [FilePriority],
+                           opt_cancel: Option<&'a CancelToken>) -> TorrentResult<PieceCheckerState> {
         let total_blocks = info_dict.pieces().count();
         let last_piece_size = last_piece_size(info_dict);
 
         let mut checker_state = PieceCheckerState::new(total_blocks, last_piece_size);
         {
-            let mut piece_checker = PieceChecker::with_state(fs, info_dict, &mut checker_state);
-            
-            try!(piece_checker.validate_files_sizes());
+            let mut piece_checker = PieceChecker::with_state(fs, info_dict, &mut checker_state, allocator, hasher)
+                .with_num_threads(num_threads)
+                .with_file_priorities(file_priorities);
+            if let Some(progress) = opt_progress {
+                piece_checker = piece_checker.with_progress(progress);
+            }
+            if let Some(cancel) = opt_cancel {
+                piece_checker = piece_checker.with_cancel_token(cancel);
+            }
+
+            if let Some(resume_data) = opt_resume {
+                try!(piece_checker.restore_good_pieces(resume_data));
+            }
+            try!(piece_checker.validate_files_sizes(alloc_mode));
             try!(piece_checker.fill_checker_state());
             try!(piece_checker.calculate_diff());
         }
@@ -37,39 +125,96 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
     }
 
     /// Create a new PieceChecker with the given state.
-    pub fn with_state(fs: F, info_dict: &'a Info, checker_state: &'a mut PieceCheckerState) -> PieceChecker<'a, F> {
+    ///
+    /// Buffers used to read in whole pieces are pulled from, and given back to, the given allocator.
+    /// Whole pieces are checked against the info dictionary using the digest calculated by the given hasher.
+    pub fn with_state(fs: F, info_dict: &'a Info, checker_state: &'a mut PieceCheckerState,
+                      allocator: &'a BlockAllocator, hasher: &'a PieceHasher) -> PieceChecker<'a, F> {
         PieceChecker {
             fs:            fs,
             info_dict:     info_dict,
-            checker_state: checker_state
+            checker_state: checker_state,
+            allocator:     allocator,
+            hasher:        hasher,
+            num_threads:   DEFAULT_NUM_THREADS,
+            progress:      None,
+            priorities:    &[],
+            cancel:        None
         }
     }
 
+    /// Check whole pieces using at most `num_threads` threads.
+    ///
+    /// Defaults to checking all pieces on the calling thread.
+    pub fn with_num_threads(mut self, num_threads: usize) -> PieceChecker<'a, F> {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Invoke the given callback with `(pieces_done, pieces_total)` after each whole piece is checked.
+    ///
+    /// The callback is invoked exactly once for each whole piece, whether it was good or bad.
+    pub fn with_progress(mut self, progress: &'a mut (FnMut(u64, u64) + Send)) -> PieceChecker<'a, F> {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Use the given priorities for the files in the info dictionary, in the same order.
+    ///
+    /// Defaults to treating all files as `FilePriority::Normal`.
+    pub fn with_file_priorities(mut self, priorities: &'a [FilePriority]) -> PieceChecker<'a, F> {
+        self.priorities = priorities;
+        self
+    }
+
+    /// Stop checking, with a `Cancelled` error, once the given token is cancelled.
+    ///
+    /// The token is polled between each whole piece that is checked.
+    pub fn with_cancel_token(mut self, cancel: &'a CancelToken) -> PieceChecker<'a, F> {
+        self.cancel = Some(cancel);
+        self
+    }
+
     /// Calculate the diff of old to new good/bad pieces and store them in the piece checker state
     /// to be retrieved by the caller.
-    pub fn calculate_diff(self) -> io::Result<()> {
-        let piece_length = self.info_dict.piece_length() as u64;
-        // TODO: Use Block Allocator
-        let mut piece_buffer = vec![0u8; piece_length as usize];
+    ///
+    /// Whole pieces are split up evenly between the configured number of threads.
+    pub fn calculate_diff(self) -> TorrentResult<()> {
+        let piece_length = self.info_dict.piece_length() as usize;
 
+        let fs = &self.fs;
         let info_dict = self.info_dict;
-        let piece_accessor = PieceAccessor::new(&self.fs, self.info_dict);
-        
-        try!(self.checker_state.run_with_whole_pieces(piece_length as usize, |message| {
-            try!(piece_accessor.read_piece(&mut piece_buffer[..message.block_length()], message));
-            
-            let calculated_hash = InfoHash::from_bytes(&piece_buffer[..message.block_length()]);
-            let expected_hash = InfoHash::from_hash(info_dict
-                .pieces()
-                .skip(message.piece_index() as usize)
-                .next()
-                .expect("bip_peer: Piece Checker Failed To Retrieve Expected Hash"))
-                .expect("bip_peer: Wrong Length Of Expected Hash Received");
-                
-            Ok(calculated_hash == expected_hash)
-        }));
+        let allocator = self.allocator;
+        let hasher = self.hasher;
+        let num_threads = self.num_threads;
+        let opt_progress = self.progress;
+        let opt_cancel = self.cancel;
+
+        self.checker_state.run_with_whole_pieces(piece_length, |whole_pieces| {
+            let progress = CheckProgress::new(whole_pieces.len() as u64, opt_progress);
+            let progress = &progress;
+
+            let num_threads = cmp::min(num_threads, whole_pieces.len());
+            if num_threads <= 1 {
+                return check_pieces(fs, info_dict, allocator, hasher, progress, opt_cancel, whole_pieces)
+            }
 
-        Ok(())
+            let chunk_size = (whole_pieces.len() + num_threads - 1) / num_threads;
+            let chunk_results: Vec<TorrentResult<Vec<bool>>> = crossbeam::scope(|scope| {
+                let handles: Vec<_> = whole_pieces.chunks(chunk_size)
+                    .map(|chunk| scope.spawn(move || check_pieces(fs, info_dict, allocator, hasher, progress, opt_cancel, chunk)))
+                    .collect();
+
+                handles.into_iter().map(|handle| handle.join()).collect()
+            });
+
+            let mut piece_results = Vec::with_capacity(whole_pieces.len());
+            for chunk_result in chunk_results {
+                piece_results.extend(try!(chunk_result));
+            }
+
+            Ok(piece_results)
+        })
     }
 
     /// Fill the PieceCheckerState with all piece messages for each file in our info dictionary.
@@ -82,28 +227,107 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
 
         let full_pieces = total_bytes / piece_length;
         let last_piece_size = last_piece_size(self.info_dict);
+        let unchecked_pieces = try!(self.unallocated_skipped_pieces());
 
         for piece_index in 0..full_pieces {
-            self.checker_state.add_pending_block(BlockMetadata::with_default_hash(piece_index, 0, piece_length as usize));
+            if !self.checker_state.is_good(piece_index) && !unchecked_pieces.contains(&piece_index) {
+                self.checker_state.add_pending_block(BlockMetadata::with_default_hash(piece_index, 0, piece_length as usize));
+            }
         }
         
-        if last_piece_size != 0 {
+        if last_piece_size != 0 && !self.checker_state.is_good(full_pieces) && !unchecked_pieces.contains(&full_pieces) {
             self.checker_state.add_pending_block(BlockMetadata::with_default_hash(full_pieces, 0, last_piece_size as usize));
         }
 
         Ok(())
     }
 
+    /// Whether the file at the given index in the info dictionary should be skipped.
+    fn is_skipped(&self, file_index: usize) -> bool {
+        self.priorities.get(file_index) == Some(&FilePriority::Skip)
+    }
+
+    /// Indices of all pieces spanning a skipped file that has not been allocated, which cannot be read in whole.
+    fn unallocated_skipped_pieces(&self) -> io::Result<HashSet<u64>> {
+        let piece_length = self.info_dict.piece_length() as u64;
+
+        let mut pieces = HashSet::new();
+        let mut file_start = 0;
+        for (file_index, file) in self.info_dict.files().enumerate() {
+            let file_length = file.length() as u64;
+
+            if self.is_skipped(file_index) && file_length != 0 {
+                let file_path = helpers::build_path(self.info_dict.directory(), file);
+                let actual_size = try!(self.fs.open_file(file_path).and_then(|file| self.fs.file_size(&file)));
+
+                if actual_size != file_length {
+                    pieces.extend(file_start / piece_length..(file_start + file_length - 1) / piece_length + 1);
+                }
+            }
+            file_start += file_length;
+        }
+
+        Ok(pieces)
+    }
+
+    /// Mark the good pieces in the resume data as good, if the files have not changed size since it was exported.
+    ///
+    /// This must be done before any files are allocated, otherwise a file that was removed would look untouched.
+    fn restore_good_pieces(&mut self, resume_data: &ResumeData) -> TorrentResult<()> {
+        if resume_data.info_hash() != self.info_dict.info_hash() {
+            return Err(TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{
+                details: format!("InfoHash {:?} Does Not Match The Torrent", resume_data.info_hash())
+            }))
+        }
+
+        let total_pieces = self.info_dict.pieces().count() as u64;
+        if resume_data.good_pieces().iter().any(|&index| index >= total_pieces) {
+            return Err(TorrentError::from_kind(TorrentErrorKind::InvalidResumeData{
+                details: format!("Good Piece Is Past The Last Piece {}", total_pieces.saturating_sub(1))
+            }))
+        }
+
+        for file in self.info_dict.files() {
+            try!(helpers::validate_path(self.info_dict.directory(), file));
+        }
+
+        let mut file_sizes = Vec::new();
+        for file in self.info_dict.files() {
+            let file_path = helpers::build_path(self.info_dict.directory(), file);
+
+            file_sizes.push(try!(self.fs.open_file(file_path).and_then(|file| self.fs.file_size(&file))));
+        }
+
+        if &file_sizes[..] == resume_data.file_sizes() {
+            self.checker_state.restore_good_pieces(resume_data.good_pieces());
+        }
+
+        Ok(())
+    }
+
     /// Validates the file sizes for the given torrent file and block allocates them if they do not exist.
     ///
-    /// This function will, if the file does not exist, or exists and is zero size, fill the file with zeroes.
+    /// This function will, if the file does not exist, or exists and is zero size, allocate the file using
+    /// the given allocation mode, unless the file is skipped.
     /// Otherwise, if the file exists and it is of the correct size, it will be left alone. If it is of the wrong
     /// size, an error will be thrown as we do not want to overwrite and existing file that maybe just had the same
     /// name as a file in our dictionary.
-    fn validate_files_sizes(&mut self) -> TorrentResult<()> {
+    ///
+    /// All file paths are validated before any file is opened, so a torrent with a path that would escape the
+    /// download directory is rejected without touching the file system. Available space is checked before any
+    /// file is allocated, so a torrent that will not fit is rejected without half allocating it.
+    fn validate_files_sizes(&mut self, alloc_mode: AllocationMode) -> TorrentResult<()> {
         for file in self.info_dict.files() {
+            try!(helpers::validate_path(self.info_dict.directory(), file));
+        }
+        try!(self.check_available_space());
+
+        for (file_index, file) in self.info_dict.files().enumerate() {
+            try!(check_cancelled(self.cancel, self.info_dict));
+
             let file_path = helpers::build_path(self.info_dict.directory(), file);
             let expected_size = file.length() as u64;
+            let is_skipped = self.is_skipped(file_index);
 
             try!(self.fs.open_file(file_path.clone())
                 .map_err(|err| err.into())
@@ -115,9 +339,10 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
                 let size_matches = actual_size == expected_size;
                 let size_is_zero = actual_size == 0;
 
-                if !size_matches && size_is_zero {
-                    self.fs.write_file(&mut file, expected_size - 1, &[0])
-                        .expect("bip_peer: Failed To Create File When Validating Sizes");
+                if !size_matches && size_is_zero && is_skipped {
+                    // Skipped files are left unallocated until they are wanted
+                } else if !size_matches && size_is_zero {
+                    try!(allocate_file(&self.fs, &mut file, &file_path, expected_size, alloc_mode));
                 } else if !size_matches {
                     return Err(TorrentError::from_kind(TorrentErrorKind::ExistingFileSizeCheck{
                         file_path: file_path,
@@ -132,6 +357,152 @@ impl<'a, F> PieceChecker<'a, F> where F: FileSystem + 'a {
 
         Ok(())
     }
+
+    /// Checks that there is enough space available to allocate all files that do not exist yet.
+    ///
+    /// Files that already exist, correctly sized or not, as well as skipped files, do not count towards the space needed.
+    fn check_available_space(&self) -> TorrentResult<()> {
+        let mut needed = 0;
+
+        for (_, file) in self.info_dict.files().enumerate().filter(|&(file_index, _)| !self.is_skipped(file_index)) {
+            let file_path = helpers::build_path(self.info_dict.directory(), file);
+            let actual_size = try!(self.fs.open_file(file_path)
+                .and_then(|file| self.fs.file_size(&file)));
+
+            if actual_size == 0 {
+                needed += file.length() as u64;
+            }
+        }
+
+        let directory = self.info_dict.directory().unwrap_or(Path::new(""));
+        let available = try!(self.fs.available_space(directory));
+
+        if needed > available {
+            Err(TorrentError::from_kind(TorrentErrorKind::InsufficientSpace{
+                file_path: directory.to_owned(),
+                needed: needed
+            }))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Grow the given empty file to the given size, using the given allocation mode.
+///
+/// If the disk fills up, an `InsufficientSpace` error is returned with the number of bytes left to allocate.
+fn allocate_file<F>(fs: &F, file: &mut F::File, file_path: &Path, size: u64, alloc_mode: AllocationMode)
+    -> TorrentResult<()> where F: FileSystem {
+    match alloc_mode {
+        AllocationMode::Sparse => {
+            fs.write_file(file, size - 1, &[0])
+                .map(|_| ())
+                .map_err(|err| allocation_error(err, file_path, size))
+        },
+        AllocationMode::Full   => {
+            let zeroes = vec![0u8; cmp::min(size, ALLOCATION_CHUNK_SIZE as u64) as usize];
+
+            let mut offset = 0;
+            while offset < size {
+                let write_length = cmp::min(size - offset, zeroes.len() as u64) as usize;
+                let bytes_written = try!(fs.write_file(file, offset, &zeroes[..write_length])
+                    .map_err(|err| allocation_error(err, file_path, size - offset)));
+
+                if bytes_written == 0 {
+                    return Err(TorrentError::from_kind(TorrentErrorKind::InsufficientSpace{
+                        file_path: file_path.to_owned(),
+                        needed: size - offset
+                    }))
+                }
+                offset += bytes_written as u64;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Convert an error from allocating a file, mapping out of space errors to `InsufficientSpace`.
+fn allocation_error(err: io::Error, file_path: &Path, needed: u64) -> TorrentError {
+    let is_out_of_space = err.kind() == io::ErrorKind::WriteZero ||
+        err.raw_os_error().map(|code| OUT_OF_SPACE_CODES.contains(&code)).unwrap_or(false);
+
+    if is_out_of_space {
+        TorrentError::from_kind(TorrentErrorKind::InsufficientSpace{ file_path: file_path.to_owned(), needed: needed })
+    } else {
+        err.into()
+    }
+}
+
+/// Tracks the number of whole pieces checked, shared between all checking threads.
+struct CheckProgress<'a> {
+    pieces_total: u64,
+    state:        Mutex<(u64, Option<&'a mut (FnMut(u64, u64) + Send)>)>
+}
+
+impl<'a> CheckProgress<'a> {
+    fn new(pieces_total: u64, opt_progress: Option<&'a mut (FnMut(u64, u64) + Send)>) -> CheckProgress<'a> {
+        CheckProgress{ pieces_total: pieces_total, state: Mutex::new((0, opt_progress)) }
+    }
+
+    fn piece_checked(&self) {
+        let mut lock_state = self.state.lock()
+            .expect("bip_disk: CheckProgress::piece_checked Failed To Lock State");
+        let (ref mut pieces_done, ref mut opt_progress) = *lock_state;
+
+        *pieces_done += 1;
+        if let Some(ref mut progress) = *opt_progress {
+            progress(*pieces_done, self.pieces_total);
+        }
+    }
+}
+
+/// Return a `Cancelled` error if the given token has been cancelled.
+fn check_cancelled(opt_cancel: Option<&CancelToken>, info_dict: &Info) -> TorrentResult<()> {
+    match opt_cancel {
+        Some(cancel) if cancel.is_cancelled() => Err(TorrentError::from_kind(TorrentErrorKind::Cancelled{ hash: info_dict.info_hash() })),
+        _                                     => Ok(())
+    }
+}
+
+/// Read in and hash each of the given whole pieces, returning whether or not each piece was good.
+///
+/// Stops with a `Cancelled` error before reading in the next piece if the optional token has been cancelled.
+fn check_pieces<F>(fs: &F, info_dict: &Info, allocator: &BlockAllocator, hasher: &PieceHasher, progress: &CheckProgress,
+                   opt_cancel: Option<&CancelToken>, whole_pieces: &[BlockMetadata]) -> TorrentResult<Vec<bool>>
+    where F: FileSystem {
+    // Pieces are streamed in to the hasher, so we only need a buffer large enough for a chunk of a piece
+    let mut chunk_buffer = allocator.allocate(cmp::min(info_dict.piece_length() as usize, CHECK_CHUNK_SIZE));
+    let piece_accessor = PieceAccessor::new(fs, info_dict);
+    // Info in the bip_metainfo version we depend on has no indexed lookup, so collect the hashes once up front
+    let piece_hashes: Vec<&[u8]> = info_dict.pieces().collect();
+
+    let piece_results = whole_pieces.iter()
+        .map(|message| {
+            try!(check_cancelled(opt_cancel, info_dict));
+
+            let mut digest = hasher.start_digest();
+            try!(piece_accessor.read_piece_into_hasher(&mut chunk_buffer, message, &mut *digest));
+
+            let calculated_hash = digest.finish();
+            let expected_hash = *try!(piece_hashes
+                .get(message.piece_index() as usize)
+                .ok_or_else(|| TorrentError::from_kind(TorrentErrorKind::MissingPieceHash{ piece_index: message.piece_index() })));
+            if expected_hash.len() != hasher.digest_length() {
+                return Err(TorrentError::from_kind(TorrentErrorKind::InvalidPieceHashLength{
+                    piece_index: message.piece_index(),
+                    expected_length: hasher.digest_length(),
+                    actual_length: expected_hash.len()
+                }))
+            }
+            progress.piece_checked();
+
+            Ok(&calculated_hash[..] == expected_hash)
+        })
+        .collect();
+    allocator.deallocate(chunk_buffer);
+
+    piece_results
 }
 
 fn last_piece_size(info_dict: &Info) -> usize {
@@ -147,17 +518,20 @@ fn last_piece_size(info_dict: &Info) -> usize {
 pub struct PieceCheckerState {
     new_states:      Vec<PieceState>,
     old_states:      HashSet<PieceState>,
+    good_pieces:     HashSet<u64>,
     pending_blocks:  HashMap<u64, Vec<BlockMetadata>>,
     total_blocks:    usize,
     last_block_size: usize
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum PieceState {
     /// Piece was discovered as good.
     Good(u64),
     /// Piece was discovered as bad.
-    Bad(u64)
+    Bad(u64),
+    /// Piece was discovered to have some, but not all, of its blocks.
+    Partial(u64)
 }
 
 impl PieceCheckerState {
@@ -166,85 +540,134 @@ impl PieceCheckerState {
         PieceCheckerState {
             new_states: Vec::new(),
             old_states: HashSet::new(),
+            good_pieces: HashSet::new(),
             pending_blocks: HashMap::new(),
             total_blocks: total_blocks,
             last_block_size: last_block_size
         }
     }
 
+    /// Mark the given pieces as good without checking them.
+    ///
+    /// The pieces will be passed to the next `run_with_diff` as good pieces.
+    pub fn restore_good_pieces(&mut self, pieces: &[u64]) {
+        for &index in pieces {
+            if self.old_states.insert(PieceState::Good(index)) {
+                self.new_states.push(PieceState::Good(index));
+                self.good_pieces.insert(index);
+            }
+        }
+    }
+
+    /// Whether the given piece has been discovered as good.
+    pub fn is_good(&self, index: u64) -> bool {
+        self.good_pieces.contains(&index)
+    }
+
+    /// Indices of all pieces that have been discovered as good, in ascending order.
+    pub fn good_pieces(&self) -> Vec<u64> {
+        let mut good_pieces: Vec<u64> = self.good_pieces.iter().cloned().collect();
+        good_pieces.sort();
+
+        good_pieces
+    }
+
     /// Add a pending piece block to the current pending blocks.
     pub fn add_pending_block(&mut self, msg: BlockMetadata) {
         self.pending_blocks.entry(msg.piece_index()).or_insert(Vec::new()).push(msg);
     }
     
-    /// Run the given closures against NewGood and NewBad messages. Each of the messages will
-    /// then either be dropped (NewBad) or converted to OldGood (NewGood).
+    /// Run the given closures against NewGood, NewBad, and NewPartial messages. Each of the messages
+    /// will then be converted to OldGood, OldBad, or OldPartial.
+    ///
+    /// A piece is only reported as partial once, until it is discovered as either good or bad.
     pub fn run_with_diff<F>(&mut self, mut callback: F)
         where F: FnMut(&PieceState) {
         for piece_state in self.new_states.drain(..) {
             callback(&piece_state);
 
+            match piece_state {
+                PieceState::Good(index) | PieceState::Bad(index) => { self.old_states.remove(&PieceState::Partial(index)); },
+                PieceState::Partial(_) => ()
+            }
             self.old_states.insert(piece_state);
         }
     }
 
     /// Pass any pieces that have not been identified as OldGood into the callback which determines
-    /// if the piece is good or bad so it can be marked as NewGood or NewBad.
-    fn run_with_whole_pieces<F>(&mut self, piece_length: usize, mut callback: F) -> io::Result<()>
-        where F: FnMut(&BlockMetadata) -> io::Result<bool> {
-        self.merge_pieces();
+    /// if each piece is good or bad so it can be marked as NewGood or NewBad.
+    ///
+    /// Pieces are passed to the callback in order of their piece index, and the callback should
+    /// return whether or not each piece was good, in the same order.
+    fn run_with_whole_pieces<F>(&mut self, piece_length: usize, callback: F) -> TorrentResult<()>
+        where F: FnOnce(&[BlockMetadata]) -> TorrentResult<Vec<bool>> {
+        for piece_index in self.partial_pieces(piece_length) {
+            let already_known = self.old_states.contains(&PieceState::Partial(piece_index)) ||
+                self.old_states.contains(&PieceState::Good(piece_index)) ||
+                self.new_states.contains(&PieceState::Partial(piece_index));
+
+            if !already_known {
+                self.new_states.push(PieceState::Partial(piece_index));
+            }
+        }
 
-        let new_states = &mut self.new_states;
-        let old_states = &self.old_states;
+        let mut whole_pieces: Vec<BlockMetadata> = {
+            let old_states = &self.old_states;
 
-        let total_blocks = self.total_blocks;
-        let last_block_size = self.last_block_size;
+            let total_blocks = self.total_blocks;
+            let last_block_size = self.last_block_size;
+
+            self.pending_blocks.values()
+                .filter(|ref messages| piece_is_complete(total_blocks, last_block_size, piece_length, messages))
+                .filter(|ref messages| !old_states.contains(&PieceState::Good(messages[0].piece_index())))
+                .map(|messages| messages[0])
+                .collect()
+        };
+        whole_pieces.sort_by_key(|message| message.piece_index());
 
-        for messages in self.pending_blocks.values_mut()
-            .filter(|ref messages| piece_is_complete(total_blocks, last_block_size, piece_length, messages))
-            .filter(|ref messages| !old_states.contains(&PieceState::Good(messages[0].piece_index()))) {
-            let is_good = try!(callback(&messages[0]));
+        // TODO: Should do a partial clear if user callback errors.
+        let piece_results = try!(callback(&whole_pieces));
 
+        for (message, is_good) in whole_pieces.iter().zip(piece_results) {
             if is_good {
-                new_states.push(PieceState::Good(messages[0].piece_index()));
+                self.new_states.push(PieceState::Good(message.piece_index()));
+                self.good_pieces.insert(message.piece_index());
             } else {
-                new_states.push(PieceState::Bad(messages[0].piece_index()));
+                self.new_states.push(PieceState::Bad(message.piece_index()));
             }
 
-            // TODO: Should do a partial clear if user callback errors.
-            messages.clear();
+            if let Some(messages) = self.pending_blocks.get_mut(&message.piece_index()) {
+                messages.clear();
+            }
         }
         
         Ok(())
     }
 
-    /// Merges all pending piece messages into a single messages if possible.
+    /// Merges all pending piece messages, so that each run of overlapping or adjacent messages
+    /// becomes a single message.
     fn merge_pieces(&mut self) {
-        for (_, ref mut messages) in self.pending_blocks.iter_mut() {
-            // Sort the messages by their block offset
-            messages.sort_by(|a, b| a.block_offset().cmp(&b.block_offset()));
-
-            let mut messages_len = messages.len();
-            let mut merge_success = true;
-            // See if we can merge all messages into a single message
-            while merge_success && messages_len > 1 {
-                let actual_last = messages.pop().expect("bip_peer: Failed To Merge Blocks");
-                let second_last = messages.pop().expect("bip_peer: Failed To Merge Blocks");
-
-                let opt_merged =  merge_piece_messages(&second_last, &actual_last);
-                if let Some(merged) = opt_merged {
-                    messages.push(merged);
-                } else {
-                    messages.push(second_last);
-                    messages.push(actual_last);
-
-                    merge_success = false;
-                }
-
-                messages_len = messages.len();
-            }
+        for messages in self.pending_blocks.values_mut() {
+            merge_all_piece_messages(messages);
         }
     }
+
+    /// Retrieve the indices of pieces that have some, but not all, of their blocks present.
+    pub fn partial_pieces(&mut self, piece_length: usize) -> Vec<u64> {
+        self.merge_pieces();
+
+        let total_blocks = self.total_blocks;
+        let last_block_size = self.last_block_size;
+
+        let mut partial_pieces: Vec<u64> = self.pending_blocks.values()
+            .filter(|ref messages| !messages.is_empty())
+            .filter(|ref messages| !piece_is_complete(total_blocks, last_block_size, piece_length, messages))
+            .map(|messages| messages[0].piece_index())
+            .collect();
+        partial_pieces.sort();
+
+        partial_pieces
+    }
 }
 
 /// True if the piece is ready to be hashed and checked (full) as good or not.
@@ -263,6 +686,28 @@ fn piece_is_complete(total_blocks: usize, last_block_size: usize, piece_length:
     is_single_message && (is_piece_length || (is_last_block && is_last_block_length))
 }
 
+/// Merge all overlapping or adjacent messages for a single piece.
+///
+/// Messages are left sorted by block offset, with any gaps in the piece left between separate messages.
+fn merge_all_piece_messages(messages: &mut Vec<BlockMetadata>) {
+    messages.sort_by(|a, b| a.block_offset().cmp(&b.block_offset()));
+
+    let mut merged_messages: Vec<BlockMetadata> = Vec::with_capacity(messages.len());
+    for message in messages.drain(..) {
+        let opt_merged = merged_messages.last()
+            .and_then(|last_message| merge_piece_messages(last_message, &message));
+
+        if let Some(merged) = opt_merged {
+            merged_messages.pop();
+            merged_messages.push(merged);
+        } else {
+            merged_messages.push(message);
+        }
+    }
+
+    *messages = merged_messages;
+}
+
 /// Merge a piece message a with a piece message b if possible.
 ///
 /// First message's block offset should come before (or at) the block offset of the second message.
@@ -299,9 +744,678 @@ fn merge_piece_messages(message_a: &BlockMetadata, message_b: &BlockMetadata) ->
 
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use disk::{AllocationMode, FilePriority};
+    use disk::fs::FileSystem;
+    use disk::resume::ResumeData;
+    use disk::fs::memory::{InMemoryFile, InMemoryFileSystem};
+    use disk::fs::native::NativeFileSystem;
+    use disk::tasks::helpers;
+    use disk::tasks::helpers::piece_checker::{CancelToken, PieceChecker, PieceCheckerState, PieceState};
+    use disk::tasks::helpers::piece_hasher::{PieceHasher, Sha1PieceHasher};
+    use error::TorrentErrorKind;
+    use memory::allocator::PooledBlockAllocator;
     use memory::block::BlockMetadata;
 
+    use bip_metainfo::Info;
     use bip_util::bt;
+    use bip_util::sha::ShaHash;
+
+    /// File system that panics if any file is touched.
+    struct PanicFileSystem;
+
+    impl FileSystem for PanicFileSystem {
+        type File = ();
+
+        fn open_file<P>(&self, _path: P) -> io::Result<()>
+            where P: AsRef<Path> + Send + 'static {
+            panic!("PanicFileSystem::open_file Called")
+        }
+
+        fn sync_path<P>(&self, _path: P) -> io::Result<()>
+            where P: AsRef<Path> + Send + 'static {
+            panic!("PanicFileSystem::sync_path Called")
+        }
+
+        fn sync_file(&self, _file: &()) -> io::Result<()> {
+            panic!("PanicFileSystem::sync_file Called")
+        }
+
+        fn file_size(&self, _file: &()) -> io::Result<u64> {
+            panic!("PanicFileSystem::file_size Called")
+        }
+
+        fn read_file(&self, _file: &mut (), _offset: u64, _buffer: &mut [u8]) -> io::Result<usize> {
+            panic!("PanicFileSystem::read_file Called")
+        }
+
+        fn write_file(&self, _file: &mut (), _offset: u64, _buffer: &[u8]) -> io::Result<usize> {
+            panic!("PanicFileSystem::write_file Called")
+        }
+
+        fn available_space(&self, _path: &Path) -> io::Result<u64> {
+            panic!("PanicFileSystem::available_space Called")
+        }
+    }
+
+    /// Build a multi file info dictionary with a single file with the given directory and path (as bencode).
+    fn info_with_path(directory: &[u8], path: &[u8]) -> Info {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(b"d5:filesld6:lengthi1e4:pathl");
+        bytes.extend_from_slice(path);
+        bytes.extend_from_slice(format!("eee4:name{}:", directory.len()).as_bytes());
+        bytes.extend_from_slice(directory);
+        bytes.extend_from_slice(b"12:piece lengthi1024e6:pieces20:");
+        bytes.extend_from_slice(&[0u8; 20]);
+        bytes.extend_from_slice(b"e");
+
+        Info::from_bytes(bytes).unwrap()
+    }
+
+    /// File system that fails with an out of space error for any write past the quota.
+    ///
+    /// The space it reports as available is set separately, so a quota can be hit after the space check.
+    struct QuotaFileSystem {
+        inner:     InMemoryFileSystem,
+        quota:     u64,
+        available: u64
+    }
+
+    impl FileSystem for QuotaFileSystem {
+        type File = InMemoryFile;
+
+        fn open_file<P>(&self, path: P) -> io::Result<InMemoryFile>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.open_file(path)
+        }
+
+        fn sync_path<P>(&self, path: P) -> io::Result<()>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.sync_path(path)
+        }
+
+        fn sync_file(&self, file: &InMemoryFile) -> io::Result<()> {
+            self.inner.sync_file(file)
+        }
+
+        fn file_size(&self, file: &InMemoryFile) -> io::Result<u64> {
+            self.inner.file_size(file)
+        }
+
+        fn read_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+            self.inner.read_file(file, offset, buffer)
+        }
+
+        fn write_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+            if offset + buffer.len() as u64 > self.quota {
+                Err(io::Error::new(io::ErrorKind::WriteZero, "Quota Exceeded"))
+            } else {
+                self.inner.write_file(file, offset, buffer)
+            }
+        }
+
+        fn available_space(&self, _path: &Path) -> io::Result<u64> {
+            Ok(self.available)
+        }
+    }
+
+    fn assert_insufficient_space(alloc_mode: AllocationMode, quota: u64, length: usize, expected_needed: u64) {
+        let fs = QuotaFileSystem{ inner: InMemoryFileSystem::new(), quota: quota, available: u64::max_value() };
+        let info = info_with_length(length);
+
+        let error = PieceChecker::init_state(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher, alloc_mode, 1, None)
+            .err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::InsufficientSpace{ ref file_path, needed } => {
+                assert_eq!(Path::new("file"), file_path);
+                assert_eq!(expected_needed, needed);
+            },
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    /// Clean directory (unique to the test) to use for the file system, which is removed when dropped.
+    struct TestDirectory {
+        path: PathBuf
+    }
+
+    impl TestDirectory {
+        fn new(name: &str) -> TestDirectory {
+            let path = env::temp_dir().join("bip_disk_piece_checker_tests").join(name);
+            let _ = fs::remove_dir_all(&path);
+
+            TestDirectory{ path: path }
+        }
+
+        fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// Build a single file info dictionary with the given file length, made up of a single piece.
+    fn info_with_length(length: usize) -> Info {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(format!("d6:lengthi{}e4:name4:file12:piece lengthi{}e6:pieces20:", length, length).as_bytes());
+        bytes.extend_from_slice(&[0u8; 20]);
+        bytes.extend_from_slice(b"e");
+
+        Info::from_bytes(bytes).unwrap()
+    }
+
+    /// Hasher which produces a constant digest, regardless of the piece given.
+    struct ConstantPieceHasher;
+
+    const CONSTANT_DIGEST_BYTE: u8 = 0xAB;
+
+    impl PieceHasher for ConstantPieceHasher {
+        fn digest_length(&self) -> usize {
+            bt::INFO_HASH_LEN
+        }
+
+        fn hash_piece(&self, _piece: &[u8]) -> Vec<u8> {
+            vec![CONSTANT_DIGEST_BYTE; bt::INFO_HASH_LEN]
+        }
+    }
+
+    /// Hasher which produces digests longer than the hashes stored in a v1 info dictionary.
+    struct LongDigestPieceHasher;
+
+    impl PieceHasher for LongDigestPieceHasher {
+        fn digest_length(&self) -> usize {
+            32
+        }
+
+        fn hash_piece(&self, _piece: &[u8]) -> Vec<u8> {
+            vec![0u8; 32]
+        }
+    }
+
+    /// Hasher which uses SHA-1, while counting the number of pieces hashed.
+    struct CountingPieceHasher {
+        hashed: AtomicUsize
+    }
+
+    impl PieceHasher for CountingPieceHasher {
+        fn digest_length(&self) -> usize {
+            Sha1PieceHasher.digest_length()
+        }
+
+        fn hash_piece(&self, piece: &[u8]) -> Vec<u8> {
+            self.hashed.fetch_add(1, Ordering::SeqCst);
+
+            Sha1PieceHasher.hash_piece(piece)
+        }
+    }
+
+    /// Collect whether or not each discovered piece was good, in order of piece index.
+    fn diff_pieces(checker_state: &mut PieceCheckerState) -> Vec<(u64, bool)> {
+        let mut pieces = Vec::new();
+        checker_state.run_with_diff(|piece_state| {
+            match piece_state {
+                &PieceState::Good(index) => pieces.push((index, true)),
+                &PieceState::Bad(index)  => pieces.push((index, false)),
+                &PieceState::Partial(_)  => panic!("Unexpected Partial Piece")
+            }
+        });
+        pieces.sort();
+
+        pieces
+    }
+
+    /// Check the torrent from scratch, then export and re-import resume data, returning the restored
+    /// pieces along with the number of pieces hashed while restoring.
+    ///
+    /// The modify callback is run against the file system before the resume data is restored.
+    fn restore_round_trip<M>(modify: M) -> (Vec<(u64, bool)>, usize)
+        where M: FnOnce(&InMemoryFileSystem) {
+        let piece_length = 16;
+        let (data, pieces) = data_with_corrupt_pieces(piece_length);
+
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_pieces(&fs, &data, piece_length, &pieces);
+        let allocator = PooledBlockAllocator::new();
+
+        let checker_state = PieceChecker::init_state(fs.clone(), &info, &allocator, &Sha1PieceHasher, AllocationMode::Sparse, 1, None)
+            .unwrap();
+        let resume_bytes = ResumeData::new(info.info_hash(), checker_state.good_pieces(), vec![data.len() as u64]).to_bytes();
+
+        modify(&fs);
+
+        let resume_data = ResumeData::from_bytes(&resume_bytes).unwrap();
+        let hasher = CountingPieceHasher{ hashed: AtomicUsize::new(0) };
+        let mut restored_state = PieceChecker::init_state_with(fs, &info, &allocator, &hasher, AllocationMode::Sparse, 1, None,
+                                                                  Some(&resume_data), &[], None)
+            .unwrap();
+
+        (diff_pieces(&mut restored_state), hasher.hashed.load(Ordering::SeqCst))
+    }
+
+    /// Build a single file info dictionary with the given piece length and hashes, and write the given data.
+    fn info_with_pieces(fs: &InMemoryFileSystem, data: &[u8], piece_length: usize, pieces: &[u8]) -> Info {
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(format!("d6:lengthi{}e4:name4:file12:piece lengthi{}e6:pieces{}:",
+                                        data.len(), piece_length, pieces.len()).as_bytes());
+        bytes.extend_from_slice(pieces);
+        bytes.extend_from_slice(b"e");
+
+        let mut file = fs.open_file("file").unwrap();
+        fs.write_file(&mut file, 0, data).unwrap();
+
+        Info::from_bytes(bytes).unwrap()
+    }
+
+    /// Build a single file info dictionary with a single piece spanning the whole file, and write the given data.
+    fn info_with_piece_hash(fs: &InMemoryFileSystem, data: &[u8], piece_hash: &[u8]) -> Info {
+        info_with_pieces(fs, data, data.len(), piece_hash)
+    }
+
+    /// Generate ten and a half pieces of data, as well as piece hashes where every third piece hash is corrupt.
+    fn data_with_corrupt_pieces(piece_length: usize) -> (Vec<u8>, Vec<u8>) {
+        let data: Vec<u8> = (0..(piece_length * 10 + piece_length / 2)).map(|byte| byte as u8).collect();
+
+        let mut pieces = Vec::new();
+        for (index, piece) in data.chunks(piece_length).enumerate() {
+            if index % 3 == 0 {
+                pieces.extend_from_slice(&[CONSTANT_DIGEST_BYTE; bt::INFO_HASH_LEN]);
+            } else {
+                pieces.extend_from_slice(ShaHash::from_bytes(piece).as_ref());
+            }
+        }
+
+        (data, pieces)
+    }
+
+    /// Run calculate_diff over all pieces with the given threads, returning each progress update received.
+    fn calculate_diff_progress(num_threads: usize) -> Vec<(u64, u64)> {
+        let piece_length = 16;
+        let (data, pieces) = data_with_corrupt_pieces(piece_length);
+
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_pieces(&fs, &data, piece_length, &pieces);
+        let allocator = PooledBlockAllocator::new();
+
+        let mut updates = Vec::new();
+        {
+            let mut progress = |pieces_done, pieces_total| updates.push((pieces_done, pieces_total));
+
+            let mut checker_state = PieceCheckerState::new(info.pieces().count(), piece_length / 2);
+            let mut piece_checker = PieceChecker::with_state(fs, &info, &mut checker_state, &allocator, &Sha1PieceHasher)
+                .with_num_threads(num_threads)
+                .with_progress(&mut progress);
+
+            piece_checker.fill_checker_state().unwrap();
+            piece_checker.calculate_diff().unwrap();
+        }
+
+        updates
+    }
+
+    /// Run init_state with the given hasher and threads, returning whether or not each discovered piece was good.
+    fn init_state_good_pieces(fs: InMemoryFileSystem, info: &Info, hasher: &PieceHasher, num_threads: usize)
+        -> Vec<(u64, bool)> {
+        let mut checker_state = PieceChecker::init_state(fs, info, &PooledBlockAllocator::new(), hasher, AllocationMode::Sparse, num_threads, None)
+            .unwrap();
+
+        let mut pieces = Vec::new();
+        checker_state.run_with_diff(|piece_state| {
+            match piece_state {
+                &PieceState::Good(index) => pieces.push((index, true)),
+                &PieceState::Bad(index)  => pieces.push((index, false)),
+                &PieceState::Partial(_)  => panic!("Unexpected Partial Piece")
+            }
+        });
+
+        pieces
+    }
+
+    fn assert_invalid_file_path(info: &Info) {
+        let error = PieceChecker::init_state(PanicFileSystem, info, &PooledBlockAllocator::new(), &Sha1PieceHasher, AllocationMode::Sparse, 1, None).err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::InvalidFilePath{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_init_state_sha1_good_piece() {
+        let fs = InMemoryFileSystem::new();
+        let data = b"piece data";
+        let info = info_with_piece_hash(&fs, data, ShaHash::from_bytes(data).as_ref());
+
+        assert_eq!(vec![(0, true)], init_state_good_pieces(fs, &info, &Sha1PieceHasher, 1));
+    }
+
+    #[test]
+    fn negative_init_state_sha1_bad_piece() {
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_piece_hash(&fs, b"piece data", &[CONSTANT_DIGEST_BYTE; bt::INFO_HASH_LEN]);
+
+        assert_eq!(vec![(0, false)], init_state_good_pieces(fs, &info, &Sha1PieceHasher, 1));
+    }
+
+    #[test]
+    fn positive_init_state_alternate_hasher_good_piece() {
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_piece_hash(&fs, b"piece data", &[CONSTANT_DIGEST_BYTE; bt::INFO_HASH_LEN]);
+
+        assert_eq!(vec![(0, true)], init_state_good_pieces(fs, &info, &ConstantPieceHasher, 1));
+    }
+
+    #[test]
+    fn negative_init_state_alternate_hasher_bad_piece() {
+        let fs = InMemoryFileSystem::new();
+        let data = b"piece data";
+        let info = info_with_piece_hash(&fs, data, ShaHash::from_bytes(data).as_ref());
+
+        assert_eq!(vec![(0, false)], init_state_good_pieces(fs, &info, &ConstantPieceHasher, 1));
+    }
+
+    #[test]
+    fn positive_init_state_parallel_matches_sequential() {
+        let piece_length = 16;
+        let (data, pieces) = data_with_corrupt_pieces(piece_length);
+
+        let sequential_fs = InMemoryFileSystem::new();
+        let sequential_info = info_with_pieces(&sequential_fs, &data, piece_length, &pieces);
+        let sequential_pieces = init_state_good_pieces(sequential_fs, &sequential_info, &Sha1PieceHasher, 1);
+
+        let parallel_fs = InMemoryFileSystem::new();
+        let parallel_info = info_with_pieces(&parallel_fs, &data, piece_length, &pieces);
+        let parallel_pieces = init_state_good_pieces(parallel_fs, &parallel_info, &Sha1PieceHasher, 4);
+
+        let expected_pieces: Vec<(u64, bool)> = (0..11).map(|index| (index, index % 3 != 0)).collect();
+        assert_eq!(expected_pieces, sequential_pieces);
+        assert_eq!(sequential_pieces, parallel_pieces);
+    }
+
+    #[test]
+    fn positive_init_state_resume_skips_good_pieces() {
+        let (restored_pieces, num_hashed) = restore_round_trip(|_| ());
+
+        let expected_pieces: Vec<(u64, bool)> = (0..11).map(|index| (index, index % 3 != 0)).collect();
+        assert_eq!(expected_pieces, restored_pieces);
+        // Only the four bad pieces should have been hashed again
+        assert_eq!(4, num_hashed);
+    }
+
+    #[test]
+    fn positive_init_state_resume_changed_file_size() {
+        let (restored_pieces, num_hashed) = restore_round_trip(|fs| {
+            fs.run_with_lock(|files| files.get_mut(Path::new("file")).unwrap().truncate(0));
+        });
+
+        // File was zero filled, so every piece should have been hashed, and found to be bad
+        let expected_pieces: Vec<(u64, bool)> = (0..11).map(|index| (index, false)).collect();
+        assert_eq!(expected_pieces, restored_pieces);
+        assert_eq!(11, num_hashed);
+    }
+
+    #[test]
+    fn negative_init_state_resume_info_hash_mismatch() {
+        let fs = InMemoryFileSystem::new();
+        let data = b"piece data";
+        let info = info_with_piece_hash(&fs, data, ShaHash::from_bytes(data).as_ref());
+        let resume_data = ResumeData::new([0u8; bt::INFO_HASH_LEN].into(), vec![0], vec![data.len() as u64]);
+
+        let error = PieceChecker::init_state_with(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher,
+                                                  AllocationMode::Sparse, 1, None, Some(&resume_data), &[], None).err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::InvalidResumeData{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_calculate_diff_progress_once_per_piece() {
+        let expected_updates: Vec<(u64, u64)> = (1..12).map(|pieces_done| (pieces_done, 11)).collect();
+
+        assert_eq!(expected_updates, calculate_diff_progress(1));
+    }
+
+    #[test]
+    fn positive_calculate_diff_progress_once_per_piece_parallel() {
+        let expected_updates: Vec<(u64, u64)> = (1..12).map(|pieces_done| (pieces_done, 11)).collect();
+
+        assert_eq!(expected_updates, calculate_diff_progress(4));
+    }
+
+    #[test]
+    fn negative_init_state_cancelled_partway() {
+        let piece_length = 16;
+        let (data, pieces) = data_with_corrupt_pieces(piece_length);
+
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_pieces(&fs, &data, piece_length, &pieces);
+        let cancel = CancelToken::new();
+
+        let mut updates = Vec::new();
+        let result = {
+            let mut progress = |pieces_done, pieces_total| {
+                updates.push((pieces_done, pieces_total));
+
+                if pieces_done == 3 {
+                    cancel.cancel();
+                }
+            };
+
+            PieceChecker::init_state_with(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher, AllocationMode::Sparse,
+                                          1, Some(&mut progress), None, &[], Some(&cancel))
+        };
+
+        match result.err().unwrap().kind() {
+            &TorrentErrorKind::Cancelled{ hash } => assert_eq!(info.info_hash(), hash),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+        // Check stopped right after the piece it was cancelled on, out of the 11 pieces
+        assert_eq!(vec![(1, 11), (2, 11), (3, 11)], updates);
+    }
+
+    #[test]
+    fn negative_init_state_missing_piece_hash() {
+        let fs = InMemoryFileSystem::new();
+        let data = [0u8; 32];
+        let info = info_with_pieces(&fs, &data, 16, ShaHash::from_bytes(&data[..16]).as_ref());
+
+        let error = PieceChecker::init_state(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher, AllocationMode::Sparse, 1, None)
+            .err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::MissingPieceHash{ piece_index } => assert_eq!(1, piece_index),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_init_state_invalid_piece_hash_length() {
+        let fs = InMemoryFileSystem::new();
+        let data = b"piece data";
+        let info = info_with_piece_hash(&fs, data, ShaHash::from_bytes(data).as_ref());
+
+        let error = PieceChecker::init_state(fs, &info, &PooledBlockAllocator::new(), &LongDigestPieceHasher, AllocationMode::Sparse, 1, None)
+            .err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::InvalidPieceHashLength{ piece_index, expected_length, actual_length } => {
+                assert_eq!((0, 32, 20), (piece_index, expected_length, actual_length));
+            },
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_init_state_sparse_allocation_size() {
+        let dir = TestDirectory::new("positive_init_state_sparse_allocation_size");
+        let info = info_with_length(1024 * 1024);
+
+        PieceChecker::init_state(NativeFileSystem::with_directory(dir.path()), &info, &PooledBlockAllocator::new(),
+                                 &Sha1PieceHasher, AllocationMode::Sparse, 1, None).unwrap();
+
+        assert_eq!(1024 * 1024, fs::metadata(dir.path().join("file")).unwrap().len());
+    }
+
+    #[test]
+    fn positive_init_state_full_allocation_size() {
+        let dir = TestDirectory::new("positive_init_state_full_allocation_size");
+        let info = info_with_length(1024 * 1024 + 1);
+
+        PieceChecker::init_state(NativeFileSystem::with_directory(dir.path()), &info, &PooledBlockAllocator::new(),
+                                 &Sha1PieceHasher, AllocationMode::Full, 1, None).unwrap();
+
+        assert_eq!(1024 * 1024 + 1, fs::metadata(dir.path().join("file")).unwrap().len());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_init_state_full_allocation_occupies_blocks() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = TestDirectory::new("positive_init_state_full_allocation_occupies_blocks");
+        let info = info_with_length(1024 * 1024);
+
+        PieceChecker::init_state(NativeFileSystem::with_directory(dir.path()), &info, &PooledBlockAllocator::new(),
+                                 &Sha1PieceHasher, AllocationMode::Full, 1, None).unwrap();
+
+        // Blocks are always reported in 512 byte units
+        assert!(fs::metadata(dir.path().join("file")).unwrap().blocks() * 512 >= 1024 * 1024);
+    }
+
+    #[test]
+    fn negative_init_state_sparse_insufficient_space() {
+        assert_insufficient_space(AllocationMode::Sparse, 100 * 1024, 256 * 1024, 256 * 1024);
+    }
+
+    #[test]
+    fn negative_init_state_full_insufficient_space() {
+        // First 64 KiB chunk fits, the second does not
+        assert_insufficient_space(AllocationMode::Full, 100 * 1024, 256 * 1024, 192 * 1024);
+    }
+
+    #[test]
+    fn negative_init_state_preflight_insufficient_space() {
+        let inner = InMemoryFileSystem::new();
+        let fs = QuotaFileSystem{ inner: inner.clone(), quota: u64::max_value(), available: 1000 };
+        let info = info_with_length(1024);
+
+        let error = PieceChecker::init_state(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher,
+                                             AllocationMode::Sparse, 1, None).err().unwrap();
+
+        match error.kind() {
+            &TorrentErrorKind::InsufficientSpace{ needed, .. } => assert_eq!(1024, needed),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+
+        // Nothing should have been allocated
+        let file_buffer = inner.run_with_lock(|files| files.get(Path::new("file")).cloned());
+        assert_eq!(Some(Vec::new()), file_buffer);
+    }
+
+    #[test]
+    fn positive_init_state_preflight_existing_file() {
+        let inner = InMemoryFileSystem::new();
+        let fs = QuotaFileSystem{ inner: inner.clone(), quota: u64::max_value(), available: 0 };
+        let info = info_with_length(1024);
+
+        let mut file = inner.open_file("file").unwrap();
+        inner.write_file(&mut file, 0, &[0u8; 1024]).unwrap();
+
+        PieceChecker::init_state(fs, &info, &PooledBlockAllocator::new(), &Sha1PieceHasher,
+                                 AllocationMode::Sparse, 1, None).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn positive_allocation_error_enospc() {
+        let error = super::allocation_error(io::Error::from_raw_os_error(28), Path::new("file"), 10);
+
+        match error.kind() {
+            &TorrentErrorKind::InsufficientSpace{ needed, .. } => assert_eq!(10, needed),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_init_state_zero_fills_files() {
+        let fs = InMemoryFileSystem::new();
+        let info = info_with_path(b"dir", b"3:sub4:file");
+
+        PieceChecker::init_state(fs.clone(), &info, &PooledBlockAllocator::new(), &Sha1PieceHasher, AllocationMode::Sparse, 1, None).unwrap();
+
+        let file_buffer = fs.run_with_lock(|files| files.get(Path::new("dir/sub/file")).cloned());
+        assert_eq!(Some(vec![0u8]), file_buffer);
+    }
+
+    #[test]
+    fn positive_init_state_skipped_file_not_allocated() {
+        let fs = InMemoryFileSystem::new();
+        let data: Vec<u8> = (0..24).collect();
+
+        // Piece 0 lies within file a, piece 1 spans files a and b, and piece 2 lies within file b
+        let mut bytes = b"d5:filesld6:lengthi24e4:pathl1:aeed6:lengthi24e4:pathl1:beee4:name3:dir12:piece lengthi16e6:pieces60:".to_vec();
+        bytes.extend_from_slice(ShaHash::from_bytes(&data[..16]).as_ref());
+        bytes.extend_from_slice(&[0u8; 40]);
+        bytes.extend_from_slice(b"e");
+        let info = Info::from_bytes(bytes).unwrap();
+
+        let mut file = fs.open_file("dir/a").unwrap();
+        fs.write_file(&mut file, 0, &data).unwrap();
+
+        let priorities = [FilePriority::Normal, FilePriority::Skip];
+        let mut checker_state = PieceChecker::init_state_with(fs.clone(), &info, &PooledBlockAllocator::new(), &Sha1PieceHasher,
+                                                              AllocationMode::Sparse, 1, None, None, &priorities, None).unwrap();
+
+        assert_eq!(vec![(0, true)], diff_pieces(&mut checker_state));
+        let file_buffer = fs.run_with_lock(|files| files.get(Path::new("dir/b")).cloned());
+        assert_eq!(Some(Vec::new()), file_buffer);
+    }
+
+    #[test]
+    fn positive_validate_path_nested() {
+        let info = info_with_path(b"dir", b"3:sub4:file");
+
+        helpers::validate_path(info.directory(), info.files().next().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn negative_validate_path_parent_escape() {
+        assert_invalid_file_path(&info_with_path(b"dir", b"2:..6:escape"));
+    }
+
+    #[test]
+    fn negative_validate_path_nested_parent_escape() {
+        assert_invalid_file_path(&info_with_path(b"dir", b"3:sub2:..2:..6:escape"));
+    }
+
+    #[test]
+    fn negative_validate_path_absolute() {
+        assert_invalid_file_path(&info_with_path(b"dir", b"11:/etc/passwd"));
+    }
+
+    #[test]
+    fn negative_validate_path_directory_escape() {
+        assert_invalid_file_path(&info_with_path(b"..", b"6:escape"));
+    }
+
+    #[test]
+    fn negative_validate_path_empty() {
+        assert_invalid_file_path(&info_with_path(b"dir", b"0:"));
+    }
 
     #[test]
     fn positive_merge_duplicate_messages() {
@@ -312,6 +1426,92 @@ mod tests {
         assert_eq!(metadata_a, merged.unwrap());
     }
 
+    #[test]
+    fn positive_merge_pieces_out_of_order_run() {
+        let mut checker_state = PieceCheckerState::new(1, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 20, 10));
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 10, 10));
+        checker_state.merge_pieces();
+
+        assert_eq!(vec![BlockMetadata::with_default_hash(0, 0, 30)], checker_state.pending_blocks[&0]);
+        assert!(checker_state.partial_pieces(30).is_empty());
+    }
+
+    #[test]
+    fn positive_merge_pieces_run_before_gap() {
+        let mut checker_state = PieceCheckerState::new(1, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 10, 10));
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 30, 10));
+        checker_state.merge_pieces();
+
+        let expected = vec![BlockMetadata::with_default_hash(0, 0, 20), BlockMetadata::with_default_hash(0, 30, 10)];
+        assert_eq!(expected, checker_state.pending_blocks[&0]);
+        assert_eq!(vec![0], checker_state.partial_pieces(40));
+    }
+
+    #[test]
+    fn negative_merge_pieces_gap() {
+        let mut checker_state = PieceCheckerState::new(1, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 30, 10));
+        checker_state.merge_pieces();
+
+        let expected = vec![BlockMetadata::with_default_hash(0, 0, 10), BlockMetadata::with_default_hash(0, 30, 10)];
+        assert_eq!(expected, checker_state.pending_blocks[&0]);
+        assert_eq!(vec![0], checker_state.partial_pieces(40));
+    }
+
+    /// Run the whole pieces with the given piece length, marking all of them as good, and return the diff.
+    fn whole_pieces_diff(checker_state: &mut PieceCheckerState, piece_length: usize) -> Vec<PieceState> {
+        checker_state.run_with_whole_pieces(piece_length, |whole_pieces| {
+            Ok(whole_pieces.iter().map(|_| true).collect())
+        }).unwrap();
+
+        let mut diff = Vec::new();
+        checker_state.run_with_diff(|piece_state| {
+            diff.push(*piece_state)
+        });
+
+        diff
+    }
+
+    #[test]
+    fn positive_run_with_diff_first_half_partial() {
+        let mut checker_state = PieceCheckerState::new(2, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+
+        assert_eq!(vec![PieceState::Partial(0)], whole_pieces_diff(&mut checker_state, 20));
+    }
+
+    #[test]
+    fn positive_run_with_diff_partial_reported_once() {
+        let mut checker_state = PieceCheckerState::new(2, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+        whole_pieces_diff(&mut checker_state, 20);
+
+        assert!(whole_pieces_diff(&mut checker_state, 20).is_empty());
+    }
+
+    #[test]
+    fn positive_run_with_diff_partial_completed() {
+        let mut checker_state = PieceCheckerState::new(2, 0);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 0, 10));
+        whole_pieces_diff(&mut checker_state, 20);
+
+        checker_state.add_pending_block(BlockMetadata::with_default_hash(0, 10, 10));
+
+        assert_eq!(vec![PieceState::Good(0)], whole_pieces_diff(&mut checker_state, 20));
+        assert!(!checker_state.old_states.contains(&PieceState::Partial(0)));
+    }
+
     #[test]
     fn negative_merge_duplicate_messages_diff_hash() {
         let metadata_a = BlockMetadata::new([0u8; bt::INFO_HASH_LEN].into(), 0, 5, 5);
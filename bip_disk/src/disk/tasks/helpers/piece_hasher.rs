@@ -0,0 +1,80 @@
+use std::mem;
+
+use bip_util::sha::{self, ShaHash, ShaHashBuilder};
+
+/// Trait for calculating the digest of a whole piece so it can be checked against the info dictionary.
+pub trait PieceHasher: Sync {
+    /// Length, in bytes, of the digests produced by this hasher.
+    fn digest_length(&self) -> usize;
+
+    /// Calculate the digest of the given piece.
+    fn hash_piece(&self, piece: &[u8]) -> Vec<u8>;
+
+    /// Start calculating the digest of a piece that will be given a region at a time.
+    ///
+    /// By default, regions are buffered until the whole piece can be given to `hash_piece`.
+    fn start_digest<'a>(&'a self) -> Box<PieceDigest + 'a> {
+        Box::new(BufferedPieceDigest{ hasher: self, piece: Vec::new() })
+    }
+}
+
+/// Trait for incrementally calculating the digest of a piece, given in order a region at a time.
+pub trait PieceDigest {
+    /// Add the next region of the piece to the digest.
+    fn update(&mut self, region: &[u8]);
+
+    /// Calculate the digest of all regions given.
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+/// `PieceDigest` which buffers the whole piece for hashers that cannot hash incrementally.
+struct BufferedPieceDigest<'a, H: ?Sized + 'a> {
+    hasher: &'a H,
+    piece:  Vec<u8>
+}
+
+impl<'a, H: ?Sized> PieceDigest for BufferedPieceDigest<'a, H> where H: PieceHasher {
+    fn update(&mut self, region: &[u8]) {
+        self.piece.extend_from_slice(region);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.hasher.hash_piece(&self.piece)
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// `PieceHasher` using SHA-1, as used by v1 torrents.
+pub struct Sha1PieceHasher;
+
+impl PieceHasher for Sha1PieceHasher {
+    fn digest_length(&self) -> usize {
+        sha::SHA_HASH_LEN
+    }
+
+    fn hash_piece(&self, piece: &[u8]) -> Vec<u8> {
+        ShaHash::from_bytes(piece).as_ref().to_vec()
+    }
+
+    fn start_digest<'a>(&'a self) -> Box<PieceDigest + 'a> {
+        Box::new(Sha1PieceDigest{ builder: ShaHashBuilder::new() })
+    }
+}
+
+/// `PieceDigest` which feeds each region straight in to SHA-1.
+struct Sha1PieceDigest {
+    builder: ShaHashBuilder
+}
+
+impl PieceDigest for Sha1PieceDigest {
+    fn update(&mut self, region: &[u8]) {
+        let builder = mem::replace(&mut self.builder, ShaHashBuilder::new());
+
+        self.builder = builder.add_bytes(region);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.builder.build().as_ref().to_vec()
+    }
+}
@@ -1,12 +1,16 @@
+use std::io;
+
 use disk::fs::FileSystem;
-use disk::{IDiskMessage, ODiskMessage};
+use disk::{IDiskMessage, ODiskMessage, FilePriority, TorrentEvent};
+use disk::resume::ResumeData;
 use disk::tasks::helpers::piece_checker::{PieceChecker, PieceCheckerState, PieceState};
 use disk::tasks::helpers::piece_accessor::PieceAccessor;
+use disk::tasks::helpers::piece_hasher::Sha1PieceHasher;
 use disk::tasks::context::DiskManagerContext;
-use memory::block::{Block, BlockMut};
+use memory::block::{Block, BlockMut, BlockMetadata};
 use error::{TorrentResult, BlockResult, BlockError, BlockErrorKind, TorrentError, TorrentErrorKind};
 
-use bip_metainfo::Metainfo;
+use bip_metainfo::{Info, Metainfo};
 use bip_util::bt::InfoHash;
 use futures::sink::Wait;
 use futures::sync::mpsc::Sender;
@@ -17,60 +21,164 @@ mod helpers;
 
 pub fn execute_on_pool<F>(msg: IDiskMessage, pool: &CpuPool, context: DiskManagerContext<F>)
     where F: FileSystem + Send + Sync + 'static {
+    // Track work before it hits the pool, so a shutdown will always wait on messages sent before it
+    context.start_work();
+    match msg {
+        IDiskMessage::Shutdown => context.start_shutdown(),
+        // Track checks before they hit the pool, so an add can be cancelled before it starts
+        IDiskMessage::AddTorrent(ref metainfo) |
+        IDiskMessage::AddTorrentWithResumeData(ref metainfo, _) |
+        IDiskMessage::AddTorrentWithPriorities(ref metainfo, _) => { context.start_check(metainfo.info().info_hash()); },
+        _ => ()
+    }
+
+    // Blocks being coalesced are written by whichever worker takes the batch they end up in
+    let opt_msg = match msg {
+        IDiskMessage::ProcessBlock(block) if context.write_coalescing() => {
+            context.queue_write(block);
+
+            None
+        },
+        other => Some(other)
+    };
+
     pool.spawn_fn(move || {
         let mut blocking_sender = context.blocking_sender();
 
-        let out_msg = match msg {
-            IDiskMessage::AddTorrent(metainfo) => {
-                let info_hash = metainfo.info().info_hash();
-                
-                match execute_add_torrent(metainfo, &context, &mut blocking_sender) {
-                    Ok(_)    => ODiskMessage::TorrentAdded(info_hash),
-                    Err(err) => ODiskMessage::TorrentError(info_hash, err)
-                }
-            },
-            IDiskMessage::RemoveTorrent(hash) => {
-                match execute_remove_torrent(hash, &context) {
-                    Ok(_)    => ODiskMessage::TorrentRemoved(hash),
-                    Err(err) => ODiskMessage::TorrentError(hash, err)
-                }
-            },
-            IDiskMessage::SyncTorrent(hash) => {
-                match execute_sync_torrent(hash, &context) {
-                    Ok(_)    => ODiskMessage::TorrentSynced(hash),
-                    Err(err) => ODiskMessage::TorrentError(hash, err)
-                }
-            },
-            IDiskMessage::LoadBlock(mut block) => {
-                match execute_load_block(&mut block, &context) {
-                    Ok(_)    => ODiskMessage::BlockLoaded(block),
-                    Err(err) => ODiskMessage::LoadBlockError(block, err)
-                }
-            },
-            IDiskMessage::ProcessBlock(mut block) => {
-                match execute_process_block(&mut block, &context, &mut blocking_sender) {
-                    Ok(_)    => ODiskMessage::BlockProcessed(block),
-                    Err(err) => ODiskMessage::ProcessBlockError(block, err)
-                }
-            }
+        let out_msgs = match opt_msg {
+            Some(msg) => vec![execute_message(msg, &context, &mut blocking_sender)],
+            None      => execute_queued_writes(&context, &mut blocking_sender)
         };
 
-        blocking_sender.send(out_msg)
-            .expect("bip_disk: Failed To Send Out Message In execute_on_pool");
+        for out_msg in out_msgs {
+            publish_error_event(&out_msg, &context);
+
+            blocking_sender.send(out_msg)
+                .expect("bip_disk: Failed To Send Out Message In execute_on_pool");
+        }
         blocking_sender.flush()
             .expect("bip_disk: Failed to Flush Out Messages In execute_on_pool");
-        
+        context.finish_work();
+
         Ok::<(),()>(())
     }).forget()
 }
 
+fn execute_message<F>(msg: IDiskMessage, context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> ODiskMessage
+    where F: FileSystem + Sync {
+    match msg {
+        IDiskMessage::AddTorrent(metainfo) => {
+            let info_hash = metainfo.info().info_hash();
+            
+            match execute_add_torrent(metainfo, context, blocking_sender) {
+                Ok(_)    => ODiskMessage::TorrentAdded(info_hash),
+                Err(err) => ODiskMessage::TorrentError(info_hash, err)
+            }
+        },
+        IDiskMessage::AddTorrentWithResumeData(metainfo, resume_bytes) => {
+            let info_hash = metainfo.info().info_hash();
+
+            // Check was started before we hit the pool, so we have to finish it if we never get to the add
+            let add_result = ResumeData::from_bytes(&resume_bytes)
+                .map_err(|err| { context.finish_check(info_hash); err })
+                .and_then(|resume_data| execute_add_torrent_with(metainfo, Some(&resume_data), &[], context, blocking_sender));
+            match add_result {
+                Ok(_)    => ODiskMessage::TorrentAdded(info_hash),
+                Err(err) => ODiskMessage::TorrentError(info_hash, err)
+            }
+        },
+        IDiskMessage::RemoveTorrent(hash) => {
+            match execute_remove_torrent(hash, context) {
+                Ok(_)    => ODiskMessage::TorrentRemoved(hash),
+                Err(err) => ODiskMessage::TorrentError(hash, err)
+            }
+        },
+        IDiskMessage::SyncTorrent(hash) => {
+            match execute_sync_torrent(hash, context) {
+                Ok(_)    => ODiskMessage::TorrentSynced(hash),
+                Err(err) => ODiskMessage::TorrentError(hash, err)
+            }
+        },
+        IDiskMessage::AddTorrentWithPriorities(metainfo, file_priorities) => {
+            let info_hash = metainfo.info().info_hash();
+
+            match execute_add_torrent_with(metainfo, None, &file_priorities, context, blocking_sender) {
+                Ok(_)    => ODiskMessage::TorrentAdded(info_hash),
+                Err(err) => ODiskMessage::TorrentError(info_hash, err)
+            }
+        },
+        IDiskMessage::ExportResumeData(hash) => {
+            match execute_export_resume_data(hash, context) {
+                Ok(resume_bytes) => ODiskMessage::ResumeDataExported(hash, resume_bytes),
+                Err(err)         => ODiskMessage::TorrentError(hash, err)
+            }
+        },
+        IDiskMessage::LoadBlock(mut block) => {
+            match execute_load_block(&mut block, context) {
+                Ok(_)    => ODiskMessage::BlockLoaded(block),
+                Err(err) => ODiskMessage::LoadBlockError(block, err)
+            }
+        },
+        IDiskMessage::ProcessBlock(mut block) => {
+            match execute_process_block(&mut block, context, blocking_sender) {
+                Ok(_)    => ODiskMessage::BlockProcessed(block),
+                Err(err) => ODiskMessage::ProcessBlockError(block, err)
+            }
+        },
+        IDiskMessage::Shutdown => {
+            execute_shutdown(context, blocking_sender);
+
+            ODiskMessage::ShutdownComplete
+        }
+    }
+}
+
+/// Publish an `Error` event if the given message is an error.
+fn publish_error_event<F>(out_msg: &ODiskMessage, context: &DiskManagerContext<F>) {
+    let opt_event = match out_msg {
+        &ODiskMessage::TorrentError(hash, ref err)           => Some(TorrentEvent::Error(hash, err.to_string())),
+        &ODiskMessage::LoadBlockError(ref block, ref err)    => Some(TorrentEvent::Error(block.metadata().info_hash(), err.to_string())),
+        &ODiskMessage::ProcessBlockError(ref block, ref err) => Some(TorrentEvent::Error(block.metadata().info_hash(), err.to_string())),
+        _                                                    => None
+    };
+
+    if let Some(event) = opt_event {
+        context.publish_event(event);
+    }
+}
+
 fn execute_add_torrent<F>(file: Metainfo, context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> TorrentResult<()>
-    where F: FileSystem {
+    where F: FileSystem + Sync {
+    execute_add_torrent_with(file, None, &[], context, blocking_sender)
+}
+
+fn execute_add_torrent_with<F>(file: Metainfo, opt_resume: Option<&ResumeData>, file_priorities: &[FilePriority],
+                               context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> TorrentResult<()>
+    where F: FileSystem + Sync {
     let info_hash = file.info().info_hash();
-    let mut init_state = try!(PieceChecker::init_state(context.filesystem(), file.info()));
+    let cancel = context.start_check(info_hash);
+    let init_result = {
+        let mut send_progress = |pieces_done, pieces_total| {
+            blocking_sender.send(ODiskMessage::TorrentCheckProgress(info_hash, pieces_done, pieces_total))
+                .expect("bip_disk: Failed To Send Check Progress Message");
+            blocking_sender.flush()
+                .expect("bip_disk: Failed To Flush Check Progress Message");
+        };
+        let opt_progress: Option<&mut (FnMut(u64, u64) + Send)> = if context.check_progress() {
+            Some(&mut send_progress)
+        } else {
+            None
+        };
+
+        PieceChecker::init_state_with(context.filesystem(), file.info(), context.allocator(), &Sha1PieceHasher,
+                                      context.allocation_mode(), context.check_threads(), opt_progress,
+                                      opt_resume, file_priorities, Some(&cancel))
+    };
+    context.finish_check(info_hash);
+    let mut init_state = try!(init_result);
 
     // In case we are resuming a download, we need to send the diff for the newly added torrent
-    send_piece_diff(&mut init_state, info_hash, blocking_sender, true);
+    send_piece_diff(&mut init_state, file.info(), context, blocking_sender, true);
     
     if context.insert_torrent(file, init_state) {
         Ok(())
@@ -99,7 +207,7 @@ fn execute_sync_torrent<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> T
         for file in metainfo_file.info().files() {
             let path = helpers::build_path(opt_parent_dir, file);
 
-            sync_result = filesystem.sync_file(path);
+            sync_result = filesystem.sync_path(path);
         }
     });
 
@@ -110,6 +218,48 @@ fn execute_sync_torrent<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> T
     }
 }
 
+fn execute_export_resume_data<F>(hash: InfoHash, context: &DiskManagerContext<F>) -> TorrentResult<Vec<u8>>
+    where F: FileSystem {
+    let filesystem = context.filesystem();
+
+    let mut export_result = Ok(Vec::new());
+    let found_hash = context.update_torrent(hash, |metainfo_file, checker_state| {
+        let opt_parent_dir = metainfo_file.info().directory();
+
+        let mut file_sizes = Vec::new();
+        for file in metainfo_file.info().files() {
+            let path = helpers::build_path(opt_parent_dir, file);
+
+            match filesystem.open_file(path).and_then(|file| filesystem.file_size(&file)) {
+                Ok(size) => file_sizes.push(size),
+                Err(err) => { export_result = Err(err); return }
+            }
+        }
+
+        export_result = Ok(ResumeData::new(hash, checker_state.good_pieces(), file_sizes).to_bytes());
+    });
+
+    if found_hash {
+        Ok(try!(export_result))
+    } else {
+        Err(TorrentError::from_kind(TorrentErrorKind::InfoHashNotFound{ hash: hash }))
+    }
+}
+
+fn execute_shutdown<F>(context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>)
+    where F: FileSystem {
+    context.wait_for_other_work();
+
+    for hash in context.torrent_hashes() {
+        if let Err(err) = execute_sync_torrent(hash, context) {
+            blocking_sender.send(ODiskMessage::TorrentError(hash, err))
+                .expect("bip_disk: Failed To Send Shutdown Sync Error Message");
+            blocking_sender.flush()
+                .expect("bip_disk: Failed To Flush Shutdown Sync Error Message");
+        }
+    }
+}
+
 fn execute_load_block<F>(block: &mut BlockMut, context: &DiskManagerContext<F>) -> BlockResult<()>
     where F: FileSystem {
     let metadata = block.metadata();
@@ -131,26 +281,20 @@ fn execute_load_block<F>(block: &mut BlockMut, context: &DiskManagerContext<F>)
 }
 
 fn execute_process_block<F>(block: &mut Block, context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> BlockResult<()>
-    where F: FileSystem {
+    where F: FileSystem + Sync {
     let metadata = block.metadata();
     let info_hash = metadata.info_hash();
 
     let mut block_result = Ok(());
-    let found_hash = context.update_torrent(info_hash, |metainfo_file, mut checker_state| {
+    let found_hash = context.update_torrent(info_hash, |metainfo_file, checker_state| {
         info!("Processsing Block, Acquired Torrent Lock For {:?}", metainfo_file.info().info_hash());
 
         let piece_accessor = PieceAccessor::new(context.filesystem(), metainfo_file.info());
 
         // Write Out Piece Out To The Filesystem And Recalculate The Diff
-        block_result = piece_accessor.write_piece(&block, &metadata)
-            .and_then(|_| {
-                checker_state.add_pending_block(metadata);
-                
-                PieceChecker::with_state(context.filesystem(), metainfo_file.info(), &mut checker_state)
-                    .calculate_diff()
-            });
-
-        send_piece_diff(checker_state, metainfo_file.info().info_hash(), blocking_sender, false);
+        let write_result = piece_accessor.write_piece(&block, &metadata);
+        block_result = process_written_block(write_result, metadata, &piece_accessor, metainfo_file.info(), checker_state,
+                                             context, blocking_sender);
 
         info!("Processsing Block, Released Torrent Lock For {:?}", metainfo_file.info().info_hash());
     });
@@ -162,12 +306,160 @@ fn execute_process_block<F>(block: &mut Block, context: &DiskManagerContext<F>,
     }
 }
 
-fn send_piece_diff(checker_state: &mut PieceCheckerState, hash: InfoHash, blocking_sender: &mut Wait<Sender<ODiskMessage>>, ignore_bad: bool) {
+/// Take the blocks queued for coalescing and write them out, returning the message for each block.
+///
+/// Blocks are written in order of their torrent, then their position within the torrent (which is
+/// the order of the files and the offsets within them), so that runs of adjacent blocks can be merged.
+fn execute_queued_writes<F>(context: &DiskManagerContext<F>, blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> Vec<ODiskMessage>
+    where F: FileSystem + Sync {
+    let mut blocks = context.take_queued_writes();
+    blocks.sort_by_key(|block| {
+        let metadata = block.metadata();
+
+        (metadata.info_hash(), metadata.piece_index(), metadata.block_offset())
+    });
+
+    let mut out_msgs = Vec::with_capacity(blocks.len());
+    let mut blocks = blocks.into_iter().peekable();
+    while let Some(first_block) = blocks.next() {
+        let info_hash = first_block.metadata().info_hash();
+
+        let mut torrent_blocks = vec![first_block];
+        while blocks.peek().map(|block| block.metadata().info_hash() == info_hash).unwrap_or(false) {
+            torrent_blocks.extend(blocks.next());
+        }
+
+        let mut block_results = Vec::with_capacity(torrent_blocks.len());
+        let found_hash = context.update_torrent(info_hash, |metainfo_file, checker_state| {
+            info!("Processsing Queued Blocks, Acquired Torrent Lock For {:?}", info_hash);
+
+            let piece_accessor = PieceAccessor::new(context.filesystem(), metainfo_file.info());
+            let write_results = write_coalesced(&piece_accessor, metainfo_file.info().piece_length() as u64, &torrent_blocks);
+
+            for (write_result, block) in write_results.into_iter().zip(torrent_blocks.iter()) {
+                block_results.push(process_written_block(write_result, block.metadata(), &piece_accessor, metainfo_file.info(),
+                                                         checker_state, context, blocking_sender));
+            }
+
+            info!("Processsing Queued Blocks, Released Torrent Lock For {:?}", info_hash);
+        });
+
+        if !found_hash {
+            block_results = torrent_blocks.iter()
+                .map(|_| Err(BlockError::from_kind(BlockErrorKind::InfoHashNotFound{ hash: info_hash })))
+                .collect();
+        }
+
+        out_msgs.extend(torrent_blocks.into_iter().zip(block_results).map(|(block, block_result)| {
+            match block_result {
+                Ok(_)    => ODiskMessage::BlockProcessed(block),
+                Err(err) => ODiskMessage::ProcessBlockError(block, err)
+            }
+        }));
+    }
+
+    out_msgs
+}
+
+/// Write the given blocks, sorted by position, merging each run of adjacent blocks into a single write.
+///
+/// If a merged write fails, the blocks in that run are retried individually, so that each block gets its own result.
+fn write_coalesced<F>(piece_accessor: &PieceAccessor<F>, piece_length: u64, blocks: &[Block]) -> Vec<io::Result<()>>
+    where F: FileSystem {
+    let block_begin = |block: &Block| block.metadata().piece_index() * piece_length + block.metadata().block_offset();
+    let block_end = |block: &Block| block_begin(block) + block.metadata().block_length() as u64;
+
+    let mut write_results = Vec::with_capacity(blocks.len());
+    let mut run_begin = 0;
+    while run_begin < blocks.len() {
+        let mut run_end = run_begin + 1;
+        while run_end < blocks.len() && block_end(&blocks[run_end - 1]) == block_begin(&blocks[run_end]) {
+            run_end += 1;
+        }
+        let run = &blocks[run_begin..run_end];
+
+        if run.len() == 1 {
+            write_results.push(piece_accessor.write_piece(&run[0], &run[0].metadata()));
+        } else {
+            let first_metadata = run[0].metadata();
+            let run_buffer = run.iter().fold(Vec::new(), |mut run_buffer, block| {
+                run_buffer.extend_from_slice(&block[..block.metadata().block_length()]);
+                run_buffer
+            });
+            let run_metadata = BlockMetadata::new(first_metadata.info_hash(), first_metadata.piece_index(),
+                                                  first_metadata.block_offset(), run_buffer.len());
+
+            match piece_accessor.write_piece(&run_buffer, &run_metadata) {
+                Ok(_)  => write_results.extend(run.iter().map(|_| Ok(()))),
+                Err(_) => write_results.extend(run.iter().map(|block| piece_accessor.write_piece(block, &block.metadata())))
+            }
+        }
+
+        run_begin = run_end;
+    }
+
+    write_results
+}
+
+/// Check the given block, now that it has been written, then send the piece diff and sync any pieces that are now good.
+fn process_written_block<F>(write_result: io::Result<()>, metadata: BlockMetadata, piece_accessor: &PieceAccessor<&F>, info: &Info,
+                            checker_state: &mut PieceCheckerState, context: &DiskManagerContext<F>,
+                            blocking_sender: &mut Wait<Sender<ODiskMessage>>) -> BlockResult<()>
+    where F: FileSystem + Sync {
+    let info_hash = metadata.info_hash();
+
+    let mut block_result = write_result
+        .map_err(|err| err.into())
+        .and_then(|_| {
+            checker_state.add_pending_block(metadata);
+
+            PieceChecker::with_state(context.filesystem(), info, checker_state, context.allocator(), &Sha1PieceHasher)
+                .with_num_threads(context.check_threads())
+                .calculate_diff()
+                .map_err(|err| torrent_to_block_error(err, info_hash))
+        });
+
+    let good_pieces = send_piece_diff(checker_state, info, context, blocking_sender, false);
+
+    // Sync at piece boundaries so that completed pieces survive a crash
+    for piece_index in context.add_unsynced_pieces(info_hash, &good_pieces) {
+        if let Err(err) = piece_accessor.sync_piece(piece_index) {
+            block_result = Err(err.into());
+        }
+    }
+
+    block_result
+}
+
+/// Convert an error from checking a torrent into an error for the block that triggered the check.
+fn torrent_to_block_error(err: TorrentError, hash: InfoHash) -> BlockError {
+    match err {
+        TorrentError(TorrentErrorKind::Io(io_err), _)       => io_err.into(),
+        TorrentError(TorrentErrorKind::Block(block_err), _) => block_err,
+        err                                                  => BlockError::with_chain(err, BlockErrorKind::InvalidTorrent{ hash: hash })
+    }
+}
+
+/// Send messages for the pieces that have changed state, returning the indices of the pieces that are now good.
+///
+/// Events are published for the pieces that are now good, as well as any files, or the torrent, that they completed.
+fn send_piece_diff<F>(checker_state: &mut PieceCheckerState, info: &Info, context: &DiskManagerContext<F>,
+                      blocking_sender: &mut Wait<Sender<ODiskMessage>>, ignore_bad: bool) -> Vec<u64> {
+    let hash = info.info_hash();
+    let mut good_pieces = Vec::new();
+
     checker_state.run_with_diff(|piece_state| {
+        if let &PieceState::Good(index) = piece_state {
+            good_pieces.push(index);
+            context.publish_event(TorrentEvent::PieceCompleted(hash, index));
+        }
+
         let opt_out_msg = match (piece_state, ignore_bad) {
             (&PieceState::Good(index), _)    => Some(ODiskMessage::FoundGoodPiece(hash, index)),
             (&PieceState::Bad(index), false) => Some(ODiskMessage::FoundBadPiece(hash, index)),
-            (&PieceState::Bad(_), true)      => None
+            (&PieceState::Bad(_), true)      => None,
+            // Partial pieces are tracked for the checker, but only whole pieces are reported
+            (&PieceState::Partial(_), _)     => None
         };
 
         if let Some(out_msg) = opt_out_msg {
@@ -176,5 +468,354 @@ fn send_piece_diff(checker_state: &mut PieceCheckerState, hash: InfoHash, blocki
             blocking_sender.flush()
             .expect("bip_disk: Failed To Flush Piece State Message");
         }
-    })
-}
\ No newline at end of file
+    });
+
+    if !good_pieces.is_empty() {
+        publish_completion_events(checker_state, info, &good_pieces, context);
+    }
+
+    good_pieces
+}
+
+/// Publish events for any files, as well as the torrent, that were completed by the given newly good pieces.
+fn publish_completion_events<F>(checker_state: &PieceCheckerState, info: &Info, good_pieces: &[u64], context: &DiskManagerContext<F>) {
+    let hash = info.info_hash();
+    let piece_length = info.piece_length() as u64;
+
+    let mut file_start = 0;
+    for (file_index, file) in info.files().enumerate() {
+        let file_length = file.length() as u64;
+
+        // Empty files do not span any pieces, so they are never completed by one
+        if file_length != 0 {
+            let (first_piece, last_piece) = (file_start / piece_length, (file_start + file_length - 1) / piece_length);
+            let spans_good_piece = good_pieces.iter().any(|&index| first_piece <= index && index <= last_piece);
+
+            if spans_good_piece && (first_piece..(last_piece + 1)).all(|index| checker_state.is_good(index)) {
+                context.publish_event(TorrentEvent::FileCompleted(hash, file_index));
+            }
+        }
+        file_start += file_length;
+    }
+
+    let total_pieces = info.pieces().count() as u64;
+    if (0..total_pieces).all(|index| checker_state.is_good(index)) {
+        context.publish_event(TorrentEvent::TorrentFinished(hash));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use disk::{AllocationMode, SyncMode, IDiskMessage, ODiskMessage, TorrentEvent};
+    use disk::fs::FileSystem;
+    use disk::fs::memory::{InMemoryFile, InMemoryFileSystem};
+    use disk::tasks::context::DiskManagerContext;
+    use memory::block::{Block, BlockMetadata};
+
+    use bip_metainfo::{Accessor, DirectAccessor, IntoAccessor, Metainfo, MetainfoBuilder, PieceAccess, PieceLength};
+    use futures::stream::Stream;
+    use futures::sync::mpsc::{self, Receiver};
+    use futures_cpupool::CpuPool;
+
+    /// File system that counts the number of times file data is synced, and records the offset and length of each write.
+    struct RecordingFileSystem {
+        inner:  InMemoryFileSystem,
+        syncs:  Arc<AtomicUsize>,
+        writes: Arc<Mutex<Vec<(u64, usize)>>>
+    }
+
+    impl FileSystem for RecordingFileSystem {
+        type File = InMemoryFile;
+
+        fn open_file<P>(&self, path: P) -> io::Result<InMemoryFile>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.open_file(path)
+        }
+
+        fn sync_path<P>(&self, path: P) -> io::Result<()>
+            where P: AsRef<Path> + Send + 'static {
+            self.inner.sync_path(path)
+        }
+
+        fn sync_file(&self, file: &InMemoryFile) -> io::Result<()> {
+            self.syncs.fetch_add(1, Ordering::SeqCst);
+
+            self.inner.sync_file(file)
+        }
+
+        fn file_size(&self, file: &InMemoryFile) -> io::Result<u64> {
+            self.inner.file_size(file)
+        }
+
+        fn read_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
+            self.inner.read_file(file, offset, buffer)
+        }
+
+        fn write_file(&self, file: &mut InMemoryFile, offset: u64, buffer: &[u8]) -> io::Result<usize> {
+            self.writes.lock().unwrap().push((offset, buffer.len()));
+
+            self.inner.write_file(file, offset, buffer)
+        }
+
+        fn available_space(&self, path: &Path) -> io::Result<u64> {
+            self.inner.available_space(path)
+        }
+    }
+
+    /// Process every piece of a single file torrent of four pieces, returning the number of syncs that happened.
+    fn syncs_for_complete_torrent(sync_mode: SyncMode) -> usize {
+        let file_data = (0..4096).map(|index| index as u8).collect::<Vec<u8>>();
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, DirectAccessor::new("file", &file_data), |_| ()).unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info_hash = metainfo.info().info_hash();
+
+        let syncs = Arc::new(AtomicUsize::new(0));
+        let fs = RecordingFileSystem{ inner: InMemoryFileSystem::new(), syncs: syncs.clone(), writes: Arc::new(Mutex::new(Vec::new())) };
+
+        // Buffer enough messages that we never block on the receiver
+        let (send, _recv): (_, Receiver<ODiskMessage>) = mpsc::channel(100);
+        let context = DiskManagerContext::new(send, fs, 1, false, AllocationMode::Sparse, sync_mode);
+        let mut blocking_sender = context.blocking_sender();
+
+        super::execute_add_torrent(metainfo, &context, &mut blocking_sender).unwrap();
+        for piece_index in 0..4 {
+            let start = piece_index as usize * 1024;
+            let mut block = Block::new(BlockMetadata::new(info_hash, piece_index, 0, 1024),
+                                       file_data[start..(start + 1024)].to_vec().into());
+
+            super::execute_process_block(&mut block, &context, &mut blocking_sender).unwrap();
+        }
+
+        syncs.load(Ordering::SeqCst)
+    }
+
+    /// Process the given data as two blocks of a single piece torrent, returning the piece messages sent after each block.
+    fn piece_messages_for_blocks(file_data: &[u8], block_data: &[u8]) -> Vec<Vec<ODiskMessage>> {
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(2048))
+            .build(1, DirectAccessor::new("file", file_data), |_| ()).unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info_hash = metainfo.info().info_hash();
+
+        let (send, recv): (_, Receiver<ODiskMessage>) = mpsc::channel(100);
+        let context = DiskManagerContext::new(send, InMemoryFileSystem::new(), 1, false, AllocationMode::Sparse, SyncMode::Never);
+        let mut blocking_sender = context.blocking_sender();
+        let mut recv = recv.wait();
+
+        super::execute_add_torrent(metainfo, &context, &mut blocking_sender).unwrap();
+        let mut messages = Vec::new();
+        for block_offset in vec![0, 1024] {
+            let start = block_offset as usize;
+            let mut block = Block::new(BlockMetadata::new(info_hash, 0, block_offset, 1024),
+                                       block_data[start..(start + 1024)].to_vec().into());
+
+            super::execute_process_block(&mut block, &context, &mut blocking_sender).unwrap();
+
+            // Marker separates the messages sent for each block
+            blocking_sender.send(ODiskMessage::TorrentSynced(info_hash)).unwrap();
+            let block_messages = recv.by_ref()
+                .map(|msg| msg.unwrap())
+                .take_while(|msg| match msg { &ODiskMessage::TorrentSynced(_) => false, _ => true })
+                .filter(|msg| match msg { &ODiskMessage::FoundGoodPiece(..) | &ODiskMessage::FoundBadPiece(..) => true, _ => false })
+                .collect();
+            messages.push(block_messages);
+        }
+
+        messages
+    }
+
+    /// Accessor for a directory of two files, which are given as one buffer split at the given index.
+    struct TwoFileAccessor<'a> {
+        data:  &'a [u8],
+        split: usize
+    }
+
+    impl<'a> IntoAccessor for TwoFileAccessor<'a> {
+        type Accessor = TwoFileAccessor<'a>;
+
+        fn into_accessor(self) -> io::Result<TwoFileAccessor<'a>> {
+            Ok(self)
+        }
+    }
+
+    impl<'a> Accessor for TwoFileAccessor<'a> {
+        fn access_directory(&self) -> Option<&Path> {
+            Some(Path::new("dir"))
+        }
+
+        fn access_metadata<C>(&self, mut callback: C) -> io::Result<()>
+            where C: FnMut(u64, &Path) {
+            callback(self.split as u64, Path::new("a"));
+            callback((self.data.len() - self.split) as u64, Path::new("b"));
+
+            Ok(())
+        }
+
+        fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
+            where C: for<'b> FnMut(PieceAccess<'b>) -> io::Result<()> {
+            try!(callback(PieceAccess::Compute(&mut &self.data[..self.split])));
+
+            callback(PieceAccess::Compute(&mut &self.data[self.split..]))
+        }
+    }
+
+    #[test]
+    fn positive_subscribe_piece_file_and_torrent_events() {
+        // File a spans piece 0, and file b spans pieces 1 and 2
+        let file_data = (0..3072).map(|index| index as u8).collect::<Vec<u8>>();
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, TwoFileAccessor{ data: &file_data, split: 1024 }, |_| ()).unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info_hash = metainfo.info().info_hash();
+
+        let (send, _recv): (_, Receiver<ODiskMessage>) = mpsc::channel(100);
+        let context = DiskManagerContext::new(send, InMemoryFileSystem::new(), 1, false, AllocationMode::Sparse, SyncMode::Never);
+        let mut blocking_sender = context.blocking_sender();
+        let events = context.subscribe();
+
+        super::execute_add_torrent(metainfo, &context, &mut blocking_sender).unwrap();
+        for &piece_index in [0, 2, 1].iter() {
+            let start = piece_index as usize * 1024;
+            let mut block = Block::new(BlockMetadata::new(info_hash, piece_index, 0, 1024),
+                                       file_data[start..(start + 1024)].to_vec().into());
+
+            super::execute_process_block(&mut block, &context, &mut blocking_sender).unwrap();
+        }
+
+        let expected_events = vec![
+            TorrentEvent::PieceCompleted(info_hash, 0), TorrentEvent::FileCompleted(info_hash, 0),
+            TorrentEvent::PieceCompleted(info_hash, 2),
+            TorrentEvent::PieceCompleted(info_hash, 1), TorrentEvent::FileCompleted(info_hash, 1),
+            TorrentEvent::TorrentFinished(info_hash)
+        ];
+        let received_events = events.wait().take(expected_events.len()).map(|event| event.unwrap()).collect::<Vec<_>>();
+        assert_eq!(expected_events, received_events);
+    }
+
+    #[test]
+    fn positive_queued_writes_coalesce_adjacent_blocks() {
+        let file_data = (0..4096).map(|index| index as u8).collect::<Vec<u8>>();
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, DirectAccessor::new("file", &file_data), |_| ()).unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info_hash = metainfo.info().info_hash();
+
+        let inner = InMemoryFileSystem::new();
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let fs = RecordingFileSystem{ inner: inner.clone(), syncs: Arc::new(AtomicUsize::new(0)), writes: writes.clone() };
+
+        let (send, _recv): (_, Receiver<ODiskMessage>) = mpsc::channel(100);
+        let context = DiskManagerContext::new(send, fs, 1, false, AllocationMode::Sparse, SyncMode::Never);
+        let mut blocking_sender = context.blocking_sender();
+        context.set_write_coalescing(true);
+
+        super::execute_add_torrent(metainfo, &context, &mut blocking_sender).unwrap();
+        writes.lock().unwrap().clear();
+
+        // Queue blocks out of order, where all but the block in piece 3 are adjacent to one another
+        for &(piece_index, block_offset) in [(1, 0), (3, 0), (0, 512), (0, 0)].iter() {
+            let start = (piece_index * 1024 + block_offset) as usize;
+
+            context.queue_write(Block::new(BlockMetadata::new(info_hash, piece_index, block_offset, 512),
+                                           file_data[start..(start + 512)].to_vec().into()));
+        }
+        let out_msgs = super::execute_queued_writes(&context, &mut blocking_sender);
+
+        assert_eq!(vec![(0, 1536), (3072, 512)], *writes.lock().unwrap());
+
+        let processed = out_msgs.iter()
+            .map(|msg| match msg {
+                &ODiskMessage::BlockProcessed(ref block) => (block.metadata().piece_index(), block.metadata().block_offset()),
+                other                                     => panic!("Unexpected Message {:?}", other)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (0, 512), (1, 0), (3, 0)], processed);
+
+        let written_data = inner.run_with_lock(|files| files[Path::new("file")].clone());
+        assert_eq!(&file_data[..1536], &written_data[..1536]);
+        assert_eq!(&file_data[3072..3584], &written_data[3072..3584]);
+    }
+
+    #[test]
+    fn positive_process_block_verifies_good_piece() {
+        let file_data = (0..2048).map(|index| index as u8).collect::<Vec<u8>>();
+
+        let messages = piece_messages_for_blocks(&file_data, &file_data);
+
+        assert!(messages[0].is_empty());
+        match &messages[1][..] {
+            &[ODiskMessage::FoundGoodPiece(_, 0)] => (),
+            other => panic!("Unexpected Piece Messages {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_process_block_verifies_bad_piece() {
+        let file_data = (0..2048).map(|index| index as u8).collect::<Vec<u8>>();
+        let block_data = vec![0u8; 2048];
+
+        let messages = piece_messages_for_blocks(&file_data, &block_data);
+
+        assert!(messages[0].is_empty());
+        match &messages[1][..] {
+            &[ODiskMessage::FoundBadPiece(_, 0)] => (),
+            other => panic!("Unexpected Piece Messages {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_sync_mode_never() {
+        assert_eq!(0, syncs_for_complete_torrent(SyncMode::Never));
+    }
+
+    #[test]
+    fn positive_sync_mode_piece() {
+        assert_eq!(4, syncs_for_complete_torrent(SyncMode::Piece));
+    }
+
+    #[test]
+    fn positive_sync_mode_periodic() {
+        // Pieces found good after the last period are left unsynced
+        assert_eq!(3, syncs_for_complete_torrent(SyncMode::Periodic(3)));
+    }
+
+    #[test]
+    fn positive_add_torrent_after_bad_resume_data() {
+        let file_data = (0..2048).map(|index| index as u8).collect::<Vec<u8>>();
+        let metainfo_bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build(1, DirectAccessor::new("file", &file_data), |_| ()).unwrap();
+        let metainfo = Metainfo::from_bytes(metainfo_bytes).unwrap();
+        let info_hash = metainfo.info().info_hash();
+
+        let pool = CpuPool::new(1);
+        let (send, recv): (_, Receiver<ODiskMessage>) = mpsc::channel(100);
+        let context = DiskManagerContext::new(send, InMemoryFileSystem::new(), 1, false, AllocationMode::Sparse, SyncMode::Never);
+        let mut recv = recv.wait();
+
+        super::execute_on_pool(IDiskMessage::AddTorrentWithResumeData(metainfo.clone(), b"not resume data".to_vec()),
+                               &pool, context.clone());
+        match recv.next().unwrap().unwrap() {
+            ODiskMessage::TorrentError(hash, _) => assert_eq!(info_hash, hash),
+            other                               => panic!("Unexpected Message {:?}", other)
+        }
+
+        // Failed add should not have left its check behind, which a cancel would otherwise apply to the next add
+        assert!(!context.cancel_check(info_hash));
+
+        super::execute_on_pool(IDiskMessage::AddTorrent(metainfo), &pool, context.clone());
+        match recv.next().unwrap().unwrap() {
+            ODiskMessage::TorrentAdded(hash) => assert_eq!(info_hash, hash),
+            other                            => panic!("Unexpected Message {:?}", other)
+        }
+    }
+}
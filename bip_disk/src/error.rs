@@ -19,6 +19,12 @@ error_chain! {
             description("Failed To Load/Process Block Because Torrent Is Not Loaded")
             display("Failed To Load/Process Block Because The InfoHash {:?} It Is Not Currently Added", hash)
         }
+        InvalidTorrent {
+            hash: InfoHash
+        } {
+            description("Failed To Process Block Because The Torrent Could Not Be Checked")
+            display("Failed To Process Block Because The Torrent With InfoHash {:?} Could Not Be Checked", hash)
+        }
     }
 }
 
@@ -41,12 +47,51 @@ error_chain! {
             description("Failed To Add Torrent Because Size Checker Failed For A File")
             display("Failed To Add Torrent Because Size Checker Failed For {:?} Where File Size Was {} But Should Have Been {}", file_path, actual_size, expected_size)
         }
+        InvalidFilePath {
+            file_path: PathBuf
+        } {
+            description("Failed To Add Torrent Because A File Path Would Escape The Download Directory")
+            display("Failed To Add Torrent Because The File Path {:?} Would Escape The Download Directory", file_path)
+        }
+        MissingPieceHash {
+            piece_index: u64
+        } {
+            description("Failed To Check Piece Because The Info Dictionary Has No Hash For It")
+            display("Failed To Check Piece {} Because The Info Dictionary Has No Hash For It", piece_index)
+        }
+        InvalidPieceHashLength {
+            piece_index:     u64,
+            expected_length: usize,
+            actual_length:   usize
+        } {
+            description("Failed To Check Piece Because The Hash In The Info Dictionary Has The Wrong Length")
+            display("Failed To Check Piece {} Because The Hash In The Info Dictionary Was {} Bytes But Should Have Been {} Bytes", piece_index, actual_length, expected_length)
+        }
+        InsufficientSpace {
+            file_path: PathBuf,
+            needed:    u64
+        } {
+            description("Failed To Add Torrent Because There Was Not Enough Space To Allocate A File")
+            display("Failed To Add Torrent Because There Was Not Enough Space To Allocate {:?} Which Needed {} More Bytes", file_path, needed)
+        }
         ExistingInfoHash {
             hash: InfoHash
         } {
             description("Failed To Add Torrent Because Another Torrent With The Same InfoHash Is Already Added")
             display("Failed To Add Torrent Because Another Torrent With The Same InfoHash {:?} Is Already Added", hash)
         }
+        InvalidResumeData {
+            details: String
+        } {
+            description("Failed To Add Torrent Because The Resume Data Could Not Be Used")
+            display("Failed To Add Torrent Because The Resume Data Could Not Be Used: {}", details)
+        }
+        Cancelled {
+            hash: InfoHash
+        } {
+            description("Failed To Add Torrent Because Checking It Was Cancelled")
+            display("Failed To Add Torrent Because Checking The InfoHash {:?} Was Cancelled", hash)
+        }
         InfoHashNotFound {
             hash: InfoHash
         } {
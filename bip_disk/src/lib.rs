@@ -1,14 +1,18 @@
+#[macro_use]
+extern crate bip_bencode;
 extern crate bip_metainfo;
 extern crate bip_util;
 extern crate bytes;
 extern crate crossbeam;
 #[macro_use]
 extern crate error_chain;
+extern crate fs2;
 extern crate futures;
 extern crate futures_cpupool;
 #[macro_use]
 extern crate log;
 extern crate lru_cache;
+extern crate memmap;
 
 mod disk;
 mod memory;
@@ -16,15 +20,19 @@ mod memory;
 /// Both `Block` and `Torrent` error types.
 pub mod error;
 
-pub use disk::{IDiskMessage, ODiskMessage};
+pub use disk::{AllocationMode, FilePriority, SyncMode, IDiskMessage, ODiskMessage, TorrentEvent};
 pub use disk::fs::FileSystem;
+pub use disk::resume::ResumeData;
 pub use disk::builder::DiskManagerBuilder;
 pub use disk::manager::{DiskManager, DiskManagerSink, DiskManagerStream};
 
+pub use memory::allocator::{BlockAllocator, PooledBlockAllocator};
 pub use memory::block::{Block, BlockMut, BlockMetadata};
 
 /// Built in objects implementing `FileSystem`.
 pub mod fs {
+    pub use disk::fs::memory::{InMemoryFile, InMemoryFileSystem};
+    pub use disk::fs::mmap::{MmapFile, MmapFileSystem};
     pub use disk::fs::native::{NativeFile, NativeFileSystem};
 }
 
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+
+const DEFAULT_MAX_BUFFERS: usize = 16;
+
+/// Trait for handing out and recycling buffers used when reading in whole pieces.
+pub trait BlockAllocator: Sync {
+    /// Allocate a buffer of exactly the given length.
+    ///
+    /// The contents of the buffer are unspecified, callers should overwrite any bytes they read.
+    fn allocate(&self, length: usize) -> Vec<u8>;
+
+    /// Give a buffer back to the allocator so that it may be reused.
+    fn deallocate(&self, buffer: Vec<u8>);
+}
+
+/// `BlockAllocator` which keeps a pool of previously allocated buffers around for reuse.
+///
+/// At most `max_buffers` buffers are kept in the pool, any extra buffers given back are dropped.
+pub struct PooledBlockAllocator {
+    buffers:     Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize
+}
+
+impl PooledBlockAllocator {
+    /// Create a new `PooledBlockAllocator`.
+    pub fn new() -> PooledBlockAllocator {
+        PooledBlockAllocator::with_max_buffers(DEFAULT_MAX_BUFFERS)
+    }
+
+    /// Create a new `PooledBlockAllocator` which will pool at most `max_buffers` buffers.
+    pub fn with_max_buffers(max_buffers: usize) -> PooledBlockAllocator {
+        PooledBlockAllocator{ buffers: Mutex::new(Vec::new()), max_buffers: max_buffers }
+    }
+
+    /// Number of buffers currently sitting in the pool.
+    pub fn pooled_buffers(&self) -> usize {
+        self.buffers.lock()
+            .expect("bip_disk: PooledBlockAllocator::pooled_buffers Failed To Lock Buffers")
+            .len()
+    }
+}
+
+impl BlockAllocator for PooledBlockAllocator {
+    fn allocate(&self, length: usize) -> Vec<u8> {
+        let opt_buffer = self.buffers.lock()
+            .expect("bip_disk: PooledBlockAllocator::allocate Failed To Lock Buffers")
+            .pop();
+
+        match opt_buffer {
+            Some(mut buffer) => {
+                buffer.resize(length, 0);
+
+                buffer
+            },
+            None => vec![0u8; length]
+        }
+    }
+
+    fn deallocate(&self, buffer: Vec<u8>) {
+        let mut buffers = self.buffers.lock()
+            .expect("bip_disk: PooledBlockAllocator::deallocate Failed To Lock Buffers");
+
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
+
+impl Default for PooledBlockAllocator {
+    fn default() -> PooledBlockAllocator {
+        PooledBlockAllocator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memory::allocator::{BlockAllocator, PooledBlockAllocator};
+
+    #[test]
+    fn positive_allocate_reuses_buffer() {
+        let allocator = PooledBlockAllocator::new();
+
+        let buffer = allocator.allocate(1024);
+        let buffer_ptr = buffer.as_ptr();
+        allocator.deallocate(buffer);
+
+        assert_eq!(1, allocator.pooled_buffers());
+
+        let buffer = allocator.allocate(1024);
+        assert_eq!(buffer_ptr, buffer.as_ptr());
+        assert_eq!(0, allocator.pooled_buffers());
+    }
+
+    #[test]
+    fn positive_allocate_resizes_reused_buffer() {
+        let allocator = PooledBlockAllocator::new();
+
+        allocator.deallocate(allocator.allocate(16));
+        assert_eq!(8, allocator.allocate(8).len());
+
+        allocator.deallocate(allocator.allocate(8));
+        assert_eq!(32, allocator.allocate(32).len());
+    }
+
+    #[test]
+    fn positive_deallocate_respects_max_buffers() {
+        let allocator = PooledBlockAllocator::with_max_buffers(1);
+
+        allocator.deallocate(vec![0u8; 4]);
+        allocator.deallocate(vec![0u8; 4]);
+
+        assert_eq!(1, allocator.pooled_buffers());
+    }
+}
@@ -1 +1,2 @@
+pub mod allocator;
 pub mod block;
\ No newline at end of file
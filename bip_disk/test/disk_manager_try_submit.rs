@@ -0,0 +1,57 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage};
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use tokio_core::reactor::{Core};
+use futures::future::{Future};
+use futures::stream::Stream;
+
+#[test]
+fn positive_disk_manager_try_submit_saturated() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(50), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager that can only have a single message pending
+    let filesystem = InMemoryFileSystem::new();
+    let (m_send, m_recv) = DiskManagerBuilder::new()
+        .with_sink_buffer_capacity(1)
+        .build(filesystem.clone())
+        .into_parts();
+
+    let mut core = Core::new().unwrap();
+
+    // Add a torrent, which saturates the sink until we receive the torrent added message
+    m_send.try_submit(IDiskMessage::AddTorrent(metainfo_file)).unwrap();
+
+    // Try to submit a remove message, which should be handed back to us
+    match m_send.try_submit(IDiskMessage::RemoveTorrent(info_hash)) {
+        Err(IDiskMessage::RemoveTorrent(hash)) => assert_eq!(info_hash, hash),
+        _                                      => panic!("Unexpected Result From Try Submit Test")
+    };
+
+    // Receive from our stream to free up the sink
+    let (opt_msg, m_recv) = core.run(m_recv.into_future().map_err(|_| ())).unwrap();
+    match opt_msg {
+        Some(ODiskMessage::TorrentAdded(_)) => (),
+        unexpected @ _                      => panic!("Unexpected Message: {:?}", unexpected)
+    };
+
+    // Try to submit the remove message again which should go through
+    m_send.try_submit(IDiskMessage::RemoveTorrent(info_hash)).unwrap();
+
+    // Receive confirmation (just so the pool doesnt panic because we ended before it could send the message back)
+    let (opt_msg, _) = core.run(m_recv.into_future().map_err(|_| ())).unwrap();
+    match opt_msg {
+        Some(ODiskMessage::TorrentRemoved(_)) => (),
+        unexpected @ _                        => panic!("Unexpected Message: {:?}", unexpected)
+    };
+}
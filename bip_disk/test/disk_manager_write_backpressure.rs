@@ -0,0 +1,104 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, BlockMetadata, Block};
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use bip_util::bt::InfoHash;
+use bytes::BytesMut;
+use tokio_core::reactor::{Core};
+use futures::future::{Future};
+use futures::stream::Stream;
+use futures::sink::Sink;
+use futures::{future, AsyncSink};
+
+/// Create a block, which does not complete a piece, at the given offset in the second piece.
+fn partial_block(hash: InfoHash, data: &[u8], block_offset: u64) -> Block {
+    let mut bytes = BytesMut::new();
+    bytes.extend_from_slice(&data[(1 + block_offset as usize)..(1 + block_offset as usize + 50)]);
+
+    Block::new(BlockMetadata::new(hash, 1, block_offset, 50), bytes.freeze())
+}
+
+#[test]
+fn positive_disk_manager_write_backpressure() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager, with plenty of sink capacity, but only a single pending write
+    let filesystem = InMemoryFileSystem::new();
+    let (m_send, m_recv) = DiskManagerBuilder::new()
+        .with_sink_buffer_capacity(10)
+        .with_write_queue_depth(1)
+        .build(filesystem.clone())
+        .into_parts();
+
+    let mut core = Core::new().unwrap();
+
+    // Add our torrent, and wait for it to be added so it is not holding up the sink
+    let m_send = core.run(m_send.send(IDiskMessage::AddTorrent(metainfo_file))).unwrap();
+    let (msg, m_recv) = core.run(m_recv.into_future().map_err(|_| ())).unwrap();
+    match msg {
+        Some(ODiskMessage::TorrentAdded(_)) => (),
+        unexpected @ _                      => panic!("Unexpected Message: {:?}", unexpected)
+    };
+
+    // Fill up our write queue
+    let m_send = core.run(m_send.send(IDiskMessage::ProcessBlock(partial_block(info_hash, &data_b.0, 0)))).unwrap();
+
+    // Try to send another block (but it should fail), while other messages still go through
+    let (result, m_send) = core.run(future::lazy(|| {
+        let mut m_send = m_send;
+        let result = m_send.start_send(IDiskMessage::ProcessBlock(partial_block(info_hash, &data_b.0, 50)));
+
+        future::ok::<_, ()>((result, m_send))
+    })).unwrap();
+    let pending_block = match result {
+        Ok(AsyncSink::NotReady(IDiskMessage::ProcessBlock(block))) => block,
+        _                                                          => panic!("Unexpected Result From Write Backpressure Test")
+    };
+
+    let m_send = core.run(m_send.send(IDiskMessage::SyncTorrent(info_hash))).unwrap();
+
+    // Receive the processed block (and synced torrent) to unblock the backpressure
+    let (msgs, m_recv) = core.run(next_messages(m_recv, 2)).unwrap();
+    assert!(msgs.iter().any(|msg| match msg {
+        &ODiskMessage::BlockProcessed(_) => true,
+        _                                => false
+    }));
+
+    // Try to send our block again which should go through
+    let _ = core.run(m_send.send(IDiskMessage::ProcessBlock(pending_block))).unwrap();
+
+    // Receive confirmation (just so the pool doesnt panic because we ended before it could send the message back)
+    let (msgs, _) = core.run(next_messages(m_recv, 1)).unwrap();
+    match msgs[0] {
+        ODiskMessage::BlockProcessed(_) => (),
+        ref unexpected                  => panic!("Unexpected Message: {:?}", unexpected)
+    };
+}
+
+/// Receive the given number of messages from the stream, handing back the stream afterwards.
+fn next_messages<S>(stream: S, count: usize) -> Box<Future<Item=(Vec<ODiskMessage>, S), Error=()>>
+    where S: Stream<Item=ODiskMessage> + 'static {
+    Box::new(future::loop_fn((Vec::new(), stream), move |(mut msgs, stream)| {
+        stream.into_future()
+            .map_err(|_| ())
+            .map(move |(opt_msg, stream)| {
+                msgs.push(opt_msg.unwrap_or_else(|| panic!("End Of Stream Reached")));
+
+                if msgs.len() == count {
+                    future::Loop::Break((msgs, stream))
+                } else {
+                    future::Loop::Continue((msgs, stream))
+                }
+            })
+    }))
+}
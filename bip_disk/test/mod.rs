@@ -6,14 +6,12 @@ extern crate futures;
 extern crate tokio_core;
 extern crate rand;
 
-use std::collections::HashMap;
 use std::io::{self};
 use std::path::{Path, PathBuf};
-use std::sync::{Mutex, Arc};
-use std::cmp;
 use std::time::Duration;
 
-use bip_disk::{FileSystem, IDiskMessage, BlockMetadata, BlockMut};
+use bip_disk::{IDiskMessage, BlockMetadata, BlockMut};
+use bip_disk::fs::InMemoryFileSystem;
 use bip_metainfo::{IntoAccessor, Accessor, PieceAccess};
 use bip_util::bt::InfoHash;
 use bytes::BytesMut;
@@ -25,11 +23,14 @@ use futures::sink::{Sink, Wait};
 
 mod add_torrent;
 mod disk_manager_send_backpressure;
+mod disk_manager_try_submit;
+mod disk_manager_write_backpressure;
 mod complete_torrent;
 mod load_block;
 mod process_block;
 mod remove_torrent;
 mod resume_torrent;
+mod shutdown;
 
 /// Generate buffer of size random bytes.
 fn random_buffer(size: usize) -> Vec<u8> {
@@ -134,98 +135,3 @@ impl Accessor for MultiFileDirectAccessor {
         Ok(())
     }
 }
-
-//----------------------------------------------------------------------------//
-
-/// Allow us to mock out the file system.
-#[derive(Clone)]
-struct InMemoryFileSystem {
-    files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>
-}
-
-impl InMemoryFileSystem {
-    pub fn new() -> InMemoryFileSystem {
-        InMemoryFileSystem{ files: Arc::new(Mutex::new(HashMap::new())) }
-    }
-
-    pub fn run_with_lock<C, R>(&self, call: C) -> R
-        where C: FnOnce(&mut HashMap<PathBuf, Vec<u8>>) -> R {
-        let mut lock_files = self.files.lock().unwrap();
-
-        call(&mut *lock_files)
-    }
-}
-
-struct InMemoryFile {
-    path: PathBuf
-}
-
-impl FileSystem for InMemoryFileSystem {
-    type File = InMemoryFile;
-
-    fn open_file<P>(&self, path: P) -> io::Result<Self::File> 
-        where P: AsRef<Path> + Send + 'static {
-        let file_path = path.as_ref().to_path_buf();
-
-        self.run_with_lock(|files| {
-            if !files.contains_key(&file_path) {
-                files.insert(file_path.clone(), Vec::new());
-            }
-        });
-
-        Ok(InMemoryFile{ path: file_path })
-    }
-
-    fn sync_file<P>(&self, _path: P) -> io::Result<()>
-        where P: AsRef<Path> + Send + 'static {
-        Ok(())
-    }
-
-    fn file_size(&self, file: &Self::File) -> io::Result<u64> {
-        self.run_with_lock(|files| {
-            files.get(&file.path)
-                .map(|file| file.len() as u64)
-                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
-        })
-    }
-
-    fn read_file(&self, file: &mut Self::File, offset: u64, buffer: &mut [u8]) -> io::Result<usize> {
-        self.run_with_lock(|files| {
-            files.get(&file.path)
-                .map(|file_buffer| {
-                    let cast_offset = offset as usize;
-                    let bytes_to_copy = cmp::min(file_buffer.len() - cast_offset, buffer.len());
-                    let bytes = &file_buffer[cast_offset..(bytes_to_copy + cast_offset)];
-
-                    buffer.clone_from_slice(bytes);
-
-                    bytes_to_copy
-                })
-                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
-        })
-    }
-
-    fn write_file(&self, file: &mut Self::File, offset: u64, buffer: &[u8]) -> io::Result<usize> {
-        self.run_with_lock(|files| {
-            files.get_mut(&file.path)
-                .map(|file_buffer| {
-                    let cast_offset = offset as usize;
-
-                    let last_byte_pos = cast_offset + buffer.len();
-                    if last_byte_pos > file_buffer.len() {
-                        file_buffer.resize(last_byte_pos, 0);
-                    }
-
-                    let bytes_to_copy = cmp::min(file_buffer.len() - cast_offset, buffer.len());
-                    
-                    if bytes_to_copy != 0 {
-                        file_buffer[cast_offset..(cast_offset + bytes_to_copy)].clone_from_slice(buffer);
-                    }
-
-                    // TODO: If the file is full, this will return zero, we should also simulate io::ErrorKind::WriteZero
-                    bytes_to_copy
-                })
-                .ok_or(io::Error::new(io::ErrorKind::NotFound, "File Not Found"))
-        })
-    }
-}
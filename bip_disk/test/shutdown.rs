@@ -0,0 +1,74 @@
+use {MultiFileDirectAccessor, InMemoryFileSystem};
+use bip_disk::{DiskManagerBuilder, IDiskMessage, ODiskMessage, FileSystem};
+use bip_metainfo::{MetainfoBuilder, PieceLength, Metainfo};
+use tokio_core::reactor::{Core};
+use futures::future::{Loop};
+use futures::stream::Stream;
+use futures::sink::{Sink};
+
+#[test]
+fn positive_shutdown_after_pending_writes() {
+    // Create some "files" as random bytes
+    let data_a = (::random_buffer(1023), "/path/to/file/a".into());
+    let data_b = (::random_buffer(2000), "/path/to/file/b".into());
+
+    // Create our accessor for our in memory files and create a torrent file for them
+    let files_accessor = MultiFileDirectAccessor::new("/my/downloads/".into(),
+        vec![data_a.clone(), data_b.clone()]);
+    let metainfo_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(1024))
+        .build(1, files_accessor, |_| ()).unwrap();
+    let metainfo_file = Metainfo::from_bytes(metainfo_bytes).unwrap();
+    let info_hash = metainfo_file.info().info_hash();
+
+    // Spin up a disk manager and add our created torrent to it
+    let filesystem = InMemoryFileSystem::new();
+    let disk_manager = DiskManagerBuilder::new()
+        .build(filesystem.clone());
+
+    let (send, recv) = disk_manager.split();
+    let mut blocking_send = send.wait();
+    blocking_send.send(IDiskMessage::AddTorrent(metainfo_file.clone())).unwrap();
+
+    let mut core = Core::new().unwrap();
+    let recv = ::core_loop_with_timeout(&mut core, 500, ((), recv), |_, recv, msg| {
+        match msg {
+            ODiskMessage::TorrentAdded(_) => Loop::Break(recv),
+            unexpected @ _                => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+
+    let mut files_bytes = Vec::new();
+    files_bytes.extend_from_slice(&data_a.0);
+    files_bytes.extend_from_slice(&data_b.0);
+
+    // Queue up writes for the whole torrent, then immediately shut down
+    ::send_block(&mut blocking_send, &files_bytes[0..1024], info_hash, 0, 0, 1024, |_| ());
+    ::send_block(&mut blocking_send, &files_bytes[1024..2048], info_hash, 1, 0, 1024, |_| ());
+    ::send_block(&mut blocking_send, &files_bytes[2048..2548], info_hash, 2, 0, 500, |_| ());
+    ::send_block(&mut blocking_send, &files_bytes[2548..3023], info_hash, 2, 500, 475, |_| ());
+    blocking_send.send(IDiskMessage::Shutdown).unwrap();
+
+    // Every write should be processed before the shutdown completes
+    let blocks_processed = ::core_loop_with_timeout(&mut core, 500, (0, recv), |blocks_processed, recv, msg| {
+        match msg {
+            ODiskMessage::BlockProcessed(_)    => Loop::Continue((blocks_processed + 1, recv)),
+            ODiskMessage::FoundGoodPiece(_, _) => Loop::Continue((blocks_processed, recv)),
+            ODiskMessage::ShutdownComplete     => Loop::Break(blocks_processed),
+            unexpected @ _                     => panic!("Unexpected Message: {:?}", unexpected)
+        }
+    });
+    assert_eq!(4, blocks_processed);
+
+    // Messages sent after shutting down are rejected
+    assert!(blocking_send.send(IDiskMessage::SyncTorrent(info_hash)).is_err());
+
+    // Verify all of the data landed in the files
+    for (expected_data, path) in vec![data_a, data_b] {
+        let mut received_file = filesystem.open_file(path).unwrap();
+
+        let mut received_data = vec![0u8; expected_data.len()];
+        assert_eq!(expected_data.len(), filesystem.read_file(&mut received_file, 0, &mut received_data).unwrap());
+        assert_eq!(expected_data, received_data);
+    }
+}
@@ -0,0 +1,361 @@
+use std::any::Any;
+use std::cmp;
+use std::io::{self, BufRead};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use filter::{FilterDecision, HandshakeFilter};
+
+// Access levels at or above this in a .dat list mean the range is allowed
+const DAT_ALLOWED_LEVEL: u32 = 128;
+
+/// Filter blocking peers whose address falls within any of a set of ip ranges.
+///
+/// Ranges are kept sorted and merged, so that lookups are a binary search. Lists
+/// can be loaded from the P2P (PeerGuardian) text format, the eMule `.dat` format,
+/// or a list of CIDR blocks, which may be mixed within the same list.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockList {
+    v4_ranges: Vec<(u32, u32)>,
+    v6_ranges: Vec<(u128, u128)>
+}
+
+impl BlockList {
+    /// Create a new, empty, `BlockList`.
+    pub fn new() -> BlockList {
+        BlockList::default()
+    }
+
+    /// Load a `BlockList` from the given reader.
+    ///
+    /// Each line may be one of the following, while empty lines and lines starting
+    /// with `#` or `//` are ignored:
+    ///
+    /// * P2P format, `Some Description:1.2.3.0-1.2.3.255`
+    /// * `.dat` format, `001.002.003.000 - 001.002.003.255 , 000 , Some Description`, where
+    ///   ranges with an access level of 128 or more are allowed, and so skipped
+    /// * CIDR format, `1.2.3.0/24` or `2001:db8::/32`
+    pub fn from_reader<R>(reader: R) -> io::Result<BlockList>
+        where R: BufRead {
+        let mut block_list = BlockList::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = try!(line);
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue
+            }
+
+            match parse_line(line) {
+                Some(Some((start, end))) => block_list.block_range(start, end),
+                Some(None)               => (),
+                None                     => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                                      format!("Invalid Block List Entry On Line {}", index + 1)))
+            }
+        }
+
+        Ok(block_list)
+    }
+
+    /// Block every address from start to end, inclusive.
+    ///
+    /// Ranges where start and end are of different address families, or where end
+    /// comes before start, are ignored.
+    pub fn block_range(&mut self, start: IpAddr, end: IpAddr) {
+        match (start, end) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => insert_range(&mut self.v4_ranges, u32::from(start), u32::from(end)),
+            (IpAddr::V6(start), IpAddr::V6(end)) => insert_range(&mut self.v6_ranges, u128::from(start), u128::from(end)),
+            _                                    => ()
+        }
+    }
+
+    /// Block every address within the given CIDR block.
+    ///
+    /// Prefix lengths longer than the address are treated as a single address.
+    pub fn block_cidr(&mut self, addr: IpAddr, prefix_len: u8) {
+        let (start, end) = cidr_range(addr, prefix_len);
+
+        self.block_range(start, end);
+    }
+
+    /// Whether or not the given address is blocked.
+    ///
+    /// IPv4 mapped IPv6 addresses are checked against the IPv4 ranges.
+    pub fn is_blocked(&self, addr: &IpAddr) -> bool {
+        match *addr {
+            IpAddr::V4(addr) => contains(&self.v4_ranges, u32::from(addr)),
+            IpAddr::V6(addr) => {
+                match addr.to_ipv4_mapped() {
+                    Some(v4_addr) => contains(&self.v4_ranges, u32::from(v4_addr)),
+                    None          => contains(&self.v6_ranges, u128::from(addr))
+                }
+            }
+        }
+    }
+
+    /// Number of disjoint ranges being blocked.
+    pub fn num_ranges(&self) -> usize {
+        self.v4_ranges.len() + self.v6_ranges.len()
+    }
+}
+
+impl HandshakeFilter for BlockList {
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn on_addr(&self, opt_addr: Option<&SocketAddr>) -> FilterDecision {
+        match opt_addr {
+            Some(addr) if self.is_blocked(&addr.ip()) => FilterDecision::Block,
+            _                                         => FilterDecision::Pass
+        }
+    }
+}
+
+/// Insert the range, merging it with any ranges it overlaps or touches.
+fn insert_range<T>(ranges: &mut Vec<(T, T)>, start: T, end: T)
+    where T: Ord + Copy + Successor {
+    if end < start {
+        return
+    }
+
+    // First range that could be merged with ours, ranges are disjoint so their ends are sorted as well
+    let first = match ranges.binary_search_by(|&(_, range_end)| range_end.successor().cmp(&start)) {
+        Ok(index) | Err(index) => index
+    };
+    let mut last = first;
+    let (mut merged_start, mut merged_end) = (start, end);
+
+    while last < ranges.len() && ranges[last].0 <= end.successor() {
+        merged_start = cmp::min(merged_start, ranges[last].0);
+        merged_end = cmp::max(merged_end, ranges[last].1);
+        last += 1;
+    }
+
+    ranges.splice(first..last, Some((merged_start, merged_end)));
+}
+
+fn contains<T>(ranges: &[(T, T)], addr: T) -> bool
+    where T: Ord + Copy {
+    match ranges.binary_search_by(|&(start, _)| start.cmp(&addr)) {
+        Ok(_)      => true,
+        Err(0)     => false,
+        Err(index) => addr <= ranges[index - 1].1
+    }
+}
+
+/// Next value, saturating at the maximum value.
+trait Successor {
+    fn successor(self) -> Self;
+}
+
+impl Successor for u32 {
+    fn successor(self) -> u32 {
+        self.saturating_add(1)
+    }
+}
+
+impl Successor for u128 {
+    fn successor(self) -> u128 {
+        self.saturating_add(1)
+    }
+}
+
+/// First and last address within the given CIDR block.
+fn cidr_range(addr: IpAddr, prefix_len: u8) -> (IpAddr, IpAddr) {
+    match addr {
+        IpAddr::V4(addr) => {
+            let host_bits = 32 - cmp::min(prefix_len as u32, 32);
+            let mask = u32::max_value().checked_shl(host_bits).unwrap_or(0);
+            let start = u32::from(addr) & mask;
+
+            (IpAddr::V4(Ipv4Addr::from(start)), IpAddr::V4(Ipv4Addr::from(start | !mask)))
+        },
+        IpAddr::V6(addr) => {
+            let host_bits = 128 - cmp::min(prefix_len as u32, 128);
+            let mask = u128::max_value().checked_shl(host_bits).unwrap_or(0);
+            let start = u128::from(addr) & mask;
+
+            (IpAddr::V6(Ipv6Addr::from(start)), IpAddr::V6(Ipv6Addr::from(start | !mask)))
+        }
+    }
+}
+
+/// Parse a single line into the range it blocks, or `Some(None)` if the range is allowed.
+fn parse_line(line: &str) -> Option<Option<(IpAddr, IpAddr)>> {
+    if line.contains(',') {
+        // .dat format, range followed by access level and description
+        let mut fields = line.split(',');
+        let range = fields.next().and_then(parse_range);
+        let level = fields.next().and_then(|level| level.trim().parse::<u32>().ok());
+
+        match (range, level) {
+            (Some(range), Some(level)) => Some(if level < DAT_ALLOWED_LEVEL { Some(range) } else { None }),
+            _                          => None
+        }
+    } else {
+        parse_cidr(line).or_else(|| parse_p2p_range(line)).map(Some)
+    }
+}
+
+/// Parse a P2P format range, which is optionally preceded by a description and a colon.
+///
+/// Both the description and an IPv6 start address may contain colons, so the start address
+/// begins after the first colon that leaves an address of the same family as the end address.
+fn parse_p2p_range(line: &str) -> Option<(IpAddr, IpAddr)> {
+    let (start_part, opt_end) = match line.rfind('-') {
+        Some(index) => (&line[..index], parse_ip(&line[index + 1..])),
+        None        => return None
+    };
+
+    opt_end.and_then(|end| {
+        let colon_suffixes = start_part.match_indices(':').map(|(index, _)| &start_part[index + 1..]);
+
+        Some(start_part).into_iter()
+            .chain(colon_suffixes)
+            .filter_map(parse_ip)
+            .find(|start| start.is_ipv4() == end.is_ipv4())
+            .map(|start| (start, end))
+    })
+}
+
+fn parse_cidr(cidr: &str) -> Option<(IpAddr, IpAddr)> {
+    let mut parts = cidr.splitn(2, '/');
+    let addr = parts.next().and_then(parse_ip);
+    let prefix_len = parts.next().and_then(|prefix_len| prefix_len.trim().parse::<u8>().ok());
+
+    match (addr, prefix_len) {
+        (Some(addr), Some(prefix_len)) => Some(cidr_range(addr, prefix_len)),
+        _                              => None
+    }
+}
+
+fn parse_range(range: &str) -> Option<(IpAddr, IpAddr)> {
+    let mut bounds = range.splitn(2, '-');
+
+    match (bounds.next().and_then(parse_ip), bounds.next().and_then(parse_ip)) {
+        (Some(start), Some(end)) => Some((start, end)),
+        _                        => None
+    }
+}
+
+/// Parse an ip address, accepting IPv4 octets padded with leading zeros as found in .dat lists.
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    let addr = addr.trim();
+
+    addr.parse::<IpAddr>().ok().or_else(|| {
+        let octets = addr.split('.').map(|octet| octet.parse::<u8>().ok()).collect::<Option<Vec<u8>>>();
+
+        match octets {
+            Some(ref octets) if octets.len() == 4 => Some(IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))),
+            _                                     => None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use super::BlockList;
+    use filter::{FilterDecision, HandshakeFilter};
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn positive_range_boundaries() {
+        let mut block_list = BlockList::new();
+        block_list.block_range(ip("10.0.0.10"), ip("10.0.0.20"));
+
+        assert!(!block_list.is_blocked(&ip("10.0.0.9")));
+        assert!(block_list.is_blocked(&ip("10.0.0.10")));
+        assert!(block_list.is_blocked(&ip("10.0.0.15")));
+        assert!(block_list.is_blocked(&ip("10.0.0.20")));
+        assert!(!block_list.is_blocked(&ip("10.0.0.21")));
+        assert!(block_list.is_blocked(&ip("::ffff:10.0.0.10")));
+    }
+
+    #[test]
+    fn positive_cidr_boundaries() {
+        let mut block_list = BlockList::new();
+        block_list.block_cidr(ip("192.168.1.77"), 24);
+        block_list.block_cidr(ip("2001:db8::"), 32);
+
+        assert!(!block_list.is_blocked(&ip("192.168.0.255")));
+        assert!(block_list.is_blocked(&ip("192.168.1.0")));
+        assert!(block_list.is_blocked(&ip("192.168.1.255")));
+        assert!(!block_list.is_blocked(&ip("192.168.2.0")));
+        assert!(block_list.is_blocked(&ip("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!block_list.is_blocked(&ip("2001:db9::")));
+    }
+
+    #[test]
+    fn positive_ranges_merged() {
+        let mut block_list = BlockList::new();
+        block_list.block_range(ip("10.0.0.30"), ip("10.0.0.40"));
+        block_list.block_range(ip("10.0.0.0"), ip("10.0.0.9"));
+        block_list.block_range(ip("10.0.0.10"), ip("10.0.0.29"));
+        block_list.block_range(ip("10.0.0.50"), ip("10.0.0.60"));
+        block_list.block_range(ip("255.255.255.255"), ip("255.255.255.255"));
+
+        assert_eq!(3, block_list.num_ranges());
+        assert!(block_list.is_blocked(&ip("10.0.0.29")));
+        assert!(!block_list.is_blocked(&ip("10.0.0.45")));
+        assert!(block_list.is_blocked(&ip("255.255.255.255")));
+    }
+
+    #[test]
+    fn positive_load_mixed_list() {
+        let list = b"# Comment\n\
+                     Bad Peers: Inc/Co:1.2.3.0-1.2.3.255\n\
+                     \n\
+                     004.005.006.000 - 004.005.006.010 , 000 , Dat Range\n\
+                     007.008.009.000 - 007.008.009.255 , 200 , Allowed Dat Range\n\
+                     10.0.0.0/8\n";
+        let block_list = BlockList::from_reader(&list[..]).unwrap();
+
+        assert_eq!(3, block_list.num_ranges());
+        assert!(block_list.is_blocked(&ip("1.2.3.128")));
+        assert!(block_list.is_blocked(&ip("4.5.6.10")));
+        assert!(!block_list.is_blocked(&ip("4.5.6.11")));
+        assert!(!block_list.is_blocked(&ip("7.8.9.1")));
+        assert!(block_list.is_blocked(&ip("10.255.255.255")));
+        assert!(!block_list.is_blocked(&ip("11.0.0.0")));
+    }
+
+    #[test]
+    fn positive_load_ipv6_p2p_range() {
+        let list = b"Bad Peers: v6:2001:db8::1-2001:db8::ff\n\
+                     2001:db9::-2001:db9::ffff\n";
+        let block_list = BlockList::from_reader(&list[..]).unwrap();
+
+        assert_eq!(2, block_list.num_ranges());
+        assert!(!block_list.is_blocked(&ip("2001:db8::")));
+        assert!(block_list.is_blocked(&ip("2001:db8::1")));
+        assert!(block_list.is_blocked(&ip("2001:db8::ff")));
+        assert!(!block_list.is_blocked(&ip("2001:db8::100")));
+        assert!(block_list.is_blocked(&ip("2001:db9::ffff")));
+    }
+
+    #[test]
+    fn positive_filter_blocks_addr() {
+        let mut block_list = BlockList::new();
+        block_list.block_cidr(ip("10.0.0.0"), 8);
+
+        let blocked: SocketAddr = "10.1.2.3:6881".parse().unwrap();
+        let allowed: SocketAddr = "11.1.2.3:6881".parse().unwrap();
+
+        assert_eq!(FilterDecision::Block, block_list.on_addr(Some(&blocked)));
+        assert_eq!(FilterDecision::Pass, block_list.on_addr(Some(&allowed)));
+        assert_eq!(FilterDecision::Pass, block_list.on_addr(None));
+    }
+
+    #[test]
+    fn negative_load_invalid_line() {
+        let error = BlockList::from_reader(&b"1.2.3.0/24\nnot an entry\n"[..]).unwrap_err();
+
+        assert!(error.to_string().contains("Line 2"));
+    }
+}
@@ -7,6 +7,7 @@ use message::extensions::{Extensions};
 
 use bip_util::bt::{InfoHash, PeerId};
 
+pub mod blocklist;
 pub mod filters;
 
 /// Trait for adding and removing `HandshakeFilter`s.
@@ -5,9 +5,9 @@ const DEFAULT_HANDSHAKE_BUFFER_SIZE: usize = 1000;
 const DEFAULT_WAIT_BUFFER_SIZE:      usize = 10;
 const DEFAULT_DONE_BUFFER_SIZE:      usize = 10;
 
-/// Once we get parallel handshake support (requires
-/// mpmc future channel support, we can bump this up).
-const DEFAULT_HANDSHAKE_TIMEOUT_MILLIS:         u64 = 1000;
+/// Handshakes are processed in parallel, so we can afford to give
+/// slow peers a while to send us the full handshake.
+const DEFAULT_HANDSHAKE_TIMEOUT_MILLIS:         u64 = 10000;
 const DEFAULT_HANDSHAKE_CONNECT_TIMEOUT_MILLIS: u64 = 1000;
 
 /// Configures the internals of a `Handshaker`.
@@ -44,6 +44,9 @@ impl HandshakerConfig {
 
     /// Sets the handshake timeout that `Handshaker` uses to
     /// make sure peers dont take too long to respond to us.
+    ///
+    /// Peers that do not send us their full handshake within
+    /// this timeout are dropped. Defaults to 10 seconds.
     pub fn with_handshake_timeout(mut self, timeout: Duration) -> HandshakerConfig {
         self.handshake_timeout = timeout;
         self
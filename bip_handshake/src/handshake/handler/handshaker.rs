@@ -15,19 +15,48 @@ use futures::future::Future;
 use futures::stream::Stream;
 use futures::sink::Sink;
 use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::TimeoutError;
+
+/// Reasons that a handshake with a peer failed.
+///
+/// Returned as an error item from the `Handshaker` stream, which can be polled again for later handshakes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// Peer did not finish the handshake within the handshake timeout.
+    Timeout,
+    /// Peer sent us our own peer id, so we are connected to ourselves.
+    SelfConnection,
+    /// Peer responded to our handshake with a different info hash.
+    InfoHashMismatch,
+    /// Peer closed the connection, sent an invalid handshake, or did not pass our checks.
+    Failed
+}
+
+impl<F> From<TimeoutError<F>> for HandshakeError {
+    fn from(error: TimeoutError<F>) -> HandshakeError {
+        match error {
+            TimeoutError::TimedOut(_) => HandshakeError::Timeout,
+            TimeoutError::Timer(_, _) => HandshakeError::Failed
+        }
+    }
+}
 
 pub fn execute_handshake<S>(item: HandshakeType<S>, context: &(Extensions, PeerId, Filters, HandshakeTimer))
-    -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
+    -> Box<Future<Item=Option<Result<CompleteMessage<S>, HandshakeError>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
     let &(ref ext, ref pid, ref filters, ref timer) = context;
 
-    match item {
+    let handshake_future = match item {
         HandshakeType::Initiate(sock, init_msg) => initiate_handshake(sock, init_msg, *ext, *pid, filters.clone(), timer.clone()),
         HandshakeType::Complete(sock, addr)     => complete_handshake(sock, addr, *ext, *pid, filters.clone(), timer.clone())
-    }
+    };
+
+    // Failed handshakes are forwarded as items, so that the handler can move on to the next connection
+    Box::new(handshake_future
+        .then(|result| Ok(Some(result))))
 }
 
 fn initiate_handshake<S>(sock: S, init_msg: InitiateMessage, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer)
-    -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
+    -> Box<Future<Item=CompleteMessage<S>, Error=HandshakeError>> where S: AsyncRead + AsyncWrite + 'static {
     let framed = FramedHandshake::new(sock);
     
     let (prot, hash, addr) = init_msg.into_parts();
@@ -35,77 +64,80 @@ fn initiate_handshake<S>(sock: S, init_msg: InitiateMessage, ext: Extensions, pi
 
     let composed_future = timer.timeout(
             framed.send(handshake_msg)
-                .map_err(|_| ())
+                .map_err(|_| HandshakeError::Failed)
         )
         .and_then(move |framed| {
             timer.timeout(
                 framed.into_future()
-                    .map_err(|_| ())
-                    .and_then(|(opt_msg, framed)| opt_msg.ok_or(())
+                    .map_err(|_| HandshakeError::Failed)
+                    .and_then(|(opt_msg, framed)| opt_msg.ok_or(HandshakeError::Failed)
                     .map(|msg| (msg, framed)))
             )
             .and_then(move |(msg, framed)| {
                 let (remote_prot, remote_ext, remote_hash, remote_pid) = msg.into_parts();
                 let socket = framed.into_inner();
                 
-                // Check that it responds with the same hash and protocol, also check our filters
-                if remote_hash != hash ||
-                    remote_prot != prot ||
+                // Check that it responds with the same hash and protocol, that it isnt us, also check our filters
+                if remote_pid == pid {
+                    Err(HandshakeError::SelfConnection)
+                } else if remote_hash != hash {
+                    Err(HandshakeError::InfoHashMismatch)
+                } else if remote_prot != prot ||
                     handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
-                    Err(())
+                    Err(HandshakeError::Failed)
                 } else {
-                    Ok(Some(CompleteMessage::new(prot, ext.union(&remote_ext), hash, remote_pid, addr, socket)))
+                    Ok(CompleteMessage::new(prot, ext.intersection(&remote_ext), hash, remote_pid, addr, socket))
                 }
             })
-        })
-        .or_else(|_| Ok(None));
+        });
 
     Box::new(composed_future)
 }
 
 fn complete_handshake<S>(sock: S, addr: SocketAddr, ext: Extensions, pid: PeerId, filters: Filters, timer: HandshakeTimer)
-    -> Box<Future<Item=Option<CompleteMessage<S>>, Error=()>> where S: AsyncRead + AsyncWrite + 'static {
+    -> Box<Future<Item=CompleteMessage<S>, Error=HandshakeError>> where S: AsyncRead + AsyncWrite + 'static {
     let framed = FramedHandshake::new(sock);
 
     let composed_future = timer.timeout(
             framed.into_future()
-                .map_err(|_| ())
+                .map_err(|_| HandshakeError::Failed)
                 .and_then(|(opt_msg, framed)| {
-                    opt_msg.ok_or(())
+                    opt_msg.ok_or(HandshakeError::Failed)
                         .map(|msg| (msg, framed))
             })
         )
         .and_then(move |(msg, framed)| {
             let (remote_prot, remote_ext, remote_hash, remote_pid) = msg.into_parts();
             
-            // Check our filters
-            if handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
-                Err(())
+            // Check that it isnt us, also check our filters
+            if remote_pid == pid {
+                Err(HandshakeError::SelfConnection)
+            } else if handler::should_filter(Some(&addr), Some(&remote_prot), Some(&remote_ext), Some(&remote_hash), Some(&remote_pid), &filters) {
+                Err(HandshakeError::Failed)
             } else {
                 let handshake_msg = HandshakeMessage::from_parts(remote_prot.clone(), ext, remote_hash, pid);
 
                 Ok(timer.timeout(framed.send(handshake_msg)
-                        .map_err(|_| ())
+                        .map_err(|_| HandshakeError::Failed)
                         .map(move |framed| {
                             let socket = framed.into_inner();
 
-                            Some(CompleteMessage::new(remote_prot, ext.union(&remote_ext), remote_hash, remote_pid, addr, socket))
+                            CompleteMessage::new(remote_prot, ext.intersection(&remote_ext), remote_hash, remote_pid, addr, socket)
                         })
                 ))
             }
         })
-        .flatten()
-        .or_else(|_| Ok(None));
+        .flatten();
 
     Box::new(composed_future)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor};
+    use std::io::{self, Cursor, Read, Write};
     use std::time::Duration;
 
-    use super::{HandshakeMessage};
+    use super::{HandshakeMessage, HandshakeError};
     use message::extensions::{self, Extensions};
     use message::protocol::Protocol;
     use message::initiate::InitiateMessage;
@@ -115,6 +147,46 @@ mod tests {
     use bip_util::bt::{self, PeerId, InfoHash};
     use tokio_timer;
     use futures::future::{self, Future};
+    use futures::{Async, Poll};
+    use tokio_io::{AsyncRead, AsyncWrite};
+
+    /// Stream that yields the given bytes, and then stalls forever.
+    struct StallingStream {
+        read_bytes: Cursor<Vec<u8>>
+    }
+
+    impl StallingStream {
+        fn new(read_bytes: Vec<u8>) -> StallingStream {
+            StallingStream{ read_bytes: Cursor::new(read_bytes) }
+        }
+    }
+
+    impl Read for StallingStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.read_bytes.read(buf) {
+                Ok(0) => Err(io::Error::new(io::ErrorKind::WouldBlock, "Stalled")),
+                other => other
+            }
+        }
+    }
+
+    impl Write for StallingStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for StallingStream {}
+
+    impl AsyncWrite for StallingStream {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
 
     fn any_peer_id() -> PeerId {
         [22u8; bt::PEER_ID_LEN].into()
@@ -163,7 +235,7 @@ mod tests {
         let init_timer = any_handshake_timer();
 
         // Wrap in lazy since we can call wait on non sized types...
-        let complete_message = future::lazy(|| super::initiate_handshake(writer, init_message, init_ext, init_pid, init_filters, init_timer)).wait().unwrap().unwrap();
+        let complete_message = future::lazy(|| super::initiate_handshake(writer, init_message, init_ext, init_pid, init_filters, init_timer)).wait().unwrap();
 
         assert_eq!(init_prot, *complete_message.protocol());
         assert_eq!(init_ext, *complete_message.extensions());
@@ -202,7 +274,7 @@ mod tests {
         let comp_timer = any_handshake_timer();
 
         // Wrap in lazy since we can call wait on non sized types...
-        let complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, comp_ext, comp_pid, comp_filters, comp_timer)).wait().unwrap().unwrap();
+        let complete_message = future::lazy(|| super::complete_handshake(writer, remote_addr, comp_ext, comp_pid, comp_filters, comp_timer)).wait().unwrap();
 
         assert_eq!(remote_protocol, *complete_message.protocol());
         assert_eq!(comp_ext, *complete_message.extensions());
@@ -218,4 +290,101 @@ mod tests {
         assert_eq!(local_message, sent_message);
         assert_eq!(remote_message, recv_message);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn positive_complete_handshake_negotiates_extensions() {
+        let mut remote_ext = Extensions::new();
+        remote_ext.set_fast(true);
+        remote_ext.set_dht(true);
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, remote_ext, any_info_hash(), any_peer_id());
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let mut comp_ext = Extensions::new();
+        comp_ext.set_fast(true);
+        comp_ext.set_extension_protocol(true);
+
+        let complete_message = future::lazy(|| super::complete_handshake(writer, "1.2.3.4:5".parse().unwrap(), comp_ext, any_other_peer_id(),
+                                                                         Filters::new(), any_handshake_timer())).wait().unwrap();
+
+        assert!(complete_message.extensions().supports_fast());
+        assert!(!complete_message.extensions().supports_dht());
+        assert!(!complete_message.extensions().supports_extension_protocol());
+    }
+
+    #[test]
+    fn negative_complete_handshake_stalls() {
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), any_info_hash(), any_peer_id());
+
+        let mut remote_bytes = Vec::new();
+        remote_message.write_bytes(&mut remote_bytes).unwrap();
+        remote_bytes.truncate(remote_message.write_len() / 2);
+
+        let result = future::lazy(|| super::complete_handshake(StallingStream::new(remote_bytes), "1.2.3.4:5".parse().unwrap(), any_extensions(),
+                                                               any_other_peer_id(), Filters::new(), any_handshake_timer())).wait();
+
+        assert_eq!(HandshakeError::Timeout, result.err().unwrap());
+    }
+
+    #[test]
+    fn negative_initiate_handshake_stalls() {
+        let init_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
+
+        let result = future::lazy(|| super::initiate_handshake(StallingStream::new(Vec::new()), init_message, any_extensions(),
+                                                               any_other_peer_id(), Filters::new(), any_handshake_timer())).wait();
+
+        assert_eq!(HandshakeError::Timeout, result.err().unwrap());
+    }
+
+    #[test]
+    fn negative_initiate_handshake_self_connection() {
+        let init_pid = any_peer_id();
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), any_info_hash(), init_pid);
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        writer.set_position(remote_message.write_len() as u64);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let init_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
+
+        let result = future::lazy(|| super::initiate_handshake(writer, init_message, any_extensions(), init_pid,
+                                                               Filters::new(), any_handshake_timer())).wait();
+
+        assert_eq!(HandshakeError::SelfConnection, result.err().unwrap());
+    }
+
+    #[test]
+    fn negative_complete_handshake_self_connection() {
+        let comp_pid = any_peer_id();
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), any_info_hash(), comp_pid);
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let result = future::lazy(|| super::complete_handshake(writer, "1.2.3.4:5".parse().unwrap(), any_extensions(), comp_pid,
+                                                               Filters::new(), any_handshake_timer())).wait();
+
+        assert_eq!(HandshakeError::SelfConnection, result.err().unwrap());
+    }
+
+    #[test]
+    fn negative_initiate_handshake_info_hash_mismatch() {
+        let remote_message = HandshakeMessage::from_parts(Protocol::BitTorrent, any_extensions(), [66u8; bt::INFO_HASH_LEN].into(), any_peer_id());
+
+        let mut writer = Cursor::new(vec![0u8; remote_message.write_len() * 2]);
+        writer.set_position(remote_message.write_len() as u64);
+        remote_message.write_bytes(&mut writer).unwrap();
+        writer.set_position(0);
+
+        let init_message = InitiateMessage::new(Protocol::BitTorrent, any_info_hash(), "1.2.3.4:5".parse().unwrap());
+
+        let result = future::lazy(|| super::initiate_handshake(writer, init_message, any_extensions(), any_other_peer_id(),
+                                                               Filters::new(), any_handshake_timer())).wait();
+
+        assert_eq!(HandshakeError::InfoHashMismatch, result.err().unwrap());
+    }
+}
@@ -17,10 +17,11 @@ use filter::filters::Filters;
 use filter::{HandshakeFilter, HandshakeFilters};
 use handshake::config::HandshakerConfig;
 use handshake::handler::timer::HandshakeTimer;
+use handshake::handler::handshaker::HandshakeError;
 
 use bip_util::bt::PeerId;
 use bip_util::convert;
-use futures::{StartSend, Poll};
+use futures::{Async, StartSend, Poll};
 use futures::sync::mpsc::{self, Sender, Receiver, SendError};
 use futures::sink::Sink;
 use futures::stream::Stream;
@@ -92,6 +93,15 @@ impl HandshakerBuilder {
         self
     }
 
+    /// Timeout for a peer to send us their full handshake, after which the connection is dropped.
+    ///
+    /// Defaults to 10 seconds. This overrides the handshake timeout of any previously set `HandshakerConfig`.
+    pub fn with_handshake_timeout(&mut self, timeout: Duration) -> &mut HandshakerBuilder {
+        self.config = self.config.with_handshake_timeout(timeout);
+
+        self
+    }
+
     /// Configuration that will be used to alter the internal behavior of handshaking.
     ///
     /// This will typically not need to be set unless you know what you are doing.
@@ -191,9 +201,9 @@ impl<S> Sink for Handshaker<S> {
 
 impl<S> Stream for Handshaker<S> {
     type Item = CompleteMessage<S>;
-    type Error = ();
+    type Error = HandshakeError;
 
-    fn poll(&mut self) -> Poll<Option<CompleteMessage<S>>, ()> {
+    fn poll(&mut self) -> Poll<Option<CompleteMessage<S>>, HandshakeError> {
         self.stream.poll()
     }
 }
@@ -273,21 +283,28 @@ impl HandshakeFilters for HandshakerSink {
 //----------------------------------------------------------------------------------//
 
 /// `Stream` portion of the `Handshaker` for completed handshakes.
+///
+/// Failed handshakes are returned as errors, after which the stream can continue to be polled.
 pub struct HandshakerStream<S> {
-    recv: Receiver<CompleteMessage<S>>
+    recv: Receiver<Result<CompleteMessage<S>, HandshakeError>>
 }
 
 impl<S> HandshakerStream<S> {
-    fn new(recv: Receiver<CompleteMessage<S>>) -> HandshakerStream<S> {
+    fn new(recv: Receiver<Result<CompleteMessage<S>, HandshakeError>>) -> HandshakerStream<S> {
         HandshakerStream{ recv: recv }
     }
 }
 
 impl<S> Stream for HandshakerStream<S> {
     type Item = CompleteMessage<S>;
-    type Error = ();
-
-    fn poll(&mut self) -> Poll<Option<CompleteMessage<S>>, ()> {
-        self.recv.poll()
+    type Error = HandshakeError;
+
+    fn poll(&mut self) -> Poll<Option<CompleteMessage<S>>, HandshakeError> {
+        match self.recv.poll() {
+            Ok(Async::Ready(Some(Ok(complete)))) => Ok(Async::Ready(Some(complete))),
+            Ok(Async::Ready(Some(Err(error))))   => Err(error),
+            Ok(Async::NotReady)                  => Ok(Async::NotReady),
+            Ok(Async::Ready(None)) | Err(())     => Ok(Async::Ready(None))
+        }
     }
 }
\ No newline at end of file
@@ -20,12 +20,14 @@ mod transport;
 pub use message::complete::CompleteMessage;
 pub use message::initiate::InitiateMessage;
 pub use message::protocol::Protocol;
-pub use message::extensions::{Extensions, Extension};
+pub use message::extensions::{Extensions, Extension, ReservedBits};
 
 pub use handshake::config::HandshakerConfig;
 pub use handshake::handshaker::{HandshakerBuilder, Handshaker, HandshakerStream, HandshakerSink};
+pub use handshake::handler::handshaker::HandshakeError;
 
 pub use filter::{FilterDecision, HandshakeFilter, HandshakeFilters};
+pub use filter::blocklist::BlockList;
 
 pub use discovery::DiscoveryInfo;
 pub use local_addr::LocalAddr;
@@ -9,9 +9,16 @@ pub const NUM_EXTENSION_BYTES: usize = 8;
 /// Enumeration of all extensions that can be activated.
 pub enum Extension {
     /// Support for the extension protocol `http://www.bittorrent.org/beps/bep_0010.html`.
-    ExtensionProtocol = 43
+    ExtensionProtocol = 43,
+    /// Support for the fast extension `http://www.bittorrent.org/beps/bep_0006.html`.
+    FastExtension = 61,
+    /// Support for the dht `http://www.bittorrent.org/beps/bep_0005.html`.
+    Dht = 63
 }
 
+/// `ReservedBits` is another name for `Extensions`, after the reserved bytes they occupy in the handshake.
+pub type ReservedBits = Extensions;
+
 /// `Extensions` supported by either end of a handshake.
 #[derive(Copy, Clone, Eq, Hash, PartialEq, Debug)]
 pub struct Extensions {
@@ -58,16 +65,46 @@ impl Extensions {
         self.bytes[byte_index] & (0x80 >> bit_index) != 0
     }
 
+    /// Set whether or not we support the extension protocol.
+    pub fn set_extension_protocol(&mut self, enabled: bool) {
+        self.set(Extension::ExtensionProtocol, enabled)
+    }
+
+    /// Check if the extension protocol is supported.
+    pub fn supports_extension_protocol(&self) -> bool {
+        self.contains(Extension::ExtensionProtocol)
+    }
+
+    /// Set whether or not we support the fast extension.
+    pub fn set_fast(&mut self, enabled: bool) {
+        self.set(Extension::FastExtension, enabled)
+    }
+
+    /// Check if the fast extension is supported.
+    pub fn supports_fast(&self) -> bool {
+        self.contains(Extension::FastExtension)
+    }
+
+    /// Set whether or not we support the dht.
+    pub fn set_dht(&mut self, enabled: bool) {
+        self.set(Extension::Dht, enabled)
+    }
+
+    /// Check if the dht is supported.
+    pub fn supports_dht(&self) -> bool {
+        self.contains(Extension::Dht)
+    }
+
     /// Write the `Extensions` to the given writer.
     pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
         where W: Write {
         writer.write_all(&self.bytes[..])
     }
 
-    /// Create a union of the two extensions.
+    /// Create an intersection of the two extensions.
     ///
     /// This is useful for getting the extensions that both clients support.
-    pub fn union(&self, ext: &Extensions) -> Extensions {
+    pub fn intersection(&self, ext: &Extensions) -> Extensions {
         let mut result_ext = Extensions::new();
 
         for index in 0..NUM_EXTENSION_BYTES {
@@ -77,6 +114,22 @@ impl Extensions {
         result_ext
     }
 
+    /// Create an intersection of the two extensions.
+    ///
+    /// Despite the name, this is the same as `Extensions::intersection`, and is kept for compatibility.
+    pub fn union(&self, ext: &Extensions) -> Extensions {
+        self.intersection(ext)
+    }
+
+    /// Add or remove the given extension.
+    fn set(&mut self, extension: Extension, enabled: bool) {
+        if enabled {
+            self.add(extension)
+        } else {
+            self.remove(extension)
+        }
+    }
+
     /// Create a new `Extensions` using the given bytes directly.
     fn with_bytes(bytes: [u8; NUM_EXTENSION_BYTES]) -> Extensions {
         Extensions{ bytes: bytes }
@@ -123,4 +176,82 @@ mod tests {
         assert_eq!(expected_extensions, extensions);
         assert!(!extensions.contains(Extension::ExtensionProtocol));
     }
+
+    fn round_trip(extensions: &Extensions) -> Extensions {
+        let mut bytes = Vec::new();
+        extensions.write_bytes(&mut bytes).unwrap();
+
+        Extensions::from_bytes(&bytes).unwrap().1
+    }
+
+    #[test]
+    fn positive_round_trip_extension_protocol() {
+        let mut extensions = Extensions::new();
+        extensions.set_extension_protocol(true);
+
+        let expected_extensions: Extensions = [0, 0, 0, 0, 0, 0x10, 0, 0].into();
+        let decoded = round_trip(&extensions);
+
+        assert_eq!(expected_extensions, decoded);
+        assert!(decoded.supports_extension_protocol());
+        assert!(!decoded.supports_fast());
+        assert!(!decoded.supports_dht());
+    }
+
+    #[test]
+    fn positive_round_trip_fast() {
+        let mut extensions = Extensions::new();
+        extensions.set_fast(true);
+
+        let expected_extensions: Extensions = [0, 0, 0, 0, 0, 0, 0, 0x04].into();
+        let decoded = round_trip(&extensions);
+
+        assert_eq!(expected_extensions, decoded);
+        assert!(decoded.supports_fast());
+        assert!(!decoded.supports_extension_protocol());
+        assert!(!decoded.supports_dht());
+    }
+
+    #[test]
+    fn positive_round_trip_dht() {
+        let mut extensions = Extensions::new();
+        extensions.set_dht(true);
+
+        let expected_extensions: Extensions = [0, 0, 0, 0, 0, 0, 0, 0x01].into();
+        let decoded = round_trip(&extensions);
+
+        assert_eq!(expected_extensions, decoded);
+        assert!(decoded.supports_dht());
+        assert!(!decoded.supports_extension_protocol());
+        assert!(!decoded.supports_fast());
+    }
+
+    #[test]
+    fn positive_unset_flag() {
+        let mut extensions = Extensions::new();
+        extensions.set_dht(true);
+        extensions.set_fast(true);
+        extensions.set_dht(false);
+
+        assert!(!extensions.supports_dht());
+        assert!(extensions.supports_fast());
+    }
+
+    #[test]
+    fn positive_intersection() {
+        let mut ours = Extensions::new();
+        ours.set_extension_protocol(true);
+        ours.set_fast(true);
+
+        let mut theirs = Extensions::new();
+        theirs.set_fast(true);
+        theirs.set_dht(true);
+
+        let negotiated = ours.intersection(&theirs);
+
+        assert!(negotiated.supports_fast());
+        assert!(!negotiated.supports_extension_protocol());
+        assert!(!negotiated.supports_dht());
+        assert_eq!(negotiated, theirs.intersection(&ours));
+    }
 }
\ No newline at end of file
@@ -11,6 +11,9 @@ mod test_filter_allow_all;
 mod test_filter_block_all;
 mod test_filter_whitelist_same_data;
 mod test_filter_whitelist_diff_data;
+mod test_self_connection;
+
+use futures::stream::Stream;
 
 //----------------------------------------------------------------------------------//
 
@@ -19,3 +22,9 @@ pub enum TimeoutResult {
     TimedOut,
     GotResult
 }
+
+/// Completed handshakes from the given stream, skipping over any failed handshakes.
+pub fn completed_handshakes<S>(stream: S) -> Box<Stream<Item=S::Item, Error=()>>
+    where S: Stream + 'static {
+    Box::new(stream.then(|result| Ok(result.ok())).filter_map(|opt_item| opt_item))
+}
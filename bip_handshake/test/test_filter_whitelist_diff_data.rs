@@ -1,7 +1,7 @@
 use std::time::Duration;
 use std::any::Any;
 
-use {TimeoutResult};
+use {TimeoutResult, completed_handshakes};
 use bip_handshake::{HandshakerBuilder, InitiateMessage, Protocol, DiscoveryInfo,
     FilterDecision, HandshakeFilter, HandshakeFilters};
 use bip_handshake::transports::TcpTransport;
@@ -79,8 +79,8 @@ fn test_filter_whitelist_diff_data() {
         .and_then(|_|  {
             let timeout = Timeout::new(Duration::from_millis(50), &handle).unwrap().map(|_| TimeoutResult::TimedOut).map_err(|_| ());
                 
-            let result_one = stream_one.into_future().map(|_| TimeoutResult::GotResult).map_err(|_| ());
-            let result_two = stream_two.into_future().map(|_| TimeoutResult::GotResult).map_err(|_| ());
+            let result_one = completed_handshakes(stream_one).into_future().map(|_| TimeoutResult::GotResult).map_err(|_| ());
+            let result_two = completed_handshakes(stream_two).into_future().map(|_| TimeoutResult::GotResult).map_err(|_| ());
 
             result_one.select(result_two).map(|_| TimeoutResult::GotResult).map_err(|_| ()).select(timeout).map(|(item, _)| item).map_err(|_| ())
         })
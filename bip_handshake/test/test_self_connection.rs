@@ -0,0 +1,49 @@
+use std::thread;
+use std::io::{Write, Read};
+use std::net::TcpStream;
+
+use bip_handshake::{HandshakerBuilder, HandshakeError, DiscoveryInfo};
+use bip_handshake::transports::TcpTransport;
+
+use bip_util::bt::{self};
+use tokio_core::reactor::{Core};
+use futures::Future;
+use futures::stream::Stream;
+
+#[test]
+fn negative_self_connection() {
+    let mut core = Core::new().unwrap();
+
+    let mut handshaker_one_addr = "127.0.0.1:0".parse().unwrap();
+    let handshaker_one_pid = [4u8; bt::PEER_ID_LEN];
+
+    let handshaker_one = HandshakerBuilder::new()
+        .with_bind_addr(handshaker_one_addr)
+        .with_peer_id(handshaker_one_pid.into())
+        .build(TcpTransport, core.handle()).unwrap();
+
+    handshaker_one_addr.set_port(handshaker_one.port());
+
+    thread::spawn(move || {
+        let mut stream = TcpStream::connect(handshaker_one_addr).unwrap();
+        let mut write_buffer = Vec::new();
+
+        // Send back the peer id of the handshaker, as if it had connected to itself
+        write_buffer.write_all(&[1, 1]).unwrap();
+        write_buffer.write_all(&[0u8; 8]).unwrap();
+        write_buffer.write_all(&[0u8; bt::INFO_HASH_LEN]).unwrap();
+        write_buffer.write_all(&handshaker_one_pid).unwrap();
+
+        stream.write_all(&write_buffer).unwrap();
+
+        // Blocks until the handshaker drops the connection
+        let _ = stream.read(&mut [0u8; 1]);
+    });
+
+    let error = core.run(handshaker_one.into_future()
+        .map(|_| ())
+        .map_err(|(error, _)| error)
+    ).unwrap_err();
+
+    assert_eq!(HandshakeError::SelfConnection, error);
+}
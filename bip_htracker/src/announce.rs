@@ -0,0 +1,346 @@
+//! Messaging primitives for announcing to a tracker.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bip_bencode::{BencodeRef, BRefAccess, BDecodeOpt, BConvert};
+use bip_util::bt::{InfoHash, PeerId};
+
+use convert::{CONVERT, ROOT_ERROR_KEY};
+use error::{TrackerErrorKind, TrackerResult};
+use peers;
+
+/// Keys found within the root dictionary of an announce response.
+const FAILURE_REASON_KEY:  &'static [u8] = b"failure reason";
+const WARNING_MESSAGE_KEY: &'static [u8] = b"warning message";
+const INTERVAL_KEY:        &'static [u8] = b"interval";
+const MIN_INTERVAL_KEY:    &'static [u8] = b"min interval";
+
+/// Event reported by the client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnnounceEvent {
+    /// No event is reported.
+    None,
+    /// Torrent download has completed.
+    Completed,
+    /// Torrent download has started.
+    Started,
+    /// Torrent download has stopped.
+    Stopped,
+}
+
+impl AnnounceEvent {
+    /// Value of the event query parameter, if any.
+    fn as_param(&self) -> Option<&'static str> {
+        match *self {
+            AnnounceEvent::None      => None,
+            AnnounceEvent::Completed => Some("completed"),
+            AnnounceEvent::Started   => Some("started"),
+            AnnounceEvent::Stopped   => Some("stopped"),
+        }
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Announce request sent to a tracker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceRequest {
+    info_hash:  InfoHash,
+    peer_id:    PeerId,
+    port:       u16,
+    uploaded:   u64,
+    downloaded: u64,
+    left:       u64,
+    event:      AnnounceEvent,
+}
+
+impl AnnounceRequest {
+    /// Create a new AnnounceRequest for the given torrent, where we are listening on the given port.
+    ///
+    /// Transfer statistics default to zero, and the event defaults to `AnnounceEvent::None`.
+    pub fn new(info_hash: InfoHash, peer_id: PeerId, port: u16) -> AnnounceRequest {
+        AnnounceRequest{ info_hash: info_hash, peer_id: peer_id, port: port, uploaded: 0,
+                         downloaded: 0, left: 0, event: AnnounceEvent::None }
+    }
+
+    /// Set the number of bytes we have uploaded.
+    pub fn set_uploaded(mut self, uploaded: u64) -> AnnounceRequest {
+        self.uploaded = uploaded;
+
+        self
+    }
+
+    /// Set the number of bytes we have downloaded.
+    pub fn set_downloaded(mut self, downloaded: u64) -> AnnounceRequest {
+        self.downloaded = downloaded;
+
+        self
+    }
+
+    /// Set the number of bytes we have left to download.
+    pub fn set_left(mut self, left: u64) -> AnnounceRequest {
+        self.left = left;
+
+        self
+    }
+
+    /// Set the event we are reporting.
+    pub fn set_event(mut self, event: AnnounceEvent) -> AnnounceRequest {
+        self.event = event;
+
+        self
+    }
+
+    /// InfoHash of the torrent.
+    pub fn info_hash(&self) -> InfoHash {
+        self.info_hash
+    }
+
+    /// PeerId of the client.
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Port the client is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Bytes uploaded.
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// Bytes downloaded.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// Bytes left to download.
+    pub fn left(&self) -> u64 {
+        self.left
+    }
+
+    /// Event reported.
+    pub fn event(&self) -> AnnounceEvent {
+        self.event
+    }
+
+    /// Build the url for announcing to the given tracker announce url.
+    ///
+    /// Query parameters already present in the tracker url are preserved.
+    pub fn to_url(&self, announce_url: &str) -> String {
+        let mut url = String::from(announce_url);
+        url.push(if announce_url.contains('?') { '&' } else { '?' });
+
+        url.push_str("info_hash=");
+        url.push_str(&url_encode(self.info_hash.as_ref()));
+        url.push_str("&peer_id=");
+        url.push_str(&url_encode(self.peer_id.as_ref()));
+        url.push_str(&format!("&port={}&uploaded={}&downloaded={}&left={}&compact=1",
+                              self.port, self.uploaded, self.downloaded, self.left));
+
+        if let Some(event) = self.event.as_param() {
+            url.push_str("&event=");
+            url.push_str(event);
+        }
+
+        url
+    }
+}
+
+/// Percent encode the given bytes for use in a query parameter.
+fn url_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+
+    for &byte in bytes {
+        let unreserved = (byte as char).is_ascii_alphanumeric() || b"-._~".contains(&byte);
+
+        if unreserved {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+//----------------------------------------------------------------------------//
+
+/// Announce response received from a tracker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    interval:     Duration,
+    min_interval: Option<Duration>,
+    warning:      Option<String>,
+    peers:        Vec<SocketAddr>,
+}
+
+impl AnnounceResponse {
+    /// Parse an AnnounceResponse from the body of a tracker response.
+    ///
+    /// If the tracker responded with a failure reason, a `TrackerErrorKind::TrackerFailure` is returned.
+    pub fn from_bytes(bytes: &[u8]) -> TrackerResult<AnnounceResponse> {
+        let bencode = try!(BencodeRef::decode(bytes, BDecodeOpt::default()));
+        let root_dict = try!(CONVERT.convert_dict(&bencode, ROOT_ERROR_KEY));
+
+        if let Some(reason) = root_dict.lookup(FAILURE_REASON_KEY) {
+            let reason = try!(CONVERT.convert_str(reason, FAILURE_REASON_KEY));
+
+            return Err(TrackerErrorKind::TrackerFailure{ reason: reason.to_owned() }.into())
+        }
+
+        let interval = try!(CONVERT.lookup_and_convert_int(root_dict, INTERVAL_KEY)
+            .map_err(From::from)
+            .and_then(|interval| interval_to_duration(interval, INTERVAL_KEY)));
+        let min_interval = match root_dict.lookup(MIN_INTERVAL_KEY).and_then(|min_interval| min_interval.int()) {
+            Some(min_interval) => Some(try!(interval_to_duration(min_interval, MIN_INTERVAL_KEY))),
+            None               => None
+        };
+        let warning = root_dict.lookup(WARNING_MESSAGE_KEY)
+            .and_then(|warning| warning.str())
+            .map(String::from);
+
+        let peers = try!(peers::parse_peers(root_dict));
+
+        Ok(AnnounceResponse{ interval: interval, min_interval: min_interval,
+                             warning: warning, peers: peers })
+    }
+
+    /// Interval at which the client should announce.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Minimum interval at which the client may announce, if provided.
+    pub fn min_interval(&self) -> Option<Duration> {
+        self.min_interval
+    }
+
+    /// Warning message from the tracker, if provided.
+    pub fn warning_message(&self) -> Option<&str> {
+        self.warning.as_ref().map(|warning| &warning[..])
+    }
+
+    /// Peers given to us by the tracker.
+    pub fn peers(&self) -> &[SocketAddr] {
+        &self.peers
+    }
+}
+
+/// Convert the interval under the given key to a duration, rejecting negative intervals.
+fn interval_to_duration(interval: i64, key: &[u8]) -> TrackerResult<Duration> {
+    if interval < 0 {
+        Err(TrackerErrorKind::InvalidResponse{
+            details: format!("Key {:?} Has Negative Interval {}", String::from_utf8_lossy(key), interval)
+        }.into())
+    } else {
+        Ok(Duration::from_secs(interval as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use bip_util::bt::{self, InfoHash, PeerId};
+
+    use super::{AnnounceEvent, AnnounceRequest, AnnounceResponse};
+    use error::TrackerErrorKind;
+
+    #[test]
+    fn positive_request_to_url() {
+        let mut info_hash = [0x41u8; bt::INFO_HASH_LEN];
+        info_hash[0] = 0x00;
+        info_hash[1] = 0xFF;
+
+        let request = AnnounceRequest::new(InfoHash::from(info_hash), PeerId::from([0x2Du8; bt::PEER_ID_LEN]), 6881)
+            .set_uploaded(10)
+            .set_downloaded(20)
+            .set_left(30)
+            .set_event(AnnounceEvent::Started);
+
+        let expected = format!("http://tracker.example/announce?info_hash=%00%FF{}&peer_id={}\
+                                &port=6881&uploaded=10&downloaded=20&left=30&compact=1&event=started",
+                               "A".repeat(bt::INFO_HASH_LEN - 2), "-".repeat(bt::PEER_ID_LEN));
+        assert_eq!(expected, request.to_url("http://tracker.example/announce"));
+    }
+
+    #[test]
+    fn positive_request_to_url_existing_query() {
+        let request = AnnounceRequest::new(InfoHash::from([0u8; bt::INFO_HASH_LEN]), PeerId::from([0u8; bt::PEER_ID_LEN]), 6881);
+
+        let url = request.to_url("http://tracker.example/announce?passkey=abc");
+        assert!(url.starts_with("http://tracker.example/announce?passkey=abc&info_hash="));
+        assert!(!url.contains("event="));
+    }
+
+    #[test]
+    fn positive_parse_compact_peers() {
+        let mut bytes = b"d8:intervali1800e12:min intervali900e5:peers12:".to_vec();
+        bytes.extend_from_slice(&[127, 0, 0, 1, 0x1A, 0xE1, 10, 0, 0, 2, 0x1A, 0xE2]);
+        bytes.push(b'e');
+
+        let response = AnnounceResponse::from_bytes(&bytes).unwrap();
+
+        let expected_peers: Vec<SocketAddr> = vec!["127.0.0.1:6881".parse().unwrap(), "10.0.0.2:6882".parse().unwrap()];
+        assert_eq!(Duration::from_secs(1800), response.interval());
+        assert_eq!(Some(Duration::from_secs(900)), response.min_interval());
+        assert_eq!(None, response.warning_message());
+        assert_eq!(&expected_peers[..], response.peers());
+    }
+
+    #[test]
+    fn positive_parse_dict_peers() {
+        let bytes = b"d8:intervali60e5:peersld2:ip9:127.0.0.17:peer id20:AAAAAAAAAAAAAAAAAAAA4:porti6881eed2:ip15:tracker.example4:porti6882eee\
+                      15:warning message4:slowe";
+
+        let response = AnnounceResponse::from_bytes(&bytes[..]).unwrap();
+
+        let expected_peers: Vec<SocketAddr> = vec!["127.0.0.1:6881".parse().unwrap()];
+        assert_eq!(None, response.min_interval());
+        assert_eq!(Some("slow"), response.warning_message());
+        assert_eq!(&expected_peers[..], response.peers());
+    }
+
+    #[test]
+    fn negative_parse_failure_reason() {
+        let bytes = b"d14:failure reason17:torrent not founde";
+
+        let error = AnnounceResponse::from_bytes(&bytes[..]).unwrap_err();
+        match error.kind() {
+            &TrackerErrorKind::TrackerFailure{ ref reason } => assert_eq!("torrent not found", reason),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_compact_peers_bad_length() {
+        let bytes = b"d8:intervali60e5:peers5:aaaaae";
+
+        assert!(AnnounceResponse::from_bytes(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn negative_parse_negative_interval() {
+        let bytes = b"d8:intervali-1e5:peers0:e";
+
+        match AnnounceResponse::from_bytes(&bytes[..]).unwrap_err().kind() {
+            &TrackerErrorKind::InvalidResponse{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_negative_min_interval() {
+        let bytes = b"d8:intervali60e12:min intervali-900e5:peers0:e";
+
+        match AnnounceResponse::from_bytes(&bytes[..]).unwrap_err().kind() {
+            &TrackerErrorKind::InvalidResponse{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+}
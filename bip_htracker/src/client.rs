@@ -0,0 +1,217 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use announce::{AnnounceRequest, AnnounceResponse};
+use error::{TrackerErrorKind, TrackerResult};
+
+/// Default timeout for connecting to, writing to, and reading from a tracker.
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+/// Default port for the `http` scheme.
+const DEFAULT_HTTP_PORT: u16 = 80;
+
+/// Transport used to perform HTTP GET requests against a tracker.
+///
+/// Implement this to support schemes other than plain `http`, for example
+/// `https` by running the request over a TLS stream.
+pub trait HttpTransport {
+    /// Perform a GET request for the given url, returning the body of the response.
+    fn get(&self, url: &str) -> TrackerResult<Vec<u8>>;
+}
+
+/// `HttpTransport` performing plain `http` requests over a `TcpStream`.
+#[derive(Debug, Copy, Clone)]
+pub struct TcpTransport {
+    timeout: Duration
+}
+
+impl TcpTransport {
+    /// Create a new TcpTransport with the default timeout.
+    pub fn new() -> TcpTransport {
+        TcpTransport::with_timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECS))
+    }
+
+    /// Create a new TcpTransport with the given timeout for connecting, writing, and reading.
+    pub fn with_timeout(timeout: Duration) -> TcpTransport {
+        TcpTransport{ timeout: timeout }
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> TcpTransport {
+        TcpTransport::new()
+    }
+}
+
+impl HttpTransport for TcpTransport {
+    fn get(&self, url: &str) -> TrackerResult<Vec<u8>> {
+        let (host, port, path) = try!(split_http_url(url));
+
+        let mut opt_stream = None;
+        for addr in try!((host, port).to_socket_addrs()) {
+            if let Ok(stream) = TcpStream::connect_timeout(&addr, self.timeout) {
+                opt_stream = Some(stream);
+                break;
+            }
+        }
+        let mut stream = try!(opt_stream.ok_or(TrackerErrorKind::InvalidUrl{ url: url.to_owned() }));
+
+        try!(stream.set_read_timeout(Some(self.timeout)));
+        try!(stream.set_write_timeout(Some(self.timeout)));
+
+        // Using HTTP/1.0 means we will not have to deal with a chunked response
+        let host_header = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+        let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host_header);
+        try!(stream.write_all(request.as_bytes()));
+
+        let mut response = Vec::new();
+        try!(stream.read_to_end(&mut response));
+
+        parse_http_body(response)
+    }
+}
+
+/// Split an `http` url into its host, port, and path (including the query).
+fn split_http_url(url: &str) -> TrackerResult<(&str, u16, String)> {
+    let scheme_end = try!(url.find("://").ok_or(TrackerErrorKind::InvalidUrl{ url: url.to_owned() }));
+    let (scheme, rest) = (&url[..scheme_end], &url[scheme_end + 3..]);
+
+    if !scheme.eq_ignore_ascii_case("http") {
+        return Err(TrackerErrorKind::UnsupportedScheme{ scheme: scheme.to_owned() }.into())
+    }
+
+    let (authority, path) = match rest.find(|c| c == '/' || c == '?') {
+        Some(index) if rest[index..].starts_with('?') => (&rest[..index], format!("/{}", &rest[index..])),
+        Some(index) => (&rest[..index], rest[index..].to_owned()),
+        None        => (rest, "/".to_owned())
+    };
+
+    // Port follows the last colon, unless that colon is part of a bracketed IPv6 address
+    let (host, port) = match authority.rfind(':') {
+        Some(index) if !authority[index..].contains(']') => {
+            let port = try!(authority[index + 1..].parse().map_err(|_| TrackerErrorKind::InvalidUrl{ url: url.to_owned() }));
+
+            (&authority[..index], port)
+        },
+        _ => (authority, DEFAULT_HTTP_PORT)
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    if host.is_empty() {
+        Err(TrackerErrorKind::InvalidUrl{ url: url.to_owned() }.into())
+    } else {
+        Ok((host, port, path))
+    }
+}
+
+/// Check the status of an HTTP response, and return its body.
+fn parse_http_body(mut response: Vec<u8>) -> TrackerResult<Vec<u8>> {
+    let header_end = try!(response.windows(4).position(|window| window == b"\r\n\r\n")
+        .ok_or(TrackerErrorKind::InvalidResponse{ details: "Missing End Of HTTP Headers".to_owned() }));
+
+    let status_code = {
+        let status_line = String::from_utf8_lossy(&response[..header_end]);
+
+        status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok())
+    };
+    match status_code {
+        Some(200) => (),
+        Some(code) => return Err(TrackerErrorKind::InvalidResponse{ details: format!("HTTP Status Code {}", code) }.into()),
+        None => return Err(TrackerErrorKind::InvalidResponse{ details: "Missing HTTP Status Code".to_owned() }.into())
+    }
+
+    Ok(response.split_off(header_end + 4))
+}
+
+//----------------------------------------------------------------------------//
+
+/// Client for announcing to HTTP trackers.
+///
+/// Requests are blocking, so callers wanting to announce in the background
+/// should do so from their own thread.
+pub struct HttpTrackerClient<T = TcpTransport> {
+    transport: T
+}
+
+impl HttpTrackerClient<TcpTransport> {
+    /// Create a new HttpTrackerClient supporting plain `http` trackers.
+    pub fn new() -> HttpTrackerClient<TcpTransport> {
+        HttpTrackerClient::with_transport(TcpTransport::new())
+    }
+}
+
+impl<T> HttpTrackerClient<T> where T: HttpTransport {
+    /// Create a new HttpTrackerClient sending requests over the given transport.
+    pub fn with_transport(transport: T) -> HttpTrackerClient<T> {
+        HttpTrackerClient{ transport: transport }
+    }
+
+    /// Announce to the tracker at the given announce url.
+    ///
+    /// Failure reasons given by the tracker are returned as a `TrackerErrorKind::TrackerFailure`.
+    pub fn announce(&self, announce_url: &str, request: &AnnounceRequest) -> TrackerResult<AnnounceResponse> {
+        let body = try!(self.transport.get(&request.to_url(announce_url)));
+
+        AnnounceResponse::from_bytes(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    use bip_util::bt::{self, InfoHash, PeerId};
+
+    use announce::AnnounceRequest;
+    use error::TrackerErrorKind;
+    use super::HttpTrackerClient;
+
+    #[test]
+    fn positive_split_http_url() {
+        assert_eq!(("tracker.example", 80, "/announce?a=b".to_owned()), super::split_http_url("http://tracker.example/announce?a=b").unwrap());
+        assert_eq!(("127.0.0.1", 6969, "/".to_owned()), super::split_http_url("http://127.0.0.1:6969").unwrap());
+        assert_eq!(("127.0.0.1", 6969, "/?a=b".to_owned()), super::split_http_url("http://127.0.0.1:6969?a=b").unwrap());
+        assert_eq!(("::1", 6969, "/announce".to_owned()), super::split_http_url("http://[::1]:6969/announce").unwrap());
+    }
+
+    #[test]
+    fn negative_split_https_url() {
+        let error = super::split_http_url("https://tracker.example/announce").unwrap_err();
+
+        match error.kind() {
+            &TrackerErrorKind::UnsupportedScheme{ ref scheme } => assert_eq!("https", scheme),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_announce_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let tracker_addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let num_read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..num_read]);
+            }
+
+            stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\n\
+                               d8:intervali1800e5:peers6:\x7F\x00\x00\x01\x1A\xE1e").unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        let request = AnnounceRequest::new(InfoHash::from([0u8; bt::INFO_HASH_LEN]), PeerId::from([0u8; bt::PEER_ID_LEN]), 6881);
+        let response = HttpTrackerClient::new().announce(&format!("http://{}/announce", tracker_addr), &request).unwrap();
+
+        assert_eq!(&["127.0.0.1:6881".parse::<SocketAddr>().unwrap()][..], response.peers());
+        assert!(server.join().unwrap().starts_with("GET /announce?info_hash="));
+    }
+}
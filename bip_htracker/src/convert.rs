@@ -0,0 +1,20 @@
+use bip_bencode::{BConvert, BencodeConvertError};
+
+use error::TrackerError;
+
+/// Struct implementing the BConvert trait for decoding tracker responses.
+pub struct ResponseConverter;
+
+impl BConvert for ResponseConverter {
+    type Error = TrackerError;
+
+    fn handle_error(&self, error: BencodeConvertError) -> TrackerError {
+        error.into()
+    }
+}
+
+/// Global instance for our conversion struct.
+pub const CONVERT: ResponseConverter = ResponseConverter;
+
+/// Used as an error key to refer to the root bencode object.
+pub const ROOT_ERROR_KEY: &'static [u8] = b"root";
@@ -0,0 +1,48 @@
+//! Errors for HTTP tracker communication.
+
+use std::io;
+
+use bip_bencode::{BencodeConvertError, BencodeParseError};
+
+error_chain! {
+    types {
+        TrackerError, TrackerErrorKind, TrackerResultEx, TrackerResult;
+    }
+
+    foreign_links {
+        Io(io::Error);
+        BencodeConvert(BencodeConvertError);
+        BencodeParse(BencodeParseError);
+    }
+
+    errors {
+        TrackerFailure {
+            reason: String
+        } {
+            description("Tracker Responded With A Failure Reason")
+            display("Tracker Responded With A Failure Reason: {}", reason)
+        }
+        InvalidResponse {
+            details: String
+        } {
+            description("Tracker Responded With An Invalid Response")
+            display("Tracker Responded With An Invalid Response: {}", details)
+        }
+        InvalidUrl {
+            url: String
+        } {
+            description("Tracker Url Could Not Be Parsed")
+            display("Tracker Url {:?} Could Not Be Parsed", url)
+        }
+        NoTrackers {
+            description("No Trackers Were Available To Announce To")
+            display("No Trackers Were Available To Announce To")
+        }
+        UnsupportedScheme {
+            scheme: String
+        } {
+            description("Tracker Url Scheme Is Not Supported By The Transport")
+            display("Tracker Url Scheme {:?} Is Not Supported By The Transport", scheme)
+        }
+    }
+}
@@ -1,3 +1,24 @@
-#[test]
-fn it_works() {
-}
+//! Library for communicating with bittorrent HTTP trackers.
+//!
+//! Includes builders for announce requests, parsing of announce responses and
+//! the peer lists within them, and a blocking `HttpTrackerClient` which sends
+//! announces over an `HttpTransport`. Torrents with multiple tiers of trackers
+//! can use an `AnnounceManager` to fail over between them.
+
+extern crate bip_bencode;
+extern crate bip_util;
+#[macro_use]
+extern crate error_chain;
+
+mod client;
+mod convert;
+mod manager;
+
+pub mod announce;
+pub mod error;
+pub mod peers;
+
+pub use client::{HttpTrackerClient, HttpTransport, TcpTransport};
+pub use manager::AnnounceManager;
+
+pub use bip_util::bt::{InfoHash, PeerId};
@@ -0,0 +1,239 @@
+use std::time::Instant;
+
+use bip_util;
+
+use announce::{AnnounceRequest, AnnounceResponse};
+use client::{HttpTrackerClient, HttpTransport, TcpTransport};
+use error::{TrackerErrorKind, TrackerResult};
+
+/// Tracker within a tier, along with when it asked us to announce again.
+struct TrackerEntry {
+    url:           String,
+    next_announce: Option<Instant>,
+}
+
+impl TrackerEntry {
+    fn new(url: String) -> TrackerEntry {
+        TrackerEntry{ url: url, next_announce: None }
+    }
+}
+
+/// Announces to a tiered list of trackers, as specified in BEP 12.
+///
+/// Tiers are tried in order, as are the trackers within a tier. When a tracker
+/// responds, it is moved to the front of its tier so that it is tried first on
+/// the next announce.
+pub struct AnnounceManager<T = TcpTransport> {
+    client:        HttpTrackerClient<T>,
+    tiers:         Vec<Vec<TrackerEntry>>,
+    next_announce: Option<Instant>,
+}
+
+impl AnnounceManager<TcpTransport> {
+    /// Create a new AnnounceManager for plain `http` trackers.
+    ///
+    /// Trackers within each tier are shuffled, as specified in BEP 12.
+    pub fn new(tiers: Vec<Vec<String>>) -> AnnounceManager<TcpTransport> {
+        AnnounceManager::with_client(HttpTrackerClient::new(), tiers)
+    }
+}
+
+impl<T> AnnounceManager<T> where T: HttpTransport {
+    /// Create a new AnnounceManager sending announces through the given client.
+    ///
+    /// Trackers within each tier are shuffled, as specified in BEP 12.
+    pub fn with_client(client: HttpTrackerClient<T>, mut tiers: Vec<Vec<String>>) -> AnnounceManager<T> {
+        for tier in tiers.iter_mut() {
+            bip_util::fisher_shuffle(tier);
+        }
+
+        AnnounceManager::with_ordered_tiers(client, tiers)
+    }
+
+    /// Create a new AnnounceManager sending announces through the given client.
+    ///
+    /// Trackers within each tier are kept in the given order, which is useful for
+    /// restoring tiers that were already shuffled and reordered in a previous session.
+    pub fn with_ordered_tiers(client: HttpTrackerClient<T>, tiers: Vec<Vec<String>>) -> AnnounceManager<T> {
+        let tiers = tiers.into_iter()
+            .map(|tier| tier.into_iter().map(TrackerEntry::new).collect())
+            .collect();
+
+        AnnounceManager{ client: client, tiers: tiers, next_announce: None }
+    }
+
+    /// Current ordering of the tiers, and the tracker urls within them.
+    pub fn tiers(&self) -> Vec<Vec<&str>> {
+        self.tiers.iter()
+            .map(|tier| tier.iter().map(|entry| &entry.url[..]).collect())
+            .collect()
+    }
+
+    /// When the tracker that last responded asked us to announce again, if any tracker has responded.
+    pub fn next_announce(&self) -> Option<Instant> {
+        self.next_announce
+    }
+
+    /// When the given tracker asked us to announce again, if it has responded to us.
+    pub fn tracker_next_announce(&self, url: &str) -> Option<Instant> {
+        self.tiers.iter()
+            .flat_map(|tier| tier.iter())
+            .find(|entry| entry.url == url)
+            .and_then(|entry| entry.next_announce)
+    }
+
+    /// Whether the interval given by the tracker that last responded has elapsed.
+    ///
+    /// If no tracker has responded yet, an announce is always due.
+    pub fn is_announce_due(&self, now: Instant) -> bool {
+        self.next_announce.map(|next_announce| now >= next_announce).unwrap_or(true)
+    }
+
+    /// Announce to the first tracker that responds, returning its response.
+    ///
+    /// If every tracker fails, the error from the last tracker tried is returned.
+    pub fn announce(&mut self, request: &AnnounceRequest) -> TrackerResult<AnnounceResponse> {
+        let mut last_error = None;
+
+        for tier_index in 0..self.tiers.len() {
+            for tracker_index in 0..self.tiers[tier_index].len() {
+                let result = self.client.announce(&self.tiers[tier_index][tracker_index].url, request);
+
+                // A tracker asking for an interval we can not represent is treated as a failed announce
+                let result = result.and_then(|response| {
+                    match Instant::now().checked_add(response.interval()) {
+                        Some(next_announce) => Ok((response, next_announce)),
+                        None => Err(TrackerErrorKind::InvalidResponse{
+                            details: format!("Interval Of {:?} Is Out Of Range", response.interval())
+                        }.into())
+                    }
+                });
+
+                match result {
+                    Ok((response, next_announce)) => {
+                        let mut entry = self.tiers[tier_index].remove(tracker_index);
+                        entry.next_announce = Some(next_announce);
+                        self.tiers[tier_index].insert(0, entry);
+
+                        self.next_announce = Some(next_announce);
+
+                        return Ok(response)
+                    },
+                    Err(error) => last_error = Some(error)
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(TrackerErrorKind::NoTrackers.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    use bip_util::bt::{self, InfoHash, PeerId};
+
+    use announce::AnnounceRequest;
+    use client::{HttpTrackerClient, HttpTransport};
+    use error::{TrackerErrorKind, TrackerResult};
+    use super::AnnounceManager;
+
+    /// Transport where only trackers under the working url respond.
+    struct MockTransport {
+        working:   &'static str,
+        interval:  i64,
+        requested: RefCell<Vec<String>>,
+    }
+
+    impl<'a> HttpTransport for &'a MockTransport {
+        fn get(&self, url: &str) -> TrackerResult<Vec<u8>> {
+            self.requested.borrow_mut().push(url.split('?').next().unwrap().to_owned());
+
+            if url.starts_with(self.working) {
+                Ok(format!("d8:intervali{}e5:peers0:e", self.interval).into_bytes())
+            } else {
+                Err(io::Error::new(io::ErrorKind::ConnectionRefused, "Connection Refused").into())
+            }
+        }
+    }
+
+    fn tiers(tiers: &[&[&str]]) -> Vec<Vec<String>> {
+        tiers.iter().map(|tier| tier.iter().map(|url| url.to_string()).collect()).collect()
+    }
+
+    fn request() -> AnnounceRequest {
+        AnnounceRequest::new(InfoHash::from([0u8; bt::INFO_HASH_LEN]), PeerId::from([0u8; bt::PEER_ID_LEN]), 6881)
+    }
+
+    #[test]
+    fn positive_failover_promotes_working_tracker() {
+        let transport = MockTransport{ working: "http://b", interval: 1800, requested: RefCell::new(Vec::new()) };
+        let mut manager = AnnounceManager::with_ordered_tiers(HttpTrackerClient::with_transport(&transport),
+                                                              tiers(&[&["http://a", "http://b", "http://c"], &["http://d"]]));
+
+        let before = Instant::now();
+        manager.announce(&request()).unwrap();
+
+        assert_eq!(vec!["http://a", "http://b"], *transport.requested.borrow());
+        assert_eq!(vec![vec!["http://b", "http://a", "http://c"], vec!["http://d"]], manager.tiers());
+        assert!(manager.next_announce().unwrap() >= before + Duration::from_secs(1800));
+        assert!(manager.tracker_next_announce("http://a").is_none());
+        assert!(!manager.is_announce_due(before));
+
+        // Working tracker is tried first from now on
+        transport.requested.borrow_mut().clear();
+        manager.announce(&request()).unwrap();
+
+        assert_eq!(vec!["http://b"], *transport.requested.borrow());
+    }
+
+    #[test]
+    fn positive_failover_to_next_tier() {
+        let transport = MockTransport{ working: "http://d", interval: 1800, requested: RefCell::new(Vec::new()) };
+        let mut manager = AnnounceManager::with_ordered_tiers(HttpTrackerClient::with_transport(&transport),
+                                                              tiers(&[&["http://a", "http://b"], &["http://c", "http://d"]]));
+
+        manager.announce(&request()).unwrap();
+
+        assert_eq!(vec!["http://a", "http://b", "http://c", "http://d"], *transport.requested.borrow());
+        assert_eq!(vec![vec!["http://a", "http://b"], vec!["http://d", "http://c"]], manager.tiers());
+    }
+
+    #[test]
+    fn negative_all_trackers_fail() {
+        let transport = MockTransport{ working: "http://z", interval: 1800, requested: RefCell::new(Vec::new()) };
+        let mut manager = AnnounceManager::with_ordered_tiers(HttpTrackerClient::with_transport(&transport),
+                                                              tiers(&[&["http://a"], &["http://b"]]));
+
+        assert!(manager.announce(&request()).is_err());
+        assert!(manager.is_announce_due(Instant::now()));
+    }
+
+    #[test]
+    fn negative_no_trackers() {
+        let transport = MockTransport{ working: "http://a", interval: 1800, requested: RefCell::new(Vec::new()) };
+        let mut manager = AnnounceManager::with_client(HttpTrackerClient::with_transport(&transport), Vec::new());
+
+        match manager.announce(&request()).unwrap_err().kind() {
+            &TrackerErrorKind::NoTrackers => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_interval_out_of_range() {
+        let transport = MockTransport{ working: "http://b", interval: i64::max_value(), requested: RefCell::new(Vec::new()) };
+        let mut manager = AnnounceManager::with_ordered_tiers(HttpTrackerClient::with_transport(&transport),
+                                                              tiers(&[&["http://a", "http://b"]]));
+
+        match manager.announce(&request()).unwrap_err().kind() {
+            &TrackerErrorKind::InvalidResponse{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+        assert_eq!(vec![vec!["http://a", "http://b"]], manager.tiers());
+        assert!(manager.is_announce_due(Instant::now()));
+    }
+}
@@ -0,0 +1,197 @@
+//! Parsing of the peer lists given in tracker responses.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use bip_bencode::{BConvert, BDictAccess, BListAccess, BRefAccess};
+
+use convert::CONVERT;
+use error::{TrackerErrorKind, TrackerResult};
+
+/// Keys found within the root dictionary of a tracker response.
+pub const PEERS_KEY:  &'static [u8] = b"peers";
+pub const PEERS6_KEY: &'static [u8] = b"peers6";
+
+/// Keys found within a peer dictionary of a tracker response.
+const IP_KEY:   &'static [u8] = b"ip";
+const PORT_KEY: &'static [u8] = b"port";
+
+/// Number of bytes for a single IPv4 peer in the compact form.
+pub const COMPACT_PEER_LEN: usize = 6;
+/// Number of bytes for a single IPv6 peer in the compact form.
+pub const COMPACT_PEER6_LEN: usize = 18;
+
+/// Parse all peers within the root dictionary of a tracker response.
+///
+/// Peers under the `peers` key may be in the compact IPv4 form, or a list of dictionaries,
+/// while peers under the `peers6` key are in the compact IPv6 form. Either key may be missing.
+pub fn parse_peers<B>(root_dict: &BDictAccess<B::BKey, B>) -> TrackerResult<Vec<SocketAddr>>
+    where B: BRefAccess<BType=B> {
+    let mut peers = match root_dict.lookup(PEERS_KEY) {
+        Some(peers) => {
+            match peers.bytes() {
+                Some(compact_peers) => try!(parse_compact_peers(compact_peers)),
+                None                => try!(parse_dict_peers(try!(CONVERT.convert_list(peers, PEERS_KEY))))
+            }
+        },
+        None => Vec::new()
+    };
+
+    if root_dict.lookup(PEERS6_KEY).is_some() {
+        let compact_peers6 = try!(CONVERT.lookup_and_convert_bytes(root_dict, PEERS6_KEY));
+
+        peers.extend(try!(parse_compact_peers6(compact_peers6)));
+    }
+
+    Ok(peers)
+}
+
+/// Parse peers in the compact IPv4 form, 4 bytes of address followed by 2 bytes of port.
+///
+/// This is the same form that UDP trackers use, so it can be applied to those responses as well.
+pub fn parse_compact_peers(bytes: &[u8]) -> TrackerResult<Vec<SocketAddr>> {
+    try!(check_compact_len(bytes, COMPACT_PEER_LEN));
+
+    Ok(bytes.chunks(COMPACT_PEER_LEN).map(|chunk| {
+        let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
+
+        SocketAddr::V4(SocketAddrV4::new(ip, port_from_bytes(&chunk[4..])))
+    }).collect())
+}
+
+/// Parse peers in the compact IPv6 form, 16 bytes of address followed by 2 bytes of port.
+///
+/// This is the same form that UDP trackers use, so it can be applied to those responses as well.
+pub fn parse_compact_peers6(bytes: &[u8]) -> TrackerResult<Vec<SocketAddr>> {
+    try!(check_compact_len(bytes, COMPACT_PEER6_LEN));
+
+    Ok(bytes.chunks(COMPACT_PEER6_LEN).map(|chunk| {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&chunk[..16]);
+
+        SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(octets), port_from_bytes(&chunk[16..]), 0, 0))
+    }).collect())
+}
+
+/// Parse peers given as a list of dictionaries with `ip`, `port`, and optionally `peer id` keys.
+///
+/// Peers whose ip is a hostname instead of an address are skipped, since resolving them would block.
+pub fn parse_dict_peers<B>(peer_list: &BListAccess<B>) -> TrackerResult<Vec<SocketAddr>>
+    where B: BRefAccess<BType=B> {
+    let mut peers = Vec::with_capacity(peer_list.len());
+
+    for peer in peer_list {
+        let peer_dict = try!(CONVERT.convert_dict(peer, PEERS_KEY));
+        let ip = try!(CONVERT.lookup_and_convert_str(peer_dict, IP_KEY));
+        let port = try!(CONVERT.lookup_and_convert_int(peer_dict, PORT_KEY));
+
+        if port < 0 || port > u16::max_value() as i64 {
+            return Err(TrackerErrorKind::InvalidResponse{ details: format!("Peer Port {} Is Out Of Range", port) }.into())
+        }
+
+        if let Ok(ip) = ip.parse::<IpAddr>() {
+            peers.push(SocketAddr::new(ip, port as u16));
+        }
+    }
+
+    Ok(peers)
+}
+
+fn check_compact_len(bytes: &[u8], entry_len: usize) -> TrackerResult<()> {
+    if bytes.len() % entry_len != 0 {
+        Err(TrackerErrorKind::InvalidResponse{ details: format!("Compact Peers Length {} Is Not A Multiple Of {}",
+                                                                bytes.len(), entry_len) }.into())
+    } else {
+        Ok(())
+    }
+}
+
+fn port_from_bytes(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) << 8 | bytes[1] as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bip_bencode::{BencodeRef, BDecodeOpt, BRefAccess};
+
+    use error::{TrackerErrorKind, TrackerResult};
+
+    fn parse_peers(bytes: &[u8]) -> TrackerResult<Vec<SocketAddr>> {
+        let bencode = BencodeRef::decode(bytes, BDecodeOpt::default()).unwrap();
+
+        super::parse_peers(bencode.dict().unwrap())
+    }
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn positive_parse_compact_peers() {
+        let mut bytes = b"d5:peers12:".to_vec();
+        bytes.extend_from_slice(&[127, 0, 0, 1, 0x1A, 0xE1, 10, 0, 0, 2, 0x1A, 0xE2]);
+        bytes.push(b'e');
+
+        assert_eq!(addrs(&["127.0.0.1:6881", "10.0.0.2:6882"]), parse_peers(&bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_parse_compact_peers6() {
+        let mut bytes = b"d6:peers618:".to_vec();
+        bytes.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x1A, 0xE1]);
+        bytes.push(b'e');
+
+        assert_eq!(addrs(&["[2001:db8::1]:6881"]), parse_peers(&bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_parse_dict_peers() {
+        let bytes = b"d5:peersld2:ip9:127.0.0.17:peer id20:AAAAAAAAAAAAAAAAAAAA4:porti6881eed2:ip3:::14:porti6882eeee";
+
+        assert_eq!(addrs(&["127.0.0.1:6881", "[::1]:6882"]), parse_peers(&bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn positive_parse_dict_peers_skips_hostname() {
+        let bytes = b"d5:peersld2:ip15:tracker.example4:porti6881eeee";
+
+        assert!(parse_peers(&bytes[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn positive_parse_peers_and_peers6() {
+        let mut bytes = b"d5:peers6:".to_vec();
+        bytes.extend_from_slice(&[127, 0, 0, 1, 0x1A, 0xE1]);
+        bytes.extend_from_slice(b"6:peers618:");
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x1A, 0xE2]);
+        bytes.push(b'e');
+
+        assert_eq!(addrs(&["127.0.0.1:6881", "[::1]:6882"]), parse_peers(&bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_parse_no_peers() {
+        assert!(parse_peers(b"d8:intervali60ee").unwrap().is_empty());
+    }
+
+    #[test]
+    fn negative_parse_compact_peers_bad_length() {
+        let error = parse_peers(b"d5:peers7:aaaaaaae").unwrap_err();
+
+        match error.kind() {
+            &TrackerErrorKind::InvalidResponse{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_compact_peers6_bad_length() {
+        let error = parse_peers(b"d6:peers66:aaaaaae").unwrap_err();
+
+        match error.kind() {
+            &TrackerErrorKind::InvalidResponse{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+}
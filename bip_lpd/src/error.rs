@@ -0,0 +1,22 @@
+//! Errors for local service discovery.
+
+use std::io;
+
+error_chain! {
+    types {
+        LsdError, LsdErrorKind, LsdResultEx, LsdResult;
+    }
+
+    foreign_links {
+        Io(io::Error);
+    }
+
+    errors {
+        InvalidMessage {
+            details: String
+        } {
+            description("Local Service Discovery Message Is Invalid")
+            display("Local Service Discovery Message Is Invalid: {}", details)
+        }
+    }
+}
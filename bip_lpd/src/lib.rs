@@ -1,3 +1,19 @@
-#[test]
-fn it_works() {
-}
+//! Library for discovering peers on the local network, as specified in BEP 14.
+//!
+//! Includes parsing and writing of `BT-SEARCH` announce messages, and a blocking
+//! `LocalDiscovery` service which multicasts announces for our torrents and turns
+//! announces from other peers into peer candidates.
+
+extern crate bip_util;
+#[macro_use]
+extern crate error_chain;
+extern crate rand;
+
+mod service;
+
+pub mod error;
+pub mod message;
+
+pub use service::LocalDiscovery;
+
+pub use bip_util::bt::InfoHash;
@@ -0,0 +1,204 @@
+//! Messaging primitives for local service discovery announces.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use bip_util::bt::{self, InfoHash};
+
+use error::{LsdErrorKind, LsdResult};
+
+/// Port that local service discovery announces are multicast to.
+pub const LSD_PORT: u16 = 6771;
+
+/// IPv4 group that local service discovery announces are multicast to.
+pub const LSD_IPV4_GROUP: Ipv4Addr = Ipv4Addr::new(239, 192, 152, 143);
+
+/// IPv6 group that local service discovery announces are multicast to.
+pub const LSD_IPV6_GROUP: Ipv6Addr = Ipv6Addr::new(0xff15, 0, 0, 0, 0, 0, 0xefc0, 0x988f);
+
+/// Request line starting every announce message.
+const REQUEST_LINE: &'static str = "BT-SEARCH * HTTP/1.1";
+
+/// Headers found within an announce message.
+const HOST_HEADER:     &'static str = "Host";
+const PORT_HEADER:     &'static str = "Port";
+const INFOHASH_HEADER: &'static str = "Infohash";
+const COOKIE_HEADER:   &'static str = "cookie";
+
+/// Address that IPv4 announces are multicast to.
+pub fn ipv4_multicast_addr() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(LSD_IPV4_GROUP, LSD_PORT))
+}
+
+/// Address that IPv6 announces are multicast to.
+pub fn ipv6_multicast_addr() -> SocketAddr {
+    SocketAddr::V6(SocketAddrV6::new(LSD_IPV6_GROUP, LSD_PORT, 0, 0))
+}
+
+//----------------------------------------------------------------------------//
+
+/// Announce multicast to peers on the local network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsdMessage {
+    port:        u16,
+    info_hashes: Vec<InfoHash>,
+    cookie:      Option<String>,
+}
+
+impl LsdMessage {
+    /// Create a new LsdMessage announcing that we are listening on the given port for the given torrents.
+    pub fn new(port: u16, info_hashes: Vec<InfoHash>) -> LsdMessage {
+        LsdMessage{ port: port, info_hashes: info_hashes, cookie: None }
+    }
+
+    /// Set the cookie we use to recognize our own announces.
+    pub fn set_cookie(mut self, cookie: String) -> LsdMessage {
+        self.cookie = Some(cookie);
+
+        self
+    }
+
+    /// Parse an LsdMessage from the bytes of a datagram.
+    ///
+    /// Header names are matched case insensitively, and unknown headers are ignored.
+    pub fn from_bytes(bytes: &[u8]) -> LsdResult<LsdMessage> {
+        let message = try!(::std::str::from_utf8(bytes).map_err(|_| invalid("Message Is Not Valid UTF-8")));
+        let mut lines = message.lines();
+
+        if lines.next().map(|line| line.trim()) != Some(REQUEST_LINE) {
+            return Err(invalid("Missing BT-SEARCH Request Line").into())
+        }
+
+        let mut opt_port = None;
+        let mut info_hashes = Vec::new();
+        let mut cookie = None;
+
+        for line in lines.map(|line| line.trim()).take_while(|line| !line.is_empty()) {
+            let (name, value) = match line.find(':') {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None        => return Err(invalid(&format!("Header {:?} Is Missing A Value", line)).into())
+            };
+
+            if name.eq_ignore_ascii_case(PORT_HEADER) {
+                opt_port = Some(try!(value.parse::<u16>().map_err(|_| invalid(&format!("Port {:?} Is Invalid", value)))));
+            } else if name.eq_ignore_ascii_case(INFOHASH_HEADER) {
+                info_hashes.push(try!(parse_info_hash(value)));
+            } else if name.eq_ignore_ascii_case(COOKIE_HEADER) {
+                cookie = Some(value.to_owned());
+            }
+        }
+
+        let port = try!(opt_port.ok_or_else(|| invalid("Missing Port Header")));
+        if info_hashes.is_empty() {
+            return Err(invalid("Missing Infohash Header").into())
+        }
+
+        Ok(LsdMessage{ port: port, info_hashes: info_hashes, cookie: cookie })
+    }
+
+    /// Write the LsdMessage for multicasting to the given group address.
+    pub fn to_bytes(&self, group_addr: &SocketAddr) -> Vec<u8> {
+        let mut message = format!("{}\r\n{}: {}\r\n{}: {}\r\n", REQUEST_LINE, HOST_HEADER, group_addr, PORT_HEADER, self.port);
+
+        for info_hash in self.info_hashes.iter() {
+            message.push_str(INFOHASH_HEADER);
+            message.push_str(": ");
+            for byte in info_hash.as_ref() {
+                message.push_str(&format!("{:02x}", byte));
+            }
+            message.push_str("\r\n");
+        }
+
+        if let Some(ref cookie) = self.cookie {
+            message.push_str(&format!("{}: {}\r\n", COOKIE_HEADER, cookie));
+        }
+        message.push_str("\r\n\r\n");
+
+        message.into_bytes()
+    }
+
+    /// Port the announcing peer is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Torrents the announcing peer is interested in.
+    pub fn info_hashes(&self) -> &[InfoHash] {
+        &self.info_hashes
+    }
+
+    /// Cookie the announcing peer uses to recognize its own announces, if any.
+    pub fn cookie(&self) -> Option<&str> {
+        self.cookie.as_ref().map(|cookie| &cookie[..])
+    }
+}
+
+fn parse_info_hash(hex: &str) -> LsdResult<InfoHash> {
+    if hex.len() != bt::INFO_HASH_LEN * 2 || !hex.is_ascii() {
+        return Err(invalid(&format!("Infohash {:?} Is Not {} Hex Characters", hex, bt::INFO_HASH_LEN * 2)).into())
+    }
+
+    let mut info_hash = [0u8; bt::INFO_HASH_LEN];
+    for (index, byte) in info_hash.iter_mut().enumerate() {
+        *byte = try!(u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .map_err(|_| invalid(&format!("Infohash {:?} Is Not Hex", hex))));
+    }
+
+    Ok(info_hash.into())
+}
+
+fn invalid(details: &str) -> LsdErrorKind {
+    LsdErrorKind::InvalidMessage{ details: details.to_owned() }
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_util::bt::{self, InfoHash};
+
+    use super::LsdMessage;
+    use error::LsdErrorKind;
+
+    #[test]
+    fn positive_message_round_trip() {
+        let message = LsdMessage::new(6881, vec![InfoHash::from([0xABu8; bt::INFO_HASH_LEN]), InfoHash::from([1u8; bt::INFO_HASH_LEN])])
+            .set_cookie("a1b2c3".to_owned());
+
+        let bytes = message.to_bytes(&super::ipv4_multicast_addr());
+
+        assert_eq!(message, LsdMessage::from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_message_format() {
+        let message = LsdMessage::new(6881, vec![InfoHash::from([0xABu8; bt::INFO_HASH_LEN])]);
+
+        let expected = format!("BT-SEARCH * HTTP/1.1\r\nHost: 239.192.152.143:6771\r\nPort: 6881\r\nInfohash: {}\r\n\r\n\r\n",
+                               "ab".repeat(bt::INFO_HASH_LEN));
+        assert_eq!(expected.into_bytes(), message.to_bytes(&super::ipv4_multicast_addr()));
+    }
+
+    #[test]
+    fn positive_parse_case_insensitive_headers() {
+        let bytes = format!("BT-SEARCH * HTTP/1.1\r\nhost: [ff15::efc0:988f]:6771\r\nPORT: 51413\r\ninfohash: {}\r\nCookie: x\r\n\r\n",
+                            "CD".repeat(bt::INFO_HASH_LEN));
+
+        let message = LsdMessage::from_bytes(bytes.as_bytes()).unwrap();
+        assert_eq!(51413, message.port());
+        assert_eq!(&[InfoHash::from([0xCDu8; bt::INFO_HASH_LEN])][..], message.info_hashes());
+        assert_eq!(Some("x"), message.cookie());
+    }
+
+    #[test]
+    fn negative_parse_missing_infohash() {
+        let error = LsdMessage::from_bytes(b"BT-SEARCH * HTTP/1.1\r\nPort: 6881\r\n\r\n").unwrap_err();
+
+        match error.kind() {
+            &LsdErrorKind::InvalidMessage{ .. } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_wrong_request_line() {
+        assert!(LsdMessage::from_bytes(b"M-SEARCH * HTTP/1.1\r\nPort: 6881\r\n\r\n").is_err());
+    }
+}
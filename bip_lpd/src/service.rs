@@ -0,0 +1,212 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bip_util::bt::InfoHash;
+use rand::{self, Rng};
+
+use error::LsdResult;
+use message::{self, LsdMessage};
+
+/// Interval at which we announce our torrents, BEP 14 asks for no more than once every five minutes.
+const DEFAULT_ANNOUNCE_INTERVAL_SECS: u64 = 5 * 60;
+
+/// Number of infohashes we put in a single announce, keeping announces well within a single datagram.
+const MAX_INFO_HASHES_PER_ANNOUNCE: usize = 20;
+
+/// Length of the cookie we attach to our announces.
+const COOKIE_LEN: usize = 8;
+
+/// Largest announce we will receive.
+const MAX_MESSAGE_LEN: usize = 1500;
+
+/// Local service discovery over IPv4 multicast, as specified in BEP 14.
+///
+/// Our torrents are announced periodically, and announces from other peers for
+/// those same torrents are turned into peer candidates. A random cookie is attached
+/// to each of our announces, so that we can ignore them when they are looped back.
+///
+/// Sending and receiving are blocking, so callers wanting to discover peers in the
+/// background should do so from their own thread.
+pub struct LocalDiscovery {
+    socket:            UdpSocket,
+    group_addr:        SocketAddr,
+    port:              u16,
+    cookie:            String,
+    torrents:          Vec<InfoHash>,
+    announce_interval: Duration,
+    next_announce:     Option<Instant>,
+}
+
+impl LocalDiscovery {
+    /// Create a new LocalDiscovery announcing that we are listening on the given port.
+    ///
+    /// Binds to the local service discovery port on all interfaces, and joins the multicast group.
+    pub fn new(port: u16) -> LsdResult<LocalDiscovery> {
+        let socket = try!(UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), message::LSD_PORT)));
+        try!(socket.join_multicast_v4(&message::LSD_IPV4_GROUP, &Ipv4Addr::new(0, 0, 0, 0)));
+
+        Ok(LocalDiscovery::with_socket(socket, message::ipv4_multicast_addr(), port))
+    }
+
+    /// Create a new LocalDiscovery announcing to the given group address over the given socket.
+    ///
+    /// The socket should already be bound, and have joined the group if it is a multicast group.
+    pub fn with_socket(socket: UdpSocket, group_addr: SocketAddr, port: u16) -> LocalDiscovery {
+        let cookie = rand::thread_rng().gen_ascii_chars().take(COOKIE_LEN).collect();
+
+        LocalDiscovery{ socket: socket, group_addr: group_addr, port: port, cookie: cookie, torrents: Vec::new(),
+                        announce_interval: Duration::from_secs(DEFAULT_ANNOUNCE_INTERVAL_SECS), next_announce: None }
+    }
+
+    /// Set the interval at which we announce our torrents.
+    pub fn set_announce_interval(&mut self, interval: Duration) {
+        self.announce_interval = interval;
+    }
+
+    /// Cookie attached to our announces.
+    pub fn cookie(&self) -> &str {
+        &self.cookie
+    }
+
+    /// Start announcing, and discovering peers for, the given torrent.
+    ///
+    /// The torrent is included in the next announce, which is sent on the next call to `announce_if_due`.
+    pub fn add_torrent(&mut self, hash: InfoHash) {
+        if !self.torrents.contains(&hash) {
+            self.torrents.push(hash);
+            self.next_announce = None;
+        }
+    }
+
+    /// Stop announcing, and discovering peers for, the given torrent.
+    pub fn remove_torrent(&mut self, hash: &InfoHash) {
+        self.torrents.retain(|torrent| torrent != hash);
+    }
+
+    /// Announces for all of our torrents, split so that each announce fits within a datagram.
+    pub fn announce_messages(&self) -> Vec<LsdMessage> {
+        self.torrents.chunks(MAX_INFO_HASHES_PER_ANNOUNCE)
+            .map(|hashes| LsdMessage::new(self.port, hashes.to_vec()).set_cookie(self.cookie.clone()))
+            .collect()
+    }
+
+    /// Multicast announces for our torrents if the announce interval has elapsed, or a torrent was added.
+    ///
+    /// Returns whether or not we announced.
+    pub fn announce_if_due(&mut self, now: Instant) -> LsdResult<bool> {
+        let due = self.next_announce.map(|next_announce| now >= next_announce).unwrap_or(true);
+        if !due || self.torrents.is_empty() {
+            return Ok(false)
+        }
+
+        for message in self.announce_messages() {
+            try!(self.socket.send_to(&message.to_bytes(&self.group_addr), self.group_addr));
+        }
+        self.next_announce = Some(now + self.announce_interval);
+
+        Ok(true)
+    }
+
+    /// Receive a single announce, returning the peers it announced for our torrents.
+    ///
+    /// Announces that are invalid, or that are our own, produce no peers.
+    pub fn recv_peers(&self) -> LsdResult<Vec<(InfoHash, SocketAddr)>> {
+        let mut buffer = [0u8; MAX_MESSAGE_LEN];
+        let (num_bytes, from) = try!(self.socket.recv_from(&mut buffer));
+
+        Ok(self.handle_message(&buffer[..num_bytes], from))
+    }
+
+    /// Turn an announce received from the given address into peers for our torrents.
+    ///
+    /// Peers are reachable at the address the announce came from, on the port given in the announce.
+    pub fn handle_message(&self, bytes: &[u8], from: SocketAddr) -> Vec<(InfoHash, SocketAddr)> {
+        let message = match LsdMessage::from_bytes(bytes) {
+            Ok(message) => message,
+            Err(_)      => return Vec::new()
+        };
+
+        if message.cookie() == Some(&self.cookie[..]) {
+            return Vec::new()
+        }
+
+        let peer_addr = SocketAddr::new(from.ip(), message.port());
+        message.info_hashes().iter()
+            .filter(|hash| self.torrents.contains(hash))
+            .map(|hash| (*hash, peer_addr))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    use bip_util::bt::{self, InfoHash};
+
+    use message::LsdMessage;
+    use super::LocalDiscovery;
+
+    fn discovery(port: u16) -> LocalDiscovery {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let group_addr = socket.local_addr().unwrap();
+
+        LocalDiscovery::with_socket(socket, group_addr, port)
+    }
+
+    fn hash(byte: u8) -> InfoHash {
+        InfoHash::from([byte; bt::INFO_HASH_LEN])
+    }
+
+    #[test]
+    fn positive_handle_peer_announce() {
+        let mut discovery = discovery(6881);
+        discovery.add_torrent(hash(1));
+
+        let from: SocketAddr = "192.168.1.20:6771".parse().unwrap();
+        let announce = LsdMessage::new(51413, vec![hash(1), hash(2)]).set_cookie("other".to_owned());
+
+        let peers = discovery.handle_message(&announce.to_bytes(&from), from);
+        assert_eq!(vec![(hash(1), "192.168.1.20:51413".parse().unwrap())], peers);
+    }
+
+    #[test]
+    fn positive_ignore_own_announce() {
+        let mut discovery = discovery(6881);
+        discovery.add_torrent(hash(1));
+
+        let from: SocketAddr = "192.168.1.10:6771".parse().unwrap();
+        let own_announce = discovery.announce_messages().remove(0);
+        assert_eq!(Some(discovery.cookie()), own_announce.cookie());
+
+        assert!(discovery.handle_message(&own_announce.to_bytes(&from), from).is_empty());
+    }
+
+    #[test]
+    fn positive_announce_looped_back_and_ignored() {
+        let mut discovery = discovery(6881);
+        discovery.add_torrent(hash(1));
+        discovery.set_announce_interval(Duration::from_secs(60));
+
+        // Group address is our own socket, so our announce is looped back to us
+        let now = Instant::now();
+        assert!(discovery.announce_if_due(now).unwrap());
+        assert!(!discovery.announce_if_due(now + Duration::from_secs(30)).unwrap());
+        assert!(discovery.recv_peers().unwrap().is_empty());
+
+        assert!(discovery.announce_if_due(now + Duration::from_secs(60)).unwrap());
+    }
+
+    #[test]
+    fn positive_announces_split_across_datagrams() {
+        let mut discovery = discovery(6881);
+        for byte in 0..45 {
+            discovery.add_torrent(hash(byte));
+        }
+
+        let messages = discovery.announce_messages();
+        assert_eq!(vec![20, 20, 5], messages.iter().map(|message| message.info_hashes().len()).collect::<Vec<_>>());
+        assert!(messages.iter().all(|message| message.to_bytes(&"239.192.152.143:6771".parse().unwrap()).len() <= 1400));
+    }
+}
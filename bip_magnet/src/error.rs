@@ -0,0 +1,32 @@
+//! Errors for magnet link parsing.
+
+use url;
+
+error_chain! {
+    types {
+        MagnetError, MagnetErrorKind, MagnetResultExt, MagnetResult;
+    }
+
+    foreign_links {
+        Url(url::ParseError);
+    }
+
+    errors {
+        InvalidScheme {
+            scheme: String
+        } {
+            description("Url Scheme Is Not Magnet")
+            display("Url Scheme Is {:?} But Should Have Been \"magnet\"", scheme)
+        }
+        MissingInfoHash {
+            description("Magnet Link Has No BitTorrent InfoHash Exact Topic")
+            display("Magnet Link Has No BitTorrent InfoHash Exact Topic")
+        }
+        InvalidInfoHash {
+            topic: String
+        } {
+            description("Magnet Link Has A Malformed BitTorrent InfoHash")
+            display("Magnet Link Has A Malformed BitTorrent InfoHash In Exact Topic {:?}", topic)
+        }
+    }
+}
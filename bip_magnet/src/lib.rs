@@ -1,12 +1,20 @@
 extern crate bip_util;
 extern crate url;
 extern crate base32;
+#[macro_use]
+extern crate error_chain;
 
 use bip_util::bt::InfoHash;
 use bip_util::sha::ShaHash;
 use std::default::Default;
 use url::Url;
 
+pub mod error;
+
+use error::{MagnetError, MagnetErrorKind, MagnetResult};
+
+const BTIH_PREFIX: &'static str = "urn:btih:";
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Topic {
     BitTorrentInfoHash(InfoHash),
@@ -14,7 +22,7 @@ pub enum Topic {
 
 impl Topic {
     fn parse(s: &str) -> Option<Self> {
-        if s.starts_with("urn:btih:") && s.len() == 9 + 40 {
+        if s.starts_with(BTIH_PREFIX) && s.len() == 9 + 40 && s[9..].bytes().all(|b| (b as char).is_digit(16)) {
             // BitTorrent Info Hash, hex
             let mut hash = Vec::with_capacity(20);
             for i in 0..20 {
@@ -28,9 +36,9 @@ impl Topic {
                 Ok(sha_hash) => Some(Topic::BitTorrentInfoHash(sha_hash)),
                 Err(_) => None,
             }
-        } else if s.starts_with("urn:btih:") && s.len() == 9 + 32 {
-            // BitTorrent Info Hash, base-32
-            base32::decode(base32::Alphabet::RFC4648 { padding: true }, &s[9..])
+        } else if s.starts_with(BTIH_PREFIX) && s.len() == 9 + 32 {
+            // BitTorrent Info Hash, base-32 (decoder only accepts upper case)
+            base32::decode(base32::Alphabet::RFC4648 { padding: true }, &s[9..].to_uppercase())
                 .and_then(|hash| match ShaHash::from_hash(&hash[..]) {
                     Ok(sha_hash) => Some(Topic::BitTorrentInfoHash(sha_hash)),
                     Err(_) => None,
@@ -81,23 +89,21 @@ impl Default for MagnetLink {
 }
 
 impl MagnetLink {
-    pub fn parse(s: &str) -> Option<Self> {
+    /// Parse the given magnet link.
+    ///
+    /// Fails if the link is not a magnet url, or if it does not contain a well formed
+    /// BitTorrent InfoHash (hex or base-32) exact topic. Other exact topics are ignored.
+    pub fn parse(s: &str) -> MagnetResult<Self> {
         // Parse URL
-        let url = match Url::parse(s) {
-            Ok(url) => url,
-            Err(_) => return None,
-        };
+        let url = try!(Url::parse(s));
         // Is Magnet Link?
         if url.scheme != "magnet" {
-            return None;
+            return Err(MagnetError::from_kind(MagnetErrorKind::InvalidScheme{ scheme: url.scheme.clone() }));
         };
 
         // Gather Magnet Link data from query string
         let mut result: Self = Default::default();
-        let pairs = match url.query_pairs() {
-            Some(pairs) => pairs,
-            None => return None,
-        };
+        let pairs = url.query_pairs().unwrap_or(Vec::new());
         for (k, v) in pairs {
             match &k[..] {
                 "dn" => result.display_name = Some(v),
@@ -110,6 +116,9 @@ impl MagnetLink {
                 "xt" => {
                     match Topic::parse(&v[..]) {
                         Some(topic) => result.exact_topic = Some(topic),
+                        None if v.starts_with(BTIH_PREFIX) => {
+                            return Err(MagnetError::from_kind(MagnetErrorKind::InvalidInfoHash{ topic: v }))
+                        },
                         None => (),
                     }
                 }
@@ -122,7 +131,21 @@ impl MagnetLink {
             }
         }
 
-        Some(result)
+        if result.exact_topic.is_none() {
+            return Err(MagnetError::from_kind(MagnetErrorKind::MissingInfoHash));
+        }
+
+        Ok(result)
+    }
+
+    /// Display name (dn) of the magnet link.
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_ref().map(|d| &d[..])
+    }
+
+    /// Trackers (tr) of the magnet link, in the order they were given.
+    pub fn trackers(&self) -> &[String] {
+        &self.address_tracker
     }
 
     pub fn get_info_hash(&self) -> Option<InfoHash> {
@@ -133,11 +156,20 @@ impl MagnetLink {
     }
 }
 
+/// Parse the given magnet link, see `MagnetLink::parse`.
+pub fn parse_magnet(s: &str) -> MagnetResult<MagnetLink> {
+    MagnetLink::parse(s)
+}
 
 #[cfg(test)]
 mod tests {
     use bip_util::sha::ShaHash;
 
+    use error::MagnetErrorKind;
+
+    const TPB_INFO_HASH: [u8; 20] = [0xd9, 0xbe, 0x69, 0x09, 0x32, 0x5d, 0x28, 0x91, 0x2f, 0x40,
+                                     0x0f, 0xcb, 0x32, 0x40, 0x05, 0xdd, 0x58, 0x61, 0xe4, 0x9f];
+
     #[test]
     fn test_wikipedia() {
         let url = "magnet:?xt=urn:ed2k:354B15E68FB8F36D7CD88FF94116CDC1
@@ -191,4 +223,59 @@ mod tests {
             "udp://exodus.desync.com:6969",
         ]);
     }
+
+    #[test]
+    fn positive_parse_hex() {
+        let link = ::parse_magnet("magnet:?xt=urn:btih:D9BE6909325D28912F400FCB324005DD5861E49F").unwrap();
+
+        assert_eq!(link.get_info_hash(), Some(ShaHash::from_hash(&TPB_INFO_HASH[..]).unwrap()));
+        assert_eq!(link.display_name(), None);
+        assert!(link.trackers().is_empty());
+    }
+
+    #[test]
+    fn positive_parse_base32_lower_case() {
+        let link = ::parse_magnet("magnet:?xt=urn:btih:3g7gscjsluujcl2ab7fteqaf3vmgdze7&dn=test").unwrap();
+
+        assert_eq!(link.get_info_hash(), Some(ShaHash::from_hash(&TPB_INFO_HASH[..]).unwrap()));
+        assert_eq!(link.display_name(), Some("test"));
+    }
+
+    #[test]
+    fn positive_parse_multiple_trackers() {
+        let link = ::parse_magnet("magnet:?xt=urn:btih:3G7GSCJSLUUJCL2AB7FTEQAF3VMGDZE7\
+                                   &tr=udp%3A%2F%2Fone.com%3A80&tr=http%3A%2F%2Ftwo.com%2Fannounce").unwrap();
+
+        assert_eq!(link.trackers(), &["udp://one.com:80".to_owned(), "http://two.com/announce".to_owned()][..]);
+    }
+
+    #[test]
+    fn negative_parse_missing_exact_topic() {
+        let error = ::parse_magnet("magnet:?dn=test&tr=udp%3A%2F%2Fone.com%3A80").unwrap_err();
+
+        match *error.kind() {
+            MagnetErrorKind::MissingInfoHash => (),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_malformed_info_hash() {
+        let error = ::parse_magnet("magnet:?xt=urn:btih:D9BE6909325D28912F400FCB324005DD5861E49").unwrap_err();
+
+        match *error.kind() {
+            MagnetErrorKind::InvalidInfoHash{ .. } => (),
+            ref other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_non_hex_info_hash() {
+        ::parse_magnet("magnet:?xt=urn:btih:Z9BE6909325D28912F400FCB324005DD5861E49F").unwrap_err();
+    }
+
+    #[test]
+    fn negative_parse_wrong_scheme() {
+        ::parse_magnet("http://example.com/?xt=urn:btih:D9BE6909325D28912F400FCB324005DD5861E49F").unwrap_err();
+    }
 }
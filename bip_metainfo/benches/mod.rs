@@ -15,7 +15,18 @@ mod benches {
         b.iter(|| {
             let direct_accessor = DirectAccessor::new("100MBFile", &file_content);
 
-            MetainfoBuilder::new().build(2, direct_accessor, |_| ()).unwrap();
+            MetainfoBuilder::new().set_hash_threads(2).build(direct_accessor).unwrap();
+        });
+    }
+
+    #[bench]
+    fn bench_build_multi_kb_metainfo_single_thread(b: &mut Bencher) {
+        let file_content = vec![55u8; 10 * 1024 * 1024];
+
+        b.iter(|| {
+            let direct_accessor = DirectAccessor::new("100MBFile", &file_content);
+
+            MetainfoBuilder::new().set_hash_threads(1).build(direct_accessor).unwrap();
         });
     }
 
@@ -44,20 +44,22 @@ fn create_torrent<S>(src_path: S) -> ParseResult<Vec<u8>>
     let mut pb = ProgressBar::new(count);
     pb.format("╢▌▌░╟");
     
+    let mut prev_progress = 0;
     let builder = MetainfoBuilder::new()
         .set_created_by(Some("bip_metainfo"))
-        .set_comment(Some("Just Some Comment"));
-    
-    let mut prev_progress = 0;
-    builder.build(2, src_path, move |progress| {
-        let whole_progress = (progress * (count as f64)) as u64;
-        let delta_progress = whole_progress - prev_progress;
-        
-        if delta_progress > 0 {
-            pb.add(delta_progress);
-        }
-        prev_progress = whole_progress;
-    })
+        .set_comment(Some("Just Some Comment"))
+        .set_hash_threads(2)
+        .set_progress(move |hashed, total| {
+            let whole_progress = ((hashed as f64) / (total as f64) * (count as f64)) as u64;
+            let delta_progress = whole_progress - prev_progress;
+
+            if delta_progress > 0 {
+                pb.add(delta_progress);
+            }
+            prev_progress = whole_progress;
+        });
+
+    builder.build(src_path)
 }
 
 /// Print general information about the torrent.
@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::{self, Cursor, Read};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use bip_util::sha::ShaHash;
 use walkdir::{self, WalkDir, DirEntry};
@@ -25,6 +25,15 @@ pub trait Accessor {
     /// Access the sequential pieces that make up all of the files.
     fn access_pieces<C>(&self, callback: C) -> io::Result<()>
         where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>;
+
+    /// Access the attributes and symlink path, as specified in BEP 47, for all files in the same order as `access_metadata`.
+    ///
+    /// By default, no files have attributes.
+    fn access_attributes<C>(&self, _callback: C) -> io::Result<()>
+        where C: FnMut(Option<&str>, Option<&Path>)
+    {
+        Ok(())
+    }
 }
 
 impl<'a, T> Accessor for &'a T
@@ -45,6 +54,12 @@ impl<'a, T> Accessor for &'a T
     {
         Accessor::access_pieces(*self, callback)
     }
+
+    fn access_attributes<C>(&self, callback: C) -> io::Result<()>
+        where C: FnMut(Option<&str>, Option<&Path>)
+    {
+        Accessor::access_attributes(*self, callback)
+    }
 }
 
 // ----------------------------------------------------------------------------//
@@ -67,6 +82,9 @@ pub enum PieceAccess<'a> {
 // ----------------------------------------------------------------------------//
 
 /// Accessor that pulls data in from the file system.
+///
+/// Files within a directory are accessed in sorted order of their names, so the same content
+/// produces the same torrent regardless of the order the file system lists it in.
 pub struct FileAccessor {
     absolute_path:  PathBuf,
     directory_name: Option<PathBuf>,
@@ -125,7 +143,7 @@ impl Accessor for FileAccessor {
             self.absolute_path.iter().count() - 1
         };
 
-        for res_entry in WalkDir::new(&self.absolute_path).into_iter().filter(entry_file_filter) {
+        for res_entry in sorted_walk(&self.absolute_path).into_iter().filter(entry_file_filter) {
             let entry = try!(res_entry);
             let entry_metadata = try!(entry.metadata());
 
@@ -146,7 +164,7 @@ impl Accessor for FileAccessor {
     fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
         where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
     {
-        for res_entry in WalkDir::new(&self.absolute_path).into_iter().filter(entry_file_filter) {
+        for res_entry in sorted_walk(&self.absolute_path).into_iter().filter(entry_file_filter) {
             let entry = try!(res_entry);
             let mut file = try!(File::open(entry.path()));
 
@@ -157,6 +175,14 @@ impl Accessor for FileAccessor {
     }
 }
 
+/// Walk the given path with the entries of each directory sorted by name.
+///
+/// The order that the OS lists directory entries in can differ between file systems, and even
+/// between runs, so sorting keeps the file list, and the info hash, the same for the same content.
+fn sorted_walk(path: &Path) -> WalkDir {
+    WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name()))
+}
+
 /// Filter that yields true if the entry points to a file.
 fn entry_file_filter(res_entry: &walkdir::Result<DirEntry>) -> bool {
     res_entry.as_ref().map(|f| f.file_type().is_file()).unwrap_or(true)
@@ -164,6 +190,95 @@ fn entry_file_filter(res_entry: &walkdir::Result<DirEntry>) -> bool {
 
 // ----------------------------------------------------------------------------//
 
+/// Accessor that pulls data in from a directory on the file system, in a deterministic order.
+///
+/// Files are collected up front, sorted by their path relative to the directory, so
+/// that the same directory always produces the same torrent. Symbolic links are not
+/// followed, and are left out of the torrent, along with anything that is not a file.
+pub struct DirectoryAccessor {
+    directory_name: PathBuf,
+    files:          Vec<(u64, PathBuf, PathBuf)>,
+}
+
+impl DirectoryAccessor {
+    /// Create a new DirectoryAccessor from the given directory path.
+    pub fn new<T>(path: T) -> io::Result<DirectoryAccessor>
+        where T: AsRef<Path>
+    {
+        let absolute_path = try!(path.as_ref().canonicalize());
+        if !absolute_path.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "DirectoryAccessor Path Is Not A Directory"))
+        }
+        let directory_name = absolute_path.iter().last().map(PathBuf::from).unwrap_or_default();
+
+        let mut files = Vec::new();
+        for res_entry in sorted_walk(&absolute_path).follow_links(false) {
+            let entry = try!(res_entry);
+
+            // Symlinks report their own file type since we are not following them, so they are skipped here
+            if !entry.file_type().is_file() {
+                continue
+            }
+
+            let file_length = try!(entry.metadata()).len();
+            let relative_path = entry.path().strip_prefix(&absolute_path).unwrap()
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name),
+                    _                       => None
+                })
+                .collect();
+
+            files.push((file_length, relative_path, entry.path().to_path_buf()));
+        }
+
+        Ok(DirectoryAccessor{ directory_name: directory_name, files: files })
+    }
+
+    /// Iterator over the length and relative path of each file, in the order they appear in the torrent.
+    pub fn files<'a>(&'a self) -> Box<Iterator<Item=(u64, &'a Path)> + 'a> {
+        Box::new(self.files.iter().map(|&(length, ref relative_path, _)| (length, relative_path.as_path())))
+    }
+}
+
+impl IntoAccessor for DirectoryAccessor {
+    type Accessor = DirectoryAccessor;
+
+    fn into_accessor(self) -> io::Result<DirectoryAccessor> {
+        Ok(self)
+    }
+}
+
+impl Accessor for DirectoryAccessor {
+    fn access_directory(&self) -> Option<&Path> {
+        Some(&self.directory_name)
+    }
+
+    fn access_metadata<C>(&self, mut callback: C) -> io::Result<()>
+        where C: FnMut(u64, &Path)
+    {
+        for (file_length, relative_path) in self.files() {
+            callback(file_length, relative_path);
+        }
+
+        Ok(())
+    }
+
+    fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
+        where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
+    {
+        for &(_, _, ref absolute_path) in self.files.iter() {
+            let mut file = try!(File::open(absolute_path));
+
+            try!(callback(PieceAccess::Compute(&mut file)));
+        }
+
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
 /// Accessor that pulls data in directly from memory.
 pub struct DirectAccessor<'a> {
     file_name: &'a str,
@@ -1,13 +1,18 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::iter::ExactSizeIterator;
+use std::path::Path;
 
-use bip_bencode::{BencodeMut, BMutAccess, BRefAccess};
+use bip_bencode::{BencodeMut, BDictAccess, BMutAccess, BRefAccess};
+use bip_util::bt::InfoHash;
 use bip_util::sha::{self, ShaHash};
 
-use accessor::{Accessor, IntoAccessor};
+use accessor::{Accessor, DirectoryAccessor, IntoAccessor};
 use error::ParseResult;
 use parse;
 
 mod buffer;
+mod padding;
 mod worker;
 
 // Piece length is inversly related to the file size.
@@ -37,6 +42,14 @@ const FILE_SIZE_MIN_PIECE_LENGTH: usize = 1 * 1024 * 1024;
 const TRANSFER_MAX_PIECES_SIZE: usize = 60000;
 const TRANSFER_MIN_PIECE_LENGTH: usize = 1 * 1024;
 
+// Bounds and target number of pieces for the automatic piece length
+const AUTO_MIN_PIECE_LENGTH: usize = 16 * 1024;
+const AUTO_MAX_PIECE_LENGTH: usize = 16 * 1024 * 1024;
+const AUTO_MAX_NUM_PIECES: u64 = 2000;
+
+// Number of threads used to hash pieces if not set
+const DEFAULT_HASH_THREADS: usize = 1;
+
 /// Enumerates settings for piece length for generating a torrent file.
 pub enum PieceLength {
     /// Optimize piece length for torrent file size and file transfer.
@@ -45,6 +58,8 @@ pub enum PieceLength {
     OptFileSize,
     /// Optimize piece length for torrent file transfer.
     OptTransfer,
+    /// Power of two piece length targeting between 1000 and 2000 pieces.
+    Auto,
     /// Custom piece length.
     Custom(usize),
 }
@@ -99,6 +114,31 @@ impl<'a> MetainfoBuilder<'a> {
         self
     }
 
+    /// Set or unset the web seed urls (url-list) for the torrent file.
+    pub fn set_web_seeds(mut self, opt_web_seeds: Option<&'a Vec<String>>) -> MetainfoBuilder<'a> {
+        {
+            let dict_access = self.root.dict_mut().unwrap();
+
+            if let Some(web_seeds) = opt_web_seeds {
+                let mut list = BencodeMut::new_list();
+
+                {
+                    let list_access = list.list_mut().unwrap();
+
+                    for web_seed in web_seeds.iter() {
+                        list_access.push(ben_bytes!(&web_seed[..]));
+                    }
+                }
+
+                dict_access.insert(parse::URL_LIST_KEY.into(), list);
+            } else {
+                dict_access.remove(parse::URL_LIST_KEY);
+            }
+        }
+
+        self
+    }
+
     /// Set or unset the main tracker that this torrent file points to.
     pub fn set_main_tracker(mut self, opt_tracker_url: Option<&'a str>) -> MetainfoBuilder<'a> {
         {
@@ -159,6 +199,21 @@ impl<'a> MetainfoBuilder<'a> {
         self
     }
 
+    /// Set or unset the string encoding format for the torrent file.
+    pub fn set_encoding(mut self, opt_encoding: Option<&'a str>) -> MetainfoBuilder<'a> {
+        {
+            let dict_access = self.root.dict_mut().unwrap();
+
+            if let Some(encoding) = opt_encoding {
+                dict_access.insert(parse::ENCODING_KEY.into(), ben_bytes!(encoding));
+            } else {
+                dict_access.remove(parse::ENCODING_KEY);
+            }
+        }
+
+        self
+    }
+
     /// Set or unset the private flag for the torrent file.
     pub fn set_private_flag(mut self, opt_is_private: Option<bool>) -> MetainfoBuilder<'a> {
         self.info = self.info.set_private_flag(opt_is_private);
@@ -166,6 +221,13 @@ impl<'a> MetainfoBuilder<'a> {
         self
     }
 
+    /// Sets whether or not the torrent file is private.
+    pub fn set_private(mut self, is_private: bool) -> MetainfoBuilder<'a> {
+        self.info = self.info.set_private(is_private);
+
+        self
+    }
+
     /// Sets the piece length for the torrent file.
     pub fn set_piece_length(mut self, piece_length: PieceLength) -> MetainfoBuilder<'a> {
         self.info = self.info.set_piece_length(piece_length);
@@ -173,6 +235,36 @@ impl<'a> MetainfoBuilder<'a> {
         self
     }
 
+    /// Sets the piece length to be automatically selected from the total file size.
+    pub fn auto_piece_length(mut self) -> MetainfoBuilder<'a> {
+        self.info = self.info.auto_piece_length();
+
+        self
+    }
+
+    /// Sets the number of worker threads used to hash pieces.
+    pub fn set_hash_threads(mut self, threads: usize) -> MetainfoBuilder<'a> {
+        self.info = self.info.set_hash_threads(threads);
+
+        self
+    }
+
+    /// Sets whether or not padding files are inserted so that each file starts on a piece boundary.
+    pub fn align_files(mut self, align: bool) -> MetainfoBuilder<'a> {
+        self.info = self.info.align_files(align);
+
+        self
+    }
+
+    /// Sets a callback to be invoked with the number of bytes hashed so far and the total number of bytes.
+    pub fn set_progress<C>(mut self, progress: C) -> MetainfoBuilder<'a>
+        where C: FnMut(u64, u64) + Send + 'static
+    {
+        self.info = self.info.set_progress(progress);
+
+        self
+    }
+
     /// Get decoded value of announce-list key
     pub fn get_trackers(&self) -> Option<Vec<Vec<String>>> {
         let dict_access = self.root.dict().unwrap();
@@ -180,6 +272,13 @@ impl<'a> MetainfoBuilder<'a> {
         parse::parse_announce_list(dict_access).map(parse::convert_announce_list)
     }
 
+    /// Get decoded value of url-list key
+    pub fn get_web_seeds(&self) -> Option<Vec<String>> {
+        let dict_access = self.root.dict().unwrap();
+
+        parse::parse_url_list(dict_access)
+    }
+
     /// Get decoded value of announce-url key
     pub fn get_main_tracker(&self) -> Option<String> {
         let dict_access = self.root.dict().unwrap();
@@ -208,16 +307,34 @@ impl<'a> MetainfoBuilder<'a> {
         parse::parse_created_by(dict_access).map(String::from)
     }
 
-    /// Build the metainfo file from the given accessor and the number of worker threads.
+    /// Get decoded value of encoding key
+    pub fn get_encoding(&self) -> Option<String> {
+        let dict_access = self.root.dict().unwrap();
+
+        parse::parse_encoding(dict_access).map(String::from)
+    }
+
+    /// Build the metainfo file from the given accessor.
     ///
-    /// Panics if threads is equal to zero.
-    pub fn build<A, C>(self, threads: usize, accessor: A, progress: C) -> ParseResult<Vec<u8>>
-        where A: IntoAccessor,
-              C: FnMut(f64) + Send + 'static
+    /// Panics if the number of hash threads is equal to zero.
+    pub fn build<A>(self, accessor: A) -> ParseResult<Vec<u8>>
+        where A: IntoAccessor
+    {
+        self.build_with_hash(accessor).map(|(bytes, _)| bytes)
+    }
+
+    /// Build the metainfo file from the given accessor, along with the info hash of the file.
+    ///
+    /// The info hash is computed from the info dictionary as it is encoded, so the file does
+    /// not have to be parsed again to find it.
+    ///
+    /// Panics if the number of hash threads is equal to zero.
+    pub fn build_with_hash<A>(self, accessor: A) -> ParseResult<(Vec<u8>, InfoHash)>
+        where A: IntoAccessor
     {
         let accessor = try!(accessor.into_accessor());
 
-        build_with_accessor(threads, accessor, progress, Some(self.root), self.info.info, self.info.piece_length)
+        build_with_accessor(accessor, Some(self.root), self.info)
     }
 }
 
@@ -228,28 +345,42 @@ pub struct InfoBuilder<'a> {
     info:         BencodeMut<'a>,
     // Stored outside of root as some of the variants need the total
     // file sizes in order for the final piece length to be calculated.
-    piece_length: PieceLength
+    piece_length: PieceLength,
+    hash_threads: usize,
+    align_files:  bool,
+    progress:     Option<Box<FnMut(u64, u64) + Send>>
 }
 
 impl<'a> InfoBuilder<'a> {
     pub fn new() -> InfoBuilder<'a> {
-        InfoBuilder{ info: BencodeMut::new_dict(), piece_length: PieceLength::OptBalanced }
+        InfoBuilder{ info: BencodeMut::new_dict(), piece_length: PieceLength::OptBalanced,
+                     hash_threads: DEFAULT_HASH_THREADS, align_files: false, progress: None }
     }
 
     /// Set or unset the private flag for the torrent file.
     pub fn set_private_flag(mut self, opt_is_private: Option<bool>) -> InfoBuilder<'a> {
-        let opt_numeric_is_private = opt_is_private.map(|is_private| if is_private{ 1 } else { 0 });
-
         {
             let dict_access = self.info.dict_mut().unwrap();
-            opt_numeric_is_private
-                .and_then(|numeric_is_private| dict_access.insert(parse::PRIVATE_KEY.into(), ben_int!(numeric_is_private)))
-                .or_else(|| dict_access.remove(parse::PRIVATE_KEY));
+
+            if let Some(is_private) = opt_is_private {
+                let numeric_is_private = if is_private { 1 } else { 0 };
+
+                dict_access.insert(parse::PRIVATE_KEY.into(), ben_int!(numeric_is_private));
+            } else {
+                dict_access.remove(parse::PRIVATE_KEY);
+            }
         }
 
         self
     }
 
+    /// Sets whether or not the torrent file is private.
+    ///
+    /// A private torrent will have the private flag set to 1, otherwise the flag is left out.
+    pub fn set_private(self, is_private: bool) -> InfoBuilder<'a> {
+        self.set_private_flag(if is_private { Some(true) } else { None })
+    }
+
     /// Sets the piece length for the torrent file.
     pub fn set_piece_length(mut self, piece_length: PieceLength) -> InfoBuilder<'a> {
         self.piece_length = piece_length;
@@ -257,29 +388,87 @@ impl<'a> InfoBuilder<'a> {
         self
     }
 
-    /// Build the metainfo file from the given accessor and the number of worker threads.
+    /// Sets the piece length to be automatically selected from the total file size.
+    ///
+    /// Selected piece length will be a power of two between 16 KiB and 16 MiB, and will
+    /// result in 1000 to 2000 pieces if possible within those bounds.
+    pub fn auto_piece_length(self) -> InfoBuilder<'a> {
+        self.set_piece_length(PieceLength::Auto)
+    }
+
+    /// Sets the number of worker threads used to hash pieces.
+    ///
+    /// Pieces are hashed independently of each other, so the resulting pieces will
+    /// be the same regardless of the number of threads used.
+    pub fn set_hash_threads(mut self, threads: usize) -> InfoBuilder<'a> {
+        self.hash_threads = threads;
+
+        self
+    }
+
+    /// Sets whether or not padding files are inserted so that each file starts on a piece boundary.
+    ///
+    /// Padding files are placed under `.pad`, named after their length, and marked with the
+    /// padding attribute, as specified in BEP 47. Data within padding files is hashed as zeros.
+    pub fn align_files(mut self, align: bool) -> InfoBuilder<'a> {
+        self.align_files = align;
+
+        self
+    }
+
+    /// Sets a callback to be invoked with the number of bytes hashed so far and the total number of bytes.
+    ///
+    /// Callback is invoked once per piece, and will not be invoked after the build has completed.
+    pub fn set_progress<C>(mut self, progress: C) -> InfoBuilder<'a>
+        where C: FnMut(u64, u64) + Send + 'static
+    {
+        self.progress = Some(Box::new(progress));
+
+        self
+    }
+
+    /// Build the info dictionary from the given accessor.
+    ///
+    /// Panics if the number of hash threads is equal to zero.
+    pub fn build<A>(self, accessor: A) -> ParseResult<Vec<u8>>
+        where A: IntoAccessor
+    {
+        self.build_with_hash(accessor).map(|(bytes, _)| bytes)
+    }
+
+    /// Build the info dictionary from the given accessor, along with the info hash of the dictionary.
     ///
-    /// Panics if threads is equal to zero.
-    pub fn build<A, C>(self, threads: usize, accessor: A, progress: C) -> ParseResult<Vec<u8>>
-        where A: IntoAccessor,
-              C: FnMut(f64) + Send + 'static
+    /// Panics if the number of hash threads is equal to zero.
+    pub fn build_with_hash<A>(self, accessor: A) -> ParseResult<(Vec<u8>, InfoHash)>
+        where A: IntoAccessor
     {
         let accessor = try!(accessor.into_accessor());
 
-        build_with_accessor(threads, accessor, progress, None, self.info, self.piece_length)
+        build_with_accessor(accessor, None, self)
+    }
+
+    /// Build the info dictionary for the given directory, using the default settings.
+    ///
+    /// Files are added in sorted order of their relative paths, and symbolic links are skipped.
+    /// To build with other settings, pass a `DirectoryAccessor` to `build` instead.
+    pub fn from_directory<P>(path: P) -> ParseResult<Vec<u8>>
+        where P: AsRef<Path>
+    {
+        let accessor = try!(DirectoryAccessor::new(path));
+
+        InfoBuilder::new().build(accessor)
     }
 }
 
 // ----------------------------------------------------------------------------//
 
-fn build_with_accessor<'a, A, C>(threads:       usize,
-                                accessor:       A,
-                                progress:       C,
-                                opt_root:       Option<BencodeMut<'a>>,
-                                info:           BencodeMut<'a>,
-                                piece_length:   PieceLength) -> ParseResult<Vec<u8>>
-    where A: Accessor,
-          C: FnMut(f64) + Send + 'static {
+fn build_with_accessor<'a, A>(accessor:     A,
+                              opt_root:     Option<BencodeMut<'a>>,
+                              info_builder: InfoBuilder<'a>) -> ParseResult<(Vec<u8>, InfoHash)>
+    where A: Accessor {
+        let InfoBuilder{ info, piece_length, hash_threads: threads, align_files, progress: opt_progress } = info_builder;
+        let progress = opt_progress.unwrap_or_else(|| Box::new(|_, _| ()));
+
         if threads == 0 {
             panic!("bip_metainfo: Cannot Build Metainfo File With threads == 0");
         }
@@ -294,15 +483,46 @@ fn build_with_accessor<'a, A, C>(threads:       usize,
             files_info.push((len, path_list));
         }));
 
-        // Build the pieces for the data our accessor is pointing at
+        // Attributes are looked up by path, since padding files may be inserted between the real files
+        let mut attr_index = 0;
+        let mut files_attrs = HashMap::new();
+        try!(accessor.access_attributes(|attr, symlink| {
+            if let Some(&(_, ref path_list)) = files_info.get(attr_index) {
+                let symlink_list: Option<Vec<String>> = symlink.map(|symlink| {
+                    symlink.iter()
+                        .map(|os_str| os_str.to_string_lossy().into_owned())
+                        .collect()
+                });
+
+                files_attrs.insert(path_list.clone(), (attr.map(String::from), symlink_list));
+            }
+            attr_index += 1;
+        }));
+
+        // Piece length is chosen from the real files, since padding depends on the piece length
         let total_files_len = files_info.iter().fold(0, |acc, nex| acc + nex.0);
         let piece_length = determine_piece_length(total_files_len, piece_length);
-        let total_num_pieces = ((total_files_len as f64) / (piece_length as f64)).ceil() as u64;
-        let pieces_list = try!(worker::start_hasher_workers(&accessor,
-                                                            piece_length,
-                                                            total_num_pieces,
-                                                            threads,
-                                                            progress));
+        let files_info = if align_files {
+            padding::pad_files(files_info, piece_length)
+        } else {
+            files_info
+        };
+
+        // Build the pieces for the data our accessor is pointing at
+        let total_files_len = files_info.iter().fold(0, |acc, nex| acc + nex.0);
+        let pieces_list = if align_files {
+            try!(worker::start_hasher_workers(padding::PaddedAccessor::new(&accessor, &files_info),
+                                              piece_length,
+                                              total_files_len,
+                                              threads,
+                                              progress))
+        } else {
+            try!(worker::start_hasher_workers(&accessor,
+                                              piece_length,
+                                              total_files_len,
+                                              threads,
+                                              progress))
+        };
         let pieces = map_pieces_list(pieces_list.into_iter().map(|(_, piece)| piece));
 
         let mut single_file_name = String::new();
@@ -323,57 +543,15 @@ fn build_with_accessor<'a, A, C>(threads:       usize,
             // If the directory is not present but there are multiple files, the direcotry field will be set to empty
             match (&access_directory, files_info.len() > 1) {
                 (&Some(ref directory), _) => {
-                    let mut bencode_files = BencodeMut::new_list();
-
-                    {
-                        let bencode_files_access = bencode_files.list_mut().unwrap();
-
-                        // Multi File
-                        for &(len, ref path) in files_info.iter() {
-                            let mut bencode_path = BencodeMut::new_list();
-
-                            {
-                                let bencode_path_access = bencode_path.list_mut().unwrap();
-
-                                for path_element in path.iter() {
-                                    bencode_path_access.push(ben_bytes!(&path_element[..]));
-                                }
-                            }
-
-                            bencode_files_access.push(ben_map!{
-                                parse::LENGTH_KEY => ben_int!(len as i64),
-                                parse::PATH_KEY   => bencode_path
-                            });
-                        }
-                    }
+                    // Multi File
+                    let bencode_files = bencode_files_list(&files_info, &files_attrs);
 
                     info_access.insert(parse::NAME_KEY.into(), ben_bytes!(directory.as_ref()));
                     info_access.insert(parse::FILES_KEY.into(), bencode_files);
                 }
                 (&None, true) => {
-                    let mut bencode_files = BencodeMut::new_list();
-
-                    {
-                        let bencode_files_access = bencode_files.list_mut().unwrap();
-
-                        // Multi File
-                        for &(len, ref path) in files_info.iter() {
-                            let mut bencode_path = BencodeMut::new_list();
-
-                            {
-                                let bencode_path_access = bencode_path.list_mut().unwrap();
-
-                                for path_element in path.iter() {
-                                    bencode_path_access.push(ben_bytes!(&path_element[..]));
-                                }
-                            }
-
-                            bencode_files_access.push(ben_map!{
-                                parse::LENGTH_KEY => ben_int!(len as i64),
-                                parse::PATH_KEY   => bencode_path
-                            });
-                        }
-                    }
+                    // Multi File
+                    let bencode_files = bencode_files_list(&files_info, &files_attrs);
 
                     info_access.insert(parse::NAME_KEY.into(), ben_bytes!(""));
                     info_access.insert(parse::FILES_KEY.into(), bencode_files);
@@ -386,25 +564,97 @@ fn build_with_accessor<'a, A, C>(threads:       usize,
 
                     info_access.insert(parse::LENGTH_KEY.into(), ben_int!(files_info[0].0 as i64));
                     info_access.insert(parse::NAME_KEY.into(), ben_bytes!(&single_file_name[..]));
+
+                    if let Some(&(ref opt_attr, ref opt_symlink)) = files_attrs.get(&files_info[0].1) {
+                        insert_file_attributes(info_access, opt_attr.as_ref(), opt_symlink.as_ref());
+                    }
                 }
             }
         }
 
+        // Encoding is canonical, so these are the same bytes that end up under the info key of the root
+        let info_bytes = info.encode();
+        let info_hash = InfoHash::from_bytes(&info_bytes);
+
         if let Some(mut root) = opt_root {
             root.dict_mut().unwrap().insert(parse::INFO_KEY.into(), info);
 
-            Ok(root.encode())
+            Ok((root.encode(), info_hash))
         } else {
-            Ok(info.encode())
+            Ok((info_bytes, info_hash))
         }
 }
 
+/// Generate the bencode list of files for a multi file torrent.
+fn bencode_files_list<'a>(files_info:  &'a [(u64, Vec<String>)],
+                          files_attrs: &'a HashMap<Vec<String>, (Option<String>, Option<Vec<String>>)>) -> BencodeMut<'a> {
+    let mut bencode_files = BencodeMut::new_list();
+
+    {
+        let bencode_files_access = bencode_files.list_mut().unwrap();
+
+        for &(len, ref path) in files_info.iter() {
+            let mut bencode_path = BencodeMut::new_list();
+
+            {
+                let bencode_path_access = bencode_path.list_mut().unwrap();
+
+                for path_element in path.iter() {
+                    bencode_path_access.push(ben_bytes!(&path_element[..]));
+                }
+            }
+
+            let mut bencode_file = ben_map!{
+                parse::LENGTH_KEY => ben_int!(len as i64),
+                parse::PATH_KEY   => bencode_path
+            };
+            match files_attrs.get(path) {
+                Some(&(ref opt_attr, ref opt_symlink)) => {
+                    insert_file_attributes(bencode_file.dict_mut().unwrap(), opt_attr.as_ref(), opt_symlink.as_ref());
+                }
+                None if padding::is_padding(path) => {
+                    bencode_file.dict_mut().unwrap().insert(parse::ATTR_KEY.into(), ben_bytes!(padding::PAD_ATTR));
+                }
+                None => ()
+            }
+
+            bencode_files_access.push(bencode_file);
+        }
+    }
+
+    bencode_files
+}
+
+/// Insert the attributes and symlink path, as specified in BEP 47, into the info or file dictionary.
+fn insert_file_attributes<'a>(dict:        &mut BDictAccess<Cow<'a, [u8]>, BencodeMut<'a>>,
+                              opt_attr:    Option<&'a String>,
+                              opt_symlink: Option<&'a Vec<String>>) {
+    if let Some(attr) = opt_attr {
+        dict.insert(parse::ATTR_KEY.into(), ben_bytes!(&attr[..]));
+    }
+
+    if let Some(symlink) = opt_symlink {
+        let mut bencode_symlink = BencodeMut::new_list();
+
+        {
+            let bencode_symlink_access = bencode_symlink.list_mut().unwrap();
+
+            for path_element in symlink.iter() {
+                bencode_symlink_access.push(ben_bytes!(&path_element[..]));
+            }
+        }
+
+        dict.insert(parse::SYMLINK_PATH_KEY.into(), bencode_symlink);
+    }
+}
+
 /// Calculate the final piece length given the total file size and piece length strategy.
 ///
 /// Lower piece length will result in a bigger file but better transfer reliability and vice versa.
 fn determine_piece_length(total_file_size: u64, piece_length: PieceLength) -> usize {
     match piece_length {
         PieceLength::Custom(len) => len,
+        PieceLength::Auto => calculate_auto_piece_length(total_file_size),
         PieceLength::OptBalanced => {
             calculate_piece_length(total_file_size,
                                    BALANCED_MAX_PIECES_SIZE,
@@ -439,6 +689,19 @@ fn calculate_piece_length(total_file_size: u64,
         (_, false) => ALL_OPT_MAX_PIECE_LENGTH,
     }
 }
+
+/// Calculate the smallest power of 2 piece length that does not exceed the max number of pieces.
+fn calculate_auto_piece_length(total_file_size: u64) -> usize {
+    let mut piece_length = AUTO_MIN_PIECE_LENGTH;
+
+    while piece_length < AUTO_MAX_PIECE_LENGTH &&
+          (total_file_size + piece_length as u64 - 1) / piece_length as u64 > AUTO_MAX_NUM_PIECES {
+        piece_length *= 2;
+    }
+
+    piece_length
+}
+
 /// Map the pieces list into a list of bytes (byte string).
 fn map_pieces_list<I>(pieces: I) -> Vec<u8>
     where I: Iterator<Item = ShaHash> + ExactSizeIterator
@@ -450,3 +713,45 @@ fn map_pieces_list<I>(pieces: I) -> Vec<u8>
 
     concated_pieces
 }
+
+#[cfg(test)]
+mod tests {
+    use builder::{self, PieceLength};
+
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    const GIB: u64 = 1024 * MIB;
+
+    #[test]
+    fn positive_auto_piece_length_empty() {
+        assert_eq!(16 * KIB as usize, builder::determine_piece_length(0, PieceLength::Auto));
+    }
+
+    #[test]
+    fn positive_auto_piece_length_small() {
+        assert_eq!(16 * KIB as usize, builder::determine_piece_length(10 * MIB, PieceLength::Auto));
+    }
+
+    #[test]
+    fn positive_auto_piece_length_medium() {
+        // 700 MiB / 512 KiB = 1400 pieces
+        assert_eq!(512 * KIB as usize, builder::determine_piece_length(700 * MIB, PieceLength::Auto));
+    }
+
+    #[test]
+    fn positive_auto_piece_length_boundary() {
+        assert_eq!(1 * MIB as usize, builder::determine_piece_length(2000 * MIB, PieceLength::Auto));
+        assert_eq!(2 * MIB as usize, builder::determine_piece_length(2000 * MIB + 1, PieceLength::Auto));
+    }
+
+    #[test]
+    fn positive_auto_piece_length_large() {
+        // 4 GiB / 4 MiB = 1024 pieces
+        assert_eq!(4 * MIB as usize, builder::determine_piece_length(4 * GIB, PieceLength::Auto));
+    }
+
+    #[test]
+    fn positive_auto_piece_length_max() {
+        assert_eq!(16 * MIB as usize, builder::determine_piece_length(1024 * GIB, PieceLength::Auto));
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use accessor::{Accessor, PieceAccess};
+
+/// Directory that padding files are placed in, as specified in BEP 47.
+pub const PAD_DIRECTORY: &'static str = ".pad";
+
+/// Attribute given to padding files, as specified in BEP 47.
+pub const PAD_ATTR: &'static str = "p";
+
+/// Returns true if the given path list points to a padding file.
+pub fn is_padding(path: &[String]) -> bool {
+    path.len() == 2 && path[0] == PAD_DIRECTORY
+}
+
+/// Insert padding files after each file, except the last, so that every file starts on a piece boundary.
+pub fn pad_files(files_info: Vec<(u64, Vec<String>)>, piece_length: usize) -> Vec<(u64, Vec<String>)> {
+    let piece_length = piece_length as u64;
+    let num_files = files_info.len();
+
+    let mut padded_files_info = Vec::with_capacity(num_files * 2);
+    let mut offset = 0;
+    for (index, (len, path)) in files_info.into_iter().enumerate() {
+        offset += len;
+        padded_files_info.push((len, path));
+
+        let pad_len = (piece_length - offset % piece_length) % piece_length;
+        if index + 1 != num_files && pad_len != 0 {
+            offset += pad_len;
+            padded_files_info.push((pad_len, vec![PAD_DIRECTORY.to_owned(), pad_len.to_string()]));
+        }
+    }
+
+    padded_files_info
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Accessor that inserts zeros, for each padding file, in between the files of another accessor.
+///
+/// Padding is only inserted between regions being computed, so accessors with pre computed pieces
+/// should not be padded.
+pub struct PaddedAccessor<'a, A> {
+    accessor:   A,
+    files_info: &'a [(u64, Vec<String>)]
+}
+
+impl<'a, A> PaddedAccessor<'a, A> where A: Accessor {
+    /// Create a new PaddedAccessor from the given accessor and the file list, with padding files, for it.
+    pub fn new(accessor: A, files_info: &'a [(u64, Vec<String>)]) -> PaddedAccessor<'a, A> {
+        PaddedAccessor{ accessor: accessor, files_info: files_info }
+    }
+}
+
+impl<'a, A> Accessor for PaddedAccessor<'a, A> where A: Accessor {
+    fn access_directory(&self) -> Option<&Path> {
+        self.accessor.access_directory()
+    }
+
+    fn access_metadata<C>(&self, mut callback: C) -> io::Result<()>
+        where C: FnMut(u64, &Path)
+    {
+        for &(len, ref path) in self.files_info.iter() {
+            callback(len, &path.iter().collect::<PathBuf>());
+        }
+
+        Ok(())
+    }
+
+    fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
+        where C: for<'b> FnMut(PieceAccess<'b>) -> io::Result<()>
+    {
+        let mut files = self.files_info.iter();
+        // Bytes left to read from the accessor for the current file
+        let mut remaining = 0;
+
+        self.accessor.access_pieces(|piece_access| {
+            let region = match piece_access {
+                PieceAccess::Compute(region) => region,
+                precomputed                  => return callback(precomputed)
+            };
+
+            loop {
+                // Move on to the next non empty file, zero filling any padding files before it
+                while remaining == 0 {
+                    match files.next() {
+                        Some(&(len, ref path)) if is_padding(path) => {
+                            try!(callback(PieceAccess::Compute(&mut io::repeat(0).take(len))));
+                        },
+                        Some(&(len, _)) => remaining = len,
+                        None            => return callback(PieceAccess::Compute(region))
+                    }
+                }
+
+                let mut file_region = (&mut *region).take(remaining);
+                try!(callback(PieceAccess::Compute(&mut file_region)));
+
+                let bytes_read = remaining - file_region.limit();
+                remaining -= bytes_read;
+
+                // Region ended part way through the file, the rest will come in the next region
+                if remaining != 0 {
+                    return Ok(())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PAD_DIRECTORY;
+
+    fn file(len: u64, name: &str) -> (u64, Vec<String>) {
+        (len, vec![name.to_owned()])
+    }
+
+    #[test]
+    fn positive_pad_files_to_piece_boundary() {
+        let files_info = super::pad_files(vec![file(5, "a"), file(16, "b"), file(0, "c"), file(3, "d")], 8);
+
+        let expected = vec![file(5, "a"), (3, vec![PAD_DIRECTORY.to_owned(), "3".to_owned()]),
+                            file(16, "b"), file(0, "c"), file(3, "d")];
+        assert_eq!(expected, files_info);
+    }
+
+    #[test]
+    fn positive_is_padding() {
+        assert!(super::is_padding(&[PAD_DIRECTORY.to_owned(), "10".to_owned()]));
+        assert!(!super::is_padding(&[PAD_DIRECTORY.to_owned()]));
+        assert!(!super::is_padding(&["pad".to_owned(), "10".to_owned()]));
+    }
+}
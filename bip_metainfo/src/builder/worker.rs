@@ -1,3 +1,4 @@
+use std::cmp;
 use std::sync::Arc;
 use std::sync::mpsc::{self, Sender, Receiver};
 use std::thread;
@@ -26,14 +27,16 @@ enum WorkerMessage {
 }
 
 /// Starts a number of hasher workers which will generate the hash pieces for the files we send to it.
+///
+/// Progress is reported as the number of bytes hashed so far, out of the total number of bytes.
 pub fn start_hasher_workers<A, C>(accessor: A,
                                   piece_length: usize,
-                                  num_pieces: u64,
+                                  total_length: u64,
                                   num_workers: usize,
                                   progress: C)
                                   -> ParseResult<Vec<(usize, ShaHash)>>
     where A: Accessor,
-          C: FnMut(f64) + Send + 'static
+          C: FnMut(u64, u64) + Send + 'static
 {
     // Create channels to communicate with the master
     let (master_send, master_recv) = mpsc::channel();
@@ -48,26 +51,35 @@ pub fn start_hasher_workers<A, C>(accessor: A,
     // Create n worker threads that pull work from the queue
     for _ in 0..num_workers {
         let share_master_send = master_send.clone();
+        let share_prog_send = prog_send.clone();
         let share_work_queue = work_queue.clone();
         let share_piece_buffers = piece_buffers.clone();
 
         thread::spawn(move || {
-            start_hash_worker(share_master_send, share_work_queue, share_piece_buffers);
+            start_hash_worker(share_master_send, share_prog_send, share_work_queue, share_piece_buffers);
         });
     }
 
     // Create a worker thread to execute the user callback for the progress update
-    thread::spawn(move || {
-        start_progress_updater(prog_recv, num_pieces, progress);
+    let progress_handle = thread::spawn(move || {
+        start_progress_updater(prog_recv, total_length, progress);
     });
 
     // Create the master worker to coordinate between the workers
-    start_hash_master(accessor,
-                      num_workers,
-                      master_recv,
-                      work_queue,
-                      piece_buffers,
-                      prog_send)
+    let result = start_hash_master(accessor,
+                                   piece_length,
+                                   num_workers,
+                                   master_recv,
+                                   work_queue,
+                                   piece_buffers,
+                                   prog_send);
+
+    // Wait for the last progress update, so the callback is never invoked after we return
+    if progress_handle.join().is_err() {
+        panic!("bip_metainfo: Progress callback panicked...");
+    }
+
+    result
 }
 
 // ----------------------------------------------------------------------------//
@@ -75,11 +87,12 @@ pub fn start_hasher_workers<A, C>(accessor: A,
 /// Start a master hasher which will take care of chunking sequential/overlapping pieces from the data given to it and giving
 /// updates to the hasher workers.
 fn start_hash_master<A>(accessor: A,
+                        piece_length: usize,
                         num_workers: usize,
                         recv: Receiver<MasterMessage>,
                         work: Arc<MsQueue<WorkerMessage>>,
                         buffers: Arc<PieceBuffers>,
-                        progress_sender: Sender<usize>)
+                        progress_sender: Sender<u64>)
                         -> ParseResult<Vec<(usize, ShaHash)>>
     where A: Accessor
 {
@@ -88,7 +101,7 @@ fn start_hash_master<A>(accessor: A,
 
     // Our closure may be called multiple times, save partial pieces buffers between calls
     let mut opt_piece_buffer = None;
-    try!(accessor.access_pieces(|piece_access| {
+    let access_result = accessor.access_pieces(|piece_access| {
         match piece_access {
             PieceAccess::Compute(piece_region) => {
                 let mut curr_piece_buffer = if let Some(piece_buffer) = opt_piece_buffer.take() {
@@ -107,10 +120,6 @@ fn start_hash_master<A>(accessor: A,
 
                         piece_index += 1;
                         curr_piece_buffer = buffers.checkout();
-
-                        if progress_sender.send(piece_index).is_err() {
-                            // TODO: Add logging here
-                        }
                     }
                 }
 
@@ -120,25 +129,24 @@ fn start_hash_master<A>(accessor: A,
                 pieces.push((piece_index, hash));
 
                 piece_index += 1;
+                // Updater caps this at the total length in case this is the last piece
+                if progress_sender.send(piece_length as u64).is_err() {
+                    // TODO: Add logging here
+                }
             }
         }
 
         Ok(())
-    }));
+    });
 
-    // If we still have a partial piece left over, push it to the workers
+    // If we still have a partial piece left over, push it to the workers (not needed if we are bailing out)
     if let Some(piece_buffer) = opt_piece_buffer {
-        if !piece_buffer.is_empty() {
+        if access_result.is_ok() && !piece_buffer.is_empty() {
             work.push(WorkerMessage::HashPiece(piece_index, piece_buffer));
-
-            piece_index += 1;
-            if progress_sender.send(piece_index).is_err() {
-                // TODO: Add logging here
-            }
         }
     }
 
-    // No more entries, tell workers to shut down
+    // No more entries (or we failed to access them), tell workers to shut down either way
     for _ in 0..num_workers {
         work.push(WorkerMessage::Finish);
     }
@@ -153,6 +161,9 @@ fn start_hash_master<A>(accessor: A,
         }
     }
 
+    // Only report the access error once every worker has shut down, so nothing is left waiting on us
+    try!(access_result);
+
     // Sort our list to make sure the pieces are in order before we send them off
     pieces.sort_by(|one, two| one.0.cmp(&two.0));
 
@@ -161,13 +172,15 @@ fn start_hash_master<A>(accessor: A,
 
 // ----------------------------------------------------------------------------//
 
-fn start_progress_updater<C>(recv: Receiver<usize>, num_pieces: u64, mut progress: C)
-    where C: FnMut(f64)
+fn start_progress_updater<C>(recv: Receiver<u64>, total_length: u64, mut progress: C)
+    where C: FnMut(u64, u64)
 {
-    for finished_piece in recv {
-        let percent_complete = (finished_piece as f64) / (num_pieces as f64);
+    let mut bytes_hashed = 0;
+
+    for piece_bytes in recv {
+        bytes_hashed = cmp::min(bytes_hashed + piece_bytes, total_length);
 
-        progress(percent_complete);
+        progress(bytes_hashed, total_length);
     }
 }
 
@@ -175,6 +188,7 @@ fn start_progress_updater<C>(recv: Receiver<usize>, num_pieces: u64, mut progres
 
 /// Starts a hasher worker which will hash all of the buffers it receives.
 fn start_hash_worker(send: Sender<MasterMessage>,
+                     progress_send: Sender<u64>,
                      work: Arc<MsQueue<WorkerMessage>>,
                      buffers: Arc<PieceBuffers>) {
     let mut work_to_do = true;
@@ -189,8 +203,12 @@ fn start_hash_worker(send: Sender<MasterMessage>,
             }
             WorkerMessage::HashPiece(index, buffer) => {
                 let hash = ShaHash::from_bytes(buffer.as_slice());
+                let piece_bytes = buffer.as_slice().len() as u64;
 
                 send.send(MasterMessage::AcceptPiece(index, hash)).unwrap();
+                if progress_send.send(piece_bytes).is_err() {
+                    // TODO: Add logging here
+                }
                 buffers.checkin(buffer);
             }
         }
@@ -222,6 +240,7 @@ mod tests {
     struct MockAccessor {
         buffer_ranges: Vec<Range<usize>>,
         contiguous_buffer: Vec<u8>,
+        fail_at_region: Option<usize>,
     }
 
     impl MockAccessor {
@@ -229,9 +248,14 @@ mod tests {
             MockAccessor {
                 buffer_ranges: Vec::new(),
                 contiguous_buffer: Vec::new(),
+                fail_at_region: None,
             }
         }
 
+        fn fail_at_region(&mut self, region_index: usize) {
+            self.fail_at_region = Some(region_index);
+        }
+
         fn create_region(&mut self, num_bytes: usize) {
             let mut buffer = vec![0u8; num_bytes];
             let mut rng = rand::thread_rng();
@@ -267,7 +291,11 @@ mod tests {
         fn access_pieces<C>(&self, mut callback: C) -> io::Result<()>
             where C: for<'a> FnMut(PieceAccess<'a>) -> io::Result<()>
         {
-            for range in self.buffer_ranges.iter() {
+            for (index, range) in self.buffer_ranges.iter().enumerate() {
+                if self.fail_at_region == Some(index) {
+                    return Err(io::Error::new(io::ErrorKind::Other, "MockAccessor Failed To Access Region"));
+                }
+
                 let mut next_region = Cursor::new(self.contiguous_buffer.index(range.clone()));
 
                 try!(callback(PieceAccess::Compute(&mut next_region)));
@@ -282,12 +310,13 @@ mod tests {
 
         let total_num_pieces = ((accessor.as_slice().len() as f64) / (piece_length as f64))
             .ceil() as u64;
+        let total_length = accessor.as_slice().len() as u64;
         let received_pieces = worker::start_hasher_workers(&accessor,
                                                            piece_length,
-                                                           total_num_pieces,
+                                                           total_length,
                                                            num_threads,
-                                                           move |update| {
-                                                               prog_send.send(update).unwrap();
+                                                           move |hashed, total| {
+                                                               prog_send.send((hashed, total)).unwrap();
                                                            }).unwrap();
 
         let computed_pieces = accessor.as_slice()
@@ -296,9 +325,10 @@ mod tests {
             .map(|(index, chunk)| (index, ShaHash::from_bytes(chunk)))
             .collect::<Vec<(usize, ShaHash)>>();
 
-        let updates_received = prog_recv.iter().count() as u64;
+        let updates_received = prog_recv.try_iter().collect::<Vec<(u64, u64)>>();
 
-        assert_eq!(total_num_pieces, updates_received);
+        assert_eq!(total_num_pieces, updates_received.len() as u64);
+        assert_eq!(Some(&(total_length, total_length)), updates_received.last());
         assert_eq!(received_pieces, computed_pieces);
     }
 
@@ -399,4 +429,23 @@ mod tests {
 
         validate_entries_pieces(accessor, DEFAULT_PIECE_LENGTH, 4);
     }
+
+    #[test]
+    fn negative_accessor_fails_partway_through() {
+        let mut accessor = MockAccessor::new();
+
+        for _ in 0..3 {
+            accessor.create_region(DEFAULT_PIECE_LENGTH * DEFAULT_NUM_PIECES / 3 + 1);
+        }
+        accessor.fail_at_region(2);
+
+        let total_length = accessor.as_slice().len() as u64;
+        let result = worker::start_hasher_workers(&accessor,
+                                                  DEFAULT_PIECE_LENGTH,
+                                                  total_length,
+                                                  4,
+                                                  |_, _| ());
+
+        assert!(result.is_err());
+    }
 }
@@ -24,5 +24,18 @@ error_chain! {
             description("Missing Data Detected In File")
             display("Missing Data Detected In File: {}", details)
         }
+        UnsupportedMetaVersion {
+            version: i64
+        } {
+            description("Unsupported Meta Version Found In File")
+            display("Unsupported Meta Version {} Found In File", version)
+        }
+        PieceLengthMismatch {
+            expected: u64,
+            actual:   u64
+        } {
+            description("Pieces Length Does Not Match The Total Length Of Files In File")
+            display("Pieces Length Of {} Bytes Does Not Match The Expected {} Bytes In File", actual, expected)
+        }
     }
 }
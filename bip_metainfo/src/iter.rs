@@ -1,5 +1,6 @@
 //! Iterators over torrent file information.
 
+use bip_util::bt::InfoHash;
 use bip_util::sha;
 
 use metainfo::File;
@@ -60,4 +61,44 @@ impl<'a> Iterator for Pieces<'a> {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Iterator over each piece index and piece hash within the MetainfoFile.
+pub struct PieceHashes<'a> {
+    index: usize,
+    pieces: &'a [[u8; sha::SHA_HASH_LEN]],
+}
+
+impl<'a> PieceHashes<'a> {
+    pub fn new(pieces: &'a [[u8; sha::SHA_HASH_LEN]]) -> PieceHashes<'a> {
+        PieceHashes {
+            index: 0,
+            pieces: pieces,
+        }
+    }
+}
+
+impl<'a> Iterator for PieceHashes<'a> {
+    type Item = (u32, InfoHash);
+
+    fn next(&mut self) -> Option<(u32, InfoHash)> {
+        if let Some(hash) = self.pieces.get(self.index) {
+            let piece_index = self.index as u32;
+            self.index += 1;
+
+            Some((piece_index, InfoHash::from(*hash)))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.pieces.len() - self.index;
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for PieceHashes<'a> {}
@@ -12,13 +12,14 @@
 //!     fn main() {
 //!         let builder = MetainfoBuilder::new()
 //!             .set_created_by(Some("bip_metainfo example"))
-//!             .set_comment(Some("Metainfo File From A File"));
+//!             .set_comment(Some("Metainfo File From A File"))
+//!             .set_progress(|hashed, total| {
+//!                 // Progress Is The Number Of Bytes Hashed Out Of The Total
+//!                 assert!(hashed <= total);
+//!             });
 //!
 //!         // Build the file from the crate's src folder
-//!         let bytes = builder.build(1, "src", |progress| {
-//!             // Progress Is A Value Between 0.0 And 1.0
-//!             assert!(progress <= 1.0f64);
-//!         }).unwrap();
+//!         let bytes = builder.build("src").unwrap();
 //!         let file = Metainfo::from_bytes(&bytes).unwrap();
 //!
 //!         assert_eq!(file.info().directory(), Some("src".as_ref()));
@@ -35,17 +36,18 @@
 //!     fn main() {
 //!         let builder = MetainfoBuilder::new()
 //!             .set_created_by(Some("bip_metainfo example"))
-//!             .set_comment(Some("Metainfo File From A File"));
+//!             .set_comment(Some("Metainfo File From A File"))
+//!             .set_progress(|hashed, total| {
+//!                 // Progress Is The Number Of Bytes Hashed Out Of The Total
+//!                 assert!(hashed <= total);
+//!             });
 //!
 //!         let file_name = "FileName.txt";
 //!         let file_data = b"This is our file data, it is already in memory!!!";
 //!         let accessor = DirectAccessor::new(file_name, file_data);
 //!
 //!         // Build the file from some data that is already in memory
-//!         let bytes = builder.build(1, accessor, |progress| {
-//!             // Progress Is A Value Between 0.0 And 1.0
-//!             assert!(progress <= 1.0f64);
-//!         }).unwrap();
+//!         let bytes = builder.build(accessor).unwrap();
 //!         let file = Metainfo::from_bytes(&bytes).unwrap();
 //!
 //!         assert_eq!(file.info().directory(), None);
@@ -61,6 +63,7 @@
 #[macro_use]
 extern crate bip_bencode;
 extern crate bip_util;
+extern crate crypto;
 extern crate crossbeam;
 extern crate walkdir;
 #[macro_use]
@@ -79,6 +82,6 @@ pub mod iter;
 
 pub use bip_util::bt::InfoHash;
 
-pub use accessor::{Accessor, IntoAccessor, DirectAccessor, FileAccessor, PieceAccess};
+pub use accessor::{Accessor, IntoAccessor, DirectAccessor, DirectoryAccessor, FileAccessor, PieceAccess};
 pub use builder::{MetainfoBuilder, PieceLength, InfoBuilder};
-pub use metainfo::{Info, Metainfo, File};
+pub use metainfo::{Info, Metainfo, File, FileAttributes, MetaVersion, Summary};
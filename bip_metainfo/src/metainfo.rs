@@ -1,16 +1,19 @@
 //! Accessing the fields of a Metainfo file.
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::io;
+use std::str;
 
-use bip_bencode::{BencodeRef, BDictAccess, BDecodeOpt, BRefAccess};
+use bip_bencode::{BencodeMut, BencodeRef, BDictAccess, BDecodeOpt, BMutAccess, BRefAccess};
 use bip_util::bt::InfoHash;
 use bip_util::sha::{self, ShaHash};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
 
 use accessor::{Accessor, PieceAccess, IntoAccessor};
-use builder::{MetainfoBuilder, InfoBuilder, PieceLength};
 use parse;
 use error::{ParseError, ParseErrorKind, ParseResult};
-use iter::{Files, Pieces};
+use iter::{Files, PieceHashes, Pieces};
 
 /// Contains optional metadata for a torrent file.
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -18,9 +21,12 @@ pub struct Metainfo {
     comment: Option<String>,
     announce: Option<String>,
     announce_list: Option<Vec<Vec<String>>>,
+    url_list: Option<Vec<String>>,
     encoding: Option<String>,
     created_by: Option<String>,
     creation_date: Option<i64>,
+    // Encoded piece layers, present only for v2 and hybrid torrents.
+    piece_layers: Option<Vec<u8>>,
     info: Info,
 }
 
@@ -44,6 +50,11 @@ impl Metainfo {
         self.announce_list.as_ref()
     }
 
+    /// List of web seed urls.
+    pub fn web_seeds(&self) -> Option<&Vec<String>> {
+        self.url_list.as_ref()
+    }
+
     /// Comment included within the metainfo file.
     pub fn comment(&self) -> Option<&str> {
         self.comment.as_ref().map(|c| &c[..])
@@ -69,19 +80,108 @@ impl Metainfo {
         &self.info
     }
 
+    /// Total length in bytes of all files within the metainfo file.
+    pub fn total_length(&self) -> u64 {
+        self.info.total_length()
+    }
+
+    /// Version of the metainfo file format used by the info dictionary.
+    pub fn meta_version(&self) -> MetaVersion {
+        self.info.meta_version()
+    }
+
+    /// SHA-1 info hash, present for v1 and hybrid torrents.
+    pub fn info_hash_v1(&self) -> Option<InfoHash> {
+        self.info.info_hash_v1()
+    }
+
+    /// SHA-256 info hash truncated to 20 bytes, present for v2 and hybrid torrents.
+    pub fn info_hash_v2(&self) -> Option<InfoHash> {
+        self.info.info_hash_v2()
+    }
+
+    /// Summary of the values derived from the info dictionary, such as the total size and number of pieces.
+    pub fn summary(&self) -> Summary {
+        self.info.summary()
+    }
+
+    /// Generate a magnet link for the `Metainfo` file.
+    ///
+    /// Link will include the hex encoded info hash, the name of the torrent, and
+    /// the main tracker followed by any trackers from the announce list.
+    pub fn to_magnet(&self) -> String {
+        let mut magnet = String::from("magnet:?xt=urn:btih:");
+        for byte in self.info().info_hash().as_ref() {
+            magnet.push_str(&format!("{:02x}", byte));
+        }
+
+        if let Some(name) = self.info().name() {
+            magnet.push_str("&dn=");
+            magnet.push_str(&percent_encode(&name.to_string_lossy()));
+        }
+
+        let mut trackers: Vec<&str> = self.main_tracker().into_iter().collect();
+        for tracker in self.trackers().into_iter().flat_map(|groups| groups.iter()).flat_map(|group| group.iter()) {
+            if !trackers.contains(&&tracker[..]) {
+                trackers.push(tracker);
+            }
+        }
+
+        for tracker in trackers {
+            magnet.push_str("&tr=");
+            magnet.push_str(&percent_encode(tracker));
+        }
+
+        magnet
+    }
+
     /// Retrieve the bencoded bytes for the `Metainfo` file.
+    ///
+    /// The info dictionary and piece layers are written out exactly as they were parsed, so the info hash never changes.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Since there are no file system accesses here, should be fine to unwrap
-        MetainfoBuilder::new()
-            .set_main_tracker(self.main_tracker())
-            .set_creation_date(self.creation_date())
-            .set_comment(self.comment())
-            .set_created_by(self.created_by())
-            .set_private_flag(self.info().is_private())
-            // TODO: Revisit this cast...
-            .set_piece_length(PieceLength::Custom(self.info().piece_length() as usize))
-            .build(1, &self.info, |_| ())
-            .unwrap()
+        let mut entries = vec![(parse::INFO_KEY, self.info.to_bytes())];
+
+        if let Some(ref announce) = self.announce {
+            entries.push((parse::ANNOUNCE_URL_KEY, ben_bytes!(&announce[..]).encode()));
+        }
+        if let Some(ref announce_list) = self.announce_list {
+            let mut list = BencodeMut::new_list();
+            for tier in announce_list.iter() {
+                let mut tier_list = BencodeMut::new_list();
+                for tracker in tier.iter() {
+                    tier_list.list_mut().unwrap().push(ben_bytes!(&tracker[..]));
+                }
+
+                list.list_mut().unwrap().push(tier_list);
+            }
+
+            entries.push((parse::ANNOUNCE_LIST_KEY, list.encode()));
+        }
+        if let Some(ref url_list) = self.url_list {
+            let mut list = BencodeMut::new_list();
+            for url in url_list.iter() {
+                list.list_mut().unwrap().push(ben_bytes!(&url[..]));
+            }
+
+            entries.push((parse::URL_LIST_KEY, list.encode()));
+        }
+        if let Some(ref comment) = self.comment {
+            entries.push((parse::COMMENT_KEY, ben_bytes!(&comment[..]).encode()));
+        }
+        if let Some(ref created_by) = self.created_by {
+            entries.push((parse::CREATED_BY_KEY, ben_bytes!(&created_by[..]).encode()));
+        }
+        if let Some(ref encoding) = self.encoding {
+            entries.push((parse::ENCODING_KEY, ben_bytes!(&encoding[..]).encode()));
+        }
+        if let Some(creation_date) = self.creation_date {
+            entries.push((parse::CREATION_DATE_KEY, ben_int!(creation_date).encode()));
+        }
+        if let Some(ref piece_layers) = self.piece_layers {
+            entries.push((parse::PIECE_LAYERS_KEY, piece_layers.clone()));
+        }
+
+        encode_dict_entries(entries)
     }
 }
 
@@ -91,14 +191,113 @@ impl From<Info> for Metainfo {
             comment: None,
             announce: None,
             announce_list: None,
+            url_list: None,
             encoding: None,
             created_by: None,
             creation_date: None,
+            piece_layers: None,
             info: info
         }
     }
 }
 
+/// Summary of the values derived from an info dictionary, for displaying a torrent to users.
+///
+/// The `Display` implementation gives the summary on a single line, with sizes in binary units.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Summary {
+    name:           String,
+    total_length:   u64,
+    num_pieces:     u64,
+    piece_length:   u64,
+    num_files:      usize,
+    is_private:     bool,
+    is_single_file: bool,
+}
+
+impl Summary {
+    /// Name of the torrent, which is the directory for multi-file torrents, or the file for single-file torrents.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Total length in bytes of all files within the torrent.
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    /// Number of pieces covering all files within the torrent.
+    pub fn num_pieces(&self) -> u64 {
+        self.num_pieces
+    }
+
+    /// Length in bytes of each piece.
+    pub fn piece_length(&self) -> u64 {
+        self.piece_length
+    }
+
+    /// Number of files within the torrent, not including padding files.
+    pub fn num_files(&self) -> usize {
+        self.num_files
+    }
+
+    /// Whether or not the torrent is private.
+    pub fn is_private(&self) -> bool {
+        self.is_private
+    }
+
+    /// Whether or not the torrent is a single-file torrent.
+    pub fn is_single_file(&self) -> bool {
+        self.is_single_file
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let files_suffix = if self.num_files == 1 { "" } else { "s" };
+
+        try!(write!(f, "{} ({}, {} file{}, {} pieces of {})", self.name, format_size(self.total_length),
+                    self.num_files, files_suffix, self.num_pieces, format_size(self.piece_length)));
+        if self.is_private {
+            try!(write!(f, " [private]"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Format the given number of bytes in the largest binary unit it fills, such as `1.50 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&'static str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes)
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2} {}", size, UNITS[unit])
+}
+
+/// Percent encode all bytes of the given string except for unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for &byte in value.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte))
+        }
+    }
+
+    encoded
+}
+
 /// Parses the given metainfo bytes and builds a Metainfo from them.
 fn parse_meta_bytes(bytes: &[u8]) -> ParseResult<Metainfo> {
     let root_bencode = try!(BencodeRef::decode(bytes, BDecodeOpt::default()));
@@ -112,6 +311,7 @@ fn parse_meta_bytes(bytes: &[u8]) -> ParseResult<Metainfo> {
             .or(None)
     };
 
+    let opt_url_list = parse::parse_url_list(root_dict);
     let opt_comment = parse::parse_comment(root_dict).map(|e| e.to_owned());
     let opt_encoding = parse::parse_encoding(root_dict).map(|e| e.to_owned());
     let opt_created_by = parse::parse_created_by(root_dict).map(|e| e.to_owned());
@@ -120,29 +320,72 @@ fn parse_meta_bytes(bytes: &[u8]) -> ParseResult<Metainfo> {
     let info_bencode = try!(parse::parse_info_bencode(root_dict));
     let info = try!(parse_info_dictionary(info_bencode));
 
+    let opt_piece_layers = parse::parse_piece_layers_bencode(root_dict).map(|layers| layers.buffer().to_vec());
+
     Ok(Metainfo {
         comment: opt_comment,
         announce: announce,
         announce_list: opt_announce_list,
+        url_list: opt_url_list,
         encoding: opt_encoding,
         created_by: opt_created_by,
         creation_date: opt_creation_date,
+        piece_layers: opt_piece_layers,
         info: info
     })
 }
 
+/// Encode a dictionary from its keys and already encoded values, sorting the keys as bencode requires.
+fn encode_dict_entries(mut entries: Vec<(&[u8], Vec<u8>)>) -> Vec<u8> {
+    entries.sort_by(|one, two| one.0.cmp(two.0));
+
+    let mut bytes = vec![b'd'];
+    for (key, value) in entries {
+        bytes.extend_from_slice(format!("{}:", key.len()).as_bytes());
+        bytes.extend_from_slice(key);
+        bytes.extend_from_slice(&value);
+    }
+    bytes.push(b'e');
+
+    bytes
+}
+
 // ----------------------------------------------------------------------------//
 
+/// Length of a SHA-256 hash.
+const SHA256_HASH_LEN: usize = 32;
+
+/// Length of the SHA-256 merkle root for each file in a v2 torrent.
+const PIECES_ROOT_LEN: usize = SHA256_HASH_LEN;
+
+/// Version of the metainfo file format used by an info dictionary.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MetaVersion {
+    /// Original format, pieces are hashed with SHA-1 across file boundaries.
+    V1,
+    /// BitTorrent v2 format, each file is hashed separately in to a SHA-256 merkle tree.
+    V2,
+    /// Contains both the v1 and v2 metadata, so it can be used by peers that support either.
+    Hybrid
+}
+
 /// Contains directory and checksum data for a torrent file.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Info {
     info_hash:      InfoHash,
+    // Present only for v2 and hybrid torrents.
+    info_hash_v2:   Option<InfoHash>,
     files:          Vec<File>,
     pieces:         Vec<[u8; sha::SHA_HASH_LEN]>,
     piece_len:      u64,
     is_private:     Option<bool>,
+    meta_version:   MetaVersion,
     // Present only for multi file torrents.
     file_directory: Option<PathBuf>,
+    // Present only for v2 and hybrid torrents.
+    file_tree:      Option<Vec<File>>,
+    // Bytes of the info dictionary this was parsed from.
+    info_bytes:     Vec<u8>,
 }
 
 impl Info {
@@ -156,10 +399,28 @@ impl Info {
     }
 
     /// Hash to uniquely identify this torrent.
+    ///
+    /// This is the v1 info hash if one is present, otherwise the truncated v2 info hash.
     pub fn info_hash(&self) -> InfoHash {
         self.info_hash
     }
 
+    /// SHA-1 info hash, present for v1 and hybrid torrents.
+    pub fn info_hash_v1(&self) -> Option<InfoHash> {
+        if self.meta_version == MetaVersion::V2 {
+            None
+        } else {
+            Some(self.info_hash)
+        }
+    }
+
+    /// SHA-256 info hash truncated to 20 bytes, present for v2 and hybrid torrents.
+    ///
+    /// This is the form of the v2 info hash used when announcing to trackers and the dht.
+    pub fn info_hash_v2(&self) -> Option<InfoHash> {
+        self.info_hash_v2
+    }
+
     /// Some file directory if this is a multi-file torrent, otherwise None.
     ///
     /// If you want to check to see if this is a multi-file torrent, you should
@@ -169,16 +430,64 @@ impl Info {
         self.file_directory.as_ref().map(|d| d.as_ref())
     }
 
+    /// Name of the torrent, which is the directory for multi-file torrents, or the file for single-file torrents.
+    pub fn name(&self) -> Option<&Path> {
+        self.directory().or_else(|| self.files().next().map(|file| file.path()))
+    }
+
     /// Length in bytes of each piece.
     pub fn piece_length(&self) -> u64 {
         self.piece_len
     }
 
     /// Whether or not the torrent is private.
-    pub fn is_private(&self) -> Option<bool> {
+    ///
+    /// Only a private flag equal to 1 marks the torrent as private.
+    pub fn is_private(&self) -> bool {
+        self.is_private.unwrap_or(false)
+    }
+
+    /// Private flag for the torrent, if one was present.
+    pub fn private_flag(&self) -> Option<bool> {
         self.is_private
     }
 
+    /// Total length in bytes of all files within the torrent.
+    pub fn total_length(&self) -> u64 {
+        self.files.iter().map(|file| file.length()).sum()
+    }
+
+    /// Summary of the values derived from the info dictionary, such as the total size and number of pieces.
+    pub fn summary(&self) -> Summary {
+        let total_length = self.total_length();
+        // Pure v2 torrents have no pieces list, but still have pieces of the same length
+        let num_pieces = if total_length == 0 || self.piece_len == 0 {
+            0
+        } else {
+            (total_length - 1) / self.piece_len + 1
+        };
+
+        Summary{
+            name: self.name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            total_length: total_length,
+            num_pieces: num_pieces,
+            piece_length: self.piece_len,
+            num_files: self.files().filter(|file| !file.is_padding()).count(),
+            is_private: self.is_private(),
+            is_single_file: self.directory().is_none()
+        }
+    }
+
+    /// Version of the metainfo file format used by the info dictionary.
+    pub fn meta_version(&self) -> MetaVersion {
+        self.meta_version
+    }
+
+    /// Whether or not the info dictionary contains both v1 and v2 metadata.
+    pub fn is_hybrid(&self) -> bool {
+        self.meta_version == MetaVersion::Hybrid
+    }
+
     /// Iterator over each of the pieces SHA-1 hash.
     ///
     /// Ordering of pieces yielded in the iterator is guaranteed to be the order in
@@ -188,6 +497,18 @@ impl Info {
         Pieces::new(&self.pieces)
     }
 
+    /// Iterator over each piece index along with the pieces SHA-1 hash.
+    ///
+    /// Pieces are yielded in the same order as `pieces`.
+    pub fn piece_hashes<'a>(&'a self) -> PieceHashes<'a> {
+        PieceHashes::new(&self.pieces)
+    }
+
+    /// SHA-1 hash of the piece at the given index, if the index is within the torrent.
+    pub fn piece_hash(&self, index: u32) -> Option<InfoHash> {
+        self.pieces.get(index as usize).map(|&hash| InfoHash::from(hash))
+    }
+
     /// Iterator over each file within the torrent file.
     ///
     /// Ordering of files yielded in the iterator is guaranteed to be the order in
@@ -197,15 +518,20 @@ impl Info {
         Files::new(&self.files)
     }
 
+    /// Iterator over each file within the v2 file tree, if one is present.
+    ///
+    /// Files are yielded in the order they are found in the file tree, and will
+    /// have a pieces root if they are not empty. Unlike `files`, padding files
+    /// from a hybrid torrent are not included.
+    pub fn file_tree<'a>(&'a self) -> Option<Files<'a>> {
+        self.file_tree.as_ref().map(|file_tree| Files::new(file_tree))
+    }
+
     /// Retrieve the bencoded bytes for the `Info` dictionary.
+    ///
+    /// These are the bytes the `Info` was parsed from, so any keys we do not understand are kept.
     pub fn to_bytes(&self) -> Vec<u8> {
-        // Since there are no file system accesses here, should be fine to unwrap
-        InfoBuilder::new()
-            .set_private_flag(self.is_private())
-            // TODO: Revisit this cast...
-            .set_piece_length(PieceLength::Custom(self.piece_length() as usize))
-            .build(1, self, |_| ())
-            .unwrap()
+        self.info_bytes.clone()
     }
 }
 
@@ -247,6 +573,15 @@ impl Accessor for Info {
         
         Ok(())
     }
+
+    fn access_attributes<C>(&self, mut callback: C) -> io::Result<()>
+        where C: FnMut(Option<&str>, Option<&Path>) {
+        for file in self.files() {
+            callback(file.attr(), file.symlink_path());
+        }
+
+        Ok(())
+    }
 }
 
 /// Parses the given info dictionary bytes and builds a Metainfo from them.
@@ -258,52 +593,168 @@ fn parse_info_bytes(bytes: &[u8]) -> ParseResult<Info> {
 
 /// Parses the given info dictionary and builds an Info from it.
 fn parse_info_dictionary<'a>(info_bencode: &BencodeRef<'a>) -> ParseResult<Info> {
-    let info_hash = InfoHash::from_bytes(info_bencode.buffer());
-
     let info_dict = try!(parse::parse_root_dict(info_bencode));
     let piece_len = try!(parse::parse_piece_length(info_dict));
     let is_private = parse::parse_private(info_dict);
 
-    let pieces = try!(parse::parse_pieces(info_dict));
-    let piece_buffers = try!(allocate_pieces(pieces));
+    let meta_version = match parse::parse_meta_version(info_dict) {
+        None | Some(1)                                    => MetaVersion::V1,
+        Some(2) if parse::parse_pieces(info_dict).is_ok() => MetaVersion::Hybrid,
+        Some(2)                                           => MetaVersion::V2,
+        Some(version) => return Err(ParseError::from_kind(ParseErrorKind::UnsupportedMetaVersion{ version: version }))
+    };
 
-    if is_multi_file_torrent(info_dict) {
-        let file_directory = try!(parse::parse_name(info_dict));
-        let mut file_directory_path = PathBuf::new();
-        file_directory_path.push(file_directory);
+    let opt_file_tree = if meta_version == MetaVersion::V1 {
+        None
+    } else {
+        let file_tree_dict = try!(parse::parse_file_tree(info_dict));
 
-        let files_bencode = try!(parse::parse_files_list(info_dict));
+        let mut file_tree = Vec::new();
+        try!(parse_file_tree(file_tree_dict, &mut PathBuf::new(), &mut file_tree));
 
-        let mut files_list = Vec::with_capacity(files_bencode.len());
-        for file_bencode in files_bencode {
-            let file_dict = try!(parse::parse_file_dict(file_bencode));
-            let file = try!(File::as_multi_file(file_dict));
+        Some(file_tree)
+    };
 
-            files_list.push(file);
+    let opt_info_hash_v2 = if meta_version == MetaVersion::V1 {
+        None
+    } else {
+        Some(truncated_sha256(info_bencode.buffer()))
+    };
+    let info_hash = match (meta_version, opt_info_hash_v2) {
+        (MetaVersion::V2, Some(info_hash_v2)) => info_hash_v2,
+        _                                     => InfoHash::from_bytes(info_bencode.buffer())
+    };
+
+    let (files, file_directory, piece_buffers) = match (meta_version, opt_file_tree.as_ref()) {
+        (MetaVersion::V2, Some(file_tree)) => {
+            // Pure v2 torrents only have the file tree to describe their layout
+            let file_directory = if is_single_file_tree(file_tree) {
+                None
+            } else {
+                Some(PathBuf::from(try!(parse::parse_name(info_dict))))
+            };
+
+            (file_tree.clone(), file_directory, Vec::new())
+        },
+        _ => {
+            let pieces = try!(parse::parse_pieces(info_dict));
+            let piece_buffers = try!(allocate_pieces(pieces));
+
+            let (files, file_directory) = if is_multi_file_torrent(info_dict) {
+                let file_directory = try!(parse::parse_name(info_dict));
+                let mut file_directory_path = PathBuf::new();
+                file_directory_path.push(file_directory);
+
+                let files_bencode = try!(parse::parse_files_list(info_dict));
+
+                let mut files_list = Vec::with_capacity(files_bencode.len());
+                for file_bencode in files_bencode {
+                    let file_dict = try!(parse::parse_file_dict(file_bencode));
+                    let file = try!(File::as_multi_file(file_dict));
+
+                    files_list.push(file);
+                }
+
+                (files_list, Some(file_directory_path))
+            } else {
+                let file = try!(File::as_single_file(info_dict));
+
+                (vec![file], None)
+            };
+
+            // Checking this up front saves consumers from indexing out of bounds on a corrupt file
+            let total_length = files.iter().map(|file| file.length()).sum();
+            try!(validate_pieces_length(pieces.len() as u64, total_length, piece_len));
+
+            (files, file_directory, piece_buffers)
         }
+    };
 
-        Ok(Info {
-            info_hash: info_hash,
-            files: files_list,
-            pieces: piece_buffers,
-            piece_len: piece_len,
-            is_private: is_private,
-            file_directory: Some(file_directory_path),
-        })
+    Ok(Info {
+        info_hash: info_hash,
+        info_hash_v2: opt_info_hash_v2,
+        files: files,
+        pieces: piece_buffers,
+        piece_len: piece_len,
+        is_private: is_private,
+        meta_version: meta_version,
+        file_directory: file_directory,
+        file_tree: opt_file_tree,
+        info_bytes: info_bencode.buffer().to_vec(),
+    })
+}
+
+/// Validates that there is exactly one piece hash for each piece needed to cover the total length.
+fn validate_pieces_length(pieces_length: u64, total_length: u64, piece_length: u64) -> ParseResult<()> {
+    if piece_length == 0 && total_length != 0 {
+        let error_msg = "Piece Length Of Zero For Non Empty Files".to_owned();
+        return Err(ParseError::from_kind(ParseErrorKind::MissingData { details: error_msg }))
+    }
+
+    let num_pieces = if total_length == 0 { 0 } else { (total_length - 1) / piece_length + 1 };
+    let expected_length = num_pieces * sha::SHA_HASH_LEN as u64;
+
+    if pieces_length != expected_length {
+        Err(ParseError::from_kind(ParseErrorKind::PieceLengthMismatch{ expected: expected_length, actual: pieces_length }))
     } else {
-        let file = try!(File::as_single_file(info_dict));
-
-        Ok(Info {
-            info_hash: info_hash,
-            files: vec![file],
-            pieces: piece_buffers,
-            piece_len: piece_len,
-            is_private: is_private,
-            file_directory: None,
-        })
+        Ok(())
     }
 }
 
+/// Recursively walks the v2 file tree, pushing each file found on to the files list.
+///
+/// Each node in the tree is a dictionary keyed by path component, where a file is
+/// denoted by a node containing an empty key which maps to the file dictionary.
+fn parse_file_tree<B>(node_dict: &BDictAccess<B::BKey, B>, path: &mut PathBuf, files: &mut Vec<File>) -> ParseResult<()>
+    where B: BRefAccess<BType=B>, B::BKey: AsRef<[u8]> {
+    // Dictionary keys are sorted in the bencode, but our access does not guarantee ordering
+    let mut node_entries = node_dict.to_list();
+    node_entries.sort_by(|&(a, _), &(b, _)| a.as_ref().cmp(b.as_ref()));
+
+    for (key, value) in node_entries {
+        let child_dict = try!(parse::parse_file_tree_node(value));
+
+        if key.as_ref().is_empty() {
+            if path.as_os_str().is_empty() {
+                let error_msg = "File Tree Contains A File With No Path".to_owned();
+                return Err(ParseError::from_kind(ParseErrorKind::MissingData { details: error_msg }))
+            }
+
+            files.push(try!(File::as_tree_file(child_dict, path.clone())));
+        } else {
+            let component = try!(str::from_utf8(key.as_ref()).map_err(|_| {
+                let error_msg = "File Tree Path Component Is Not Valid UTF-8".to_owned();
+                ParseError::from_kind(ParseErrorKind::MissingData { details: error_msg })
+            }));
+
+            path.push(component);
+            try!(parse_file_tree(child_dict, path, files));
+            path.pop();
+        }
+    }
+
+    Ok(())
+}
+
+/// Hashes the bytes with SHA-256, truncating the digest to the length of an `InfoHash`.
+fn truncated_sha256(bytes: &[u8]) -> InfoHash {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+
+    let mut digest = [0u8; SHA256_HASH_LEN];
+    hasher.result(&mut digest);
+
+    let mut truncated = [0u8; sha::SHA_HASH_LEN];
+    truncated.copy_from_slice(&digest[..sha::SHA_HASH_LEN]);
+
+    truncated.into()
+}
+
+/// Returns whether or not the file tree describes a single file torrent.
+fn is_single_file_tree(file_tree: &[File]) -> bool {
+    file_tree.len() == 1 && file_tree[0].path().components().count() == 1
+}
+
 /// Returns whether or not this is a multi file torrent.
 fn is_multi_file_torrent<B>(info_dict: &BDictAccess<B::BKey, B>) -> bool
     where B: BRefAccess {
@@ -336,23 +787,32 @@ fn allocate_pieces(pieces: &[u8]) -> ParseResult<Vec<[u8; sha::SHA_HASH_LEN]>> {
 /// Contains information for a single file.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct File {
-    len:    u64,
-    path:   PathBuf,
-    md5sum: Option<Vec<u8>>,
+    len:         u64,
+    path:        PathBuf,
+    md5sum:      Option<Vec<u8>>,
+    attr:        Option<String>,
+    symlink:     Option<PathBuf>,
+    // Present only for non empty files in a v2 file tree.
+    pieces_root: Option<[u8; PIECES_ROOT_LEN]>,
 }
 
 impl File {
     /// Parse the info dictionary and generate a single file File.
     fn as_single_file<B>(info_dict: &BDictAccess<B::BKey, B>) -> ParseResult<File>
-        where B: BRefAccess {
+        where B: BRefAccess<BType=B> {
         let length = try!(parse::parse_length(info_dict));
         let md5sum = parse::parse_md5sum(info_dict).map(|m| m.to_owned());
+        let attr = parse::parse_attr(info_dict).map(|a| String::from_utf8_lossy(a).into_owned());
+        let symlink = try!(parse_symlink_path(info_dict));
         let name = try!(parse::parse_name(info_dict));
 
         Ok(File {
             len: length,
             path: name.to_owned().into(),
             md5sum: md5sum,
+            attr: attr,
+            symlink: symlink,
+            pieces_root: None,
         })
     }
 
@@ -361,6 +821,8 @@ impl File {
         where B: BRefAccess<BType=B> {
         let length = try!(parse::parse_length(file_dict));
         let md5sum = parse::parse_md5sum(file_dict).map(|m| m.to_owned());
+        let attr = parse::parse_attr(file_dict).map(|a| String::from_utf8_lossy(a).into_owned());
+        let symlink = try!(parse_symlink_path(file_dict));
 
         let path_list_bencode = try!(parse::parse_path_list(file_dict));
 
@@ -375,6 +837,42 @@ impl File {
             len: length,
             path: path_buf,
             md5sum: md5sum,
+            attr: attr,
+            symlink: symlink,
+            pieces_root: None,
+        })
+    }
+
+    /// Parse the file dictionary from a v2 file tree and generate a File.
+    fn as_tree_file<B>(file_dict: &BDictAccess<B::BKey, B>, path: PathBuf) -> ParseResult<File>
+        where B: BRefAccess {
+        let length = try!(parse::parse_length(file_dict));
+
+        let pieces_root = match parse::parse_pieces_root(file_dict) {
+            Some(root) if root.len() == PIECES_ROOT_LEN => {
+                let mut root_bytes = [0u8; PIECES_ROOT_LEN];
+                root_bytes.copy_from_slice(root);
+
+                Some(root_bytes)
+            },
+            Some(root) => {
+                let error_msg = format!("Pieces Root Length Of {} Is Invalid", root.len());
+                return Err(ParseError::from_kind(ParseErrorKind::MissingData { details: error_msg }))
+            },
+            None if length != 0 => {
+                let error_msg = format!("Pieces Root Missing For File {:?}", path);
+                return Err(ParseError::from_kind(ParseErrorKind::MissingData { details: error_msg }))
+            },
+            None => None
+        };
+
+        Ok(File {
+            len: length,
+            path: path,
+            md5sum: None,
+            attr: None,
+            symlink: None,
+            pieces_root: pieces_root,
         })
     }
 
@@ -394,6 +892,105 @@ impl File {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Optional attributes of the file, where each character is an attribute.
+    ///
+    /// Attributes are specified in BEP 47.
+    pub fn attr(&self) -> Option<&str> {
+        self.attr.as_ref().map(|a| &a[..])
+    }
+
+    /// Attributes of the file, with unknown attributes ignored.
+    pub fn attributes(&self) -> FileAttributes {
+        self.attr().map(FileAttributes::from_attr).unwrap_or_default()
+    }
+
+    /// Whether or not the file is a padding file, which consists of zeros and should not be written to disk.
+    pub fn is_padding(&self) -> bool {
+        self.attributes().is_padding()
+    }
+
+    /// Path the file links to, relative to the torrent directory, if the file is a symlink.
+    pub fn symlink_path(&self) -> Option<&Path> {
+        if self.attributes().is_symlink() {
+            self.symlink.as_ref().map(|s| s.as_ref())
+        } else {
+            None
+        }
+    }
+
+    /// Root of the SHA-256 merkle tree for the file.
+    ///
+    /// Only present for non empty files within a v2 file tree.
+    pub fn pieces_root(&self) -> Option<&[u8]> {
+        self.pieces_root.as_ref().map(|r| &r[..])
+    }
+}
+
+/// Parse the optional symlink path from the info or file dictionary.
+fn parse_symlink_path<B>(info_or_file_dict: &BDictAccess<B::BKey, B>) -> ParseResult<Option<PathBuf>>
+    where B: BRefAccess<BType=B> {
+    let symlink_list_bencode = match parse::parse_symlink_path_list(info_or_file_dict) {
+        Some(symlink_list_bencode) => symlink_list_bencode,
+        None                       => return Ok(None)
+    };
+
+    let mut symlink_buf = PathBuf::new();
+    for symlink_bencode in symlink_list_bencode {
+        symlink_buf.push(try!(parse::parse_path_str(symlink_bencode)));
+    }
+
+    Ok(Some(symlink_buf))
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Attributes of a file, as specified in BEP 47.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct FileAttributes {
+    executable: bool,
+    hidden:     bool,
+    symlink:    bool,
+    padding:    bool,
+}
+
+impl FileAttributes {
+    /// Parse the FileAttributes from an attribute string, where each character is an attribute.
+    pub fn from_attr(attr: &str) -> FileAttributes {
+        let mut attributes = FileAttributes::default();
+
+        for flag in attr.chars() {
+            match flag {
+                'x' => attributes.executable = true,
+                'h' => attributes.hidden = true,
+                'l' => attributes.symlink = true,
+                'p' => attributes.padding = true,
+                _   => ()
+            }
+        }
+
+        attributes
+    }
+
+    /// Whether or not the file should be marked executable when written to disk.
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    /// Whether or not the file should be marked hidden when written to disk.
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Whether or not the file is a symlink, in which case it has no contents of its own.
+    pub fn is_symlink(&self) -> bool {
+        self.symlink
+    }
+
+    /// Whether or not the file is a padding file, which consists of zeros and should not be written to disk.
+    pub fn is_padding(&self) -> bool {
+        self.padding
+    }
 }
 
 #[cfg(test)]
@@ -404,7 +1001,9 @@ mod tests {
     use bip_util::sha;
     use bip_util::bt::InfoHash;
 
-    use metainfo::Metainfo;
+    use builder::{InfoBuilder, PieceLength};
+    use error::{ParseError, ParseErrorKind};
+    use metainfo::{FileAttributes, Info, Metainfo, MetaVersion};
     use parse;
 
     /// Helper function for manually constructing a metainfo file based on the parameters given.
@@ -514,7 +1113,8 @@ mod tests {
 
         assert_eq!(metainfo_file.info().directory(), directory.map(|d| d.as_ref()));
         assert_eq!(metainfo_file.info().piece_length(), piece_length.unwrap() as u64);
-        assert_eq!(metainfo_file.info().is_private(), private.map(|private| private == 1));
+        assert_eq!(metainfo_file.info().private_flag(), private.map(|private| private == 1));
+        assert_eq!(metainfo_file.info().is_private(), private == Some(1));
 
         let pieces = pieces.unwrap();
         assert_eq!(pieces.chunks(sha::SHA_HASH_LEN).count(),
@@ -552,7 +1152,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         validate_parse_from_params(Some(tracker),
@@ -574,7 +1174,7 @@ mod tests {
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
         let directory = "dummy_file_directory";
-        let files = vec![(Some(0),
+        let files = vec![(Some(1024),
                           None,
                           Some(vec!["dummy_sub_directory".to_owned(),
                                     "dummy_file_name".to_owned()]))];
@@ -643,7 +1243,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let creation_date = 5050505050;
@@ -666,7 +1266,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let comment = "This is my boring test comment...";
@@ -689,7 +1289,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let created_by = "Me";
@@ -712,7 +1312,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let encoding = "UTF-8";
@@ -735,7 +1335,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let private = 0;
@@ -758,7 +1358,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let private = 1;
@@ -781,7 +1381,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let private = -1;
@@ -803,7 +1403,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         validate_parse_from_params(None,
@@ -818,6 +1418,178 @@ mod tests {
                                    Some(vec![(Some(file_len), None, Some(file_paths))]));
     }
 
+    /// Helper function for building a single file metainfo file with the given url-list bencode.
+    fn build_with_url_list(url_list: BencodeMut) -> Metainfo {
+        let root_dict = ben_map!{
+            parse::URL_LIST_KEY => url_list,
+            parse::INFO_KEY     => ben_map!{
+                parse::PIECE_LENGTH_KEY => ben_int!(1024),
+                parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN][..]),
+                parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+                parse::LENGTH_KEY       => ben_int!(1024)
+            }
+        };
+
+        Metainfo::from_bytes(root_dict.encode()).unwrap()
+    }
+
+    #[test]
+    fn positive_parse_with_url_list_string() {
+        let metainfo_file = build_with_url_list(ben_bytes!("http://dummy_domain.com/file"));
+
+        assert_eq!(Some(&vec!["http://dummy_domain.com/file".to_owned()]), metainfo_file.web_seeds());
+    }
+
+    #[test]
+    fn positive_parse_with_url_list_list() {
+        let metainfo_file = build_with_url_list(ben_list!(ben_bytes!("http://dummy_domain.com/file"),
+                                                          ben_bytes!("ftp://other_dummy_domain.com/")));
+
+        let expected = vec!["http://dummy_domain.com/file".to_owned(), "ftp://other_dummy_domain.com/".to_owned()];
+        assert_eq!(Some(&expected), metainfo_file.web_seeds());
+    }
+
+    #[test]
+    fn positive_parse_with_url_list_round_trip() {
+        let metainfo_file = build_with_url_list(ben_list!(ben_bytes!("http://dummy_domain.com/file")));
+        let round_trip_file = Metainfo::from_bytes(metainfo_file.to_bytes()).unwrap();
+
+        assert_eq!(metainfo_file.web_seeds(), round_trip_file.web_seeds());
+    }
+
+    /// Helper function for building a multi file info dictionary with the given file lengths.
+    fn build_with_file_lengths(lengths: &[i64]) -> Info {
+        let mut bencode_files = BencodeMut::new_list();
+        {
+            let bencode_files_access = bencode_files.list_mut().unwrap();
+
+            for (index, &length) in lengths.iter().enumerate() {
+                bencode_files_access.push(ben_map!{
+                    parse::LENGTH_KEY => ben_int!(length),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!(format!("dummy_file_name_{}", index)))
+                });
+            }
+        }
+
+        let total_length = lengths.iter().sum::<i64>() as usize;
+        let pieces = vec![0u8; (total_length + 1023) / 1024 * sha::SHA_HASH_LEN];
+
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&pieces[..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_directory"),
+            parse::FILES_KEY        => bencode_files
+        };
+
+        Info::from_bytes(info_dict.encode()).unwrap()
+    }
+
+    #[test]
+    fn positive_total_length_single_file() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN][..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::LENGTH_KEY       => ben_int!(500)
+        };
+        let info = Info::from_bytes(info_dict.encode()).unwrap();
+
+        assert_eq!(500, info.total_length());
+        assert_eq!(500, Metainfo::from(info).total_length());
+    }
+
+    #[test]
+    fn positive_total_length_multi_file() {
+        let info = build_with_file_lengths(&[5, 1024, 3000]);
+
+        assert_eq!(4029, info.total_length());
+    }
+
+    #[test]
+    fn positive_total_length_zero_length_files() {
+        let info = build_with_file_lengths(&[0, 0]);
+
+        assert_eq!(0, info.total_length());
+    }
+
+    #[test]
+    fn positive_to_magnet_multi_file() {
+        let info = build_with_file_lengths(&[5]);
+        let info_hash_hex = info.info_hash().as_ref().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+        let magnet = Metainfo::from(info).to_magnet();
+
+        assert_eq!(format!("magnet:?xt=urn:btih:{}&dn=dummy_file_directory", info_hash_hex), magnet);
+    }
+
+    #[test]
+    fn positive_percent_encode() {
+        assert_eq!("udp%3A%2F%2Fa.b%3A80%2F%3Fx%3D1%26y%3D%20~", super::percent_encode("udp://a.b:80/?x=1&y= ~"));
+    }
+
+    #[test]
+    fn positive_parse_v2_single_file() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(16384),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::META_VERSION_KEY => ben_int!(2),
+            parse::FILE_TREE_KEY    => ben_map!{
+                "dummy_file_name" => ben_map!{
+                    "" => ben_map!{
+                        parse::LENGTH_KEY      => ben_int!(500),
+                        parse::PIECES_ROOT_KEY => ben_bytes!(&[1u8; super::PIECES_ROOT_LEN][..])
+                    }
+                }
+            }
+        };
+        let info = Info::from_bytes(info_dict.encode()).unwrap();
+
+        assert_eq!(MetaVersion::V2, info.meta_version());
+        assert_eq!(None, info.directory());
+        assert_eq!(0, info.pieces().count());
+        assert_eq!(500, info.total_length());
+
+        let file = info.file_tree().unwrap().next().unwrap();
+        assert_eq!(Path::new("dummy_file_name"), file.path());
+        assert_eq!(Some(&[1u8; super::PIECES_ROOT_LEN][..]), file.pieces_root());
+    }
+
+    #[test]
+    fn negative_parse_unsupported_meta_version() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN][..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::LENGTH_KEY       => ben_int!(500),
+            parse::META_VERSION_KEY => ben_int!(3)
+        };
+
+        match Info::from_bytes(info_dict.encode()) {
+            Err(ParseError(ParseErrorKind::UnsupportedMetaVersion{ version: 3 }, _)) => (),
+            other => panic!("Expected UnsupportedMetaVersion, Found {:?}", other)
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_parse_v2_with_invalid_pieces_root() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(16384),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::META_VERSION_KEY => ben_int!(2),
+            parse::FILE_TREE_KEY    => ben_map!{
+                "dummy_file_name" => ben_map!{
+                    "" => ben_map!{
+                        parse::LENGTH_KEY      => ben_int!(500),
+                        parse::PIECES_ROOT_KEY => ben_bytes!(&[1u8; sha::SHA_HASH_LEN][..])
+                    }
+                }
+            }
+        };
+
+        Info::from_bytes(info_dict.encode()).unwrap();
+    }
+
     #[test]
     #[should_panic]
     fn negative_parse_from_empty_bytes() {
@@ -830,7 +1602,7 @@ mod tests {
         let tracker = "udp://dummy_domain.com:8989";
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
         let file_paths = vec!["dummy_file_name".to_owned()];
 
         let private = -1;
@@ -896,7 +1668,7 @@ mod tests {
         let piece_len = 1024;
         let pieces = [0u8; sha::SHA_HASH_LEN];
 
-        let file_len = 0;
+        let file_len = 1024;
 
         validate_parse_from_params(Some(tracker),
                                    None,
@@ -909,4 +1681,220 @@ mod tests {
                                    None,
                                    Some(vec![(Some(file_len), None, None)]));
     }
+
+    #[test]
+    fn positive_parse_file_attributes() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN * 2][..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_directory"),
+            parse::FILES_KEY        => ben_list!(
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(1000),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("run.sh")),
+                    parse::ATTR_KEY   => ben_bytes!("xh")
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(24),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!(".pad"), ben_bytes!("24")),
+                    parse::ATTR_KEY   => ben_bytes!("p")
+                },
+                ben_map!{
+                    parse::LENGTH_KEY       => ben_int!(0),
+                    parse::PATH_KEY         => ben_list!(ben_bytes!("link.sh")),
+                    parse::ATTR_KEY         => ben_bytes!("l"),
+                    parse::SYMLINK_PATH_KEY => ben_list!(ben_bytes!("bin"), ben_bytes!("run.sh"))
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(10),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("data.bin"))
+                }
+            )
+        };
+        let info = Info::from_bytes(info_dict.encode()).unwrap();
+        let files = info.files().collect::<Vec<_>>();
+
+        let executable = files[0].attributes();
+        assert!(executable.is_executable() && executable.is_hidden());
+        assert!(!executable.is_padding() && !executable.is_symlink());
+
+        assert!(files[1].is_padding());
+        assert_eq!(FileAttributes::from_attr("p"), files[1].attributes());
+
+        assert!(files[2].attributes().is_symlink());
+        assert_eq!(Some(Path::new("bin/run.sh")), files[2].symlink_path());
+        assert_eq!(None, files[0].symlink_path());
+
+        assert_eq!(None, files[3].attr());
+        assert_eq!(FileAttributes::default(), files[3].attributes());
+    }
+
+    #[test]
+    fn positive_build_keeps_file_attributes() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN * 2][..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_directory"),
+            parse::FILES_KEY        => ben_list!(
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(1000),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("run.sh")),
+                    parse::ATTR_KEY   => ben_bytes!("xh")
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(24),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!(".pad"), ben_bytes!("24")),
+                    parse::ATTR_KEY   => ben_bytes!("p")
+                },
+                ben_map!{
+                    parse::LENGTH_KEY       => ben_int!(0),
+                    parse::PATH_KEY         => ben_list!(ben_bytes!("link.sh")),
+                    parse::ATTR_KEY         => ben_bytes!("l"),
+                    parse::SYMLINK_PATH_KEY => ben_list!(ben_bytes!("bin"), ben_bytes!("run.sh"))
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(10),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("data.bin"))
+                }
+            )
+        };
+        let info_bytes = info_dict.encode();
+        let info = Info::from_bytes(&info_bytes).unwrap();
+
+        let (built_bytes, built_hash) = InfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1024))
+            .build_with_hash(&info)
+            .unwrap();
+
+        assert_eq!(info_bytes, built_bytes);
+        assert_eq!(info.info_hash(), built_hash);
+        assert_eq!(info, Info::from_bytes(built_bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_file_attributes_ignore_unknown() {
+        let attributes = FileAttributes::from_attr("zx?");
+
+        assert!(attributes.is_executable());
+        assert_eq!(FileAttributes::from_attr("x"), attributes);
+    }
+
+    /// Helper function for building a single file info dictionary with the given number of pieces.
+    fn parse_with_num_pieces(file_len: i64, num_pieces: usize) -> Result<Info, ParseError> {
+        let pieces = vec![0u8; num_pieces * sha::SHA_HASH_LEN];
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&pieces[..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::LENGTH_KEY       => ben_int!(file_len)
+        };
+
+        Info::from_bytes(info_dict.encode())
+    }
+
+    #[test]
+    fn positive_parse_pieces_cover_total_length() {
+        assert!(parse_with_num_pieces(0, 0).is_ok());
+        assert!(parse_with_num_pieces(1024, 1).is_ok());
+        assert!(parse_with_num_pieces(1025, 2).is_ok());
+    }
+
+    #[test]
+    fn negative_parse_with_short_pieces() {
+        let error = parse_with_num_pieces(3 * 1024 + 1, 3).unwrap_err();
+
+        match error.kind() {
+            &ParseErrorKind::PieceLengthMismatch{ expected: 80, actual: 60 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_parse_with_wrong_total_length() {
+        let error = parse_with_num_pieces(1024, 2).unwrap_err();
+
+        match error.kind() {
+            &ParseErrorKind::PieceLengthMismatch{ expected: 20, actual: 40 } => (),
+            other => panic!("Unexpected Error Kind {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_summary_multi_file() {
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&[0u8; sha::SHA_HASH_LEN * 3][..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_directory"),
+            parse::PRIVATE_KEY      => ben_int!(1),
+            parse::FILES_KEY        => ben_list!(
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(1000),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("dummy_file_one"))
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(24),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!(".pad"), ben_bytes!("24")),
+                    parse::ATTR_KEY   => ben_bytes!("p")
+                },
+                ben_map!{
+                    parse::LENGTH_KEY => ben_int!(1500),
+                    parse::PATH_KEY   => ben_list!(ben_bytes!("dummy_file_two"))
+                }
+            )
+        };
+        let metainfo = Metainfo::from(Info::from_bytes(info_dict.encode()).unwrap());
+        let summary = metainfo.summary();
+
+        assert_eq!("dummy_directory", summary.name());
+        assert_eq!(2524, summary.total_length());
+        assert_eq!(3, summary.num_pieces());
+        assert_eq!(1024, summary.piece_length());
+        assert_eq!(2, summary.num_files());
+        assert!(summary.is_private());
+        assert!(!summary.is_single_file());
+        assert_eq!("dummy_directory (2.46 KiB, 2 files, 3 pieces of 1.00 KiB) [private]", summary.to_string());
+    }
+
+    #[test]
+    fn positive_summary_single_file() {
+        let metainfo = Metainfo::from(parse_with_num_pieces(1025, 2).unwrap());
+        let summary = metainfo.summary();
+
+        assert_eq!("dummy_file_name", summary.name());
+        assert_eq!(1, summary.num_files());
+        assert!(summary.is_single_file());
+        assert!(!summary.is_private());
+        assert_eq!("dummy_file_name (1.00 KiB, 1 file, 2 pieces of 1.00 KiB)", summary.to_string());
+    }
+
+    #[test]
+    fn positive_format_size() {
+        assert_eq!("512 B", super::format_size(512));
+        assert_eq!("1.50 MiB", super::format_size(3 * 512 * 1024));
+        assert_eq!("4.00 GiB", super::format_size(4 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn positive_piece_hashes_iterate_and_index() {
+        let mut pieces = Vec::new();
+        for index in 0..3u8 {
+            pieces.extend_from_slice(&[index; sha::SHA_HASH_LEN]);
+        }
+        let info_dict = ben_map!{
+            parse::PIECE_LENGTH_KEY => ben_int!(1024),
+            parse::PIECES_KEY       => ben_bytes!(&pieces[..]),
+            parse::NAME_KEY         => ben_bytes!("dummy_file_name"),
+            parse::LENGTH_KEY       => ben_int!(3000)
+        };
+        let info = Info::from_bytes(info_dict.encode()).unwrap();
+
+        let piece_hashes = info.piece_hashes().collect::<Vec<_>>();
+        let expected = (0..3u8).map(|index| (index as u32, InfoHash::from([index; sha::SHA_HASH_LEN]))).collect::<Vec<_>>();
+        assert_eq!(expected, piece_hashes);
+        assert_eq!(3, info.piece_hashes().len());
+
+        assert_eq!(Some(InfoHash::from([2u8; sha::SHA_HASH_LEN])), info.piece_hash(2));
+        assert_eq!(Some(InfoHash::from([0u8; sha::SHA_HASH_LEN])), info.piece_hash(0));
+        assert_eq!(None, info.piece_hash(3));
+    }
 }
@@ -28,6 +28,8 @@ pub const COMMENT_KEY:       &'static [u8] = b"comment";
 pub const CREATED_BY_KEY:    &'static [u8] = b"created by";
 pub const ENCODING_KEY:      &'static [u8] = b"encoding";
 pub const INFO_KEY:          &'static [u8] = b"info";
+pub const URL_LIST_KEY:      &'static [u8] = b"url-list";
+pub const PIECE_LAYERS_KEY:  &'static [u8] = b"piece layers";
 
 /// Keys found within the info dictionary of a metainfo file.
 pub const PIECE_LENGTH_KEY: &'static [u8] = b"piece length";
@@ -35,11 +37,18 @@ pub const PIECES_KEY:       &'static [u8] = b"pieces";
 pub const PRIVATE_KEY:      &'static [u8] = b"private";
 pub const NAME_KEY:         &'static [u8] = b"name";
 pub const FILES_KEY:        &'static [u8] = b"files";
+pub const META_VERSION_KEY: &'static [u8] = b"meta version";
+pub const FILE_TREE_KEY:    &'static [u8] = b"file tree";
 
 /// Keys found within the files dictionary of a metainfo file.
-pub const LENGTH_KEY: &'static [u8] = b"length";
-pub const MD5SUM_KEY: &'static [u8] = b"md5sum";
-pub const PATH_KEY:   &'static [u8] = b"path";
+pub const LENGTH_KEY:       &'static [u8] = b"length";
+pub const MD5SUM_KEY:       &'static [u8] = b"md5sum";
+pub const PATH_KEY:         &'static [u8] = b"path";
+pub const ATTR_KEY:         &'static [u8] = b"attr";
+pub const SYMLINK_PATH_KEY: &'static [u8] = b"symlink path";
+
+/// Keys found within the file tree of a v2 metainfo file.
+pub const PIECES_ROOT_KEY: &'static [u8] = b"pieces root";
 
 /// Parses the root bencode as a dictionary.
 pub fn parse_root_dict<B>(root_bencode: &B) -> ParseResult<&BDictAccess<B::BKey, B::BType>>
@@ -97,12 +106,37 @@ pub fn parse_encoding<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&
     CONVERT.lookup_and_convert_str(root_dict, ENCODING_KEY).ok()
 }
 
+/// Parses the url list from the root dictionary.
+///
+/// Accepts both a single url string, and a list of url strings.
+pub fn parse_url_list<B>(root_dict: &BDictAccess<B::BKey, B>) -> Option<Vec<String>>
+    where B: BRefAccess<BType=B> {
+    CONVERT.lookup(root_dict, URL_LIST_KEY).ok().and_then(|bencode| {
+        if let Some(url) = bencode.str() {
+            Some(vec![url.to_owned()])
+        } else {
+            bencode.list().map(|list| {
+                list.into_iter()
+                    .filter_map(|bencode_str| bencode_str.str())
+                    .map(String::from)
+                    .collect()
+            })
+        }
+    })
+}
+
 /// Parses the info dictionary from the root dictionary.
 pub fn parse_info_bencode<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> ParseResult<&B>
     where B: BRefAccess {
     CONVERT.lookup(root_dict, INFO_KEY)
 }
 
+/// Parses the v2 piece layers from the root dictionary.
+pub fn parse_piece_layers_bencode<'a, B>(root_dict: &'a BDictAccess<B::BKey, B>) -> Option<&B>
+    where B: BRefAccess {
+    CONVERT.lookup(root_dict, PIECE_LAYERS_KEY).ok()
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Parses the piece length from the info dictionary.
@@ -135,6 +169,32 @@ pub fn parse_files_list<B>(info_dict: &BDictAccess<B::BKey, B>) -> ParseResult<&
     CONVERT.lookup_and_convert_list(info_dict, FILES_KEY)
 }
 
+/// Parses the meta version from the info dictionary.
+pub fn parse_meta_version<B>(info_dict: &BDictAccess<B::BKey, B>) -> Option<i64>
+    where B: BRefAccess {
+    CONVERT.lookup_and_convert_int(info_dict, META_VERSION_KEY).ok()
+}
+
+/// Parses the file tree from the info dictionary.
+pub fn parse_file_tree<B>(info_dict: &BDictAccess<B::BKey, B>) -> ParseResult<&BDictAccess<B::BKey, B>>
+    where B: BRefAccess<BType=B> {
+    CONVERT.lookup_and_convert_dict(info_dict, FILE_TREE_KEY)
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Parses a node dictionary from the file tree bencode.
+pub fn parse_file_tree_node<B>(node_bencode: &B) -> ParseResult<&BDictAccess<B::BKey, B::BType>>
+    where B: BRefAccess {
+    CONVERT.convert_dict(node_bencode, FILE_TREE_KEY)
+}
+
+/// Parses the pieces root from the file tree file dictionary.
+pub fn parse_pieces_root<'a, B>(file_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a [u8]>
+    where B: BRefAccess + 'a {
+    CONVERT.lookup_and_convert_bytes(file_dict, PIECES_ROOT_KEY).ok()
+}
+
 // ----------------------------------------------------------------------------//
 
 /// Parses the file dictionary from the file bencode.
@@ -155,6 +215,18 @@ pub fn parse_md5sum<'a, B>(info_or_file_dict: &'a BDictAccess<B::BKey, B>) -> Op
     CONVERT.lookup_and_convert_bytes(info_or_file_dict, MD5SUM_KEY).ok()
 }
 
+/// Parses the attributes from the info or file dictionary.
+pub fn parse_attr<'a, B>(info_or_file_dict: &'a BDictAccess<B::BKey, B>) -> Option<&'a [u8]>
+    where B: BRefAccess + 'a {
+    CONVERT.lookup_and_convert_bytes(info_or_file_dict, ATTR_KEY).ok()
+}
+
+/// Parses the symlink path list from the info or file dictionary.
+pub fn parse_symlink_path_list<B>(info_or_file_dict: &BDictAccess<B::BKey, B>) -> Option<&BListAccess<B>>
+    where B: BRefAccess<BType=B> {
+    CONVERT.lookup_and_convert_list(info_or_file_dict, SYMLINK_PATH_KEY).ok()
+}
+
 /// Parses the path list from the file dictionary.
 pub fn parse_path_list<B>(file_dict: &BDictAccess<B::BKey, B>) -> ParseResult<&BListAccess<B>>
     where B: BRefAccess<BType=B> {
@@ -1,11 +1,18 @@
 extern crate bip_metainfo;
 
-use bip_metainfo::MetainfoBuilder;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc;
+
+use bip_metainfo::{MetainfoBuilder, Metainfo, Info, InfoBuilder, DirectAccessor, DirectoryAccessor, PieceLength};
 
 const TRACKER: &'static str = "udp://foo.bar.baz:6969";
 const DATE: i64 = 1517651523851;
 const COMMENT: &'static str = "Foo bar baz";
 const CREATED_BY: &'static str = "Fridge";
+const ENCODING: &'static str = "UTF-8";
 
 #[test]
 fn positive_set_trackers() {
@@ -50,3 +57,337 @@ fn positive_set_created_by() {
 
     assert_eq!(builder.get_created_by(), Some(CREATED_BY.to_string()));
 }
+
+#[test]
+fn positive_set_encoding() {
+    let builder = MetainfoBuilder::new()
+        .set_encoding(Some(ENCODING));
+
+    assert_eq!(builder.get_encoding(), Some(ENCODING.to_string()));
+}
+
+#[test]
+fn positive_metadata_round_trip() {
+    let accessor = DirectAccessor::new("FileName.txt", b"Some file data");
+
+    let bytes = MetainfoBuilder::new()
+        .set_creation_date(Some(DATE))
+        .set_created_by(Some(CREATED_BY))
+        .set_encoding(Some(ENCODING))
+        .build(accessor)
+        .unwrap();
+    let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+    assert_eq!(Some(DATE), metainfo.creation_date());
+    assert_eq!(Some(CREATED_BY), metainfo.created_by());
+    assert_eq!(Some(ENCODING), metainfo.encoding());
+
+    let metainfo = Metainfo::from_bytes(metainfo.to_bytes()).unwrap();
+
+    assert_eq!(Some(DATE), metainfo.creation_date());
+    assert_eq!(Some(CREATED_BY), metainfo.created_by());
+    assert_eq!(Some(ENCODING), metainfo.encoding());
+}
+
+#[test]
+fn positive_set_hash_threads_same_pieces() {
+    let file_content = (0..(1024 * 1024 + 7)).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+
+    let build_with_threads = |threads| {
+        let accessor = DirectAccessor::new("FileName.txt", &file_content);
+
+        MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(16 * 1024))
+            .set_hash_threads(threads)
+            .build(accessor)
+            .unwrap()
+    };
+
+    let single_bytes = build_with_threads(1);
+    let multi_bytes = build_with_threads(4);
+
+    assert_eq!(single_bytes, multi_bytes);
+    assert_eq!(65, Metainfo::from_bytes(&multi_bytes).unwrap().info().pieces().count());
+}
+
+#[test]
+fn positive_set_progress_reports_total() {
+    let file_content = vec![55u8; 10 * 16 * 1024 + 1];
+    let total = file_content.len() as u64;
+    let accessor = DirectAccessor::new("FileName.txt", &file_content);
+
+    let (send, recv) = mpsc::channel();
+    MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(16 * 1024))
+        .set_hash_threads(2)
+        .set_progress(move |hashed, total| send.send((hashed, total)).unwrap())
+        .build(accessor)
+        .unwrap();
+
+    // Callback is not invoked after build, so everything has been sent already
+    let updates = recv.try_iter().collect::<Vec<(u64, u64)>>();
+
+    assert_eq!(11, updates.len());
+    assert!(updates.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(Some(&(total, total)), updates.last());
+}
+
+#[test]
+fn positive_auto_piece_length() {
+    let file_content = vec![55u8; 32 * 1024 * 1024];
+    let accessor = DirectAccessor::new("FileName.txt", &file_content);
+
+    let bytes = MetainfoBuilder::new()
+        .auto_piece_length()
+        .set_hash_threads(4)
+        .build(accessor)
+        .unwrap();
+    let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+    assert_eq!(32 * 1024, metainfo.info().piece_length());
+    assert_eq!(1024, metainfo.info().pieces().count());
+}
+
+#[test]
+fn positive_set_trackers_tiers_round_trip() {
+    let trackers = vec![
+        vec!["udp://tier.one:1".to_string(), "udp://tier.one:2".to_string()],
+        vec!["http://tier.two/announce".to_string()]
+    ];
+    let accessor = DirectAccessor::new("FileName.txt", b"Some file data");
+
+    let bytes = MetainfoBuilder::new()
+        .set_trackers(Some(&trackers))
+        .build(accessor)
+        .unwrap();
+
+    let expected_list = b"13:announce-listll16:udp://tier.one:116:udp://tier.one:2el24:http://tier.two/announceee";
+    assert!(bytes.windows(expected_list.len()).any(|window| &window[..] == &expected_list[..]));
+
+    let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+    assert_eq!(Some(&trackers), metainfo.trackers());
+
+    let metainfo = Metainfo::from_bytes(metainfo.to_bytes()).unwrap();
+    assert_eq!(Some(&trackers), metainfo.trackers());
+}
+
+#[test]
+fn positive_set_web_seeds() {
+    let web_seeds = vec!["http://foo.bar.baz/file".to_string()];
+
+    let builder = MetainfoBuilder::new()
+        .set_web_seeds(Some(&web_seeds));
+
+    assert_eq!(builder.get_web_seeds(), Some(web_seeds.clone()));
+}
+
+#[test]
+fn positive_set_private_changes_info_hash() {
+    let build_with_private = |is_private| {
+        let accessor = DirectAccessor::new("FileName.txt", b"Some file data");
+
+        let bytes = MetainfoBuilder::new()
+            .set_private(is_private)
+            .build(accessor)
+            .unwrap();
+
+        Metainfo::from_bytes(&bytes).unwrap()
+    };
+
+    let public_metainfo = build_with_private(false);
+    let private_metainfo = build_with_private(true);
+
+    assert!(!public_metainfo.info().is_private());
+    assert!(private_metainfo.info().is_private());
+    assert!(public_metainfo.info().info_hash() != private_metainfo.info().info_hash());
+
+    let round_trip_metainfo = Metainfo::from_bytes(private_metainfo.to_bytes()).unwrap();
+    assert_eq!(private_metainfo.info().info_hash(), round_trip_metainfo.info().info_hash());
+}
+
+#[test]
+fn positive_metainfo_build_with_hash() {
+    let accessor = DirectAccessor::new("FileName.txt", b"Some file data to be split in to a few pieces");
+
+    let (bytes, info_hash) = MetainfoBuilder::new()
+        .set_main_tracker(Some(TRACKER))
+        .set_piece_length(PieceLength::Custom(16))
+        .build_with_hash(accessor)
+        .unwrap();
+
+    assert_eq!(Metainfo::from_bytes(&bytes).unwrap().info().info_hash(), info_hash);
+}
+
+#[test]
+fn positive_info_build_with_hash() {
+    let accessor = DirectAccessor::new("FileName.txt", b"Some file data to be split in to a few pieces");
+
+    let (bytes, info_hash) = InfoBuilder::new()
+        .set_private(true)
+        .build_with_hash(accessor)
+        .unwrap();
+
+    assert_eq!(Info::from_bytes(&bytes).unwrap().info_hash(), info_hash);
+}
+
+#[test]
+fn positive_build_with_hash_same_bytes_as_build() {
+    let data = b"Some file data to be split in to a few pieces";
+
+    let bytes = MetainfoBuilder::new()
+        .set_comment(Some(COMMENT))
+        .build(DirectAccessor::new("FileName.txt", data))
+        .unwrap();
+    let (hash_bytes, _) = MetainfoBuilder::new()
+        .set_comment(Some(COMMENT))
+        .build_with_hash(DirectAccessor::new("FileName.txt", data))
+        .unwrap();
+
+    assert_eq!(bytes, hash_bytes);
+}
+
+#[test]
+fn positive_info_from_directory() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/directory");
+
+    let bytes = InfoBuilder::from_directory(&directory).unwrap();
+    let info = Info::from_bytes(&bytes).unwrap();
+
+    // Files are sorted by path, and the link.txt symlink is left out
+    let files = info.files().map(|file| (file.path().to_path_buf(), file.length())).collect::<Vec<_>>();
+    let expected = vec![(PathBuf::from("a/b.txt"), 1), (PathBuf::from("a/z.txt"), 3),
+                        (PathBuf::from("b.txt"), 2), (PathBuf::from("c/d/e.txt"), 5)];
+
+    assert_eq!(Some(Path::new("directory")), info.directory());
+    assert_eq!(expected, files);
+    assert_eq!(11, info.total_length());
+    assert_eq!(bytes, InfoBuilder::from_directory(&directory).unwrap());
+}
+
+#[test]
+fn positive_align_files_to_piece_boundary() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/directory");
+    let piece_length = 16 * 1024;
+
+    let bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(piece_length))
+        .set_hash_threads(2)
+        .align_files(true)
+        .build(DirectoryAccessor::new(&directory).unwrap())
+        .unwrap();
+    let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+    let mut offset = 0;
+    let mut padded_content = Vec::new();
+    for file in metainfo.info().files() {
+        if file.is_padding() {
+            assert!(file.path().starts_with(".pad"));
+            padded_content.extend(vec![0u8; file.length() as usize]);
+        } else {
+            assert_eq!(0, offset % piece_length as u64);
+            padded_content.extend(fs::read(directory.join(file.path())).unwrap());
+        }
+
+        offset += file.length();
+    }
+    assert_eq!(7, metainfo.info().files().count());
+    assert_eq!(3 * piece_length as u64 + 5, metainfo.info().total_length());
+
+    // Padding should hash as zeros, the same as if the zeros were part of the files
+    let expected_bytes = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(piece_length))
+        .build(DirectAccessor::new("Padded.txt", &padded_content))
+        .unwrap();
+    let expected_metainfo = Metainfo::from_bytes(&expected_bytes).unwrap();
+
+    assert_eq!(expected_metainfo.info().pieces().collect::<Vec<_>>(), metainfo.info().pieces().collect::<Vec<_>>());
+    assert_eq!(metainfo.info().info_hash(), Metainfo::from_bytes(metainfo.to_bytes()).unwrap().info().info_hash());
+}
+
+/// Directory of files for a single test, which is removed when dropped.
+struct Fixture {
+    parent:    PathBuf,
+    directory: PathBuf,
+}
+
+impl Fixture {
+    /// Create a directory named `reproducible`, unique to the test, with the given files written in the given order.
+    fn new(test_name: &str, files: &[(&str, &[u8])]) -> Fixture {
+        let parent = env::temp_dir().join(format!("bip_metainfo_builder_{}_{}", test_name, process::id()));
+        let _ = fs::remove_dir_all(&parent);
+
+        let directory = parent.join("reproducible");
+        for &(relative_path, contents) in files {
+            let path = directory.join(relative_path);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        Fixture{ parent: parent, directory: directory }
+    }
+
+    fn directory(&self) -> &Path {
+        &self.directory
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.parent);
+    }
+}
+
+const FIXTURE_FILES: &'static [(&'static str, &'static [u8])] = &[("b.txt", b"second file"),
+                                                                  ("a/z.txt", b"nested last"),
+                                                                  ("a/b.txt", b"nested first"),
+                                                                  ("C.txt", b"upper case sorts first"),
+                                                                  ("c/d/e.txt", b"deeply nested")];
+
+#[test]
+fn positive_build_same_fixture_identical_bytes() {
+    let fixture = Fixture::new("identical_bytes", FIXTURE_FILES);
+    let build = || {
+        MetainfoBuilder::new()
+            .set_main_tracker(Some(TRACKER))
+            .set_creation_date(Some(DATE))
+            .set_piece_length(PieceLength::Custom(16))
+            .set_hash_threads(4)
+            .build(fixture.directory())
+            .unwrap()
+    };
+
+    assert_eq!(build(), build());
+}
+
+#[test]
+fn positive_build_independent_of_creation_order() {
+    let mut reversed_files = FIXTURE_FILES.to_vec();
+    reversed_files.reverse();
+    let forward_fixture = Fixture::new("creation_order_forward", FIXTURE_FILES);
+    let reversed_fixture = Fixture::new("creation_order_reversed", &reversed_files);
+    let (forward_directory, reversed_directory) = (forward_fixture.directory(), reversed_fixture.directory());
+
+    let build_hash = |directory: &Path| {
+        MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(16))
+            .build_with_hash(directory)
+            .unwrap()
+            .1
+    };
+    let forward_hash = build_hash(forward_directory);
+
+    assert_eq!(forward_hash, build_hash(reversed_directory));
+
+    // Building from a path walks the files in the same order as a DirectoryAccessor
+    let (_, accessor_hash) = MetainfoBuilder::new()
+        .set_piece_length(PieceLength::Custom(16))
+        .build_with_hash(DirectoryAccessor::new(reversed_directory).unwrap())
+        .unwrap();
+    assert_eq!(forward_hash, accessor_hash);
+
+    let info = Info::from_bytes(InfoBuilder::new().build(reversed_directory).unwrap()).unwrap();
+    let paths = info.files().map(|file| file.path().to_path_buf()).collect::<Vec<_>>();
+    assert_eq!(vec![PathBuf::from("C.txt"), PathBuf::from("a/b.txt"), PathBuf::from("a/z.txt"),
+                    PathBuf::from("b.txt"), PathBuf::from("c/d/e.txt")], paths);
+}
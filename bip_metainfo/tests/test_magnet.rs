@@ -0,0 +1,30 @@
+extern crate bip_magnet;
+extern crate bip_metainfo;
+
+use bip_metainfo::{MetainfoBuilder, Metainfo, DirectAccessor};
+
+const MAIN_TRACKER: &'static str = "udp://foo.bar.baz:6969";
+
+#[test]
+fn positive_to_magnet_round_trip() {
+    let trackers = vec![
+        vec![MAIN_TRACKER.to_string(), "http://foo.bar.baz/announce?key=a b".to_string()],
+        vec!["udp://other.tracker:80".to_string()]
+    ];
+    let accessor = DirectAccessor::new("File Name & More.txt", b"Some file data");
+
+    let bytes = MetainfoBuilder::new()
+        .set_main_tracker(Some(MAIN_TRACKER))
+        .set_trackers(Some(&trackers))
+        .build(accessor)
+        .unwrap();
+    let metainfo = Metainfo::from_bytes(&bytes).unwrap();
+
+    let magnet = bip_magnet::parse_magnet(&metainfo.to_magnet()).unwrap();
+
+    assert_eq!(metainfo.info().info_hash().as_ref(), magnet.get_info_hash().unwrap().as_ref());
+    assert_eq!(Some("File Name & More.txt"), magnet.display_name());
+    assert_eq!(&[MAIN_TRACKER.to_string(),
+                 "http://foo.bar.baz/announce?key=a b".to_string(),
+                 "udp://other.tracker:80".to_string()][..], magnet.trackers());
+}
@@ -0,0 +1,115 @@
+extern crate bip_metainfo;
+
+use std::path::Path;
+
+use bip_metainfo::{Info, Metainfo, MetaVersion};
+
+const V2_TORRENT: &'static [u8] = include_bytes!("fixtures/v2.torrent");
+const HYBRID_TORRENT: &'static [u8] = include_bytes!("fixtures/hybrid.torrent");
+
+const A_LENGTH: u64 = 22;
+const A_PIECES_ROOT: &'static str = "4f94a5cdf4f984a332a4be441a18857f59da2cbccec8afc2dab664239f9202a2";
+const B_LENGTH: u64 = 141;
+const B_PIECES_ROOT: &'static str = "8ead59f23a1f108678a2ac7f5d46ca119f1e37f9ec0ae0c8cedbfd90c3544422";
+
+// SHA-256 info hashes are truncated to their first 20 bytes
+const V2_INFO_HASH_V2: &'static str = "191ed1ae690b4d20bb9e52479b7ab3bb778a32fc";
+const HYBRID_INFO_HASH_V1: &'static str = "75764de73f02ce08b978416150665b7c5284ca56";
+const HYBRID_INFO_HASH_V2: &'static str = "b8d982ca832ae670927e328473cefc4f5fdf5ef3";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn assert_file_tree(metainfo: &Metainfo) {
+    let file_tree: Vec<_> = metainfo.info().file_tree().unwrap().collect();
+    assert_eq!(3, file_tree.len());
+
+    assert_eq!(Path::new("a.txt"), file_tree[0].path());
+    assert_eq!(A_LENGTH, file_tree[0].length());
+    assert_eq!(Some(A_PIECES_ROOT.to_owned()), file_tree[0].pieces_root().map(hex));
+
+    assert_eq!(Path::new("sub/a_empty.txt"), file_tree[1].path());
+    assert_eq!(0, file_tree[1].length());
+    assert_eq!(None, file_tree[1].pieces_root());
+
+    assert_eq!(Path::new("sub/b.txt"), file_tree[2].path());
+    assert_eq!(B_LENGTH, file_tree[2].length());
+    assert_eq!(Some(B_PIECES_ROOT.to_owned()), file_tree[2].pieces_root().map(hex));
+}
+
+#[test]
+fn positive_parse_v2_fixture() {
+    let metainfo = Metainfo::from_bytes(V2_TORRENT).unwrap();
+
+    assert_eq!(MetaVersion::V2, metainfo.meta_version());
+    assert!(!metainfo.info().is_hybrid());
+    assert_eq!(Some(Path::new("v2_torrent")), metainfo.info().directory());
+    assert_eq!(16384, metainfo.info().piece_length());
+    assert_eq!(0, metainfo.info().pieces().count());
+    assert_eq!(A_LENGTH + B_LENGTH, metainfo.total_length());
+
+    assert_file_tree(&metainfo);
+    assert_eq!(3, metainfo.info().files().count());
+}
+
+#[test]
+fn positive_parse_hybrid_fixture() {
+    let metainfo = Metainfo::from_bytes(HYBRID_TORRENT).unwrap();
+
+    assert_eq!(MetaVersion::Hybrid, metainfo.meta_version());
+    assert!(metainfo.info().is_hybrid());
+    assert_eq!(Some(Path::new("hybrid_torrent")), metainfo.info().directory());
+    assert_eq!(2, metainfo.info().pieces().count());
+
+    assert_file_tree(&metainfo);
+
+    // The v1 file list includes the padding file, which aligns the next file to a piece boundary
+    let files: Vec<_> = metainfo.info().files().collect();
+    assert_eq!(4, files.len());
+    assert_eq!(Path::new(".pad/16362"), files[1].path());
+    assert_eq!(16384 + B_LENGTH, metainfo.total_length());
+}
+
+#[test]
+fn positive_v2_fixture_info_hashes() {
+    let metainfo = Metainfo::from_bytes(V2_TORRENT).unwrap();
+
+    assert_eq!(None, metainfo.info_hash_v1());
+    assert_eq!(Some(V2_INFO_HASH_V2.to_owned()), metainfo.info_hash_v2().map(|hash| hex(hash.as_ref())));
+    assert_eq!(metainfo.info_hash_v2(), Some(metainfo.info().info_hash()));
+}
+
+#[test]
+fn positive_hybrid_fixture_info_hashes() {
+    let metainfo = Metainfo::from_bytes(HYBRID_TORRENT).unwrap();
+
+    assert_eq!(Some(HYBRID_INFO_HASH_V1.to_owned()), metainfo.info_hash_v1().map(|hash| hex(hash.as_ref())));
+    assert_eq!(Some(HYBRID_INFO_HASH_V2.to_owned()), metainfo.info_hash_v2().map(|hash| hex(hash.as_ref())));
+    assert_eq!(metainfo.info_hash_v1(), Some(metainfo.info().info_hash()));
+}
+
+/// Re-encode the fixture and check that it parses back with the same version and info hashes.
+fn assert_round_trip(torrent: &[u8]) {
+    let metainfo = Metainfo::from_bytes(torrent).unwrap();
+    let round_trip = Metainfo::from_bytes(metainfo.to_bytes()).unwrap();
+
+    assert_eq!(metainfo.meta_version(), round_trip.meta_version());
+    assert_eq!(metainfo.info_hash_v1(), round_trip.info_hash_v1());
+    assert_eq!(metainfo.info_hash_v2(), round_trip.info_hash_v2());
+    assert_eq!(metainfo.info().info_hash(), round_trip.info().info_hash());
+    assert_eq!(metainfo, round_trip);
+
+    let info_round_trip = Info::from_bytes(metainfo.info().to_bytes()).unwrap();
+    assert_eq!(metainfo.info(), &info_round_trip);
+}
+
+#[test]
+fn positive_v2_fixture_round_trip() {
+    assert_round_trip(V2_TORRENT);
+}
+
+#[test]
+fn positive_hybrid_fixture_round_trip() {
+    assert_round_trip(HYBRID_TORRENT);
+}
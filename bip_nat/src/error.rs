@@ -0,0 +1,41 @@
+//! Errors for port mapping.
+
+use std::io;
+
+error_chain! {
+    types {
+        NatError, NatErrorKind, NatResultEx, NatResult;
+    }
+
+    foreign_links {
+        Io(io::Error);
+    }
+
+    errors {
+        InvalidResponse {
+            details: String
+        } {
+            description("Gateway Sent An Invalid Response")
+            display("Gateway Sent An Invalid Response: {}", details)
+        }
+        GatewayRejected {
+            code:    u16,
+            details: String
+        } {
+            description("Gateway Rejected The Request")
+            display("Gateway Rejected The Request With Code {}: {}", code, details)
+        }
+        NoGatewayFound {
+            details: String
+        } {
+            description("Failed To Find A Gateway")
+            display("Failed To Find A Gateway: {}", details)
+        }
+        MappingFailed {
+            details: String
+        } {
+            description("Failed To Map The Port With Any Method")
+            display("Failed To Map The Port With Any Method: {}", details)
+        }
+    }
+}
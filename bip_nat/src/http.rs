@@ -0,0 +1,185 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str;
+use std::time::Duration;
+
+use error::{NatError, NatErrorKind, NatResult};
+
+/// Location of a resource on a device, split out of an http url.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HttpUrl {
+    addr: SocketAddr,
+    path: String
+}
+
+impl HttpUrl {
+    /// Parse the given http url, which must have an ip address (or resolvable host) and may have a port and a path.
+    pub fn parse(url: &str) -> NatResult<HttpUrl> {
+        let rest = try!(strip_prefix_ignore_case(url.trim(), "http://")
+            .ok_or_else(|| invalid_response(format!("Url {:?} Is Not An Http Url", url))));
+
+        let (host, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None        => (rest, "/")
+        };
+        let host = if host.contains(':') { host.to_owned() } else { format!("{}:80", host) };
+
+        let addr = try!(try!(host.to_socket_addrs()).next()
+            .ok_or_else(|| invalid_response(format!("Url {:?} Has No Address", url))));
+
+        Ok(HttpUrl{ addr: addr, path: path.to_owned() })
+    }
+
+    /// Resolve the given url, which may be relative to this one, or absolute.
+    pub fn join(&self, url: &str) -> NatResult<HttpUrl> {
+        let url = url.trim();
+
+        if strip_prefix_ignore_case(url, "http://").is_some() {
+            HttpUrl::parse(url)
+        } else if url.starts_with('/') {
+            Ok(HttpUrl{ addr: self.addr, path: url.to_owned() })
+        } else {
+            Ok(HttpUrl{ addr: self.addr, path: format!("/{}", url) })
+        }
+    }
+
+    /// Address of the device.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Path of the resource on the device.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Send a request for the given url, returning the status code and body of the response.
+///
+/// Each request is made over a new connection which is closed by the device after it responds.
+pub fn request(method: &str, url: &HttpUrl, headers: &[(&str, &str)], body: &str, timeout: Duration) -> NatResult<(u16, String)> {
+    let mut stream = try!(TcpStream::connect(url.addr()));
+    try!(stream.set_read_timeout(Some(timeout)));
+    try!(stream.set_write_timeout(Some(timeout)));
+
+    let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+                              method, url.path(), url.addr(), body.len());
+    for &(name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+
+    try!(stream.write_all(request.as_bytes()));
+
+    let mut response = Vec::new();
+    try!(stream.read_to_end(&mut response));
+
+    parse_response(&response)
+}
+
+/// Parse the status code and body out of the given response, decoding a chunked body if necessary.
+fn parse_response(response: &[u8]) -> NatResult<(u16, String)> {
+    let header_end = try!(find_bytes(response, b"\r\n\r\n")
+        .ok_or_else(|| invalid_response("Response Has No End Of Headers".to_owned())));
+    let head = try!(str::from_utf8(&response[..header_end])
+        .map_err(|_| invalid_response("Response Headers Are Not Utf-8".to_owned())));
+    let raw_body = &response[(header_end + 4)..];
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or("");
+    let status = try!(status_line.split_whitespace().nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid_response(format!("Response Has Invalid Status Line {:?}", status_line))));
+
+    let is_chunked = lines.filter_map(|line| split_header(line))
+        .any(|(name, value)| name.eq_ignore_ascii_case("Transfer-Encoding") && value.eq_ignore_ascii_case("chunked"));
+    let body = if is_chunked { try!(decode_chunked(raw_body)) } else { raw_body.to_vec() };
+
+    let body = try!(String::from_utf8(body)
+        .map_err(|_| invalid_response("Response Body Is Not Utf-8".to_owned())));
+
+    Ok((status, body))
+}
+
+/// Decode the given chunked body, ignoring any trailers.
+fn decode_chunked(mut raw_body: &[u8]) -> NatResult<Vec<u8>> {
+    let mut body = Vec::new();
+
+    loop {
+        let line_end = try!(find_bytes(raw_body, b"\r\n")
+            .ok_or_else(|| invalid_response("Chunk Has No Size".to_owned())));
+        let size_line = String::from_utf8_lossy(&raw_body[..line_end]);
+        let size = try!(usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| invalid_response(format!("Chunk Has Invalid Size {:?}", size_line))));
+
+        let chunk_start = line_end + 2;
+        if size == 0 {
+            return Ok(body)
+        } else if raw_body.len() < chunk_start + size {
+            return Err(invalid_response("Chunk Is Truncated".to_owned()))
+        }
+
+        body.extend_from_slice(&raw_body[chunk_start..(chunk_start + size)]);
+        raw_body = &raw_body[cmp_min(chunk_start + size + 2, raw_body.len())..];
+    }
+}
+
+/// Split the given header line in to its name and value.
+pub fn split_header(line: &str) -> Option<(&str, &str)> {
+    line.find(':').map(|index| (line[..index].trim(), line[(index + 1)..].trim()))
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    if value.len() >= prefix.len() && value.is_char_boundary(prefix.len()) && value[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        Some(&value[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+fn cmp_min(a: usize, b: usize) -> usize {
+    if a < b { a } else { b }
+}
+
+fn invalid_response(details: String) -> NatError {
+    NatError::from_kind(NatErrorKind::InvalidResponse{ details: details })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpUrl;
+
+    #[test]
+    fn positive_parse_url_with_port_and_path() {
+        let url = HttpUrl::parse("http://192.168.1.1:5000/rootDesc.xml").unwrap();
+
+        assert_eq!("192.168.1.1:5000".parse(), Ok(url.addr()));
+        assert_eq!("/rootDesc.xml", url.path());
+    }
+
+    #[test]
+    fn positive_join_relative_and_absolute_urls() {
+        let url = HttpUrl::parse("HTTP://192.168.1.1:5000/rootDesc.xml").unwrap();
+
+        assert_eq!("/ctl/IPConn", url.join("/ctl/IPConn").unwrap().path());
+        assert_eq!("/ctl/IPConn", url.join("ctl/IPConn").unwrap().path());
+        assert_eq!("192.168.1.2:80".parse(), Ok(url.join("http://192.168.1.2/ctl").unwrap().addr()));
+    }
+
+    #[test]
+    fn positive_parse_chunked_response() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+        assert_eq!((200, "hello world".to_owned()), super::parse_response(response).unwrap());
+    }
+
+    #[test]
+    fn negative_parse_response_without_headers_end() {
+        assert!(super::parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+//! Library for opening our listen port on home routers, using UPnP IGD or NAT-PMP.
+//!
+//! A `PortMapper` is given the port we are listening on and tries a UPnP internet
+//! gateway device first, then a NAT-PMP gateway, to map the port for tcp and udp (utp).
+//! Failing to map the port is not fatal to a client, so every failure is reported
+//! back instead of panicking, and the mapping that succeeds is handed back as a
+//! `PortMapping` which renews the lease in the background and removes the mapping
+//! from the gateway when it is dropped.
+
+#[macro_use]
+extern crate error_chain;
+
+mod http;
+mod mapping;
+mod natpmp;
+mod upnp;
+
+pub mod error;
+
+pub use mapping::{MappingMethod, PortMapper, PortMapping, Protocol};
+pub use natpmp::NatPmpGateway;
+pub use upnp::Gateway;
@@ -0,0 +1,458 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use error::{NatError, NatErrorKind, NatResult};
+use natpmp::NatPmpGateway;
+use upnp::Gateway;
+
+const DEFAULT_LEASE_SECS:     u32          = 3600;
+const DEFAULT_TIMEOUT_MILLIS: u64          = 2000;
+const DEFAULT_DESCRIPTION:    &'static str = "bip-rs";
+
+/// Renewals happen at most this often, regardless of how short the lease is.
+const MIN_RENEWAL_SECS: u32 = 1;
+
+/// Protocol of a port mapping.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Protocol {
+    /// Mapping for tcp peer connections.
+    Tcp,
+    /// Mapping for utp peer connections, and anything else over udp.
+    Udp
+}
+
+/// Method used to map a port.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MappingMethod {
+    /// Mapped through an internet gateway device.
+    Upnp,
+    /// Mapped through NAT-PMP.
+    NatPmp
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Opens our listen port on the gateway, trying UPnP IGD and then NAT-PMP.
+#[derive(Clone, Debug)]
+pub struct PortMapper {
+    port:           u16,
+    lease:          u32,
+    description:    String,
+    protocols:      Vec<Protocol>,
+    timeout:        Duration,
+    upnp:           bool,
+    upnp_location:  Option<String>,
+    natpmp:         bool,
+    natpmp_gateway: Option<SocketAddr>
+}
+
+impl PortMapper {
+    /// Create a new PortMapper for both the tcp and udp listen port.
+    pub fn new(port: u16) -> PortMapper {
+        PortMapper{ port: port, lease: DEFAULT_LEASE_SECS, description: DEFAULT_DESCRIPTION.to_owned(),
+                    protocols: vec![Protocol::Tcp, Protocol::Udp], timeout: Duration::from_millis(DEFAULT_TIMEOUT_MILLIS),
+                    upnp: true, upnp_location: None, natpmp: true, natpmp_gateway: None }
+    }
+
+    /// Set the lease duration, in seconds, that mappings are requested and renewed with.
+    ///
+    /// A lease of zero requests a permanent mapping, which is never renewed.
+    pub fn with_lease(mut self, lease: u32) -> PortMapper {
+        self.lease = lease;
+
+        self
+    }
+
+    /// Set the description that UPnP gateways show for the mappings.
+    pub fn with_description<S>(mut self, description: S) -> PortMapper
+        where S: Into<String> {
+        self.description = description.into();
+
+        self
+    }
+
+    /// Set the protocols to map the port for.
+    pub fn with_protocols(mut self, protocols: &[Protocol]) -> PortMapper {
+        self.protocols = protocols.to_vec();
+
+        self
+    }
+
+    /// Set the timeout for discovering, and waiting on responses from, a UPnP gateway.
+    pub fn with_timeout(mut self, timeout: Duration) -> PortMapper {
+        self.timeout = timeout;
+
+        self
+    }
+
+    /// Enable or disable mapping through UPnP.
+    pub fn with_upnp(mut self, enabled: bool) -> PortMapper {
+        self.upnp = enabled;
+
+        self
+    }
+
+    /// Use the UPnP gateway described at the given location instead of searching for one.
+    pub fn with_upnp_location<S>(mut self, location: S) -> PortMapper
+        where S: Into<String> {
+        self.upnp_location = Some(location.into());
+
+        self
+    }
+
+    /// Enable or disable mapping through NAT-PMP.
+    pub fn with_natpmp(mut self, enabled: bool) -> PortMapper {
+        self.natpmp = enabled;
+
+        self
+    }
+
+    /// Use the NAT-PMP gateway at the given address instead of guessing it.
+    pub fn with_natpmp_gateway(mut self, addr: SocketAddr) -> PortMapper {
+        self.natpmp_gateway = Some(addr);
+
+        self
+    }
+
+    /// Map the port with the first method that succeeds.
+    ///
+    /// Failures of methods tried before the one that succeeded are kept on the returned mapping.
+    pub fn map(&self) -> NatResult<PortMapping> {
+        let mut failures = Vec::new();
+
+        if self.upnp {
+            match self.map_upnp() {
+                Ok(mapped) => return Ok(PortMapping::new(mapped, self, failures)),
+                Err(error) => failures.push((MappingMethod::Upnp, error))
+            }
+        }
+
+        if self.natpmp {
+            match self.map_natpmp() {
+                Ok(mapped) => return Ok(PortMapping::new(mapped, self, failures)),
+                Err(error) => failures.push((MappingMethod::NatPmp, error))
+            }
+        }
+
+        let details = if failures.is_empty() {
+            "No Methods Are Enabled".to_owned()
+        } else {
+            failures.iter()
+                .map(|&(method, ref error)| format!("{:?} Failed With {}", method, error))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        Err(NatError::from_kind(NatErrorKind::MappingFailed{ details: details }))
+    }
+
+    fn map_upnp(&self) -> NatResult<Mapped> {
+        let gateway = match self.upnp_location {
+            Some(ref location) => try!(Gateway::from_location(location, self.timeout)),
+            None               => try!(Gateway::discover(self.timeout))
+        };
+        let internal = SocketAddrV4::new(try!(gateway.local_ip()), self.port);
+
+        let backend = Backend::Upnp(gateway, internal);
+        let lease = try!(backend.add_all(&self.protocols, self.port, self.lease, &self.description));
+        let external_ip = match backend {
+            Backend::Upnp(ref gateway, _) => gateway.external_ip(),
+            Backend::NatPmp(..)           => unreachable!()
+        };
+
+        match external_ip {
+            Ok(ip) => Ok(Mapped{ backend: backend, external: SocketAddrV4::new(ip, self.port), lease: lease }),
+            Err(error) => {
+                backend.remove_all(&self.protocols, self.port);
+
+                Err(error)
+            }
+        }
+    }
+
+    fn map_natpmp(&self) -> NatResult<Mapped> {
+        let gateway = match self.natpmp_gateway {
+            Some(addr) => NatPmpGateway::new(addr),
+            None       => try!(NatPmpGateway::guess())
+        };
+
+        let external_ip = try!(gateway.external_ip());
+        let mut external_port = self.port;
+        let mut lease = self.lease;
+        for (index, &protocol) in self.protocols.iter().enumerate() {
+            match gateway.add_port_mapping(protocol, self.port, self.port, self.lease) {
+                // The external port reported is the one for the first protocol that was mapped
+                Ok((port, granted)) => if index == 0 {
+                    external_port = port;
+                    lease = granted;
+                },
+                Err(error) => {
+                    Backend::NatPmp(gateway, external_port).remove_all(&self.protocols[..index], self.port);
+
+                    return Err(error)
+                }
+            }
+        }
+
+        Ok(Mapped{ backend: Backend::NatPmp(gateway, external_port), external: SocketAddrV4::new(external_ip, external_port),
+                   lease: lease })
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Mapping that was opened on the gateway, along with the gateway that opened it.
+struct Mapped {
+    backend:  Backend,
+    external: SocketAddrV4,
+    lease:    u32
+}
+
+/// Gateway that holds a mapping.
+enum Backend {
+    /// Gateway along with the internal address mapped to.
+    Upnp(Gateway, SocketAddrV4),
+    /// Gateway along with the external port that was granted.
+    NatPmp(NatPmpGateway, u16)
+}
+
+impl Backend {
+    /// Add a mapping for each protocol, returning the shortest lease granted.
+    ///
+    /// If any protocol fails, mappings for the protocols before it are removed.
+    fn add_all(&self, protocols: &[Protocol], port: u16, lease: u32, description: &str) -> NatResult<u32> {
+        let mut granted_lease = lease;
+
+        for (index, &protocol) in protocols.iter().enumerate() {
+            let result = match *self {
+                Backend::Upnp(ref gateway, internal) => gateway.add_port_mapping(protocol, port, internal, lease, description),
+                Backend::NatPmp(ref gateway, external_port) => {
+                    gateway.add_port_mapping(protocol, port, external_port, lease).map(|(_, granted)| granted)
+                }
+            };
+
+            match result {
+                Ok(granted) => granted_lease = shortest_lease(granted_lease, granted),
+                Err(error)  => {
+                    self.remove_all(&protocols[..index], port);
+
+                    return Err(error)
+                }
+            }
+        }
+
+        Ok(granted_lease)
+    }
+
+    /// Remove the mapping for each protocol, returning the last error seen.
+    fn remove_all(&self, protocols: &[Protocol], port: u16) -> Option<NatError> {
+        let mut last_error = None;
+
+        for &protocol in protocols {
+            let result = match *self {
+                Backend::Upnp(ref gateway, _)    => gateway.remove_port_mapping(protocol, port),
+                Backend::NatPmp(ref gateway, _) => gateway.remove_port_mapping(protocol, port)
+            };
+
+            if let Err(error) = result {
+                last_error = Some(error);
+            }
+        }
+
+        last_error
+    }
+
+    fn method(&self) -> MappingMethod {
+        match *self {
+            Backend::Upnp(..)   => MappingMethod::Upnp,
+            Backend::NatPmp(..) => MappingMethod::NatPmp
+        }
+    }
+}
+
+/// Shortest of the two leases, where a lease of zero is permanent.
+fn shortest_lease(a: u32, b: u32) -> u32 {
+    match (a, b) {
+        (0, lease) | (lease, 0) => lease,
+        (a, b)                  => if a < b { a } else { b }
+    }
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Lease on a port mapping, which is renewed in the background and removed from the gateway on drop.
+pub struct PortMapping {
+    external:      SocketAddrV4,
+    method:        MappingMethod,
+    failures:      Vec<(MappingMethod, NatError)>,
+    renewal_error: Arc<Mutex<Option<NatError>>>,
+    stop:          Option<Sender<()>>,
+    handle:        Option<JoinHandle<()>>
+}
+
+impl PortMapping {
+    fn new(mapped: Mapped, mapper: &PortMapper, failures: Vec<(MappingMethod, NatError)>) -> PortMapping {
+        let (stop_send, stop_recv) = mpsc::channel();
+        let renewal_error = Arc::new(Mutex::new(None));
+
+        let (method, external) = (mapped.backend.method(), mapped.external);
+        let thread_error = renewal_error.clone();
+        let protocols = mapper.protocols.clone();
+        let (port, lease, description) = (mapper.port, mapper.lease, mapper.description.clone());
+        let handle = thread::spawn(move || {
+            let Mapped{ backend, lease: mut granted_lease, .. } = mapped;
+
+            loop {
+                // Permanent leases never need renewing, so we just wait to be stopped
+                let stopped = if granted_lease == 0 {
+                    let _ = stop_recv.recv();
+                    true
+                } else {
+                    let renewal = Duration::from_secs(cmp_max(granted_lease / 2, MIN_RENEWAL_SECS) as u64);
+
+                    match stop_recv.recv_timeout(renewal) {
+                        Err(RecvTimeoutError::Timeout) => false,
+                        _                              => true
+                    }
+                };
+
+                if stopped {
+                    break
+                }
+
+                // Failed renewals are retried at the same interval, since the old lease may still be live
+                match backend.add_all(&protocols, port, lease, &description) {
+                    Ok(granted) => granted_lease = granted,
+                    Err(error)  => *thread_error.lock().unwrap() = Some(error)
+                }
+            }
+
+            if let Some(error) = backend.remove_all(&protocols, port) {
+                *thread_error.lock().unwrap() = Some(error);
+            }
+        });
+
+        PortMapping{ external: external, method: method, failures: failures, renewal_error: renewal_error,
+                     stop: Some(stop_send), handle: Some(handle) }
+    }
+
+    /// External address that peers can reach our listen port on.
+    pub fn external_addr(&self) -> SocketAddrV4 {
+        self.external
+    }
+
+    /// External ip address of the gateway.
+    pub fn external_ip(&self) -> Ipv4Addr {
+        *self.external.ip()
+    }
+
+    /// Method that the port was mapped with.
+    pub fn method(&self) -> MappingMethod {
+        self.method
+    }
+
+    /// Failures of the methods tried before the one that mapped the port.
+    pub fn failures(&self) -> &[(MappingMethod, NatError)] {
+        &self.failures
+    }
+
+    /// Take the error from the most recent failed renewal, if any.
+    pub fn take_renewal_error(&self) -> Option<NatError> {
+        self.renewal_error.lock().unwrap().take()
+    }
+}
+
+impl Drop for PortMapping {
+    fn drop(&mut self) {
+        // Dropping the sender wakes up the renewal thread, which removes the mappings before exiting
+        self.stop.take();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn cmp_max(a: u32, b: u32) -> u32 {
+    if a > b { a } else { b }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use natpmp::tests::MockNatPmp;
+    use upnp::tests::{self, MockGateway};
+    use super::{MappingMethod, PortMapper, Protocol};
+
+    fn unused_addr() -> SocketAddr {
+        // Bind then drop, so that nothing answers on the address
+        ::std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    #[test]
+    fn positive_map_through_upnp_and_remove_on_drop() {
+        let mock = MockGateway::start(false);
+
+        {
+            let mapping = PortMapper::new(6881).with_upnp_location(mock.location()).with_natpmp(false).map().unwrap();
+
+            assert_eq!(MappingMethod::Upnp, mapping.method());
+            assert_eq!(format!("{}:6881", tests::EXTERNAL_IP), mapping.external_addr().to_string());
+            assert!(mapping.failures().is_empty());
+        }
+
+        let actions: Vec<String> = mock.requests().into_iter()
+            .filter(|&(ref path, _, _)| path == "/ctl/IPConn")
+            .map(|(_, action, body)| {
+                let action = action.rsplit('#').next().unwrap().trim_matches('"').to_owned();
+                let protocol = if body.contains("<NewProtocol>TCP<") { "TCP" } else { "UDP" };
+
+                format!("{} {}", action, protocol)
+            })
+            .collect();
+        assert_eq!(vec!["AddPortMapping TCP", "AddPortMapping UDP", "GetExternalIPAddress UDP",
+                        "DeletePortMapping TCP", "DeletePortMapping UDP"], actions);
+    }
+
+    #[test]
+    fn positive_falls_back_to_natpmp_and_reports_upnp_failure() {
+        let mock = MockNatPmp::start(false);
+
+        let mapping = PortMapper::new(6881)
+            .with_protocols(&[Protocol::Tcp])
+            .with_timeout(Duration::from_millis(500))
+            .with_upnp_location(format!("http://{}/rootDesc.xml", unused_addr()))
+            .with_natpmp_gateway(mock.gateway().addr())
+            .map().unwrap();
+
+        assert_eq!(MappingMethod::NatPmp, mapping.method());
+        assert_eq!("198.51.100.9:6882", mapping.external_addr().to_string());
+        assert_eq!(1, mapping.failures().len());
+        assert_eq!(MappingMethod::Upnp, mapping.failures()[0].0);
+    }
+
+    #[test]
+    fn negative_map_fails_with_every_method() {
+        let mock = MockNatPmp::start(true);
+
+        let result = PortMapper::new(6881)
+            .with_upnp_location(format!("http://{}/rootDesc.xml", unused_addr()))
+            .with_natpmp_gateway(mock.gateway().addr())
+            .map();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn positive_shortest_lease_treats_zero_as_permanent() {
+        assert_eq!(30, super::shortest_lease(0, 30));
+        assert_eq!(30, super::shortest_lease(30, 0));
+        assert_eq!(20, super::shortest_lease(30, 20));
+        assert_eq!(0, super::shortest_lease(0, 0));
+    }
+}
@@ -0,0 +1,280 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use error::{NatError, NatErrorKind, NatResult};
+use mapping::Protocol;
+
+/// Port that gateways listen for NAT-PMP requests on.
+pub const NAT_PMP_PORT: u16 = 5351;
+
+const NAT_PMP_VERSION: u8 = 0;
+
+const EXTERNAL_ADDRESS_OPCODE: u8 = 0;
+const MAP_UDP_OPCODE:          u8 = 1;
+const MAP_TCP_OPCODE:          u8 = 2;
+
+/// Added to the opcode of a request to get the opcode of its response.
+const RESPONSE_OPCODE_OFFSET: u8 = 128;
+
+const EXTERNAL_ADDRESS_RESPONSE_LEN: usize = 12;
+const MAP_RESPONSE_LEN:              usize = 16;
+
+const DEFAULT_INITIAL_TIMEOUT_MILLIS: u64 = 250;
+const DEFAULT_MAX_ATTEMPTS:           u32 = 4;
+
+/// Gateway which maps ports using NAT-PMP, as specified in RFC 6886.
+#[derive(Copy, Clone, Debug)]
+pub struct NatPmpGateway {
+    addr:            SocketAddr,
+    initial_timeout: Duration,
+    max_attempts:    u32
+}
+
+impl NatPmpGateway {
+    /// Create a gateway for the given address.
+    pub fn new(addr: SocketAddr) -> NatPmpGateway {
+        NatPmpGateway{ addr: addr, initial_timeout: Duration::from_millis(DEFAULT_INITIAL_TIMEOUT_MILLIS),
+                       max_attempts: DEFAULT_MAX_ATTEMPTS }
+    }
+
+    /// Guess the gateway address, assuming it is the first address on our local subnet.
+    ///
+    /// NAT-PMP expects the gateway to be our default route, which is not exposed by the standard library,
+    /// but home routers almost always sit at the first address of the subnet they hand out.
+    pub fn guess() -> NatResult<NatPmpGateway> {
+        // Connecting a udp socket sends nothing, but tells us the local address of our default route
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        try!(socket.connect("198.51.100.1:9"));
+
+        match try!(socket.local_addr()).ip() {
+            IpAddr::V4(ip) if ip.is_private() => {
+                let octets = ip.octets();
+                let gateway_ip = Ipv4Addr::new(octets[0], octets[1], octets[2], 1);
+
+                Ok(NatPmpGateway::new(SocketAddr::new(IpAddr::V4(gateway_ip), NAT_PMP_PORT)))
+            },
+            ip => Err(NatError::from_kind(NatErrorKind::NoGatewayFound{
+                details: format!("Local Address {} Is Not On A Private Network", ip)
+            }))
+        }
+    }
+
+    /// Set the timeout for the first attempt of each request, which is doubled on every retry.
+    pub fn with_retries(mut self, initial_timeout: Duration, max_attempts: u32) -> NatPmpGateway {
+        self.initial_timeout = initial_timeout;
+        self.max_attempts = max_attempts;
+
+        self
+    }
+
+    /// Address of the gateway.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Ask the gateway for its external address.
+    pub fn external_ip(&self) -> NatResult<Ipv4Addr> {
+        let response = try!(self.request(&[NAT_PMP_VERSION, EXTERNAL_ADDRESS_OPCODE], EXTERNAL_ADDRESS_RESPONSE_LEN));
+
+        Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+    }
+
+    /// Map our internal port to the suggested external port, returning the external port and lease that were granted.
+    ///
+    /// The gateway is free to pick a different external port than the one suggested.
+    pub fn add_port_mapping(&self, protocol: Protocol, internal_port: u16, external_port: u16, lease: u32) -> NatResult<(u16, u32)> {
+        let mut request = [0u8; 12];
+        request[0] = NAT_PMP_VERSION;
+        request[1] = map_opcode(protocol);
+        write_u16(&mut request[4..6], internal_port);
+        write_u16(&mut request[6..8], external_port);
+        write_u32(&mut request[8..12], lease);
+
+        let response = try!(self.request(&request, MAP_RESPONSE_LEN));
+        if read_u16(&response[8..10]) != internal_port {
+            return Err(invalid_response(format!("Mapping Response Is For Internal Port {} Instead Of {}",
+                                                read_u16(&response[8..10]), internal_port)))
+        }
+
+        Ok((read_u16(&response[10..12]), read_u32(&response[12..16])))
+    }
+
+    /// Remove the mapping for our internal port.
+    pub fn remove_port_mapping(&self, protocol: Protocol, internal_port: u16) -> NatResult<()> {
+        self.add_port_mapping(protocol, internal_port, 0, 0).map(|_| ())
+    }
+
+    /// Send the request, retrying with doubling timeouts, and validate the header of the response.
+    fn request(&self, request: &[u8], response_len: usize) -> NatResult<Vec<u8>> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        try!(socket.connect(self.addr));
+
+        let mut buffer = [0u8; 64];
+        let mut timeout = self.initial_timeout;
+        for _ in 0..self.max_attempts {
+            try!(socket.send(request));
+            try!(socket.set_read_timeout(Some(timeout)));
+
+            match socket.recv(&mut buffer) {
+                Ok(bytes_read) => return validate_response(request[1], &buffer[..bytes_read], response_len),
+                Err(_)         => timeout = timeout * 2
+            }
+        }
+
+        Err(NatError::from_kind(NatErrorKind::NoGatewayFound{
+            details: format!("Gateway At {} Did Not Respond After {} Attempts", self.addr, self.max_attempts)
+        }))
+    }
+}
+
+fn validate_response(opcode: u8, response: &[u8], response_len: usize) -> NatResult<Vec<u8>> {
+    if response.len() < 4 {
+        return Err(invalid_response(format!("Response Of Length {} Is Too Short", response.len())))
+    } else if response[0] != NAT_PMP_VERSION {
+        return Err(invalid_response(format!("Response Has Unknown Version {}", response[0])))
+    } else if response[1] != opcode + RESPONSE_OPCODE_OFFSET {
+        return Err(invalid_response(format!("Response Has Opcode {} For Request Opcode {}", response[1], opcode)))
+    }
+
+    let code = read_u16(&response[2..4]);
+    let details = match code {
+        0 => None,
+        1 => Some("Unsupported Version"),
+        2 => Some("Not Authorized Or Refused"),
+        3 => Some("Network Failure"),
+        4 => Some("Out Of Resources"),
+        5 => Some("Unsupported Opcode"),
+        _ => Some("Unknown Result Code")
+    };
+    if let Some(details) = details {
+        return Err(NatError::from_kind(NatErrorKind::GatewayRejected{ code: code, details: details.to_owned() }))
+    } else if response.len() < response_len {
+        return Err(invalid_response(format!("Response Of Length {} Is Shorter Than {}", response.len(), response_len)))
+    }
+
+    Ok(response[..response_len].to_vec())
+}
+
+fn map_opcode(protocol: Protocol) -> u8 {
+    match protocol {
+        Protocol::Udp => MAP_UDP_OPCODE,
+        Protocol::Tcp => MAP_TCP_OPCODE
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    (bytes[0] as u16) << 8 | bytes[1] as u16
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    (read_u16(&bytes[0..2]) as u32) << 16 | read_u16(&bytes[2..4]) as u32
+}
+
+fn write_u16(bytes: &mut [u8], value: u16) {
+    bytes[0] = (value >> 8) as u8;
+    bytes[1] = value as u8;
+}
+
+fn write_u32(bytes: &mut [u8], value: u32) {
+    write_u16(&mut bytes[0..2], (value >> 16) as u16);
+    write_u16(&mut bytes[2..4], value as u16);
+}
+
+fn invalid_response(details: String) -> NatError {
+    NatError::from_kind(NatErrorKind::InvalidResponse{ details: details })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use error::NatErrorKind;
+    use mapping::Protocol;
+    use super::NatPmpGateway;
+
+    /// Gateway which answers NAT-PMP requests, optionally refusing mappings, and records each request.
+    pub struct MockNatPmp {
+        addr:     SocketAddr,
+        requests: Arc<Mutex<Vec<Vec<u8>>>>
+    }
+
+    impl MockNatPmp {
+        pub fn start(refuse: bool) -> MockNatPmp {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let thread_requests = requests.clone();
+            thread::spawn(move || {
+                let mut buffer = [0u8; 64];
+                while let Ok((bytes_read, from)) = socket.recv_from(&mut buffer) {
+                    let request = buffer[..bytes_read].to_vec();
+                    thread_requests.lock().unwrap().push(request.clone());
+
+                    let mut response = vec![0, request[1] + 128, 0, if refuse { 2 } else { 0 }, 0, 0, 0, 1];
+                    if request[1] == 0 {
+                        response.extend_from_slice(&[198, 51, 100, 9]);
+                    } else {
+                        // Internal port, then an external port one higher than suggested, then the lease
+                        let external_port = ((request[6] as u16) << 8 | request[7] as u16).wrapping_add(1);
+                        response.extend_from_slice(&request[4..6]);
+                        response.extend_from_slice(&[(external_port >> 8) as u8, external_port as u8]);
+                        response.extend_from_slice(&request[8..12]);
+                    }
+
+                    socket.send_to(&response, from).unwrap();
+                }
+            });
+
+            MockNatPmp{ addr: addr, requests: requests }
+        }
+
+        pub fn gateway(&self) -> NatPmpGateway {
+            NatPmpGateway::new(self.addr).with_retries(Duration::from_millis(500), 2)
+        }
+
+        pub fn requests(&self) -> Vec<Vec<u8>> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn positive_external_ip() {
+        let mock = MockNatPmp::start(false);
+
+        assert_eq!("198.51.100.9".parse(), Ok(mock.gateway().external_ip().unwrap()));
+    }
+
+    #[test]
+    fn positive_add_port_mapping() {
+        let mock = MockNatPmp::start(false);
+
+        assert_eq!((6882, 7200), mock.gateway().add_port_mapping(Protocol::Tcp, 6881, 6881, 7200).unwrap());
+        assert_eq!(vec![vec![0, 2, 0, 0, 0x1A, 0xE1, 0x1A, 0xE1, 0, 0, 0x1C, 0x20]], mock.requests());
+    }
+
+    #[test]
+    fn negative_add_port_mapping_refused() {
+        let mock = MockNatPmp::start(true);
+
+        match mock.gateway().add_port_mapping(Protocol::Udp, 6881, 6881, 7200).unwrap_err().kind() {
+            &NatErrorKind::GatewayRejected{ code, .. } => assert_eq!(2, code),
+            other                                      => panic!("Unexpected Error {:?}", other)
+        }
+    }
+
+    #[test]
+    fn negative_no_response_from_gateway() {
+        // Nothing is listening on the socket, so the requests go unanswered
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let gateway = NatPmpGateway::new(socket.local_addr().unwrap()).with_retries(Duration::from_millis(10), 2);
+
+        match gateway.external_ip().unwrap_err().kind() {
+            &NatErrorKind::NoGatewayFound{ .. } => (),
+            other                               => panic!("Unexpected Error {:?}", other)
+        }
+    }
+}
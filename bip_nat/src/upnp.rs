@@ -0,0 +1,433 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::str;
+use std::time::{Duration, Instant};
+
+use error::{NatError, NatErrorKind, NatResult};
+use http::{self, HttpUrl};
+use mapping::Protocol;
+
+const SSDP_MULTICAST_ADDR: &'static str = "239.255.255.250:1900";
+const SSDP_SEARCH_TARGET:  &'static str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+
+const WAN_IP_CONNECTION:  &'static str = "urn:schemas-upnp-org:service:WANIPConnection:";
+const WAN_PPP_CONNECTION: &'static str = "urn:schemas-upnp-org:service:WANPPPConnection:";
+
+/// Error code sent back by gateways that only support permanent leases.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+
+/// Connection service on an internet gateway device, as specified in UPnP IGD.
+#[derive(Clone, Debug)]
+pub struct Gateway {
+    control:      HttpUrl,
+    service_type: String,
+    timeout:      Duration
+}
+
+impl Gateway {
+    /// Search the local network for an internet gateway device, waiting up to timeout for responses.
+    pub fn discover(timeout: Duration) -> NatResult<Gateway> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        let search = format!("M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+                             SSDP_MULTICAST_ADDR, SSDP_SEARCH_TARGET);
+        try!(socket.send_to(search.as_bytes(), SSDP_MULTICAST_ADDR));
+
+        let start = Instant::now();
+        let mut buffer = [0u8; 2048];
+        let mut last_error = None;
+        while let Some(remaining) = timeout.checked_sub(start.elapsed()) {
+            if remaining == Duration::from_secs(0) {
+                break
+            }
+            try!(socket.set_read_timeout(Some(remaining)));
+
+            let bytes_read = match socket.recv_from(&mut buffer) {
+                Ok((bytes_read, _)) => bytes_read,
+                Err(_)              => break
+            };
+            let location = str::from_utf8(&buffer[..bytes_read]).ok()
+                .and_then(|response| response.split("\r\n")
+                    .filter_map(http::split_header)
+                    .find(|&(name, _)| name.eq_ignore_ascii_case("LOCATION"))
+                    .map(|(_, value)| value.to_owned()));
+
+            // Devices that are not gateways, or have no usable connection service, are skipped over
+            if let Some(location) = location {
+                match Gateway::from_location(&location, timeout) {
+                    Ok(gateway) => return Ok(gateway),
+                    Err(error)  => last_error = Some(error)
+                }
+            }
+        }
+
+        Err(NatError::from_kind(NatErrorKind::NoGatewayFound{
+            details: match last_error {
+                Some(error) => format!("No Usable Gateway Responded To The Search, Last Error Was {}", error),
+                None        => "No Gateway Responded To The Search".to_owned()
+            }
+        }))
+    }
+
+    /// Create a gateway from the location of its device description.
+    pub fn from_location(location: &str, timeout: Duration) -> NatResult<Gateway> {
+        let location = try!(HttpUrl::parse(location));
+        let (status, description) = try!(http::request("GET", &location, &[], "", timeout));
+        if status != 200 {
+            return Err(invalid_response(format!("Device Description Request Failed With Status {}", status)))
+        }
+
+        // Prefer ip connections over ppp connections, since either will map the port for us
+        let services = description.split("<service>").skip(1)
+            .filter_map(|service| match (tag_text(service, "serviceType"), tag_text(service, "controlURL")) {
+                (Some(service_type), Some(control)) => Some((service_type, control)),
+                _                                   => None
+            });
+        let mut opt_service = None;
+        for (service_type, control) in services {
+            if service_type.starts_with(WAN_IP_CONNECTION) {
+                opt_service = Some((service_type, control));
+                break
+            } else if service_type.starts_with(WAN_PPP_CONNECTION) && opt_service.is_none() {
+                opt_service = Some((service_type, control));
+            }
+        }
+
+        let (service_type, control) = try!(opt_service
+            .ok_or_else(|| NatError::from_kind(NatErrorKind::NoGatewayFound{
+                details: "Device Has No WAN Connection Service".to_owned()
+            })));
+
+        Ok(Gateway{ control: try!(location.join(control)), service_type: service_type.to_owned(), timeout: timeout })
+    }
+
+    /// Local address that the gateway sees us connecting from.
+    pub fn local_ip(&self) -> NatResult<Ipv4Addr> {
+        let socket = try!(UdpSocket::bind("0.0.0.0:0"));
+        try!(socket.connect(self.control.addr()));
+
+        match try!(socket.local_addr()).ip() {
+            IpAddr::V4(ip) => Ok(ip),
+            IpAddr::V6(ip) => Err(NatError::from_kind(NatErrorKind::NoGatewayFound{
+                details: format!("Gateway Is Reached Over Ipv6 From {}", ip)
+            }))
+        }
+    }
+
+    /// Ask the gateway for its external address.
+    pub fn external_ip(&self) -> NatResult<Ipv4Addr> {
+        let response = try!(self.soap_request("GetExternalIPAddress", &[]));
+
+        tag_text(&response, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or_else(|| invalid_response("GetExternalIPAddress Response Has No Valid Address".to_owned()))
+    }
+
+    /// Map the external port to the internal address, returning the lease duration that was granted.
+    ///
+    /// If the gateway only supports permanent leases, the mapping is retried with a lease of zero.
+    pub fn add_port_mapping(&self, protocol: Protocol, external_port: u16, internal: SocketAddrV4,
+                            lease: u32, description: &str) -> NatResult<u32> {
+        match self.add_port_mapping_with_lease(protocol, external_port, internal, lease, description) {
+            Err(NatError(NatErrorKind::GatewayRejected{ code: ONLY_PERMANENT_LEASES_SUPPORTED, .. }, _)) if lease != 0 => {
+                self.add_port_mapping_with_lease(protocol, external_port, internal, 0, description)
+                    .map(|_| 0)
+            },
+            other => other.map(|_| lease)
+        }
+    }
+
+    fn add_port_mapping_with_lease(&self, protocol: Protocol, external_port: u16, internal: SocketAddrV4,
+                                   lease: u32, description: &str) -> NatResult<()> {
+        self.soap_request("AddPortMapping", &[("NewRemoteHost", String::new()),
+                                              ("NewExternalPort", external_port.to_string()),
+                                              ("NewProtocol", protocol_name(protocol).to_owned()),
+                                              ("NewInternalPort", internal.port().to_string()),
+                                              ("NewInternalClient", internal.ip().to_string()),
+                                              ("NewEnabled", "1".to_owned()),
+                                              ("NewPortMappingDescription", escape_xml(description)),
+                                              ("NewLeaseDuration", lease.to_string())])
+            .map(|_| ())
+    }
+
+    /// Remove the mapping for the external port.
+    pub fn remove_port_mapping(&self, protocol: Protocol, external_port: u16) -> NatResult<()> {
+        self.soap_request("DeletePortMapping", &[("NewRemoteHost", String::new()),
+                                                 ("NewExternalPort", external_port.to_string()),
+                                                 ("NewProtocol", protocol_name(protocol).to_owned())])
+            .map(|_| ())
+    }
+
+    /// Invoke the action on the connection service, returning the body of a successful response.
+    fn soap_request(&self, action: &str, arguments: &[(&str, String)]) -> NatResult<String> {
+        let mut body = format!("<?xml version=\"1.0\"?>\r\n\
+                                <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+                                s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+                                <s:Body><u:{} xmlns:u=\"{}\">", action, self.service_type);
+        for &(name, ref value) in arguments {
+            body.push_str(&format!("<{0}>{1}</{0}>", name, value));
+        }
+        body.push_str(&format!("</u:{}></s:Body></s:Envelope>", action));
+
+        let soap_action = format!("\"{}#{}\"", self.service_type, action);
+        let (status, response) = try!(http::request("POST", &self.control,
+                                                    &[("Content-Type", "text/xml; charset=\"utf-8\""),
+                                                      ("SOAPAction", &soap_action)],
+                                                    &body, self.timeout));
+
+        if status == 200 {
+            Ok(response)
+        } else {
+            // Faults carry a upnp error code, which is more useful than the http status
+            match tag_text(&response, "errorCode").and_then(|code| code.parse().ok()) {
+                Some(code) => Err(NatError::from_kind(NatErrorKind::GatewayRejected{
+                    code:    code,
+                    details: format!("{} Failed: {}", action, tag_text(&response, "errorDescription").unwrap_or("No Description"))
+                })),
+                None => Err(invalid_response(format!("{} Failed With Status {}", action, status)))
+            }
+        }
+    }
+}
+
+/// Name for the protocol used in port mapping requests.
+fn protocol_name(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "TCP",
+        Protocol::Udp => "UDP"
+    }
+}
+
+/// Text within the first element with the given name, ignoring any namespace prefix on the element.
+fn tag_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut search_start = 0;
+
+    while let Some(index) = xml[search_start..].find(name) {
+        let name_start = search_start + index;
+        let name_end = name_start + name.len();
+        search_start = name_end;
+
+        let is_open_tag = xml[..name_start].rfind('<')
+            .map(|open| &xml[(open + 1)..name_start])
+            .map(|prefix| prefix.is_empty() || (prefix.ends_with(':') && prefix.chars().all(|c| c == ':' || c.is_alphanumeric())))
+            .unwrap_or(false);
+        let name_ends = xml[name_end..].chars().next().map(|c| c == '>' || c.is_whitespace()).unwrap_or(false);
+
+        if is_open_tag && name_ends {
+            return xml[name_end..].find('>')
+                .map(|close| name_end + close + 1)
+                .and_then(|content_start| xml[content_start..].find("</")
+                    .map(|content_len| xml[content_start..(content_start + content_len)].trim()))
+        }
+    }
+
+    None
+}
+
+fn escape_xml(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn invalid_response(details: String) -> NatError {
+    NatError::from_kind(NatErrorKind::InvalidResponse{ details: details })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddrV4, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use error::NatErrorKind;
+    use mapping::Protocol;
+    use super::Gateway;
+
+    const DESCRIPTION: &'static str = "<?xml version=\"1.0\"?>\
+        <root xmlns=\"urn:schemas-upnp-org:device-1-0\"><device>\
+        <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>\
+        <serviceList><service>\
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+        <controlURL>/ctl/L3F</controlURL>\
+        </service></serviceList>\
+        <deviceList><device><deviceList><device><serviceList>\
+        <service>\
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+        <controlURL>/ctl/IPConn</controlURL>\
+        </service>\
+        </serviceList></device></deviceList></device></deviceList>\
+        </device></root>";
+
+    pub const EXTERNAL_IP: &'static str = "203.0.113.7";
+
+    /// Request received by the mock gateway, as (path, soap action, body).
+    pub type MockRequest = (String, String, String);
+
+    /// Internet gateway device that serves its description and answers port mapping requests.
+    pub struct MockGateway {
+        location: String,
+        requests: Arc<Mutex<Vec<MockRequest>>>
+    }
+
+    impl MockGateway {
+        /// Start a gateway, which optionally rejects any leases that are not permanent.
+        pub fn start(permanent_only: bool) -> MockGateway {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let location = format!("http://{}/rootDesc.xml", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let thread_requests = requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => respond(stream, permanent_only, &thread_requests),
+                        Err(_)     => break
+                    }
+                }
+            });
+
+            MockGateway{ location: location, requests: requests }
+        }
+
+        pub fn location(&self) -> &str {
+            &self.location
+        }
+
+        pub fn requests(&self) -> Vec<MockRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn respond(mut stream: TcpStream, permanent_only: bool, requests: &Mutex<Vec<MockRequest>>) {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+        let (head, body) = loop {
+            let bytes_read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..bytes_read]);
+
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let head = text[..header_end].to_owned();
+                let content_length = header_value(&head, "Content-Length").and_then(|len| len.parse().ok()).unwrap_or(0);
+
+                if text.len() >= header_end + 4 + content_length {
+                    break (head, text[(header_end + 4)..].to_owned())
+                }
+            }
+        };
+
+        let path = head.split_whitespace().nth(1).unwrap().to_owned();
+        let action = header_value(&head, "SOAPAction").unwrap_or_default();
+        requests.lock().unwrap().push((path.clone(), action.clone(), body.clone()));
+
+        let (status, response) = if path == "/rootDesc.xml" {
+            ("200 OK", DESCRIPTION.to_owned())
+        } else if action.ends_with("#AddPortMapping\"") && permanent_only && !body.contains("<NewLeaseDuration>0<") {
+            ("500 Internal Server Error", soap_fault(725, "OnlyPermanentLeasesSupported"))
+        } else if action.ends_with("#AddPortMapping\"") {
+            ("200 OK", soap_response("AddPortMapping", ""))
+        } else if action.ends_with("#DeletePortMapping\"") {
+            ("200 OK", soap_response("DeletePortMapping", ""))
+        } else if action.ends_with("#GetExternalIPAddress\"") {
+            ("200 OK", soap_response("GetExternalIPAddress",
+                                     &format!("<NewExternalIPAddress>{}</NewExternalIPAddress>", EXTERNAL_IP)))
+        } else {
+            ("500 Internal Server Error", soap_fault(401, "Invalid Action"))
+        };
+
+        let _ = stream.write_all(format!("HTTP/1.1 {}\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                         status, response.len(), response).as_bytes());
+    }
+
+    fn header_value(head: &str, name: &str) -> Option<String> {
+        head.split("\r\n")
+            .filter_map(::http::split_header)
+            .find(|&(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_owned())
+    }
+
+    fn soap_response(action: &str, arguments: &str) -> String {
+        format!("<?xml version=\"1.0\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+                 <u:{0}Response xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">{1}</u:{0}Response>\
+                 </s:Body></s:Envelope>", action, arguments)
+    }
+
+    fn soap_fault(code: u16, description: &str) -> String {
+        format!("<?xml version=\"1.0\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+                 <s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail>\
+                 <UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\"><errorCode>{}</errorCode>\
+                 <errorDescription>{}</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>",
+                code, description)
+    }
+
+    fn gateway(mock: &MockGateway) -> Gateway {
+        Gateway::from_location(mock.location(), Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn positive_from_location_picks_wan_ip_connection() {
+        let mock = MockGateway::start(false);
+        let gateway = gateway(&mock);
+
+        assert_eq!("/ctl/IPConn", gateway.control.path());
+        assert_eq!("urn:schemas-upnp-org:service:WANIPConnection:1", gateway.service_type);
+    }
+
+    #[test]
+    fn positive_add_port_mapping_sends_soap_request() {
+        let mock = MockGateway::start(false);
+        let gateway = gateway(&mock);
+
+        let internal: SocketAddrV4 = "192.168.1.20:6881".parse().unwrap();
+        let lease = gateway.add_port_mapping(Protocol::Tcp, 6881, internal, 3600, "bip <test>").unwrap();
+        assert_eq!(3600, lease);
+
+        let requests = mock.requests();
+        let &(ref path, ref action, ref body) = requests.last().unwrap();
+        assert_eq!("/ctl/IPConn", path);
+        assert_eq!("\"urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping\"", action);
+        assert!(body.contains("<u:AddPortMapping xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">"));
+        assert!(body.contains("<NewExternalPort>6881</NewExternalPort>"));
+        assert!(body.contains("<NewProtocol>TCP</NewProtocol>"));
+        assert!(body.contains("<NewInternalPort>6881</NewInternalPort>"));
+        assert!(body.contains("<NewInternalClient>192.168.1.20</NewInternalClient>"));
+        assert!(body.contains("<NewPortMappingDescription>bip &lt;test&gt;</NewPortMappingDescription>"));
+        assert!(body.contains("<NewLeaseDuration>3600</NewLeaseDuration>"));
+    }
+
+    #[test]
+    fn positive_add_port_mapping_falls_back_to_permanent_lease() {
+        let mock = MockGateway::start(true);
+        let gateway = gateway(&mock);
+
+        let internal: SocketAddrV4 = "192.168.1.20:6881".parse().unwrap();
+        let lease = gateway.add_port_mapping(Protocol::Udp, 6881, internal, 3600, "bip").unwrap();
+        assert_eq!(0, lease);
+
+        let mappings = mock.requests().into_iter().filter(|&(_, ref action, _)| action.ends_with("#AddPortMapping\"")).count();
+        assert_eq!(2, mappings);
+    }
+
+    #[test]
+    fn positive_external_ip() {
+        let mock = MockGateway::start(false);
+
+        assert_eq!(EXTERNAL_IP.parse(), Ok(gateway(&mock).external_ip().unwrap()));
+    }
+
+    #[test]
+    fn negative_unknown_action_is_rejected() {
+        let mock = MockGateway::start(false);
+
+        match gateway(&mock).soap_request("GetGenericPortMappingEntry", &[]).unwrap_err().kind() {
+            &NatErrorKind::GatewayRejected{ code, .. } => assert_eq!(401, code),
+            other                                      => panic!("Unexpected Error {:?}", other)
+        }
+    }
+
+    #[test]
+    fn positive_tag_text_ignores_namespace_prefix() {
+        assert_eq!(Some("1.2.3.4"), super::tag_text("<m:Resp><m:NewExternalIPAddress>1.2.3.4</m:NewExternalIPAddress>", "NewExternalIPAddress"));
+        assert_eq!(Some("725"), super::tag_text("<UPnPError xmlns=\"x\"><errorCode> 725 </errorCode>", "errorCode"));
+        assert_eq!(None, super::tag_text("<errorCodes>725</errorCodes>", "errorCode"));
+    }
+}
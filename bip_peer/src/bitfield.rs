@@ -0,0 +1,229 @@
+//! Tracking of the pieces a peer has.
+
+use std::io;
+
+use message::BitFieldMessage;
+
+use bytes::Bytes;
+
+/// Set of pieces, stored as a bit-packed bitfield.
+///
+/// Piece 0 is stored in the most significant bit of the first byte, matching
+/// the layout of a `BitFieldMessage`. Any spare bits in the last byte are zero.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Bitfield {
+    bytes:      Vec<u8>,
+    num_pieces: usize
+}
+
+impl Bitfield {
+    /// Create a new, empty, `Bitfield` for the given number of pieces.
+    pub fn new(num_pieces: usize) -> Bitfield {
+        Bitfield{ bytes: vec![0u8; num_bytes(num_pieces)], num_pieces: num_pieces }
+    }
+
+    /// Create a `Bitfield` for the given number of pieces from the given bytes.
+    ///
+    /// Fails if the number of bytes is incorrect for the number of pieces,
+    /// or if any of the spare bits in the last byte are set.
+    pub fn from_bytes(bytes: &[u8], num_pieces: usize) -> io::Result<Bitfield> {
+        if bytes.len() != num_bytes(num_pieces) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Bitfield Has {} Bytes, Expected {} For {} Pieces", bytes.len(), num_bytes(num_pieces), num_pieces)))
+        }
+
+        let spare_bits = bytes.len() * 8 - num_pieces;
+        let spare_mask = ((1u16 << spare_bits) - 1) as u8;
+        if bytes.last().map(|byte| byte & spare_mask != 0).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Bitfield Has Spare Bits Set"))
+        }
+
+        Ok(Bitfield{ bytes: bytes.to_vec(), num_pieces: num_pieces })
+    }
+
+    /// Create a `Bitfield` for the given number of pieces from the given `BitFieldMessage`.
+    pub fn from_message(message: &BitFieldMessage, num_pieces: usize) -> io::Result<Bitfield> {
+        Bitfield::from_bytes(message.bitfield(), num_pieces)
+    }
+
+    /// Create a `BitFieldMessage` from the `Bitfield`.
+    pub fn to_message(&self) -> BitFieldMessage {
+        BitFieldMessage::new(Bytes::from(&self.bytes[..]))
+    }
+
+    /// Mark the given piece as present.
+    ///
+    /// Fails if the piece index is out of range.
+    pub fn set(&mut self, index: usize) -> io::Result<()> {
+        if index >= self.num_pieces {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Piece Index {} Out Of Range For {} Pieces", index, self.num_pieces)))
+        }
+
+        self.bytes[index / 8] |= 0x80 >> (index % 8);
+
+        Ok(())
+    }
+
+    /// Mark the given piece as not present.
+    ///
+    /// Fails if the piece index is out of range.
+    pub fn unset(&mut self, index: usize) -> io::Result<()> {
+        if index >= self.num_pieces {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Piece Index {} Out Of Range For {} Pieces", index, self.num_pieces)))
+        }
+
+        self.bytes[index / 8] &= !(0x80 >> (index % 8));
+
+        Ok(())
+    }
+
+    /// Whether or not the given piece is present.
+    ///
+    /// Pieces out of range are never present.
+    pub fn get(&self, index: usize) -> bool {
+        index < self.num_pieces && self.bytes[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Number of pieces present.
+    pub fn count_ones(&self) -> usize {
+        self.bytes.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+
+    /// Number of pieces tracked.
+    pub fn num_pieces(&self) -> usize {
+        self.num_pieces
+    }
+
+    /// Whether or not all pieces are present.
+    pub fn is_complete(&self) -> bool {
+        self.count_ones() == self.num_pieces
+    }
+
+    /// Whether or not this `Bitfield` has any piece that is not present in ours.
+    pub fn is_interesting(&self, ours: &Bitfield) -> bool {
+        self.bytes.iter().enumerate().any(|(index, theirs)| {
+            let ours_byte = ours.bytes.get(index).map(|byte| *byte).unwrap_or(0);
+
+            theirs & !ours_byte != 0
+        })
+    }
+
+    /// Bit-packed bytes of the `Bitfield`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Number of bytes needed to store a bit for each piece.
+fn num_bytes(num_pieces: usize) -> usize {
+    (num_pieces + 7) / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bitfield;
+
+    #[test]
+    fn positive_set_get_boundary_pieces() {
+        let mut bitfield = Bitfield::new(10);
+
+        bitfield.set(0).unwrap();
+        bitfield.set(7).unwrap();
+        bitfield.set(8).unwrap();
+        bitfield.set(9).unwrap();
+
+        assert!(bitfield.get(0));
+        assert!(!bitfield.get(1));
+        assert!(bitfield.get(7));
+        assert!(bitfield.get(8));
+        assert!(bitfield.get(9));
+        assert!(!bitfield.get(10));
+        assert_eq!(4, bitfield.count_ones());
+        assert_eq!(&[0x81, 0xC0], bitfield.as_bytes());
+    }
+
+    #[test]
+    fn positive_unset_piece() {
+        let mut bitfield = Bitfield::from_bytes(&[0xFF, 0xC0], 10).unwrap();
+
+        bitfield.unset(9).unwrap();
+
+        assert!(!bitfield.get(9));
+        assert!(bitfield.get(8));
+        assert_eq!(&[0xFF, 0x80], bitfield.as_bytes());
+        assert!(bitfield.unset(10).is_err());
+    }
+
+    #[test]
+    fn positive_is_complete() {
+        let bitfield = Bitfield::from_bytes(&[0xFF, 0xC0], 10).unwrap();
+
+        assert!(bitfield.is_complete());
+        assert_eq!(10, bitfield.count_ones());
+    }
+
+    #[test]
+    fn positive_from_bytes_exact_byte_boundary() {
+        let bitfield = Bitfield::from_bytes(&[0xFF, 0xFF], 16).unwrap();
+
+        assert!(bitfield.get(15));
+        assert!(bitfield.is_complete());
+    }
+
+    #[test]
+    fn positive_message_round_trip() {
+        let mut bitfield = Bitfield::new(12);
+        bitfield.set(3).unwrap();
+        bitfield.set(11).unwrap();
+
+        let message = bitfield.to_message();
+
+        assert_eq!(bitfield, Bitfield::from_message(&message, 12).unwrap());
+    }
+
+    #[test]
+    fn positive_is_interesting_missing_piece() {
+        let mut ours = Bitfield::new(10);
+        let mut theirs = Bitfield::new(10);
+        ours.set(0).unwrap();
+        theirs.set(0).unwrap();
+        theirs.set(9).unwrap();
+
+        assert!(theirs.is_interesting(&ours));
+        assert!(!ours.is_interesting(&theirs));
+    }
+
+    #[test]
+    fn negative_is_interesting_subset() {
+        let mut ours = Bitfield::new(10);
+        let mut theirs = Bitfield::new(10);
+        ours.set(2).unwrap();
+        ours.set(9).unwrap();
+        theirs.set(9).unwrap();
+
+        assert!(!theirs.is_interesting(&ours));
+        assert!(!Bitfield::new(10).is_interesting(&ours));
+    }
+
+    #[test]
+    fn negative_set_out_of_range() {
+        let mut bitfield = Bitfield::new(10);
+
+        assert!(bitfield.set(10).is_err());
+        assert_eq!(0, bitfield.count_ones());
+    }
+
+    #[test]
+    fn negative_from_bytes_wrong_length() {
+        assert!(Bitfield::from_bytes(&[0xFF], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0xFF, 0xC0, 0x00], 10).is_err());
+    }
+
+    #[test]
+    fn negative_from_bytes_spare_bits_set() {
+        assert!(Bitfield::from_bytes(&[0xFF, 0xE0], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0x00, 0x01], 10).is_err());
+    }
+}
@@ -5,6 +5,7 @@ extern crate bip_util;
 extern crate bytes;
 extern crate byteorder;
 extern crate crossbeam;
+extern crate crypto;
 #[macro_use]
 extern crate error_chain;
 extern crate futures;
@@ -13,16 +14,24 @@ extern crate tokio_io;
 extern crate tokio_timer;
 #[macro_use]
 extern crate nom;
+extern crate num;
+extern crate rand;
 
 #[macro_use]
 mod macros;
 
+mod bitfield;
 mod codec;
+mod limiter;
 mod manager;
 mod message;
 mod protocol;
 
+pub mod mse;
+
+pub use bitfield::Bitfield;
 pub use codec::PeerProtocolCodec;
+pub use limiter::{RateLimiter, RateLimitedStream};
 pub use protocol::{PeerProtocol, NestedPeerProtocol};
 pub use manager::{ManagedMessage, PeerManager, PeerManagerSink, PeerManagerStream, IPeerManagerMessage, OPeerManagerMessage, MessageId};
 pub use manager::builder::PeerManagerBuilder;
@@ -35,9 +44,15 @@ pub mod messages {
         pub use message::{ExtendedMessageBuilder};
     }
 
+    /// Registry types for extension protocol messages.
+    pub mod extensions {
+        pub use message::{ExtensionHandler, ExtensionProtocol};
+    }
+
     pub use message::{BitFieldIter, BitFieldMessage, CancelMessage, ExtendedMessage, HaveMessage, PieceMessage, PortMessage,
         RequestMessage, UtMetadataRequestMessage, UtMetadataDataMessage, UtMetadataRejectMessage, BitsExtensionMessage, ExtendedType,
-        NullProtocolMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage, UtMetadataMessage};
+        NullProtocolMessage, PeerExtensionProtocolMessage, PeerWireProtocolMessage, UtMetadataMessage, UtPexFlags, UtPexMessage,
+        SuggestPieceMessage, AllowedFastMessage, RejectRequestMessage};
 }
 
 /// `PeerManager` error types.
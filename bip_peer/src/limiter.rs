@@ -0,0 +1,390 @@
+//! Bandwidth limiting for peer connections.
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::{Sleep, Timer};
+
+// Longest we will sleep before re-checking a bucket, keeps us responsive to rate changes
+const MAX_WAIT_MILLIS: u64 = 1000;
+
+/// Token bucket which refills at a fixed number of bytes per second.
+struct TokenBucket {
+    rate:     u64,
+    capacity: Option<u64>,
+    tokens:   u64,
+    refilled: Instant
+}
+
+impl TokenBucket {
+    fn new(now: Instant) -> TokenBucket {
+        TokenBucket{ rate: 0, capacity: None, tokens: 0, refilled: now }
+    }
+
+    fn set_rate(&mut self, rate: u64, now: Instant) {
+        self.refill(now);
+
+        self.rate = rate;
+        self.tokens = cmp::min(self.tokens, self.capacity());
+    }
+
+    fn set_capacity(&mut self, capacity: Option<u64>, now: Instant) {
+        self.refill(now);
+
+        self.capacity = capacity;
+        self.tokens = cmp::min(self.tokens, self.capacity());
+    }
+
+    /// Maximum number of tokens the bucket holds, defaults to one second worth of tokens.
+    fn capacity(&self) -> u64 {
+        cmp::max(self.capacity.unwrap_or(self.rate), 1)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.refilled {
+            return
+        }
+
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed_nanos = elapsed.as_secs() as u128 * 1_000_000_000 + elapsed.subsec_nanos() as u128;
+        let new_tokens = self.rate as u128 * elapsed_nanos / 1_000_000_000;
+
+        if new_tokens > 0 {
+            // Only advance by the time used to generate whole tokens, so fractions accumulate
+            let used_nanos = new_tokens * 1_000_000_000 / self.rate as u128;
+            self.refilled += Duration::new((used_nanos / 1_000_000_000) as u64, (used_nanos % 1_000_000_000) as u32);
+            self.tokens = cmp::min(self.tokens.saturating_add(new_tokens as u64), self.capacity());
+
+            if self.tokens == self.capacity() {
+                self.refilled = now;
+            }
+        }
+    }
+
+    /// Take up to `wanted` tokens, or return how long until tokens are available.
+    fn take(&mut self, wanted: usize, now: Instant) -> Result<usize, Duration> {
+        if self.rate == 0 {
+            return Ok(wanted)
+        }
+        self.refill(now);
+
+        if self.tokens > 0 {
+            let granted = cmp::min(self.tokens, wanted as u64);
+            self.tokens -= granted;
+
+            Ok(granted as usize)
+        } else {
+            // Wait until we could grant the whole request, bounded by our capacity
+            let needed = cmp::min(cmp::max(wanted as u64, 1), self.capacity());
+            let wait_nanos = needed as u128 * 1_000_000_000 / self.rate as u128 + 1;
+            let wait = Duration::new((wait_nanos / 1_000_000_000) as u64, (wait_nanos % 1_000_000_000) as u32);
+
+            Err(cmp::min(wait, Duration::from_millis(MAX_WAIT_MILLIS)))
+        }
+    }
+
+    /// Give back tokens that were taken but not used.
+    fn give(&mut self, unused: usize) {
+        if self.rate != 0 {
+            self.tokens = cmp::min(self.tokens.saturating_add(unused as u64), self.capacity());
+        }
+    }
+}
+
+struct Buckets {
+    upload:   TokenBucket,
+    download: TokenBucket
+}
+
+/// Rate limiter for the bytes uploaded to, and downloaded from, peers.
+///
+/// A single `RateLimiter` can be shared across many `RateLimitedStream`s, in
+/// which case the limits apply to the sum of their traffic. Rates are in bytes
+/// per second, where a rate of zero means unlimited.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+    timer:   Timer
+}
+
+impl RateLimiter {
+    /// Create a new, unlimited, `RateLimiter`.
+    pub fn new() -> RateLimiter {
+        RateLimiter::with_timer(Timer::default())
+    }
+
+    /// Create a new, unlimited, `RateLimiter` using the given `Timer` to wait for bandwidth.
+    pub fn with_timer(timer: Timer) -> RateLimiter {
+        let now = Instant::now();
+        let buckets = Buckets{ upload: TokenBucket::new(now), download: TokenBucket::new(now) };
+
+        RateLimiter{ buckets: Arc::new(Mutex::new(buckets)), timer: timer }
+    }
+
+    /// Set the upload rate, in bytes per second.
+    pub fn set_upload_rate(&self, rate: u64) {
+        self.buckets.lock().unwrap().upload.set_rate(rate, Instant::now());
+    }
+
+    /// Set the download rate, in bytes per second.
+    pub fn set_download_rate(&self, rate: u64) {
+        self.buckets.lock().unwrap().download.set_rate(rate, Instant::now());
+    }
+
+    /// Set the maximum number of bytes that can be uploaded or downloaded in a single burst.
+    ///
+    /// Defaults to one second worth of bytes at the configured rate.
+    pub fn set_burst_capacity(&self, capacity: u64) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        buckets.upload.set_capacity(Some(capacity), now);
+        buckets.download.set_capacity(Some(capacity), now);
+    }
+
+    fn take_upload(&self, wanted: usize) -> Result<usize, Duration> {
+        self.buckets.lock().unwrap().upload.take(wanted, Instant::now())
+    }
+
+    fn take_download(&self, wanted: usize) -> Result<usize, Duration> {
+        self.buckets.lock().unwrap().download.take(wanted, Instant::now())
+    }
+
+    fn give_upload(&self, unused: usize) {
+        self.buckets.lock().unwrap().upload.give(unused);
+    }
+
+    fn give_download(&self, unused: usize) {
+        self.buckets.lock().unwrap().download.give(unused);
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+/// Stream whose reads and writes are limited by a `RateLimiter`.
+///
+/// Wrap a peer's transport with this before framing it with a `PeerProtocolCodec`.
+/// When the limit is reached, reads and writes return `WouldBlock` and the current
+/// task is woken up once more bandwidth is available.
+pub struct RateLimitedStream<S> {
+    stream:      S,
+    limiter:     RateLimiter,
+    read_sleep:  Option<Sleep>,
+    write_sleep: Option<Sleep>
+}
+
+impl<S> RateLimitedStream<S> {
+    /// Create a new `RateLimitedStream`.
+    pub fn new(stream: S, limiter: RateLimiter) -> RateLimitedStream<S> {
+        RateLimitedStream{ stream: stream, limiter: limiter, read_sleep: None, write_sleep: None }
+    }
+
+    /// Access the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consume the `RateLimitedStream`, returning the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+/// Wait on the given sleep, or on a new sleep if tokens are unavailable.
+///
+/// Returns the number of tokens granted.
+fn acquire<F>(opt_sleep: &mut Option<Sleep>, timer: &Timer, take: F) -> io::Result<usize>
+    where F: Fn() -> Result<usize, Duration> {
+    loop {
+        if let Some(mut sleep) = opt_sleep.take() {
+            match sleep.poll() {
+                Ok(Async::NotReady) => {
+                    *opt_sleep = Some(sleep);
+
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "Waiting On Rate Limit"))
+                },
+                Ok(Async::Ready(())) => (),
+                Err(err)             => return Err(io::Error::new(io::ErrorKind::Other, err))
+            }
+        }
+
+        match take() {
+            Ok(granted) => return Ok(granted),
+            Err(wait)   => *opt_sleep = Some(timer.sleep(wait))
+        }
+    }
+}
+
+impl<S> Read for RateLimitedStream<S> where S: Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.stream.read(buf)
+        }
+
+        let limiter = &self.limiter;
+        let granted = try!(acquire(&mut self.read_sleep, &limiter.timer, || limiter.take_download(buf.len())));
+
+        let result = self.stream.read(&mut buf[..granted]);
+        let used = match result {
+            Ok(read) => read,
+            Err(_)   => 0
+        };
+        limiter.give_download(granted - used);
+
+        result
+    }
+}
+
+impl<S> Write for RateLimitedStream<S> where S: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.stream.write(buf)
+        }
+
+        let limiter = &self.limiter;
+        let granted = try!(acquire(&mut self.write_sleep, &limiter.timer, || limiter.take_upload(buf.len())));
+
+        let result = self.stream.write(&buf[..granted]);
+        let used = match result {
+            Ok(written) => written,
+            Err(_)      => 0
+        };
+        limiter.give_upload(granted - used);
+
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<S> AsyncRead for RateLimitedStream<S> where S: AsyncRead { }
+
+impl<S> AsyncWrite for RateLimitedStream<S> where S: AsyncWrite {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.stream.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    use super::{TokenBucket, RateLimiter, RateLimitedStream};
+
+    use tokio_core::reactor::Core;
+    use tokio_io::io;
+
+    fn bucket(rate: u64, capacity: Option<u64>, now: Instant) -> TokenBucket {
+        let mut bucket = TokenBucket::new(now);
+        bucket.set_capacity(capacity, now);
+        bucket.set_rate(rate, now);
+
+        bucket
+    }
+
+    /// Keep taking as much as possible every `step`, over the `window`, returning the total taken.
+    fn sustained_take(bucket: &mut TokenBucket, start: Instant, step: Duration, window: Duration) -> u64 {
+        let mut now = start;
+        let mut taken = 0;
+
+        while now < start + window {
+            now += step;
+
+            if let Ok(granted) = bucket.take(64 * 1024, now) {
+                taken += granted as u64;
+            }
+        }
+
+        taken
+    }
+
+    #[test]
+    fn positive_zero_rate_unlimited() {
+        let now = Instant::now();
+        let mut bucket = bucket(0, None, now);
+
+        assert_eq!(Ok(1024 * 1024), bucket.take(1024 * 1024, now));
+        assert_eq!(Ok(1024 * 1024), bucket.take(1024 * 1024, now));
+    }
+
+    #[test]
+    fn positive_sustained_rate_within_limit() {
+        let start = Instant::now();
+        let mut bucket = bucket(100 * 1024, Some(16 * 1024), start);
+
+        let taken = sustained_take(&mut bucket, start, Duration::from_millis(10), Duration::from_secs(5));
+
+        // At most the rate over the window, plus one burst worth of tokens
+        assert!(taken <= 5 * 100 * 1024 + 16 * 1024);
+        assert!(taken >= 5 * 100 * 1024 - 16 * 1024);
+    }
+
+    #[test]
+    fn positive_sustained_rate_slow_steps() {
+        let start = Instant::now();
+        let mut bucket = bucket(1000, Some(100), start);
+
+        let taken = sustained_take(&mut bucket, start, Duration::from_millis(7), Duration::from_secs(10));
+
+        assert!(taken <= 10 * 1000 + 100);
+        assert!(taken >= 10 * 1000 - 100);
+    }
+
+    #[test]
+    fn positive_burst_bounded_by_capacity() {
+        let start = Instant::now();
+        let mut bucket = bucket(10 * 1024, Some(4 * 1024), start);
+
+        // Idle for a long time, we should still only get one burst
+        let later = start + Duration::from_secs(60);
+        assert_eq!(Ok(4 * 1024), bucket.take(64 * 1024, later));
+        assert!(bucket.take(64 * 1024, later).is_err());
+    }
+
+    #[test]
+    fn positive_empty_bucket_wait_time() {
+        let start = Instant::now();
+        let mut bucket = bucket(1000, Some(500), start);
+
+        let wait = bucket.take(100, start).unwrap_err();
+
+        assert!(wait >= Duration::from_millis(100));
+        assert!(wait <= Duration::from_millis(101));
+        assert_eq!(Ok(100), bucket.take(100, start + wait));
+    }
+
+    #[test]
+    fn positive_rate_change_at_runtime() {
+        let start = Instant::now();
+        let mut bucket = bucket(1000, None, start);
+
+        bucket.set_rate(0, start);
+
+        assert_eq!(Ok(64 * 1024), bucket.take(64 * 1024, start));
+    }
+
+    #[test]
+    fn positive_limited_stream_waits_for_tokens() {
+        let mut core = Core::new().unwrap();
+        let limiter = RateLimiter::new();
+        limiter.set_download_rate(64 * 1024);
+        limiter.set_burst_capacity(16 * 1024);
+
+        let stream = RateLimitedStream::new(Cursor::new(vec![0u8; 32 * 1024]), limiter);
+
+        let start = Instant::now();
+        let (_, buf) = core.run(io::read_exact(stream, vec![0u8; 32 * 1024])).unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(32 * 1024, buf.len());
+        // Bucket starts out empty, so we need half a second worth of tokens
+        assert!(elapsed >= Duration::from_millis(400));
+    }
+}
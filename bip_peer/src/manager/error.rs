@@ -12,6 +12,11 @@ error_chain! {
             description("Peer Was Not Found")
             display("Peer Was Not Found With PeerInfo {:?}", info)
         }
-        
+        ConnectionLimit {
+            info: PeerInfo
+        } {
+            description("Peer Was Rejected By Connection Limits")
+            display("Peer Was Rejected By Connection Limits With PeerInfo {:?}", info)
+        }
     }
 }
\ No newline at end of file
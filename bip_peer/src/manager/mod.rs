@@ -1,6 +1,5 @@
 use std::io;
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
 use std::cmp;
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
@@ -8,6 +7,7 @@ use std::sync::{Arc, Mutex};
 use manager::builder::PeerManagerBuilder;
 use manager::peer_info::PeerInfo;
 use manager::error::{PeerManagerError, PeerManagerErrorKind};
+use manager::slots::{Admission, ConnectionSlots};
 
 use crossbeam::sync::MsQueue;
 use futures::{StartSend, Poll, AsyncSink, Async};
@@ -23,6 +23,7 @@ pub mod peer_info;
 pub mod error;
 
 mod future;
+mod slots;
 mod task;
 
 // We configure our tick duration based on this, could let users configure this in the future...
@@ -62,14 +63,34 @@ impl<P> PeerManager<P>
         
         let (res_send, res_recv) = mpsc::channel(builder.stream_buffer_capacity());
         let peers = Arc::new(Mutex::new(HashMap::new()));
+        let slots = Arc::new(Mutex::new(ConnectionSlots::new()));
         let task_queue = Arc::new(MsQueue::new());
 
-        let sink = PeerManagerSink::new(handle, timer, builder, res_send, peers.clone(), task_queue.clone());
-        let stream = PeerManagerStream::new(res_recv, peers, task_queue);
+        let sink = PeerManagerSink::new(handle, timer, builder, res_send, peers.clone(), slots.clone(), task_queue.clone());
+        let stream = PeerManagerStream::new(res_recv, peers, slots, task_queue);
 
         PeerManager{ sink: sink, stream: stream }
     }
 
+    /// Set the maximum number of peers we will be connected to.
+    ///
+    /// See `PeerManagerSink::set_max_connections`.
+    pub fn set_max_connections(&self, max: usize) {
+        self.sink.set_max_connections(max);
+    }
+
+    /// Set the maximum number of peers we will be connected to for any single torrent.
+    ///
+    /// See `PeerManagerSink::set_max_connections`.
+    pub fn set_max_connections_per_torrent(&self, max: usize) {
+        self.sink.set_max_connections_per_torrent(max);
+    }
+
+    /// Set the score of a peer, used to decide which peer to evict when connection limits are reached.
+    pub fn set_peer_score(&self, info: &PeerInfo, score: i64) {
+        self.sink.set_peer_score(info, score);
+    }
+
     /// Break the `PeerManager` into a sink and stream.
     ///
     /// The returned sink implements `Clone`.
@@ -116,13 +137,15 @@ pub struct PeerManagerSink<P> where P: Sink + Stream {
     build:      PeerManagerBuilder,
     send:       Sender<OPeerManagerMessage<P::Item>>,
     peers:      Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+    slots:      Arc<Mutex<ConnectionSlots>>,
     task_queue: Arc<MsQueue<Task>>
 }
 
 impl<P> Clone for PeerManagerSink<P> where P: Sink + Stream {
     fn clone(&self) -> PeerManagerSink<P> {
         PeerManagerSink{ handle: self.handle.clone(), timer: self.timer.clone(), build: self.build,
-                         send: self.send.clone(), peers: self.peers.clone(), task_queue: self.task_queue.clone() }
+                         send: self.send.clone(), peers: self.peers.clone(), slots: self.slots.clone(),
+                         task_queue: self.task_queue.clone() }
     }
 }
 
@@ -130,8 +153,34 @@ impl<P> PeerManagerSink<P> where P: Sink + Stream {
     fn new(handle: Handle, timer: Timer, build: PeerManagerBuilder,
            send: Sender<OPeerManagerMessage<P::Item>>,
            peers: Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+           slots: Arc<Mutex<ConnectionSlots>>,
            task_queue: Arc<MsQueue<Task>>) -> PeerManagerSink<P> {
-        PeerManagerSink{ handle: handle, timer: timer, build: build, send: send, peers: peers, task_queue: task_queue}
+        PeerManagerSink{ handle: handle, timer: timer, build: build, send: send, peers: peers, slots: slots,
+                         task_queue: task_queue}
+    }
+
+    /// Set the maximum number of peers we will be connected to.
+    ///
+    /// Once a limit is reached, a peer added with a higher score than the worst connected
+    /// peer evicts that peer, while any other peer is rejected with a
+    /// `PeerManagerErrorKind::ConnectionLimit` error, and dropped. Lowering a limit does
+    /// not evict peers that are already connected.
+    pub fn set_max_connections(&self, max: usize) {
+        self.slots.lock().unwrap().set_max_connections(max);
+    }
+
+    /// Set the maximum number of peers we will be connected to for any single torrent.
+    ///
+    /// See `PeerManagerSink::set_max_connections`.
+    pub fn set_max_connections_per_torrent(&self, max: usize) {
+        self.slots.lock().unwrap().set_max_connections_per_torrent(max);
+    }
+
+    /// Set the score of a peer, used to decide which peer to evict when connection limits are reached.
+    ///
+    /// Higher scores are better, and peers added with `IPeerManagerMessage::AddPeer` start with a score of zero.
+    pub fn set_peer_score(&self, info: &PeerInfo, score: i64) {
+        self.slots.lock().unwrap().set_score(info, score);
     }
 
     fn run_with_lock_sink<F, T, E, G, I>(&mut self, item: I, call: F, not: G) -> StartSend<T, E>
@@ -204,6 +253,49 @@ impl<P> PeerManagerSink<P> where P: Sink + Stream {
     }
 }
 
+impl<P> PeerManagerSink<P>
+    where P: Sink<SinkError=io::Error> +
+             Stream<Error=io::Error> +
+             'static,
+          P::SinkItem: ManagedMessage,
+          P::Item:     ManagedMessage {
+    fn start_add_peer(&mut self, info: PeerInfo, peer: P, score: i64) -> StartSend<IPeerManagerMessage<P>, PeerManagerError> {
+        let slots = self.slots.clone();
+
+        self.run_with_lock_sink((info, peer, score), move |(info, peer, score), handle, timer, builder, send, peers| {
+            if peers.len() >= builder.peer_capacity() {
+                return Ok(AsyncSink::NotReady(IPeerManagerMessage::AddScoredPeer(info, peer, score)))
+            } else if peers.contains_key(&info) {
+                return Err(PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info }))
+            }
+
+            let mut slots = slots.lock().unwrap();
+            match slots.check(&info, score) {
+                Admission::Accept       => (),
+                Admission::Reject       => return Err(PeerManagerError::from_kind(PeerManagerErrorKind::ConnectionLimit{ info: info })),
+                Admission::Evict(worst) => {
+                    // Evicted peer is removed from our peer map once its PeerRemoved message comes through
+                    let evicted = peers.get_mut(&worst)
+                        .map(|worst_send| worst_send.start_send(IPeerManagerMessage::RemovePeer(worst))
+                                                    .map_err(|_| panic!("bip_peer: PeerManager Failed To Send RemovePeer")))
+                        .unwrap_or(Ok(AsyncSink::Ready));
+
+                    match evicted {
+                        Ok(AsyncSink::Ready) => { slots.remove(&worst); },
+                        _                    => return Ok(AsyncSink::NotReady(IPeerManagerMessage::AddScoredPeer(info, peer, score)))
+                    }
+                }
+            }
+
+            slots.insert(info, score);
+            peers.insert(info, task::run_peer(peer, info, send.clone(), timer.clone(), builder, handle));
+
+            Ok(AsyncSink::Ready)
+        },
+        |(info, peer, score)| IPeerManagerMessage::AddScoredPeer(info, peer, score))
+    }
+}
+
 impl<P> Sink for PeerManagerSink<P>
     where P: Sink<SinkError=io::Error> +
              Stream<Error=io::Error> +
@@ -216,21 +308,10 @@ impl<P> Sink for PeerManagerSink<P>
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
         match item {
             IPeerManagerMessage::AddPeer(info, peer) => {
-                self.run_with_lock_sink((info, peer), |(info, peer), handle, timer, builder, send, peers| {
-                    if peers.len() >= builder.peer_capacity() {
-                        Ok(AsyncSink::NotReady(IPeerManagerMessage::AddPeer(info, peer)))
-                    } else {
-                        match peers.entry(info) {
-                            Entry::Occupied(_) => Err(PeerManagerError::from_kind(PeerManagerErrorKind::PeerNotFound{ info: info })),
-                            Entry::Vacant(vac) => {
-                                vac.insert(task::run_peer(peer, info, send.clone(), timer.clone(), builder, handle));
-
-                                Ok(AsyncSink::Ready)
-                            }
-                        }
-                    }
-                },
-                |(info, peer)| IPeerManagerMessage::AddPeer(info, peer))
+                self.start_add_peer(info, peer, 0)
+            },
+            IPeerManagerMessage::AddScoredPeer(info, peer, score) => {
+                self.start_add_peer(info, peer, score)
             },
             IPeerManagerMessage::RemovePeer(info) => {
                 self.run_with_lock_sink(info, |info, _, _, _, _, peers| {
@@ -277,6 +358,7 @@ impl<P> Sink for PeerManagerSink<P>
 pub struct PeerManagerStream<P> where P: Sink + Stream {
     recv:        Receiver<OPeerManagerMessage<P::Item>>,
     peers:       Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+    slots:       Arc<Mutex<ConnectionSlots>>,
     task_queue:  Arc<MsQueue<Task>>,
     opt_pending: Option<Option<OPeerManagerMessage<P::Item>>>
 }
@@ -284,8 +366,9 @@ pub struct PeerManagerStream<P> where P: Sink + Stream {
 impl<P> PeerManagerStream<P> where P: Sink + Stream {
     fn new(recv: Receiver<OPeerManagerMessage<P::Item>>,
            peers: Arc<Mutex<HashMap<PeerInfo, Sender<IPeerManagerMessage<P>>>>>,
+           slots: Arc<Mutex<ConnectionSlots>>,
            task_queue: Arc<MsQueue<Task>>) -> PeerManagerStream<P> {
-        PeerManagerStream{ recv: recv, peers: peers, task_queue: task_queue, opt_pending: None }
+        PeerManagerStream{ recv: recv, peers: peers, slots: slots, task_queue: task_queue, opt_pending: None }
     }
 
     fn run_with_lock_poll<F, T, E, I, G>(&mut self, item: I, call: F, not: G) -> Poll<T, E>
@@ -333,6 +416,7 @@ impl<P> Stream for PeerManagerStream<P>
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         // Intercept and propogate any messages indicating the peer shutdown so we can remove them from our peer map
+        let slots = self.slots.clone();
         let next_message = self.opt_pending.take().map(|pending| Ok(Async::Ready(pending))).unwrap_or_else(|| self.recv.poll());
 
         next_message.and_then(|result| {
@@ -340,6 +424,7 @@ impl<P> Stream for PeerManagerStream<P>
                 Async::Ready(Some(OPeerManagerMessage::PeerRemoved(info))) => {
                     self.run_with_lock_poll(info, |info, peers| {
                         peers.remove(&info).unwrap_or_else(|| panic!("bip_peer: Received PeerRemoved Message With No Matching Peer In Map"));
+                        slots.lock().unwrap().remove(&info);
 
                         Ok(Async::Ready(Some(OPeerManagerMessage::PeerRemoved(info))))
                     },
@@ -348,6 +433,7 @@ impl<P> Stream for PeerManagerStream<P>
                 Async::Ready(Some(OPeerManagerMessage::PeerDisconnect(info))) => {
                     self.run_with_lock_poll(info, |info, peers| {
                         peers.remove(&info).unwrap_or_else(|| panic!("bip_peer: Received PeerDisconnect Message With No Matching Peer In Map"));
+                        slots.lock().unwrap().remove(&info);
 
                         Ok(Async::Ready(Some(OPeerManagerMessage::PeerDisconnect(info))))
                     },
@@ -356,6 +442,7 @@ impl<P> Stream for PeerManagerStream<P>
                 Async::Ready(Some(OPeerManagerMessage::PeerError(info, error))) => {
                     self.run_with_lock_poll((info, error), |(info, error), peers| {
                         peers.remove(&info).unwrap_or_else(|| panic!("bip_peer: Received PeerError Message With No Matching Peer In Map"));
+                        slots.lock().unwrap().remove(&info);
 
                         Ok(Async::Ready(Some(OPeerManagerMessage::PeerError(info, error))))
                     },
@@ -392,6 +479,8 @@ pub enum IPeerManagerMessage<P>
     where P: Sink {
     /// Add a peer to the peer manager.
     AddPeer(PeerInfo, P),
+    /// Add a peer to the peer manager, with a score used when connection limits are reached.
+    AddScoredPeer(PeerInfo, P, i64),
     /// Remove a peer from the peer manager.
     RemovePeer(PeerInfo),
     /// Send a message to a peer.
@@ -40,6 +40,9 @@ impl PeerInfo {
     }
 
     /// Retrieve the extensions supported by this peer.
+    ///
+    /// When created from a `CompleteMessage`, these are the extensions negotiated
+    /// during the handshake, that is, the intersection of ours and the peer's.
     pub fn extensions(&self) -> &Extensions {
         &self.ext
     }
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use manager::peer_info::PeerInfo;
+
+/// Outcome of checking whether a new peer fits within our connection limits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Admission {
+    /// Peer fits within our connection limits.
+    Accept,
+    /// Peer fits within our connection limits once the given, worse, peer is evicted.
+    Evict(PeerInfo),
+    /// Peer does not fit within our connection limits.
+    Reject
+}
+
+/// Tracks connection slots, in total and per torrent, for peers added to a `PeerManager`.
+///
+/// Each peer has a score, where a higher score is better. When a limit has been
+/// reached, a new peer only takes a slot if it has a higher score than the worst
+/// peer it would compete with, which is then evicted.
+pub struct ConnectionSlots {
+    max_connections: Option<usize>,
+    max_per_torrent: Option<usize>,
+    peers:           HashMap<PeerInfo, i64>
+}
+
+impl ConnectionSlots {
+    /// Create a new `ConnectionSlots` without any limits.
+    pub fn new() -> ConnectionSlots {
+        ConnectionSlots{ max_connections: None, max_per_torrent: None, peers: HashMap::new() }
+    }
+
+    /// Set the maximum number of connections.
+    ///
+    /// Lowering the limit below the number of current connections does not evict peers.
+    pub fn set_max_connections(&mut self, max: usize) {
+        self.max_connections = Some(max);
+    }
+
+    /// Set the maximum number of connections for any single torrent.
+    ///
+    /// Lowering the limit below the number of current connections does not evict peers.
+    pub fn set_max_connections_per_torrent(&mut self, max: usize) {
+        self.max_per_torrent = Some(max);
+    }
+
+    /// Set the score of an added peer.
+    pub fn set_score(&mut self, info: &PeerInfo, score: i64) {
+        if let Some(peer_score) = self.peers.get_mut(info) {
+            *peer_score = score;
+        }
+    }
+
+    /// Check whether a new peer with the given score fits within our connection limits.
+    pub fn check(&self, info: &PeerInfo, score: i64) -> Admission {
+        let torrent_connections = self.peers.keys().filter(|peer| peer.hash() == info.hash()).count();
+
+        let total_full = self.max_connections.map(|max| self.peers.len() >= max).unwrap_or(false);
+        let torrent_full = self.max_per_torrent.map(|max| torrent_connections >= max).unwrap_or(false);
+
+        if !total_full && !torrent_full {
+            return Admission::Accept
+        }
+
+        // If the torrent is full, we have to evict a peer from the same torrent, which frees a total slot as well
+        let opt_worst = self.peers.iter()
+            .filter(|&(peer, _)| !torrent_full || peer.hash() == info.hash())
+            .min_by_key(|&(_, peer_score)| *peer_score);
+
+        match opt_worst {
+            Some((worst, &worst_score)) if worst_score < score => Admission::Evict(*worst),
+            _                                                  => Admission::Reject
+        }
+    }
+
+    /// Take a slot for the given peer.
+    pub fn insert(&mut self, info: PeerInfo, score: i64) {
+        self.peers.insert(info, score);
+    }
+
+    /// Release the slot for the given peer, returning its score.
+    pub fn remove(&mut self, info: &PeerInfo) -> Option<i64> {
+        self.peers.remove(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Admission, ConnectionSlots};
+    use manager::peer_info::PeerInfo;
+
+    use bip_handshake::Extensions;
+    use bip_util::bt::{self, InfoHash};
+
+    fn peer(id: u8, hash: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), InfoHash::from([hash; bt::INFO_HASH_LEN]), Extensions::new())
+    }
+
+    #[test]
+    fn positive_reject_over_max_connections() {
+        let mut slots = ConnectionSlots::new();
+        slots.set_max_connections(2);
+
+        for id in 0..2 {
+            assert_eq!(Admission::Accept, slots.check(&peer(id, 0), 0));
+            slots.insert(peer(id, 0), 0);
+        }
+
+        assert_eq!(Admission::Reject, slots.check(&peer(2, 1), 0));
+
+        slots.remove(&peer(0, 0));
+        assert_eq!(Admission::Accept, slots.check(&peer(2, 1), 0));
+    }
+
+    #[test]
+    fn positive_reject_over_max_connections_per_torrent() {
+        let mut slots = ConnectionSlots::new();
+        slots.set_max_connections_per_torrent(1);
+
+        slots.insert(peer(0, 0), 0);
+
+        assert_eq!(Admission::Reject, slots.check(&peer(1, 0), 0));
+        assert_eq!(Admission::Accept, slots.check(&peer(1, 1), 0));
+    }
+
+    #[test]
+    fn positive_evict_worst_peer_for_better_candidate() {
+        let mut slots = ConnectionSlots::new();
+        slots.set_max_connections(2);
+
+        slots.insert(peer(0, 0), 5);
+        slots.insert(peer(1, 1), 10);
+        slots.set_score(&peer(0, 0), 1);
+
+        assert_eq!(Admission::Reject, slots.check(&peer(2, 1), 1));
+        assert_eq!(Admission::Evict(peer(0, 0)), slots.check(&peer(2, 1), 2));
+    }
+
+    #[test]
+    fn positive_evict_within_full_torrent() {
+        let mut slots = ConnectionSlots::new();
+        slots.set_max_connections_per_torrent(1);
+
+        slots.insert(peer(0, 0), 0);
+        slots.insert(peer(1, 1), -10);
+
+        // Worst peer overall is on another torrent, which would not free a slot for this torrent
+        assert_eq!(Admission::Evict(peer(0, 0)), slots.check(&peer(2, 0), 1));
+    }
+}
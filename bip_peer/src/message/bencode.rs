@@ -33,18 +33,28 @@ pub const METADATA_SIZE_KEY:       &'static [u8] = b"metadata_size";
 
 pub fn parse_id_map<K, V>(root: &BDictAccess<K, V>) -> HashMap<ExtendedType, u8>
     where V: BRefAccess, V::BKey: AsRef<[u8]> {
-    let mut id_map = HashMap::new();
-    
+    // An id of 0 means the extension is disabled, and ids have to fit in a single byte
+    parse_id_map_entries(root).into_iter()
+        .filter(|&(_, value)| value > 0 && value <= u8::max_value() as i64)
+        .map(|(ext_type, value)| (ext_type, value as u8))
+        .collect()
+}
+
+/// Parses all entries in the id map, including those which disable an extension.
+pub fn parse_id_map_entries<K, V>(root: &BDictAccess<K, V>) -> Vec<(ExtendedType, i64)>
+    where V: BRefAccess, V::BKey: AsRef<[u8]> {
+    let mut id_entries = Vec::new();
+
     if let Ok(ben_id_map) = CONVERT.lookup_and_convert_dict(root, ID_MAP_KEY) {
         for (id, ben_value) in ben_id_map.to_list() {
             match (str::from_utf8(id.as_ref()), CONVERT.convert_int(ben_value, id)) {
-                (Ok(str_id), Ok(value)) => { id_map.insert(ExtendedType::from_id(str_id), value as u8); },
+                (Ok(str_id), Ok(value)) => { id_entries.push((ExtendedType::from_id(str_id), value)); },
                 _                       => ()
             }
         }
     }
 
-    id_map
+    id_entries
 }
 
 pub fn parse_client_id<K, V>(root: &BDictAccess<K, V>) -> Option<String>
@@ -150,4 +160,22 @@ pub fn parse_piece_index<K, V>(root: &BDictAccess<K, V>) -> io::Result<i64>
 pub fn parse_total_size<K, V>(root: &BDictAccess<K, V>) -> io::Result<i64>
     where V: BRefAccess {
     CONVERT.lookup_and_convert_int(root, TOTAL_SIZE_KEY).into()
-}
\ No newline at end of file
+}
+// ----------------------------------------------------------------------------//
+
+pub const ADDED_IPV4_KEY:       &'static [u8] = b"added";
+pub const ADDED_IPV4_FLAGS_KEY: &'static [u8] = b"added.f";
+pub const ADDED_IPV6_KEY:       &'static [u8] = b"added6";
+pub const ADDED_IPV6_FLAGS_KEY: &'static [u8] = b"added6.f";
+pub const DROPPED_IPV4_KEY:     &'static [u8] = b"dropped";
+pub const DROPPED_IPV6_KEY:     &'static [u8] = b"dropped6";
+
+/// Parses one of the (optional) compact byte strings from a pex message, defaulting to empty.
+pub fn parse_pex_bytes<'a, K, V>(root: &'a BDictAccess<K, V>, key: &'static [u8]) -> io::Result<&'a [u8]>
+    where V: BRefAccess {
+    if root.lookup(key).is_some() {
+        CONVERT.lookup_and_convert_bytes(root, key)
+    } else {
+        Ok(&[])
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use byteorder::{WriteBytesExt, BigEndian};
+use nom::{IResult, be_u32};
+
+use message;
+use message::bits_ext;
+
+/// Message for suggesting a piece that a peer may want to download from us.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SuggestPieceMessage {
+    piece_index: u32
+}
+
+impl SuggestPieceMessage {
+    pub fn new(piece_index: u32) -> SuggestPieceMessage {
+        SuggestPieceMessage{ piece_index: piece_index }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<SuggestPieceMessage>> {
+        throwaway_input!(parse_suggest_piece(bytes.as_ref()))
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, bits_ext::SUGGEST_PIECE_MESSAGE_LEN, Some(bits_ext::SUGGEST_PIECE_MESSAGE_ID)));
+
+        writer.write_u32::<BigEndian>(self.piece_index)
+    }
+
+    pub fn piece_index(&self) -> u32 {
+        self.piece_index
+    }
+}
+
+fn parse_suggest_piece(bytes: &[u8]) -> IResult<&[u8], io::Result<SuggestPieceMessage>> {
+    map!(bytes, be_u32, |index| Ok(SuggestPieceMessage::new(index)))
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Message for telling a peer it may request a piece from us even while choked.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct AllowedFastMessage {
+    piece_index: u32
+}
+
+impl AllowedFastMessage {
+    pub fn new(piece_index: u32) -> AllowedFastMessage {
+        AllowedFastMessage{ piece_index: piece_index }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<AllowedFastMessage>> {
+        throwaway_input!(parse_allowed_fast(bytes.as_ref()))
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, bits_ext::ALLOWED_FAST_MESSAGE_LEN, Some(bits_ext::ALLOWED_FAST_MESSAGE_ID)));
+
+        writer.write_u32::<BigEndian>(self.piece_index)
+    }
+
+    pub fn piece_index(&self) -> u32 {
+        self.piece_index
+    }
+}
+
+fn parse_allowed_fast(bytes: &[u8]) -> IResult<&[u8], io::Result<AllowedFastMessage>> {
+    map!(bytes, be_u32, |index| Ok(AllowedFastMessage::new(index)))
+}
+
+// ----------------------------------------------------------------------------//
+
+/// Message for telling a peer we will not be responding to one of their `RequestMessage`s.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct RejectRequestMessage {
+    piece_index: u32,
+    block_offset: u32,
+    block_length: usize
+}
+
+impl RejectRequestMessage {
+    pub fn new(piece_index: u32, block_offset: u32, block_length: usize) -> RejectRequestMessage {
+        RejectRequestMessage {
+            piece_index: piece_index,
+            block_offset: block_offset,
+            block_length: block_length
+        }
+    }
+
+    pub fn parse_bytes(_input: (), bytes: Bytes) -> IResult<(), io::Result<RejectRequestMessage>> {
+        throwaway_input!(parse_reject_request(bytes.as_ref()))
+    }
+
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        try!(message::write_length_id_pair(&mut writer, bits_ext::REJECT_REQUEST_MESSAGE_LEN, Some(bits_ext::REJECT_REQUEST_MESSAGE_ID)));
+
+        try!(writer.write_u32::<BigEndian>(self.piece_index));
+        try!(writer.write_u32::<BigEndian>(self.block_offset));
+        writer.write_u32::<BigEndian>(self.block_length as u32)
+    }
+
+    pub fn piece_index(&self) -> u32 {
+        self.piece_index
+    }
+
+    pub fn block_offset(&self) -> u32 {
+        self.block_offset
+    }
+
+    pub fn block_length(&self) -> usize {
+        self.block_length
+    }
+}
+
+fn parse_reject_request(bytes: &[u8]) -> IResult<&[u8], io::Result<RejectRequestMessage>> {
+    map!(bytes,
+         tuple!(be_u32, be_u32, be_u32),
+         |(index, offset, length)| Ok(RejectRequestMessage::new(index, offset, message::u32_to_usize(length)))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SuggestPieceMessage, AllowedFastMessage, RejectRequestMessage};
+    use message::bits_ext::BitsExtensionMessage;
+
+    use bytes::Bytes;
+    use nom::IResult;
+
+    fn round_trip(message: BitsExtensionMessage, expected_bytes: &[u8]) {
+        let mut bytes = Vec::new();
+        message.write_bytes(&mut bytes).unwrap();
+
+        assert_eq!(expected_bytes, &bytes[..]);
+        assert_eq!(bytes.len(), 4 + message.message_size());
+
+        match BitsExtensionMessage::parse_bytes((), Bytes::from(bytes)) {
+            IResult::Done(_, Ok(parsed)) => assert_eq!(message, parsed),
+            _                            => panic!("Failed To Parse BitsExtensionMessage")
+        }
+    }
+
+    #[test]
+    fn positive_have_all_round_trip() {
+        round_trip(BitsExtensionMessage::HaveAll, &[0, 0, 0, 1, 0x0E]);
+    }
+
+    #[test]
+    fn positive_have_none_round_trip() {
+        round_trip(BitsExtensionMessage::HaveNone, &[0, 0, 0, 1, 0x0F]);
+    }
+
+    #[test]
+    fn positive_suggest_piece_round_trip() {
+        round_trip(BitsExtensionMessage::SuggestPiece(SuggestPieceMessage::new(258)),
+                   &[0, 0, 0, 5, 0x0D, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn positive_allowed_fast_round_trip() {
+        round_trip(BitsExtensionMessage::AllowedFast(AllowedFastMessage::new(7)),
+                   &[0, 0, 0, 5, 0x11, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn positive_reject_request_round_trip() {
+        round_trip(BitsExtensionMessage::RejectRequest(RejectRequestMessage::new(1, 16384, 16384)),
+                   &[0, 0, 0, 13, 0x10, 0, 0, 0, 1, 0, 0, 0x40, 0, 0, 0, 0x40, 0]);
+    }
+
+    #[test]
+    fn negative_have_all_wrong_length() {
+        let bytes = Bytes::from(&[0u8, 0, 0, 2, 0x0E, 0][..]);
+
+        match BitsExtensionMessage::parse_bytes((), bytes) {
+            IResult::Done(_, Ok(_)) => panic!("Parsed HaveAll With Invalid Length"),
+            _                       => ()
+        }
+    }
+}
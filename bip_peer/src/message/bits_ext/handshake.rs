@@ -265,7 +265,7 @@ impl ExtendedMessage {
         let real_length = 2 + self.bencode_size();
         try!(message::write_length_id_pair(&mut writer, real_length as u32, Some(bits_ext::EXTENDED_MESSAGE_ID)));
 
-        writer.write_all(&[bits_ext::EXTENDED_MESSAGE_HANDSHAKE_ID]);
+        try!(writer.write_all(&[bits_ext::EXTENDED_MESSAGE_HANDSHAKE_ID]));
 
         writer.write_all(self.raw_bencode.as_ref())
     }
@@ -320,4 +320,58 @@ impl ExtendedMessage {
         // We already verified that this is valid bencode
         BencodeRef::decode(&*self.raw_bencode, BDecodeOpt::default()).unwrap()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use bytes::Bytes;
+    use nom::IResult;
+
+    use super::{ExtendedMessage, ExtendedMessageBuilder, ExtendedType};
+    use message;
+
+    #[test]
+    fn positive_extended_message_round_trip() {
+        let message = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(3))
+            .with_extended_type(ExtendedType::UtPex, Some(1))
+            .with_our_id(Some("bip_peer 0.5".to_string()))
+            .with_our_tcp_port(Some(6881))
+            .with_their_ip(Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))))
+            .with_max_requests(Some(250))
+            .with_metadata_size(Some(31235))
+            .build();
+
+        let mut bytes = Vec::new();
+        message.write_bytes(&mut bytes).unwrap();
+
+        // Skip the length, message id, and extended message id
+        let header_len = message::HEADER_LEN + 1;
+        let bencode_len = (bytes.len() - header_len) as u32;
+        let parsed = match ExtendedMessage::parse_bytes((), Bytes::from(&bytes[header_len..]), bencode_len) {
+            IResult::Done(_, res_message) => res_message.unwrap(),
+            _                             => panic!("Failed To Parse ExtendedMessage")
+        };
+
+        assert_eq!(message, parsed);
+        assert_eq!(Some(3), parsed.query_id(&ExtendedType::UtMetadata));
+        assert_eq!(Some("bip_peer 0.5"), parsed.our_id());
+        assert_eq!(Some(6881), parsed.our_tcp_port());
+        assert_eq!(Some(250), parsed.our_max_requests());
+    }
+
+    #[test]
+    fn positive_extended_message_disabled_extension() {
+        let message = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(0))
+            .build();
+
+        let parsed = match ExtendedMessage::parse_bytes((), Bytes::from(message.bencode_ref().buffer()), message.bencode_size() as u32) {
+            IResult::Done(_, res_message) => res_message.unwrap(),
+            _                             => panic!("Failed To Parse ExtendedMessage")
+        };
+
+        assert_eq!(None, parsed.query_id(&ExtendedType::UtMetadata));
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use bip_bencode::{BencodeRef, BDecodeOpt, BConvert, BencodeMut, BMutAccess};
+use bip_handshake::Extensions;
 use bip_util::convert;
 use bytes::Bytes;
 use byteorder::{WriteBytesExt, BigEndian};
@@ -11,19 +12,36 @@ use nom::{IResult, be_u32, be_u8, be_u16, Needed};
 use message;
 use message::bencode;
 
-const PORT_MESSAGE_LEN:          u32 = 3;
-const BASE_EXTENDED_MESSAGE_LEN: u32 = 6;
+const PORT_MESSAGE_LEN:           u32 = 3;
+const BASE_EXTENDED_MESSAGE_LEN:  u32 = 6;
+const HAVE_ALL_MESSAGE_LEN:       u32 = 1;
+const HAVE_NONE_MESSAGE_LEN:      u32 = 1;
+const SUGGEST_PIECE_MESSAGE_LEN:  u32 = 5;
+const REJECT_REQUEST_MESSAGE_LEN: u32 = 13;
+const ALLOWED_FAST_MESSAGE_LEN:   u32 = 5;
 
-const PORT_MESSAGE_ID:         u8 = 9;
-pub const EXTENDED_MESSAGE_ID: u8 = 20;
+const PORT_MESSAGE_ID:           u8 = 9;
+const SUGGEST_PIECE_MESSAGE_ID:  u8 = 13;
+const HAVE_ALL_MESSAGE_ID:       u8 = 14;
+const HAVE_NONE_MESSAGE_ID:      u8 = 15;
+const REJECT_REQUEST_MESSAGE_ID: u8 = 16;
+const ALLOWED_FAST_MESSAGE_ID:   u8 = 17;
+pub const EXTENDED_MESSAGE_ID:   u8 = 20;
 
 const EXTENDED_MESSAGE_HANDSHAKE_ID: u8 = 0;
 
+/// Reserved bit (counting from the most significant bit) for the fast extension.
+const FAST_EXTENSION_BIT: usize = 61;
+
+mod fast;
 mod handshake;
 mod port;
+mod registry;
 
+pub use self::fast::{SuggestPieceMessage, AllowedFastMessage, RejectRequestMessage};
 pub use self::handshake::{ExtendedType, ExtendedMessage, ExtendedMessageBuilder};
 pub use self::port::PortMessage;
+pub use self::registry::{ExtensionHandler, ExtensionProtocol};
 
 /// Enumeration of messages for `PeerWireProtocolMessage`, activated via `Extensions` bits.
 ///
@@ -33,8 +51,17 @@ pub enum BitsExtensionMessage {
     /// Messsage for determining the port a peer's DHT is listening on.
     Port(PortMessage),
     /// Message for sending a peer the map of extensions we support.
-    Extended(ExtendedMessage)
-
+    Extended(ExtendedMessage),
+    /// Message to tell a peer we have all pieces, in place of a `BitFieldMessage`.
+    HaveAll,
+    /// Message to tell a peer we have no pieces, in place of a `BitFieldMessage`.
+    HaveNone,
+    /// Message to suggest a piece that a peer may want to download.
+    SuggestPiece(SuggestPieceMessage),
+    /// Message to tell a peer we will not be responding to one of their requests.
+    RejectRequest(RejectRequestMessage),
+    /// Message to tell a peer it may request a piece from us even while choked.
+    AllowedFast(AllowedFastMessage)
 }
 
 impl BitsExtensionMessage {
@@ -46,19 +73,53 @@ impl BitsExtensionMessage {
         where W: Write
     {
         match self {
-            &BitsExtensionMessage::Port(msg)         => msg.write_bytes(writer),
-            &BitsExtensionMessage::Extended(ref msg) => msg.write_bytes(writer)
+            &BitsExtensionMessage::Port(msg)          => msg.write_bytes(writer),
+            &BitsExtensionMessage::Extended(ref msg)  => msg.write_bytes(writer),
+            &BitsExtensionMessage::HaveAll            => message::write_length_id_pair(writer, HAVE_ALL_MESSAGE_LEN, Some(HAVE_ALL_MESSAGE_ID)),
+            &BitsExtensionMessage::HaveNone           => message::write_length_id_pair(writer, HAVE_NONE_MESSAGE_LEN, Some(HAVE_NONE_MESSAGE_ID)),
+            &BitsExtensionMessage::SuggestPiece(msg)  => msg.write_bytes(writer),
+            &BitsExtensionMessage::RejectRequest(msg) => msg.write_bytes(writer),
+            &BitsExtensionMessage::AllowedFast(msg)   => msg.write_bytes(writer)
         }
     }
 
     pub fn message_size(&self) -> usize {
         match self {
-            &BitsExtensionMessage::Port(_)           => PORT_MESSAGE_LEN as usize,
-            &BitsExtensionMessage::Extended(ref msg) => BASE_EXTENDED_MESSAGE_LEN as usize + msg.bencode_size()
+            &BitsExtensionMessage::Port(_)            => PORT_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::Extended(ref msg)  => BASE_EXTENDED_MESSAGE_LEN as usize + msg.bencode_size(),
+            &BitsExtensionMessage::HaveAll            => HAVE_ALL_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::HaveNone           => HAVE_NONE_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::SuggestPiece(_)    => SUGGEST_PIECE_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::RejectRequest(_)   => REJECT_REQUEST_MESSAGE_LEN as usize,
+            &BitsExtensionMessage::AllowedFast(_)     => ALLOWED_FAST_MESSAGE_LEN as usize
+        }
+    }
+
+    /// Whether or not this message belongs to the fast extension.
+    pub fn is_fast_extension(&self) -> bool {
+        match self {
+            &BitsExtensionMessage::HaveAll          |
+            &BitsExtensionMessage::HaveNone         |
+            &BitsExtensionMessage::SuggestPiece(_)  |
+            &BitsExtensionMessage::RejectRequest(_) |
+            &BitsExtensionMessage::AllowedFast(_)   => true,
+            _                                       => false
         }
     }
 }
 
+/// Whether or not the fast extension bit is set in the given `Extensions`.
+///
+/// See `http://www.bittorrent.org/beps/bep_0006.html`.
+pub fn fast_extension_enabled(extensions: &Extensions) -> bool {
+    let mut bytes = Vec::new();
+    extensions.write_bytes(&mut bytes).expect("bip_peer: Writing Extensions To Vec Failed");
+
+    bytes.get(FAST_EXTENSION_BIT / 8)
+        .map(|byte| byte & (0x80 >> (FAST_EXTENSION_BIT % 8)) != 0)
+        .unwrap_or(false)
+}
+
 fn parse_extension(mut bytes: Bytes) -> IResult<(), io::Result<BitsExtensionMessage>> {
     let header_bytes = bytes.clone();
 
@@ -71,6 +132,28 @@ fn parse_extension(mut bytes: Bytes) -> IResult<(), io::Result<BitsExtensionMess
                 )
             )
         ) |
+        ignore_input!(
+            switch!(header_bytes.as_ref(), throwaway_input!(tuple!(be_u32, be_u8)),
+                (HAVE_ALL_MESSAGE_LEN, HAVE_ALL_MESSAGE_ID) => value!(
+                    Ok(BitsExtensionMessage::HaveAll)
+                ) |
+                (HAVE_NONE_MESSAGE_LEN, HAVE_NONE_MESSAGE_ID) => value!(
+                    Ok(BitsExtensionMessage::HaveNone)
+                ) |
+                (SUGGEST_PIECE_MESSAGE_LEN, SUGGEST_PIECE_MESSAGE_ID) => map!(
+                    call!(SuggestPieceMessage::parse_bytes, bytes.split_off(message::HEADER_LEN)),
+                    |res_suggest| res_suggest.map(|suggest| BitsExtensionMessage::SuggestPiece(suggest))
+                ) |
+                (REJECT_REQUEST_MESSAGE_LEN, REJECT_REQUEST_MESSAGE_ID) => map!(
+                    call!(RejectRequestMessage::parse_bytes, bytes.split_off(message::HEADER_LEN)),
+                    |res_reject| res_reject.map(|reject| BitsExtensionMessage::RejectRequest(reject))
+                ) |
+                (ALLOWED_FAST_MESSAGE_LEN, ALLOWED_FAST_MESSAGE_ID) => map!(
+                    call!(AllowedFastMessage::parse_bytes, bytes.split_off(message::HEADER_LEN)),
+                    |res_allowed| res_allowed.map(|allowed| BitsExtensionMessage::AllowedFast(allowed))
+                )
+            )
+        ) |
         ignore_input!(
             switch!(header_bytes.as_ref(), throwaway_input!(tuple!(be_u32, be_u8, be_u8)),
                 (message_len, EXTENDED_MESSAGE_ID, EXTENDED_MESSAGE_HANDSHAKE_ID) => map!(
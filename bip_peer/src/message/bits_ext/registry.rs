@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::io;
+
+use bip_bencode::BConvert;
+use bytes::Bytes;
+
+use message::bencode;
+use message::bits_ext::handshake::{ExtendedMessage, ExtendedMessageBuilder, ExtendedType};
+
+const ROOT_ERROR_KEY: &'static str = "ExtensionProtocol";
+
+/// Trait for handling messages for a single extension of the `BEP 10` extension protocol.
+pub trait ExtensionHandler {
+    /// Handle the payload of an extension message received from a peer.
+    ///
+    /// Payload does not include the length, message id, or extended message id.
+    fn on_message(&mut self, payload: Bytes) -> io::Result<()>;
+}
+
+/// Registry of extensions negotiated with a peer via `ExtendedMessage`.
+///
+/// Each extension we register is assigned an id, which we advertise to the peer
+/// and which the peer uses when sending us messages for that extension. The peer
+/// advertises its own ids, which we have to use when sending messages to the peer.
+///
+/// See `http://www.bittorrent.org/beps/bep_0010.html`.
+pub struct ExtensionProtocol {
+    our_ids:   HashMap<ExtendedType, u8>,
+    their_ids: HashMap<ExtendedType, u8>,
+    handlers:  HashMap<u8, Box<ExtensionHandler>>
+}
+
+impl ExtensionProtocol {
+    /// Create a new, empty, `ExtensionProtocol`.
+    pub fn new() -> ExtensionProtocol {
+        ExtensionProtocol{ our_ids: HashMap::new(), their_ids: HashMap::new(), handlers: HashMap::new() }
+    }
+
+    /// Register a handler for the given `ExtendedType`, returning the id assigned to it.
+    ///
+    /// Registering an already registered extension will replace the handler, but keep the id.
+    ///
+    /// Returns `None` if all extension ids are in use.
+    pub fn register<H>(&mut self, ext_type: ExtendedType, handler: H) -> Option<u8>
+        where H: ExtensionHandler + 'static {
+        let opt_id = self.our_ids.get(&ext_type).map(|id| *id)
+            .or_else(|| (1..=u8::max_value()).find(|id| !self.handlers.contains_key(id)));
+
+        opt_id.map(|id| {
+            self.our_ids.insert(ext_type, id);
+            self.handlers.insert(id, Box::new(handler));
+
+            id
+        })
+    }
+
+    /// Add our extension ids to the given `ExtendedMessageBuilder`.
+    pub fn extend(&self, builder: ExtendedMessageBuilder) -> ExtendedMessageBuilder {
+        self.our_ids.iter().fold(builder, |builder, (ext_type, &id)| {
+            builder.with_extended_type(ext_type.clone(), Some(id))
+        })
+    }
+
+    /// Update the ids the peer has assigned to extensions from their `ExtendedMessage`.
+    ///
+    /// As subsequent handshakes may only contain changes, extensions not present in
+    /// the message are left untouched, and extensions mapped to 0 are disabled.
+    pub fn received_handshake(&mut self, message: &ExtendedMessage) -> io::Result<()> {
+        let bencode = message.bencode_ref();
+        let ben_dict = try!(bencode::CONVERT.convert_dict(&bencode, ROOT_ERROR_KEY));
+
+        for (ext_type, value) in bencode::parse_id_map_entries(ben_dict) {
+            if value > 0 && value <= u8::max_value() as i64 {
+                self.their_ids.insert(ext_type, value as u8);
+            } else {
+                self.their_ids.remove(&ext_type);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Id we assigned to the given `ExtendedType`, which the peer uses when sending to us.
+    pub fn our_id(&self, ext_type: &ExtendedType) -> Option<u8> {
+        self.our_ids.get(ext_type).map(|id| *id)
+    }
+
+    /// Id the peer assigned to the given `ExtendedType`, which we use when sending to the peer.
+    pub fn their_id(&self, ext_type: &ExtendedType) -> Option<u8> {
+        self.their_ids.get(ext_type).map(|id| *id)
+    }
+
+    /// Whether or not both us and the peer support the given `ExtendedType`.
+    pub fn is_negotiated(&self, ext_type: &ExtendedType) -> bool {
+        self.our_id(ext_type).is_some() && self.their_id(ext_type).is_some()
+    }
+
+    /// Dispatch the payload of an extension message, received with the given id, to its handler.
+    pub fn dispatch(&mut self, id: u8, payload: Bytes) -> io::Result<()> {
+        match self.handlers.get_mut(&id) {
+            Some(handler) => handler.on_message(payload),
+            None          => Err(io::Error::new(io::ErrorKind::Other, format!("No Handler Registered For Extension Id: {}", id)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    use bytes::Bytes;
+
+    use super::{ExtensionHandler, ExtensionProtocol};
+    use message::bits_ext::handshake::{ExtendedMessageBuilder, ExtendedType};
+
+    struct RecordingHandler {
+        payloads: Rc<RefCell<Vec<Bytes>>>
+    }
+
+    impl ExtensionHandler for RecordingHandler {
+        fn on_message(&mut self, payload: Bytes) -> io::Result<()> {
+            self.payloads.borrow_mut().push(payload);
+
+            Ok(())
+        }
+    }
+
+    fn recording_handler() -> (RecordingHandler, Rc<RefCell<Vec<Bytes>>>) {
+        let payloads = Rc::new(RefCell::new(Vec::new()));
+
+        (RecordingHandler{ payloads: payloads.clone() }, payloads)
+    }
+
+    #[test]
+    fn positive_register_assigns_distinct_ids() {
+        let mut protocol = ExtensionProtocol::new();
+
+        let metadata_id = protocol.register(ExtendedType::UtMetadata, recording_handler().0).unwrap();
+        let pex_id = protocol.register(ExtendedType::UtPex, recording_handler().0).unwrap();
+
+        assert!(metadata_id != 0);
+        assert!(pex_id != 0);
+        assert!(metadata_id != pex_id);
+        assert_eq!(Some(metadata_id), protocol.register(ExtendedType::UtMetadata, recording_handler().0));
+    }
+
+    #[test]
+    fn positive_extend_advertises_our_ids() {
+        let mut protocol = ExtensionProtocol::new();
+        let metadata_id = protocol.register(ExtendedType::UtMetadata, recording_handler().0).unwrap();
+
+        let message = protocol.extend(ExtendedMessageBuilder::new()).build();
+
+        assert_eq!(Some(metadata_id), message.query_id(&ExtendedType::UtMetadata));
+        assert_eq!(None, message.query_id(&ExtendedType::UtPex));
+    }
+
+    #[test]
+    fn positive_resolve_their_id() {
+        let mut protocol = ExtensionProtocol::new();
+        protocol.register(ExtendedType::UtMetadata, recording_handler().0);
+
+        let their_message = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(3))
+            .with_extended_type(ExtendedType::Custom("lt_donthave".to_string()), Some(7))
+            .build();
+        protocol.received_handshake(&their_message).unwrap();
+
+        assert_eq!(Some(3), protocol.their_id(&ExtendedType::UtMetadata));
+        assert_eq!(Some(7), protocol.their_id(&ExtendedType::Custom("lt_donthave".to_string())));
+        assert!(protocol.is_negotiated(&ExtendedType::UtMetadata));
+        assert!(!protocol.is_negotiated(&ExtendedType::Custom("lt_donthave".to_string())));
+    }
+
+    #[test]
+    fn positive_handshake_update_disables_extension() {
+        let mut protocol = ExtensionProtocol::new();
+
+        let first_message = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtMetadata, Some(3))
+            .with_extended_type(ExtendedType::UtPex, Some(4))
+            .build();
+        let update_message = ExtendedMessageBuilder::new()
+            .with_extended_type(ExtendedType::UtPex, Some(0))
+            .build();
+        protocol.received_handshake(&first_message).unwrap();
+        protocol.received_handshake(&update_message).unwrap();
+
+        assert_eq!(Some(3), protocol.their_id(&ExtendedType::UtMetadata));
+        assert_eq!(None, protocol.their_id(&ExtendedType::UtPex));
+    }
+
+    #[test]
+    fn positive_dispatch_to_handler() {
+        let mut protocol = ExtensionProtocol::new();
+        let (handler, payloads) = recording_handler();
+        let metadata_id = protocol.register(ExtendedType::UtMetadata, handler).unwrap();
+
+        protocol.dispatch(metadata_id, Bytes::from(&b"payload"[..])).unwrap();
+
+        assert_eq!(vec![Bytes::from(&b"payload"[..])], *payloads.borrow());
+    }
+
+    #[test]
+    fn negative_dispatch_unknown_id() {
+        let mut protocol = ExtensionProtocol::new();
+        protocol.register(ExtendedType::UtMetadata, recording_handler().0);
+
+        assert!(protocol.dispatch(200, Bytes::from(&b"payload"[..])).is_err());
+    }
+}
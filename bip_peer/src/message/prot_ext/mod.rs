@@ -13,13 +13,15 @@ use protocol::{PeerProtocol};
 const EXTENSION_HEADER_LEN: usize = message::HEADER_LEN + 1;
 
 mod ut_metadata;
+mod ut_pex;
 
 pub use self::ut_metadata::{UtMetadataMessage, UtMetadataDataMessage, UtMetadataRequestMessage, UtMetadataRejectMessage};
+pub use self::ut_pex::{UtPexFlags, UtPexMessage};
 
 /// Enumeration of `BEP 10` extension protocol compatible messages.
 pub enum PeerExtensionProtocolMessage<P> where P: PeerProtocol {
     UtMetadata(UtMetadataMessage),
-    //UtPex(UtPexMessage),
+    UtPex(UtPexMessage),
     Custom(P::ProtocolMessage)
 }
 
@@ -52,6 +54,18 @@ impl<P> PeerExtensionProtocolMessage<P> where P: PeerProtocol {
 
                 msg.write_bytes(writer)
             },
+            &PeerExtensionProtocolMessage::UtPex(ref msg) => {
+                let ext_id = if let Some(ext_id) = extended.query_id(&ExtendedType::UtPex) {
+                    ext_id
+                } else { return Err(io::Error::new(io::ErrorKind::Other, "Can't Send UtPexMessage As We Have No Id Mapping")) };
+
+                let total_len = (2 + msg.message_size()) as u32;
+
+                try!(message::write_length_id_pair(&mut writer, total_len, Some(bits_ext::EXTENDED_MESSAGE_ID)));
+                try!(writer.write_u8(ext_id));
+
+                msg.write_bytes(writer)
+            },
             &PeerExtensionProtocolMessage::Custom(ref msg)     => custom_prot.write_bytes(msg, writer)
         }
     }
@@ -59,6 +73,7 @@ impl<P> PeerExtensionProtocolMessage<P> where P: PeerProtocol {
     pub fn message_size(&self, custom_prot: &mut P) -> usize {
         match self {
             &PeerExtensionProtocolMessage::UtMetadata(ref msg) => msg.message_size(),
+            &PeerExtensionProtocolMessage::UtPex(ref msg)      => msg.message_size(),
             &PeerExtensionProtocolMessage::Custom(ref msg)     => custom_prot.message_size(&msg)
         }
     }
@@ -83,11 +98,14 @@ fn parse_extensions<P>(mut bytes: Bytes, extended: &ExtendedMessage, custom_prot
 fn parse_extensions_with_id<P>(_input: (), bytes: Bytes, extended: &ExtendedMessage, id: u8) -> IResult<(), io::Result<PeerExtensionProtocolMessage<P>>>
     where P: PeerProtocol {
     let lt_metadata_id = extended.query_id(&ExtendedType::UtMetadata);
-    //let ut_pex_id = extended.query_id(&ExtendedType::UtPex);
+    let ut_pex_id = extended.query_id(&ExtendedType::UtPex);
 
     let result = if lt_metadata_id == Some(id) {
         UtMetadataMessage::parse_bytes(bytes)
                 .map(|lt_metadata_msg| PeerExtensionProtocolMessage::UtMetadata(lt_metadata_msg))
+    } else if ut_pex_id == Some(id) {
+        UtPexMessage::parse_bytes(bytes)
+                .map(|ut_pex_msg| PeerExtensionProtocolMessage::UtPex(ut_pex_msg))
     } else {
         Err(io::Error::new(io::ErrorKind::Other, format!("Unknown Id For PeerExtensionProtocolMessage: {}", id)))
     };
@@ -0,0 +1,286 @@
+use std::io::{self, Write};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use bip_bencode::{BDecodeOpt, BencodeRef, BConvert};
+use bip_util::convert;
+use bytes::Bytes;
+
+use message::bencode;
+
+const IPV4_COMPACT_LEN: usize = 6;
+const IPV6_COMPACT_LEN: usize = 18;
+
+const PREFERS_ENCRYPTION_FLAG: u8 = 0x01;
+const IS_SEED_FLAG:            u8 = 0x02;
+const SUPPORTS_UTP_FLAG:       u8 = 0x04;
+const SUPPORTS_HOLEPUNCH_FLAG: u8 = 0x08;
+const IS_REACHABLE_FLAG:       u8 = 0x10;
+
+const ROOT_ERROR_KEY: &'static str = "UtPexMessage";
+
+/// Flags describing a peer added in a `UtPexMessage`.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct UtPexFlags {
+    flags: u8
+}
+
+impl UtPexFlags {
+    /// Create a new `UtPexFlags` from the raw flags byte.
+    pub fn new(flags: u8) -> UtPexFlags {
+        UtPexFlags{ flags: flags }
+    }
+
+    /// Raw flags byte.
+    pub fn bits(&self) -> u8 {
+        self.flags
+    }
+
+    /// Whether or not the peer prefers encrypted connections.
+    pub fn prefers_encryption(&self) -> bool {
+        self.flags & PREFERS_ENCRYPTION_FLAG != 0
+    }
+
+    /// Whether or not the peer is a seed (or upload only).
+    pub fn is_seed(&self) -> bool {
+        self.flags & IS_SEED_FLAG != 0
+    }
+
+    /// Whether or not the peer supports uTP.
+    pub fn supports_utp(&self) -> bool {
+        self.flags & SUPPORTS_UTP_FLAG != 0
+    }
+
+    /// Whether or not the peer supports the holepunch extension.
+    pub fn supports_holepunch(&self) -> bool {
+        self.flags & SUPPORTS_HOLEPUNCH_FLAG != 0
+    }
+
+    /// Whether or not the peer is reachable (we connected out to them).
+    pub fn is_reachable(&self) -> bool {
+        self.flags & IS_REACHABLE_FLAG != 0
+    }
+}
+
+/// Message for exchanging peers that were added or dropped since the last exchange.
+///
+/// See `http://www.bittorrent.org/beps/bep_0011.html`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UtPexMessage {
+    added:        Vec<(SocketAddr, UtPexFlags)>,
+    dropped:      Vec<SocketAddr>,
+    bencode_size: usize
+}
+
+impl UtPexMessage {
+    /// Create a new `UtPexMessage` from the given added and dropped peers.
+    pub fn new(added: Vec<(SocketAddr, UtPexFlags)>, dropped: Vec<SocketAddr>) -> UtPexMessage {
+        let mut message = UtPexMessage{ added: added, dropped: dropped, bencode_size: 0 };
+        message.bencode_size = message.encode().len();
+
+        message
+    }
+
+    /// Parse a `UtPexMessage` from the given bytes.
+    pub fn parse_bytes(bytes: Bytes) -> io::Result<UtPexMessage> {
+        let bencode = try!(BencodeRef::decode(bytes.as_ref(), BDecodeOpt::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("Failed To Parse UtPexMessage As Bencode: {}", err))));
+        let bencode_dict = try!(bencode::CONVERT.convert_dict(&bencode, ROOT_ERROR_KEY));
+
+        let mut added = try!(parse_added(try!(bencode::parse_pex_bytes(bencode_dict, bencode::ADDED_IPV4_KEY)),
+                                         try!(bencode::parse_pex_bytes(bencode_dict, bencode::ADDED_IPV4_FLAGS_KEY)),
+                                         IPV4_COMPACT_LEN));
+        added.extend(try!(parse_added(try!(bencode::parse_pex_bytes(bencode_dict, bencode::ADDED_IPV6_KEY)),
+                                      try!(bencode::parse_pex_bytes(bencode_dict, bencode::ADDED_IPV6_FLAGS_KEY)),
+                                      IPV6_COMPACT_LEN)));
+
+        let mut dropped = try!(parse_compact_addrs(try!(bencode::parse_pex_bytes(bencode_dict, bencode::DROPPED_IPV4_KEY)),
+                                                   IPV4_COMPACT_LEN));
+        dropped.extend(try!(parse_compact_addrs(try!(bencode::parse_pex_bytes(bencode_dict, bencode::DROPPED_IPV6_KEY)),
+                                                IPV6_COMPACT_LEN)));
+
+        Ok(UtPexMessage{ added: added, dropped: dropped, bencode_size: bytes.len() })
+    }
+
+    /// Write the `UtPexMessage` out to the given writer.
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write
+    {
+        writer.write_all(self.encode().as_ref())
+    }
+
+    /// Size of the `UtPexMessage` in bytes.
+    pub fn message_size(&self) -> usize {
+        self.bencode_size
+    }
+
+    /// Peers that were added, along with their flags.
+    pub fn added(&self) -> &[(SocketAddr, UtPexFlags)] {
+        &self.added
+    }
+
+    /// Peers that were dropped.
+    pub fn dropped(&self) -> &[SocketAddr] {
+        &self.dropped
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let (mut added_v4, mut added_v4_flags) = (Vec::new(), Vec::new());
+        let (mut added_v6, mut added_v6_flags) = (Vec::new(), Vec::new());
+        for &(addr, flags) in self.added.iter() {
+            match addr {
+                SocketAddr::V4(v4_addr) => {
+                    added_v4.extend_from_slice(&convert::sock_v4_to_bytes_be(v4_addr));
+                    added_v4_flags.push(flags.bits());
+                },
+                SocketAddr::V6(v6_addr) => {
+                    added_v6.extend_from_slice(&convert::sock_v6_to_bytes_be(v6_addr));
+                    added_v6_flags.push(flags.bits());
+                }
+            }
+        }
+
+        let (mut dropped_v4, mut dropped_v6) = (Vec::new(), Vec::new());
+        for &addr in self.dropped.iter() {
+            match addr {
+                SocketAddr::V4(v4_addr) => dropped_v4.extend_from_slice(&convert::sock_v4_to_bytes_be(v4_addr)),
+                SocketAddr::V6(v6_addr) => dropped_v6.extend_from_slice(&convert::sock_v6_to_bytes_be(v6_addr))
+            }
+        }
+
+        (ben_map!{
+            bencode::ADDED_IPV4_KEY       => ben_bytes!(added_v4),
+            bencode::ADDED_IPV4_FLAGS_KEY => ben_bytes!(added_v4_flags),
+            bencode::ADDED_IPV6_KEY       => ben_bytes!(added_v6),
+            bencode::ADDED_IPV6_FLAGS_KEY => ben_bytes!(added_v6_flags),
+            bencode::DROPPED_IPV4_KEY     => ben_bytes!(dropped_v4),
+            bencode::DROPPED_IPV6_KEY     => ben_bytes!(dropped_v6)
+        }).encode()
+    }
+}
+
+/// Parse the compact addresses and their flags, flags are optional but must match the addresses if present.
+fn parse_added(compact: &[u8], flags: &[u8], compact_len: usize) -> io::Result<Vec<(SocketAddr, UtPexFlags)>> {
+    let addrs = try!(parse_compact_addrs(compact, compact_len));
+
+    if flags.is_empty() {
+        Ok(addrs.into_iter().map(|addr| (addr, UtPexFlags::default())).collect())
+    } else if flags.len() == addrs.len() {
+        Ok(addrs.into_iter().zip(flags.iter().map(|&flag| UtPexFlags::new(flag))).collect())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, format!("Failed To Parse UtPexMessage, {} Flags For {} Peers", flags.len(), addrs.len())))
+    }
+}
+
+/// Parse compact addresses of the given length, either 6 bytes (ipv4) or 18 bytes (ipv6).
+fn parse_compact_addrs(compact: &[u8], compact_len: usize) -> io::Result<Vec<SocketAddr>> {
+    if compact.len() % compact_len != 0 {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  format!("Failed To Parse UtPexMessage, Compact Length {} Is Not A Multiple Of {}", compact.len(), compact_len)))
+    }
+
+    Ok(compact.chunks(compact_len)
+        .map(|chunk| {
+            if compact_len == IPV4_COMPACT_LEN {
+                let mut v4_bytes = [0u8; IPV4_COMPACT_LEN];
+                v4_bytes.copy_from_slice(chunk);
+
+                SocketAddr::V4(convert::bytes_be_to_sock_v4(v4_bytes))
+            } else {
+                let mut v6_bytes = [0u8; IPV6_COMPACT_LEN];
+                v6_bytes.copy_from_slice(chunk);
+
+                SocketAddr::V6(convert::bytes_be_to_sock_v6(v6_bytes))
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use bytes::Bytes;
+
+    use super::{UtPexFlags, UtPexMessage};
+    use message::bencode;
+
+    #[test]
+    fn positive_parse_ipv4_message() {
+        // Two added peers (one seed supporting utp), one dropped peer
+        let bytes = (ben_map!{
+            bencode::ADDED_IPV4_KEY       => ben_bytes!(&[10, 0, 0, 1, 0x1A, 0xE1, 192, 168, 1, 20, 0xC8, 0xD5][..]),
+            bencode::ADDED_IPV4_FLAGS_KEY => ben_bytes!(&[0x01, 0x06][..]),
+            bencode::DROPPED_IPV4_KEY     => ben_bytes!(&[172, 16, 0, 3, 0x1A, 0xE9][..])
+        }).encode();
+
+        let message = UtPexMessage::parse_bytes(Bytes::from(bytes.clone())).unwrap();
+
+        let expected_added: Vec<(SocketAddr, UtPexFlags)> = vec![
+            ("10.0.0.1:6881".parse().unwrap(), UtPexFlags::new(0x01)),
+            ("192.168.1.20:51413".parse().unwrap(), UtPexFlags::new(0x06))
+        ];
+        assert_eq!(&expected_added[..], message.added());
+        assert_eq!(&["172.16.0.3:6889".parse::<SocketAddr>().unwrap()][..], message.dropped());
+        assert_eq!(bytes.len(), message.message_size());
+
+        let seed_flags = message.added()[1].1;
+        assert!(seed_flags.is_seed() && seed_flags.supports_utp() && !seed_flags.prefers_encryption());
+    }
+
+    #[test]
+    fn positive_parse_ipv6_message_without_flags() {
+        let mut added6 = vec![0u8; 16];
+        added6[15] = 1;
+        added6.extend_from_slice(&[0x1A, 0xE1]);
+
+        let bytes = (ben_map!{
+            bencode::ADDED_IPV6_KEY => ben_bytes!(added6)
+        }).encode();
+
+        let message = UtPexMessage::parse_bytes(Bytes::from(bytes)).unwrap();
+
+        assert_eq!(&[("[::1]:6881".parse().unwrap(), UtPexFlags::default())][..], message.added());
+        assert!(message.dropped().is_empty());
+    }
+
+    #[test]
+    fn positive_message_round_trip() {
+        let message = UtPexMessage::new(vec![("10.0.0.1:6881".parse().unwrap(), UtPexFlags::new(0x10)),
+                                             ("[::1]:6882".parse().unwrap(), UtPexFlags::new(0x02))],
+                                        vec!["[::2]:6883".parse().unwrap()]);
+
+        let mut bytes = Vec::new();
+        message.write_bytes(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), message.message_size());
+        assert_eq!(message, UtPexMessage::parse_bytes(Bytes::from(bytes)).unwrap());
+    }
+
+    #[test]
+    fn negative_parse_ipv4_bad_compact_length() {
+        let bytes = (ben_map!{
+            bencode::ADDED_IPV4_KEY => ben_bytes!(&[10, 0, 0, 1, 0x1A, 0xE1, 10][..])
+        }).encode();
+
+        assert!(UtPexMessage::parse_bytes(Bytes::from(bytes)).is_err());
+    }
+
+    #[test]
+    fn negative_parse_ipv6_bad_compact_length() {
+        let bytes = (ben_map!{
+            bencode::DROPPED_IPV6_KEY => ben_bytes!(&[0u8; 12][..])
+        }).encode();
+
+        assert!(UtPexMessage::parse_bytes(Bytes::from(bytes)).is_err());
+    }
+
+    #[test]
+    fn negative_parse_mismatched_flags() {
+        let bytes = (ben_map!{
+            bencode::ADDED_IPV4_KEY       => ben_bytes!(&[10, 0, 0, 1, 0x1A, 0xE1][..]),
+            bencode::ADDED_IPV4_FLAGS_KEY => ben_bytes!(&[0x01, 0x02][..])
+        }).encode();
+
+        assert!(UtPexMessage::parse_bytes(Bytes::from(bytes)).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+use crypto::digest::Digest;
+use crypto::rc4::Rc4;
+use crypto::sha1::Sha1;
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use num::bigint::BigUint;
+use num::{Num, One};
+use rand::{self, Rng};
+
+const DH_PRIME_HEX: &'static str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A63A36210000000000090563";
+const DH_GENERATOR:     u32   = 2;
+const PRIVATE_KEY_LEN:  usize = 20;
+const RC4_DISCARD_LEN:  usize = 1024;
+
+pub const DH_KEY_LEN: usize = 96;
+pub const HASH_LEN:   usize = 20;
+
+/// Diffie-Hellman key pair used to derive the shared secret.
+pub struct DhKeyPair {
+    private: BigUint,
+    public:  Vec<u8>
+}
+
+impl DhKeyPair {
+    /// Create a new `DhKeyPair` with a random private key.
+    pub fn random() -> DhKeyPair {
+        let mut private = [0u8; PRIVATE_KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut private);
+
+        DhKeyPair::from_private(&private)
+    }
+
+    /// Create a new `DhKeyPair` from the given private key.
+    pub fn from_private(private: &[u8]) -> DhKeyPair {
+        let private = BigUint::from_bytes_be(private);
+        let public = BigUint::from(DH_GENERATOR).modpow(&private, &dh_prime());
+
+        DhKeyPair{ private: private, public: to_key_bytes(&public) }
+    }
+
+    /// Public key to send to the peer.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public
+    }
+
+    /// Shared secret derived from the public key of the peer.
+    pub fn shared_secret(&self, remote_public: &[u8]) -> io::Result<Vec<u8>> {
+        let prime = dh_prime();
+        let remote = BigUint::from_bytes_be(remote_public);
+
+        // Reject keys which would leave us with a trivial secret
+        if remote_public.len() != DH_KEY_LEN || remote <= BigUint::one() || remote >= &prime - BigUint::one() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid Diffie-Hellman Public Key"))
+        }
+
+        Ok(to_key_bytes(&remote.modpow(&self.private, &prime)))
+    }
+}
+
+fn dh_prime() -> BigUint {
+    BigUint::from_str_radix(DH_PRIME_HEX, 16).unwrap()
+}
+
+/// Pad the key to the fixed key length, big endian.
+fn to_key_bytes(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut key_bytes = vec![0u8; DH_KEY_LEN - bytes.len()];
+    key_bytes.extend_from_slice(&bytes);
+
+    key_bytes
+}
+
+/// Sha1 hash of the concatenation of all parts.
+pub fn hash(parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut sha = Sha1::new();
+    for part in parts {
+        sha.input(part);
+    }
+
+    let mut hash = [0u8; HASH_LEN];
+    sha.result(&mut hash);
+
+    hash
+}
+
+/// Stream cipher for one direction of the connection.
+pub struct Cipher {
+    rc4: Rc4
+}
+
+impl Cipher {
+    /// Create a new `Cipher` keyed with the given key name (`keyA` or `keyB`), secret, and skey.
+    pub fn new(key_name: &[u8], secret: &[u8], skey: &[u8]) -> Cipher {
+        let mut cipher = Cipher{ rc4: Rc4::new(&hash(&[key_name, secret, skey])) };
+        cipher.process(&mut [0u8; RC4_DISCARD_LEN]);
+
+        cipher
+    }
+
+    /// Encrypt or decrypt the given bytes in place.
+    pub fn process(&mut self, bytes: &mut [u8]) {
+        let input = bytes.to_vec();
+
+        self.rc4.process(&input, bytes);
+    }
+}
@@ -0,0 +1,447 @@
+use std::io;
+
+use mse::CryptoPolicy;
+use mse::crypto::{self, Cipher, DhKeyPair, DH_KEY_LEN, HASH_LEN};
+use mse::stream::EncryptedStream;
+
+use bip_util::bt::InfoHash;
+use byteorder::{ByteOrder, BigEndian, WriteBytesExt};
+use futures::future::{self, Future, Loop};
+use rand::{self, Rng};
+use tokio_io::{io as tio, AsyncRead, AsyncWrite};
+
+const VC_LEN:      usize = 8;
+const CRYPTO_LEN:  usize = 4;
+const MAX_PAD_LEN: usize = 512;
+
+pub const CRYPTO_PLAINTEXT: u32 = 0x01;
+pub const CRYPTO_RC4:       u32 = 0x02;
+
+const BT_PROTOCOL_HEADER: &'static [u8] = b"\x13BitTorrent protocol";
+
+/// Initiate the MSE handshake over the given stream, for the torrent with the given `InfoHash`.
+pub fn initiate<S>(stream: S, skey: InfoHash, policy: CryptoPolicy) -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>>
+    where S: AsyncRead + AsyncWrite + 'static {
+    initiate_with(stream, skey, policy, DhKeyPair::random(), random_pad())
+}
+
+/// Accept the MSE handshake over the given stream, for a torrent with any of the given `InfoHash`es.
+///
+/// If the policy allows plaintext, peers starting with a plaintext BitTorrent handshake are also
+/// accepted, in which case the handshake bytes read so far are returned first by the stream.
+pub fn accept<S>(stream: S, skeys: Vec<InfoHash>, policy: CryptoPolicy) -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>>
+    where S: AsyncRead + AsyncWrite + 'static {
+    accept_with(stream, skeys, policy, DhKeyPair::random(), random_pad())
+}
+
+fn random_pad() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut pad = vec![0u8; rng.gen_range(0, MAX_PAD_LEN + 1)];
+    rng.fill_bytes(&mut pad);
+
+    pad
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn xor_hash(lhs: &[u8; HASH_LEN], rhs: &[u8; HASH_LEN]) -> [u8; HASH_LEN] {
+    let mut result = [0u8; HASH_LEN];
+    for (index, byte) in result.iter_mut().enumerate() {
+        *byte = lhs[index] ^ rhs[index];
+    }
+
+    result
+}
+
+/// Read from the stream, one byte at a time, until the last bytes read equal the pattern.
+fn read_until<S>(stream: S, pattern: Vec<u8>, max_len: usize) -> Box<Future<Item=S, Error=io::Error>>
+    where S: AsyncRead + 'static {
+    Box::new(future::loop_fn((stream, Vec::with_capacity(pattern.len()), 0), move |(stream, mut window, read)| {
+        let pattern = pattern.clone();
+
+        tio::read_exact(stream, [0u8; 1]).and_then(move |(stream, byte)| {
+            window.push(byte[0]);
+            if window.len() > pattern.len() {
+                window.remove(0);
+            }
+
+            if window == pattern {
+                Ok(Loop::Break(stream))
+            } else if read + 1 >= max_len {
+                Err(invalid_data("Failed To Synchronize On MSE Handshake"))
+            } else {
+                Ok(Loop::Continue((stream, window, read + 1)))
+            }
+        })
+    }))
+}
+
+fn finish<S>(stream: S, select: u32, encrypt: Cipher, decrypt: Cipher, initial: Vec<u8>) -> EncryptedStream<S> {
+    if select == CRYPTO_RC4 {
+        EncryptedStream::rc4(stream, encrypt, decrypt, initial)
+    } else {
+        EncryptedStream::plaintext(stream, initial)
+    }
+}
+
+//----------------------------------------------------------------------------//
+
+pub fn initiate_with<S>(stream: S, skey: InfoHash, policy: CryptoPolicy, key: DhKeyPair, pad: Vec<u8>)
+    -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>> where S: AsyncRead + AsyncWrite + 'static {
+    let mut public_pad = key.public_key().to_vec();
+    public_pad.extend_from_slice(&pad);
+
+    let composed_future = tio::write_all(stream, public_pad)
+        .and_then(|(stream, _)| tio::read_exact(stream, vec![0u8; DH_KEY_LEN]))
+        .and_then(move |(stream, remote_public)| {
+            let secret = try!(key.shared_secret(&remote_public));
+            let mut encrypt = Cipher::new(b"keyA", &secret, skey.as_ref());
+            let mut decrypt = Cipher::new(b"keyB", &secret, skey.as_ref());
+
+            let mut message = crypto::hash(&[b"req1", &secret]).to_vec();
+            message.extend_from_slice(&xor_hash(&crypto::hash(&[b"req2", skey.as_ref()]), &crypto::hash(&[b"req3", &secret])));
+
+            // VC, crypto_provide, len(PadC), PadC, len(IA), IA
+            let mut encrypted = vec![0u8; VC_LEN];
+            try!(encrypted.write_u32::<BigEndian>(policy.provide()));
+            try!(encrypted.write_u16::<BigEndian>(0));
+            try!(encrypted.write_u16::<BigEndian>(0));
+            encrypt.process(&mut encrypted);
+            message.extend_from_slice(&encrypted);
+
+            // Peer starts its encrypted bytes with VC, which we have to find after PadB
+            let mut remote_vc = vec![0u8; VC_LEN];
+            decrypt.process(&mut remote_vc);
+
+            Ok((stream, message, encrypt, decrypt, remote_vc))
+        })
+        .and_then(|(stream, message, encrypt, decrypt, remote_vc)| {
+            tio::write_all(stream, message)
+                .and_then(move |(stream, _)| read_until(stream, remote_vc, MAX_PAD_LEN + VC_LEN))
+                .map(move |stream| (stream, encrypt, decrypt))
+        })
+        .and_then(move |(stream, encrypt, mut decrypt)| {
+            tio::read_exact(stream, [0u8; CRYPTO_LEN + 2]).and_then(move |(stream, mut header)| {
+                decrypt.process(&mut header);
+
+                let select = BigEndian::read_u32(&header[..CRYPTO_LEN]);
+                let pad_len = BigEndian::read_u16(&header[CRYPTO_LEN..]) as usize;
+                if (select != CRYPTO_PLAINTEXT && select != CRYPTO_RC4) || select & policy.provide() == 0 {
+                    Err(invalid_data("Peer Selected Crypto Method We Did Not Provide"))
+                } else if pad_len > MAX_PAD_LEN {
+                    Err(invalid_data("Peer Sent MSE Padding Over 512 Bytes"))
+                } else {
+                    Ok((stream, encrypt, decrypt, select, pad_len))
+                }
+            })
+        })
+        .and_then(|(stream, encrypt, mut decrypt, select, pad_len)| {
+            tio::read_exact(stream, vec![0u8; pad_len]).map(move |(stream, mut pad)| {
+                decrypt.process(&mut pad);
+
+                finish(stream, select, encrypt, decrypt, Vec::new())
+            })
+        });
+
+    Box::new(composed_future)
+}
+
+pub fn accept_with<S>(stream: S, skeys: Vec<InfoHash>, policy: CryptoPolicy, key: DhKeyPair, pad: Vec<u8>)
+    -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>> where S: AsyncRead + AsyncWrite + 'static {
+    let composed_future = tio::read_exact(stream, vec![0u8; BT_PROTOCOL_HEADER.len()])
+        .and_then(move |(stream, header)| -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>> {
+            if header != BT_PROTOCOL_HEADER {
+                accept_encrypted(stream, header, skeys, policy, key, pad)
+            } else if policy.select(CRYPTO_PLAINTEXT).is_some() {
+                Box::new(future::ok(EncryptedStream::plaintext(stream, header)))
+            } else {
+                Box::new(future::err(invalid_data("Peer Started Plaintext Handshake, Which We Do Not Allow")))
+            }
+        });
+
+    Box::new(composed_future)
+}
+
+fn accept_encrypted<S>(stream: S, mut remote_public: Vec<u8>, skeys: Vec<InfoHash>, policy: CryptoPolicy, key: DhKeyPair, pad: Vec<u8>)
+    -> Box<Future<Item=EncryptedStream<S>, Error=io::Error>> where S: AsyncRead + AsyncWrite + 'static {
+    let remaining_public = DH_KEY_LEN - remote_public.len();
+
+    let composed_future = tio::read_exact(stream, vec![0u8; remaining_public])
+        .and_then(move |(stream, rest)| {
+            remote_public.extend_from_slice(&rest);
+            let secret = try!(key.shared_secret(&remote_public));
+
+            let mut public_pad = key.public_key().to_vec();
+            public_pad.extend_from_slice(&pad);
+
+            Ok((stream, secret, public_pad))
+        })
+        .and_then(|(stream, secret, public_pad)| {
+            let req1 = crypto::hash(&[b"req1", &secret]).to_vec();
+
+            tio::write_all(stream, public_pad)
+                .and_then(move |(stream, _)| read_until(stream, req1, MAX_PAD_LEN + HASH_LEN))
+                .and_then(|stream| tio::read_exact(stream, [0u8; HASH_LEN]))
+                .map(move |(stream, req2_req3)| (stream, secret, req2_req3))
+        })
+        .and_then(move |(stream, secret, req2_req3)| {
+            let req2 = xor_hash(&req2_req3, &crypto::hash(&[b"req3", &secret]));
+            let skey = try!(skeys.into_iter()
+                .find(|skey| crypto::hash(&[b"req2", skey.as_ref()]) == req2)
+                .ok_or(invalid_data("Peer Requested Torrent We Are Not Serving")));
+
+            let encrypt = Cipher::new(b"keyB", &secret, skey.as_ref());
+            let decrypt = Cipher::new(b"keyA", &secret, skey.as_ref());
+
+            Ok((stream, encrypt, decrypt))
+        })
+        .and_then(|(stream, encrypt, mut decrypt)| {
+            tio::read_exact(stream, [0u8; VC_LEN + CRYPTO_LEN + 2]).and_then(move |(stream, mut header)| {
+                decrypt.process(&mut header);
+
+                let provide = BigEndian::read_u32(&header[VC_LEN..VC_LEN + CRYPTO_LEN]);
+                let pad_len = BigEndian::read_u16(&header[VC_LEN + CRYPTO_LEN..]) as usize;
+                if header[..VC_LEN] != [0u8; VC_LEN] {
+                    Err(invalid_data("Peer Sent Invalid MSE Verification Constant"))
+                } else if pad_len > MAX_PAD_LEN {
+                    Err(invalid_data("Peer Sent MSE Padding Over 512 Bytes"))
+                } else {
+                    Ok((stream, encrypt, decrypt, provide, pad_len))
+                }
+            })
+        })
+        .and_then(|(stream, encrypt, mut decrypt, provide, pad_len)| {
+            tio::read_exact(stream, vec![0u8; pad_len + 2]).map(move |(stream, mut pad)| {
+                decrypt.process(&mut pad);
+                let initial_len = BigEndian::read_u16(&pad[pad_len..]) as usize;
+
+                (stream, encrypt, decrypt, provide, initial_len)
+            })
+        })
+        .and_then(move |(stream, mut encrypt, mut decrypt, provide, initial_len)| {
+            tio::read_exact(stream, vec![0u8; initial_len]).and_then(move |(stream, mut initial)| {
+                decrypt.process(&mut initial);
+                let select = try!(policy.select(provide)
+                    .ok_or(invalid_data("Peer Did Not Provide A Crypto Method We Allow")));
+
+                // VC, crypto_select, len(PadD), PadD
+                let mut message = vec![0u8; VC_LEN];
+                try!(message.write_u32::<BigEndian>(select));
+                try!(message.write_u16::<BigEndian>(0));
+                encrypt.process(&mut message);
+
+                Ok((stream, encrypt, decrypt, select, initial, message))
+            })
+        })
+        .and_then(|(stream, encrypt, decrypt, select, initial, message)| {
+            tio::write_all(stream, message).map(move |(stream, _)| finish(stream, select, encrypt, decrypt, initial))
+        });
+
+    Box::new(composed_future)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use super::{initiate, accept, initiate_with, accept_with, BT_PROTOCOL_HEADER};
+    use mse::CryptoPolicy;
+    use mse::crypto::DhKeyPair;
+
+    use bip_util::bt::{self, InfoHash};
+    use futures::{Future, Poll, Async};
+    use tokio_core::net::{TcpListener, TcpStream};
+    use tokio_core::reactor::Core;
+    use futures::stream::Stream;
+    use tokio_io::{io as tio, AsyncRead, AsyncWrite};
+
+    // Recorded exchange between an initiator with private key [0x11; 20] and PadA [1, 2, 3, 4, 5], and
+    // a responder with private key [0x22; 20] and PadB [0xBB; 3], for skey [0x55; 20], where both prefer
+    // RC4. After the handshake, the initiator sends "ping" and the responder sends "pong".
+    const INITIATOR_BYTES: &'static [u8] = &[
+        0x34, 0x76, 0xdc, 0x8d, 0x12, 0x36, 0x97, 0xfc, 0xc6, 0x9e, 0x91, 0x21, 0x61, 0x8a, 0x08, 0x51,
+        0x4f, 0xfa, 0x53, 0x65, 0x06, 0xdf, 0x20, 0x9c, 0xe9, 0x56, 0xad, 0x3c, 0xf8, 0x19, 0xf7, 0x6f,
+        0x3f, 0xce, 0xbc, 0x46, 0xf4, 0xe8, 0xbe, 0x36, 0x4e, 0xc8, 0xf1, 0x44, 0x5b, 0x9d, 0xbf, 0x8a,
+        0x95, 0x28, 0xca, 0x7b, 0xfb, 0xc8, 0x9a, 0xd1, 0x3f, 0x28, 0x13, 0x76, 0x40, 0xa2, 0xcf, 0xd1,
+        0x05, 0x5d, 0x55, 0xd5, 0xcc, 0x66, 0x76, 0xd1, 0x8b, 0x41, 0x0e, 0x08, 0x66, 0x31, 0x30, 0x9e,
+        0x95, 0xfa, 0x7b, 0x0d, 0xae, 0x2b, 0x83, 0x79, 0x18, 0x6b, 0x17, 0xad, 0x34, 0x72, 0xc7, 0x55,
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x32, 0xdd, 0x7e, 0x3b, 0x16, 0xe8, 0xd7, 0xa1, 0xb4, 0x44, 0xea,
+        0x3f, 0x41, 0x67, 0xe2, 0x02, 0x51, 0x4f, 0x9f, 0xeb, 0x66, 0x3a, 0x9e, 0x2d, 0x76, 0x0c, 0x75,
+        0x34, 0xad, 0x70, 0x06, 0xf8, 0xd0, 0x63, 0xc4, 0xf6, 0xc8, 0x42, 0x1d, 0xfe, 0x9f, 0x37, 0xd3,
+        0xeb, 0x8b, 0x9d, 0xa3, 0x84, 0x73, 0xc6, 0x7d, 0xff, 0xf0, 0x4c, 0xfb, 0x7e, 0xab, 0x85, 0x1a,
+        0xb6,
+    ];
+    const RESPONDER_BYTES: &'static [u8] = &[
+        0x08, 0x16, 0x09, 0x2a, 0xa9, 0xb6, 0xa0, 0xbf, 0xdd, 0x7b, 0x86, 0xa6, 0xba, 0xc8, 0x30, 0xde,
+        0xa6, 0xc7, 0x1c, 0x40, 0x08, 0xa1, 0xfb, 0x53, 0xb6, 0x9d, 0xc1, 0xc4, 0xac, 0xd9, 0x9f, 0x8f,
+        0x3c, 0xf7, 0x9d, 0x9d, 0x9a, 0xc4, 0x11, 0xe1, 0x8b, 0x59, 0xc0, 0xb5, 0x81, 0x46, 0x91, 0x2e,
+        0x51, 0xd0, 0xe5, 0x08, 0xd2, 0x00, 0x64, 0xb8, 0x63, 0x3a, 0x85, 0xe2, 0x00, 0x61, 0x82, 0x3d,
+        0x92, 0x84, 0xb9, 0x07, 0xb2, 0xdd, 0x06, 0x12, 0x88, 0x4e, 0x61, 0xe9, 0xeb, 0xb8, 0xc9, 0xb4,
+        0x62, 0xed, 0xa2, 0xf2, 0x27, 0x49, 0x1a, 0x60, 0x82, 0xbc, 0x58, 0xd6, 0xa1, 0x31, 0x5c, 0x39,
+        0xbb, 0xbb, 0xbb, 0x59, 0xbb, 0xc3, 0x00, 0x88, 0xc4, 0xd3, 0xca, 0x20, 0xc8, 0x26, 0x26, 0x8f,
+        0x7a, 0xc8, 0x45, 0x50, 0x16,
+    ];
+
+    struct MockStream {
+        input:  Cursor<Vec<u8>>,
+        output: Vec<u8>
+    }
+
+    impl MockStream {
+        fn new(input: &[u8]) -> MockStream {
+            MockStream{ input: Cursor::new(input.to_vec()), output: Vec::new() }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for MockStream { }
+
+    impl AsyncWrite for MockStream {
+        fn shutdown(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    fn skey() -> InfoHash {
+        [0x55u8; bt::INFO_HASH_LEN].into()
+    }
+
+    /// Run the initiator and responder futures over a local tcp connection.
+    fn connect<I, A, IF, AF>(initiator: I, acceptor: A) -> (io::Result<IF::Item>, io::Result<AF::Item>)
+        where I: FnOnce(TcpStream) -> IF, A: FnOnce(TcpStream) -> AF,
+              IF: Future<Error=io::Error>, AF: Future<Error=io::Error> {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accepted = listener.incoming().into_future()
+            .map_err(|(err, _)| err)
+            .and_then(|(opt_socket, _)| acceptor(opt_socket.unwrap().0))
+            .then(|result| Ok::<_, ()>(result));
+        let initiated = TcpStream::connect(&addr, &handle)
+            .and_then(initiator)
+            .then(|result| Ok::<_, ()>(result));
+
+        let (initiated, accepted) = core.run(initiated.join(accepted)).unwrap();
+
+        (initiated, accepted)
+    }
+
+    #[test]
+    fn positive_initiate_recorded_exchange() {
+        let mock = MockStream::new(RESPONDER_BYTES);
+        let key = DhKeyPair::from_private(&[0x11u8; 20]);
+
+        let stream = initiate_with(mock, skey(), CryptoPolicy::PreferRc4, key, vec![1, 2, 3, 4, 5]).wait().unwrap();
+        assert!(stream.is_encrypted());
+
+        let (stream, _) = tio::write_all(stream, b"ping").wait().unwrap();
+        let (stream, pong) = tio::read_exact(stream, [0u8; 4]).wait().unwrap();
+
+        assert_eq!(b"pong", &pong);
+        assert_eq!(INITIATOR_BYTES, &stream.get_ref().output[..]);
+    }
+
+    #[test]
+    fn positive_accept_recorded_exchange() {
+        let mock = MockStream::new(INITIATOR_BYTES);
+        let key = DhKeyPair::from_private(&[0x22u8; 20]);
+
+        let stream = accept_with(mock, vec![[0u8; bt::INFO_HASH_LEN].into(), skey()], CryptoPolicy::PreferRc4, key, vec![0xBB; 3]).wait().unwrap();
+        assert!(stream.is_encrypted());
+
+        let (stream, ping) = tio::read_exact(stream, [0u8; 4]).wait().unwrap();
+        let (stream, _) = tio::write_all(stream, b"pong").wait().unwrap();
+
+        assert_eq!(b"ping", &ping);
+        assert_eq!(RESPONDER_BYTES, &stream.get_ref().output[..]);
+    }
+
+    #[test]
+    fn positive_round_trip_rc4() {
+        let (initiated, accepted) = connect(
+            |socket| initiate(socket, skey(), CryptoPolicy::PreferRc4)
+                .and_then(|stream| tio::write_all(stream, b"hello"))
+                .and_then(|(stream, _)| tio::read_exact(stream, [0u8; 5]))
+                .map(|(stream, reply)| (stream.is_encrypted(), reply)),
+            |socket| accept(socket, vec![skey()], CryptoPolicy::Rc4Only)
+                .and_then(|stream| tio::read_exact(stream, [0u8; 5]))
+                .and_then(|(stream, hello)| tio::write_all(stream, b"world").map(move |(stream, _)| (stream.is_encrypted(), hello)))
+        );
+
+        assert_eq!((true, *b"world"), initiated.unwrap());
+        assert_eq!((true, *b"hello"), accepted.unwrap());
+    }
+
+    #[test]
+    fn positive_round_trip_plaintext_fallback() {
+        let (initiated, accepted) = connect(
+            |socket| initiate(socket, skey(), CryptoPolicy::PreferRc4)
+                .and_then(|stream| tio::write_all(stream, b"hello"))
+                .map(|(stream, _)| stream.is_encrypted()),
+            |socket| accept(socket, vec![skey()], CryptoPolicy::PreferPlaintext)
+                .and_then(|stream| tio::read_exact(stream, [0u8; 5]))
+                .map(|(stream, hello)| (stream.is_encrypted(), hello))
+        );
+
+        assert_eq!(false, initiated.unwrap());
+        assert_eq!((false, *b"hello"), accepted.unwrap());
+    }
+
+    #[test]
+    fn positive_accept_plaintext_bittorrent_handshake() {
+        let mut input = BT_PROTOCOL_HEADER.to_vec();
+        input.extend_from_slice(b"rest");
+
+        let stream = accept(MockStream::new(&input), vec![skey()], CryptoPolicy::PreferRc4).wait().unwrap();
+        let (stream, header) = tio::read_exact(stream, vec![0u8; input.len()]).wait().unwrap();
+
+        assert!(!stream.is_encrypted());
+        assert_eq!(input, header);
+    }
+
+    #[test]
+    fn negative_accept_plaintext_bittorrent_handshake_rc4_only() {
+        let stream = MockStream::new(BT_PROTOCOL_HEADER);
+
+        assert!(accept(stream, vec![skey()], CryptoPolicy::Rc4Only).wait().is_err());
+    }
+
+    #[test]
+    fn negative_accept_unknown_skey() {
+        let mock = MockStream::new(INITIATOR_BYTES);
+        let key = DhKeyPair::from_private(&[0x22u8; 20]);
+
+        assert!(accept_with(mock, vec![[0u8; bt::INFO_HASH_LEN].into()], CryptoPolicy::PreferRc4, key, Vec::new()).wait().is_err());
+    }
+
+    #[test]
+    fn positive_round_trip_rc4_required_by_initiator() {
+        let (initiated, accepted) = connect(
+            |socket| initiate(socket, skey(), CryptoPolicy::Rc4Only).map(|stream| stream.is_encrypted()),
+            |socket| accept(socket, vec![skey()], CryptoPolicy::PreferPlaintext).map(|stream| stream.is_encrypted())
+        );
+
+        assert!(initiated.unwrap());
+        assert!(accepted.unwrap());
+    }
+}
@@ -0,0 +1,48 @@
+//! Message stream encryption (MSE) for peer connections.
+//!
+//! MSE runs before the BitTorrent handshake, negotiating a shared secret via
+//! Diffie-Hellman, keyed on the info hash of the torrent, after which the
+//! connection is either RC4 encrypted or continues in plaintext.
+
+mod crypto;
+mod handshake;
+mod stream;
+mod transport;
+
+pub use self::handshake::{initiate, accept};
+pub use self::stream::EncryptedStream;
+pub use self::transport::{MseTransport, MseListener};
+
+use self::handshake::{CRYPTO_PLAINTEXT, CRYPTO_RC4};
+
+/// Policy for which crypto methods we provide and select during the MSE handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CryptoPolicy {
+    /// Only allow RC4 encrypted connections.
+    Rc4Only,
+    /// Prefer RC4, but fall back to plaintext if the peer accepts it.
+    PreferRc4,
+    /// Prefer plaintext, but use RC4 if the peer requires it.
+    PreferPlaintext
+}
+
+impl CryptoPolicy {
+    /// Crypto methods we provide as the initiator.
+    fn provide(&self) -> u32 {
+        match *self {
+            CryptoPolicy::Rc4Only => CRYPTO_RC4,
+            _                     => CRYPTO_RC4 | CRYPTO_PLAINTEXT
+        }
+    }
+
+    /// Crypto method we select out of the ones provided by the initiator.
+    fn select(&self, provide: u32) -> Option<u32> {
+        let preference = match *self {
+            CryptoPolicy::Rc4Only         => [CRYPTO_RC4, CRYPTO_RC4],
+            CryptoPolicy::PreferRc4       => [CRYPTO_RC4, CRYPTO_PLAINTEXT],
+            CryptoPolicy::PreferPlaintext => [CRYPTO_PLAINTEXT, CRYPTO_RC4]
+        };
+
+        preference.iter().find(|method| provide & **method != 0).map(|method| *method)
+    }
+}
@@ -0,0 +1,117 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+use mse::crypto::Cipher;
+
+use futures::{Async, Poll};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// Stream which, once the MSE handshake completes, encrypts and decrypts the
+/// payload stream if RC4 was negotiated, or passes it through as plaintext.
+pub struct EncryptedStream<S> {
+    stream:  S,
+    ciphers: Option<(Cipher, Cipher)>,
+    // Decrypted bytes received during the handshake, returned before reading from the stream
+    initial: Vec<u8>,
+    // Encrypted bytes that the stream has not yet accepted
+    pending: Vec<u8>
+}
+
+impl<S> EncryptedStream<S> {
+    /// Create an `EncryptedStream` which encrypts with the first `Cipher`, and decrypts with the second.
+    pub fn rc4(stream: S, encrypt: Cipher, decrypt: Cipher, initial: Vec<u8>) -> EncryptedStream<S> {
+        EncryptedStream{ stream: stream, ciphers: Some((encrypt, decrypt)), initial: initial, pending: Vec::new() }
+    }
+
+    /// Create an `EncryptedStream` which passes data through unmodified.
+    pub fn plaintext(stream: S, initial: Vec<u8>) -> EncryptedStream<S> {
+        EncryptedStream{ stream: stream, ciphers: None, initial: initial, pending: Vec::new() }
+    }
+
+    /// Whether or not the payload stream is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.ciphers.is_some()
+    }
+
+    /// Access the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+}
+
+impl<S> EncryptedStream<S> where S: Write {
+    /// Write out any encrypted bytes the stream has not yet accepted.
+    fn write_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            let written = try!(self.stream.write(&self.pending));
+            if written == 0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "Failed To Write Encrypted Bytes"))
+            }
+
+            self.pending.drain(..written);
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> Read for EncryptedStream<S> where S: Read {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.initial.is_empty() {
+            let read = cmp::min(buf.len(), self.initial.len());
+            buf[..read].copy_from_slice(&self.initial[..read]);
+            self.initial.drain(..read);
+
+            return Ok(read)
+        }
+
+        let read = try!(self.stream.read(buf));
+        if let Some((_, ref mut decrypt)) = self.ciphers {
+            decrypt.process(&mut buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<S> Write for EncryptedStream<S> where S: Write {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.ciphers.is_none() {
+            return self.stream.write(buf)
+        }
+        // Once bytes are encrypted, the cipher has advanced, so we have to hold on to
+        // them until the stream accepts them, and only accept new bytes once it has
+        try!(self.write_pending());
+
+        self.pending.extend_from_slice(buf);
+        if let Some((ref mut encrypt, _)) = self.ciphers {
+            encrypt.process(&mut self.pending);
+        }
+
+        match self.write_pending() {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(buf.len()),
+            Err(err)                                                 => Err(err),
+            Ok(())                                                   => Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.write_pending());
+
+        self.stream.flush()
+    }
+}
+
+impl<S> AsyncRead for EncryptedStream<S> where S: AsyncRead { }
+
+impl<S> AsyncWrite for EncryptedStream<S> where S: AsyncWrite {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self.write_pending() {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            Err(err)                                                 => return Err(err),
+            Ok(())                                                   => ()
+        }
+
+        self.stream.shutdown()
+    }
+}
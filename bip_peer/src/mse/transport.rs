@@ -0,0 +1,90 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use mse::CryptoPolicy;
+use mse::handshake;
+use mse::stream::EncryptedStream;
+
+use bip_handshake::{LocalAddr, Transport};
+use bip_util::bt::InfoHash;
+use futures::{Future, Poll, Stream};
+use tokio_core::reactor::Handle;
+use tokio_timer;
+
+const MAX_CONCURRENT_ACCEPTS: usize = 32;
+
+/// Peers that take longer than this to complete the MSE handshake are dropped, freeing up an accept slot.
+const ACCEPT_TIMEOUT_SECS: u64 = 10;
+
+/// `Transport` which performs the MSE handshake over an underlying `Transport`.
+///
+/// Since MSE is keyed on the torrent, connections are made and accepted for a single `InfoHash`.
+/// This can be passed to a `HandshakerBuilder`, so that the BitTorrent handshake runs over the
+/// `EncryptedStream`.
+pub struct MseTransport<T> {
+    transport: T,
+    skey:      InfoHash,
+    policy:    CryptoPolicy
+}
+
+impl<T> MseTransport<T> {
+    /// Create a new `MseTransport` for the given `InfoHash`.
+    pub fn new(transport: T, skey: InfoHash, policy: CryptoPolicy) -> MseTransport<T> {
+        MseTransport{ transport: transport, skey: skey, policy: policy }
+    }
+}
+
+impl<T> Transport for MseTransport<T> where T: Transport {
+    type Socket = EncryptedStream<T::Socket>;
+    type FutureSocket = Box<Future<Item=Self::Socket, Error=io::Error>>;
+    type Listener = MseListener<T::Socket>;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::FutureSocket> {
+        let connect = try!(self.transport.connect(addr, handle));
+        let (skey, policy) = (self.skey, self.policy);
+
+        Ok(Box::new(connect.and_then(move |socket| handshake::initiate(socket, skey, policy))))
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        let listener = try!(self.transport.listen(addr, handle));
+        let listen_addr = try!(listener.local_addr());
+        let (skey, policy) = (self.skey, self.policy);
+        let timer = tokio_timer::wheel().build();
+
+        // Peers failing the MSE handshake are dropped, without tearing down the listener
+        let accepted = listener
+            .map(move |(socket, addr)| {
+                let accept = handshake::accept(socket, vec![skey], policy);
+
+                timer.timeout(accept, Duration::from_secs(ACCEPT_TIMEOUT_SECS))
+                    .then(move |result| Ok::<_, io::Error>(result.ok().map(|socket| (socket, addr))))
+            })
+            .buffer_unordered(MAX_CONCURRENT_ACCEPTS)
+            .filter_map(|opt_accepted| opt_accepted);
+
+        Ok(MseListener{ listen_addr: listen_addr, listener: Box::new(accepted) })
+    }
+}
+
+/// Listener yielding connections that completed the MSE handshake.
+pub struct MseListener<S> {
+    listen_addr: SocketAddr,
+    listener:    Box<Stream<Item=(EncryptedStream<S>, SocketAddr), Error=io::Error>>
+}
+
+impl<S> Stream for MseListener<S> {
+    type Item = (EncryptedStream<S>, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.listener.poll()
+    }
+}
+
+impl<S> LocalAddr for MseListener<S> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.listen_addr)
+    }
+}
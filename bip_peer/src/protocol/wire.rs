@@ -1,13 +1,15 @@
 use std::io::{self, Write};
 
-use message::{PeerWireProtocolMessage, ExtendedMessage, BitsExtensionMessage};
+use message::{self, PeerWireProtocolMessage, ExtendedMessage, BitsExtensionMessage};
 use protocol::{PeerProtocol, NestedPeerProtocol};
 
+use bip_handshake::Extensions;
 use bytes::Bytes;
 
 /// Protocol for peer wire messages.
 pub struct PeerWireProtocol<P> {
-    ext_protocol: P
+    ext_protocol:   P,
+    fast_extension: bool
 }
 
 impl<P> PeerWireProtocol<P> {
@@ -17,7 +19,27 @@ impl<P> PeerWireProtocol<P> {
     /// as the peer wire protocol. This means it should expect a 4 byte (`u32`) message
     /// length prefix. Nested protocols will NOT have their `bytes_needed` method called.
     pub fn new(ext_protocol: P) -> PeerWireProtocol<P> {
-        PeerWireProtocol{ ext_protocol: ext_protocol }
+        PeerWireProtocol{ ext_protocol: ext_protocol, fast_extension: false }
+    }
+
+    /// Create a new `PeerWireProtocol` with the given extension protocol, activating
+    /// messages for any extension bits set in the given `Extensions`.
+    ///
+    /// The given `Extensions` should be the intersection of ours and the peer's, so that
+    /// messages are only activated if both sides set the bit during the handshake.
+    pub fn with_extensions(ext_protocol: P, extensions: &Extensions) -> PeerWireProtocol<P> {
+        PeerWireProtocol{ ext_protocol: ext_protocol, fast_extension: message::fast_extension_enabled(extensions) }
+    }
+
+    /// Fails if the given message belongs to an extension that was not activated.
+    fn check_activated(&self, message: &PeerWireProtocolMessage<P>) -> io::Result<()>
+        where P: PeerProtocol {
+        match message {
+            &PeerWireProtocolMessage::BitsExtension(ref msg) if msg.is_fast_extension() && !self.fast_extension => {
+                Err(io::Error::new(io::ErrorKind::Other, "Fast Extension Message Sent Without Fast Extension Bit"))
+            },
+            _                                                                                                => Ok(())
+        }
     }
 }
 
@@ -35,12 +57,19 @@ impl<P> PeerProtocol for PeerWireProtocol<P> where P: PeerProtocol + NestedPeerP
 
                 Ok(PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(msg)))
             },
+            Ok(msg)                                                                         => {
+                try!(self.check_activated(&msg));
+
+                Ok(msg)
+            },
             other                                                                           => other
         }
     }
 
     fn write_bytes<W>(&mut self, message: &Self::ProtocolMessage, writer: W) -> io::Result<()>
         where W: Write {
+        try!(self.check_activated(message));
+
         match (message.write_bytes(writer, &mut self.ext_protocol), message) {
             (Ok(()), &PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::Extended(ref msg))) => {
                 self.ext_protocol.sent_message(msg);
@@ -54,4 +83,40 @@ impl<P> PeerProtocol for PeerWireProtocol<P> where P: PeerProtocol + NestedPeerP
     fn message_size(&mut self, message: &Self::ProtocolMessage) -> usize {
         message.message_size(&mut self.ext_protocol)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::PeerWireProtocol;
+    use message::{PeerWireProtocolMessage, BitsExtensionMessage};
+    use protocol::PeerProtocol;
+    use protocol::null::NullProtocol;
+
+    use bip_handshake::Extensions;
+    use bytes::Bytes;
+
+    fn fast_extensions() -> Extensions {
+        Extensions::from([0, 0, 0, 0, 0, 0, 0, 0x04])
+    }
+
+    #[test]
+    fn positive_fast_extension_activated() {
+        let mut protocol = PeerWireProtocol::with_extensions(NullProtocol::new(), &fast_extensions());
+
+        let mut bytes = Vec::new();
+        protocol.write_bytes(&PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::HaveAll), &mut bytes).unwrap();
+
+        match protocol.parse_bytes(Bytes::from(bytes)).unwrap() {
+            PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::HaveAll) => (),
+            _                                                                     => panic!("Failed To Parse HaveAll Message")
+        }
+    }
+
+    #[test]
+    fn negative_fast_extension_not_activated() {
+        let mut protocol = PeerWireProtocol::new(NullProtocol::new());
+
+        let mut bytes = Vec::new();
+        assert!(protocol.write_bytes(&PeerWireProtocolMessage::BitsExtension(BitsExtensionMessage::HaveNone), &mut bytes).is_err());
+        assert!(protocol.parse_bytes(Bytes::from(&[0u8, 0, 0, 1, 0x0F][..])).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Module for choosing which peers we upload to.
+
+use bip_peer::PeerInfo;
+use rate::{self, RateHistory};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+const DEFAULT_UNCHOKE_SLOTS: usize = 4;
+
+const RECHOKE_INTERVAL_MILLIS: u64 = 10 * 1000;
+const OPTIMISTIC_INTERVAL_MILLIS: u64 = 30 * 1000;
+
+/// Enumeration of messages that can be received from a `Choker`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OChokeMessage {
+    /// Send a `Choke` message to the peer.
+    Choke(PeerInfo),
+    /// Send an `Unchoke` message to the peer.
+    Unchoke(PeerInfo),
+}
+
+#[derive(Default)]
+struct PeerState {
+    interested: bool,
+    unchoked: bool,
+    download: RateHistory,
+    upload: RateHistory,
+}
+
+/// Tit-for-tat choker, with optimistic unchoking.
+///
+/// Every ten seconds, the interested peers with the highest rolling download
+/// rate are unchoked, and the rest are choked. When seeding, peers are ranked
+/// by the rate we upload to them instead. In addition, one other interested
+/// peer is optimistically unchoked, which rotates every thirty seconds so that
+/// new peers get a chance to show what rate they can reciprocate at.
+pub struct Choker {
+    peers: HashMap<PeerInfo, PeerState>,
+    // Peers in the order they will be considered for an optimistic unchoke
+    rotation: VecDeque<PeerInfo>,
+    optimistic: Option<PeerInfo>,
+    unchoke_slots: usize,
+    seeding: bool,
+    since_rechoke: u64,
+    since_optimistic: u64,
+}
+
+impl Choker {
+    /// Create a new `Choker`.
+    ///
+    /// The first tick will always rechoke peers.
+    pub fn new() -> Choker {
+        Choker {
+            peers: HashMap::new(),
+            rotation: VecDeque::new(),
+            optimistic: None,
+            unchoke_slots: DEFAULT_UNCHOKE_SLOTS,
+            seeding: false,
+            since_rechoke: RECHOKE_INTERVAL_MILLIS,
+            since_optimistic: OPTIMISTIC_INTERVAL_MILLIS,
+        }
+    }
+
+    /// Set the number of peers unchoked based on their rate, not including the optimistic unchoke.
+    pub fn set_unchoke_slots(&mut self, slots: usize) {
+        self.unchoke_slots = slots;
+    }
+
+    /// Set whether or not we are seeding, in which case peers are ranked by upload rate.
+    pub fn set_seeding(&mut self, seeding: bool) {
+        self.seeding = seeding;
+    }
+
+    /// Whether or not the given peer is currently unchoked.
+    pub fn is_unchoked(&self, peer: &PeerInfo) -> bool {
+        self.peers.get(peer).map(|state| state.unchoked).unwrap_or(false)
+    }
+
+    /// Peer that is currently optimistically unchoked.
+    pub fn optimistic(&self) -> Option<PeerInfo> {
+        self.optimistic
+    }
+
+    /// Rolling download rate from the given peer, in bytes per second.
+    pub fn download_rate(&self, peer: &PeerInfo) -> u64 {
+        self.peers.get(peer).map(|state| state.download.rate()).unwrap_or(0)
+    }
+
+    /// Rolling upload rate to the given peer, in bytes per second.
+    pub fn upload_rate(&self, peer: &PeerInfo) -> u64 {
+        self.peers.get(peer).map(|state| state.upload.rate()).unwrap_or(0)
+    }
+
+    /// Add the given peer, which starts out choked and not interested.
+    pub fn peer_connected(&mut self, peer: PeerInfo) {
+        self.peer_disconnected(&peer);
+
+        self.peers.insert(peer, PeerState::default());
+        self.rotation.push_back(peer);
+    }
+
+    /// Remove the given peer.
+    pub fn peer_disconnected(&mut self, peer: &PeerInfo) {
+        if self.peers.remove(peer).is_some() {
+            self.rotation.retain(|rotation_peer| rotation_peer != peer);
+        }
+
+        if self.optimistic.as_ref() == Some(peer) {
+            self.optimistic = None;
+        }
+    }
+
+    /// The given peer is interested in pieces we have.
+    pub fn peer_interested(&mut self, peer: &PeerInfo) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.interested = true;
+        }
+    }
+
+    /// The given peer is not interested in pieces we have.
+    pub fn peer_not_interested(&mut self, peer: &PeerInfo) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.interested = false;
+        }
+    }
+
+    /// Downloaded the given number of bytes from the peer.
+    pub fn downloaded(&mut self, peer: &PeerInfo, bytes: u64) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.download.add_bytes(bytes);
+        }
+    }
+
+    /// Uploaded the given number of bytes to the peer.
+    pub fn uploaded(&mut self, peer: &PeerInfo, bytes: u64) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.upload.add_bytes(bytes);
+        }
+    }
+
+    /// A span of time has passed, sample peer rates and rechoke if it is time to.
+    ///
+    /// Returns choke and unchoke messages for peers whose state changed.
+    pub fn tick(&mut self, duration: Duration) -> Vec<OChokeMessage> {
+        let millis = rate::duration_millis(duration);
+
+        for state in self.peers.values_mut() {
+            state.download.add_sample(millis);
+            state.upload.add_sample(millis);
+        }
+
+        self.since_rechoke += millis;
+        self.since_optimistic += millis;
+
+        if self.since_rechoke >= RECHOKE_INTERVAL_MILLIS {
+            self.since_rechoke = 0;
+
+            self.rechoke()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn rechoke(&mut self) -> Vec<OChokeMessage> {
+        let mut ranked: Vec<(PeerInfo, u64)> = self
+            .rotation
+            .iter()
+            .filter_map(|peer| self.peers.get(peer).map(|state| (peer, state)))
+            .filter(|&(_, state)| state.interested)
+            .map(|(peer, state)| {
+                let rate = if self.seeding { state.upload.rate() } else { state.download.rate() };
+
+                (*peer, rate)
+            })
+            .collect();
+        ranked.sort_by(|&(_, rate_one), &(_, rate_two)| rate_two.cmp(&rate_one));
+
+        let regular: HashSet<PeerInfo> = ranked.into_iter().take(self.unchoke_slots).map(|(peer, _)| peer).collect();
+        self.rotate_optimistic(&regular);
+
+        let mut messages = Vec::new();
+        for peer in self.rotation.iter() {
+            let unchoke = regular.contains(peer) || self.optimistic.as_ref() == Some(peer);
+            let state = self.peers.get_mut(peer).unwrap();
+
+            if state.unchoked != unchoke {
+                state.unchoked = unchoke;
+
+                messages.push(if unchoke {
+                    OChokeMessage::Unchoke(*peer)
+                } else {
+                    OChokeMessage::Choke(*peer)
+                });
+            }
+        }
+
+        messages
+    }
+
+    /// Pick the next optimistic unchoke, if the current one expired or is no longer eligible.
+    fn rotate_optimistic(&mut self, regular: &HashSet<PeerInfo>) {
+        let current_eligible = self.optimistic.map(|peer| self.optimistic_eligible(&peer, regular)).unwrap_or(false);
+        if current_eligible && self.since_optimistic < OPTIMISTIC_INTERVAL_MILLIS {
+            return;
+        }
+
+        let opt_position = self
+            .rotation
+            .iter()
+            .position(|peer| Some(*peer) != self.optimistic && self.optimistic_eligible(peer, regular));
+        match opt_position {
+            Some(position) => {
+                // Moving the peer to the back makes every other peer get a turn before it does again
+                let peer = self.rotation.remove(position).unwrap();
+                self.rotation.push_back(peer);
+
+                self.optimistic = Some(peer);
+            },
+            None if current_eligible => (),
+            None => self.optimistic = None,
+        }
+        self.since_optimistic = 0;
+    }
+
+    fn optimistic_eligible(&self, peer: &PeerInfo, regular: &HashSet<PeerInfo>) -> bool {
+        let interested = self.peers.get(peer).map(|state| state.interested).unwrap_or(false);
+
+        interested && !regular.contains(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Choker, OChokeMessage};
+    use bip_handshake::Extensions;
+    use bip_peer::PeerInfo;
+    use bip_util::bt;
+    use std::time::Duration;
+
+    const KB: u64 = 1024;
+
+    fn peer(id: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new())
+    }
+
+    // Interested peers one through count
+    fn choker_with_peers(count: u8) -> Choker {
+        let mut choker = Choker::new();
+        for id in 1..(count + 1) {
+            choker.peer_connected(peer(id));
+            choker.peer_interested(&peer(id));
+        }
+
+        choker
+    }
+
+    // Each second, download rate[i] kilobytes from peer i + 1
+    fn feed_download(choker: &mut Choker, rates: &[u64], seconds: u64) -> Vec<OChokeMessage> {
+        let mut messages = Vec::new();
+        for _ in 0..seconds {
+            for (index, rate) in rates.iter().enumerate() {
+                choker.downloaded(&peer(index as u8 + 1), rate * KB);
+            }
+            messages.extend(choker.tick(Duration::from_secs(1)));
+        }
+
+        messages
+    }
+
+    fn unchoked(choker: &Choker, count: u8) -> Vec<u8> {
+        (1..(count + 1)).filter(|id| choker.is_unchoked(&peer(*id))).collect()
+    }
+
+    #[test]
+    fn positive_unchokes_top_peers_and_one_optimistic() {
+        let mut choker = choker_with_peers(6);
+        choker.set_unchoke_slots(3);
+
+        feed_download(&mut choker, &[10, 50, 20, 40, 30, 5], 10);
+
+        assert_eq!(50 * KB, choker.download_rate(&peer(2)));
+        let optimistic = choker.optimistic().unwrap();
+        assert!(optimistic != peer(2) && optimistic != peer(4) && optimistic != peer(5));
+
+        let mut expected = vec![2, 4, 5, optimistic.addr().port() as u8];
+        expected.sort();
+        assert_eq!(expected, unchoked(&choker, 6));
+    }
+
+    #[test]
+    fn positive_only_emits_changes() {
+        let mut choker = choker_with_peers(3);
+        choker.set_unchoke_slots(1);
+
+        let messages = choker.tick(Duration::from_secs(1));
+        assert_eq!(2, messages.len());
+        assert!(messages.iter().all(|message| match message {
+            &OChokeMessage::Unchoke(_) => true,
+            _ => false,
+        }));
+
+        assert!(choker.tick(Duration::from_secs(9)).is_empty());
+    }
+
+    #[test]
+    fn positive_rechokes_peer_that_slows_down() {
+        let mut choker = choker_with_peers(3);
+        choker.set_unchoke_slots(1);
+
+        feed_download(&mut choker, &[100, 50, 0], 10);
+        assert!(choker.is_unchoked(&peer(1)));
+
+        // Rolling rate still favors peer one shortly after it slows down
+        let messages = feed_download(&mut choker, &[0, 50, 0], 10);
+        assert!(choker.is_unchoked(&peer(1)));
+        assert!(messages.is_empty());
+
+        // Peer two was the optimistic unchoke, and takes over the regular slot
+        feed_download(&mut choker, &[0, 50, 0], 10);
+        assert!(choker.is_unchoked(&peer(2)));
+        assert_eq!(Some(peer(1)), choker.optimistic());
+    }
+
+    #[test]
+    fn positive_optimistic_rotates_every_thirty_seconds() {
+        let mut choker = choker_with_peers(4);
+        choker.set_unchoke_slots(1);
+
+        let rates = [100, 0, 0, 0];
+        feed_download(&mut choker, &rates, 1);
+        let first = choker.optimistic().unwrap();
+
+        feed_download(&mut choker, &rates, 29);
+        assert_eq!(Some(first), choker.optimistic());
+
+        let messages = feed_download(&mut choker, &rates, 1);
+        let second = choker.optimistic().unwrap();
+        assert!(first != second);
+        assert_eq!(vec![OChokeMessage::Choke(first), OChokeMessage::Unchoke(second)], messages);
+
+        // Every candidate gets a turn before the first one comes around again
+        feed_download(&mut choker, &rates, 30);
+        let third = choker.optimistic().unwrap();
+        assert!(third != first && third != second && third != peer(1));
+
+        feed_download(&mut choker, &rates, 30);
+        assert_eq!(Some(first), choker.optimistic());
+    }
+
+    #[test]
+    fn positive_seeding_ranks_by_upload_rate() {
+        let mut choker = choker_with_peers(3);
+        choker.set_unchoke_slots(1);
+        choker.set_seeding(true);
+
+        choker.downloaded(&peer(1), 100 * KB);
+        choker.uploaded(&peer(2), 100 * KB);
+        choker.uploaded(&peer(3), 10 * KB);
+        choker.tick(Duration::from_secs(10));
+
+        assert!(choker.is_unchoked(&peer(2)));
+        assert_eq!(Some(peer(1)), choker.optimistic());
+    }
+
+    #[test]
+    fn positive_uninterested_peers_stay_choked() {
+        let mut choker = choker_with_peers(2);
+        choker.peer_connected(peer(3));
+
+        feed_download(&mut choker, &[0, 0, 100], 10);
+
+        assert_eq!(vec![1, 2], unchoked(&choker, 3));
+    }
+
+    #[test]
+    fn positive_disconnected_optimistic_replaced_on_rechoke() {
+        let mut choker = choker_with_peers(3);
+        choker.set_unchoke_slots(1);
+
+        feed_download(&mut choker, &[100, 0, 0], 1);
+        let first = choker.optimistic().unwrap();
+
+        choker.peer_disconnected(&first);
+        assert_eq!(None, choker.optimistic());
+
+        feed_download(&mut choker, &[100, 0, 0], 10);
+        let second = choker.optimistic().unwrap();
+        assert!(second != first && second != peer(1));
+        assert!(choker.is_unchoked(&second));
+    }
+}
@@ -0,0 +1,146 @@
+//! Decoding of the client name and version embedded in a peer id.
+
+use bip_util::bt::PeerId;
+
+// Azureus style clients, identified by the two characters following the leading dash
+const AZUREUS_CLIENTS: &'static [(&'static [u8; 2], &'static str)] = &[
+    (b"AZ", "Vuze"),
+    (b"BC", "BitComet"),
+    (b"BI", "bip-rs"),
+    (b"BT", "BitTorrent"),
+    (b"DE", "Deluge"),
+    (b"KT", "KTorrent"),
+    (b"LT", "libtorrent"),
+    (b"lt", "libTorrent"),
+    (b"qB", "qBittorrent"),
+    (b"TR", "Transmission"),
+    (b"TX", "Tixati"),
+    (b"UM", "\u{b5}Torrent Mac"),
+    (b"UT", "\u{b5}Torrent"),
+];
+
+// Shadow style clients, identified by the leading character
+const SHADOW_CLIENTS: &'static [(u8, &'static str)] = &[
+    (b'A', "ABC"),
+    (b'O', "Osprey Permaseed"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+];
+
+// Shadow style version characters, where each character encodes a number in this alphabet
+const SHADOW_ALPHABET: &'static [u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz.-";
+const SHADOW_VERSION_LEN: usize = 5;
+// Versions using all five characters are followed by this instead of padding
+const SHADOW_VERSION_END: &'static [u8] = b"---";
+
+/// Decode the client name and version from the given peer id, if it follows a known convention.
+///
+/// Azureus style peer ids (`-XX1234-`) give each version digit as a component, while Shadow
+/// style peer ids (`S58B-----`) give each version character as a component, padded with dashes.
+/// Clients not in our table of known clients are named by their client id.
+pub fn client_name(peer_id: &PeerId) -> Option<String> {
+    let bytes = peer_id.as_ref();
+
+    decode_azureus(bytes).or_else(|| decode_shadow(bytes))
+}
+
+fn decode_azureus(bytes: &[u8]) -> Option<String> {
+    if bytes[0] != b'-' || bytes[7] != b'-' || !bytes[1..3].iter().all(|byte| byte.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let components = bytes[3..7]
+        .iter()
+        .map(|&byte| match byte {
+            b'0'..=b'9' => Some((byte - b'0') as u32),
+            b'A'..=b'Z' => Some((byte - b'A') as u32 + 10),
+            _ => None,
+        })
+        .collect::<Option<Vec<u32>>>();
+
+    components.map(|components| {
+        let name = AZUREUS_CLIENTS
+            .iter()
+            .find(|&&(client, _)| &client[..] == &bytes[1..3])
+            .map(|&(_, name)| name.to_owned())
+            .unwrap_or_else(|| String::from_utf8_lossy(&bytes[1..3]).into_owned());
+
+        format!("{} {}", name, join_version(&components))
+    })
+}
+
+fn decode_shadow(bytes: &[u8]) -> Option<String> {
+    let name = match SHADOW_CLIENTS.iter().find(|&&(client, _)| client == bytes[0]) {
+        Some(&(_, name)) => name,
+        None => return None,
+    };
+
+    let version_end = 1 + SHADOW_VERSION_LEN;
+    let version = &bytes[1..version_end];
+    let version_len = version.iter().position(|&byte| byte == b'-').unwrap_or(SHADOW_VERSION_LEN);
+
+    // Anything after the version has to be padding, otherwise this is likely a random peer id
+    let padded = if version_len == SHADOW_VERSION_LEN {
+        &bytes[version_end..version_end + SHADOW_VERSION_END.len()] == SHADOW_VERSION_END
+    } else {
+        version[version_len..].iter().all(|&byte| byte == b'-')
+    };
+    if version_len == 0 || !padded {
+        return None;
+    }
+
+    let components = version[..version_len]
+        .iter()
+        .map(|byte| SHADOW_ALPHABET.iter().position(|alpha| alpha == byte).map(|position| position as u32))
+        .collect::<Option<Vec<u32>>>();
+
+    components.map(|components| format!("{} {}", name, join_version(&components)))
+}
+
+fn join_version(components: &[u32]) -> String {
+    components.iter().map(|component| component.to_string()).collect::<Vec<String>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use bip_util::bt::{self, PeerId};
+
+    fn peer_id(prefix: &[u8]) -> PeerId {
+        let mut bytes = [b'x'; bt::PEER_ID_LEN];
+        bytes[..prefix.len()].copy_from_slice(prefix);
+
+        bytes.into()
+    }
+
+    #[test]
+    fn positive_decode_azureus_clients() {
+        assert_eq!(Some("qBittorrent 4.2.5.0".to_owned()), super::client_name(&peer_id(b"-qB4250-")));
+        assert_eq!(Some("Transmission 2.9.4.0".to_owned()), super::client_name(&peer_id(b"-TR2940-")));
+        assert_eq!(Some("\u{b5}Torrent 3.5.5.0".to_owned()), super::client_name(&peer_id(b"-UT3550-")));
+        assert_eq!(Some("Deluge 1.3.15.0".to_owned()), super::client_name(&peer_id(b"-DE13F0-")));
+        assert_eq!(Some("bip-rs 0.1.0.0".to_owned()), super::client_name(&peer_id(b"-BI0100-")));
+    }
+
+    #[test]
+    fn positive_decode_unknown_azureus_client() {
+        assert_eq!(Some("XX 1.2.3.4".to_owned()), super::client_name(&peer_id(b"-XX1234-")));
+    }
+
+    #[test]
+    fn positive_decode_shadow_clients() {
+        assert_eq!(Some("Shadow 5.8.11".to_owned()), super::client_name(&peer_id(b"S58B-----")));
+        assert_eq!(Some("BitTornado 0.3.18".to_owned()), super::client_name(&peer_id(b"T03I-----")));
+        assert_eq!(Some("ABC 3.1.0".to_owned()), super::client_name(&peer_id(b"A310-----")));
+        assert_eq!(Some("Tribler 3.2.0.1.5".to_owned()), super::client_name(&peer_id(b"R32015---")));
+    }
+
+    #[test]
+    fn negative_decode_random_peer_id() {
+        assert_eq!(None, super::client_name(&peer_id(b"")));
+        assert_eq!(None, super::client_name(&peer_id(b"Sxxxxx")));
+        assert_eq!(None, super::client_name(&peer_id(b"-qB42!0-")));
+    }
+}
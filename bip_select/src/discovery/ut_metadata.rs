@@ -27,12 +27,13 @@ use rand::{self, Rng};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::cmp;
 use std::collections::hash_map::Entry;
-use std::io::Write;
 use std::time::Duration;
 
 const REQUEST_TIMEOUT_MILLIS: u64 = 2000;
 const MAX_REQUEST_SIZE: usize = 16 * 1024;
+const MAX_METADATA_SIZE: i64 = 16 * 1024 * 1024;
 
 const MAX_ACTIVE_REQUESTS: usize = 100;
 const MAX_PEER_REQUESTS: usize = 100;
@@ -41,6 +42,8 @@ struct PendingInfo {
     messages: Vec<UtMetadataRequestMessage>,
     left: usize,
     bytes: Vec<u8>,
+    // Peers that sent us pieces, blamed if the metadata fails validation
+    contributors: HashSet<PeerInfo>,
 }
 
 struct ActiveRequest {
@@ -55,8 +58,31 @@ struct PeerRequest {
 }
 
 struct ActivePeers {
-    peers: HashSet<PeerInfo>,
-    metadata_size: i64,
+    peers: HashMap<PeerInfo, i64>,
+}
+
+impl ActivePeers {
+    /// Metadata size reported by the most peers, if any.
+    fn metadata_size(&self) -> Option<i64> {
+        let mut size_counts = HashMap::new();
+        for &metadata_size in self.peers.values() {
+            *size_counts.entry(metadata_size).or_insert(0) += 1;
+        }
+
+        size_counts
+            .into_iter()
+            .max_by_key(|&(metadata_size, count)| (count, metadata_size))
+            .map(|(metadata_size, _)| metadata_size)
+    }
+
+    /// Peers that reported the given metadata size.
+    fn peers_with_size(&self, metadata_size: i64) -> Vec<&PeerInfo> {
+        self.peers
+            .iter()
+            .filter(|&(_, &size)| size == metadata_size)
+            .map(|(info, _)| info)
+            .collect()
+    }
 }
 
 /// Module for sending/receiving metadata from other peers.
@@ -142,18 +168,14 @@ impl UtMetadataModule {
             opt_metadata_size
         );
         // If peer supports it, but they dont have the metadata size, then they probably dont have the file yet...
+        // Peers that give us a nonsensical metadata size are ignored, so they cant make us allocate huge buffers
         match (our_support, they_support, opt_metadata_size) {
-            (true, true, Some(metadata_size)) => {
+            (true, true, Some(metadata_size)) if metadata_size > 0 && metadata_size <= MAX_METADATA_SIZE => {
                 self.active_peers
                     .entry(*info.hash())
-                    .or_insert_with(|| {
-                        ActivePeers {
-                            peers: HashSet::new(),
-                            metadata_size: metadata_size,
-                        }
-                    })
+                    .or_insert_with(|| ActivePeers { peers: HashMap::new() })
                     .peers
-                    .insert(info);
+                    .insert(info, metadata_size);
             },
             _ => {
                 ()
@@ -246,26 +268,48 @@ impl UtMetadataModule {
 
         // If so, go ahead and process it, if not, ignore it (could ban peer...)
         if let Some(index) = opt_index {
-            self.active_requests.swap_remove(index);
+            let request = self.active_requests.swap_remove(index);
 
-            if let Some(&mut Some(ref mut pending)) = self.pending_map.get_mut(&info.hash()) {
-                let data_offset = (data.piece() as usize) * MAX_REQUEST_SIZE;
+            let is_valid = match self.pending_map.get_mut(&info.hash()) {
+                Some(&mut Some(ref mut pending)) => write_pending_piece(pending, info, &data),
+                _ => true,
+            };
 
-                pending.left -= 1;
-                (&mut pending.bytes.as_mut_slice()[data_offset..])
-                    .write(data.data().as_ref())
-                    .unwrap();
+            // Peer lied about the metadata size or sent us a bad piece, dont ask them again
+            if !is_valid {
+                info!("Peer {:?} Sent Invalid Data For Piece {:?}", info.addr(), data.piece());
+                self.remove_active_peer(info, request.message);
             }
         }
 
         Ok(AsyncSink::Ready)
     }
 
-    fn recv_reject(&mut self, _info: PeerInfo, _reject: UtMetadataRejectMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
-        // TODO: Remove any requests after receiving a reject, for now, we will just timeout
+    fn recv_reject(&mut self, info: PeerInfo, reject: UtMetadataRejectMessage) -> StartSend<IDiscoveryMessage, DiscoveryError> {
+        let opt_index = self.active_requests
+            .iter()
+            .position(|request| request.sent_to == info && request.message.piece() == reject.piece());
+
+        // Peer wont give us the piece, so stop asking them and give the request to someone else
+        if let Some(index) = opt_index {
+            let request = self.active_requests.swap_remove(index);
+
+            self.remove_active_peer(info, request.message);
+        }
+
         Ok(AsyncSink::Ready)
     }
 
+    fn remove_active_peer(&mut self, info: PeerInfo, message: UtMetadataRequestMessage) {
+        if let Some(active) = self.active_peers.get_mut(info.hash()) {
+            active.peers.remove(&info);
+        }
+
+        if let Some(&mut Some(ref mut pending)) = self.pending_map.get_mut(info.hash()) {
+            pending.messages.push(message);
+        }
+    }
+
     //-------------------------------------------------------------------------------//
 
     fn retrieve_completed_download(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
@@ -297,24 +341,30 @@ impl UtMetadataModule {
     }
 
     fn retrieve_piece_request(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
+        let all_active_peers = &self.active_peers;
+
         for (hash, opt_pending) in self.pending_map.iter_mut() {
             let has_ready_requests = opt_pending
                 .as_ref()
                 .map(|pending| !pending.messages.is_empty())
                 .unwrap_or(false);
-            let has_active_peers = self.active_peers
-                .get(hash)
-                .map(|peers| !peers.peers.is_empty())
-                .unwrap_or(false);
+            // Only ask peers that agree with the metadata size we are downloading
+            let active_peers = opt_pending
+                .as_ref()
+                .and_then(|pending| {
+                    all_active_peers
+                        .get(hash)
+                        .map(|peers| peers.peers_with_size(pending.bytes.len() as i64))
+                })
+                .unwrap_or(Vec::new());
 
-            if has_ready_requests && has_active_peers {
+            if has_ready_requests && !active_peers.is_empty() {
                 let pending = opt_pending.as_mut().unwrap();
 
-                let mut active_peers = self.active_peers.get(hash).unwrap().peers.iter();
                 let num_active_peers = active_peers.len();
                 let selected_peer_num = rand::thread_rng().next_u32() as usize % num_active_peers;
 
-                let selected_peer = active_peers.nth(selected_peer_num).unwrap();
+                let selected_peer = active_peers[selected_peer_num];
                 let selected_message = pending.messages.pop().unwrap();
 
                 self.active_requests
@@ -332,29 +382,37 @@ impl UtMetadataModule {
     }
 
     fn retrieve_piece_response(&mut self) -> Option<Result<ODiscoveryMessage, DiscoveryError>> {
-        while let Some(request) = self.peer_requests.pop_front() {
+        let completed_map = &self.completed_map;
+
+        self.peer_requests.pop_front().map(|request| {
             let hash = request.send_to.hash();
             let piece = request.request.piece();
 
             let start = piece as usize * MAX_REQUEST_SIZE;
-            let end = start + MAX_REQUEST_SIZE;
-
-            if let Some(data) = self.completed_map.get(hash) {
-                if start <= data.len() && end <= data.len() {
-                    let info_slice = &data[start..end];
-                    let mut info_payload = BytesMut::with_capacity(info_slice.len());
 
-                    info_payload.extend_from_slice(info_slice);
-                    let message = UtMetadataDataMessage::new(piece, info_slice.len() as i64, info_payload.freeze());
+            let opt_data = completed_map.get(hash).and_then(|data| {
+                if piece >= 0 && start < data.len() {
+                    // Last piece may be smaller than the max request size
+                    let end = cmp::min(start + MAX_REQUEST_SIZE, data.len());
 
-                    return Some(Ok(ODiscoveryMessage::SendUtMetadataMessage(request.send_to, UtMetadataMessage::Data(message))));
+                    Some((&data[start..end], data.len()))
                 } else {
-                    // Peer asked for a piece outside of the range...dont respond to that
+                    None
                 }
-            }
-        }
+            });
 
-        None
+            let message = if let Some((info_slice, total_size)) = opt_data {
+                let mut info_payload = BytesMut::with_capacity(info_slice.len());
+                info_payload.extend_from_slice(info_slice);
+
+                UtMetadataMessage::Data(UtMetadataDataMessage::new(piece, total_size as i64, info_payload.freeze()))
+            } else {
+                // We dont have the metadata, or the peer asked for a piece outside of the range
+                UtMetadataMessage::Reject(UtMetadataRejectMessage::new(piece))
+            };
+
+            Ok(ODiscoveryMessage::SendUtMetadataMessage(request.send_to, message))
+        })
     }
 
     //-------------------------------------------------------------------------------//
@@ -367,7 +425,8 @@ impl UtMetadataModule {
             if opt_pending.is_none() {
                 let opt_pending_info = self.active_peers
                     .get(hash)
-                    .map(|active_peers| pending_info_from_metadata_size(active_peers.metadata_size));
+                    .and_then(ActivePeers::metadata_size)
+                    .map(pending_info_from_metadata_size);
 
                 *opt_pending = opt_pending_info;
             }
@@ -388,6 +447,7 @@ impl UtMetadataModule {
         // Sweep over all "pending" requests, and check if completed downloads pass hash validation
         // If not, set them back to None so they get re-initialized
         // If yes, mark down that we have completed downloads
+        let active_peers = &mut self.active_peers;
         for (&expected_hash, opt_pending) in self.pending_map.iter_mut() {
             let should_reset = opt_pending
                 .as_mut()
@@ -408,7 +468,14 @@ impl UtMetadataModule {
                 .unwrap_or(false);
 
             if should_reset {
-                *opt_pending = None;
+                // We cant tell which piece was bad, so stop using any peer that gave us a piece
+                let contributors = opt_pending.take().unwrap().contributors;
+
+                if let Some(active) = active_peers.get_mut(&expected_hash) {
+                    for contributor in contributors {
+                        active.peers.remove(&contributor);
+                    }
+                }
             }
         }
 
@@ -473,7 +540,31 @@ fn pending_info_from_metadata_size(metadata_size: i64) -> PendingInfo {
         messages: messages,
         left: num_pieces,
         bytes: bytes,
+        contributors: HashSet::new(),
+    }
+}
+
+/// Write the piece from the data message in to the pending metadata.
+///
+/// Returns false if the data does not line up with the metadata we are downloading.
+fn write_pending_piece(pending: &mut PendingInfo, info: PeerInfo, data: &UtMetadataDataMessage) -> bool {
+    let metadata_size = pending.bytes.len();
+    let data_offset = (data.piece() as usize) * MAX_REQUEST_SIZE;
+
+    if data.total_size() != metadata_size as i64 || data_offset >= metadata_size {
+        return false;
+    }
+
+    let expected_length = cmp::min(MAX_REQUEST_SIZE, metadata_size - data_offset);
+    if data.data().len() != expected_length {
+        return false;
     }
+
+    pending.bytes[data_offset..(data_offset + expected_length)].copy_from_slice(data.data().as_ref());
+    pending.left -= 1;
+    pending.contributors.insert(info);
+
+    true
 }
 
 //-------------------------------------------------------------------------------//
@@ -576,3 +667,294 @@ impl Stream for UtMetadataModule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{UtMetadataModule, MAX_METADATA_SIZE, MAX_REQUEST_SIZE};
+    use ControlMessage;
+    use bip_handshake::Extensions;
+    use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
+    use bip_peer::PeerInfo;
+    use bip_peer::messages::{ExtendedType, UtMetadataDataMessage, UtMetadataMessage, UtMetadataRejectMessage,
+                             UtMetadataRequestMessage};
+    use bip_peer::messages::builders::ExtendedMessageBuilder;
+    use bip_util::bt;
+    use bytes::Bytes;
+    use discovery::{IDiscoveryMessage, ODiscoveryMessage};
+    use extended::{ExtendedListener, ExtendedPeerInfo};
+    use futures::{future, Async, Future, Sink, Stream};
+    use std::collections::HashMap;
+
+    /// Peer on the other side of the connection, responds to requests with its own view of the metadata.
+    struct MockPeer {
+        info: PeerInfo,
+        metadata: Vec<u8>,
+        reject: bool,
+    }
+
+    impl MockPeer {
+        fn new(port: u16, metainfo: &Metainfo, metadata: Vec<u8>) -> MockPeer {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let info = PeerInfo::new(addr, [port as u8; bt::PEER_ID_LEN].into(), metainfo.info().info_hash(), Extensions::new());
+
+            MockPeer {
+                info: info,
+                metadata: metadata,
+                reject: false,
+            }
+        }
+
+        fn rejecting(mut self) -> MockPeer {
+            self.reject = true;
+            self
+        }
+
+        fn respond(&self, request: UtMetadataRequestMessage) -> UtMetadataMessage {
+            let start = request.piece() as usize * MAX_REQUEST_SIZE;
+            let end = ::std::cmp::min(start + MAX_REQUEST_SIZE, self.metadata.len());
+
+            if self.reject || start >= self.metadata.len() {
+                UtMetadataMessage::Reject(UtMetadataRejectMessage::new(request.piece()))
+            } else {
+                let data = Bytes::from(&self.metadata[start..end]);
+
+                UtMetadataMessage::Data(UtMetadataDataMessage::new(request.piece(), self.metadata.len() as i64, data))
+            }
+        }
+
+        fn connect(&self, module: &mut UtMetadataModule, metadata_size: i64) {
+            let ours = ExtendedMessageBuilder::new()
+                .with_extended_type(ExtendedType::UtMetadata, Some(5))
+                .build();
+            let theirs = ExtendedMessageBuilder::new()
+                .with_extended_type(ExtendedType::UtMetadata, Some(3))
+                .with_metadata_size(Some(metadata_size))
+                .build();
+
+            module.on_update(&self.info, &ExtendedPeerInfo::new(Some(ours), Some(theirs)));
+        }
+    }
+
+    /// Metainfo with an info dictionary spanning multiple metadata pieces.
+    fn metainfo() -> Metainfo {
+        let data = vec![0u8; 1000];
+
+        let accessor = DirectAccessor::new("MyFile.txt", &data);
+        let bytes = MetainfoBuilder::new()
+            .set_piece_length(PieceLength::Custom(1))
+            .build(1, accessor, |_| ())
+            .unwrap();
+
+        Metainfo::from_bytes(bytes).unwrap()
+    }
+
+    /// Run the given closure within a task, so the module can park itself.
+    fn with_task<F>(test: F)
+    where
+        F: FnOnce(),
+    {
+        future::lazy(|| {
+            test();
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap()
+    }
+
+    /// Drive the module, answering each request with the peer it was sent to, until the metainfo is downloaded.
+    ///
+    /// Returns the downloaded metainfo along with the number of requests each peer received.
+    fn drive_download(module: &mut UtMetadataModule, peers: &[MockPeer]) -> (Metainfo, HashMap<PeerInfo, usize>) {
+        let mut requests_sent = HashMap::new();
+
+        loop {
+            match module.poll().unwrap() {
+                Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(info, UtMetadataMessage::Request(request)))) => {
+                    *requests_sent.entry(info).or_insert(0) += 1;
+
+                    let peer = peers.iter().find(|peer| peer.info == info).unwrap();
+                    module
+                        .start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(info, peer.respond(request)))
+                        .unwrap();
+                },
+                Async::Ready(Some(ODiscoveryMessage::DownloadedMetainfo(metainfo))) => return (metainfo, requests_sent),
+                Async::NotReady => panic!("UtMetadataModule Stalled Before Downloading Metainfo"),
+                other => panic!("Received Unexpected Message {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn positive_download_metadata_multiple_pieces() {
+        with_task(|| {
+            let metainfo = metainfo();
+            let metadata = metainfo.info().to_bytes();
+            assert!(metadata.len() > MAX_REQUEST_SIZE);
+
+            let mut module = UtMetadataModule::new();
+            let peer = MockPeer::new(1, &metainfo, metadata.clone());
+
+            module
+                .start_send(IDiscoveryMessage::DownloadMetainfo(metainfo.info().info_hash()))
+                .unwrap();
+            peer.connect(&mut module, metadata.len() as i64);
+
+            let (downloaded, requests_sent) = drive_download(&mut module, &[peer]);
+
+            assert_eq!(metainfo.info().info_hash(), downloaded.info().info_hash());
+            assert_eq!(2, requests_sent.values().sum::<usize>());
+        });
+    }
+
+    #[test]
+    fn positive_download_metadata_after_reject() {
+        with_task(|| {
+            let metainfo = metainfo();
+            let metadata = metainfo.info().to_bytes();
+
+            let mut module = UtMetadataModule::new();
+            let rejecting_peer = MockPeer::new(1, &metainfo, metadata.clone()).rejecting();
+            let honest_peer = MockPeer::new(2, &metainfo, metadata.clone());
+
+            module
+                .start_send(IDiscoveryMessage::DownloadMetainfo(metainfo.info().info_hash()))
+                .unwrap();
+            rejecting_peer.connect(&mut module, metadata.len() as i64);
+
+            let request = match module.poll().unwrap() {
+                Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(info, UtMetadataMessage::Request(request)))) => {
+                    assert_eq!(rejecting_peer.info, info);
+
+                    request
+                },
+                other => panic!("Received Unexpected Message {:?}", other),
+            };
+            module
+                .start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(rejecting_peer.info, rejecting_peer.respond(request)))
+                .unwrap();
+
+            // Rejecting peer is not asked again, the rejected request goes to the next peer
+            assert!(module.poll().unwrap().is_not_ready());
+            honest_peer.connect(&mut module, metadata.len() as i64);
+
+            let rejecting_info = rejecting_peer.info;
+            let (downloaded, requests_sent) = drive_download(&mut module, &[rejecting_peer, honest_peer]);
+
+            assert_eq!(metainfo.info().info_hash(), downloaded.info().info_hash());
+            assert_eq!(None, requests_sent.get(&rejecting_info));
+            assert_eq!(2, requests_sent.values().sum::<usize>());
+        });
+    }
+
+    #[test]
+    fn positive_download_metadata_with_lying_peer() {
+        with_task(|| {
+            let metainfo = metainfo();
+            let metadata = metainfo.info().to_bytes();
+            let fake_metadata = vec![0u8; metadata.len() + 100];
+
+            let mut module = UtMetadataModule::new();
+            let lying_peer = MockPeer::new(1, &metainfo, fake_metadata.clone());
+            let honest_peer = MockPeer::new(2, &metainfo, metadata.clone());
+
+            module
+                .start_send(IDiscoveryMessage::DownloadMetainfo(metainfo.info().info_hash()))
+                .unwrap();
+            lying_peer.connect(&mut module, fake_metadata.len() as i64);
+            honest_peer.connect(&mut module, metadata.len() as i64);
+
+            let (downloaded, _) = drive_download(&mut module, &[lying_peer, honest_peer]);
+
+            assert_eq!(metainfo.info().info_hash(), downloaded.info().info_hash());
+        });
+    }
+
+    #[test]
+    fn positive_serve_last_piece_and_reject_out_of_range() {
+        with_task(|| {
+            let metainfo = metainfo();
+            let metadata = metainfo.info().to_bytes();
+
+            let mut module = UtMetadataModule::new();
+            let peer = MockPeer::new(1, &metainfo, Vec::new());
+
+            module
+                .start_send(IDiscoveryMessage::Control(ControlMessage::AddTorrent(metainfo.clone())))
+                .unwrap();
+            module
+                .start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(
+                    peer.info,
+                    UtMetadataMessage::Request(UtMetadataRequestMessage::new(1)),
+                ))
+                .unwrap();
+            module
+                .start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(
+                    peer.info,
+                    UtMetadataMessage::Request(UtMetadataRequestMessage::new(2)),
+                ))
+                .unwrap();
+
+            match module.poll().unwrap() {
+                Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(info, UtMetadataMessage::Data(data)))) => {
+                    assert_eq!(peer.info, info);
+                    assert_eq!(1, data.piece());
+                    assert_eq!(metadata.len() as i64, data.total_size());
+                    assert_eq!(&metadata[MAX_REQUEST_SIZE..], data.data().as_ref());
+                },
+                other => panic!("Received Unexpected Message {:?}", other),
+            }
+
+            match module.poll().unwrap() {
+                Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(info, UtMetadataMessage::Reject(reject)))) => {
+                    assert_eq!(peer.info, info);
+                    assert_eq!(2, reject.piece());
+                },
+                other => panic!("Received Unexpected Message {:?}", other),
+            }
+        });
+    }
+
+    #[test]
+    fn negative_drop_peer_with_wrong_total_size() {
+        with_task(|| {
+            let metainfo = metainfo();
+            let metadata = metainfo.info().to_bytes();
+
+            let mut module = UtMetadataModule::new();
+            let peer = MockPeer::new(1, &metainfo, vec![0u8; metadata.len() * 2]);
+
+            module
+                .start_send(IDiscoveryMessage::DownloadMetainfo(metainfo.info().info_hash()))
+                .unwrap();
+            // Peer advertises the correct size, but sends pieces from a different sized buffer
+            peer.connect(&mut module, metadata.len() as i64);
+
+            let request = match module.poll().unwrap() {
+                Async::Ready(Some(ODiscoveryMessage::SendUtMetadataMessage(_, UtMetadataMessage::Request(request)))) => request,
+                other => panic!("Received Unexpected Message {:?}", other),
+            };
+            module
+                .start_send(IDiscoveryMessage::ReceivedUtMetadataMessage(peer.info, peer.respond(request)))
+                .unwrap();
+
+            assert!(module.poll().unwrap().is_not_ready());
+        });
+    }
+
+    #[test]
+    fn negative_ignore_peer_with_oversized_metadata() {
+        with_task(|| {
+            let metainfo = metainfo();
+
+            let mut module = UtMetadataModule::new();
+            let peer = MockPeer::new(1, &metainfo, Vec::new());
+
+            module
+                .start_send(IDiscoveryMessage::DownloadMetainfo(metainfo.info().info_hash()))
+                .unwrap();
+            peer.connect(&mut module, MAX_METADATA_SIZE + 1);
+
+            assert!(module.poll().unwrap().is_not_ready());
+        });
+    }
+}
@@ -19,11 +19,16 @@ use bip_metainfo::Metainfo;
 use bip_peer::PeerInfo;
 use std::time::Duration;
 
+pub mod choker;
 pub mod discovery;
 pub mod error;
+pub mod picker;
 pub mod revelation;
+pub mod stats;
 
+mod client;
 mod extended;
+mod rate;
 mod uber;
 
 pub use extended::{ExtendedListener, ExtendedPeerInfo, IExtendedMessage, OExtendedMessage};
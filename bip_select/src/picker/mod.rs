@@ -0,0 +1,42 @@
+//! Module for piece picking and block request scheduling.
+
+use bip_peer::PeerInfo;
+use bip_peer::messages::{CancelMessage, RequestMessage};
+
+mod rarest;
+mod requests;
+mod scheduler;
+
+pub use self::rarest::RarestFirstPicker;
+pub use self::requests::RequestTable;
+pub use self::scheduler::RequestScheduler;
+
+/// Enumeration of messages that can be received from a `RequestScheduler`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OScheduleMessage {
+    /// Send a `RequestMessage`.
+    SendRequest(PeerInfo, RequestMessage),
+    /// Send a `CancelMessage`.
+    SendCancel(PeerInfo, CancelMessage),
+}
+
+/// Priority of a file within a torrent, used to decide which pieces to request.
+///
+/// Pieces of higher priority files are requested before pieces of lower priority
+/// files, while pieces of equal priority are requested rarest first. A piece
+/// spanning multiple files takes on the highest priority of those files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilePriority {
+    /// File is not wanted.
+    ///
+    /// Pieces lying entirely within skipped files are never requested, while pieces
+    /// spanning both a skipped and a wanted file are requested in whole, so that
+    /// they can be checked.
+    Skip,
+    /// File is wanted, after all other files.
+    Low,
+    /// File is wanted.
+    Normal,
+    /// File is wanted, before all other files.
+    High,
+}
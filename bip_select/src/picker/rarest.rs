@@ -0,0 +1,351 @@
+use bip_peer::Bitfield;
+use bip_peer::PeerInfo;
+use picker::FilePriority;
+use rand::{self, Rng};
+use std::collections::HashMap;
+
+/// Piece picker which prefers the pieces available from the fewest peers.
+///
+/// Ties between equally rare pieces are broken randomly, so that peers
+/// downloading the same torrent don't all request the same pieces.
+///
+/// Pieces with a higher priority are always preferred over rarer pieces
+/// with a lower priority, and skipped pieces are never picked.
+///
+/// In sequential mode, which is useful for streaming, the lowest index
+/// piece is picked instead of the rarest piece.
+pub struct RarestFirstPicker {
+    ours: Bitfield,
+    peers: HashMap<PeerInfo, Bitfield>,
+    availability: Vec<usize>,
+    priorities: Vec<FilePriority>,
+    sequential: bool,
+}
+
+impl RarestFirstPicker {
+    /// Create a new `RarestFirstPicker` from the pieces we already have.
+    pub fn new(ours: Bitfield) -> RarestFirstPicker {
+        let num_pieces = ours.num_pieces();
+
+        RarestFirstPicker {
+            ours: ours,
+            peers: HashMap::new(),
+            availability: vec![0; num_pieces],
+            priorities: vec![FilePriority::Normal; num_pieces],
+            sequential: false,
+        }
+    }
+
+    /// Add the given peer with the pieces it has.
+    ///
+    /// If the peer was already added, its previous pieces are replaced.
+    pub fn peer_connected(&mut self, peer: PeerInfo, bitfield: Bitfield) {
+        self.peer_disconnected(&peer);
+
+        for index in 0..self.availability.len() {
+            if bitfield.get(index) {
+                self.availability[index] += 1;
+            }
+        }
+
+        self.peers.insert(peer, bitfield);
+    }
+
+    /// Remove the given peer, and the pieces it had.
+    pub fn peer_disconnected(&mut self, peer: &PeerInfo) {
+        if let Some(bitfield) = self.peers.remove(peer) {
+            for index in 0..self.availability.len() {
+                if bitfield.get(index) {
+                    self.availability[index] -= 1;
+                }
+            }
+        }
+    }
+
+    /// Mark the given piece as present for the given peer.
+    ///
+    /// Ignored if the peer is not connected, or the piece is out of range.
+    pub fn peer_has(&mut self, peer: &PeerInfo, index: usize) {
+        if let Some(bitfield) = self.peers.get_mut(peer) {
+            if !bitfield.get(index) && bitfield.set(index).is_ok() && index < self.availability.len() {
+                self.availability[index] += 1;
+            }
+        }
+    }
+
+    /// Mark the given piece as completed by us.
+    pub fn piece_completed(&mut self, index: usize) {
+        let _ = self.ours.set(index);
+    }
+
+    /// Pick the lowest index missing piece that some peer has, instead of the rarest.
+    ///
+    /// Piece priorities are still respected, so only pieces within the highest
+    /// available priority are picked in order.
+    pub fn set_sequential(&mut self, sequential: bool) {
+        self.sequential = sequential;
+    }
+
+    /// Whether or not pieces are picked in order.
+    pub fn is_sequential(&self) -> bool {
+        self.sequential
+    }
+
+    /// Set the priority of the given piece, taking effect on the next pick.
+    ///
+    /// Ignored if the piece is out of range.
+    pub fn set_piece_priority(&mut self, index: usize, priority: FilePriority) {
+        if let Some(piece_priority) = self.priorities.get_mut(index) {
+            *piece_priority = priority;
+        }
+    }
+
+    /// Priority of the given piece.
+    ///
+    /// Pieces default to `FilePriority::Normal`.
+    pub fn piece_priority(&self, index: usize) -> FilePriority {
+        self.priorities.get(index).map(|priority| *priority).unwrap_or(FilePriority::Normal)
+    }
+
+    /// Mark the given piece as no longer completed by us, so that it is picked again.
+    ///
+    /// Used when a completed piece fails its hash check.
+    pub fn piece_failed(&mut self, index: usize) {
+        let _ = self.ours.unset(index);
+    }
+
+    /// Number of connected peers that have the given piece.
+    pub fn availability(&self, index: usize) -> usize {
+        self.availability.get(index).map(|count| *count).unwrap_or(0)
+    }
+
+    /// Pieces we have.
+    pub fn ours(&self) -> &Bitfield {
+        &self.ours
+    }
+
+    /// Next piece we should request from any peer.
+    ///
+    /// Returns `None` if no connected peer has a piece we are missing.
+    pub fn next_piece(&self) -> Option<usize> {
+        self.pick(|_| true)
+    }
+
+    /// Next piece we should request from the given peer.
+    ///
+    /// Returns `None` if the peer is not connected or has no piece we are missing.
+    pub fn next_piece_from(&self, peer: &PeerInfo) -> Option<usize> {
+        self.next_piece_from_matching(peer, |_| true)
+    }
+
+    /// Next piece we should request from the given peer, out of the pieces matching the given predicate.
+    pub fn next_piece_from_matching<F>(&self, peer: &PeerInfo, matching: F) -> Option<usize>
+    where
+        F: Fn(usize) -> bool,
+    {
+        self.peers
+            .get(peer)
+            .and_then(|bitfield| self.pick(|index| bitfield.get(index) && matching(index)))
+    }
+
+    /// Pieces the given peer has.
+    pub fn peer_bitfield(&self, peer: &PeerInfo) -> Option<&Bitfield> {
+        self.peers.get(peer)
+    }
+
+    fn pick<F>(&self, allowed: F) -> Option<usize>
+    where
+        F: Fn(usize) -> bool,
+    {
+        let mut rarest = Vec::new();
+        let mut rarest_priority = FilePriority::Skip;
+        let mut rarest_count = usize::max_value();
+
+        for (index, &available) in self.availability.iter().enumerate() {
+            let priority = self.priorities[index];
+            if available == 0 || priority == FilePriority::Skip || self.ours.get(index) || !allowed(index) {
+                continue;
+            }
+
+            // Every piece is equally rare when picking in order, the first one wins below
+            let count = if self.sequential { 1 } else { available };
+
+            if priority > rarest_priority || (priority == rarest_priority && count < rarest_count) {
+                rarest_priority = priority;
+                rarest_count = count;
+                rarest.clear();
+            } else if priority < rarest_priority || count > rarest_count {
+                continue;
+            }
+            rarest.push(index);
+        }
+
+        if rarest.is_empty() {
+            None
+        } else if self.sequential {
+            Some(rarest[0])
+        } else {
+            Some(rarest[rand::thread_rng().gen_range(0, rarest.len())])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RarestFirstPicker;
+    use bip_handshake::Extensions;
+    use bip_peer::Bitfield;
+    use bip_peer::PeerInfo;
+    use bip_util::bt;
+    use picker::FilePriority;
+
+    fn peer(id: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new())
+    }
+
+    fn bitfield(num_pieces: usize, pieces: &[usize]) -> Bitfield {
+        let mut bitfield = Bitfield::new(num_pieces);
+        for &index in pieces {
+            bitfield.set(index).unwrap();
+        }
+
+        bitfield
+    }
+
+    // Availability: piece 0 -> 3, piece 1 -> 1, piece 2 -> 2, piece 3 -> 0, piece 4 -> 3 (ours)
+    fn fixed_picker() -> RarestFirstPicker {
+        let mut picker = RarestFirstPicker::new(bitfield(5, &[4]));
+
+        picker.peer_connected(peer(1), bitfield(5, &[0, 1, 2, 4]));
+        picker.peer_connected(peer(2), bitfield(5, &[0, 2, 4]));
+        picker.peer_connected(peer(3), bitfield(5, &[0, 4]));
+
+        picker
+    }
+
+    #[test]
+    fn positive_pick_order_rarest_first() {
+        let mut picker = fixed_picker();
+        let mut order = Vec::new();
+
+        while let Some(index) = picker.next_piece() {
+            order.push(index);
+            picker.piece_completed(index);
+        }
+
+        assert_eq!(vec![1, 2, 0], order);
+    }
+
+    #[test]
+    fn positive_pick_from_peer() {
+        let picker = fixed_picker();
+
+        assert_eq!(Some(1), picker.next_piece_from(&peer(1)));
+        assert_eq!(Some(2), picker.next_piece_from(&peer(2)));
+        assert_eq!(Some(0), picker.next_piece_from(&peer(3)));
+        assert_eq!(None, picker.next_piece_from(&peer(4)));
+    }
+
+    #[test]
+    fn positive_peer_disconnect_updates_availability() {
+        let mut picker = fixed_picker();
+
+        picker.peer_disconnected(&peer(1));
+
+        assert_eq!(2, picker.availability(0));
+        assert_eq!(0, picker.availability(1));
+        assert_eq!(1, picker.availability(2));
+        assert_eq!(Some(2), picker.next_piece());
+    }
+
+    #[test]
+    fn positive_peer_reconnect_replaces_bitfield() {
+        let mut picker = fixed_picker();
+
+        picker.peer_connected(peer(3), bitfield(5, &[3]));
+
+        assert_eq!(2, picker.availability(0));
+        assert_eq!(1, picker.availability(3));
+    }
+
+    #[test]
+    fn positive_peer_has_updates_availability() {
+        let mut picker = fixed_picker();
+
+        picker.peer_has(&peer(3), 3);
+        picker.peer_has(&peer(3), 3);
+
+        assert_eq!(1, picker.availability(3));
+    }
+
+    #[test]
+    fn positive_random_tie_break_among_rarest() {
+        let mut picker = RarestFirstPicker::new(Bitfield::new(4));
+        picker.peer_connected(peer(1), bitfield(4, &[0, 1, 2, 3]));
+        picker.peer_connected(peer(2), bitfield(4, &[0, 3]));
+
+        let mut picked = [false; 4];
+        for _ in 0..200 {
+            picked[picker.next_piece().unwrap()] = true;
+        }
+
+        assert_eq!([false, true, true, false], picked);
+    }
+
+    #[test]
+    fn positive_pick_order_by_priority() {
+        let mut picker = fixed_picker();
+        picker.set_piece_priority(0, FilePriority::High);
+        picker.set_piece_priority(1, FilePriority::Low);
+
+        let mut order = Vec::new();
+        while let Some(index) = picker.next_piece() {
+            order.push(index);
+            picker.piece_completed(index);
+        }
+
+        assert_eq!(vec![0, 2, 1], order);
+    }
+
+    #[test]
+    fn positive_priority_change_takes_effect_on_next_pick() {
+        let mut picker = fixed_picker();
+        assert_eq!(Some(1), picker.next_piece());
+
+        picker.set_piece_priority(2, FilePriority::High);
+        assert_eq!(Some(2), picker.next_piece());
+
+        picker.set_piece_priority(2, FilePriority::Skip);
+        assert_eq!(Some(1), picker.next_piece());
+    }
+
+    #[test]
+    fn positive_sequential_pick_order() {
+        let mut picker = RarestFirstPicker::new(bitfield(5, &[1]));
+        picker.set_sequential(true);
+
+        // Piece 2 is unavailable, and piece 4 is the rarest
+        picker.peer_connected(peer(1), bitfield(5, &[0, 1, 3, 4]));
+        picker.peer_connected(peer(2), bitfield(5, &[0, 3]));
+
+        let mut order = Vec::new();
+        while let Some(index) = picker.next_piece() {
+            order.push(index);
+            picker.piece_completed(index);
+        }
+        assert_eq!(vec![0, 3, 4], order);
+
+        picker.peer_has(&peer(2), 2);
+        assert_eq!(Some(2), picker.next_piece());
+    }
+
+    #[test]
+    fn negative_no_pieces_available() {
+        let mut picker = RarestFirstPicker::new(bitfield(3, &[0]));
+        picker.peer_connected(peer(1), bitfield(3, &[0]));
+
+        assert_eq!(None, picker.next_piece());
+    }
+}
@@ -0,0 +1,223 @@
+use bip_peer::PeerInfo;
+use bip_peer::messages::RequestMessage;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 60;
+
+/// Table of blocks that have been requested from peers, but not yet received.
+///
+/// Each block may be owned by multiple peers, for example in endgame mode, and
+/// ownership is reclaimed once a peer has gone silent for longer than the timeout
+/// since the block was requested from it.
+pub struct RequestTable {
+    timeout: Duration,
+    blocks: HashMap<RequestMessage, Vec<(PeerInfo, Instant)>>,
+    // Blocks requested from each peer, in the order they were requested
+    peers: HashMap<PeerInfo, Vec<RequestMessage>>,
+}
+
+impl RequestTable {
+    /// Create a new `RequestTable` with the default request timeout.
+    pub fn new() -> RequestTable {
+        RequestTable::with_timeout(Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS))
+    }
+
+    /// Create a new `RequestTable` where requests time out after the given duration.
+    pub fn with_timeout(timeout: Duration) -> RequestTable {
+        RequestTable {
+            timeout: timeout,
+            blocks: HashMap::new(),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Set the duration after which requests time out.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Mark the block as requested from the given peer at the given time.
+    ///
+    /// Returns false if the block was already requested from the peer.
+    pub fn mark_requested(&mut self, peer: &PeerInfo, request: RequestMessage, now: Instant) -> bool {
+        let owners = self.blocks.entry(request).or_insert_with(Vec::new);
+        if owners.iter().any(|&(owner, _)| owner == *peer) {
+            return false;
+        }
+
+        owners.push((*peer, now));
+        self.peers.entry(*peer).or_insert_with(Vec::new).push(request);
+
+        true
+    }
+
+    /// Mark the block as received, removing it from every peer it was requested from.
+    ///
+    /// Returns the peers other than the given peer that the block was requested from.
+    pub fn mark_received(&mut self, peer: &PeerInfo, request: &RequestMessage) -> Vec<PeerInfo> {
+        let owners = self.blocks.remove(request).unwrap_or_else(Vec::new);
+
+        owners
+            .into_iter()
+            .filter_map(|(owner, _)| {
+                self.remove_from_peer(&owner, request);
+
+                if owner != *peer {
+                    Some(owner)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Remove the block from the given peer only, for example when the peer sent it after it was reclaimed.
+    pub fn remove_request(&mut self, peer: &PeerInfo, request: &RequestMessage) {
+        self.remove_owner(peer, request);
+        self.remove_from_peer(peer, request);
+    }
+
+    /// Remove every block requested from the given peer, returning those blocks.
+    pub fn release_peer(&mut self, peer: &PeerInfo) -> Vec<RequestMessage> {
+        let released = self.peers.remove(peer).unwrap_or_else(Vec::new);
+
+        for request in released.iter() {
+            self.remove_owner(peer, request);
+        }
+
+        released
+    }
+
+    /// Reclaim every block whose request has been outstanding for longer than the timeout.
+    ///
+    /// Returns the peers and blocks that timed out.
+    pub fn reclaim_timed_out(&mut self, now: Instant) -> Vec<(PeerInfo, RequestMessage)> {
+        let timeout = self.timeout;
+        let mut timed_out = Vec::new();
+
+        for (request, owners) in self.blocks.iter_mut() {
+            owners.retain(|&(owner, requested_at)| {
+                let expired = now.duration_since(requested_at) >= timeout;
+                if expired {
+                    timed_out.push((owner, *request));
+                }
+
+                !expired
+            });
+        }
+        self.blocks.retain(|_, owners| !owners.is_empty());
+
+        for &(ref owner, ref request) in timed_out.iter() {
+            self.remove_from_peer(owner, request);
+        }
+
+        timed_out
+    }
+
+    /// Whether the block has been requested from any peer.
+    pub fn is_requested(&self, request: &RequestMessage) -> bool {
+        self.blocks.contains_key(request)
+    }
+
+    /// Whether the block has been requested from the given peer.
+    pub fn is_requested_from(&self, peer: &PeerInfo, request: &RequestMessage) -> bool {
+        self.blocks
+            .get(request)
+            .map(|owners| owners.iter().any(|&(owner, _)| owner == *peer))
+            .unwrap_or(false)
+    }
+
+    /// Blocks requested from the given peer, in the order they were requested.
+    pub fn requested_from(&self, peer: &PeerInfo) -> &[RequestMessage] {
+        self.peers.get(peer).map(|requests| &requests[..]).unwrap_or(&[])
+    }
+
+    fn remove_owner(&mut self, peer: &PeerInfo, request: &RequestMessage) {
+        let now_empty = match self.blocks.get_mut(request) {
+            Some(owners) => {
+                owners.retain(|&(owner, _)| owner != *peer);
+
+                owners.is_empty()
+            }
+            None => false,
+        };
+
+        if now_empty {
+            self.blocks.remove(request);
+        }
+    }
+
+    fn remove_from_peer(&mut self, peer: &PeerInfo, request: &RequestMessage) {
+        let now_empty = match self.peers.get_mut(peer) {
+            Some(requests) => {
+                requests.retain(|requested| requested != request);
+
+                requests.is_empty()
+            }
+            None => false,
+        };
+
+        if now_empty {
+            self.peers.remove(peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestTable;
+    use bip_handshake::Extensions;
+    use bip_peer::PeerInfo;
+    use bip_peer::messages::RequestMessage;
+    use bip_util::bt;
+    use std::time::{Duration, Instant};
+
+    fn peer(id: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new())
+    }
+
+    #[test]
+    fn positive_mark_requested_rejects_duplicate() {
+        let mut table = RequestTable::new();
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let now = Instant::now();
+
+        assert!(table.mark_requested(&peer(1), request, now));
+        assert!(!table.mark_requested(&peer(1), request, now));
+        assert!(table.mark_requested(&peer(2), request, now));
+        assert_eq!(&[request], table.requested_from(&peer(1)));
+    }
+
+    #[test]
+    fn positive_mark_received_returns_other_owners() {
+        let mut table = RequestTable::new();
+        let request = RequestMessage::new(0, 0, 16 * 1024);
+        let now = Instant::now();
+
+        table.mark_requested(&peer(1), request, now);
+        table.mark_requested(&peer(2), request, now);
+
+        assert_eq!(vec![peer(2)], table.mark_received(&peer(1), &request));
+        assert!(!table.is_requested(&request));
+        assert!(table.requested_from(&peer(2)).is_empty());
+    }
+
+    #[test]
+    fn positive_reclaim_timed_out() {
+        let mut table = RequestTable::with_timeout(Duration::from_secs(10));
+        let first = RequestMessage::new(0, 0, 16 * 1024);
+        let second = RequestMessage::new(0, 16 * 1024, 16 * 1024);
+        let now = Instant::now();
+
+        table.mark_requested(&peer(1), first, now);
+        table.mark_requested(&peer(2), second, now + Duration::from_secs(5));
+
+        assert!(table.reclaim_timed_out(now + Duration::from_secs(9)).is_empty());
+        assert_eq!(vec![(peer(1), first)], table.reclaim_timed_out(now + Duration::from_secs(10)));
+        assert!(!table.is_requested(&first));
+        assert!(table.is_requested_from(&peer(2), &second));
+    }
+}
@@ -0,0 +1,958 @@
+use bip_peer::Bitfield;
+use bip_peer::PeerInfo;
+use bip_peer::messages::{CancelMessage, PieceMessage, RequestMessage};
+use picker::{FilePriority, OScheduleMessage, RarestFirstPicker, RequestTable};
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const BLOCK_SIZE: usize = 16 * 1024;
+const DEFAULT_ENDGAME_THRESHOLD: usize = 20;
+
+const DEFAULT_PIPELINE_DEPTH: usize = 4;
+const MIN_PIPELINE_DEPTH: usize = 2;
+const MAX_PIPELINE_DEPTH: usize = 128;
+// Derived pipeline depth covers this many milliseconds of a peer's observed throughput
+const PIPELINE_WINDOW_MILLIS: u64 = 2000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum BlockState {
+    Missing,
+    Requested,
+    Received,
+}
+
+struct PeerState {
+    choked: bool,
+    // Bytes received since the last tick
+    bytes_received: u64,
+    pipeline_depth: usize,
+    bad_pieces: usize,
+}
+
+/// Scheduler for block requests sent to peers.
+///
+/// Pieces are started in rarest first order, or index order if sequential,
+/// and blocks of started pieces are requested before starting new pieces.
+/// Once fewer blocks than the endgame threshold remain, every outstanding
+/// block is requested from all unchoked peers that have it, and duplicate
+/// requests are cancelled as blocks arrive.
+///
+/// Pieces with a deadline are requested before all other pieces, earliest
+/// deadline first. Once the deadline for a piece has passed, its outstanding
+/// blocks are requested from every peer that has it.
+///
+/// Outside of endgame mode, multiple requests are kept in flight to each peer.
+/// Unless a fixed pipeline depth is set, the depth for each peer is derived from
+/// its observed throughput on every tick.
+///
+/// Requests that go unanswered for longer than the request timeout are reclaimed
+/// with `reclaim_timed_out`, so that they can be requested from other peers.
+pub struct RequestScheduler {
+    picker: RarestFirstPicker,
+    piece_length: usize,
+    total_length: u64,
+    file_lengths: Vec<u64>,
+    pieces: HashMap<usize, Vec<BlockState>>,
+    deadlines: HashMap<usize, Instant>,
+    // Peers that supplied blocks for each piece, until it is verified
+    suppliers: HashMap<usize, Vec<PeerInfo>>,
+    // Peers that supplied blocks for a piece that failed its hash check
+    avoided: HashMap<usize, Vec<PeerInfo>>,
+    peers: HashMap<PeerInfo, PeerState>,
+    requests: RequestTable,
+    endgame_threshold: usize,
+    fixed_pipeline_depth: Option<usize>,
+}
+
+impl RequestScheduler {
+    /// Create a new `RequestScheduler` from the pieces we already have.
+    pub fn new(ours: Bitfield, piece_length: usize, total_length: u64) -> RequestScheduler {
+        RequestScheduler::with_files(ours, piece_length, vec![total_length])
+    }
+
+    /// Create a new `RequestScheduler` from the pieces we already have, for a torrent
+    /// made up of files with the given lengths.
+    ///
+    /// File lengths should be given in the same order as the files in the info dictionary.
+    pub fn with_files(ours: Bitfield, piece_length: usize, file_lengths: Vec<u64>) -> RequestScheduler {
+        RequestScheduler {
+            picker: RarestFirstPicker::new(ours),
+            piece_length: piece_length,
+            total_length: file_lengths.iter().sum(),
+            file_lengths: file_lengths,
+            pieces: HashMap::new(),
+            deadlines: HashMap::new(),
+            suppliers: HashMap::new(),
+            avoided: HashMap::new(),
+            peers: HashMap::new(),
+            requests: RequestTable::new(),
+            endgame_threshold: DEFAULT_ENDGAME_THRESHOLD,
+            fixed_pipeline_depth: None,
+        }
+    }
+
+    /// Set the number of remaining blocks below which we enter endgame mode.
+    ///
+    /// A threshold of zero disables endgame mode.
+    pub fn set_endgame_threshold(&mut self, threshold: usize) {
+        self.endgame_threshold = threshold;
+    }
+
+    /// Set a fixed number of requests to keep in flight to each peer.
+    ///
+    /// This overrides the pipeline depth derived from each peer's throughput.
+    pub fn set_pipeline_depth(&mut self, depth: usize) {
+        self.fixed_pipeline_depth = Some(depth);
+    }
+
+    /// Set the duration after which unanswered requests are reclaimed by `reclaim_timed_out`.
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.requests.set_timeout(timeout);
+    }
+
+    /// Set a deadline for the given piece, so that it is requested before all pieces without one.
+    ///
+    /// The deadline is cleared once the piece is completed.
+    pub fn set_piece_deadline(&mut self, piece: u32, deadline: Instant) {
+        self.deadlines.insert(piece as usize, deadline);
+    }
+
+    /// Clear the deadline for the given piece.
+    pub fn clear_piece_deadline(&mut self, piece: u32) {
+        self.deadlines.remove(&(piece as usize));
+    }
+
+    /// Start pieces in index order instead of rarest first, which is useful for streaming.
+    pub fn set_sequential(&mut self, sequential: bool) {
+        self.picker.set_sequential(sequential);
+    }
+
+    /// Set the priority of each file, in the same order as the file lengths.
+    ///
+    /// Pieces lying entirely within skipped files are never requested, and pieces of
+    /// higher priority files are started before pieces of lower priority files. Files
+    /// without a priority are treated as `FilePriority::Normal`.
+    ///
+    /// Priorities can be changed at any time, and take effect on the next pick.
+    pub fn set_file_priorities(&mut self, priorities: &[FilePriority]) {
+        let piece_length = self.piece_length as u64;
+        let mut piece_priorities = vec![FilePriority::Skip; self.picker.ours().num_pieces()];
+        let mut file_start = 0;
+
+        for (index, &file_length) in self.file_lengths.iter().enumerate() {
+            let priority = priorities.get(index).map(|priority| *priority).unwrap_or(FilePriority::Normal);
+
+            if file_length != 0 {
+                let first_piece = (file_start / piece_length) as usize;
+                let last_piece = ((file_start + file_length - 1) / piece_length) as usize;
+
+                for piece_priority in piece_priorities.iter_mut().take(last_piece + 1).skip(first_piece) {
+                    *piece_priority = cmp::max(*piece_priority, priority);
+                }
+            }
+            file_start += file_length;
+        }
+
+        for (index, priority) in piece_priorities.into_iter().enumerate() {
+            self.picker.set_piece_priority(index, priority);
+        }
+    }
+
+    /// Whether or not the given piece lies entirely within skipped files.
+    pub fn is_skipped(&self, index: usize) -> bool {
+        self.picker.piece_priority(index) == FilePriority::Skip
+    }
+
+    /// Number of requests we keep in flight to the given peer.
+    ///
+    /// Never exceeds the number of blocks in a piece.
+    pub fn pipeline_depth(&self, peer: &PeerInfo) -> usize {
+        let depth = self.fixed_pipeline_depth
+            .or_else(|| self.peers.get(peer).map(|state| state.pipeline_depth))
+            .unwrap_or(DEFAULT_PIPELINE_DEPTH);
+
+        cmp::min(depth, cmp::max(self.num_blocks(0), 1))
+    }
+
+    /// A span of time has passed, update the pipeline depth of each peer from its throughput.
+    pub fn tick(&mut self, duration: Duration) {
+        let millis = duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64;
+        if millis == 0 {
+            return;
+        }
+
+        for state in self.peers.values_mut() {
+            let window_bytes = state.bytes_received * PIPELINE_WINDOW_MILLIS / millis;
+            let depth = (window_bytes / BLOCK_SIZE as u64) as usize;
+
+            state.pipeline_depth = cmp::max(MIN_PIPELINE_DEPTH, cmp::min(MAX_PIPELINE_DEPTH, depth));
+            state.bytes_received = 0;
+        }
+    }
+
+    /// Whether or not we are in endgame mode.
+    pub fn is_endgame(&self) -> bool {
+        let remaining = self.remaining_blocks();
+
+        remaining != 0 && remaining < self.endgame_threshold
+    }
+
+    /// Piece picker used to start new pieces.
+    pub fn picker(&self) -> &RarestFirstPicker {
+        &self.picker
+    }
+
+    /// Requests that have been sent to the given peer, but not yet fulfilled.
+    pub fn in_flight(&self, peer: &PeerInfo) -> &[RequestMessage] {
+        self.requests.requested_from(peer)
+    }
+
+    /// Add the given peer with the pieces it has.
+    ///
+    /// Peers start out choking us.
+    pub fn peer_connected(&mut self, peer: PeerInfo, bitfield: Bitfield) {
+        self.peer_disconnected(&peer);
+
+        self.picker.peer_connected(peer, bitfield);
+        self.peers.insert(
+            peer,
+            PeerState {
+                choked: true,
+                bytes_received: 0,
+                pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+                bad_pieces: 0,
+            },
+        );
+    }
+
+    /// Remove the given peer, releasing any requests sent to it.
+    pub fn peer_disconnected(&mut self, peer: &PeerInfo) {
+        self.release_requests(peer);
+
+        self.picker.peer_disconnected(peer);
+        self.peers.remove(peer);
+    }
+
+    /// Mark the given piece as present for the given peer.
+    pub fn peer_has(&mut self, peer: &PeerInfo, index: usize) {
+        self.picker.peer_has(peer, index);
+    }
+
+    /// The given peer choked us, releasing any requests sent to it.
+    pub fn peer_choked(&mut self, peer: &PeerInfo) {
+        self.release_requests(peer);
+
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.choked = true;
+        }
+    }
+
+    /// The given peer unchoked us.
+    pub fn peer_unchoked(&mut self, peer: &PeerInfo) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.choked = false;
+        }
+    }
+
+    /// Received a block from the given peer.
+    ///
+    /// Returns cancels for any other peers the same block was requested from.
+    pub fn block_received(&mut self, peer: &PeerInfo, piece: &PieceMessage) -> Vec<OScheduleMessage> {
+        let request = RequestMessage::new(piece.piece_index(), piece.block_offset(), piece.block_length());
+        let mut messages = Vec::new();
+
+        self.requests.remove_request(peer, &request);
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.bytes_received += piece.block_length() as u64;
+        }
+
+        let index = piece.piece_index() as usize;
+        let block = piece.block_offset() as usize / BLOCK_SIZE;
+        if self.block_request(index, block) != Some(request) {
+            return messages;
+        }
+
+        let piece_done = match self.pieces.get_mut(&index) {
+            Some(blocks) => {
+                blocks[block] = BlockState::Received;
+
+                blocks.iter().all(|state| *state == BlockState::Received)
+            }
+            None => return messages,
+        };
+
+        let suppliers = self.suppliers.entry(index).or_insert_with(Vec::new);
+        if !suppliers.contains(peer) {
+            suppliers.push(*peer);
+        }
+
+        if piece_done {
+            self.pieces.remove(&index);
+            self.deadlines.remove(&index);
+            self.picker.piece_completed(index);
+        }
+
+        for other_peer in self.requests.mark_received(peer, &request) {
+            let cancel = CancelMessage::new(request.piece_index(), request.block_offset(), request.block_length());
+            messages.push(OScheduleMessage::SendCancel(other_peer, cancel));
+        }
+
+        messages
+    }
+
+    /// The given piece passed its hash check after all of its blocks were received.
+    pub fn piece_verified(&mut self, index: usize) {
+        self.suppliers.remove(&index);
+        self.avoided.remove(&index);
+    }
+
+    /// The given piece failed its hash check after all of its blocks were received.
+    ///
+    /// The whole piece will be requested again, avoiding the peers that supplied the bad
+    /// piece unless no other unchoked peer has it.
+    pub fn piece_failed(&mut self, index: usize) {
+        let suppliers = self.suppliers.remove(&index).unwrap_or_else(Vec::new);
+        for supplier in suppliers.iter() {
+            if let Some(state) = self.peers.get_mut(supplier) {
+                state.bad_pieces += 1;
+            }
+        }
+
+        let avoided = self.avoided.entry(index).or_insert_with(Vec::new);
+        for supplier in suppliers {
+            if !avoided.contains(&supplier) {
+                avoided.push(supplier);
+            }
+        }
+
+        self.pieces.remove(&index);
+        self.picker.piece_failed(index);
+    }
+
+    /// Number of pieces that failed their hash check which the given peer supplied blocks for.
+    pub fn bad_pieces(&self, peer: &PeerInfo) -> usize {
+        self.peers.get(peer).map(|state| state.bad_pieces).unwrap_or(0)
+    }
+
+    /// Reclaim requests that have gone unanswered for longer than the request timeout.
+    ///
+    /// Returns cancels for the reclaimed requests, whose blocks can then be requested from other peers.
+    pub fn reclaim_timed_out(&mut self, now: Instant) -> Vec<OScheduleMessage> {
+        let mut messages = Vec::new();
+
+        for (peer, request) in self.requests.reclaim_timed_out(now) {
+            self.release_block(&request);
+
+            let cancel = CancelMessage::new(request.piece_index(), request.block_offset(), request.block_length());
+            messages.push(OScheduleMessage::SendCancel(peer, cancel));
+        }
+
+        messages
+    }
+
+    /// Generate requests for all unchoked peers.
+    pub fn requests(&mut self) -> Vec<OScheduleMessage> {
+        let now = Instant::now();
+        let endgame = self.is_endgame();
+        let unchoked_peers: Vec<PeerInfo> = self.peers
+            .iter()
+            .filter(|&(_, state)| !state.choked)
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        let mut messages = Vec::new();
+        for peer in unchoked_peers {
+            let requests = if endgame {
+                self.endgame_requests(&peer)
+            } else {
+                self.normal_requests(&peer, now)
+            };
+
+            for request in requests.iter() {
+                self.requests.mark_requested(&peer, *request, now);
+            }
+            messages.extend(requests.into_iter().map(|request| OScheduleMessage::SendRequest(peer, request)));
+        }
+
+        messages
+    }
+
+    /// Request blocks until the peer's pipeline is full.
+    fn normal_requests(&mut self, peer: &PeerInfo, now: Instant) -> Vec<RequestMessage> {
+        let depth = self.pipeline_depth(peer);
+        let mut requests = Vec::new();
+
+        while self.in_flight(peer).len() + requests.len() < depth {
+            let opt_request = self.next_deadline_block(peer, now, &requests).or_else(|| self.next_block(peer));
+
+            match opt_request {
+                Some(request) => requests.push(request),
+                None => break,
+            }
+        }
+
+        requests
+    }
+
+    /// Request every block we are missing that the peer has, and that we haven't already requested from it.
+    fn endgame_requests(&mut self, peer: &PeerInfo) -> Vec<RequestMessage> {
+        let mut requests = Vec::new();
+
+        for index in 0..self.picker.ours().num_pieces() {
+            if self.picker.ours().get(index) || self.is_skipped(index) || !self.peer_has_piece(peer, index)
+                || self.is_avoided(peer, index)
+            {
+                continue;
+            }
+
+            let num_blocks = self.num_blocks(index);
+            for block in 0..num_blocks {
+                let state = self.pieces
+                    .get(&index)
+                    .map(|blocks| blocks[block])
+                    .unwrap_or(BlockState::Missing);
+                let request = self.block_request(index, block).unwrap();
+
+                if state != BlockState::Received && !self.requests.is_requested_from(peer, &request) {
+                    self.pieces
+                        .entry(index)
+                        .or_insert_with(|| vec![BlockState::Missing; num_blocks])[block] = BlockState::Requested;
+                    requests.push(request);
+                }
+            }
+        }
+
+        requests
+    }
+
+    /// Next block the peer has out of the pieces with a deadline, earliest deadline first.
+    ///
+    /// Blocks already requested from other peers are requested again if the deadline has passed.
+    fn next_deadline_block(
+        &mut self,
+        peer: &PeerInfo,
+        now: Instant,
+        pending: &[RequestMessage],
+    ) -> Option<RequestMessage> {
+        let mut deadlines: Vec<(Instant, usize)> = self.deadlines
+            .iter()
+            .filter(|&(index, _)| {
+                !self.picker.ours().get(*index) && self.peer_has_piece(peer, *index) && !self.is_avoided(peer, *index)
+            })
+            .map(|(index, deadline)| (*deadline, *index))
+            .collect();
+        deadlines.sort();
+
+        for (deadline, index) in deadlines {
+            let num_blocks = self.num_blocks(index);
+
+            for block in 0..num_blocks {
+                let state = self.pieces
+                    .get(&index)
+                    .map(|blocks| blocks[block])
+                    .unwrap_or(BlockState::Missing);
+                let request = self.block_request(index, block).unwrap();
+
+                let already_requested = self.requests.is_requested_from(peer, &request) || pending.contains(&request);
+                let should_request = match state {
+                    BlockState::Missing => true,
+                    BlockState::Requested => deadline <= now && !already_requested,
+                    BlockState::Received => false,
+                };
+
+                if should_request {
+                    self.pieces
+                        .entry(index)
+                        .or_insert_with(|| vec![BlockState::Missing; num_blocks])[block] = BlockState::Requested;
+
+                    return Some(request);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Next missing block the peer has, preferring pieces that were already started.
+    fn next_block(&mut self, peer: &PeerInfo) -> Option<RequestMessage> {
+        let opt_started = self.pieces
+            .iter()
+            .filter(|&(index, _)| self.peer_has_piece(peer, *index) && !self.is_avoided(peer, *index))
+            .filter_map(|(index, blocks)| {
+                blocks
+                    .iter()
+                    .position(|state| *state == BlockState::Missing)
+                    .map(|block| (*index, block))
+            })
+            .next();
+
+        let opt_block = opt_started.or_else(|| {
+            self.picker
+                .next_piece_from_matching(peer, |index| {
+                    !self.pieces.contains_key(&index) && !self.is_skipped(index) && !self.is_avoided(peer, index)
+                })
+                .map(|index| (index, 0))
+        });
+
+        opt_block.and_then(|(index, block)| {
+            let num_blocks = self.num_blocks(index);
+            self.pieces
+                .entry(index)
+                .or_insert_with(|| vec![BlockState::Missing; num_blocks])[block] = BlockState::Requested;
+
+            self.block_request(index, block)
+        })
+    }
+
+    /// Release requests sent to the given peer, so that they can be requested from other peers.
+    fn release_requests(&mut self, peer: &PeerInfo) {
+        for request in self.requests.release_peer(peer) {
+            self.release_block(&request);
+        }
+    }
+
+    /// Mark the block as missing if it is no longer requested from any peer.
+    fn release_block(&mut self, request: &RequestMessage) {
+        let still_requested = self.requests.is_requested(request);
+        let block = request.block_offset() as usize / BLOCK_SIZE;
+
+        if let Some(blocks) = self.pieces.get_mut(&(request.piece_index() as usize)) {
+            if !still_requested && blocks[block] == BlockState::Requested {
+                blocks[block] = BlockState::Missing;
+            }
+        }
+    }
+
+    /// Whether the peer supplied a bad copy of the piece, and some other unchoked peer has the piece.
+    fn is_avoided(&self, peer: &PeerInfo, index: usize) -> bool {
+        match self.avoided.get(&index) {
+            Some(avoided) if avoided.contains(peer) => self.peers
+                .iter()
+                .any(|(other, state)| !state.choked && !avoided.contains(other) && self.peer_has_piece(other, index)),
+            _ => false,
+        }
+    }
+
+    fn peer_has_piece(&self, peer: &PeerInfo, index: usize) -> bool {
+        self.picker
+            .peer_bitfield(peer)
+            .map(|bitfield| bitfield.get(index))
+            .unwrap_or(false)
+    }
+
+    fn remaining_blocks(&self) -> usize {
+        let ours = self.picker.ours();
+
+        (0..ours.num_pieces())
+            .filter(|index| !ours.get(*index) && !self.is_skipped(*index))
+            .map(|index| match self.pieces.get(&index) {
+                Some(blocks) => blocks.iter().filter(|state| **state != BlockState::Received).count(),
+                None => self.num_blocks(index),
+            })
+            .sum()
+    }
+
+    fn piece_size(&self, index: usize) -> usize {
+        let piece_start = index as u64 * self.piece_length as u64;
+
+        cmp::min(self.piece_length as u64, self.total_length.saturating_sub(piece_start)) as usize
+    }
+
+    fn num_blocks(&self, index: usize) -> usize {
+        (self.piece_size(index) + BLOCK_SIZE - 1) / BLOCK_SIZE
+    }
+
+    fn block_request(&self, index: usize, block: usize) -> Option<RequestMessage> {
+        let piece_size = self.piece_size(index);
+        let block_offset = block * BLOCK_SIZE;
+
+        if block_offset < piece_size {
+            let block_length = cmp::min(BLOCK_SIZE, piece_size - block_offset);
+
+            Some(RequestMessage::new(index as u32, block_offset as u32, block_length))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestScheduler, BLOCK_SIZE};
+    use bip_handshake::Extensions;
+    use bip_peer::Bitfield;
+    use bip_peer::PeerInfo;
+    use bip_peer::messages::{CancelMessage, PieceMessage, RequestMessage};
+    use bip_util::bt;
+    use bytes::Bytes;
+    use picker::{FilePriority, OScheduleMessage};
+    use std::time::{Duration, Instant};
+
+    fn peer(id: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), [0u8; bt::INFO_HASH_LEN].into(), Extensions::new())
+    }
+
+    fn bitfield(num_pieces: usize, pieces: &[usize]) -> Bitfield {
+        let mut bitfield = Bitfield::new(num_pieces);
+        for &index in pieces {
+            bitfield.set(index).unwrap();
+        }
+
+        bitfield
+    }
+
+    fn piece(request: &RequestMessage) -> PieceMessage {
+        PieceMessage::new(request.piece_index(), request.block_offset(), Bytes::from(vec![0u8; request.block_length()]))
+    }
+
+    fn requests_for(messages: &[OScheduleMessage], for_peer: &PeerInfo) -> Vec<RequestMessage> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                &OScheduleMessage::SendRequest(ref peer, request) if peer == for_peer => Some(request),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Two pieces of two blocks each, where we already have the first piece
+    fn last_piece_scheduler() -> RequestScheduler {
+        let mut scheduler = RequestScheduler::new(bitfield(2, &[0]), 2 * BLOCK_SIZE, 4 * BLOCK_SIZE as u64);
+
+        scheduler.peer_connected(peer(1), bitfield(2, &[0, 1]));
+        scheduler.peer_connected(peer(2), bitfield(2, &[0, 1]));
+        scheduler.peer_unchoked(&peer(1));
+        scheduler.peer_unchoked(&peer(2));
+
+        scheduler
+    }
+
+    #[test]
+    fn positive_endgame_requests_from_all_peers() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(4);
+        assert!(scheduler.is_endgame());
+
+        let messages = scheduler.requests();
+        let expected = vec![RequestMessage::new(1, 0, BLOCK_SIZE), RequestMessage::new(1, BLOCK_SIZE as u32, BLOCK_SIZE)];
+
+        assert_eq!(expected, requests_for(&messages, &peer(1)));
+        assert_eq!(expected, requests_for(&messages, &peer(2)));
+        assert!(scheduler.requests().is_empty());
+    }
+
+    #[test]
+    fn positive_endgame_cancels_duplicates_on_receive() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(4);
+        scheduler.requests();
+
+        let first_block = RequestMessage::new(1, 0, BLOCK_SIZE);
+        let second_block = RequestMessage::new(1, BLOCK_SIZE as u32, BLOCK_SIZE);
+
+        let messages = scheduler.block_received(&peer(1), &piece(&first_block));
+        assert_eq!(
+            vec![OScheduleMessage::SendCancel(peer(2), CancelMessage::new(1, 0, BLOCK_SIZE))],
+            messages
+        );
+        assert_eq!(&[second_block], scheduler.in_flight(&peer(1)));
+        assert_eq!(&[second_block], scheduler.in_flight(&peer(2)));
+
+        let messages = scheduler.block_received(&peer(2), &piece(&second_block));
+        assert_eq!(
+            vec![OScheduleMessage::SendCancel(peer(1), CancelMessage::new(1, BLOCK_SIZE as u32, BLOCK_SIZE))],
+            messages
+        );
+        assert!(scheduler.picker().ours().is_complete());
+        assert!(!scheduler.is_endgame());
+    }
+
+    #[test]
+    fn positive_normal_mode_no_duplicate_requests() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(0);
+        scheduler.set_pipeline_depth(1);
+
+        let messages = scheduler.requests();
+        let peer_one = requests_for(&messages, &peer(1));
+        let peer_two = requests_for(&messages, &peer(2));
+
+        assert_eq!(1, peer_one.len());
+        assert_eq!(1, peer_two.len());
+        assert!(peer_one[0] != peer_two[0]);
+        assert!(scheduler.block_received(&peer(1), &piece(&peer_one[0])).is_empty());
+    }
+
+    #[test]
+    fn positive_choke_releases_requests() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(0);
+        scheduler.peer_choked(&peer(2));
+
+        let messages = scheduler.requests();
+        let requested = requests_for(&messages, &peer(1));
+        scheduler.peer_choked(&peer(1));
+        scheduler.peer_unchoked(&peer(2));
+
+        assert_eq!(requested, requests_for(&scheduler.requests(), &peer(2)));
+    }
+
+    #[test]
+    fn positive_timed_out_request_reassigned() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(0);
+        scheduler.set_request_timeout(Duration::from_secs(30));
+        scheduler.peer_choked(&peer(2));
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        assert!(scheduler.reclaim_timed_out(Instant::now()).is_empty());
+
+        // Peer one went silent, so its requests are cancelled and given to peer two
+        let cancels = scheduler.reclaim_timed_out(Instant::now() + Duration::from_secs(30));
+        assert_eq!(requests.len(), cancels.len());
+        for request in requests.iter() {
+            let cancel = CancelMessage::new(request.piece_index(), request.block_offset(), request.block_length());
+            assert!(cancels.contains(&OScheduleMessage::SendCancel(peer(1), cancel)));
+        }
+        assert!(scheduler.in_flight(&peer(1)).is_empty());
+
+        scheduler.peer_choked(&peer(1));
+        scheduler.peer_unchoked(&peer(2));
+        assert_eq!(requests, requests_for(&scheduler.requests(), &peer(2)));
+    }
+
+    #[test]
+    fn positive_failed_piece_requested_again() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(0);
+        scheduler.peer_choked(&peer(2));
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        for request in requests.iter() {
+            scheduler.block_received(&peer(1), &piece(request));
+        }
+        assert!(scheduler.picker().ours().is_complete());
+        assert!(scheduler.requests().is_empty());
+
+        scheduler.piece_failed(1);
+        assert!(!scheduler.picker().ours().get(1));
+        assert_eq!(requests, requests_for(&scheduler.requests(), &peer(1)));
+    }
+
+    #[test]
+    fn positive_failed_piece_avoids_supplier() {
+        let mut scheduler = last_piece_scheduler();
+        scheduler.set_endgame_threshold(0);
+        scheduler.peer_choked(&peer(2));
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        for request in requests.iter() {
+            scheduler.block_received(&peer(1), &piece(request));
+        }
+        scheduler.piece_failed(1);
+        assert_eq!(1, scheduler.bad_pieces(&peer(1)));
+        assert_eq!(0, scheduler.bad_pieces(&peer(2)));
+
+        // Blocks are back in the request queue, but only for the peer that did not supply the bad piece
+        scheduler.peer_unchoked(&peer(2));
+        let messages = scheduler.requests();
+        assert!(requests_for(&messages, &peer(1)).is_empty());
+        assert_eq!(requests, requests_for(&messages, &peer(2)));
+    }
+
+    // Single peer with four pieces of four blocks each
+    fn pipeline_scheduler() -> RequestScheduler {
+        let mut scheduler = RequestScheduler::new(Bitfield::new(4), 4 * BLOCK_SIZE, 16 * BLOCK_SIZE as u64);
+        scheduler.set_endgame_threshold(0);
+
+        scheduler.peer_connected(peer(1), bitfield(4, &[0, 1, 2, 3]));
+        scheduler.peer_unchoked(&peer(1));
+
+        scheduler
+    }
+
+    #[test]
+    fn positive_pipeline_respects_configured_depth() {
+        let mut scheduler = pipeline_scheduler();
+        scheduler.set_pipeline_depth(3);
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        assert_eq!(3, requests.len());
+        assert_eq!(3, scheduler.in_flight(&peer(1)).len());
+        assert!(scheduler.requests().is_empty());
+
+        scheduler.block_received(&peer(1), &piece(&requests[0]));
+        assert_eq!(1, requests_for(&scheduler.requests(), &peer(1)).len());
+        assert_eq!(3, scheduler.in_flight(&peer(1)).len());
+    }
+
+    #[test]
+    fn positive_pipeline_capped_at_piece_block_count() {
+        let mut scheduler = pipeline_scheduler();
+        scheduler.set_pipeline_depth(10);
+
+        assert_eq!(4, scheduler.pipeline_depth(&peer(1)));
+        assert_eq!(4, requests_for(&scheduler.requests(), &peer(1)).len());
+    }
+
+    #[test]
+    fn positive_pipeline_depth_derived_from_throughput() {
+        let mut scheduler = RequestScheduler::new(Bitfield::new(1), 256 * BLOCK_SIZE, 256 * BLOCK_SIZE as u64);
+        scheduler.set_endgame_threshold(0);
+        scheduler.peer_connected(peer(1), bitfield(1, &[0]));
+        scheduler.peer_unchoked(&peer(1));
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        for request in requests.iter() {
+            scheduler.block_received(&peer(1), &piece(request));
+        }
+        // Four blocks in half a second covers sixteen blocks in the pipeline window
+        scheduler.tick(Duration::from_millis(500));
+        assert_eq!(16, scheduler.pipeline_depth(&peer(1)));
+
+        scheduler.tick(Duration::from_millis(500));
+        assert_eq!(2, scheduler.pipeline_depth(&peer(1)));
+    }
+
+    #[test]
+    fn positive_skipped_only_pieces_never_requested() {
+        // Pieces 0 and 1 lie within the first file, piece 2 spans both files, and piece 3 lies within the second file
+        let file_lengths = vec![2 * BLOCK_SIZE as u64 + 10, 2 * BLOCK_SIZE as u64 - 10];
+        let mut scheduler = RequestScheduler::with_files(Bitfield::new(4), BLOCK_SIZE, file_lengths);
+        scheduler.set_endgame_threshold(0);
+        scheduler.set_file_priorities(&[FilePriority::Normal, FilePriority::Skip]);
+
+        scheduler.peer_connected(peer(1), bitfield(4, &[0, 1, 2, 3]));
+        scheduler.peer_unchoked(&peer(1));
+
+        let mut requested = Vec::new();
+        loop {
+            let requests = requests_for(&scheduler.requests(), &peer(1));
+            if requests.is_empty() {
+                break;
+            }
+
+            for request in requests {
+                requested.push(request.piece_index());
+                scheduler.block_received(&peer(1), &piece(&request));
+            }
+        }
+        requested.sort();
+
+        assert_eq!(vec![0, 1, 2], requested);
+        assert!(scheduler.is_skipped(3));
+        assert!(!scheduler.picker().ours().get(3));
+    }
+
+    #[test]
+    fn positive_high_priority_file_picked_first() {
+        // Pieces 0 and 1 lie within the first file, and pieces 2 and 3 lie within the second file
+        let mut scheduler = RequestScheduler::with_files(Bitfield::new(4), BLOCK_SIZE, vec![2 * BLOCK_SIZE as u64; 2]);
+        scheduler.set_endgame_threshold(0);
+        scheduler.set_pipeline_depth(1);
+        scheduler.set_file_priorities(&[FilePriority::Normal, FilePriority::High]);
+
+        // Make the first file's pieces the rarest, so rarest first alone would pick them first
+        scheduler.peer_connected(peer(1), bitfield(4, &[0, 1, 2, 3]));
+        scheduler.peer_connected(peer(2), bitfield(4, &[2, 3]));
+        scheduler.peer_unchoked(&peer(1));
+
+        let mut requested = Vec::new();
+        for _ in 0..4 {
+            let request = requests_for(&scheduler.requests(), &peer(1))[0];
+
+            requested.push(request.piece_index());
+            scheduler.block_received(&peer(1), &piece(&request));
+        }
+
+        // Ties within a priority are broken randomly
+        requested[..2].sort();
+        requested[2..].sort();
+        assert_eq!(vec![2, 3, 0, 1], requested);
+    }
+
+    // Single sequential peer with four pieces of a single block each
+    fn deadline_scheduler() -> RequestScheduler {
+        let mut scheduler = RequestScheduler::new(Bitfield::new(4), BLOCK_SIZE, 4 * BLOCK_SIZE as u64);
+        scheduler.set_endgame_threshold(0);
+        scheduler.set_pipeline_depth(1);
+        scheduler.set_sequential(true);
+
+        scheduler.peer_connected(peer(1), bitfield(4, &[0, 1, 2, 3]));
+        scheduler.peer_unchoked(&peer(1));
+
+        scheduler
+    }
+
+    #[test]
+    fn positive_deadline_piece_jumps_ahead_of_sequential() {
+        let mut scheduler = deadline_scheduler();
+        scheduler.set_piece_deadline(2, Instant::now() + Duration::from_secs(60));
+
+        let mut requested = Vec::new();
+        for _ in 0..4 {
+            let request = requests_for(&scheduler.requests(), &peer(1))[0];
+
+            requested.push(request.piece_index());
+            scheduler.block_received(&peer(1), &piece(&request));
+        }
+
+        assert_eq!(vec![2, 0, 1, 3], requested);
+    }
+
+    #[test]
+    fn positive_earliest_deadline_first() {
+        let mut scheduler = deadline_scheduler();
+        let now = Instant::now();
+        scheduler.set_piece_deadline(3, now + Duration::from_secs(60));
+        scheduler.set_piece_deadline(1, now + Duration::from_secs(30));
+
+        assert_eq!(vec![RequestMessage::new(1, 0, BLOCK_SIZE)], requests_for(&scheduler.requests(), &peer(1)));
+    }
+
+    #[test]
+    fn positive_expired_deadline_requested_from_all_peers() {
+        let mut scheduler = deadline_scheduler();
+        scheduler.peer_connected(peer(2), bitfield(4, &[0, 1, 2, 3]));
+        scheduler.peer_unchoked(&peer(2));
+        scheduler.set_piece_deadline(3, Instant::now());
+
+        let messages = scheduler.requests();
+        let expected = vec![RequestMessage::new(3, 0, BLOCK_SIZE)];
+        assert_eq!(expected, requests_for(&messages, &peer(1)));
+        assert_eq!(expected, requests_for(&messages, &peer(2)));
+
+        let messages = scheduler.block_received(&peer(2), &piece(&expected[0]));
+        assert_eq!(vec![OScheduleMessage::SendCancel(peer(1), CancelMessage::new(3, 0, BLOCK_SIZE))], messages);
+    }
+
+    #[test]
+    fn positive_skipped_pieces_excluded_from_endgame() {
+        let mut scheduler = RequestScheduler::with_files(Bitfield::new(2), BLOCK_SIZE, vec![BLOCK_SIZE as u64, BLOCK_SIZE as u64]);
+        scheduler.set_endgame_threshold(4);
+        scheduler.set_file_priorities(&[FilePriority::Skip, FilePriority::Normal]);
+
+        scheduler.peer_connected(peer(1), bitfield(2, &[0, 1]));
+        scheduler.peer_unchoked(&peer(1));
+
+        assert!(scheduler.is_endgame());
+        assert_eq!(vec![RequestMessage::new(1, 0, BLOCK_SIZE)], requests_for(&scheduler.requests(), &peer(1)));
+    }
+
+    #[test]
+    fn positive_pipeline_re_requests_on_choke() {
+        let mut scheduler = pipeline_scheduler();
+        scheduler.set_pipeline_depth(3);
+
+        let requests = requests_for(&scheduler.requests(), &peer(1));
+        scheduler.peer_choked(&peer(1));
+        assert!(scheduler.in_flight(&peer(1)).is_empty());
+        assert!(scheduler.requests().is_empty());
+
+        scheduler.peer_unchoked(&peer(1));
+        assert_eq!(requests, requests_for(&scheduler.requests(), &peer(1)));
+    }
+}
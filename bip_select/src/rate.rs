@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Rates are averaged over samples spanning this many milliseconds
+const RATE_WINDOW_MILLIS: u64 = 20 * 1000;
+
+/// Rolling history of bytes transferred, sampled on every tick.
+#[derive(Default)]
+pub struct RateHistory {
+    // Bytes transferred since the last tick
+    pending: u64,
+    // Bytes transferred and the span of each past tick
+    samples: VecDeque<(u64, u64)>,
+}
+
+impl RateHistory {
+    /// Transferred the given number of bytes since the last sample.
+    pub fn add_bytes(&mut self, bytes: u64) {
+        self.pending += bytes;
+    }
+
+    /// Sample the bytes transferred over the given span of time.
+    pub fn add_sample(&mut self, millis: u64) {
+        self.samples.push_back((self.pending, millis));
+        self.pending = 0;
+
+        while self.samples.iter().skip(1).map(|&(_, millis)| millis).sum::<u64>() >= RATE_WINDOW_MILLIS {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Rate in bytes per second.
+    pub fn rate(&self) -> u64 {
+        let (bytes, millis) = self.samples.iter().fold((0, 0), |(bytes, millis), &(sample_bytes, sample_millis)| {
+            (bytes + sample_bytes, millis + sample_millis)
+        });
+
+        if millis == 0 {
+            0
+        } else {
+            bytes * 1000 / millis
+        }
+    }
+}
+
+/// Convert the given duration to milliseconds.
+pub fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
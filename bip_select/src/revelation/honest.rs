@@ -22,18 +22,79 @@ use std::collections::VecDeque;
 use std::collections::hash_map::Entry;
 
 /// Revelation module that will honestly report any pieces we have to peers.
+///
+/// Superseeding can be enabled for initial seeders, in which case newly connected peers
+/// are only told about a single piece at a time.
 pub struct HonestRevealModule {
     torrents: HashMap<InfoHash, PeersInfo>,
     out_queue: VecDeque<ORevealMessage>,
     // Shared bytes container to write bitfield messages to
     out_bytes: BytesMut,
     opt_stream: Option<Task>,
+    superseed: bool,
 }
 
 struct PeersInfo {
     num_pieces: usize,
     status: BitSet<u8>,
     peers: HashSet<PeerInfo>,
+    // Pieces each peer has told us it has
+    peer_pieces: HashMap<PeerInfo, BitSet<u8>>,
+    // Pieces revealed to peers we are superseeding to, other peers know about all of our pieces
+    revealed: HashMap<PeerInfo, BitSet<u8>>,
+    // Piece each superseeded peer has to complete before we reveal another
+    assigned: HashMap<PeerInfo, usize>,
+}
+
+impl PeersInfo {
+    /// Reveal the next piece to a superseeded peer, preferring pieces that are least available.
+    fn reveal_next_piece(&mut self, peer: PeerInfo, out_queue: &mut VecDeque<ORevealMessage>) {
+        let opt_index = match (self.revealed.get(&peer), self.peer_pieces.get(&peer)) {
+            (Some(revealed), Some(peer_pieces)) => self.status
+                .iter()
+                .filter(|index| !revealed.contains(*index) && !peer_pieces.contains(*index))
+                .min_by_key(|index| self.availability(*index)),
+            _ => return,
+        };
+
+        match opt_index {
+            Some(index) => {
+                self.assigned.insert(peer, index);
+                self.revealed.get_mut(&peer).unwrap().insert(index);
+
+                out_queue.push_back(ORevealMessage::SendHave(peer, HaveMessage::new(index as u32)));
+            },
+            None => {
+                self.assigned.remove(&peer);
+            },
+        }
+    }
+
+    /// Record that the peer has the given piece, revealing the next piece if it was the one assigned to it.
+    fn update_peer_has(&mut self, peer: PeerInfo, index: usize, out_queue: &mut VecDeque<ORevealMessage>) {
+        match self.peer_pieces.get_mut(&peer) {
+            Some(pieces) => pieces.insert(index),
+            None => return,
+        };
+
+        if self.assigned.get(&peer) == Some(&index) {
+            self.reveal_next_piece(peer, out_queue);
+        }
+    }
+
+    /// Number of peers that have, or were assigned, the given piece.
+    fn availability(&self, index: usize) -> usize {
+        let num_have = self.peer_pieces
+            .values()
+            .filter(|pieces| pieces.contains(index))
+            .count();
+        let num_assigned = self.assigned
+            .values()
+            .filter(|assigned| **assigned == index)
+            .count();
+
+        num_have + num_assigned
+    }
 }
 
 impl HonestRevealModule {
@@ -44,9 +105,34 @@ impl HonestRevealModule {
             out_queue: VecDeque::new(),
             out_bytes: BytesMut::new(),
             opt_stream: None,
+            superseed: false,
         }
     }
 
+    /// Enable or disable superseeding.
+    ///
+    /// While superseeding, peers that connect are told about a single piece, and are only told
+    /// about the next piece once they report that they completed the previous one, so that an
+    /// initial seeder uploads as few duplicate pieces as possible. Disabling superseeding reveals
+    /// any remaining pieces to those peers.
+    pub fn enable_superseed(&mut self, enable: bool) {
+        self.superseed = enable;
+
+        if !enable {
+            for peers_info in self.torrents.values_mut() {
+                for (peer, revealed) in peers_info.revealed.drain() {
+                    for index in peers_info.status.iter().filter(|index| !revealed.contains(*index)) {
+                        self.out_queue
+                            .push_back(ORevealMessage::SendHave(peer, HaveMessage::new(index as u32)));
+                    }
+                }
+                peers_info.assigned.clear();
+            }
+        }
+
+        self.check_stream_unblock();
+    }
+
     fn add_torrent(&mut self, metainfo: &Metainfo) -> StartSend<IRevealMessage, RevealError> {
         let info_hash = metainfo.info().info_hash();
 
@@ -57,13 +143,13 @@ impl HonestRevealModule {
             Entry::Vacant(vac) => {
                 let num_pieces = metainfo.info().pieces().count();
 
-                let mut piece_set = BitSet::default();
-                piece_set.reserve_len_exact(num_pieces);
-
                 let peers_info = PeersInfo {
                     num_pieces: num_pieces,
-                    status: piece_set,
+                    status: empty_piece_set(num_pieces),
                     peers: HashSet::new(),
+                    peer_pieces: HashMap::new(),
+                    revealed: HashMap::new(),
+                    assigned: HashMap::new(),
                 };
                 vac.insert(peers_info);
 
@@ -85,6 +171,7 @@ impl HonestRevealModule {
     fn add_peer(&mut self, peer: PeerInfo) -> StartSend<IRevealMessage, RevealError> {
         let info_hash = *peer.hash();
 
+        let superseed = self.superseed;
         let out_bytes = &mut self.out_bytes;
         let out_queue = &mut self.out_queue;
         self.torrents
@@ -92,9 +179,18 @@ impl HonestRevealModule {
             .map(|peers_info| {
                 // Add the peer to our list, so we send have messages to them
                 peers_info.peers.insert(peer);
-
-                // If our bitfield has any pieces in it, send the bitfield, otherwise, dont send it
-                if !peers_info.status.is_empty() {
+                peers_info
+                    .peer_pieces
+                    .insert(peer, empty_piece_set(peers_info.num_pieces));
+
+                if superseed {
+                    // Peer only gets to know about the pieces we reveal to it
+                    peers_info
+                        .revealed
+                        .insert(peer, empty_piece_set(peers_info.num_pieces));
+                    peers_info.reveal_next_piece(peer, out_queue);
+                } else if !peers_info.status.is_empty() {
+                    // If our bitfield has any pieces in it, send the bitfield, otherwise, dont send it
                     // Get our current bitfield, write it to our shared bytes
                     let bitfield_slice = peers_info.status.get_ref().storage();
                     // Bitfield stores index 0 at bit 7 from the left, we want index 0 to be at bit 0 from the left
@@ -120,6 +216,9 @@ impl HonestRevealModule {
             .get_mut(&info_hash)
             .map(|peers_info| {
                 peers_info.peers.remove(&peer);
+                peers_info.peer_pieces.remove(&peer);
+                peers_info.revealed.remove(&peer);
+                peers_info.assigned.remove(&peer);
 
                 Ok(AsyncSink::Ready)
             })
@@ -137,20 +236,82 @@ impl HonestRevealModule {
                         hash: hash,
                     }))
                 } else {
-                    // Queue up all have messages
+                    // Queue up have messages for peers we are not superseeding to
                     for peer in peers_info.peers.iter() {
-                        out_queue.push_back(ORevealMessage::SendHave(*peer, HaveMessage::new(index as u32)));
+                        if !peers_info.revealed.contains_key(peer) {
+                            out_queue.push_back(ORevealMessage::SendHave(*peer, HaveMessage::new(index as u32)));
+                        }
                     }
 
                     // Insert into bitfield
                     peers_info.status.insert(index as usize);
 
+                    // Superseeded peers that completed all revealed pieces can be given the new piece
+                    let waiting: Vec<PeerInfo> = peers_info
+                        .revealed
+                        .keys()
+                        .filter(|peer| !peers_info.assigned.contains_key(*peer))
+                        .cloned()
+                        .collect();
+                    for peer in waiting {
+                        peers_info.reveal_next_piece(peer, out_queue);
+                    }
+
                     Ok(AsyncSink::Ready)
                 }
             })
             .unwrap_or_else(|| Err(RevealError::from_kind(RevealErrorKind::InvalidMetainfoNotExists { hash: hash })))
     }
 
+    fn peer_bitfield(&mut self, peer: PeerInfo, bitfield: &BitFieldMessage) -> StartSend<IRevealMessage, RevealError> {
+        let info_hash = *peer.hash();
+
+        let out_queue = &mut self.out_queue;
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|peers_info| {
+                if bitfield.bitfield().len() != (peers_info.num_pieces + 7) / 8 {
+                    return Err(RevealError::from_kind(RevealErrorKind::InvalidMessage {
+                        info: peer,
+                        message: "Bitfield Has Wrong Length".to_string(),
+                    }));
+                }
+
+                let indices = (0..peers_info.num_pieces)
+                    .filter(|index| bitfield.bitfield()[index / 8] & (0x80 >> (index % 8)) != 0)
+                    .collect::<Vec<usize>>();
+                for index in indices {
+                    peers_info.update_peer_has(peer, index, out_queue);
+                }
+
+                Ok(AsyncSink::Ready)
+            })
+            .unwrap_or_else(|| Err(RevealError::from_kind(RevealErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
+    fn peer_have(&mut self, peer: PeerInfo, have: &HaveMessage) -> StartSend<IRevealMessage, RevealError> {
+        let info_hash = *peer.hash();
+
+        let out_queue = &mut self.out_queue;
+        self.torrents
+            .get_mut(&info_hash)
+            .map(|peers_info| {
+                let index = have.piece_index() as usize;
+
+                if index >= peers_info.num_pieces {
+                    Err(RevealError::from_kind(RevealErrorKind::InvalidMessage {
+                        info: peer,
+                        message: format!("Have Message Index {} Out Of Range", index),
+                    }))
+                } else {
+                    peers_info.update_peer_has(peer, index, out_queue);
+
+                    Ok(AsyncSink::Ready)
+                }
+            })
+            .unwrap_or_else(|| Err(RevealError::from_kind(RevealErrorKind::InvalidMetainfoNotExists { hash: info_hash })))
+    }
+
     //------------------------------------------------------//
 
     fn check_stream_unblock(&mut self) {
@@ -160,6 +321,14 @@ impl HonestRevealModule {
     }
 }
 
+/// Creates a `BitSet` with room for the given number of pieces.
+fn empty_piece_set(num_pieces: usize) -> BitSet<u8> {
+    let mut piece_set = BitSet::default();
+    piece_set.reserve_len_exact(num_pieces);
+
+    piece_set
+}
+
 /// Inserts the slice into the `BytesMut` but reverses the bits in each byte.
 fn insert_reversed_bits(bytes: &mut BytesMut, slice: &[u8]) {
     for mut byte in slice.iter().map(|byte| *byte) {
@@ -199,7 +368,13 @@ impl Sink for HonestRevealModule {
             IRevealMessage::FoundGoodPiece(hash, index) => {
                 self.insert_piece(hash, index)
             },
-            IRevealMessage::Control(ControlMessage::Tick(_)) | IRevealMessage::ReceivedBitField(_, _) | IRevealMessage::ReceivedHave(_, _) => {
+            IRevealMessage::ReceivedBitField(info, bitfield) => {
+                self.peer_bitfield(info, &bitfield)
+            },
+            IRevealMessage::ReceivedHave(info, have) => {
+                self.peer_have(info, &have)
+            },
+            IRevealMessage::Control(ControlMessage::Tick(_)) => {
                 Ok(AsyncSink::Ready)
             },
         };
@@ -238,9 +413,11 @@ mod tests {
     use bip_handshake::Extensions;
     use bip_metainfo::{DirectAccessor, Metainfo, MetainfoBuilder, PieceLength};
     use bip_peer::PeerInfo;
+    use bip_peer::messages::{BitFieldMessage, HaveMessage};
     use bip_util::bt;
     use bip_util::bt::InfoHash;
-    use futures::{Async, Sink, Stream};
+    use bytes::Bytes;
+    use futures::{Async, Future, Sink, Stream};
     use futures_test::harness::Harness;
     use revelation::{IRevealMessage, ORevealMessage};
     use revelation::error::RevealErrorKind;
@@ -437,4 +614,196 @@ mod tests {
             },
         };
     }
+
+    fn superseed_peer_info(hash: InfoHash, port: u16) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        PeerInfo::new(addr, [port as u8; bt::PEER_ID_LEN].into(), hash, Extensions::new())
+    }
+
+    // Superseeding module for a torrent where we have all pieces
+    fn superseed_module(metainfo: &Metainfo) -> HonestRevealModule {
+        let info_hash = metainfo.info().info_hash();
+        let num_pieces = metainfo.info().pieces().count();
+
+        let mut module = HonestRevealModule::new();
+        module.enable_superseed(true);
+
+        module = module
+            .send(IRevealMessage::Control(ControlMessage::AddTorrent(metainfo.clone())))
+            .wait()
+            .unwrap();
+        for index in 0..num_pieces {
+            module = module
+                .send(IRevealMessage::FoundGoodPiece(info_hash, index as u64))
+                .wait()
+                .unwrap();
+        }
+
+        module
+    }
+
+    fn expect_have(message: ORevealMessage, expected_info: PeerInfo) -> u32 {
+        match message {
+            ORevealMessage::SendHave(info, have) => {
+                assert_eq!(expected_info, info);
+
+                have.piece_index()
+            },
+            _ => {
+                panic!("Received Unexpected Message")
+            },
+        }
+    }
+
+    #[test]
+    fn positive_superseed_reveals_single_piece() {
+        let metainfo = metainfo(8);
+        let peer_info = superseed_peer_info(metainfo.info().info_hash(), 1);
+        let (send, recv) = superseed_module(&metainfo).split();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+
+        match non_block_recv.poll_next() {
+            Ok(Async::Ready(Some(message))) => {
+                expect_have(message, peer_info);
+            },
+            _ => {
+                panic!("Received Unexpected Message")
+            },
+        }
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+    }
+
+    #[test]
+    fn positive_superseed_reveals_next_piece_once_completed() {
+        let metainfo = metainfo(8);
+        let peer_info = superseed_peer_info(metainfo.info().info_hash(), 1);
+        let (send, recv) = superseed_module(&metainfo).split();
+
+        let mut block_send = send.wait();
+        let mut non_block_recv = Harness::new(recv);
+
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+        let first_index = match non_block_recv.poll_next() {
+            Ok(Async::Ready(Some(message))) => expect_have(message, peer_info),
+            _ => panic!("Received Unexpected Message"),
+        };
+
+        // Completing some other piece does not count
+        let other_index = (first_index + 1) % 8;
+        block_send
+            .send(IRevealMessage::ReceivedHave(peer_info, HaveMessage::new(other_index)))
+            .unwrap();
+        assert!(
+            non_block_recv
+                .poll_next()
+                .as_ref()
+                .map(Async::is_not_ready)
+                .unwrap_or(false)
+        );
+
+        block_send
+            .send(IRevealMessage::ReceivedHave(peer_info, HaveMessage::new(first_index)))
+            .unwrap();
+        let second_index = match non_block_recv.poll_next() {
+            Ok(Async::Ready(Some(message))) => expect_have(message, peer_info),
+            _ => panic!("Received Unexpected Message"),
+        };
+
+        assert!(second_index != first_index);
+        assert!(second_index != other_index);
+    }
+
+    #[test]
+    fn positive_superseed_spreads_pieces_across_peers() {
+        let metainfo = metainfo(8);
+        let info_hash = metainfo.info().info_hash();
+        let (peer_one, peer_two) = (superseed_peer_info(info_hash, 1), superseed_peer_info(info_hash, 2));
+        let (send, recv) = superseed_module(&metainfo).split();
+
+        let mut block_send = send.wait();
+        let mut block_recv = recv.wait();
+
+        // Peer two turns out to already have the first piece
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_two)))
+            .unwrap();
+        block_send
+            .send(IRevealMessage::ReceivedBitField(peer_two, BitFieldMessage::new(Bytes::from(vec![0x80]))))
+            .unwrap();
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_one)))
+            .unwrap();
+
+        // Peer two already had the piece it was assigned, so it was assigned another
+        assert_eq!(0, expect_have(block_recv.next().unwrap().unwrap(), peer_two));
+        let two_index = expect_have(block_recv.next().unwrap().unwrap(), peer_two);
+        let one_index = expect_have(block_recv.next().unwrap().unwrap(), peer_one);
+
+        assert!(one_index != 0);
+        assert!(one_index != two_index);
+    }
+
+    #[test]
+    fn positive_disable_superseed_reveals_remaining_pieces() {
+        let metainfo = metainfo(3);
+        let peer_info = superseed_peer_info(metainfo.info().info_hash(), 1);
+        let mut module = superseed_module(&metainfo);
+
+        module = module
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .wait()
+            .unwrap();
+        module.enable_superseed(false);
+
+        let mut indices = Stream::wait(module)
+            .take(3)
+            .map(|message| expect_have(message.unwrap(), peer_info))
+            .collect::<Vec<u32>>();
+        indices.sort();
+
+        assert_eq!(vec![0, 1, 2], indices);
+    }
+
+    #[test]
+    fn negative_have_out_of_range() {
+        let (send, _recv) = HonestRevealModule::new().split();
+        let metainfo = metainfo(8);
+        let peer_info = superseed_peer_info(metainfo.info().info_hash(), 1);
+
+        let mut block_send = send.wait();
+
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::AddTorrent(metainfo)))
+            .unwrap();
+        block_send
+            .send(IRevealMessage::Control(ControlMessage::PeerConnected(peer_info)))
+            .unwrap();
+
+        let error = block_send
+            .send(IRevealMessage::ReceivedHave(peer_info, HaveMessage::new(8)))
+            .unwrap_err();
+        match error.kind() {
+            &RevealErrorKind::InvalidMessage { info, .. } => {
+                assert_eq!(peer_info, info);
+            },
+            _ => {
+                panic!("Received Unexpected Message")
+            },
+        };
+    }
 }
@@ -0,0 +1,432 @@
+//! Module for tracking transfer statistics of torrents.
+
+use bip_peer::{Bitfield, PeerInfo};
+use bip_util::bt::InfoHash;
+use client;
+use rate::{self, RateHistory};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Snapshot of the statistics for a single torrent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TorrentStats {
+    downloaded: u64,
+    uploaded: u64,
+    download_rate: u64,
+    upload_rate: u64,
+    peers: usize,
+    pieces_complete: u64,
+}
+
+impl TorrentStats {
+    /// Total bytes downloaded.
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    /// Total bytes uploaded.
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// Rolling download rate, in bytes per second.
+    pub fn download_rate(&self) -> u64 {
+        self.download_rate
+    }
+
+    /// Rolling upload rate, in bytes per second.
+    pub fn upload_rate(&self) -> u64 {
+        self.upload_rate
+    }
+
+    /// Number of connected peers.
+    pub fn peers(&self) -> usize {
+        self.peers
+    }
+
+    /// Number of pieces that have been completed.
+    pub fn pieces_complete(&self) -> u64 {
+        self.pieces_complete
+    }
+}
+
+/// Snapshot of the statistics for a single connected peer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerStats {
+    info: PeerInfo,
+    client: Option<String>,
+    download_rate: u64,
+    upload_rate: u64,
+    am_choking: bool,
+    am_interested: bool,
+    peer_choking: bool,
+    peer_interested: bool,
+    pieces: Option<Bitfield>,
+}
+
+impl PeerStats {
+    /// Information identifying the peer, including its address.
+    pub fn info(&self) -> &PeerInfo {
+        &self.info
+    }
+
+    /// Client name and version decoded from the peer id, if it follows a known convention.
+    pub fn client(&self) -> Option<&str> {
+        self.client.as_ref().map(|client| &client[..])
+    }
+
+    /// Rolling download rate from the peer, in bytes per second.
+    pub fn download_rate(&self) -> u64 {
+        self.download_rate
+    }
+
+    /// Rolling upload rate to the peer, in bytes per second.
+    pub fn upload_rate(&self) -> u64 {
+        self.upload_rate
+    }
+
+    /// Whether we are choking the peer.
+    pub fn am_choking(&self) -> bool {
+        self.am_choking
+    }
+
+    /// Whether we are interested in the peer.
+    pub fn am_interested(&self) -> bool {
+        self.am_interested
+    }
+
+    /// Whether the peer is choking us.
+    pub fn peer_choking(&self) -> bool {
+        self.peer_choking
+    }
+
+    /// Whether the peer is interested in us.
+    pub fn peer_interested(&self) -> bool {
+        self.peer_interested
+    }
+
+    /// Pieces the peer has, if it has sent us its bitfield.
+    pub fn pieces(&self) -> Option<&Bitfield> {
+        self.pieces.as_ref()
+    }
+}
+
+#[derive(Default)]
+struct TorrentState {
+    downloaded: u64,
+    uploaded: u64,
+    download: RateHistory,
+    upload: RateHistory,
+    peers: HashMap<PeerInfo, PeerState>,
+    pieces_complete: u64,
+}
+
+struct PeerState {
+    download: RateHistory,
+    upload: RateHistory,
+    am_choking: bool,
+    am_interested: bool,
+    peer_choking: bool,
+    peer_interested: bool,
+    pieces: Option<Bitfield>,
+}
+
+impl PeerState {
+    fn new() -> PeerState {
+        // Connections start out choked and not interested on both sides
+        PeerState {
+            download: RateHistory::default(),
+            upload: RateHistory::default(),
+            am_choking: true,
+            am_interested: false,
+            peer_choking: true,
+            peer_interested: false,
+            pieces: None,
+        }
+    }
+}
+
+/// Tracks bytes transferred, rolling transfer rates, connected peers, and completed pieces for each torrent.
+///
+/// Rates are sampled on every tick, and averaged over the last twenty seconds of samples.
+pub struct StatsTracker {
+    torrents: HashMap<InfoHash, TorrentState>,
+}
+
+impl StatsTracker {
+    /// Create a new `StatsTracker`.
+    pub fn new() -> StatsTracker {
+        StatsTracker { torrents: HashMap::new() }
+    }
+
+    /// Start tracking the given torrent.
+    pub fn add_torrent(&mut self, hash: InfoHash) {
+        self.torrents.entry(hash).or_insert_with(TorrentState::default);
+    }
+
+    /// Stop tracking the given torrent.
+    pub fn remove_torrent(&mut self, hash: &InfoHash) {
+        self.torrents.remove(hash);
+    }
+
+    /// Connected to the given peer.
+    pub fn peer_connected(&mut self, peer: PeerInfo) {
+        if let Some(state) = self.torrents.get_mut(peer.hash()) {
+            state.peers.entry(peer).or_insert_with(PeerState::new);
+        }
+    }
+
+    /// Disconnected from the given peer.
+    pub fn peer_disconnected(&mut self, peer: &PeerInfo) {
+        if let Some(state) = self.torrents.get_mut(peer.hash()) {
+            state.peers.remove(peer);
+        }
+    }
+
+    /// We choked or unchoked the given peer.
+    pub fn set_am_choking(&mut self, peer: &PeerInfo, choking: bool) {
+        if let Some(peer_state) = self.peer_state_mut(peer) {
+            peer_state.am_choking = choking;
+        }
+    }
+
+    /// We became interested or not interested in the given peer.
+    pub fn set_am_interested(&mut self, peer: &PeerInfo, interested: bool) {
+        if let Some(peer_state) = self.peer_state_mut(peer) {
+            peer_state.am_interested = interested;
+        }
+    }
+
+    /// The given peer choked or unchoked us.
+    pub fn set_peer_choking(&mut self, peer: &PeerInfo, choking: bool) {
+        if let Some(peer_state) = self.peer_state_mut(peer) {
+            peer_state.peer_choking = choking;
+        }
+    }
+
+    /// The given peer became interested or not interested in us.
+    pub fn set_peer_interested(&mut self, peer: &PeerInfo, interested: bool) {
+        if let Some(peer_state) = self.peer_state_mut(peer) {
+            peer_state.peer_interested = interested;
+        }
+    }
+
+    /// The given peer sent us its bitfield.
+    pub fn set_peer_bitfield(&mut self, peer: &PeerInfo, bitfield: Bitfield) {
+        if let Some(peer_state) = self.peer_state_mut(peer) {
+            peer_state.pieces = Some(bitfield);
+        }
+    }
+
+    /// The given peer has the given piece.
+    ///
+    /// Ignored if the peer has not sent us its bitfield, or the piece is out of range.
+    pub fn peer_has(&mut self, peer: &PeerInfo, index: usize) {
+        if let Some(pieces) = self.peer_state_mut(peer).and_then(|peer_state| peer_state.pieces.as_mut()) {
+            let _ = pieces.set(index);
+        }
+    }
+
+    /// Downloaded the given number of bytes from the peer.
+    pub fn downloaded(&mut self, peer: &PeerInfo, bytes: u64) {
+        if let Some(state) = self.torrents.get_mut(peer.hash()) {
+            state.downloaded += bytes;
+            state.download.add_bytes(bytes);
+
+            if let Some(peer_state) = state.peers.get_mut(peer) {
+                peer_state.download.add_bytes(bytes);
+            }
+        }
+    }
+
+    /// Uploaded the given number of bytes to the peer.
+    pub fn uploaded(&mut self, peer: &PeerInfo, bytes: u64) {
+        if let Some(state) = self.torrents.get_mut(peer.hash()) {
+            state.uploaded += bytes;
+            state.upload.add_bytes(bytes);
+
+            if let Some(peer_state) = state.peers.get_mut(peer) {
+                peer_state.upload.add_bytes(bytes);
+            }
+        }
+    }
+
+    /// Completed a piece for the given torrent.
+    pub fn piece_completed(&mut self, hash: &InfoHash) {
+        if let Some(state) = self.torrents.get_mut(hash) {
+            state.pieces_complete += 1;
+        }
+    }
+
+    /// A span of time has passed, sample torrent rates.
+    pub fn tick(&mut self, duration: Duration) {
+        let millis = rate::duration_millis(duration);
+
+        for state in self.torrents.values_mut() {
+            state.download.add_sample(millis);
+            state.upload.add_sample(millis);
+
+            for peer_state in state.peers.values_mut() {
+                peer_state.download.add_sample(millis);
+                peer_state.upload.add_sample(millis);
+            }
+        }
+    }
+
+    /// Snapshot of the current statistics for the given torrent, if it is being tracked.
+    pub fn stats(&self, hash: &InfoHash) -> Option<TorrentStats> {
+        self.torrents.get(hash).map(|state| TorrentStats {
+            downloaded: state.downloaded,
+            uploaded: state.uploaded,
+            download_rate: state.download.rate(),
+            upload_rate: state.upload.rate(),
+            peers: state.peers.len(),
+            pieces_complete: state.pieces_complete,
+        })
+    }
+
+    /// Snapshot of the current statistics for each peer connected for the given torrent, in no particular order.
+    pub fn peers(&self, hash: &InfoHash) -> Vec<PeerStats> {
+        self.torrents
+            .get(hash)
+            .map(|state| {
+                state
+                    .peers
+                    .iter()
+                    .map(|(info, peer_state)| PeerStats {
+                        info: *info,
+                        client: client::client_name(info.peer_id()),
+                        download_rate: peer_state.download.rate(),
+                        upload_rate: peer_state.upload.rate(),
+                        am_choking: peer_state.am_choking,
+                        am_interested: peer_state.am_interested,
+                        peer_choking: peer_state.peer_choking,
+                        peer_interested: peer_state.peer_interested,
+                        pieces: peer_state.pieces.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new)
+    }
+
+    fn peer_state_mut(&mut self, peer: &PeerInfo) -> Option<&mut PeerState> {
+        self.torrents
+            .get_mut(peer.hash())
+            .and_then(|state| state.peers.get_mut(peer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatsTracker;
+    use bip_handshake::Extensions;
+    use bip_peer::{Bitfield, PeerInfo};
+    use bip_util::bt::{self, InfoHash};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    const KB: u64 = 1024;
+
+    fn hash() -> InfoHash {
+        [0u8; bt::INFO_HASH_LEN].into()
+    }
+
+    fn peer(id: u8) -> PeerInfo {
+        let addr = format!("127.0.0.1:{}", id).parse().unwrap();
+
+        PeerInfo::new(addr, [id; bt::PEER_ID_LEN].into(), hash(), Extensions::new())
+    }
+
+    #[test]
+    fn positive_snapshot_totals_and_rates() {
+        let mut tracker = StatsTracker::new();
+        tracker.add_torrent(hash());
+        tracker.peer_connected(peer(1));
+        tracker.peer_connected(peer(2));
+        // Reconnecting a peer does not count it twice
+        tracker.peer_connected(peer(2));
+
+        for _ in 0..10 {
+            tracker.downloaded(&peer(1), 30 * KB);
+            tracker.downloaded(&peer(2), 10 * KB);
+            tracker.uploaded(&peer(1), 5 * KB);
+            tracker.tick(Duration::from_secs(1));
+        }
+        tracker.piece_completed(&hash());
+        tracker.piece_completed(&hash());
+
+        let stats = tracker.stats(&hash()).unwrap();
+        assert_eq!(400 * KB, stats.downloaded());
+        assert_eq!(50 * KB, stats.uploaded());
+        assert_eq!(40 * KB, stats.download_rate());
+        assert_eq!(5 * KB, stats.upload_rate());
+        assert_eq!(2, stats.peers());
+        assert_eq!(2, stats.pieces_complete());
+    }
+
+    #[test]
+    fn positive_rate_uses_rolling_window() {
+        let mut tracker = StatsTracker::new();
+        tracker.add_torrent(hash());
+        tracker.peer_connected(peer(1));
+
+        for _ in 0..30 {
+            tracker.downloaded(&peer(1), 100 * KB);
+            tracker.tick(Duration::from_secs(1));
+        }
+        // Transfers stall, rate falls off once the busy samples leave the window
+        for _ in 0..30 {
+            tracker.tick(Duration::from_secs(1));
+        }
+        tracker.peer_disconnected(&peer(1));
+
+        let stats = tracker.stats(&hash()).unwrap();
+        assert_eq!(3000 * KB, stats.downloaded());
+        assert_eq!(0, stats.download_rate());
+        assert_eq!(0, stats.peers());
+    }
+
+    #[test]
+    fn positive_peer_snapshot() {
+        let mut tracker = StatsTracker::new();
+        tracker.add_torrent(hash());
+
+        let mut peer_id = [b'x'; bt::PEER_ID_LEN];
+        peer_id[..8].copy_from_slice(b"-qB4250-");
+        let qbittorrent = PeerInfo::new("127.0.0.1:6881".parse().unwrap(), peer_id.into(), hash(), Extensions::new());
+        tracker.peer_connected(qbittorrent);
+
+        for _ in 0..10 {
+            tracker.downloaded(&qbittorrent, 20 * KB);
+            tracker.uploaded(&qbittorrent, 2 * KB);
+            tracker.tick(Duration::from_secs(1));
+        }
+        tracker.set_peer_choking(&qbittorrent, false);
+        tracker.set_am_interested(&qbittorrent, true);
+        tracker.set_peer_bitfield(&qbittorrent, Bitfield::new(4));
+        tracker.peer_has(&qbittorrent, 2);
+
+        let peers = tracker.peers(&hash());
+        assert_eq!(1, peers.len());
+
+        let stats = &peers[0];
+        assert_eq!("127.0.0.1:6881".parse::<SocketAddr>().unwrap(), *stats.info().addr());
+        assert_eq!(Some("qBittorrent 4.2.5.0"), stats.client());
+        assert_eq!(20 * KB, stats.download_rate());
+        assert_eq!(2 * KB, stats.upload_rate());
+        assert!(stats.am_choking());
+        assert!(stats.am_interested());
+        assert!(!stats.peer_choking());
+        assert!(!stats.peer_interested());
+        assert_eq!(1, stats.pieces().unwrap().count_ones());
+        assert!(stats.pieces().unwrap().get(2));
+    }
+
+    #[test]
+    fn negative_untracked_torrent() {
+        let mut tracker = StatsTracker::new();
+        tracker.downloaded(&peer(1), KB);
+
+        assert!(tracker.stats(&hash()).is_none());
+    }
+}
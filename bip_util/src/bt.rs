@@ -1,3 +1,5 @@
+use rand::{self, Rng};
+
 use sha;
 
 /// Bittorrent `NodeId`.
@@ -17,3 +19,53 @@ pub const PEER_ID_LEN: usize = sha::SHA_HASH_LEN;
 
 /// Length of an `InfoHash`.
 pub const INFO_HASH_LEN: usize = sha::SHA_HASH_LEN;
+
+/// Client id that bip-rs uses within its `PeerId` prefix.
+pub const BIP_CLIENT_ID: [u8; 2] = [b'B', b'I'];
+
+/// Client version that bip-rs uses within its `PeerId` prefix.
+pub const BIP_CLIENT_VERSION: [u8; 4] = [b'0', b'1', b'0', b'0'];
+
+impl PeerId {
+    /// Generate a `PeerId` with an Azureus style prefix, identifying the client, followed by random bytes.
+    ///
+    /// For example, a client id of `BI` and a version of `0100` gives a prefix of `-BI0100-`.
+    ///
+    /// See http://www.bittorrent.org/beps/bep_0020.html.
+    pub fn generate(client: [u8; 2], version: [u8; 4]) -> PeerId {
+        let mut peer_id = [0u8; PEER_ID_LEN];
+
+        peer_id[0] = b'-';
+        peer_id[1..3].copy_from_slice(&client);
+        peer_id[3..7].copy_from_slice(&version);
+        peer_id[7] = b'-';
+
+        // Thread rng is seeded from the os, so peers cant predict our id
+        rand::thread_rng().fill_bytes(&mut peer_id[8..]);
+
+        peer_id.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerId, PEER_ID_LEN};
+
+    #[test]
+    fn positive_generate_peer_id() {
+        let peer_id = PeerId::generate(super::BIP_CLIENT_ID, super::BIP_CLIENT_VERSION);
+
+        assert_eq!(PEER_ID_LEN, peer_id.as_ref().len());
+        assert_eq!(b"-BI0100-", &peer_id.as_ref()[..8]);
+    }
+
+    #[test]
+    fn positive_generate_peer_id_random_suffix() {
+        let peer_id_one = PeerId::generate(*b"XX", *b"1234");
+        let peer_id_two = PeerId::generate(*b"XX", *b"1234");
+
+        assert_eq!(b"-XX1234-", &peer_id_one.as_ref()[..8]);
+        assert_eq!(&peer_id_one.as_ref()[..8], &peer_id_two.as_ref()[..8]);
+        assert!(peer_id_one.as_ref()[8..] != peer_id_two.as_ref()[8..]);
+    }
+}
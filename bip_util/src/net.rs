@@ -1,5 +1,9 @@
+use std::cmp;
+use std::collections::HashMap;
 use std::net::{SocketAddr, Ipv4Addr, SocketAddrV4, Ipv6Addr};
 
+use convert;
+
 /// Abstraction of some ip address.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub enum IpAddr {
@@ -24,3 +28,177 @@ pub fn default_route_v4() -> SocketAddr {
 
     SocketAddr::V4(v4_sock)
 }
+
+// ----------------------------------------------------------------------------//
+
+/// Source that a hint for our external address came from.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum ExternalIpSource {
+    /// The `yourip` key in a peer's extended handshake.
+    ExtendedHandshake,
+    /// An external address reported by a tracker.
+    Tracker,
+    /// The `ip` key in a dht response, as specified in BEP 42.
+    Dht,
+}
+
+/// Aggregates hints of our external address, reported by remote hosts, in to a trusted address.
+///
+/// Each remote host gets a single vote, which is replaced if it reports a different address,
+/// so that a single misbehaving host can not outvote the rest. An address is only trusted once
+/// at least `quorum` hosts have reported it, and it has strictly more votes than any other address.
+pub struct ExternalIp {
+    quorum: usize,
+    votes: HashMap<IpAddr, (ExternalIpSource, IpAddr)>,
+}
+
+impl ExternalIp {
+    /// Create a new ExternalIp which requires the given number of agreeing hosts.
+    pub fn new(quorum: usize) -> ExternalIp {
+        ExternalIp {
+            quorum: cmp::max(quorum, 1),
+            votes: HashMap::new(),
+        }
+    }
+
+    /// Add a hint, from the given remote host, that our external address is the given address.
+    pub fn add_hint(&mut self, source: ExternalIpSource, reporter: SocketAddr, external: IpAddr) {
+        self.votes.insert(IpAddr::from_socket_addr(reporter), (source, external));
+    }
+
+    /// Add a hint in the compact format, which is an ipv4 or ipv6 address, optionally followed by a port.
+    ///
+    /// Returns false if the bytes were not a valid compact address.
+    pub fn add_compact_hint(&mut self, source: ExternalIpSource, reporter: SocketAddr, bytes: &[u8]) -> bool {
+        let external = match bytes.len() {
+            4 | 6 => {
+                let mut ipv4_bytes = [0u8; 4];
+                ipv4_bytes.copy_from_slice(&bytes[..4]);
+
+                IpAddr::V4(convert::bytes_be_to_ipv4(ipv4_bytes))
+            }
+            16 | 18 => {
+                let mut ipv6_bytes = [0u8; 16];
+                ipv6_bytes.copy_from_slice(&bytes[..16]);
+
+                IpAddr::V6(convert::bytes_be_to_ipv6(ipv6_bytes))
+            }
+            _ => return false,
+        };
+
+        self.add_hint(source, reporter, external);
+        true
+    }
+
+    /// Remove the hint from the given remote host, if it gave us one.
+    pub fn remove_hint(&mut self, reporter: SocketAddr) {
+        self.votes.remove(&IpAddr::from_socket_addr(reporter));
+    }
+
+    /// Number of remote hosts that have given us a hint.
+    pub fn num_hints(&self) -> usize {
+        self.votes.len()
+    }
+
+    /// Address with the most agreement, if it has reached the quorum and is not tied with another address.
+    pub fn external_ip(&self) -> Option<IpAddr> {
+        let mut tally: HashMap<IpAddr, usize> = HashMap::new();
+        for &(_, external) in self.votes.values() {
+            *tally.entry(external).or_insert(0) += 1;
+        }
+
+        let mut best: Option<(IpAddr, usize)> = None;
+        let mut is_tied = false;
+        for (external, count) in tally {
+            match best {
+                Some((_, best_count)) if count < best_count => (),
+                Some((_, best_count)) if count == best_count => is_tied = true,
+                _ => {
+                    best = Some((external, count));
+                    is_tied = false;
+                }
+            }
+        }
+
+        match best {
+            Some((external, count)) if count >= self.quorum && !is_tied => Some(external),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::{ExternalIp, ExternalIpSource, IpAddr};
+
+    fn reporter(last_octet: u8) -> SocketAddr {
+        format!("10.0.0.{}:6881", last_octet).parse().unwrap()
+    }
+
+    fn ipv4(last_octet: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, last_octet))
+    }
+
+    #[test]
+    fn positive_majority_wins_conflicting_hints() {
+        let mut external_ip = ExternalIp::new(3);
+
+        external_ip.add_hint(ExternalIpSource::ExtendedHandshake, reporter(1), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Tracker, reporter(2), ipv4(8));
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(3), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(4), ipv4(9));
+        external_ip.add_hint(ExternalIpSource::ExtendedHandshake, reporter(5), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Tracker, reporter(6), ipv4(8));
+
+        assert_eq!(Some(ipv4(7)), external_ip.external_ip());
+    }
+
+    #[test]
+    fn negative_no_address_below_quorum() {
+        let mut external_ip = ExternalIp::new(3);
+
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(1), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(2), ipv4(7));
+
+        assert_eq!(None, external_ip.external_ip());
+    }
+
+    #[test]
+    fn negative_no_address_when_tied() {
+        let mut external_ip = ExternalIp::new(2);
+
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(1), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(2), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Tracker, reporter(3), ipv4(8));
+        external_ip.add_hint(ExternalIpSource::Tracker, reporter(4), ipv4(8));
+
+        assert_eq!(None, external_ip.external_ip());
+    }
+
+    #[test]
+    fn positive_repeated_hints_from_one_reporter_count_once() {
+        let mut external_ip = ExternalIp::new(2);
+
+        for _ in 0..5 {
+            external_ip.add_hint(ExternalIpSource::ExtendedHandshake, reporter(1), ipv4(9));
+        }
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(2), ipv4(7));
+        external_ip.add_hint(ExternalIpSource::Dht, reporter(3), ipv4(7));
+
+        assert_eq!(1 + 2, external_ip.num_hints());
+        assert_eq!(Some(ipv4(7)), external_ip.external_ip());
+    }
+
+    #[test]
+    fn positive_add_compact_hints() {
+        let mut external_ip = ExternalIp::new(2);
+
+        assert!(external_ip.add_compact_hint(ExternalIpSource::ExtendedHandshake, reporter(1), &[203, 0, 113, 7]));
+        assert!(external_ip.add_compact_hint(ExternalIpSource::Dht, reporter(2), &[203, 0, 113, 7, 0x1A, 0xE1]));
+        assert!(!external_ip.add_compact_hint(ExternalIpSource::Dht, reporter(3), &[203, 0, 113]));
+
+        assert_eq!(Some(ipv4(7)), external_ip.external_ip());
+    }
+}
@@ -34,6 +34,13 @@ impl ShaHash {
         }
     }
 
+    /// Compare the ShaHash against the given hash, without copying the hash.
+    ///
+    /// Hashes with a length other than `SHA_HASH_LEN` are never equal.
+    pub fn equals_slice(&self, hash: &[u8]) -> bool {
+        &self.hash[..] == hash
+    }
+
     pub fn bits<'a>(&'a self) -> Bits<'a> {
         Bits::new(&self.hash)
     }
@@ -63,9 +70,7 @@ impl From<[u8; SHA_HASH_LEN]> for ShaHash {
 
 impl PartialEq<[u8]> for ShaHash {
     fn eq(&self, other: &[u8]) -> bool {
-        let is_equal = other.len() == self.hash.len();
-
-        self.hash.iter().zip(other.iter()).fold(is_equal, |prev, (h, o)| prev && h == o)
+        self.equals_slice(other)
     }
 }
 
@@ -241,4 +246,25 @@ mod tests {
 
         ShaHash::from_hash(&bits).unwrap();
     }
+
+    #[test]
+    fn positive_equals_slice() {
+        let hash = ShaHash::from_bytes(b"dummy piece");
+        let mut other_hash: [u8; super::SHA_HASH_LEN] = hash.into();
+
+        assert!(hash.equals_slice(&other_hash));
+        assert!(hash == other_hash[..]);
+
+        other_hash[super::SHA_HASH_LEN - 1] ^= 1;
+        assert!(!hash.equals_slice(&other_hash));
+    }
+
+    #[test]
+    fn negative_equals_slice_wrong_length() {
+        let hash = ShaHash::from([7u8; super::SHA_HASH_LEN]);
+
+        assert!(!hash.equals_slice(&[7u8; super::SHA_HASH_LEN - 1]));
+        assert!(!hash.equals_slice(&[7u8; super::SHA_HASH_LEN + 1]));
+        assert!(!hash.equals_slice(&[]));
+    }
 }
@@ -0,0 +1,636 @@
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::Instant;
+
+use ledbat::Ledbat;
+use packet::{Packet, PacketType, MAX_PACKET_LEN, MAX_PAYLOAD_LEN};
+
+const RECV_BUFFER_LEN: usize = 1024 * 1024;
+const SEND_BUFFER_LEN: usize = 1024 * 1024;
+
+/// Number of times we send a packet before giving up on the connection.
+const MAX_TRANSMISSIONS: usize = 6;
+
+/// Number of duplicate acks after which we consider the next packet lost.
+const DUPLICATE_ACKS_BEFORE_RESEND: usize = 3;
+
+/// Half of the sequence number space, used to compare wrapping sequence numbers.
+const SEQ_NR_HALF: u16 = 0x8000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    SynSent,
+    Connected,
+    Closed
+}
+
+struct SentPacket {
+    packet:        Packet,
+    sent_at:       Instant,
+    transmissions: usize
+}
+
+/// State machine for a single uTP connection.
+///
+/// Does no io itself; incoming packets and timer ticks are fed in, and outgoing packets are
+/// pulled out, by the socket that the connection is multiplexed over.
+pub struct Connection {
+    epoch:              Instant,
+    state:              State,
+    error:              Option<io::ErrorKind>,
+    send_id:            u16,
+    seq_nr:             u16,
+    ack_nr:             u16,
+    reply_micro:        u32,
+    peer_window:        usize,
+    ledbat:             Ledbat,
+    in_flight:          VecDeque<SentPacket>,
+    in_flight_bytes:    usize,
+    last_ack_nr:        u16,
+    duplicate_acks:     usize,
+    resend:             VecDeque<Packet>,
+    need_ack:           bool,
+    send_buffer:        VecDeque<u8>,
+    recv_buffer:        VecDeque<u8>,
+    out_of_order:       HashMap<u16, Vec<u8>>,
+    out_of_order_bytes: usize,
+    remote_fin:         Option<u16>,
+    eof:                bool,
+    closing:            bool,
+    fin_sent:           bool,
+    fin_acked:          bool,
+    released:           bool
+}
+
+impl Connection {
+    /// Create a new `Connection` which initiates a connection, receiving on the given id.
+    pub fn connect(recv_id: u16, epoch: Instant, now: Instant) -> Connection {
+        let mut conn = Connection::new(State::SynSent, recv_id.wrapping_add(1), 1, 0, epoch, now);
+
+        let syn = Packet::new(PacketType::Syn, recv_id, conn.seq_nr, 0);
+        conn.queue_sequenced(syn, now);
+
+        conn
+    }
+
+    /// Create a new `Connection` which accepts the connection initiated with the given syn.
+    pub fn accept(syn: &Packet, seq_nr: u16, epoch: Instant, now: Instant) -> Connection {
+        let mut conn = Connection::new(State::Connected, syn.connection_id, seq_nr, syn.seq_nr, epoch, now);
+        conn.reply_micro = conn.micros(now).wrapping_sub(syn.timestamp);
+        conn.peer_window = syn.wnd_size as usize;
+        conn.need_ack = true;
+
+        conn
+    }
+
+    fn new(state: State, send_id: u16, seq_nr: u16, ack_nr: u16, epoch: Instant, now: Instant) -> Connection {
+        Connection{ epoch: epoch, state: state, error: None, send_id: send_id, seq_nr: seq_nr, ack_nr: ack_nr,
+                    reply_micro: 0, peer_window: MAX_PACKET_LEN, ledbat: Ledbat::new(MAX_PACKET_LEN, now),
+                    in_flight: VecDeque::new(), in_flight_bytes: 0, last_ack_nr: 0, duplicate_acks: 0,
+                    resend: VecDeque::new(), need_ack: false, send_buffer: VecDeque::new(),
+                    recv_buffer: VecDeque::new(), out_of_order: HashMap::new(), out_of_order_bytes: 0, remote_fin: None,
+                    eof: false, closing: false, fin_sent: false, fin_acked: false, released: false }
+    }
+
+    /// Whether or not the connection has been established, and has not since failed.
+    pub fn is_connected(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// Whether or not our fin has been acked by the remote.
+    pub fn is_shutdown(&self) -> bool {
+        self.fin_acked
+    }
+
+    /// Whether or not the handle to this connection has been dropped.
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+
+    /// Whether or not the connection has nothing left to do.
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Closed || (self.fin_acked && (self.eof || self.released))
+    }
+
+    /// Error the connection failed with, if any.
+    pub fn error(&self) -> Option<io::Error> {
+        self.error.map(|kind| {
+            let message = match kind {
+                io::ErrorKind::ConnectionRefused => "Connection Refused By Remote",
+                io::ErrorKind::ConnectionReset   => "Connection Reset By Remote",
+                _                                => "Connection Timed Out"
+            };
+
+            io::Error::new(kind, message)
+        })
+    }
+
+    /// Read in-order bytes received from the remote.
+    pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.recv_buffer.is_empty() {
+            let read = cmp::min(buf.len(), self.recv_buffer.len());
+            for (dst, src) in buf.iter_mut().zip(self.recv_buffer.drain(..read)) {
+                *dst = src;
+            }
+
+            Ok(read)
+        } else if self.eof {
+            Ok(0)
+        } else if let Some(err) = self.error() {
+            Err(err)
+        } else {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "No Bytes Received"))
+        }
+    }
+
+    /// Queue bytes to be sent to the remote.
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(err) = self.error() {
+            return Err(err)
+        } else if self.closing {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Connection Was Shutdown"))
+        }
+
+        let written = cmp::min(buf.len(), SEND_BUFFER_LEN - self.send_buffer.len());
+        if written == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "Send Buffer Is Full"))
+        }
+        self.send_buffer.extend(&buf[..written]);
+
+        Ok(written)
+    }
+
+    /// Send a fin once all queued bytes have been sent.
+    pub fn close(&mut self) {
+        self.closing = true;
+    }
+
+    /// Close the connection, since its handle was dropped.
+    pub fn release(&mut self) {
+        self.released = true;
+
+        self.close();
+    }
+
+    /// Process a packet received from the remote.
+    pub fn on_packet(&mut self, packet: Packet, now: Instant) {
+        if self.state == State::Closed {
+            return
+        }
+
+        self.reply_micro = self.micros(now).wrapping_sub(packet.timestamp);
+        self.peer_window = packet.wnd_size as usize;
+
+        match (self.state, packet.ty) {
+            (State::SynSent, PacketType::Reset)   => return self.fail(io::ErrorKind::ConnectionRefused),
+            (_, PacketType::Reset)                => return self.fail(io::ErrorKind::ConnectionReset),
+            (State::SynSent, PacketType::State)   => {
+                self.state = State::Connected;
+                // First data packet from the remote will re-use the sequence number of its state packet
+                self.ack_nr = packet.seq_nr.wrapping_sub(1);
+            },
+            (State::SynSent, _)                   => return,
+            (_, PacketType::Syn)                  => {
+                // Our state packet for the syn was lost
+                self.need_ack = true;
+                return
+            },
+            _                                     => ()
+        }
+
+        self.process_ack(&packet, now);
+
+        match packet.ty {
+            PacketType::Data => self.process_data(packet.seq_nr, packet.payload),
+            PacketType::Fin  => {
+                self.remote_fin = Some(packet.seq_nr);
+                self.process_data(packet.seq_nr, packet.payload);
+            },
+            _                => ()
+        }
+    }
+
+    /// Retransmit if the oldest packet in flight has timed out.
+    pub fn on_tick(&mut self, now: Instant) {
+        let timed_out = self.in_flight.front()
+            .map(|sent| now.duration_since(sent.sent_at) >= self.ledbat.rto())
+            .unwrap_or(false);
+
+        if self.state != State::Closed && timed_out {
+            self.ledbat.on_timeout();
+            self.retransmit_oldest(now);
+        }
+    }
+
+    /// Next packet to send to the remote, if any.
+    pub fn poll_send(&mut self, now: Instant) -> Option<Packet> {
+        if self.resend.is_empty() {
+            self.fill_window(now);
+        }
+
+        let mut packet = match self.resend.pop_front() {
+            Some(packet)                                           => packet,
+            None if self.need_ack && self.state == State::Connected => {
+                Packet::new(PacketType::State, self.send_id, self.seq_nr, self.ack_nr)
+            },
+            None                                                   => return None
+        };
+
+        packet.timestamp = self.micros(now);
+        packet.timestamp_diff = self.reply_micro;
+        packet.wnd_size = RECV_BUFFER_LEN.saturating_sub(self.recv_buffer.len()) as u32;
+        if packet.ty != PacketType::Syn {
+            packet.ack_nr = self.ack_nr;
+        }
+        self.need_ack = false;
+
+        Some(packet)
+    }
+
+    /// Packetize as many queued bytes as the window allows.
+    fn fill_window(&mut self, now: Instant) {
+        if self.state != State::Connected {
+            return
+        }
+        let window = cmp::min(self.ledbat.window(), self.peer_window);
+
+        while !self.send_buffer.is_empty() {
+            let len = cmp::min(self.send_buffer.len(), MAX_PAYLOAD_LEN);
+            // Always allow a single packet in flight, so a closed remote window is probed
+            if self.in_flight_bytes != 0 && self.in_flight_bytes + len > window {
+                return
+            }
+
+            let mut packet = Packet::new(PacketType::Data, self.send_id, self.seq_nr, self.ack_nr);
+            packet.payload = self.send_buffer.drain(..len).collect();
+
+            self.queue_sequenced(packet, now);
+        }
+
+        if self.closing && !self.fin_sent {
+            let fin = Packet::new(PacketType::Fin, self.send_id, self.seq_nr, self.ack_nr);
+            self.queue_sequenced(fin, now);
+
+            self.fin_sent = true;
+        }
+    }
+
+    fn queue_sequenced(&mut self, packet: Packet, now: Instant) {
+        self.seq_nr = self.seq_nr.wrapping_add(1);
+        self.in_flight_bytes += packet.payload.len();
+
+        self.in_flight.push_back(SentPacket{ packet: packet.clone(), sent_at: now, transmissions: 1 });
+        self.resend.push_back(packet);
+    }
+
+    fn retransmit_oldest(&mut self, now: Instant) {
+        let exhausted = match self.in_flight.front_mut() {
+            Some(ref sent) if sent.transmissions >= MAX_TRANSMISSIONS => true,
+            Some(sent)                                                => {
+                sent.sent_at = now;
+                sent.transmissions += 1;
+                self.resend.push_back(sent.packet.clone());
+
+                false
+            },
+            None                                                      => false
+        };
+
+        if exhausted {
+            self.fail(io::ErrorKind::TimedOut);
+        }
+    }
+
+    fn process_ack(&mut self, packet: &Packet, now: Instant) {
+        let mut acked_any = false;
+        let mut acked_bytes = 0;
+
+        while self.in_flight.front().map(|sent| seq_nr_before(sent.packet.seq_nr, packet.ack_nr.wrapping_add(1))).unwrap_or(false) {
+            let sent = self.in_flight.pop_front().unwrap();
+            acked_any = true;
+            acked_bytes += sent.packet.payload.len();
+
+            // Samples from retransmitted packets are ambiguous
+            if sent.transmissions == 1 {
+                self.ledbat.on_rtt(now.duration_since(sent.sent_at));
+            }
+            if sent.packet.ty == PacketType::Fin {
+                self.fin_acked = true;
+            }
+        }
+        self.in_flight_bytes -= acked_bytes;
+
+        if acked_any {
+            self.ledbat.on_ack(acked_bytes, packet.timestamp_diff, now);
+            self.duplicate_acks = 0;
+        } else if packet.ty == PacketType::State && packet.ack_nr == self.last_ack_nr && !self.in_flight.is_empty() {
+            self.duplicate_acks += 1;
+
+            if self.duplicate_acks == DUPLICATE_ACKS_BEFORE_RESEND {
+                self.ledbat.on_loss();
+                self.retransmit_oldest(now);
+            }
+        }
+        self.last_ack_nr = packet.ack_nr;
+    }
+
+    fn process_data(&mut self, seq_nr: u16, payload: Vec<u8>) {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            // Larger than any packet we would send, drop it without acking
+            return
+        }
+        self.need_ack = true;
+
+        let offset = seq_nr.wrapping_sub(self.ack_nr);
+        if self.eof || offset == 0 || offset >= SEQ_NR_HALF {
+            // Already received
+            return
+        }
+
+        let buffered = self.recv_buffer.len() + self.out_of_order_bytes + payload.len();
+        if buffered > RECV_BUFFER_LEN || self.out_of_order.contains_key(&seq_nr) {
+            return
+        } else if offset != 1 {
+            self.out_of_order_bytes += payload.len();
+            self.out_of_order.insert(seq_nr, payload);
+            return
+        }
+
+        self.deliver(seq_nr, payload);
+        while let Some(payload) = self.out_of_order.remove(&self.ack_nr.wrapping_add(1)) {
+            let next_seq_nr = self.ack_nr.wrapping_add(1);
+
+            self.out_of_order_bytes -= payload.len();
+            self.deliver(next_seq_nr, payload);
+        }
+    }
+
+    fn deliver(&mut self, seq_nr: u16, payload: Vec<u8>) {
+        self.ack_nr = seq_nr;
+        self.recv_buffer.extend(payload);
+
+        if self.remote_fin == Some(seq_nr) {
+            self.eof = true;
+            self.out_of_order.clear();
+            self.out_of_order_bytes = 0;
+        }
+    }
+
+    fn fail(&mut self, kind: io::ErrorKind) {
+        self.state = State::Closed;
+        self.error = Some(kind);
+
+        self.in_flight.clear();
+        self.in_flight_bytes = 0;
+        self.resend.clear();
+        self.need_ack = false;
+    }
+
+    /// Timestamp in microseconds, wrapping around at `u32::MAX`.
+    fn micros(&self, now: Instant) -> u32 {
+        let elapsed = now.duration_since(self.epoch);
+
+        (elapsed.as_secs() * 1_000_000 + elapsed.subsec_nanos() as u64 / 1000) as u32
+    }
+}
+
+/// Whether or not the first sequence number comes before the second, accounting for wrap around.
+fn seq_nr_before(first: u16, second: u16) -> bool {
+    first != second && second.wrapping_sub(first) < SEQ_NR_HALF
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::{Duration, Instant};
+
+    use super::Connection;
+    use packet::{PacketType, MAX_PAYLOAD_LEN};
+
+    fn pump(from: &mut Connection, to: &mut Connection, now: Instant) -> usize {
+        let mut sent = 0;
+        while let Some(packet) = from.poll_send(now) {
+            to.on_packet(packet, now);
+            sent += 1;
+        }
+
+        sent
+    }
+
+    fn settle(one: &mut Connection, two: &mut Connection, now: Instant) {
+        while pump(one, two, now) + pump(two, one, now) != 0 { }
+    }
+
+    fn read_all(conn: &mut Connection) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            match conn.read(&mut buffer) {
+                Ok(0)                                                   => return bytes,
+                Ok(read)                                                => bytes.extend_from_slice(&buffer[..read]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return bytes,
+                Err(err)                                                => panic!("{:?}", err)
+            }
+        }
+    }
+
+    fn connected_pair(now: Instant) -> (Connection, Connection) {
+        let mut initiator = Connection::connect(100, now, now);
+        let syn = initiator.poll_send(now).unwrap();
+        let mut responder = Connection::accept(&syn, 5000, now, now);
+
+        settle(&mut initiator, &mut responder, now);
+
+        (initiator, responder)
+    }
+
+    #[test]
+    fn positive_connect() {
+        let now = Instant::now();
+        let mut initiator = Connection::connect(100, now, now);
+
+        let syn = initiator.poll_send(now).unwrap();
+        assert_eq!(PacketType::Syn, syn.ty);
+        assert_eq!(100, syn.connection_id);
+
+        let mut responder = Connection::accept(&syn, 5000, now, now);
+        let state = responder.poll_send(now).unwrap();
+        assert_eq!(PacketType::State, state.ty);
+        assert_eq!(100, state.connection_id);
+        assert_eq!(syn.seq_nr, state.ack_nr);
+
+        assert!(!initiator.is_connected());
+        initiator.on_packet(state, now);
+        assert!(initiator.is_connected());
+        assert!(responder.is_connected());
+
+        initiator.write(b"hello").unwrap();
+        let data = initiator.poll_send(now).unwrap();
+        assert_eq!(101, data.connection_id);
+    }
+
+    #[test]
+    fn positive_transfer_both_directions() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        let upload = (0..100_000).map(|index| index as u8).collect::<Vec<u8>>();
+        let download = vec![55u8; 5000];
+        assert_eq!(upload.len(), initiator.write(&upload).unwrap());
+        assert_eq!(download.len(), responder.write(&download).unwrap());
+
+        settle(&mut initiator, &mut responder, now);
+
+        assert_eq!(upload, read_all(&mut responder));
+        assert_eq!(download, read_all(&mut initiator));
+    }
+
+    #[test]
+    fn positive_reorders_out_of_order_packets() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        let bytes = vec![1u8; MAX_PAYLOAD_LEN * 2];
+        initiator.write(&bytes).unwrap();
+        let first = initiator.poll_send(now).unwrap();
+        let second = initiator.poll_send(now).unwrap();
+
+        responder.on_packet(second, now);
+        assert!(read_all(&mut responder).is_empty());
+
+        responder.on_packet(first, now);
+        assert_eq!(bytes, read_all(&mut responder));
+    }
+
+    #[test]
+    fn negative_drops_oversized_payload() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.write(b"small").unwrap();
+        let mut packet = initiator.poll_send(now).unwrap();
+        let small = packet.clone();
+        packet.payload = vec![1u8; MAX_PAYLOAD_LEN + 1];
+
+        responder.on_packet(packet, now);
+        assert!(read_all(&mut responder).is_empty());
+        assert!(responder.poll_send(now).is_none());
+
+        responder.on_packet(small, now);
+        assert_eq!(b"small".to_vec(), read_all(&mut responder));
+    }
+
+    #[test]
+    fn positive_retransmits_after_timeout() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.write(b"block").unwrap();
+        initiator.poll_send(now).unwrap();
+        assert!(initiator.poll_send(now).is_none());
+
+        let later = now + Duration::from_secs(2);
+        initiator.on_tick(later);
+        settle(&mut initiator, &mut responder, later);
+
+        assert_eq!(b"block".to_vec(), read_all(&mut responder));
+    }
+
+    #[test]
+    fn positive_retransmits_after_duplicate_acks() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.write(b"lost").unwrap();
+        initiator.poll_send(now).unwrap();
+
+        // Remote keeps acking the sequence number before our lost packet
+        responder.write(b"x").unwrap();
+        let mut ack = responder.poll_send(now).unwrap();
+        ack.ty = PacketType::State;
+        ack.payload.clear();
+
+        for _ in 0..3 {
+            initiator.on_packet(ack.clone(), now);
+        }
+
+        let resent = initiator.poll_send(now).unwrap();
+        assert_eq!(b"lost".to_vec(), resent.payload);
+    }
+
+    #[test]
+    fn positive_graceful_close() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.write(b"last bytes").unwrap();
+        initiator.close();
+        settle(&mut initiator, &mut responder, now);
+
+        assert!(initiator.is_shutdown());
+        assert_eq!(b"last bytes".to_vec(), read_all(&mut responder));
+        assert_eq!(0, responder.read(&mut [0u8; 1]).unwrap());
+        assert!(!initiator.is_finished());
+
+        responder.close();
+        settle(&mut initiator, &mut responder, now);
+
+        assert!(initiator.is_finished());
+        assert!(responder.is_finished());
+    }
+
+    #[test]
+    fn positive_released_finishes_once_fin_acked() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.release();
+        settle(&mut initiator, &mut responder, now);
+
+        assert!(initiator.is_finished());
+        assert!(!responder.is_finished());
+    }
+
+    #[test]
+    fn negative_connect_times_out() {
+        let now = Instant::now();
+        let mut initiator = Connection::connect(100, now, now);
+
+        let mut elapsed = Duration::from_secs(0);
+        while initiator.error().is_none() {
+            while initiator.poll_send(now + elapsed).is_some() { }
+
+            elapsed += Duration::from_secs(1);
+            initiator.on_tick(now + elapsed);
+        }
+
+        assert_eq!(io::ErrorKind::TimedOut, initiator.error().unwrap().kind());
+        assert!(initiator.is_finished());
+    }
+
+    #[test]
+    fn negative_reset_fails_connection() {
+        let now = Instant::now();
+        let (mut initiator, mut responder) = connected_pair(now);
+
+        initiator.write(b"x").unwrap();
+        let mut reset = initiator.poll_send(now).unwrap();
+        reset.ty = PacketType::Reset;
+        responder.on_packet(reset, now);
+
+        assert_eq!(io::ErrorKind::ConnectionReset, responder.read(&mut [0u8; 1]).unwrap_err().kind());
+        assert_eq!(io::ErrorKind::ConnectionReset, responder.write(b"x").unwrap_err().kind());
+    }
+
+    #[test]
+    fn negative_write_after_close() {
+        let now = Instant::now();
+        let (mut initiator, _) = connected_pair(now);
+
+        initiator.close();
+
+        assert_eq!(io::ErrorKind::BrokenPipe, initiator.write(b"x").unwrap_err().kind());
+    }
+}
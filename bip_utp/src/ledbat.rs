@@ -0,0 +1,223 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Queuing delay we try to stay under, in microseconds.
+const TARGET_DELAY: u32 = 100_000;
+
+/// Maximum number of bytes the window can grow by per round trip.
+const MAX_CWND_INCREASE_PER_RTT: f64 = 3000.0;
+
+/// Number of intervals the base delay is tracked over.
+const BASE_DELAY_HISTORY: usize = 2;
+const BASE_DELAY_INTERVAL_SECS: u64 = 60;
+
+const INITIAL_RTO_MILLIS: u64 = 1000;
+const MIN_RTO_MILLIS:     u64 = 500;
+const MAX_RTO_MILLIS:     u64 = 60_000;
+
+/// LEDBAT congestion controller.
+///
+/// Grows the window while the one way delay of our packets stays under the target, and backs off
+/// once it goes over, so that bulk transfers yield to interactive traffic sharing the same link.
+pub struct Ledbat {
+    min_window:    usize,
+    max_window:    usize,
+    base_delays:   VecDeque<u32>,
+    base_interval: Instant,
+    srtt:          Option<(Duration, Duration)>,
+    rto:           Duration
+}
+
+impl Ledbat {
+    /// Create a new `Ledbat` where the window never falls below the given packet size.
+    pub fn new(packet_len: usize, now: Instant) -> Ledbat {
+        Ledbat{ min_window: packet_len, max_window: packet_len * 2, base_delays: VecDeque::new(),
+                base_interval: now, srtt: None, rto: Duration::from_millis(INITIAL_RTO_MILLIS) }
+    }
+
+    /// Number of bytes we can have in flight.
+    pub fn window(&self) -> usize {
+        self.max_window
+    }
+
+    /// Current retransmission timeout.
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// Update the window with the number of bytes that were acked, and the one way delay that
+    /// the remote measured for the acking packet.
+    ///
+    /// A delay of zero means the remote has not measured one yet.
+    pub fn on_ack(&mut self, bytes_acked: usize, delay: u32, now: Instant) {
+        let our_delay = if delay == 0 {
+            0
+        } else {
+            self.add_delay_sample(delay, now);
+
+            // Measured delays include the offset between the two clocks, which the base delay cancels out
+            delay.saturating_sub(self.base_delay())
+        };
+
+        let off_target = (TARGET_DELAY as f64 - our_delay as f64) / TARGET_DELAY as f64;
+        let window_factor = bytes_acked as f64 / self.max_window as f64;
+        let scaled_gain = MAX_CWND_INCREASE_PER_RTT * off_target * window_factor;
+
+        let max_window = self.max_window as f64 + scaled_gain;
+        self.max_window = cmp::max(max_window as usize, self.min_window);
+    }
+
+    /// Update the retransmission timeout with a round trip time sample.
+    pub fn on_rtt(&mut self, rtt: Duration) {
+        let (srtt, rttvar) = match self.srtt {
+            None                 => (rtt, rtt / 2),
+            Some((srtt, rttvar)) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+
+                (srtt * 7 / 8 + rtt / 8, rttvar * 3 / 4 + delta / 4)
+            }
+        };
+
+        self.srtt = Some((srtt, rttvar));
+        self.rto = cmp::max(srtt + rttvar * 4, Duration::from_millis(MIN_RTO_MILLIS));
+    }
+
+    /// Back off after a packet was lost.
+    pub fn on_loss(&mut self) {
+        self.max_window = cmp::max(self.max_window / 2, self.min_window);
+    }
+
+    /// Back off after the retransmission timer fired.
+    pub fn on_timeout(&mut self) {
+        self.max_window = self.min_window;
+        self.rto = cmp::min(self.rto * 2, Duration::from_millis(MAX_RTO_MILLIS));
+    }
+
+    fn add_delay_sample(&mut self, delay: u32, now: Instant) {
+        let rollover = now.duration_since(self.base_interval) >= Duration::from_secs(BASE_DELAY_INTERVAL_SECS);
+
+        if rollover || self.base_delays.is_empty() {
+            self.base_delays.push_back(delay);
+            self.base_interval = now;
+
+            if self.base_delays.len() > BASE_DELAY_HISTORY {
+                self.base_delays.pop_front();
+            }
+        } else if let Some(interval_min) = self.base_delays.back_mut() {
+            *interval_min = cmp::min(*interval_min, delay);
+        }
+    }
+
+    fn base_delay(&self) -> u32 {
+        self.base_delays.iter().cloned().min().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Ledbat;
+
+    const PACKET_LEN: usize = 1000;
+
+    #[test]
+    fn positive_window_grows_under_target() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+        let initial = ledbat.window();
+
+        ledbat.on_ack(PACKET_LEN, 10_000, now);
+        ledbat.on_ack(PACKET_LEN, 10_000, now);
+
+        assert!(ledbat.window() > initial);
+    }
+
+    #[test]
+    fn positive_window_shrinks_over_target() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+
+        // Establish a base delay, then grow the window
+        for _ in 0..50 {
+            ledbat.on_ack(PACKET_LEN, 10_000, now);
+        }
+        let grown = ledbat.window();
+
+        // Queuing delay of 200ms is over the 100ms target
+        ledbat.on_ack(PACKET_LEN, 210_000, now);
+
+        assert!(ledbat.window() < grown);
+    }
+
+    #[test]
+    fn positive_window_never_below_packet_len() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+
+        ledbat.on_ack(PACKET_LEN, 1, now);
+        for _ in 0..100 {
+            ledbat.on_ack(PACKET_LEN, 10_000_000, now);
+        }
+
+        assert_eq!(PACKET_LEN, ledbat.window());
+    }
+
+    #[test]
+    fn positive_base_delay_expires() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+
+        ledbat.on_ack(PACKET_LEN, 10_000, now);
+        assert_eq!(10_000, ledbat.base_delay());
+
+        // Route changed, so every sample is now higher than the old base delay
+        ledbat.on_ack(PACKET_LEN, 50_000, now + Duration::from_secs(60));
+        assert_eq!(10_000, ledbat.base_delay());
+
+        ledbat.on_ack(PACKET_LEN, 50_000, now + Duration::from_secs(120));
+        assert_eq!(50_000, ledbat.base_delay());
+    }
+
+    #[test]
+    fn positive_window_halves_on_loss() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+        for _ in 0..50 {
+            ledbat.on_ack(PACKET_LEN, 10_000, now);
+        }
+        let grown = ledbat.window();
+
+        ledbat.on_loss();
+
+        assert_eq!(grown / 2, ledbat.window());
+    }
+
+    #[test]
+    fn positive_timeout_resets_window_and_backs_off() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new(PACKET_LEN, now);
+        for _ in 0..50 {
+            ledbat.on_ack(PACKET_LEN, 10_000, now);
+        }
+
+        ledbat.on_timeout();
+
+        assert_eq!(PACKET_LEN, ledbat.window());
+        assert_eq!(Duration::from_millis(2000), ledbat.rto());
+    }
+
+    #[test]
+    fn positive_rto_tracks_rtt() {
+        let mut ledbat = Ledbat::new(PACKET_LEN, Instant::now());
+
+        ledbat.on_rtt(Duration::from_millis(400));
+        assert_eq!(Duration::from_millis(1200), ledbat.rto());
+
+        for _ in 0..100 {
+            ledbat.on_rtt(Duration::from_millis(10));
+        }
+        assert_eq!(Duration::from_millis(500), ledbat.rto());
+    }
+}
@@ -1,3 +1,22 @@
-#[test]
-fn it_works() {
-}
+//! Implementation of the uTorrent Transport Protocol (BEP 29).
+//!
+//! uTP provides reliable, ordered streams over UDP, using the LEDBAT congestion
+//! controller so that transfers back off when other traffic is queuing on the link.
+//! `UtpTransport` can be used with `bip_handshake`, so that peers can be dialed and
+//! accepted over uTP.
+
+extern crate bip_handshake;
+extern crate byteorder;
+extern crate futures;
+extern crate rand;
+extern crate tokio_core;
+extern crate tokio_io;
+
+mod conn;
+mod ledbat;
+mod packet;
+mod socket;
+mod transport;
+
+pub use socket::{UtpStream, UtpConnect, UtpListener};
+pub use transport::UtpTransport;
@@ -0,0 +1,215 @@
+use std::io::{self, Cursor, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// Length of the fixed packet header.
+pub const HEADER_LEN: usize = 20;
+
+/// Maximum size of a packet we send, chosen to avoid fragmentation on most paths.
+pub const MAX_PACKET_LEN: usize = 1400;
+
+/// Maximum payload carried by a single data packet.
+pub const MAX_PAYLOAD_LEN: usize = MAX_PACKET_LEN - HEADER_LEN;
+
+const PROTOCOL_VERSION: u8 = 1;
+
+const DATA_TYPE_ID:  u8 = 0;
+const FIN_TYPE_ID:   u8 = 1;
+const STATE_TYPE_ID: u8 = 2;
+const RESET_TYPE_ID: u8 = 3;
+const SYN_TYPE_ID:   u8 = 4;
+
+/// Type of a uTP packet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PacketType {
+    Data,
+    Fin,
+    State,
+    Reset,
+    Syn
+}
+
+impl PacketType {
+    fn from_id(id: u8) -> Option<PacketType> {
+        match id {
+            DATA_TYPE_ID  => Some(PacketType::Data),
+            FIN_TYPE_ID   => Some(PacketType::Fin),
+            STATE_TYPE_ID => Some(PacketType::State),
+            RESET_TYPE_ID => Some(PacketType::Reset),
+            SYN_TYPE_ID   => Some(PacketType::Syn),
+            _             => None
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match *self {
+            PacketType::Data  => DATA_TYPE_ID,
+            PacketType::Fin   => FIN_TYPE_ID,
+            PacketType::State => STATE_TYPE_ID,
+            PacketType::Reset => RESET_TYPE_ID,
+            PacketType::Syn   => SYN_TYPE_ID
+        }
+    }
+}
+
+/// uTP packet, as defined in BEP 29.
+///
+/// Extensions on incoming packets are skipped, and we never send any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub ty:             PacketType,
+    pub connection_id:  u16,
+    pub timestamp:      u32,
+    pub timestamp_diff: u32,
+    pub wnd_size:       u32,
+    pub seq_nr:         u16,
+    pub ack_nr:         u16,
+    pub payload:        Vec<u8>
+}
+
+impl Packet {
+    /// Create a new `Packet` with an empty payload and zeroed timestamps.
+    pub fn new(ty: PacketType, connection_id: u16, seq_nr: u16, ack_nr: u16) -> Packet {
+        Packet{ ty: ty, connection_id: connection_id, timestamp: 0, timestamp_diff: 0, wnd_size: 0,
+                seq_nr: seq_nr, ack_nr: ack_nr, payload: Vec::new() }
+    }
+
+    /// Parse a `Packet` from the given datagram.
+    pub fn parse_bytes(bytes: &[u8]) -> io::Result<Packet> {
+        if bytes.len() < HEADER_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Packet Shorter Than Header"))
+        }
+
+        let mut cursor = Cursor::new(bytes);
+        let type_version = try!(cursor.read_u8());
+        let mut extension = try!(cursor.read_u8());
+
+        if type_version & 0x0F != PROTOCOL_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Packet Has Unsupported Version"))
+        }
+        let ty = try!(PacketType::from_id(type_version >> 4)
+            .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Packet Has Unknown Type")));
+
+        let connection_id = try!(cursor.read_u16::<BigEndian>());
+        let timestamp = try!(cursor.read_u32::<BigEndian>());
+        let timestamp_diff = try!(cursor.read_u32::<BigEndian>());
+        let wnd_size = try!(cursor.read_u32::<BigEndian>());
+        let seq_nr = try!(cursor.read_u16::<BigEndian>());
+        let ack_nr = try!(cursor.read_u16::<BigEndian>());
+
+        while extension != 0 {
+            extension = try!(cursor.read_u8());
+            let length = try!(cursor.read_u8()) as u64;
+
+            let position = cursor.position() + length;
+            if position > bytes.len() as u64 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Packet Extension Truncated"))
+            }
+            cursor.set_position(position);
+        }
+
+        let payload = bytes[cursor.position() as usize..].to_vec();
+
+        Ok(Packet{ ty: ty, connection_id: connection_id, timestamp: timestamp, timestamp_diff: timestamp_diff,
+                   wnd_size: wnd_size, seq_nr: seq_nr, ack_nr: ack_nr, payload: payload })
+    }
+
+    /// Write the `Packet` out to the given writer.
+    pub fn write_bytes<W>(&self, mut writer: W) -> io::Result<()>
+        where W: Write {
+        try!(writer.write_u8(self.ty.id() << 4 | PROTOCOL_VERSION));
+        try!(writer.write_u8(0));
+        try!(writer.write_u16::<BigEndian>(self.connection_id));
+        try!(writer.write_u32::<BigEndian>(self.timestamp));
+        try!(writer.write_u32::<BigEndian>(self.timestamp_diff));
+        try!(writer.write_u32::<BigEndian>(self.wnd_size));
+        try!(writer.write_u16::<BigEndian>(self.seq_nr));
+        try!(writer.write_u16::<BigEndian>(self.ack_nr));
+
+        writer.write_all(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Packet, PacketType};
+
+    #[test]
+    fn positive_parse_data_packet() {
+        let bytes = [0x01, 0x00, 0x30, 0x39, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+                     0x00, 0x10, 0x00, 0x00, 0x00, 0x05, 0x00, 0x04, 0xAA, 0xBB];
+        let packet = Packet::parse_bytes(&bytes).unwrap();
+
+        assert_eq!(PacketType::Data, packet.ty);
+        assert_eq!(12345, packet.connection_id);
+        assert_eq!(1, packet.timestamp);
+        assert_eq!(2, packet.timestamp_diff);
+        assert_eq!(0x100000, packet.wnd_size);
+        assert_eq!(5, packet.seq_nr);
+        assert_eq!(4, packet.ack_nr);
+        assert_eq!(vec![0xAA, 0xBB], packet.payload);
+    }
+
+    #[test]
+    fn positive_write_parse_round_trip() {
+        let mut packet = Packet::new(PacketType::Fin, 500, 65535, 0);
+        packet.timestamp = 0xDEADBEEF;
+        packet.timestamp_diff = 77;
+        packet.wnd_size = 1024;
+        packet.payload = vec![1, 2, 3];
+
+        let mut bytes = Vec::new();
+        packet.write_bytes(&mut bytes).unwrap();
+
+        assert_eq!(0x11, bytes[0]);
+        assert_eq!(packet, Packet::parse_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn positive_parse_skips_extensions() {
+        let mut bytes = Vec::new();
+        Packet::new(PacketType::State, 1, 2, 3).write_bytes(&mut bytes).unwrap();
+
+        // Selective ack extension, followed by an unknown extension, then no more extensions
+        bytes[1] = 1;
+        bytes.extend_from_slice(&[9, 4, 0xFF, 0xFF, 0xFF, 0xFF, 0, 2, 0xEE, 0xEE]);
+        bytes.push(0x42);
+
+        let packet = Packet::parse_bytes(&bytes).unwrap();
+        assert_eq!(PacketType::State, packet.ty);
+        assert_eq!(vec![0x42], packet.payload);
+    }
+
+    #[test]
+    fn negative_parse_short_header() {
+        assert!(Packet::parse_bytes(&[0x01; 19]).is_err());
+    }
+
+    #[test]
+    fn negative_parse_unsupported_version() {
+        let mut bytes = Vec::new();
+        Packet::new(PacketType::Syn, 1, 1, 0).write_bytes(&mut bytes).unwrap();
+        bytes[0] = 0x42;
+
+        assert!(Packet::parse_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn negative_parse_unknown_type() {
+        let mut bytes = Vec::new();
+        Packet::new(PacketType::Syn, 1, 1, 0).write_bytes(&mut bytes).unwrap();
+        bytes[0] = 0x51;
+
+        assert!(Packet::parse_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn negative_parse_truncated_extension() {
+        let mut bytes = Vec::new();
+        Packet::new(PacketType::State, 1, 2, 3).write_bytes(&mut bytes).unwrap();
+        bytes[1] = 1;
+        bytes.extend_from_slice(&[0, 4, 0xFF]);
+
+        assert!(Packet::parse_bytes(&bytes).is_err());
+    }
+}
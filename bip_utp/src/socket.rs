@@ -0,0 +1,405 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use conn::Connection;
+use packet::{Packet, PacketType, MAX_PACKET_LEN};
+
+use futures::{Async, Future, Poll, Stream};
+use futures::task::{self, Task};
+use rand;
+use tokio_core::net::UdpSocket;
+use tokio_core::reactor::{Handle, Interval};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// Interval at which connections are checked for retransmission timeouts.
+const TICK_MILLIS: u64 = 100;
+
+/// Largest datagram we will receive.
+const MAX_DATAGRAM_LEN: usize = 64 * 1024;
+
+/// Maximum number of connections waiting to be accepted, further syns are dropped.
+const MAX_ACCEPT_BACKLOG: usize = 128;
+
+/// Connections are identified by the address of the remote, and the id we receive on.
+type ConnectionKey = (SocketAddr, u16);
+
+struct Entry {
+    conn:       Connection,
+    read_task:  Option<Task>,
+    write_task: Option<Task>
+}
+
+impl Entry {
+    fn new(conn: Connection) -> Entry {
+        Entry{ conn: conn, read_task: None, write_task: None }
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.read_task.take() {
+            task.notify();
+        }
+        if let Some(task) = self.write_task.take() {
+            task.notify();
+        }
+    }
+}
+
+/// State shared between a `Driver` and the streams and listener multiplexed over its socket.
+struct Inner {
+    socket:      UdpSocket,
+    epoch:       Instant,
+    connections: HashMap<ConnectionKey, Entry>,
+    // Present only if we are accepting connections
+    accepted:    Option<VecDeque<ConnectionKey>>,
+    accept_task: Option<Task>,
+    driver_task: Option<Task>,
+    blocked:     Option<(Vec<u8>, SocketAddr)>
+}
+
+impl Inner {
+    fn bind(addr: &SocketAddr, listen: bool, handle: &Handle) -> io::Result<Rc<RefCell<Inner>>> {
+        let socket = try!(UdpSocket::bind(addr, handle));
+        let interval = try!(Interval::new(Duration::from_millis(TICK_MILLIS), handle));
+
+        let accepted = if listen { Some(VecDeque::new()) } else { None };
+        let inner = Rc::new(RefCell::new(Inner{ socket: socket, epoch: Instant::now(), connections: HashMap::new(),
+                                                accepted: accepted, accept_task: None, driver_task: None, blocked: None }));
+
+        handle.spawn(Driver{ inner: inner.clone(), interval: interval });
+
+        Ok(inner)
+    }
+
+    fn entry(&mut self, key: &ConnectionKey) -> &mut Entry {
+        self.connections.get_mut(key).expect("bip_utp: Connection Removed While Handle Exists")
+    }
+
+    fn notify_driver(&self) {
+        if let Some(ref task) = self.driver_task {
+            task.notify();
+        }
+    }
+
+    fn recv_all(&mut self, now: Instant) {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_LEN];
+
+        // Errors are either would block, or icmp errors for a single remote, which retransmissions handle
+        while let Ok((length, addr)) = self.socket.recv_from(&mut buffer) {
+            if let Ok(packet) = Packet::parse_bytes(&buffer[..length]) {
+                self.dispatch(packet, addr, now);
+            }
+        }
+    }
+
+    fn dispatch(&mut self, packet: Packet, addr: SocketAddr, now: Instant) {
+        let key = if packet.ty == PacketType::Syn {
+            (addr, packet.connection_id.wrapping_add(1))
+        } else {
+            (addr, packet.connection_id)
+        };
+
+        if let Some(entry) = self.connections.get_mut(&key) {
+            entry.conn.on_packet(packet, now);
+            entry.notify();
+
+            return
+        }
+
+        if let (PacketType::Syn, Some(ref mut accepted)) = (packet.ty, self.accepted.as_mut()) {
+            if accepted.len() >= MAX_ACCEPT_BACKLOG {
+                return
+            }
+            let conn = Connection::accept(&packet, rand::random(), self.epoch, now);
+
+            self.connections.insert(key, Entry::new(conn));
+            accepted.push_back(key);
+
+            if let Some(task) = self.accept_task.take() {
+                task.notify();
+            }
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        for entry in self.connections.values_mut() {
+            let connected = entry.conn.is_connected();
+            entry.conn.on_tick(now);
+
+            if connected != entry.conn.is_connected() {
+                entry.notify();
+            }
+        }
+    }
+
+    fn flush(&mut self, now: Instant) {
+        let Inner{ ref socket, ref mut connections, ref mut blocked, .. } = *self;
+
+        if let Some((bytes, addr)) = blocked.take() {
+            if is_would_block(&socket.send_to(&bytes, &addr)) {
+                *blocked = Some((bytes, addr));
+                return
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(MAX_PACKET_LEN);
+        for (&(addr, _), entry) in connections.iter_mut() {
+            let mut sent_any = false;
+
+            while let Some(packet) = entry.conn.poll_send(now) {
+                bytes.clear();
+                packet.write_bytes(&mut bytes).expect("bip_utp: Failed To Write Packet To Buffer");
+                sent_any = true;
+
+                // Other errors mean the datagram was lost, which retransmissions handle
+                if is_would_block(&socket.send_to(&bytes, &addr)) {
+                    *blocked = Some((bytes, addr));
+                    return
+                }
+            }
+
+            // Packetizing frees up room in the send buffer
+            if sent_any {
+                entry.notify();
+            }
+        }
+    }
+}
+
+fn is_would_block<T>(result: &io::Result<T>) -> bool {
+    match *result {
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => true,
+        _                                                        => false
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Future spawned for each socket, which sends and receives packets for all of its connections.
+///
+/// Resolves once no handles to the socket remain and all connections have finished.
+struct Driver {
+    inner:    Rc<RefCell<Inner>>,
+    interval: Interval
+}
+
+impl Future for Driver {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut ticked = false;
+        while let Ok(Async::Ready(Some(()))) = self.interval.poll() {
+            ticked = true;
+        }
+
+        let now = Instant::now();
+        let mut inner = self.inner.borrow_mut();
+        inner.driver_task = Some(task::current());
+
+        inner.recv_all(now);
+        if ticked {
+            inner.tick(now);
+        }
+        inner.flush(now);
+
+        inner.connections.retain(|_, entry| !(entry.conn.is_released() && entry.conn.is_finished()));
+
+        if Rc::strong_count(&self.inner) == 1 && inner.connections.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Reliable, ordered stream of bytes to a remote over uTP.
+///
+/// Dropping the stream gracefully closes the connection in the background.
+pub struct UtpStream {
+    inner: Rc<RefCell<Inner>>,
+    key:   ConnectionKey
+}
+
+impl UtpStream {
+    /// Connect to the given address, from a newly bound socket.
+    pub fn connect(addr: &SocketAddr, handle: &Handle) -> io::Result<UtpConnect> {
+        let bind_addr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let inner = try!(Inner::bind(&bind_addr, false, handle));
+
+        let recv_id = rand::random();
+        let key = (*addr, recv_id);
+        {
+            let mut inner_ref = inner.borrow_mut();
+            let conn = Connection::connect(recv_id, inner_ref.epoch, Instant::now());
+
+            inner_ref.connections.insert(key, Entry::new(conn));
+        }
+
+        Ok(UtpConnect{ stream: Some(UtpStream{ inner: inner, key: key }) })
+    }
+
+    /// Address of the remote.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.key.0
+    }
+
+    /// Address of the local socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.borrow().socket.local_addr()
+    }
+}
+
+impl Read for UtpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        let entry = inner.entry(&self.key);
+
+        let result = entry.conn.read(buf);
+        if is_would_block(&result) {
+            entry.read_task = Some(task::current());
+        }
+
+        result
+    }
+}
+
+impl Write for UtpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+
+        let result = {
+            let entry = inner.entry(&self.key);
+            let result = entry.conn.write(buf);
+
+            if is_would_block(&result) {
+                entry.write_task = Some(task::current());
+            }
+            result
+        };
+        inner.notify_driver();
+
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for UtpStream { }
+
+impl AsyncWrite for UtpStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        let mut inner = self.inner.borrow_mut();
+
+        let result = {
+            let entry = inner.entry(&self.key);
+            entry.conn.close();
+
+            if entry.conn.is_shutdown() {
+                Ok(Async::Ready(()))
+            } else if let Some(err) = entry.conn.error() {
+                Err(err)
+            } else {
+                entry.write_task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+        };
+        inner.notify_driver();
+
+        result
+    }
+}
+
+impl Drop for UtpStream {
+    fn drop(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.entry(&self.key).conn.release();
+        inner.notify_driver();
+    }
+}
+
+/// Future resolving to a `UtpStream` once the remote acknowledges our connection.
+pub struct UtpConnect {
+    stream: Option<UtpStream>
+}
+
+impl Future for UtpConnect {
+    type Item = UtpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<UtpStream, io::Error> {
+        {
+            let stream = self.stream.as_ref().expect("bip_utp: UtpConnect Polled After Completion");
+            let mut inner = stream.inner.borrow_mut();
+            let entry = inner.entry(&stream.key);
+
+            if let Some(err) = entry.conn.error() {
+                return Err(err)
+            } else if !entry.conn.is_connected() {
+                entry.write_task = Some(task::current());
+                return Ok(Async::NotReady)
+            }
+        }
+
+        Ok(Async::Ready(self.stream.take().unwrap()))
+    }
+}
+
+//----------------------------------------------------------------------------------//
+
+/// Listener yielding `UtpStream`s for connections initiated by remotes.
+pub struct UtpListener {
+    inner: Rc<RefCell<Inner>>
+}
+
+impl UtpListener {
+    /// Bind a socket to the given address, and accept connections on it.
+    pub fn bind(addr: &SocketAddr, handle: &Handle) -> io::Result<UtpListener> {
+        let inner = try!(Inner::bind(addr, true, handle));
+
+        Ok(UtpListener{ inner: inner })
+    }
+
+    /// Address of the local socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.borrow().socket.local_addr()
+    }
+}
+
+impl Stream for UtpListener {
+    type Item = (UtpStream, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
+        let mut inner = self.inner.borrow_mut();
+
+        let opt_key = inner.accepted.as_mut().and_then(|accepted| accepted.pop_front());
+        match opt_key {
+            Some(key) => Ok(Async::Ready(Some((UtpStream{ inner: self.inner.clone(), key: key }, key.0)))),
+            None      => {
+                inner.accept_task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+impl Drop for UtpListener {
+    fn drop(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+
+        // Connections which were never handed out have no handle to release them
+        for key in inner.accepted.take().unwrap_or_default() {
+            inner.entry(&key).conn.release();
+        }
+        inner.notify_driver();
+    }
+}
@@ -0,0 +1,32 @@
+use std::io;
+use std::net::SocketAddr;
+
+use socket::{UtpConnect, UtpListener, UtpStream};
+
+use bip_handshake::{LocalAddr, Transport};
+use tokio_core::reactor::Handle;
+
+/// Defines a `Transport` operating over uTP.
+///
+/// Each outgoing connection is made from its own socket, bound to an ephemeral port.
+pub struct UtpTransport;
+
+impl Transport for UtpTransport {
+    type Socket = UtpStream;
+    type FutureSocket = UtpConnect;
+    type Listener = UtpListener;
+
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::FutureSocket> {
+        UtpStream::connect(addr, handle)
+    }
+
+    fn listen(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<Self::Listener> {
+        UtpListener::bind(addr, handle)
+    }
+}
+
+impl LocalAddr for UtpListener {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UtpListener::local_addr(self)
+    }
+}
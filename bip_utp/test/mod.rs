@@ -0,0 +1,9 @@
+extern crate bip_handshake;
+extern crate bip_util;
+extern crate bip_utp;
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_io;
+
+mod test_connect_handshaker;
+mod test_transfer_block;
@@ -0,0 +1,35 @@
+use bip_utp::{UtpListener, UtpStream};
+
+use futures::Future;
+use futures::stream::Stream;
+use tokio_core::reactor::Core;
+use tokio_io::io;
+
+#[test]
+fn positive_transfer_block() {
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+
+    let listener = UtpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+
+    let block = (0..16 * 1024).map(|index| (index % 251) as u8).collect::<Vec<u8>>();
+    let send_block = block.clone();
+
+    let accept = listener.into_future()
+        .map_err(|(err, _)| err)
+        .and_then(|(opt_accepted, _)| {
+            let (stream, _) = opt_accepted.unwrap();
+
+            io::read_to_end(stream, Vec::new())
+        })
+        .map(|(_, bytes)| bytes);
+
+    let connect = UtpStream::connect(&listen_addr, &handle).unwrap()
+        .and_then(move |stream| io::write_all(stream, send_block))
+        .and_then(|(stream, _)| io::shutdown(stream));
+
+    let (recv_block, _) = core.run(accept.join(connect)).unwrap();
+
+    assert_eq!(block, recv_block);
+}